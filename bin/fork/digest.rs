@@ -62,7 +62,7 @@ pub(crate) fn create_storage_layout(
     contract_data: &ContractMetadata,
     storage_layout: StorageLayout,
     db: &mut CacheDB<EmptyDB>,
-    ethers_db: &mut EthersDB<Provider<Http>>,
+    ethers_db: &mut RemoteDB,
 ) -> Result<(), ArbiterError> {
     for storage_item in storage_layout.storage {
         // The unwraps here should not fail.
@@ -17,6 +17,9 @@ use serde::Serialize;
 use super::*;
 
 pub(crate) mod digest;
+
+/// The `EthersDB` used to read from the remote node, behind a cache on disk.
+pub(crate) type RemoteDB = DiskCache<EthersDB<Provider<Http>>>;
 #[cfg(test)]
 mod tests;
 
@@ -27,6 +30,9 @@ pub(crate) struct ForkConfig {
     output_filename: Option<String>,
     provider: String,
     block_number: u64,
+    /// Where the state read from the provider is cached. Defaults to a
+    /// `.cache` directory inside the output directory.
+    cache_directory: Option<String>,
     #[serde(rename = "contracts")]
     contracts_meta: HashMap<String, ContractMetadata>,
     externally_owned_accounts: HashMap<String, Address>,
@@ -53,6 +59,15 @@ impl ForkConfig {
             println!("No output filename specified. Defaulting to `output.json.`");
             fork_config.output_filename = Some("output.json".to_string());
         }
+        if fork_config.cache_directory.is_none() {
+            let output_directory = fork_config.output_directory.as_ref().unwrap();
+            fork_config.cache_directory = Some(
+                Path::new(output_directory)
+                    .join(".cache")
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
 
         Ok(fork_config)
     }
@@ -62,7 +77,9 @@ impl ForkConfig {
     /// Once all the `AccountInfo` for the contracts are fetched, we digest the
     /// contract artifacts to get the storage layout.
     pub(crate) fn digest_config(&self) -> Result<CacheDB<EmptyDB>, ArbiterError> {
-        // Spawn the `EthersDB` and the `CacheDB` we will write to.
+        // Spawn the `EthersDB` and the `CacheDB` we will write to. Anything read
+        // from the provider is cached on disk so forking the same block again
+        // does not query it again.
         let ethers_db = &mut self.spawn_ethers_db()?;
         let mut db = CacheDB::new(EmptyDBTyped::default());
         for contract_data in self.contracts_meta.values() {
@@ -102,6 +119,9 @@ impl ForkConfig {
                 db.insert_account_info(eoa.to_fixed_bytes().into(), info);
             }
         }
+        ethers_db
+            .flush()
+            .map_err(|e| ArbiterError::DBError(e.to_string()))?;
         Ok(db)
     }
 
@@ -160,7 +180,7 @@ impl ForkConfig {
         Ok(())
     }
 
-    fn spawn_ethers_db(&self) -> Result<EthersDB<Provider<Http>>, ArbiterError> {
+    fn spawn_ethers_db(&self) -> Result<RemoteDB, ArbiterError> {
         let ethers_db = EthersDB::new(
             Arc::new(
                 Provider::<Http>::try_from(self.provider.clone())
@@ -171,6 +191,9 @@ impl ForkConfig {
             ))),
         )
         .unwrap();
-        Ok(ethers_db)
+        let cache_directory = self.cache_directory.clone().unwrap();
+        println!("Caching fork data in: {:?}", cache_directory);
+        DiskCache::new(ethers_db, cache_directory, self.block_number)
+            .map_err(|e| ArbiterError::DBError(e.to_string()))
     }
 }
@@ -0,0 +1,249 @@
+//! The [`conversions`] module converts between the ethers types the
+//! [`ArbiterMiddleware`] speaks and the alloy primitives that revm and newer
+//! projects are written against, so that neither side has to spell out the
+//! conversions by hand.
+//!
+//! [`ToAlloy`] converts ethers types into alloy primitives and [`ToEthers`]
+//! converts them back:
+//! ```
+//! use arbiter_core::conversions::{ToAlloy, ToEthers};
+//! use ethers::types::{Address, U256};
+//!
+//! let address = Address::repeat_byte(1);
+//! assert_eq!(address.to_alloy().to_ethers(), address);
+//! assert_eq!(U256::from(69).to_alloy(), revm::primitives::U256::from(69));
+//! ```
+//!
+//! [`ArbiterMiddleware`]: crate::middleware::ArbiterMiddleware
+
+use ethers::types::{Bytes as eBytes, I256 as eI256};
+use revm::primitives::{alloy_primitives, LogData, B256, I256};
+
+use super::*;
+
+/// Converts an ethers type into its alloy counterpart.
+pub trait ToAlloy {
+    /// The alloy type.
+    type Alloy;
+
+    /// Returns the value as its alloy type.
+    fn to_alloy(self) -> Self::Alloy;
+}
+
+/// Converts an alloy type into its ethers counterpart.
+pub trait ToEthers {
+    /// The ethers type.
+    type Ethers;
+
+    /// Returns the value as its ethers type.
+    fn to_ethers(self) -> Self::Ethers;
+}
+
+impl ToAlloy for eAddress {
+    type Alloy = Address;
+
+    #[inline]
+    fn to_alloy(self) -> Self::Alloy {
+        Address::from(self.to_fixed_bytes())
+    }
+}
+
+impl ToEthers for Address {
+    type Ethers = eAddress;
+
+    #[inline]
+    fn to_ethers(self) -> Self::Ethers {
+        eAddress::from(self.into_array())
+    }
+}
+
+impl ToAlloy for H256 {
+    type Alloy = B256;
+
+    #[inline]
+    fn to_alloy(self) -> Self::Alloy {
+        B256::from(self.to_fixed_bytes())
+    }
+}
+
+impl ToEthers for B256 {
+    type Ethers = H256;
+
+    #[inline]
+    fn to_ethers(self) -> Self::Ethers {
+        H256::from(self.0)
+    }
+}
+
+impl ToAlloy for eU256 {
+    type Alloy = U256;
+
+    #[inline]
+    fn to_alloy(self) -> Self::Alloy {
+        U256::from_limbs(self.0)
+    }
+}
+
+impl ToEthers for U256 {
+    type Ethers = eU256;
+
+    #[inline]
+    fn to_ethers(self) -> Self::Ethers {
+        eU256(self.into_limbs())
+    }
+}
+
+impl ToAlloy for eI256 {
+    type Alloy = I256;
+
+    #[inline]
+    fn to_alloy(self) -> Self::Alloy {
+        I256::from_raw(U256::from_limbs(self.into_raw().0))
+    }
+}
+
+impl ToEthers for I256 {
+    type Ethers = eI256;
+
+    #[inline]
+    fn to_ethers(self) -> Self::Ethers {
+        eI256::from_raw(eU256(self.into_raw().into_limbs()))
+    }
+}
+
+impl ToAlloy for U64 {
+    type Alloy = alloy_primitives::U64;
+
+    #[inline]
+    fn to_alloy(self) -> Self::Alloy {
+        alloy_primitives::U64::from(self.as_u64())
+    }
+}
+
+impl ToEthers for alloy_primitives::U64 {
+    type Ethers = U64;
+
+    #[inline]
+    fn to_ethers(self) -> Self::Ethers {
+        U64::from(self.to::<u64>())
+    }
+}
+
+impl ToAlloy for eBytes {
+    type Alloy = Bytes;
+
+    #[inline]
+    fn to_alloy(self) -> Self::Alloy {
+        Bytes(self.0)
+    }
+}
+
+impl ToEthers for Bytes {
+    type Ethers = eBytes;
+
+    #[inline]
+    fn to_ethers(self) -> Self::Ethers {
+        eBytes(self.0)
+    }
+}
+
+impl ToAlloy for eLog {
+    type Alloy = Log;
+
+    /// Converts the address, topics, and data of the log. Where the log was
+    /// emitted, e.g., its block or transaction, is not part of an alloy
+    /// [`Log`].
+    fn to_alloy(self) -> Self::Alloy {
+        Log {
+            address: self.address.to_alloy(),
+            data: LogData::new_unchecked(
+                self.topics.into_iter().map(ToAlloy::to_alloy).collect(),
+                self.data.to_alloy(),
+            ),
+        }
+    }
+}
+
+impl ToEthers for Log {
+    type Ethers = eLog;
+
+    /// Converts the address, topics, and data of the log, leaving where it was
+    /// emitted unset.
+    fn to_ethers(self) -> Self::Ethers {
+        eLog {
+            address: self.address.to_ethers(),
+            topics: self
+                .topics()
+                .iter()
+                .map(|topic| topic.to_ethers())
+                .collect(),
+            data: self.data.data.to_ethers(),
+            ..Default::default()
+        }
+    }
+}
+
+impl<T: ToAlloy> ToAlloy for Vec<T> {
+    type Alloy = Vec<T::Alloy>;
+
+    fn to_alloy(self) -> Self::Alloy {
+        self.into_iter().map(ToAlloy::to_alloy).collect()
+    }
+}
+
+impl<T: ToEthers> ToEthers for Vec<T> {
+    type Ethers = Vec<T::Ethers>;
+
+    fn to_ethers(self) -> Self::Ethers {
+        self.into_iter().map(ToEthers::to_ethers).collect()
+    }
+}
+
+impl<T: ToAlloy> ToAlloy for Option<T> {
+    type Alloy = Option<T::Alloy>;
+
+    fn to_alloy(self) -> Self::Alloy {
+        self.map(ToAlloy::to_alloy)
+    }
+}
+
+impl<T: ToEthers> ToEthers for Option<T> {
+    type Ethers = Option<T::Ethers>;
+
+    fn to_ethers(self) -> Self::Ethers {
+        self.map(ToEthers::to_ethers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let address = eAddress::from_low_u64_be(0xbeef);
+        assert_eq!(address.to_alloy().to_ethers(), address);
+        assert_eq!(
+            address.to_alloy(),
+            Address::left_padding_from(&[0xbe, 0xef])
+        );
+
+        let value = eU256::MAX - 1;
+        assert_eq!(value.to_alloy(), U256::MAX - U256::from(1));
+        assert_eq!(value.to_alloy().to_ethers(), value);
+
+        let signed = eI256::from(-69);
+        assert_eq!(signed.to_alloy(), I256::try_from(-69).unwrap());
+        assert_eq!(signed.to_alloy().to_ethers(), signed);
+
+        let log = eLog {
+            address,
+            topics: vec![H256::repeat_byte(1), H256::repeat_byte(2)],
+            data: eBytes::from(vec![1, 2, 3]),
+            ..Default::default()
+        };
+        let alloy_log = log.clone().to_alloy();
+        assert_eq!(alloy_log.topics()[1], B256::repeat_byte(2));
+        assert_eq!(alloy_log.to_ethers(), log);
+    }
+}
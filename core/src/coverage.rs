@@ -0,0 +1,214 @@
+//! The [`coverage`] module contains an [`Inspector`] that tracks which
+//! instructions and branches of the contracts in an [`Environment`] were
+//! executed.
+//!
+//! Coverage is tracked per bytecode, so every contract that shares the same
+//! code (e.g., many instances of the same token) contributes to the same
+//! [`ContractCoverage`]. Deployment code is tracked separately from runtime
+//! code since it has a different hash.
+//!
+//! A "branch" is each of the two outcomes of a `JUMPI`, so a contract that has
+//! `n` conditional jumps has `2n` branches.
+//!
+//! [`Environment`]: crate::environment::Environment
+
+use std::{collections::BTreeSet, fmt};
+
+use revm::{
+    interpreter::{opcode, Interpreter},
+    primitives::B256,
+};
+
+use super::*;
+
+/// The size of the length suffix that `solc` appends to the CBOR encoded
+/// metadata at the end of the bytecode.
+const METADATA_LENGTH_SIZE: usize = 2;
+
+/// An inspector that collects coverage of the bytecode that is executed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Coverage {
+    /// The coverage of each bytecode that was executed keyed by its hash.
+    pub contracts: HashMap<B256, ContractCoverage>,
+}
+
+/// The coverage of a single bytecode.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ContractCoverage {
+    /// The addresses that were seen executing this bytecode.
+    pub addresses: BTreeSet<Address>,
+
+    /// The program counter of every instruction in the bytecode.
+    pub instructions: BTreeSet<usize>,
+
+    /// The program counter of every `JUMPI` in the bytecode.
+    pub jumpis: BTreeSet<usize>,
+
+    /// The program counters of the instructions that were executed.
+    pub executed: BTreeSet<usize>,
+
+    /// The program counters of the `JUMPI`s that jumped.
+    pub taken: BTreeSet<usize>,
+
+    /// The program counters of the `JUMPI`s that fell through.
+    pub not_taken: BTreeSet<usize>,
+}
+
+impl ContractCoverage {
+    /// Creates an empty [`ContractCoverage`] by finding every instruction in
+    /// the given `bytecode`. Push data and the `solc` metadata are skipped.
+    pub fn analyze(bytecode: &[u8]) -> Self {
+        let mut coverage = Self::default();
+        let code = strip_metadata(bytecode);
+        let mut pc = 0;
+        while pc < code.len() {
+            let op = code[pc];
+            coverage.instructions.insert(pc);
+            if op == opcode::JUMPI {
+                coverage.jumpis.insert(pc);
+            }
+            pc += 1;
+            if (opcode::PUSH1..=opcode::PUSH32).contains(&op) {
+                pc += (op - opcode::PUSH1 + 1) as usize;
+            }
+        }
+        coverage
+    }
+
+    /// The number of instructions that were executed along with the total
+    /// number of instructions.
+    pub fn instructions_hit(&self) -> (usize, usize) {
+        (
+            self.executed.intersection(&self.instructions).count(),
+            self.instructions.len(),
+        )
+    }
+
+    /// The number of branches that were taken along with the total number of
+    /// branches.
+    pub fn branches_hit(&self) -> (usize, usize) {
+        (
+            self.taken.len() + self.not_taken.len(),
+            2 * self.jumpis.len(),
+        )
+    }
+
+    /// Merges the hits recorded in `other` into `self`.
+    pub fn merge(&mut self, other: ContractCoverage) {
+        self.addresses.extend(other.addresses);
+        self.instructions.extend(other.instructions);
+        self.jumpis.extend(other.jumpis);
+        self.executed.extend(other.executed);
+        self.taken.extend(other.taken);
+        self.not_taken.extend(other.not_taken);
+    }
+}
+
+impl Coverage {
+    /// Merges the coverage collected in `other` into `self`.
+    pub fn merge(&mut self, other: Coverage) {
+        for (hash, contract) in other.contracts {
+            self.contracts.entry(hash).or_default().merge(contract);
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for Coverage {
+    #[inline]
+    fn initialize_interp(&mut self, interp: &mut Interpreter, _context: &mut EvmContext<DB>) {
+        let contract = &interp.contract;
+        self.contracts
+            .entry(contract.hash)
+            .or_insert_with(|| {
+                ContractCoverage::analyze(contract.bytecode.original_bytecode_slice())
+            })
+            .addresses
+            .insert(contract.address);
+    }
+
+    #[inline]
+    fn step(&mut self, interp: &mut Interpreter, _context: &mut EvmContext<DB>) {
+        let pc = interp.program_counter();
+        let Some(contract) = self.contracts.get_mut(&interp.contract.hash) else {
+            return;
+        };
+        contract.executed.insert(pc);
+        if interp.current_opcode() == opcode::JUMPI {
+            // The condition is the second item on the stack, under the destination.
+            match interp.stack.peek(1) {
+                Ok(condition) if condition != U256::ZERO => contract.taken.insert(pc),
+                Ok(_) => contract.not_taken.insert(pc),
+                Err(_) => false,
+            };
+        }
+    }
+}
+
+impl fmt::Display for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut contracts: Vec<_> = self.contracts.iter().collect();
+        contracts.sort_by_key(|(_, contract)| contract.addresses.first().copied());
+        for (hash, contract) in contracts {
+            let (instructions, total_instructions) = contract.instructions_hit();
+            let (branches, total_branches) = contract.branches_hit();
+            let addresses: Vec<String> = contract
+                .addresses
+                .iter()
+                .map(|address| address.to_string())
+                .collect();
+            writeln!(
+                f,
+                "{} [{}]: instructions {}/{} ({:.1}%), branches {}/{} ({:.1}%)",
+                hash,
+                addresses.join(", "),
+                instructions,
+                total_instructions,
+                percentage(instructions, total_instructions),
+                branches,
+                total_branches,
+                percentage(branches, total_branches),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns `part` as a percentage of `total`, treating an empty `total` as
+/// fully covered.
+fn percentage(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        100.0 * part as f64 / total as f64
+    }
+}
+
+/// Removes the CBOR encoded metadata that `solc` appends to the bytecode so it
+/// is not counted as instructions.
+fn strip_metadata(bytecode: &[u8]) -> &[u8] {
+    if bytecode.len() < METADATA_LENGTH_SIZE {
+        return bytecode;
+    }
+    let length_start = bytecode.len() - METADATA_LENGTH_SIZE;
+    let length = u16::from_be_bytes([bytecode[length_start], bytecode[length_start + 1]]) as usize;
+    match length_start.checked_sub(length) {
+        // The metadata is a CBOR map which starts with `0xa1`-`0xa3`.
+        Some(start) if (0xa1..=0xa3).contains(&bytecode[start]) => &bytecode[..start],
+        _ => bytecode,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_skips_push_data_and_metadata() {
+        // PUSH1 0x01 PUSH1 0x05 JUMPI STOP, followed by a fake metadata map.
+        let bytecode = [0x60, 0x01, 0x60, 0x05, 0x57, 0x00, 0xa2, 0x57, 0x00, 0x02];
+        let coverage = ContractCoverage::analyze(&bytecode);
+        assert_eq!(coverage.instructions, BTreeSet::from([0, 2, 4, 5]));
+        assert_eq!(coverage.jumpis, BTreeSet::from([4]));
+        assert_eq!(coverage.branches_hit(), (0, 2));
+    }
+}
@@ -0,0 +1,68 @@
+//! The [`create2`] module holds the deterministic deployment proxy that every
+//! [`Environment`] has deployed at its canonical address,
+//! [`CREATE2_FACTORY_ADDRESS`], so that contracts can be deployed at addresses
+//! that are known ahead of their deployment with
+//! [`ArbiterMiddleware::deploy_deterministic`].
+//!
+//! The proxy is the one Foundry and Hardhat deploy with, which is deployed on
+//! most chains at the same address. It is called with a 32 byte salt followed
+//! by the creation bytecode of a contract, deploys it with `CREATE2`, and
+//! returns its address. A deployment that fails reverts without any return
+//! data.
+//!
+//! An [`Environment`] that already has code at [`CREATE2_FACTORY_ADDRESS`],
+//! e.g., one forked from a chain that has the proxy deployed, keeps that code
+//! instead.
+//!
+//! [`Environment`]: crate::environment::Environment
+//! [`ArbiterMiddleware::deploy_deterministic`]: crate::middleware::ArbiterMiddleware::deploy_deterministic
+
+use ethers::{
+    types::{Bytes as eBytes, H160},
+    utils::get_create2_address,
+};
+use revm::{interpreter::opcode::*, primitives::Bytecode, DatabaseRef};
+
+use super::*;
+
+/// The canonical address of the deterministic deployment proxy,
+/// `0x4e59b44847b379578588920cA78FbF26c0B4956C`.
+pub const CREATE2_FACTORY_ADDRESS: eAddress = H160([
+    0x4e, 0x59, 0xb4, 0x48, 0x47, 0xb3, 0x79, 0x57, 0x85, 0x88, 0x92, 0x0c, 0xa7, 0x8f, 0xbf, 0x26,
+    0xc0, 0xb4, 0x95, 0x6c,
+]);
+
+/// The runtime bytecode of the deterministic deployment proxy.
+#[rustfmt::skip]
+pub const CREATE2_FACTORY_CODE: &[u8] = &[
+    PUSH32, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xe0, // [-32]
+    CALLDATASIZE, ADD, // [len] the length of the creation bytecode after the salt
+    PUSH1, 0x00, DUP2, PUSH1, 0x20, DUP3, CALLDATACOPY, // [len, 0] the creation bytecode is copied to 0
+    DUP1, CALLDATALOAD, // [len, 0, salt]
+    DUP3, DUP3, CALLVALUE, CREATE2, // [len, 0, salt, address]
+    DUP1, ISZERO, ISZERO, PUSH1, 0x39, JUMPI, // jump to RETURN_ADDRESS
+    DUP2, DUP3, REVERT, // the deployment failed
+    // RETURN_ADDRESS
+    JUMPDEST, DUP1, DUP3, MSTORE, POP, POP, POP, PUSH1, 0x14, PUSH1, 0x0c, RETURN, // return the 20 bytes of the address
+];
+
+/// Returns the address that the creation bytecode `init_code` is deployed at
+/// with the given `salt` through the proxy at [`CREATE2_FACTORY_ADDRESS`].
+pub fn deterministic_address(init_code: impl Into<eBytes>, salt: H256) -> eAddress {
+    get_create2_address(CREATE2_FACTORY_ADDRESS, salt, init_code.into())
+}
+
+/// Deploys the deterministic deployment proxy at [`CREATE2_FACTORY_ADDRESS`]
+/// in `db` unless there already is code at that address.
+pub(crate) fn deploy(db: &ArbiterDB) {
+    let address = Address::from(CREATE2_FACTORY_ADDRESS.to_fixed_bytes());
+    let mut info = db.basic_ref(address).unwrap().unwrap_or_default();
+    if !info.is_empty_code_hash() {
+        return;
+    }
+    let code = Bytecode::new_raw(Bytes::from_static(CREATE2_FACTORY_CODE));
+    info.code_hash = code.hash_slow();
+    info.code = Some(code);
+    db.state.write().unwrap().insert_account_info(address, info);
+}
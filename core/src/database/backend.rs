@@ -0,0 +1,120 @@
+//! The [`StateBackend`] trait lets the state of an [`ArbiterDB`] live
+//! somewhere other than memory.
+//!
+//! The [`CacheDB`] of an [`ArbiterDB`] sits on top of a [`Backend`]: an
+//! account, storage slot, or contract that the cache does not hold yet is read
+//! from the backend and only then kept in memory. Everything the simulation
+//! writes stays in the cache, so the backend itself is read-only. This means a
+//! simulation of a state that does not fit in RAM, e.g., millions of accounts
+//! from a fork, only holds the part of the state it touches in memory.
+//!
+//! See [`DiskDB`](super::disk::DiskDB) for a backend that is stored in a
+//! memory-mapped file.
+
+use super::*;
+
+/// A read-only source of state that the [`CacheDB`] of an [`ArbiterDB`] falls
+/// back to for everything it does not hold yet.
+pub trait StateBackend: Debug + Send + Sync {
+    /// Returns the [`AccountInfo`] of the account at `address` if it exists.
+    /// The code of the account can be left out as it is looked up with
+    /// [`StateBackend::code_by_hash`].
+    fn basic(&self, address: Address) -> Option<AccountInfo>;
+
+    /// Returns the [`Bytecode`] with the given `code_hash`, or empty
+    /// bytecode if it is unknown.
+    fn code_by_hash(&self, code_hash: B256) -> Bytecode;
+
+    /// Returns the value of the storage slot `index` of the account at
+    /// `address`, which is zero for slots that were never written.
+    fn storage(&self, address: Address, index: U256) -> U256;
+}
+
+/// A shared handle to the [`StateBackend`] of an [`ArbiterDB`]. The default
+/// handle has no backend, i.e., the state only lives in memory.
+///
+/// The contents of the backend are not serialized along with the
+/// [`ArbiterDB`], only the state that has been read into or written to its
+/// [`CacheDB`].
+#[derive(Clone, Debug, Default)]
+pub struct Backend(Option<Arc<dyn StateBackend>>);
+
+impl Backend {
+    /// Creates a handle to the given `backend`.
+    pub fn new(backend: impl StateBackend + 'static) -> Self {
+        Self(Some(Arc::new(backend)))
+    }
+
+    /// Returns `true` if the handle has a backend.
+    pub fn is_some(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl DatabaseRef for Backend {
+    type Error = Infallible;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        Ok(self.0.as_ref().and_then(|backend| backend.basic(address)))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        Ok(self
+            .0
+            .as_ref()
+            .map(|backend| backend.code_by_hash(code_hash))
+            .unwrap_or_default())
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        Ok(self
+            .0
+            .as_ref()
+            .map(|backend| backend.storage(address, index))
+            .unwrap_or_default())
+    }
+
+    fn block_hash_ref(&self, number: U256) -> Result<B256, Self::Error> {
+        EmptyDB::default().block_hash_ref(number)
+    }
+}
+
+impl Serialize for Backend {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.is_some() {
+            warn!("The state of the backend is not serialized with the database.");
+        }
+        serializer.serialize_unit()
+    }
+}
+
+impl<'de> Deserialize<'de> for Backend {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Databases written before backends existed store an `EmptyDB` here.
+        serde::de::IgnoredAny::deserialize(deserializer)?;
+        Ok(Self::default())
+    }
+}
+
+/// Moves the in-memory `state` on top of the given `backend`.
+pub(crate) fn layer(state: CacheDB<EmptyDB>, backend: Backend) -> CacheDB<Backend> {
+    CacheDB {
+        accounts: state.accounts,
+        contracts: state.contracts,
+        logs: state.logs,
+        block_hashes: state.block_hashes,
+        db: backend,
+    }
+}
+
+/// Reads the account at `address` from the [`Backend`] of `state` unless
+/// `state` already holds it, so that the account can be accessed through the
+/// `accounts` of the [`CacheDB`] directly.
+pub(crate) fn load_account(state: &mut CacheDB<Backend>, address: Address) {
+    if state.accounts.contains_key(&address) {
+        return;
+    }
+    if let Ok(Some(info)) = state.db.basic_ref(address) {
+        state.insert_account_info(address, info);
+    }
+}
@@ -0,0 +1,90 @@
+//! Log bloom filters that are computed for every transaction and block in the
+//! [`environment::Environment`] so that event filtering can skip over logs
+//! that cannot possibly match a [`Filter`].
+
+pub use ethers::types::Bloom;
+use ethers::{abi::ethereum_types::BloomInput, types::ValueOrArray};
+
+use super::*;
+
+/// Computes the bloom of the addresses and topics of the given `revm` logs.
+pub fn logs_bloom<'a>(logs: impl IntoIterator<Item = &'a Log>) -> Bloom {
+    let mut bloom = Bloom::default();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_slice()));
+        for topic in log.topics() {
+            bloom.accrue(BloomInput::Raw(topic.as_slice()));
+        }
+    }
+    bloom
+}
+
+/// Computes the bloom of the addresses and topics of the given `ethers` logs.
+pub fn ethers_logs_bloom<'a>(logs: impl IntoIterator<Item = &'a eLog>) -> Bloom {
+    let mut bloom = Bloom::default();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in &log.topics {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
+
+/// Returns `false` if the `bloom` proves that none of the logs it was built
+/// from match the address and topics of the `filter`. A `true` result may be a
+/// false positive, so the logs still need to be checked individually.
+pub fn bloom_matches(bloom: &Bloom, filter: &Filter) -> bool {
+    let contains = |input: &[u8]| bloom.contains_input(BloomInput::Raw(input));
+    let address_matches = match &filter.address {
+        None => true,
+        Some(ValueOrArray::Value(address)) => contains(address.as_bytes()),
+        Some(ValueOrArray::Array(addresses)) => {
+            addresses.is_empty() || addresses.iter().any(|address| contains(address.as_bytes()))
+        }
+    };
+    address_matches
+        && filter.topics.iter().all(|topic| match topic {
+            None | Some(ValueOrArray::Value(None)) => true,
+            Some(ValueOrArray::Value(Some(topic))) => contains(topic.as_bytes()),
+            Some(ValueOrArray::Array(topics)) => {
+                topics.is_empty()
+                    || topics
+                        .iter()
+                        .any(|topic| topic.is_none_or(|topic| contains(topic.as_bytes())))
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use revm_primitives::{address, b256, LogData};
+
+    use super::*;
+
+    #[test]
+    fn matches_only_present_addresses_and_topics() {
+        let emitter = address!("067ea9e44c76a2620f10b39a1b51d5124a299192");
+        let topic = b256!("8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b925");
+        let log = Log {
+            address: emitter,
+            data: LogData::new_unchecked(vec![topic], Bytes::new()),
+        };
+        let bloom = logs_bloom([&log]);
+
+        let filter = Filter::new()
+            .address(eAddress::from(emitter.into_array()))
+            .topic0(H256::from(topic.0));
+        assert!(bloom_matches(&bloom, &filter));
+        assert!(bloom_matches(&bloom, &Filter::new()));
+        assert!(!bloom_matches(
+            &bloom,
+            &Filter::new().address(eAddress::zero())
+        ));
+        assert!(!bloom_matches(&bloom, &Filter::new().topic0(H256::zero())));
+        assert!(bloom_matches(
+            &bloom,
+            &Filter::new().topic0(vec![H256::zero(), H256::from(topic.0)])
+        ));
+    }
+}
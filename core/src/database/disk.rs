@@ -0,0 +1,332 @@
+//! The [`DiskDB`] is a [`StateBackend`] stored in a memory-mapped file so
+//! that state which does not fit in memory, e.g., millions of accounts from a
+//! [`Fork`](super::fork::Fork), can still be simulated. The operating system
+//! pages in only the parts of the file that the simulation reads.
+//!
+//! The file is written once with [`DiskDB::write`] and is read-only after
+//! that. It consists of a header followed by a table of accounts sorted by
+//! address, a table of contracts sorted by code hash, and the storage and code
+//! those tables point into. Storage slots are sorted per account so every read
+//! is a binary search of the mapped file.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use revm::{db::AccountState, primitives::KECCAK_EMPTY};
+
+use super::{backend::StateBackend, *};
+
+/// Identifies a [`DiskDB`] file and the version of its layout.
+const MAGIC: &[u8; 8] = b"ARBDISK1";
+
+/// The magic followed by the number of accounts and contracts.
+const HEADER_SIZE: usize = 24;
+
+/// An address, the balance, the nonce, the code hash, and the offset and
+/// number of the storage slots of the account.
+const ACCOUNT_SIZE: usize = 20 + 32 + 8 + 32 + 8 + 8;
+
+/// A code hash and the offset and length of the code.
+const CODE_SIZE: usize = 32 + 8 + 8;
+
+/// A storage index and its value.
+const SLOT_SIZE: usize = 32 + 32;
+
+/// A read-only [`StateBackend`] stored in a memory-mapped file.
+pub struct DiskDB {
+    path: PathBuf,
+    mmap: Mmap,
+    accounts: usize,
+    codes: usize,
+}
+
+impl DiskDB {
+    /// Writes the accounts, storage, and contracts held in memory by `state`
+    /// to a new file at `path` and opens it. An existing file at `path` is
+    /// replaced.
+    pub fn write<ExtDB>(
+        path: impl AsRef<Path>,
+        state: &CacheDB<ExtDB>,
+    ) -> Result<Self, ArbiterCoreError> {
+        let path = path.as_ref();
+        let mut accounts: Vec<_> = state
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.account_state != AccountState::NotExisting)
+            .collect();
+        accounts.sort_unstable_by_key(|(address, _)| **address);
+
+        let mut codes = BTreeMap::new();
+        for (_, account) in &accounts {
+            let code_hash = account.info.code_hash;
+            if code_hash == KECCAK_EMPTY || code_hash == B256::ZERO {
+                continue;
+            }
+            if let Some(code) = state
+                .contracts
+                .get(&code_hash)
+                .or(account.info.code.as_ref())
+            {
+                codes.insert(code_hash, code.original_bytes());
+            }
+        }
+
+        let partial = path.with_extension("partial");
+        let write = || -> std::io::Result<()> {
+            let mut file = BufWriter::new(File::create(&partial)?);
+            file.write_all(MAGIC)?;
+            file.write_all(&(accounts.len() as u64).to_le_bytes())?;
+            file.write_all(&(codes.len() as u64).to_le_bytes())?;
+
+            let mut offset = HEADER_SIZE + accounts.len() * ACCOUNT_SIZE + codes.len() * CODE_SIZE;
+            let mut storages = Vec::with_capacity(accounts.len());
+            for (address, account) in &accounts {
+                let mut storage: Vec<_> = account
+                    .storage
+                    .iter()
+                    .filter(|(_, value)| !value.is_zero())
+                    .collect();
+                storage.sort_unstable_by_key(|(index, _)| **index);
+                file.write_all(address.as_slice())?;
+                file.write_all(&account.info.balance.to_be_bytes::<32>())?;
+                file.write_all(&account.info.nonce.to_le_bytes())?;
+                file.write_all(account.info.code_hash.as_slice())?;
+                file.write_all(&(offset as u64).to_le_bytes())?;
+                file.write_all(&(storage.len() as u64).to_le_bytes())?;
+                offset += storage.len() * SLOT_SIZE;
+                storages.push(storage);
+            }
+            for (code_hash, code) in &codes {
+                file.write_all(code_hash.as_slice())?;
+                file.write_all(&(offset as u64).to_le_bytes())?;
+                file.write_all(&(code.len() as u64).to_le_bytes())?;
+                offset += code.len();
+            }
+            for (index, value) in storages.into_iter().flatten() {
+                file.write_all(&index.to_be_bytes::<32>())?;
+                file.write_all(&value.to_be_bytes::<32>())?;
+            }
+            for code in codes.values() {
+                file.write_all(code)?;
+            }
+            file.into_inner()?.sync_all()?;
+            // Renaming keeps any mapping of a previous file at `path` intact.
+            fs::rename(&partial, path)
+        };
+        write().map_err(|e| {
+            ArbiterCoreError::DiskDBError(format!("Failed to write {:?}: {}", path, e))
+        })?;
+        debug!(
+            "Wrote {} accounts and {} contracts to {:?}",
+            accounts.len(),
+            codes.len(),
+            path
+        );
+        Self::open(path)
+    }
+
+    /// Opens the [`DiskDB`] file at `path` that was written by
+    /// [`DiskDB::write`].
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ArbiterCoreError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path).map_err(|e| {
+            ArbiterCoreError::DiskDBError(format!("Failed to open {:?}: {}", path, e))
+        })?;
+        // SAFETY: The file is only ever replaced by renaming a new file over
+        // it, so the mapped contents do not change while they are read.
+        let mmap = unsafe { Mmap::map(&file) }.map_err(|e| {
+            ArbiterCoreError::DiskDBError(format!("Failed to map {:?}: {}", path, e))
+        })?;
+        if mmap.len() < HEADER_SIZE || &mmap[..8] != MAGIC {
+            return Err(invalid(&path, "the header is missing"));
+        }
+        let accounts = read_u64(&mmap, 8) as usize;
+        let codes = read_u64(&mmap, 16) as usize;
+        let tables = accounts
+            .checked_mul(ACCOUNT_SIZE)
+            .zip(codes.checked_mul(CODE_SIZE))
+            .and_then(|(accounts, codes)| accounts.checked_add(codes))
+            .and_then(|tables| tables.checked_add(HEADER_SIZE));
+        if tables.is_none_or(|tables| tables > mmap.len()) {
+            return Err(invalid(&path, "the tables are truncated"));
+        }
+
+        // Every read is checked against the file once here so that reading
+        // from the database cannot fail afterwards.
+        let db = Self {
+            path,
+            mmap,
+            accounts,
+            codes,
+        };
+        let fits = |offset: u64, len: u64| {
+            offset
+                .checked_add(len)
+                .is_some_and(|end| end <= db.mmap.len() as u64)
+        };
+        for account in db.account_table().chunks_exact(ACCOUNT_SIZE) {
+            let slots = read_u64(account, 100).checked_mul(SLOT_SIZE as u64);
+            if !slots.is_some_and(|slots| fits(read_u64(account, 92), slots)) {
+                return Err(invalid(&db.path, "the storage of an account is truncated"));
+            }
+        }
+        for code in db.code_table().chunks_exact(CODE_SIZE) {
+            if !fits(read_u64(code, 32), read_u64(code, 40)) {
+                return Err(invalid(&db.path, "the code of a contract is truncated"));
+            }
+        }
+        Ok(db)
+    }
+
+    /// The path of the file backing the database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of accounts in the database.
+    pub fn accounts(&self) -> usize {
+        self.accounts
+    }
+
+    fn account_table(&self) -> &[u8] {
+        &self.mmap[HEADER_SIZE..HEADER_SIZE + self.accounts * ACCOUNT_SIZE]
+    }
+
+    fn code_table(&self) -> &[u8] {
+        let start = HEADER_SIZE + self.accounts * ACCOUNT_SIZE;
+        &self.mmap[start..start + self.codes * CODE_SIZE]
+    }
+
+    fn account(&self, address: Address) -> Option<&[u8]> {
+        find(self.account_table(), ACCOUNT_SIZE, address.as_slice())
+    }
+}
+
+impl StateBackend for DiskDB {
+    fn basic(&self, address: Address) -> Option<AccountInfo> {
+        let account = self.account(address)?;
+        Some(AccountInfo {
+            balance: U256::from_be_slice(&account[20..52]),
+            nonce: read_u64(account, 52),
+            code_hash: B256::from_slice(&account[60..92]),
+            code: None,
+        })
+    }
+
+    fn code_by_hash(&self, code_hash: B256) -> Bytecode {
+        find(self.code_table(), CODE_SIZE, code_hash.as_slice())
+            .map(|code| {
+                let offset = read_u64(code, 32) as usize;
+                let len = read_u64(code, 40) as usize;
+                Bytecode::new_raw(Bytes::copy_from_slice(&self.mmap[offset..offset + len]))
+            })
+            .unwrap_or_default()
+    }
+
+    fn storage(&self, address: Address, index: U256) -> U256 {
+        let Some(account) = self.account(address) else {
+            return U256::ZERO;
+        };
+        let offset = read_u64(account, 92) as usize;
+        let slots = read_u64(account, 100) as usize;
+        find(
+            &self.mmap[offset..offset + slots * SLOT_SIZE],
+            SLOT_SIZE,
+            &index.to_be_bytes::<32>(),
+        )
+        .map(|slot| U256::from_be_slice(&slot[32..]))
+        .unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for DiskDB {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DiskDB")
+            .field("path", &self.path)
+            .field("accounts", &self.accounts)
+            .field("codes", &self.codes)
+            .finish()
+    }
+}
+
+/// The error for a file at `path` that is not a valid [`DiskDB`].
+fn invalid(path: &Path, reason: &str) -> ArbiterCoreError {
+    ArbiterCoreError::DiskDBError(format!("{:?} is not a valid database: {}", path, reason))
+}
+
+/// Reads the little-endian `u64` at `offset` in `bytes`.
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Binary searches the `table` of `size` byte entries that are sorted by
+/// their leading bytes for the entry that starts with `key`.
+fn find<'a>(table: &'a [u8], size: usize, key: &[u8]) -> Option<&'a [u8]> {
+    let (mut low, mut high) = (0, table.len() / size);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let entry = &table[middle * size..(middle + 1) * size];
+        match entry[..key.len()].cmp(key) {
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+            std::cmp::Ordering::Equal => return Some(entry),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn state_is_read_back_from_disk() {
+        let path = env::temp_dir().join(format!("arbiter_disk_db_{}.db", std::process::id()));
+        let code = Bytecode::new_raw(Bytes::from_static(&[0x60, 0x00, 0x60, 0x00, 0xf3]));
+        let mut state = CacheDB::new(EmptyDB::default());
+        for byte in 1..=100u8 {
+            let address = Address::repeat_byte(byte);
+            state.insert_account_info(
+                address,
+                AccountInfo {
+                    balance: U256::from(byte),
+                    nonce: byte as u64,
+                    code_hash: code.hash_slow(),
+                    code: Some(code.clone()),
+                },
+            );
+            for index in 0..byte as u64 {
+                state
+                    .insert_account_storage(address, U256::from(index), U256::from(index + 1))
+                    .unwrap();
+            }
+        }
+
+        let db = DiskDB::write(&path, &state).unwrap();
+        assert_eq!(db.accounts(), 100);
+        let address = Address::repeat_byte(42);
+        let info = db.basic(address).unwrap();
+        assert_eq!(info.balance, U256::from(42));
+        assert_eq!(info.nonce, 42);
+        assert_eq!(db.code_by_hash(info.code_hash), code);
+        assert_eq!(db.storage(address, U256::from(41)), U256::from(42));
+        assert_eq!(db.storage(address, U256::from(42)), U256::ZERO);
+        assert!(db.basic(Address::ZERO).is_none());
+        assert_eq!(db.storage(Address::ZERO, U256::ZERO), U256::ZERO);
+        drop(db);
+
+        fs::write(&path, b"not a database").unwrap();
+        assert!(matches!(
+            DiskDB::open(&path),
+            Err(ArbiterCoreError::DiskDBError(_))
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
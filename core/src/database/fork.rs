@@ -3,8 +3,16 @@
 //! [`Fork`] contains a [`CacheDB`] and [`ContractMetadata`] so
 //! that the [`Environment`] can be initialized with a forked database and the
 //! end-user still has access to the relevant metadata.
+//!
+//! It also contains the [`DiskCache`] which stores the state read from a
+//! remote node on disk so that forking the same block again does not need to
+//! hit the node.
 
-use std::{env, fs};
+use std::{
+    env, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
 
 use super::*;
 
@@ -101,3 +109,227 @@ pub struct DiskData {
     /// This is the eoa data that will be loaded into the [`Fork`].
     pub externally_owned_accounts: HashMap<String, eAddress>,
 }
+
+/// The remote state read through a [`DiskCache`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RemoteState {
+    accounts: HashMap<Address, Option<AccountInfo>>,
+    storage: HashMap<Address, HashMap<U256, U256>>,
+    block_hashes: HashMap<U256, B256>,
+}
+
+/// A [`DiskCache`] wraps a [`Database`] that reads state from a remote node,
+/// e.g., revm's `EthersDB`, and caches every account, storage slot, and block
+/// hash it reads in a file keyed by the block number being forked.
+///
+/// Reads that are already in the cache never reach the remote node, so
+/// forking the same block again only queries the node for state that was not
+/// read before. The cache is written to disk by [`DiskCache::flush`] or when
+/// the [`DiskCache`] is dropped. Since only the block number is part of the
+/// key, a separate directory should be used for each network.
+#[derive(Debug)]
+pub struct DiskCache<DB> {
+    db: DB,
+    path: PathBuf,
+    state: RemoteState,
+    dirty: bool,
+}
+
+impl<DB> DiskCache<DB> {
+    /// Creates a new [`DiskCache`] in front of `db` which reads state at
+    /// `block_number`. Previously cached state is loaded from `directory`.
+    ///
+    /// A cache file that cannot be parsed is ignored and overwritten.
+    pub fn new(
+        db: DB,
+        directory: impl AsRef<Path>,
+        block_number: u64,
+    ) -> Result<Self, ArbiterCoreError> {
+        let path = directory.as_ref().join(format!("{}.json", block_number));
+        let state = match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable fork cache at {:?}: {}", path, e);
+                RemoteState::default()
+            }),
+            Err(e) if e.kind() == ErrorKind::NotFound => RemoteState::default(),
+            Err(e) => {
+                return Err(ArbiterCoreError::ForkCacheError(format!(
+                    "Failed to read fork cache at {:?}: {}",
+                    path, e
+                )))
+            }
+        };
+        debug!(
+            "Loaded {} accounts from the fork cache at {:?}",
+            state.accounts.len(),
+            path
+        );
+        Ok(Self {
+            db,
+            path,
+            state,
+            dirty: false,
+        })
+    }
+
+    /// The path of the file that the cache is written to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes everything that was read so far to disk.
+    pub fn flush(&mut self) -> Result<(), ArbiterCoreError> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let to_cache_error = |e: std::io::Error| {
+            ArbiterCoreError::ForkCacheError(format!(
+                "Failed to write fork cache at {:?}: {}",
+                self.path, e
+            ))
+        };
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).map_err(to_cache_error)?;
+        }
+        // Write to a temporary file first so an interrupted write never leaves
+        // a truncated cache behind.
+        let partial = self.path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_vec(&self.state)?).map_err(to_cache_error)?;
+        fs::rename(&partial, &self.path).map_err(to_cache_error)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl<DB: Database> Database for DiskCache<DB> {
+    type Error = DB::Error;
+
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        if let Some(info) = self.state.accounts.get(&address) {
+            return Ok(info.clone());
+        }
+        let info = self.db.basic(address)?;
+        self.state.accounts.insert(address, info.clone());
+        self.dirty = true;
+        Ok(info)
+    }
+
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        // The code is cached along with the account it belongs to.
+        self.db.code_by_hash(code_hash)
+    }
+
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        if let Some(value) = self
+            .state
+            .storage
+            .get(&address)
+            .and_then(|storage| storage.get(&index))
+        {
+            return Ok(*value);
+        }
+        let value = self.db.storage(address, index)?;
+        self.state
+            .storage
+            .entry(address)
+            .or_default()
+            .insert(index, value);
+        self.dirty = true;
+        Ok(value)
+    }
+
+    fn block_hash(&mut self, number: U256) -> Result<B256, Self::Error> {
+        if let Some(hash) = self.state.block_hashes.get(&number) {
+            return Ok(*hash);
+        }
+        let hash = self.db.block_hash(number)?;
+        self.state.block_hashes.insert(number, hash);
+        self.dirty = true;
+        Ok(hash)
+    }
+}
+
+impl<DB> Drop for DiskCache<DB> {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            warn!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A remote database that counts how often it is queried.
+    #[derive(Default)]
+    struct CountingDB {
+        queries: usize,
+    }
+
+    impl Database for CountingDB {
+        type Error = Infallible;
+
+        fn basic(&mut self, _address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+            self.queries += 1;
+            Ok(Some(AccountInfo {
+                balance: U256::from(42),
+                ..Default::default()
+            }))
+        }
+
+        fn code_by_hash(&mut self, _code_hash: B256) -> Result<Bytecode, Self::Error> {
+            self.queries += 1;
+            Ok(Bytecode::default())
+        }
+
+        fn storage(&mut self, _address: Address, index: U256) -> Result<U256, Self::Error> {
+            self.queries += 1;
+            Ok(index + U256::from(1))
+        }
+
+        fn block_hash(&mut self, _number: U256) -> Result<B256, Self::Error> {
+            self.queries += 1;
+            Ok(B256::repeat_byte(1))
+        }
+    }
+
+    #[test]
+    fn remote_reads_are_cached_on_disk() {
+        let directory = env::temp_dir().join(format!("arbiter_fork_cache_{}", std::process::id()));
+        let address = Address::repeat_byte(7);
+
+        let mut cache = DiskCache::new(CountingDB::default(), &directory, 1).unwrap();
+        cache.basic(address).unwrap();
+        cache.basic(address).unwrap();
+        assert_eq!(
+            cache.storage(address, U256::from(3)).unwrap(),
+            U256::from(4)
+        );
+        cache.block_hash(U256::from(1)).unwrap();
+        assert_eq!(cache.db.queries, 3);
+        drop(cache);
+
+        // A second run at the same block is served entirely from disk.
+        let mut cache = DiskCache::new(CountingDB::default(), &directory, 1).unwrap();
+        assert_eq!(
+            cache.basic(address).unwrap().unwrap().balance,
+            U256::from(42)
+        );
+        assert_eq!(
+            cache.storage(address, U256::from(3)).unwrap(),
+            U256::from(4)
+        );
+        cache.block_hash(U256::from(1)).unwrap();
+        assert_eq!(cache.db.queries, 0);
+        drop(cache);
+
+        // Another block starts with an empty cache.
+        let mut cache = DiskCache::new(CountingDB::default(), &directory, 2).unwrap();
+        cache.basic(address).unwrap();
+        assert_eq!(cache.db.queries, 1);
+        drop(cache);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,7 +1,8 @@
 //! This module contains an extensible [`Inspector`] called
 //! [`ArbiterInspector`]. It is currently configurable in order to allow
-//! for users to set configuration to see logs generated in Solidity contracts
-//! and or enforce gas payment.
+//! for users to set configuration to see logs generated in Solidity contracts,
+//! enforce gas payment, track the coverage of executed contracts, and or
+//! trace calls.
 
 use revm::{
     inspectors::GasInspector,
@@ -9,13 +10,17 @@ use revm::{
 };
 
 use super::*;
-use crate::console::ConsoleLogs;
+use crate::{
+    console::ConsoleLogs, coverage::Coverage, environment::parallel::CoinbaseReads,
+    trace::CallTracer,
+};
 
 /// An configurable [`Inspector`] that collects information about the
 /// execution of the [`Interpreter`]. Depending on whether which or both
 /// features are enabled, it collects information about the gas used by each
-/// opcode and the `console2.log`s emitted during execution. It ensures gas
-/// payments are made when `gas` is enabled.
+/// opcode, the `console2.log`s emitted during execution, and the instructions
+/// and branches that were executed. It ensures gas payments are made when
+/// `gas` is enabled.
 #[derive(Debug, Clone)]
 pub struct ArbiterInspector {
     /// Whether to collect `console2.log`s.
@@ -23,11 +28,22 @@ pub struct ArbiterInspector {
 
     /// Whether to collect gas usage information.
     pub gas: Option<GasInspector>,
+
+    /// Whether to collect instruction and branch coverage.
+    pub coverage: Option<Coverage>,
+
+    /// Whether to record the [`CallTrace`](crate::trace::CallTrace) of what
+    /// is executed.
+    pub call_tracer: Option<CallTracer>,
+
+    /// Whether to record if the block beneficiary is read, which speculative
+    /// executions of a parallel batch do.
+    pub(crate) coinbase_reads: Option<CoinbaseReads>,
 }
 
 impl ArbiterInspector {
     /// Create a new [`ArbiterInspector`] with the given configuration.
-    pub fn new(console_log: bool, gas: bool) -> Self {
+    pub fn new(console_log: bool, gas: bool, coverage: bool) -> Self {
         let console_log = if console_log {
             Some(ConsoleLogs::default())
         } else {
@@ -38,31 +54,65 @@ impl ArbiterInspector {
         } else {
             None
         };
-        Self { console_log, gas }
+        let coverage = if coverage {
+            Some(Coverage::default())
+        } else {
+            None
+        };
+        Self {
+            console_log,
+            gas,
+            coverage,
+            call_tracer: None,
+            coinbase_reads: None,
+        }
     }
 }
 
-impl Inspector<ArbiterDB> for ArbiterInspector {
+impl<DB: Database> Inspector<DB> for ArbiterInspector {
     #[inline]
-    fn initialize_interp(&mut self, interp: &mut Interpreter, context: &mut EvmContext<ArbiterDB>) {
+    fn initialize_interp(&mut self, interp: &mut Interpreter, context: &mut EvmContext<DB>) {
         if let Some(gas) = &mut self.gas {
             gas.initialize_interp(interp, context);
         }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.initialize_interp(interp, context);
+        }
+    }
+
+    #[inline]
+    fn step(&mut self, interp: &mut Interpreter, context: &mut EvmContext<DB>) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.step(interp, context);
+        }
+        if let Some(coinbase_reads) = &mut self.coinbase_reads {
+            coinbase_reads.step(interp, context.env.block.coinbase);
+        }
     }
 
     #[inline]
-    fn step_end(&mut self, interp: &mut Interpreter, context: &mut EvmContext<ArbiterDB>) {
+    fn step_end(&mut self, interp: &mut Interpreter, context: &mut EvmContext<DB>) {
         if let Some(gas) = &mut self.gas {
             gas.step_end(interp, context);
         }
     }
 
+    #[inline]
+    fn log(&mut self, context: &mut EvmContext<DB>, log: &Log) {
+        if let Some(call_tracer) = &mut self.call_tracer {
+            call_tracer.log(context, log);
+        }
+    }
+
     #[inline]
     fn call(
         &mut self,
-        context: &mut EvmContext<ArbiterDB>,
+        context: &mut EvmContext<DB>,
         inputs: &mut CallInputs,
     ) -> Option<CallOutcome> {
+        if let Some(call_tracer) = &mut self.call_tracer {
+            call_tracer.call(context, inputs);
+        }
         if let Some(console_log) = &mut self.console_log {
             console_log.call(context, inputs)
         } else {
@@ -73,24 +123,45 @@ impl Inspector<ArbiterDB> for ArbiterInspector {
     #[inline]
     fn call_end(
         &mut self,
-        context: &mut EvmContext<ArbiterDB>,
+        context: &mut EvmContext<DB>,
         inputs: &CallInputs,
         outcome: CallOutcome,
     ) -> CallOutcome {
-        if let Some(gas) = &mut self.gas {
+        let outcome = if let Some(gas) = &mut self.gas {
             gas.call_end(context, inputs, outcome)
         } else {
             outcome
+        };
+        if let Some(call_tracer) = &mut self.call_tracer {
+            call_tracer.call_end(context, inputs, outcome)
+        } else {
+            outcome
         }
     }
 
+    #[inline]
+    fn create(
+        &mut self,
+        context: &mut EvmContext<DB>,
+        inputs: &mut CreateInputs,
+    ) -> Option<CreateOutcome> {
+        if let Some(call_tracer) = &mut self.call_tracer {
+            call_tracer.create(context, inputs);
+        }
+        None
+    }
+
     #[inline]
     fn create_end(
         &mut self,
-        _context: &mut EvmContext<ArbiterDB>,
-        _inputs: &CreateInputs,
+        context: &mut EvmContext<DB>,
+        inputs: &CreateInputs,
         outcome: CreateOutcome,
     ) -> CreateOutcome {
-        outcome
+        if let Some(call_tracer) = &mut self.call_tracer {
+            call_tracer.create_end(context, inputs, outcome)
+        } else {
+            outcome
+        }
     }
 }
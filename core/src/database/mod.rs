@@ -5,12 +5,16 @@
 //!
 //! Further, it gives the ability to be generated from a [`fork::Fork`] so that
 //! you can preload an [`environment::Environment`] with a specific state.
+//!
+//! The state can also be read from a [`backend::StateBackend`], e.g., a
+//! [`disk::DiskDB`], for simulations whose state does not fit in memory.
 
 use std::{
     fs,
     io::{self, Read, Write},
 };
 
+use ethers::types::{Block, TransactionReceipt};
 use revm::{
     primitives::{db::DatabaseRef, keccak256, Bytecode, B256},
     DatabaseCommit,
@@ -18,23 +22,43 @@ use revm::{
 use serde_json;
 
 use super::*;
+pub mod backend;
+pub mod bloom;
+pub mod disk;
 pub mod fork;
 pub mod inspector;
+use backend::Backend;
+use bloom::{ethers_logs_bloom, Bloom};
 
 /// A [`ArbiterDB`] is contains both a [`CacheDB`] that is used to provide
 /// state for the [`environment::Environment`]'s as well as for multiple
 /// [`coprocessor::Coprocessor`]s.
 /// The `logs` field is a [`HashMap`] to store [`ethers::types::Log`]s that can
-/// be queried from at any point.
+/// be queried from at any point along with the `blooms` of each block's logs.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ArbiterDB {
     /// The state of the `ArbiterDB`. This is a `CacheDB` that is used to
-    /// provide a db for the `Environment` to use.
-    pub state: Arc<RwLock<CacheDB<EmptyDB>>>,
+    /// provide a db for the `Environment` to use. It holds everything that has
+    /// been written or read so far on top of its [`Backend`].
+    pub state: Arc<RwLock<CacheDB<Backend>>>,
 
     /// The logs of the `ArbiterDB`. This is a `HashMap` that is used to store
     /// logs that can be queried from at any point.
     pub logs: Arc<RwLock<HashMap<U256, Vec<eLog>>>>,
+
+    /// The log [`Bloom`] of every block that has logs. These are used to skip
+    /// over blocks that cannot contain logs matching a filter.
+    #[serde(default)]
+    pub blooms: Arc<RwLock<HashMap<U256, Bloom>>>,
+
+    /// The receipt of every transaction that has been executed, keyed by its
+    /// hash.
+    #[serde(default)]
+    pub receipts: Arc<RwLock<HashMap<H256, TransactionReceipt>>>,
+
+    /// Every block that has been ended by a block update, keyed by its number.
+    #[serde(default)]
+    pub blocks: Arc<RwLock<HashMap<U256, Block<H256>>>>,
 }
 
 // Implement `Clone` by hand so we utilize the `Arc`'s `Clone` implementation.
@@ -43,6 +67,9 @@ impl Clone for ArbiterDB {
         Self {
             state: self.state.clone(),
             logs: self.logs.clone(),
+            blooms: self.blooms.clone(),
+            receipts: self.receipts.clone(),
+            blocks: self.blocks.clone(),
         }
     }
 }
@@ -51,11 +78,40 @@ impl ArbiterDB {
     /// Create a new `ArbiterDB`.
     pub fn new() -> Self {
         Self {
-            state: Arc::new(RwLock::new(CacheDB::new(EmptyDB::new()))),
+            state: Arc::new(RwLock::new(CacheDB::new(Backend::default()))),
             logs: Arc::new(RwLock::new(HashMap::new())),
+            blooms: Arc::new(RwLock::new(HashMap::new())),
+            receipts: Arc::new(RwLock::new(HashMap::new())),
+            blocks: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
+    /// Recomputes the `blooms` of every block from the stored `logs`. This is
+    /// needed when the logs are replaced, e.g., by logs from a previous
+    /// simulation.
+    pub fn rebuild_blooms(&self) {
+        let blooms = self
+            .logs
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(block, logs)| (*block, ethers_logs_bloom(logs)))
+            .collect();
+        *self.blooms.write().unwrap() = blooms;
+    }
+
+    /// Replaces everything in this `ArbiterDB` with the contents of `other`,
+    /// e.g., a state that was saved before, while keeping the handles to it
+    /// shared, so that an [`environment::Environment`] running on it continues
+    /// from the restored state.
+    pub fn restore(&self, other: &ArbiterDB) {
+        *self.state.write().unwrap() = other.state.read().unwrap().clone();
+        *self.logs.write().unwrap() = other.logs.read().unwrap().clone();
+        *self.blooms.write().unwrap() = other.blooms.read().unwrap().clone();
+        *self.receipts.write().unwrap() = other.receipts.read().unwrap().clone();
+        *self.blocks.write().unwrap() = other.blocks.read().unwrap().clone();
+    }
+
     /// Write the `ArbiterDB` to a file at the given path.``
     pub fn write_to_file(&self, path: &str) -> io::Result<()> {
         // Serialize the ArbiterDB
@@ -76,14 +132,19 @@ impl ArbiterDB {
         // Deserialize the content into ArbiterDB
         #[derive(Deserialize)]
         struct TempDB {
-            state: Option<CacheDB<EmptyDB>>,
+            state: Option<CacheDB<Backend>>,
             logs: Option<HashMap<U256, Vec<eLog>>>,
         }
         let temp_db: TempDB = serde_json::from_str(&contents)?;
-        Ok(Self {
+        let db = Self {
             state: Arc::new(RwLock::new(temp_db.state.unwrap_or_default())),
             logs: Arc::new(RwLock::new(temp_db.logs.unwrap_or_default())),
-        })
+            blooms: Arc::default(),
+            receipts: Arc::default(),
+            blocks: Arc::default(),
+        };
+        db.rebuild_blooms();
+        Ok(db)
     }
 }
 
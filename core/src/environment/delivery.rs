@@ -0,0 +1,209 @@
+//! Delivery of [`Broadcast`]s from the [`Environment`] to its subscribers.
+//!
+//! By default every subscriber reads from the same bounded channel, so a
+//! subscriber that falls more than [`EVENT_CHANNEL_CAPACITY`] broadcasts behind
+//! the [`Environment`] misses events. The [`DeliveryPolicy`] of a subscriber
+//! decides what happens in that case and every missed event is counted so it
+//! can be surfaced with [`Environment::lagged_events`].
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+};
+
+use tokio::sync::{
+    broadcast::{
+        channel,
+        error::{RecvError, TryRecvError},
+    },
+    mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+};
+
+use super::*;
+
+/// The number of [`Broadcast`]s the shared bounded channel holds before
+/// subscribers that have not read them start to lag.
+pub const EVENT_CHANNEL_CAPACITY: usize = 512;
+
+/// How [`Broadcast`]s are delivered to a subscriber that falls behind the
+/// [`Environment`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryPolicy {
+    /// The subscriber reads from the shared bounded channel and receives an
+    /// [`ArbiterCoreError::EventsLaggedError`] once it has missed events.
+    BoundedWithError,
+
+    /// The subscriber gets its own unbounded queue so it never misses an event
+    /// at the cost of buffering everything it has not read yet.
+    Unbounded,
+
+    /// The subscriber reads from the shared bounded channel and skips over the
+    /// events it missed, which are counted and logged.
+    #[default]
+    DropWithCounter,
+}
+
+/// Sends [`Broadcast`]s to every subscriber according to its
+/// [`DeliveryPolicy`].
+#[derive(Clone, Debug)]
+pub(crate) struct EventBroadcaster {
+    /// The shared bounded channel used by the lossy policies.
+    sender: BroadcastSender<Broadcast>,
+
+    /// The queues of the subscribers using [`DeliveryPolicy::Unbounded`].
+    unbounded: Arc<Mutex<Vec<UnboundedSender<Broadcast>>>>,
+
+    /// The total number of events missed by all subscribers.
+    lagged: Arc<AtomicU64>,
+}
+
+/// The receiving end of a subscription to an [`EventBroadcaster`].
+#[derive(Debug)]
+pub(crate) struct EventReceiver {
+    receiver: Receiver,
+    policy: DeliveryPolicy,
+    broadcaster: EventBroadcaster,
+}
+
+#[derive(Debug)]
+enum Receiver {
+    Bounded(BroadcastReceiver<Broadcast>),
+    Unbounded(UnboundedReceiver<Broadcast>),
+}
+
+impl EventBroadcaster {
+    /// Creates a new [`EventBroadcaster`] without any subscribers.
+    pub(crate) fn new() -> Self {
+        Self {
+            sender: channel(EVENT_CHANNEL_CAPACITY).0,
+            unbounded: Arc::new(Mutex::new(Vec::new())),
+            lagged: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Sends the `broadcast` to every subscriber and returns how many
+    /// subscribers there are.
+    pub(crate) fn send(&self, broadcast: Broadcast) -> usize {
+        let mut unbounded = self.unbounded.lock().unwrap();
+        unbounded.retain(|sender| sender.send(broadcast.clone()).is_ok());
+        unbounded.len() + self.sender.send(broadcast).unwrap_or(0)
+    }
+
+    /// Subscribes with the given [`DeliveryPolicy`].
+    pub(crate) fn subscribe_with(&self, policy: DeliveryPolicy) -> EventReceiver {
+        let receiver = match policy {
+            DeliveryPolicy::Unbounded => {
+                let (sender, receiver) = unbounded_channel();
+                self.unbounded.lock().unwrap().push(sender);
+                Receiver::Unbounded(receiver)
+            }
+            DeliveryPolicy::BoundedWithError | DeliveryPolicy::DropWithCounter => {
+                Receiver::Bounded(self.sender.subscribe())
+            }
+        };
+        EventReceiver {
+            receiver,
+            policy,
+            broadcaster: self.clone(),
+        }
+    }
+
+    /// The total number of events missed by all subscribers.
+    pub(crate) fn lagged(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
+    }
+}
+
+impl EventReceiver {
+    /// Replaces this subscription with one that uses the given
+    /// [`DeliveryPolicy`].
+    pub(crate) fn with_policy(self, policy: DeliveryPolicy) -> Self {
+        self.broadcaster.subscribe_with(policy)
+    }
+
+    /// Waits for the next [`Broadcast`]. Returns `None` once the
+    /// [`Environment`] is gone.
+    pub(crate) async fn recv(&mut self) -> Option<Result<Broadcast, ArbiterCoreError>> {
+        loop {
+            let result = match &mut self.receiver {
+                Receiver::Unbounded(receiver) => return receiver.recv().await.map(Ok),
+                Receiver::Bounded(receiver) => receiver.recv().await,
+            };
+            match result {
+                Ok(broadcast) => return Some(Ok(broadcast)),
+                Err(RecvError::Closed) => return None,
+                Err(RecvError::Lagged(missed)) => {
+                    if let Some(e) = self.lag(missed) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the next [`Broadcast`] if one is ready.
+    pub(crate) fn try_recv(&mut self) -> Option<Result<Broadcast, ArbiterCoreError>> {
+        loop {
+            let result = match &mut self.receiver {
+                Receiver::Unbounded(receiver) => return receiver.try_recv().ok().map(Ok),
+                Receiver::Bounded(receiver) => receiver.try_recv(),
+            };
+            match result {
+                Ok(broadcast) => return Some(Ok(broadcast)),
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+                Err(TryRecvError::Lagged(missed)) => {
+                    if let Some(e) = self.lag(missed) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Counts the `missed` events and returns the error to hand to the
+    /// subscriber if its policy asks for one.
+    fn lag(&self, missed: u64) -> Option<ArbiterCoreError> {
+        self.broadcaster.lagged.fetch_add(missed, Ordering::Relaxed);
+        warn!(
+            "A subscriber fell behind the environment and missed {} events.",
+            missed
+        );
+        match self.policy {
+            DeliveryPolicy::BoundedWithError => Some(ArbiterCoreError::EventsLaggedError(missed)),
+            DeliveryPolicy::Unbounded | DeliveryPolicy::DropWithCounter => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overflow(broadcaster: &EventBroadcaster) {
+        for _ in 0..EVENT_CHANNEL_CAPACITY + 2 {
+            broadcaster.send(Broadcast::StopSignal);
+        }
+    }
+
+    #[test]
+    fn lag_is_handled_per_policy() {
+        let broadcaster = EventBroadcaster::new();
+        let mut dropping = broadcaster.subscribe_with(DeliveryPolicy::DropWithCounter);
+        let mut erroring = broadcaster.subscribe_with(DeliveryPolicy::BoundedWithError);
+        let mut unbounded = broadcaster.subscribe_with(DeliveryPolicy::Unbounded);
+        overflow(&broadcaster);
+
+        assert!(matches!(dropping.try_recv(), Some(Ok(_))));
+        assert!(matches!(
+            erroring.try_recv(),
+            Some(Err(ArbiterCoreError::EventsLaggedError(2)))
+        ));
+        assert_eq!(broadcaster.lagged(), 4);
+
+        let mut received = 0;
+        while let Some(Ok(_)) = unbounded.try_recv() {
+            received += 1;
+        }
+        assert_eq!(received, EVENT_CHANNEL_CAPACITY + 2);
+    }
+}
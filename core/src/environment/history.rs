@@ -0,0 +1,71 @@
+//! The [`StateHistory`] keeps the state of the [`Environment`] at the end of
+//! its most recent blocks so that calls can be made against the state of a
+//! past block.
+
+use super::*;
+
+/// The block environment and state at the end of each of the most recent
+/// blocks, oldest first.
+#[derive(Debug, Default)]
+pub(crate) struct StateHistory {
+    /// The number of blocks that are kept.
+    depth: usize,
+
+    /// The kept blocks.
+    blocks: VecDeque<(BlockEnv, CacheDB<Backend>)>,
+}
+
+impl StateHistory {
+    /// Creates a [`StateHistory`] that keeps the last `depth` blocks.
+    pub(crate) fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            blocks: VecDeque::with_capacity(depth),
+        }
+    }
+
+    /// Keeps a copy of `state` as the state at the end of `block`, forgetting
+    /// the oldest block if there are more than `depth` blocks.
+    pub(crate) fn record(&mut self, block: &BlockEnv, state: &CacheDB<Backend>) {
+        if self.depth == 0 {
+            return;
+        }
+        if self.blocks.len() == self.depth {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((block.clone(), state.clone()));
+    }
+
+    /// Returns the block environment and state at the end of the block with
+    /// the given `number` if it is kept.
+    pub(crate) fn get(&self, number: U256) -> Option<&(BlockEnv, CacheDB<Backend>)> {
+        self.blocks
+            .iter()
+            .rev()
+            .find(|(block, _)| block.number == number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_most_recent_blocks() {
+        let mut history = StateHistory::new(2);
+        for number in 0..3 {
+            let block = BlockEnv {
+                number: U256::from(number),
+                ..Default::default()
+            };
+            history.record(&block, &CacheDB::default());
+        }
+        assert!(history.get(U256::from(0)).is_none());
+        assert!(history.get(U256::from(1)).is_some());
+        assert!(history.get(U256::from(2)).is_some());
+
+        let mut disabled = StateHistory::new(0);
+        disabled.record(&BlockEnv::default(), &CacheDB::default());
+        assert!(disabled.get(U256::ZERO).is_none());
+    }
+}
@@ -3,6 +3,7 @@
 //! [`middleware::ArbiterMiddleware`] and the [`Environment`].
 
 use super::*;
+use crate::{database::bloom::Bloom, trace::CallTrace};
 
 /// [`Instruction`]s that can be sent to the [`Environment`] via the
 /// [`Socket`].
@@ -18,7 +19,7 @@ use super::*;
 ///
 /// The [`Instruction`]s are sent to the [`Environment`] via the
 ///   [`Socket::instruction_sender`] and the results are received via the
-///   [`crate::middleware::Connection::outcome_receiver`].
+///   [`crate::middleware::connection::Connection`].
 #[derive(Debug, Clone)]
 pub(crate) enum Instruction {
     /// An `AddAccount` is used to add a default/unfunded account to the
@@ -41,6 +42,9 @@ pub(crate) enum Instruction {
         /// The block timestamp to update the [`EVM`] to.
         block_timestamp: eU256,
 
+        /// The coinbase and base fee of the new block.
+        settings: BlockSettings,
+
         /// The sender used to to send the outcome of the block update back to.
         outcome_sender: OutcomeSender,
     },
@@ -51,6 +55,14 @@ pub(crate) enum Instruction {
         /// The transaction environment for the call.
         tx_env: TxEnv,
 
+        /// The block whose state the call is made against, or the current
+        /// state if `None`.
+        block_number: Option<eU256>,
+
+        /// Whether to record the [`CallTrace`](crate::trace::CallTrace) of the
+        /// call.
+        trace: bool,
+
         /// The sender used to to send the outcome of the call back to.
         outcome_sender: OutcomeSender,
     },
@@ -98,6 +110,46 @@ pub(crate) enum Instruction {
     },
 }
 
+impl Instruction {
+    /// The name of the [`Profiler`](crate::profiler::Profiler) section that
+    /// the execution of the instruction is recorded into.
+    pub(crate) fn section(&self) -> &'static str {
+        match self {
+            Instruction::AddAccount { .. } => "environment/add_account",
+            Instruction::BlockUpdate { .. } => "environment/block_update",
+            Instruction::Call { .. } => "environment/call",
+            Instruction::Cheatcode { .. } => "environment/cheatcode",
+            Instruction::Query { .. } => "environment/query",
+            Instruction::SetGasPrice { .. } => "environment/set_gas_price",
+            Instruction::Stop(_) => "environment/stop",
+            Instruction::Transaction { .. } => "environment/transaction",
+        }
+    }
+
+    /// Returns `true` if the instruction advances the simulation and is
+    /// therefore held back while the [`Environment`] is paused.
+    pub(crate) fn is_held_while_paused(&self) -> bool {
+        matches!(
+            self,
+            Instruction::BlockUpdate { .. } | Instruction::Transaction { .. }
+        )
+    }
+
+    /// The sender used to send the outcome of the instruction back to.
+    pub(crate) fn outcome_sender(&self) -> &OutcomeSender {
+        match self {
+            Instruction::AddAccount { outcome_sender, .. }
+            | Instruction::BlockUpdate { outcome_sender, .. }
+            | Instruction::Call { outcome_sender, .. }
+            | Instruction::Cheatcode { outcome_sender, .. }
+            | Instruction::Query { outcome_sender, .. }
+            | Instruction::SetGasPrice { outcome_sender, .. }
+            | Instruction::Stop(outcome_sender)
+            | Instruction::Transaction { outcome_sender, .. } => outcome_sender,
+        }
+    }
+}
+
 /// [`Outcome`]s that can be sent back to the the client via the
 /// [`Socket`].
 /// These outcomes can be from `Call`, `Transaction`, or `BlockUpdate`
@@ -121,6 +173,10 @@ pub(crate) enum Outcome {
     /// of some [`EVM`] computation to the client.
     CallCompleted(ExecutionResult),
 
+    /// The outcome of a `Call` instruction with `trace` set, which also
+    /// provides the [`CallTrace`] of the call.
+    CallTraced(ExecutionResult, Box<CallTrace>),
+
     /// The outcome of a [`Instruction::SetGasPrice`] instruction that is used
     /// to signify that the gas price was set successfully.
     SetGasPriceCompleted,
@@ -156,6 +212,9 @@ pub(crate) enum EnvironmentData {
     /// The query is for the gas price of the [`EVM`].
     GasPrice,
 
+    /// The query is for the chain ID of the [`EVM`].
+    ChainId,
+
     /// The query is for the balance of an account given by the inner `Address`.
     Balance(eAddress),
 
@@ -168,6 +227,31 @@ pub(crate) enum EnvironmentData {
         /// The filter to use to query for logs
         filter: Filter,
     },
+
+    /// Query for the instruction and branch coverage collected so far.
+    Coverage,
+
+    /// Query for a block by its number. Past blocks are only known once a
+    /// block update ended them.
+    Block(BlockNumber),
+
+    /// Query for the receipt of a transaction given by its hash.
+    TransactionReceipt(H256),
+
+    /// Query for the fee history of the `block_count` blocks up to and
+    /// including `newest_block`, with the priority fees paid at each of the
+    /// `reward_percentiles` of the gas used in every block.
+    FeeHistory {
+        /// The number of blocks to return the history of.
+        block_count: u64,
+
+        /// The newest block to return the history of.
+        newest_block: BlockNumber,
+
+        /// The percentiles of the gas used in a block to return the priority
+        /// fees of, in increasing order between `0` and `100`.
+        reward_percentiles: Vec<f64>,
+    },
 }
 
 /// [`ReceiptData`] is a structure that holds the block number, transaction
@@ -177,12 +261,77 @@ pub struct ReceiptData {
     /// `block_number` is the number of the block in which the transaction was
     /// included.
     pub block_number: U64,
+    /// `transaction_hash` is the hash the
+    /// [`Environment`](crate::environment::Environment) assigned to the
+    /// transaction, or zero when returned from a block update.
+    #[serde(default)]
+    pub transaction_hash: H256,
     /// `transaction_index` is the index position of the transaction in the
     /// block.
     pub transaction_index: U64,
     /// `cumulative_gas_per_block` is the total amount of gas used in the
     /// block up until and including the transaction.
     pub cumulative_gas_per_block: eU256,
+    /// `logs_bloom` is the bloom of the logs emitted by the transaction, or of
+    /// the whole block when returned from a block update.
+    pub logs_bloom: Bloom,
+    /// `state_diff` is how the transaction changed the state when the
+    /// [`Environment`](crate::environment::Environment) records state diffs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_diff: Option<StateDiff>,
+    /// `fees` are the fees paid by the transactions of the block when
+    /// returned from a block update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fees: Option<BlockFees>,
+    /// `settlement_fee` is the fee the sender paid to settle the transaction
+    /// when the [`Environment`](crate::environment::Environment) has a
+    /// [`SettlementModel`](crate::settlement::SettlementModel).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub settlement_fee: Option<eU256>,
+    /// `effective_gas_price` is the price per gas the transaction paid, i.e.,
+    /// its gas price, or for an EIP-1559 transaction the base fee plus its
+    /// priority fee capped at its maximum fee.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_gas_price: Option<eU256>,
+    /// `transaction_type` is the EIP-2718 type of the transaction, i.e., `0`
+    /// for legacy, `1` for EIP-2930, and `2` for EIP-1559 transactions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transaction_type: Option<U64>,
+}
+
+/// The settings of a new block that can be changed along with its number and
+/// timestamp. Settings that are `None` are carried over from the previous
+/// block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSettings {
+    /// The beneficiary of the block that is credited with the priority fees
+    /// of its transactions.
+    pub coinbase: Option<eAddress>,
+
+    /// The base fee per gas of the block. Transactions with a lower gas price
+    /// are rejected.
+    pub base_fee: Option<eU256>,
+}
+
+/// The fees paid by the transactions of a block.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockFees {
+    /// The beneficiary of the block.
+    pub coinbase: eAddress,
+
+    /// The base fee per gas of the block.
+    pub base_fee: eU256,
+
+    /// The priority fees that were credited to the coinbase.
+    pub priority_fees: eU256,
+
+    /// The base fees that were burned, or credited to the
+    /// [`EnvironmentParameters::base_fee_recipient`] if there is one.
+    pub base_fees: eU256,
+
+    /// The fees that were paid to settle the transactions, see
+    /// [`SettlementModel`](crate::settlement::SettlementModel).
+    pub settlement_fees: eU256,
 }
 
 /// Cheatcodes are a direct way to access the underlying [`EVM`] environment and
@@ -223,6 +372,24 @@ pub enum Cheatcodes {
         /// The address of the account to fetch.
         address: ethers::types::Address,
     },
+    /// Sets the balance of an account in the [`EVM`], creating the account if
+    /// it does not exist.
+    SetBalance {
+        /// The address of the account to set the balance of.
+        address: eAddress,
+
+        /// The balance to set for the account.
+        amount: eU256,
+    },
+    /// Sets the nonce of an account in the [`EVM`], creating the account if it
+    /// does not exist.
+    SetNonce {
+        /// The address of the account to set the nonce of.
+        address: eAddress,
+
+        /// The nonce to set for the account.
+        nonce: u64,
+    },
 }
 
 /// Wrapper around [`AccountState`] that can be serialized and deserialized.
@@ -254,6 +421,10 @@ pub enum CheatcodesReturn {
     Store,
     /// A `Deal` returns nothing.
     Deal,
+    /// A `SetBalance` returns nothing.
+    SetBalance,
+    /// A `SetNonce` returns nothing.
+    SetNonce,
     /// Gets the DbAccount associated with an address.
     Access {
         /// Basic account information like nonce, balance, code hash, bytcode.
@@ -16,16 +16,29 @@
 //! - [`Instruction`]: Enum indicating the type of instruction that is being
 //!   sent to the EVM.
 
-use std::thread::{self, JoinHandle};
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread::{self, JoinHandle},
+};
 
-use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
-use ethers::{abi::AbiDecode, types::ValueOrArray};
+use crossbeam_channel::{bounded, never, select, unbounded, Receiver, Sender};
+use ethers::{
+    abi::AbiDecode,
+    types::{
+        Block, BlockNumber, FeeHistory, FilterBlockOption, FilteredParams, TransactionReceipt,
+    },
+};
 use revm::{
     db::AccountState,
     inspector_handle_register,
-    primitives::{Env, HashMap, B256},
+    primitives::{
+        db::DatabaseRef, keccak256, BlockEnv, EVMError, Env, HashMap, ResultAndState, State,
+        TransactTo, B256,
+    },
+    DatabaseCommit,
 };
-use tokio::sync::broadcast::channel;
 
 use super::*;
 #[cfg_attr(doc, doc(hidden))]
@@ -33,13 +46,33 @@ use super::*;
 #[cfg(doc)]
 use crate::middleware::ArbiterMiddleware;
 use crate::{
-    console::abi::HardhatConsoleCalls, database::inspector::ArbiterInspector,
-    middleware::connection::revm_logs_to_ethers_logs,
+    console::abi::HardhatConsoleCalls,
+    create2,
+    database::{
+        backend::{layer, load_account, Backend, StateBackend},
+        bloom::{bloom_matches, logs_bloom, Bloom},
+        inspector::ArbiterInspector,
+    },
+    middleware::connection::{revm_logs_to_ethers_logs, transaction_receipt},
+    multicall,
+    profiler::Profiler,
+    settlement::SettlementModel,
+    state_diff::StateDiff,
+    trace::{CallTracer, Tracer},
 };
 
+pub mod delivery;
+pub(crate) use delivery::{EventBroadcaster, EventReceiver};
+
 pub mod instruction;
 use instruction::*;
 
+mod history;
+use history::StateHistory;
+
+pub(crate) mod parallel;
+use parallel::{AccessSet, Speculation};
+
 /// Alias for the sender of the channel for transmitting transactions.
 pub(crate) type InstructionSender = Sender<Instruction>;
 
@@ -54,6 +87,17 @@ pub(crate) type OutcomeSender = Sender<Result<Outcome, ArbiterCoreError>>;
 /// emitted from transactions.
 pub(crate) type OutcomeReceiver = Receiver<Result<Outcome, ArbiterCoreError>>;
 
+/// Controls sent to the [`Environment`] alongside its [`Instruction`]s. Each
+/// control is acknowledged once it has taken effect.
+#[derive(Debug)]
+pub(crate) enum Control {
+    /// Holds back every [`Instruction`] that advances the simulation.
+    Pause(Sender<()>),
+
+    /// Executes the held back [`Instruction`]s and continues as normal.
+    Resume(Sender<()>),
+}
+
 /// Represents a sandboxed EVM environment.
 ///
 /// ## Features
@@ -64,7 +108,7 @@ pub(crate) type OutcomeReceiver = Receiver<Result<Outcome, ArbiterCoreError>>;
 ///   sharing of execution and write-only via the main thread. This can also be
 ///   a database read in from disk storage via [`database::fork::Fork`].
 /// * [`ArbiterInspector`] is an that allows for the EVM to be able to display
-///   logs and properly handle gas payments.
+///   logs, properly handle gas payments, and track coverage.
 /// * [`EnvironmentParameters`] are used to set the gas limit, contract size
 ///   limit, and label for the [`Environment`].
 #[derive(Debug)]
@@ -87,6 +131,16 @@ pub struct Environment {
     /// Used for assuring that the environment is stopped properly or for
     /// performing any blocking action the end user needs.
     pub(crate) handle: Option<JoinHandle<Result<(), ArbiterCoreError>>>,
+
+    /// Records the time spent executing instructions when profiling is
+    /// enabled.
+    pub(crate) profiler: Profiler,
+
+    /// Records the executed transactions when tracing is enabled.
+    pub(crate) tracer: Tracer,
+
+    /// The number of instructions held back while the environment is paused.
+    held: Arc<AtomicUsize>,
 }
 
 /// Parameters to create [`Environment`]s with different settings.
@@ -107,6 +161,97 @@ pub struct EnvironmentParameters {
     /// Allows for turning off any gas payments for transactions so no inspector
     /// is needed.
     pub pay_gas: bool,
+
+    /// Enables optimistic parallel execution of transactions that are queued
+    /// at the same time.
+    pub parallel_execution: bool,
+
+    /// Enables tracking of the instructions and branches that are executed in
+    /// each contract.
+    pub coverage: bool,
+
+    /// Enables the [`Profiler`] so that the time spent executing each kind of
+    /// [`Instruction`] is recorded.
+    pub profiling: bool,
+
+    /// The [`DeliveryPolicy`](delivery::DeliveryPolicy) of the clients
+    /// connected to the [`Environment`] unless they pick their own.
+    pub delivery_policy: delivery::DeliveryPolicy,
+
+    /// The gas limit of each transaction and call sent by the clients
+    /// connected to the [`Environment`] unless they set their own. Without it
+    /// transactions can use all the gas they want.
+    pub transaction_gas_limit: Option<u64>,
+
+    /// The percentage that gas estimates of the clients connected to the
+    /// [`Environment`] are padded with unless they set their own, e.g., `20`
+    /// to estimate 20% more gas than a transaction needs. Estimates are not
+    /// padded without it.
+    #[serde(default)]
+    pub gas_estimate_buffer: u64,
+
+    /// Enables the [`Tracer`] so that every executed transaction is recorded.
+    pub tracing: bool,
+
+    /// Enables recording the [`StateDiff`] of every transaction on its
+    /// receipt.
+    #[serde(default)]
+    pub state_diffs: bool,
+
+    /// The beneficiary of the blocks that is credited with the priority fees
+    /// until a block update sets another one.
+    #[serde(default)]
+    pub coinbase: Option<Address>,
+
+    /// The base fee per gas of the blocks until a block update sets another
+    /// one.
+    #[serde(default)]
+    pub base_fee: Option<U256>,
+
+    /// The account that is credited with the base fees instead of burning
+    /// them, e.g., the fee vault of a rollup.
+    #[serde(default)]
+    pub base_fee_recipient: Option<Address>,
+
+    /// The [`SettlementModel`] that charges transactions for settling them on
+    /// another chain, e.g., the L1 data fee of a rollup.
+    #[serde(skip)]
+    pub settlement_model: Option<Arc<dyn SettlementModel>>,
+
+    /// The account that is credited with the settlement fees instead of
+    /// burning them.
+    #[serde(default)]
+    pub settlement_fee_recipient: Option<Address>,
+
+    /// The number of past blocks whose state is kept so that calls can be
+    /// made against it. No past state is kept without it.
+    #[serde(default)]
+    pub state_history: Option<usize>,
+
+    /// When the transactions executed by the [`Environment`] are mined, which
+    /// decides when their
+    /// [`PendingTransaction`](ethers::providers::PendingTransaction)s
+    /// resolve to their receipts.
+    #[serde(default)]
+    pub mining_mode: MiningMode,
+}
+
+/// When the transactions executed by an [`Environment`] are considered mined.
+///
+/// Transactions are always executed as soon as they are received. The mining
+/// mode only decides when they are part of a mined block, i.e., when their
+/// receipts can be fetched and their pending transactions resolve.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MiningMode {
+    /// Every transaction is mined as soon as it is executed, so its pending
+    /// transaction resolves right away.
+    #[default]
+    Instant,
+
+    /// Transactions are mined when a block update ends the block they were
+    /// executed in. Until then their receipts cannot be fetched and their
+    /// pending transactions keep waiting.
+    OnBlockUpdate,
 }
 
 /// A builder for creating an [`Environment`].
@@ -138,6 +283,44 @@ impl EnvironmentBuilder {
         self
     }
 
+    /// Sets the beneficiary of the blocks that is credited with the priority
+    /// fees of their transactions. It can be changed for each block with
+    /// [`ArbiterMiddleware::update_block_with`].
+    pub fn with_coinbase(mut self, coinbase: Address) -> Self {
+        self.parameters.coinbase = Some(coinbase);
+        self
+    }
+
+    /// Sets the base fee per gas of the blocks, which is burned for every unit
+    /// of gas a transaction uses. It can be changed for each block with
+    /// [`ArbiterMiddleware::update_block_with`].
+    pub fn with_base_fee(mut self, base_fee: U256) -> Self {
+        self.parameters.base_fee = Some(base_fee);
+        self
+    }
+
+    /// Credits the base fees paid by transactions to `recipient` instead of
+    /// burning them.
+    pub fn with_base_fee_recipient(mut self, recipient: Address) -> Self {
+        self.parameters.base_fee_recipient = Some(recipient);
+        self
+    }
+
+    /// Charges every transaction the fee of the given [`SettlementModel`] on
+    /// top of its gas, e.g., an [`L1DataFee`](crate::settlement::L1DataFee)
+    /// to evaluate strategies under the economics of a rollup.
+    pub fn with_settlement_model(mut self, model: impl SettlementModel + 'static) -> Self {
+        self.parameters.settlement_model = Some(Arc::new(model));
+        self
+    }
+
+    /// Credits the settlement fees paid by transactions to `recipient`
+    /// instead of burning them.
+    pub fn with_settlement_fee_recipient(mut self, recipient: Address) -> Self {
+        self.parameters.settlement_fee_recipient = Some(recipient);
+        self
+    }
+
     /// Sets the contract size limit for the [`Environment`].
     pub fn with_contract_size_limit(mut self, contract_size_limit: usize) -> Self {
         self.parameters.contract_size_limit = Some(contract_size_limit);
@@ -147,7 +330,17 @@ impl EnvironmentBuilder {
     /// Sets the state for the [`Environment`]. This can come from a saved state
     /// of a simulation or a [`database::fork::Fork`].
     pub fn with_state(mut self, state: impl Into<CacheDB<EmptyDB>>) -> Self {
-        self.db.state = Arc::new(RwLock::new(state.into()));
+        let backend = self.db.state.read().unwrap().db.clone();
+        self.db.state = Arc::new(RwLock::new(layer(state.into(), backend)));
+        self
+    }
+
+    /// Sets the [`StateBackend`] that the
+    /// state of the [`Environment`] falls back to for accounts, storage, and
+    /// contracts it has not read yet, e.g., a [`database::disk::DiskDB`]. Only
+    /// the state that is touched by the simulation is kept in memory.
+    pub fn with_backend(self, backend: impl StateBackend + 'static) -> Self {
+        self.db.state.write().unwrap().db = Backend::new(backend);
         self
     }
 
@@ -158,6 +351,7 @@ impl EnvironmentBuilder {
         logs: impl Into<std::collections::HashMap<U256, Vec<eLog>>>,
     ) -> Self {
         self.db.logs = Arc::new(RwLock::new(logs.into()));
+        self.db.rebuild_blooms();
         self
     }
 
@@ -182,6 +376,90 @@ impl EnvironmentBuilder {
         self.parameters.pay_gas = true;
         self
     }
+
+    /// Turns on the optimistic concurrency executor so that transactions
+    /// queued at the same time are executed in parallel. Transactions that
+    /// conflict with an earlier transaction in the same batch are re-executed
+    /// serially so the resulting state matches serial execution.
+    pub fn with_parallel_execution(mut self) -> Self {
+        self.parameters.parallel_execution = true;
+        self
+    }
+
+    /// Turns on coverage tracking so that the instructions and branches
+    /// executed in each contract are recorded. A summary is logged at `info`
+    /// level when the [`Environment`] is stopped and the full
+    /// [`Coverage`](crate::coverage::Coverage) can be retrieved with
+    /// [`ArbiterMiddleware::coverage`].
+    pub fn with_coverage(mut self) -> Self {
+        self.parameters.coverage = true;
+        self
+    }
+
+    /// Turns on the [`Profiler`] so that the time spent executing each kind of
+    /// [`Instruction`] is recorded. Clients connected to the [`Environment`]
+    /// share the same profiler and can record their own sections into it.
+    pub fn with_profiling(mut self) -> Self {
+        self.parameters.profiling = true;
+        self
+    }
+
+    /// Sets the [`DeliveryPolicy`](delivery::DeliveryPolicy) of clients whose
+    /// subscriptions fall behind the [`Environment`]. Defaults to
+    /// [`DeliveryPolicy::DropWithCounter`](delivery::DeliveryPolicy::DropWithCounter).
+    pub fn with_delivery_policy(mut self, policy: delivery::DeliveryPolicy) -> Self {
+        self.parameters.delivery_policy = policy;
+        self
+    }
+
+    /// Sets the gas limit of each transaction and call sent by the clients
+    /// connected to the [`Environment`] so that a transaction that uses more
+    /// fails with [`ArbiterCoreError::OutOfGas`].
+    pub fn with_transaction_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.parameters.transaction_gas_limit = Some(gas_limit);
+        self
+    }
+
+    /// Pads the gas estimates of the clients connected to the [`Environment`]
+    /// by `percent` percent of the gas the transactions need, so that a
+    /// transaction that is sent with its estimate still has room when the
+    /// state changes before it executes.
+    pub fn with_gas_estimate_buffer(mut self, percent: u64) -> Self {
+        self.parameters.gas_estimate_buffer = percent;
+        self
+    }
+
+    /// Turns on the [`Tracer`] so that every transaction the [`Environment`]
+    /// executes is recorded in order, e.g., to compare two runs of the same
+    /// simulation.
+    pub fn with_tracing(mut self) -> Self {
+        self.parameters.tracing = true;
+        self
+    }
+
+    /// Records how each transaction changes the balance, nonce, and storage of
+    /// every account it touches and attaches the [`StateDiff`] to its receipt.
+    pub fn with_state_diffs(mut self) -> Self {
+        self.parameters.state_diffs = true;
+        self
+    }
+
+    /// Keeps the state at the end of each of the last `blocks` blocks so that
+    /// calls can be made against it, e.g., with
+    /// [`ArbiterMiddleware::call_at`]. Each kept block holds a copy of the
+    /// whole state, so the memory this takes grows with both `blocks` and the
+    /// size of the state.
+    pub fn with_state_history(mut self, blocks: usize) -> Self {
+        self.parameters.state_history = Some(blocks);
+        self
+    }
+
+    /// Sets the [`MiningMode`] of the [`Environment`]. Defaults to
+    /// [`MiningMode::Instant`].
+    pub fn with_mining_mode(mut self, mining_mode: MiningMode) -> Self {
+        self.parameters.mining_mode = mining_mode;
+        self
+    }
 }
 
 impl Environment {
@@ -195,29 +473,47 @@ impl Environment {
     }
 
     fn create(parameters: EnvironmentParameters, db: ArbiterDB) -> Self {
+        multicall::deploy(&db);
+        create2::deploy(&db);
         let (instruction_sender, instruction_receiver) = unbounded();
-        let (event_broadcaster, _) = channel(512);
+        let event_broadcaster = EventBroadcaster::new();
+        let (control_sender, control_receiver) = unbounded();
         let socket = Socket {
             instruction_sender: Arc::new(instruction_sender),
             instruction_receiver,
+            control_sender,
+            control_receiver,
             event_broadcaster,
         };
 
-        let inspector = if parameters.console_logs || parameters.pay_gas {
+        let inspector = if parameters.console_logs || parameters.pay_gas || parameters.coverage {
             Some(ArbiterInspector::new(
                 parameters.console_logs,
                 parameters.pay_gas,
+                parameters.coverage,
             ))
         } else {
-            Some(ArbiterInspector::new(false, false))
+            Some(ArbiterInspector::new(false, false, false))
         };
 
+        let profiler = Profiler::default();
+        if parameters.profiling {
+            profiler.enable();
+        }
+        let tracer = Tracer::default();
+        if parameters.tracing {
+            tracer.enable();
+        }
+
         Self {
             socket,
             inspector,
             parameters,
             db,
             handle: None,
+            profiler,
+            tracer,
+            held: Arc::default(),
         }
     }
 
@@ -226,6 +522,13 @@ impl Environment {
     fn run(mut self) -> Self {
         // Bring in parameters for the `Environment`.
         let label = self.parameters.label.clone();
+        let console_logs = self.parameters.console_logs;
+        let pay_gas = self.parameters.pay_gas;
+        let parallel_execution = self.parameters.parallel_execution;
+        let coverage = self.parameters.coverage;
+        let mining_mode = self.parameters.mining_mode;
+        let mut history = StateHistory::new(self.parameters.state_history.unwrap_or_default());
+        let finalizer = Finalizer::new(&self.parameters);
 
         // Bring in the EVM db and log storage by cloning the interior Arc
         // (lightweight).
@@ -235,12 +538,22 @@ impl Environment {
         let mut env = Env::default();
         env.cfg.limit_contract_code_size = self.parameters.contract_size_limit;
         env.block.gas_limit = self.parameters.gas_limit.unwrap_or(U256::MAX);
+        if let Some(coinbase) = self.parameters.coinbase {
+            env.block.coinbase = coinbase;
+        }
+        if let Some(base_fee) = self.parameters.base_fee {
+            env.block.basefee = base_fee;
+        }
         // Bring in the inspector
         let inspector = self.inspector.take().unwrap();
 
         // Pull communication clones to move into a new thread.
         let instruction_receiver = self.socket.instruction_receiver.clone();
+        let mut control_receiver = self.socket.control_receiver.clone();
         let event_broadcaster = self.socket.event_broadcaster.clone();
+        let profiler = self.profiler.clone();
+        let tracer = self.tracer.clone();
+        let held_count = self.held.clone();
 
         // Move the EVM and its socket to a new thread and retrieve this handle
         let handle = thread::spawn(move || {
@@ -253,16 +566,35 @@ impl Environment {
                 .build();
 
             // Initialize counters that are returned on some receipts.
-            let mut transaction_index = U64::from(0_u64);
-            let mut cumulative_gas_per_block = eU256::from(0);
+            let mut counters = BlockCounters::new(evm.block());
+
+            // The gas price clients pay by default. It is kept apart from the transaction
+            // environment of the EVM, which every call and transaction overwrites with its
+            // own.
+            let mut current_gas_price = U256::ZERO;
+
+            // An instruction that was pulled off the socket while batching
+            // transactions and still needs to be processed.
+            let mut deferred: Option<Instruction> = None;
+
+            // The instructions that were held back while the environment is paused.
+            let mut paused = false;
+            let mut held = Held::new(held_count);
 
             // Loop over the instructions sent through the socket.
-            while let Ok(instruction) = instruction_receiver.recv() {
+            while let Some(instruction) = next_instruction(
+                &mut deferred,
+                &mut held,
+                &mut paused,
+                &instruction_receiver,
+                &mut control_receiver,
+            ) {
                 trace!(
                     "Instruction {:?} received by environment labeled: {:?}",
                     instruction,
                     label
                 );
+                let _timer = profiler.timer(instruction.section());
                 match instruction {
                     Instruction::AddAccount {
                         address,
@@ -274,36 +606,71 @@ impl Environment {
                             account_state: AccountState::None,
                             storage: HashMap::new(),
                         };
-                        match db.state.write()?.accounts.insert(recast_address, account) {
-                            None => outcome_sender.send(Ok(Outcome::AddAccountCompleted))?,
+                        let mut state = db.state.write()?;
+                        load_account(&mut state, recast_address);
+                        match state.accounts.insert(recast_address, account) {
+                            None => reply(&outcome_sender, Ok(Outcome::AddAccountCompleted)),
                             Some(_) => {
-                                outcome_sender.send(Err(ArbiterCoreError::AccountCreationError))?;
+                                reply(&outcome_sender, Err(ArbiterCoreError::AccountCreationError));
                             }
                         }
                     }
                     Instruction::BlockUpdate {
                         block_number,
                         block_timestamp,
+                        settings,
                         outcome_sender,
                     } => {
                         // Return the old block data in a `ReceiptData`
                         let old_block_number = evm.block().number;
+                        let old_block_number_u64 = match convert_uint_to_u64(old_block_number) {
+                            Ok(old_block_number_u64) => old_block_number_u64,
+                            Err(e) => {
+                                reply(&outcome_sender, Err(e));
+                                continue;
+                            }
+                        };
+                        let logs_bloom = db
+                            .blooms
+                            .read()?
+                            .get(&old_block_number)
+                            .copied()
+                            .unwrap_or_default();
                         let receipt_data = ReceiptData {
-                            block_number: convert_uint_to_u64(old_block_number)?,
-                            transaction_index,
-                            cumulative_gas_per_block,
+                            block_number: old_block_number_u64,
+                            transaction_hash: H256::zero(),
+                            transaction_index: counters.transaction_index,
+                            cumulative_gas_per_block: counters.cumulative_gas,
+                            logs_bloom,
+                            state_diff: None,
+                            fees: Some(counters.fees),
+                            settlement_fee: None,
+                            effective_gas_price: None,
+                            transaction_type: None,
                         };
+                        db.blocks
+                            .write()?
+                            .insert(old_block_number, block(evm.block(), &counters, logs_bloom));
+                        history.record(evm.block(), &*db.state.read()?);
 
                         // Update the block number and timestamp
                         evm.block_mut().number = U256::from_limbs(block_number.0);
                         evm.block_mut().timestamp = U256::from_limbs(block_timestamp.0);
+                        if let Some(coinbase) = settings.coinbase {
+                            evm.block_mut().coinbase = coinbase.to_fixed_bytes().into();
+                        }
+                        if let Some(base_fee) = settings.base_fee {
+                            evm.block_mut().basefee = U256::from_limbs(base_fee.0);
+                        }
 
                         // Reset the counters.
-                        transaction_index = U64::from(0);
-                        cumulative_gas_per_block = eU256::from(0);
+                        counters = BlockCounters::new(evm.block());
 
                         // Return the old block data in a `ReceiptData` after the block update.
-                        outcome_sender.send(Ok(Outcome::BlockUpdateCompleted(receipt_data)))?;
+                        reply(
+                            &outcome_sender,
+                            Ok(Outcome::BlockUpdateCompleted(receipt_data)),
+                        );
                     }
                     Instruction::Cheatcode {
                         cheatcode,
@@ -318,21 +685,24 @@ impl Environment {
                             let recast_key = B256::from(key.as_fixed_bytes()).into();
 
                             // Get the account storage value at the key in the db.
-                            match db.state.write()?.accounts.get_mut(&recast_address) {
-                                Some(account) => {
-                                    // Returns zero if the account is missing.
-                                    let value: U256 = match account.storage.get::<U256>(&recast_key)
-                                    {
-                                        Some(value) => *value,
-                                        None => U256::ZERO,
-                                    };
-                                    outcome_sender.send(Ok(Outcome::CheatcodeReturn(
-                                        CheatcodesReturn::Load { value },
-                                    )))?;
+                            let mut state = db.state.write()?;
+                            load_account(&mut state, recast_address);
+                            match state.accounts.contains_key(&recast_address) {
+                                true => {
+                                    // Returns zero if the slot was never written.
+                                    let Ok(value) = state.storage(recast_address, recast_key);
+                                    reply(
+                                        &outcome_sender,
+                                        Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Load {
+                                            value,
+                                        })),
+                                    );
                                 }
-                                None => {
-                                    outcome_sender
-                                        .send(Err(ArbiterCoreError::AccountDoesNotExistError))?;
+                                false => {
+                                    reply(
+                                        &outcome_sender,
+                                        Err(ArbiterCoreError::AccountDoesNotExistError),
+                                    );
                                 }
                             };
                         }
@@ -347,40 +717,77 @@ impl Environment {
 
                             // Mutate the db by inserting the new key-value pair into the account's
                             // storage and send the successful CheatcodeCompleted outcome.
-                            match db.state.write()?.accounts.get_mut(&recast_address) {
+                            let mut state = db.state.write()?;
+                            load_account(&mut state, recast_address);
+                            match state.accounts.get_mut(&recast_address) {
                                 Some(account) => {
                                     account
                                         .storage
                                         .insert(recast_key.into(), recast_value.into());
 
-                                    outcome_sender.send(Ok(Outcome::CheatcodeReturn(
-                                        CheatcodesReturn::Store,
-                                    )))?;
+                                    reply(
+                                        &outcome_sender,
+                                        Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Store)),
+                                    );
                                 }
                                 None => {
-                                    outcome_sender
-                                        .send(Err(ArbiterCoreError::AccountDoesNotExistError))?;
+                                    reply(
+                                        &outcome_sender,
+                                        Err(ArbiterCoreError::AccountDoesNotExistError),
+                                    );
                                 }
                             };
                         }
                         Cheatcodes::Deal { address, amount } => {
                             let recast_address = Address::from(address.as_fixed_bytes());
-                            match db.state.write()?.accounts.get_mut(&recast_address) {
+                            let mut state = db.state.write()?;
+                            load_account(&mut state, recast_address);
+                            match state.accounts.get_mut(&recast_address) {
                                 Some(account) => {
                                     account.info.balance += U256::from_limbs(amount.0);
-                                    outcome_sender.send(Ok(Outcome::CheatcodeReturn(
-                                        CheatcodesReturn::Deal,
-                                    )))?;
+                                    reply(
+                                        &outcome_sender,
+                                        Ok(Outcome::CheatcodeReturn(CheatcodesReturn::Deal)),
+                                    );
                                 }
                                 None => {
-                                    outcome_sender
-                                        .send(Err(ArbiterCoreError::AccountDoesNotExistError))?;
+                                    reply(
+                                        &outcome_sender,
+                                        Err(ArbiterCoreError::AccountDoesNotExistError),
+                                    );
                                 }
                             };
                         }
+                        Cheatcodes::SetBalance { address, amount } => {
+                            let recast_address = Address::from(address.as_fixed_bytes());
+                            let mut state = db.state.write()?;
+                            load_account(&mut state, recast_address);
+                            state
+                                .accounts
+                                .entry(recast_address)
+                                .or_default()
+                                .info
+                                .balance = U256::from_limbs(amount.0);
+                            reply(
+                                &outcome_sender,
+                                Ok(Outcome::CheatcodeReturn(CheatcodesReturn::SetBalance)),
+                            );
+                        }
+                        Cheatcodes::SetNonce { address, nonce } => {
+                            let recast_address = Address::from(address.as_fixed_bytes());
+                            let mut state = db.state.write()?;
+                            load_account(&mut state, recast_address);
+                            state.accounts.entry(recast_address).or_default().info.nonce = nonce;
+                            reply(
+                                &outcome_sender,
+                                Ok(Outcome::CheatcodeReturn(CheatcodesReturn::SetNonce)),
+                            );
+                        }
                         Cheatcodes::Access { address } => {
                             let recast_address = Address::from(address.as_fixed_bytes());
-                            match db.state.write()?.accounts.get(&recast_address) {
+                            let mut state = db.state.write()?;
+                            load_account(&mut state, recast_address);
+                            match state.accounts.get(&recast_address) {
                                 Some(account) => {
                                     let account_state = match account.account_state {
                                         AccountState::None => AccountStateSerializable::None,
@@ -399,11 +806,13 @@ impl Environment {
                                         storage: account.storage.clone(),
                                     };
 
-                                    outcome_sender.send(Ok(Outcome::CheatcodeReturn(account)))?;
+                                    reply(&outcome_sender, Ok(Outcome::CheatcodeReturn(account)));
                                 }
                                 None => {
-                                    outcome_sender
-                                        .send(Err(ArbiterCoreError::AccountDoesNotExistError))?;
+                                    reply(
+                                        &outcome_sender,
+                                        Err(ArbiterCoreError::AccountDoesNotExistError),
+                                    );
                                 }
                             }
                         }
@@ -412,32 +821,57 @@ impl Environment {
                     // console logs.
                     Instruction::Call {
                         tx_env,
+                        block_number,
+                        trace,
                         outcome_sender,
                     } => {
-                        // Set the tx_env and prepare to process it
-                        *evm.tx_mut() = tx_env;
-
-                        let result = evm.transact()?.result;
-
-                        if let Some(console_log) = &mut evm.context.external.console_log {
-                            console_log.0.drain(..).for_each(|log| {
-                                // This unwrap is safe because the logs are guaranteed to be
-                                // `HardhatConsoleCalls` by the `ArbiterInspector`.
-                                trace!(
-                                    "Console logs: {:?}",
-                                    HardhatConsoleCalls::decode(log).unwrap().to_string()
-                                )
-                            });
+                        let past_block = block_number
+                            .map(|number| U256::from_limbs(number.0))
+                            .filter(|number| *number != evm.block().number);
+                        let outcome = match past_block {
+                            // Traced calls get an EVM of their own so that the inspector of the
+                            // environment is left as it is.
+                            None if trace => {
+                                let mut env = evm.context.evm.env.as_ref().clone();
+                                env.tx = tx_env;
+                                call_at(&*db.state.read()?, env, console_logs, pay_gas, true)
+                            }
+                            None => {
+                                // Set the tx_env and prepare to process it
+                                *evm.tx_mut() = tx_env;
+
+                                let outcome = transact(&mut evm)
+                                    .map(|result_and_state| {
+                                        Outcome::CallCompleted(result_and_state.result)
+                                    })
+                                    .map_err(ArbiterCoreError::EVMError);
+
+                                if let Some(console_log) = &mut evm.context.external.console_log {
+                                    trace_console_logs(console_log.0.drain(..));
+                                };
+                                outcome
+                            }
+                            Some(number) => match history.get(number) {
+                                Some((block, state)) => {
+                                    let mut env = evm.context.evm.env.as_ref().clone();
+                                    env.block = block.clone();
+                                    env.tx = tx_env;
+                                    call_at(state, env, console_logs, pay_gas, trace)
+                                }
+                                None => Err(ArbiterCoreError::StateUnavailableError(
+                                    number.saturating_to(),
+                                )),
+                            },
                         };
 
-                        outcome_sender.send(Ok(Outcome::CallCompleted(result)))?;
+                        reply(&outcome_sender, outcome);
                     }
                     Instruction::SetGasPrice {
                         gas_price,
                         outcome_sender,
                     } => {
-                        evm.tx_mut().gas_price = U256::from_limbs(gas_price.0);
-                        outcome_sender.send(Ok(Outcome::SetGasPriceCompleted))?;
+                        current_gas_price = U256::from_limbs(gas_price.0);
+                        reply(&outcome_sender, Ok(Outcome::SetGasPriceCompleted));
                     }
 
                     // A `Transaction` is state changing and will create events.
@@ -445,72 +879,186 @@ impl Environment {
                         tx_env,
                         outcome_sender,
                     } => {
-                        // Set the tx_env and prepare to process it
-                        *evm.tx_mut() = tx_env;
-
-                        let execution_result = match evm.transact_commit() {
-                            Ok(result) => {
-                                if let Some(console_log) = &mut evm.context.external.console_log {
-                                    console_log.0.drain(..).for_each(|log| {
-                                        // This unwrap is safe because the logs are guaranteed to be
-                                        // `HardhatConsoleCalls` by the `ArbiterInspector`.
-                                        trace!(
-                                            "Console logs: {:?}",
-                                            HardhatConsoleCalls::decode(log).unwrap().to_string()
-                                        )
-                                    });
-                                };
-                                result
-                            }
-                            Err(e) => {
-                                outcome_sender.send(Err(ArbiterCoreError::EVMError(e)))?;
-                                continue;
+                        // Gather up any other transactions that are already queued so they can
+                        // be executed together.
+                        let mut batch = vec![(tx_env, outcome_sender)];
+                        if parallel_execution {
+                            while batch.len() < parallel::MAX_BATCH_SIZE {
+                                match next_queued(&mut held, &instruction_receiver) {
+                                    Some(Instruction::Transaction {
+                                        tx_env,
+                                        outcome_sender,
+                                    }) => batch.push((tx_env, outcome_sender)),
+                                    Some(instruction) => {
+                                        deferred = Some(instruction);
+                                        break;
+                                    }
+                                    None => break,
+                                }
                             }
-                        };
-                        cumulative_gas_per_block += eU256::from(execution_result.gas_used());
-                        let block_number = convert_uint_to_u64(evm.block().number)?;
-                        let receipt_data = ReceiptData {
-                            block_number,
-                            transaction_index,
-                            cumulative_gas_per_block,
-                        };
+                        }
 
-                        let mut logs = db.logs.write()?;
-                        match logs.get_mut(&evm.block().number) {
-                            Some(log_vec) => {
-                                log_vec.extend(revm_logs_to_ethers_logs(
-                                    execution_result.logs().to_vec(),
-                                    &receipt_data,
-                                ));
-                            }
-                            None => {
-                                logs.insert(
-                                    evm.block().number,
-                                    revm_logs_to_ethers_logs(
-                                        execution_result.logs().to_vec(),
-                                        &receipt_data,
-                                    ),
-                                );
+                        if batch.len() == 1 {
+                            // This unwrap is safe because the batch has exactly one element.
+                            let (tx_env, outcome_sender) = batch.pop().unwrap();
+                            let traced = tracer.is_enabled().then(|| tx_env.clone());
+                            let gas_price = effective_gas_price(&tx_env, evm.block().basefee);
+                            let transaction_type = transaction_type(&tx_env);
+
+                            let executed = match execute(&mut evm, tx_env, &finalizer) {
+                                Ok(executed) => executed,
+                                Err(e) => {
+                                    reply(&outcome_sender, Err(e));
+                                    continue;
+                                }
+                            };
+                            if let Some(tx_env) = &traced {
+                                tracer.record(tx_env, evm.block(), &executed.result);
                             }
+                            conclude_transaction(
+                                executed,
+                                gas_price,
+                                transaction_type,
+                                &outcome_sender,
+                                &mut counters,
+                                &db,
+                                &event_broadcaster,
+                            )?;
+                            continue;
                         }
 
-                        match event_broadcaster.send(Broadcast::Event(
-                            execution_result.logs().to_vec(),
-                            receipt_data.clone(),
-                        )) {
-                            Ok(_) => {}
-                            Err(_) => {
-                                warn!(
-                                    "Event was not sent to any listeners. Are there any listeners?"
-                                )
+                        // Speculatively execute the whole batch in parallel against the current
+                        // state.
+                        let tx_envs: Vec<TxEnv> =
+                            batch.iter().map(|(tx_env, _)| tx_env.clone()).collect();
+                        let speculations = parallel::speculate(
+                            &*db.state.read()?,
+                            &evm.context.evm.env,
+                            &tx_envs,
+                            console_logs,
+                            pay_gas,
+                            coverage,
+                        );
+                        trace!(
+                            "Speculatively executed a batch of {} transactions",
+                            tx_envs.len()
+                        );
+
+                        // Commit the results in submission order, re-executing any transaction
+                        // that read state an earlier transaction in the batch wrote to.
+                        let coinbase = evm.block().coinbase;
+                        let base_fee = evm.block().basefee;
+                        let mut written = AccessSet::default();
+                        for ((tx_env, outcome_sender), speculation) in
+                            batch.into_iter().zip(speculations)
+                        {
+                            let coinbase_sensitive =
+                                parallel::is_coinbase_sensitive(&tx_env, coinbase);
+                            let traced = tracer.is_enabled().then(|| tx_env.clone());
+                            let gas_price = effective_gas_price(&tx_env, base_fee);
+                            let transaction_type = transaction_type(&tx_env);
+                            let (finalized, state, writes) = match speculation {
+                                Some(speculation)
+                                    if !speculation.reads.conflicts_with(&written) =>
+                                {
+                                    let Speculation {
+                                        result_and_state: ResultAndState { result, mut state },
+                                        writes,
+                                        coinbase_reward,
+                                        console_logs,
+                                        coverage,
+                                        ..
+                                    } = speculation;
+                                    if !coinbase_sensitive {
+                                        let current = db
+                                            .state
+                                            .read()?
+                                            .basic_ref(coinbase)
+                                            .ok()
+                                            .flatten()
+                                            .map(|info| info.balance)
+                                            .unwrap_or_default();
+                                        parallel::rebase_coinbase(
+                                            &mut state,
+                                            coinbase,
+                                            current,
+                                            coinbase_reward,
+                                        );
+                                    }
+                                    let finalized = finalizer.finalize(
+                                        &tx_env,
+                                        result,
+                                        &mut state,
+                                        &*db.state.read()?,
+                                        base_fee,
+                                    );
+                                    trace_console_logs(console_logs);
+                                    if let (Some(total), Some(coverage)) =
+                                        (&mut evm.context.external.coverage, coverage)
+                                    {
+                                        total.merge(coverage);
+                                    }
+                                    (finalized, state, writes)
+                                }
+                                _ => {
+                                    trace!("Re-executing a conflicting transaction serially");
+                                    *evm.tx_mut() = tx_env;
+                                    let ResultAndState { result, mut state } =
+                                        match transact(&mut evm) {
+                                            Ok(result_and_state) => result_and_state,
+                                            Err(e) => {
+                                                reply(
+                                                    &outcome_sender,
+                                                    Err(ArbiterCoreError::EVMError(e)),
+                                                );
+                                                continue;
+                                            }
+                                        };
+                                    let (_, writes) = parallel::access_sets(
+                                        &state,
+                                        &*db.state.read()?,
+                                        coinbase,
+                                        true,
+                                    );
+                                    let finalized = finalizer.finalize(
+                                        evm.tx(),
+                                        result,
+                                        &mut state,
+                                        &*db.state.read()?,
+                                        base_fee,
+                                    );
+                                    if let Some(console_log) = &mut evm.context.external.console_log
+                                    {
+                                        trace_console_logs(console_log.0.drain(..));
+                                    };
+                                    (finalized, state, writes)
+                                }
+                            };
+                            let executed = match finalized {
+                                Ok(executed) => executed,
+                                Err(e) => {
+                                    reply(&outcome_sender, Err(e));
+                                    continue;
+                                }
+                            };
+                            written.extend(writes);
+                            for recipient in finalizer.recipients() {
+                                written.insert_account(recipient);
                             }
+                            evm.db_mut().commit(state);
+                            if let Some(tx_env) = &traced {
+                                tracer.record(tx_env, evm.block(), &executed.result);
+                            }
+                            conclude_transaction(
+                                executed,
+                                gas_price,
+                                transaction_type,
+                                &outcome_sender,
+                                &mut counters,
+                                &db,
+                                &event_broadcaster,
+                            )?;
                         }
-                        outcome_sender.send(Ok(Outcome::TransactionCompleted(
-                            execution_result,
-                            receipt_data,
-                        )))?;
-
-                        transaction_index += U64::from(1);
                     }
                     Instruction::Query {
                         environment_data,
@@ -524,16 +1072,16 @@ impl Environment {
                                 Ok(Outcome::QueryReturn(evm.block().timestamp.to_string()))
                             }
                             EnvironmentData::GasPrice => {
-                                Ok(Outcome::QueryReturn(evm.tx().gas_price.to_string()))
+                                Ok(Outcome::QueryReturn(current_gas_price.to_string()))
+                            }
+                            EnvironmentData::ChainId => {
+                                Ok(Outcome::QueryReturn(evm.cfg().chain_id.to_string()))
                             }
                             EnvironmentData::Balance(address) => {
-                                match db
-                                    .state
-                                    .read()
-                                    .unwrap()
-                                    .accounts
-                                    .get::<Address>(&address.as_fixed_bytes().into())
-                                {
+                                let address = Address::from(address.as_fixed_bytes());
+                                let mut state = db.state.write()?;
+                                load_account(&mut state, address);
+                                match state.accounts.get(&address) {
                                     Some(account) => {
                                         Ok(Outcome::QueryReturn(account.info.balance.to_string()))
                                     }
@@ -541,13 +1089,10 @@ impl Environment {
                                 }
                             }
                             EnvironmentData::TransactionCount(address) => {
-                                match db
-                                    .state
-                                    .read()
-                                    .unwrap()
-                                    .accounts
-                                    .get::<Address>(&address.as_fixed_bytes().into())
-                                {
+                                let address = Address::from(address.as_fixed_bytes());
+                                let mut state = db.state.write()?;
+                                load_account(&mut state, address);
+                                match state.accounts.get(&address) {
                                     Some(account) => {
                                         Ok(Outcome::QueryReturn(account.info.nonce.to_string()))
                                     }
@@ -555,96 +1100,239 @@ impl Environment {
                                 }
                             }
                             EnvironmentData::Logs { filter } => {
-                                let logs = db.logs.read().unwrap();
-                                let from_block = U256::from(
-                                    filter
-                                        .block_option
-                                        .get_from_block()
-                                        .ok_or(ArbiterCoreError::MissingDataError)?
-                                        .as_number()
-                                        .ok_or(ArbiterCoreError::MissingDataError)?
-                                        .0[0],
-                                );
-                                let to_block = U256::from(
-                                    filter
-                                        .block_option
-                                        .get_to_block()
-                                        .ok_or(ArbiterCoreError::MissingDataError)?
-                                        .as_number()
-                                        .ok_or(ArbiterCoreError::MissingDataError)?
-                                        .0[0],
-                                );
-                                let mut return_logs = Vec::new();
-                                logs.keys().for_each(|blocknum| {
-                                    if blocknum >= &from_block && blocknum <= &to_block {
-                                        return_logs.extend(logs.get(blocknum).cloned().unwrap());
+                                let logs = db.logs.read()?;
+                                let blooms = db.blooms.read()?;
+                                filter_logs(&logs, &blooms, &filter, evm.block().number).and_then(
+                                    |logs| Ok(Outcome::QueryReturn(serde_json::to_string(&logs)?)),
+                                )
+                            }
+                            EnvironmentData::Block(block_number) => {
+                                let number = match block_number {
+                                    BlockNumber::Number(number) => U256::from(number.as_u64()),
+                                    BlockNumber::Earliest => U256::ZERO,
+                                    _ => evm.block().number,
+                                };
+                                let found = if number == evm.block().number {
+                                    let logs_bloom =
+                                        db.blooms.read()?.get(&number).copied().unwrap_or_default();
+                                    Some(block(evm.block(), &counters, logs_bloom))
+                                } else {
+                                    db.blocks.read()?.get(&number).cloned()
+                                };
+                                serde_json::to_string(&found)
+                                    .map(Outcome::QueryReturn)
+                                    .map_err(ArbiterCoreError::from)
+                            }
+                            EnvironmentData::TransactionReceipt(transaction_hash) => {
+                                let mut receipt =
+                                    db.receipts.read()?.get(&transaction_hash).cloned();
+                                if mining_mode == MiningMode::OnBlockUpdate {
+                                    // Only the receipts of mined blocks are known.
+                                    let blocks = db.blocks.read()?;
+                                    receipt = receipt.filter(|receipt| {
+                                        receipt.block_number.is_some_and(|number| {
+                                            blocks.contains_key(&U256::from(number.as_u64()))
+                                        })
+                                    });
+                                }
+                                serde_json::to_string(&receipt)
+                                    .map(Outcome::QueryReturn)
+                                    .map_err(ArbiterCoreError::from)
+                            }
+                            EnvironmentData::FeeHistory {
+                                block_count,
+                                newest_block,
+                                reward_percentiles,
+                            } => {
+                                let newest = match newest_block {
+                                    BlockNumber::Number(number) => {
+                                        U256::from(number.as_u64()).min(evm.block().number)
                                     }
-                                });
-                                return_logs.retain(|log| {
-                                    filter.topics.iter().any(|topic_option| match topic_option {
-                                        Some(topic_val_or_array) => match topic_val_or_array {
-                                            ValueOrArray::Value(topic) => match topic {
-                                                Some(topic) => log.topics.contains(topic),
-                                                None => true,
-                                            },
-                                            ValueOrArray::Array(topics) => {
-                                                topics.iter().any(|topic| match topic {
-                                                    Some(topic) => log.topics.contains(topic),
-                                                    None => true,
-                                                })
-                                            }
-                                        },
-                                        None => true,
-                                    })
-                                });
-                                return_logs.retain(|log| {
-                                    filter.address.iter().any(|address_value_or_array| {
-                                        match address_value_or_array {
-                                            ValueOrArray::Value(address) => &log.address == address,
-
-                                            ValueOrArray::Array(addresses) => {
-                                                addresses.iter().any(|addr| &log.address == addr)
-                                            }
-                                        }
-                                    })
-                                });
-                                Ok(Outcome::QueryReturn(
-                                    serde_json::to_string(&return_logs).unwrap(),
-                                ))
+                                    BlockNumber::Earliest => U256::ZERO,
+                                    _ => evm.block().number,
+                                };
+                                let blocks = db.blocks.read()?;
+                                // The blocks up to the newest one, newest first, along with the
+                                // base fee of the block that follows them.
+                                let mut numbers: Vec<&U256> =
+                                    blocks.keys().filter(|number| **number <= newest).collect();
+                                numbers.sort_unstable_by(|a, b| b.cmp(a));
+                                let mut window = Vec::new();
+                                let next_base_fee = if newest == evm.block().number {
+                                    let logs_bloom =
+                                        db.blooms.read()?.get(&newest).copied().unwrap_or_default();
+                                    window.push(block(evm.block(), &counters, logs_bloom));
+                                    evm.block().basefee
+                                } else {
+                                    blocks
+                                        .iter()
+                                        .filter(|(number, _)| **number > newest)
+                                        .min_by_key(|(number, _)| **number)
+                                        .map(|(_, block)| {
+                                            U256::from_limbs(
+                                                block.base_fee_per_gas.unwrap_or_default().0,
+                                            )
+                                        })
+                                        .unwrap_or(evm.block().basefee)
+                                };
+                                window.extend(
+                                    numbers.into_iter().map(|number| blocks[number].clone()),
+                                );
+                                window.truncate(block_count as usize);
+                                window.reverse();
+                                fee_history(
+                                    &window,
+                                    next_base_fee,
+                                    &*db.receipts.read()?,
+                                    &reward_percentiles,
+                                )
+                                .and_then(|history| {
+                                    Ok(Outcome::QueryReturn(serde_json::to_string(&history)?))
+                                })
                             }
+                            EnvironmentData::Coverage => evm
+                                .context
+                                .external
+                                .coverage
+                                .as_ref()
+                                .ok_or(ArbiterCoreError::CoverageDisabledError)
+                                .and_then(|coverage| {
+                                    Ok(Outcome::QueryReturn(serde_json::to_string(coverage)?))
+                                }),
                         };
-                        outcome_sender.send(outcome)?;
+                        reply(&outcome_sender, outcome);
                     }
                     Instruction::Stop(outcome_sender) => {
-                        match event_broadcaster.send(Broadcast::StopSignal) {
-                            Ok(_) => {}
-                            Err(_) => {
-                                warn!("Stop signal was not sent to any listeners. Are there any listeners?")
-                            }
+                        if let Some(coverage) = &evm.context.external.coverage {
+                            info!(
+                                "Coverage for environment labeled {:?}:\n{}",
+                                label, coverage
+                            );
+                        }
+                        if event_broadcaster.send(Broadcast::StopSignal) == 0 {
+                            warn!("Stop signal was not sent to any listeners. Are there any listeners?")
                         }
-                        outcome_sender.send(Ok(Outcome::StopCompleted(db)))?;
+                        reply(&outcome_sender, Ok(Outcome::StopCompleted(db)));
                         break;
                     }
                 }
             }
+            // Anything that was sent after the stop will never be executed, so let
+            // the clients know instead of leaving them waiting.
+            for instruction in deferred
+                .into_iter()
+                .chain(held.drain())
+                .chain(instruction_receiver.try_iter())
+            {
+                reply(
+                    instruction.outcome_sender(),
+                    Err(ArbiterCoreError::EnvironmentStoppedError),
+                );
+            }
             Ok(())
         });
         self.handle = Some(handle);
         self
     }
 
+    /// Returns the [`Profiler`] shared by the environment and its clients.
+    pub fn profiler(&self) -> Profiler {
+        self.profiler.clone()
+    }
+
+    /// Returns the [`Tracer`] that records the transactions executed by the
+    /// environment.
+    pub fn tracer(&self) -> Tracer {
+        self.tracer.clone()
+    }
+
+    /// Returns a handle to the [`ArbiterDB`] of the environment, which shares
+    /// its state with the running environment, e.g., to save it while the
+    /// simulation runs or to [`ArbiterDB::restore`] a saved state into it.
+    pub fn db(&self) -> ArbiterDB {
+        self.db.clone()
+    }
+
+    /// Pauses the [`Environment`] so that transactions and block updates are
+    /// held back until [`Environment::resume`] is called. Calls, queries, and
+    /// cheatcodes are still executed, so the state can be inspected and
+    /// modified while the simulation is frozen.
+    ///
+    /// Returns once every instruction that was being executed has finished.
+    /// Stopping a paused environment executes the held back instructions
+    /// before it stops.
+    pub fn pause(&self) -> Result<(), ArbiterCoreError> {
+        self.control(Control::Pause)
+    }
+
+    /// Resumes a paused [`Environment`]. The instructions that were held back
+    /// are executed first in the order they were sent.
+    pub fn resume(&self) -> Result<(), ArbiterCoreError> {
+        self.control(Control::Resume)
+    }
+
+    /// Returns the number of instructions that are held back while the
+    /// [`Environment`] is paused, e.g., to wait for a transaction sent from
+    /// another thread to reach a paused environment.
+    pub fn held_instructions(&self) -> usize {
+        self.held.load(Ordering::Acquire)
+    }
+
+    /// Sends a [`Control`] to the environment and waits for it to take effect.
+    fn control(&self, control: fn(Sender<()>) -> Control) -> Result<(), ArbiterCoreError> {
+        if self.handle.is_none() {
+            return Err(ArbiterCoreError::EnvironmentStoppedError);
+        }
+        let (ack_sender, ack_receiver) = bounded(1);
+        self.socket
+            .control_sender
+            .send(control(ack_sender))
+            .map_err(|_| ArbiterCoreError::EnvironmentStoppedError)?;
+        ack_receiver
+            .recv()
+            .map_err(|_| ArbiterCoreError::EnvironmentStoppedError)
+    }
+
+    /// Returns the total number of events that subscribers of this
+    /// [`Environment`] missed because they fell behind it.
+    pub fn lagged_events(&self) -> u64 {
+        self.socket.event_broadcaster.lagged()
+    }
+
     /// Stops the execution of the environment and returns the [`ArbiterDB`] in
     /// its final state.
+    ///
+    /// Every instruction that was sent before the stop is executed first, any
+    /// instruction sent afterwards is answered with an
+    /// [`ArbiterCoreError::EnvironmentStoppedError`], and the thread of the
+    /// environment is joined before returning.
     pub fn stop(mut self) -> Result<ArbiterDB, ArbiterCoreError> {
+        self.shutdown()
+    }
+
+    /// Sends the stop instruction and joins the thread of the environment.
+    fn shutdown(&mut self) -> Result<ArbiterDB, ArbiterCoreError> {
+        let handle = self
+            .handle
+            .take()
+            .ok_or(ArbiterCoreError::EnvironmentStoppedError)?;
         let (outcome_sender, outcome_receiver) = bounded(1);
         self.socket
             .instruction_sender
             .send(Instruction::Stop(outcome_sender))?;
-        let outcome = outcome_receiver.recv()??;
+        // The thread may have already exited, in which case it either replied to
+        // the stop right before or failed with an error and never will.
+        let outcome = if handle.is_finished() {
+            outcome_receiver.try_recv().ok().transpose()?
+        } else {
+            Some(outcome_receiver.recv()??)
+        };
+        handle.join().map_err(|_| ArbiterCoreError::JoinError)??;
 
         let db = match outcome {
-            Outcome::StopCompleted(stopped_db) => stopped_db,
-            _ => unreachable!(),
+            Some(Outcome::StopCompleted(stopped_db)) => stopped_db,
+            Some(_) => unreachable!(),
+            None => return Err(ArbiterCoreError::EnvironmentStoppedError),
         };
 
         if let Some(label) = &self.parameters.label {
@@ -652,16 +1340,24 @@ impl Environment {
         } else {
             warn!("Stopped environment with no label.");
         }
-        drop(self.socket.instruction_sender);
-        self.handle
-            .take()
-            .unwrap()
-            .join()
-            .map_err(|_| ArbiterCoreError::JoinError)??;
         Ok(db)
     }
 }
 
+impl Drop for Environment {
+    /// Stops an environment that is dropped while it is still running so that
+    /// its thread is joined and listeners such as the
+    /// [`Logger`](crate::events::Logger) see the stop signal and flush their
+    /// output.
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            if let Err(e) = self.shutdown() {
+                warn!("Failed to stop the environment while dropping it: {}", e);
+            }
+        }
+    }
+}
+
 /// Provides channels for communication between the EVM and external entities.
 ///
 /// The socket contains senders and receivers for transactions, as well as an
@@ -670,7 +1366,9 @@ impl Environment {
 pub(crate) struct Socket {
     pub(crate) instruction_sender: Arc<InstructionSender>,
     pub(crate) instruction_receiver: InstructionReceiver,
-    pub(crate) event_broadcaster: BroadcastSender<Broadcast>,
+    pub(crate) control_sender: Sender<Control>,
+    pub(crate) control_receiver: Receiver<Control>,
+    pub(crate) event_broadcaster: EventBroadcaster,
 }
 
 /// Enum representing the types of broadcasts that can be sent.
@@ -680,13 +1378,687 @@ pub(crate) struct Socket {
 ///
 /// Variants:
 /// * `StopSignal`: Represents a signal to stop the event logger process.
-/// * `Event(Vec<Log>)`: Represents a broadcast of a vector of Ethereum logs.
+/// * `Event(Vec<Log>, Box<ReceiptData>)`: Represents a broadcast of a vector of
+///   Ethereum logs.
 #[derive(Clone, Debug)]
 pub enum Broadcast {
     /// Represents a signal to stop the event logger process.
     StopSignal,
-    /// Represents a broadcast of a vector of Ethereum logs.
-    Event(Vec<Log>, ReceiptData),
+    /// Represents a broadcast of a vector of Ethereum logs along with the
+    /// receipt data of their transaction, which is boxed as it holds the
+    /// bloom and state diff of the transaction.
+    Event(Vec<Log>, Box<ReceiptData>),
+}
+
+/// Records the result of a committed transaction by storing and broadcasting
+/// its logs and replying to the sender with the [`ReceiptData`]. This also
+/// advances the per block counters that are returned on receipts, including
+/// the fees paid at the effective `gas_price` of the transaction.
+fn conclude_transaction(
+    executed: Executed,
+    gas_price: U256,
+    transaction_type: U64,
+    outcome_sender: &OutcomeSender,
+    counters: &mut BlockCounters,
+    db: &ArbiterDB,
+    event_broadcaster: &EventBroadcaster,
+) -> Result<(), ArbiterCoreError> {
+    let block_number = counters.number;
+    let block_number_u64 = match convert_uint_to_u64(block_number) {
+        Ok(block_number_u64) => block_number_u64,
+        Err(e) => {
+            reply(outcome_sender, Err(e));
+            return Ok(());
+        }
+    };
+    let Executed {
+        result: execution_result,
+        transaction_hash,
+        caller,
+        to,
+        state_diff,
+        settlement_fee,
+    } = executed;
+    counters.cumulative_gas += eU256::from(execution_result.gas_used());
+    counters.pay(execution_result.gas_used(), gas_price, settlement_fee);
+    let receipt_data = ReceiptData {
+        block_number: block_number_u64,
+        transaction_hash,
+        transaction_index: counters.transaction_index,
+        cumulative_gas_per_block: counters.cumulative_gas,
+        logs_bloom: logs_bloom(execution_result.logs()),
+        state_diff,
+        fees: None,
+        settlement_fee: settlement_fee.map(|fee| eU256::from_little_endian(fee.as_le_slice())),
+        effective_gas_price: Some(eU256::from_little_endian(gas_price.as_le_slice())),
+        transaction_type: Some(transaction_type),
+    };
+    db.blooms
+        .write()?
+        .entry(block_number)
+        .or_default()
+        .accrue_bloom(&receipt_data.logs_bloom);
+
+    // Logs are stored with their index in the block so that they can be
+    // queried in order later on.
+    let mut logs = db.logs.write()?;
+    let block_logs = logs.entry(block_number).or_default();
+    let first_log_index = block_logs.len();
+    block_logs.extend(
+        revm_logs_to_ethers_logs(execution_result.logs().to_vec(), &receipt_data)
+            .into_iter()
+            .enumerate()
+            .map(|(index, log)| eLog {
+                log_index: Some(eU256::from(first_log_index + index)),
+                ..log
+            }),
+    );
+    let receipt = transaction_receipt(
+        caller.into_array().into(),
+        to.map(|to| to.into_array().into()),
+        &execution_result,
+        block_logs[first_log_index..].to_vec(),
+        &receipt_data,
+    )?;
+    drop(logs);
+    db.receipts.write()?.insert(transaction_hash, receipt);
+    counters.transactions.push(transaction_hash);
+
+    if event_broadcaster.send(Broadcast::Event(
+        execution_result.logs().to_vec(),
+        Box::new(receipt_data.clone()),
+    )) == 0
+    {
+        warn!("Event was not sent to any listeners. Are there any listeners?")
+    }
+    reply(
+        outcome_sender,
+        Ok(Outcome::TransactionCompleted(
+            execution_result,
+            receipt_data,
+        )),
+    );
+
+    counters.transaction_index += U64::from(1);
+    Ok(())
+}
+
+/// Returns the hash of the transaction `tx_env` given the committed state in
+/// `db` it is executed on. Transactions are not signed in the [`Environment`],
+/// so the hash is derived from the sender, its nonce, and the calldata.
+fn transaction_hash(tx_env: &TxEnv, db: &CacheDB<Backend>) -> H256 {
+    let nonce = db
+        .basic_ref(tx_env.caller)
+        .ok()
+        .flatten()
+        .map(|info| info.nonce)
+        .unwrap_or_default();
+    let mut preimage = tx_env.caller.to_vec();
+    preimage.extend_from_slice(&nonce.to_be_bytes());
+    preimage.extend_from_slice(&tx_env.data);
+    H256::from(keccak256(preimage).0)
+}
+
+/// Returns the [`Block`] described by `block_env` with the transactions
+/// recorded in its `counters` and the bloom of their logs.
+fn block(block_env: &BlockEnv, counters: &BlockCounters, logs_bloom: Bloom) -> Block<H256> {
+    Block {
+        number: Some(U64::from(block_env.number.saturating_to::<u64>())),
+        timestamp: eU256::from_little_endian(block_env.timestamp.as_le_slice()),
+        author: Some(block_env.coinbase.into_array().into()),
+        gas_limit: eU256::from_little_endian(block_env.gas_limit.as_le_slice()),
+        gas_used: counters.cumulative_gas,
+        base_fee_per_gas: Some(eU256::from_little_endian(block_env.basefee.as_le_slice())),
+        logs_bloom: Some(logs_bloom),
+        transactions: counters.transactions.clone(),
+        ..Default::default()
+    }
+}
+
+/// Returns the fee history of `blocks`, which are ordered from the oldest to
+/// the newest, followed by the `next_base_fee` of the block after them, like
+/// `eth_feeHistory` does.
+///
+/// The rewards of a block are the priority fees paid by its transactions at
+/// each of the `reward_percentiles` of its gas used, where the transactions
+/// are ordered by the priority fee they paid and read from the `receipts`. An
+/// empty block has rewards of zero.
+fn fee_history(
+    blocks: &[Block<H256>],
+    next_base_fee: U256,
+    receipts: &HashMap<H256, TransactionReceipt>,
+    reward_percentiles: &[f64],
+) -> Result<FeeHistory, ArbiterCoreError> {
+    if reward_percentiles
+        .iter()
+        .any(|percentile| !(0.0..=100.0).contains(percentile))
+        || reward_percentiles.windows(2).any(|pair| pair[0] > pair[1])
+    {
+        return Err(ArbiterCoreError::InvalidQueryError);
+    }
+    let mut history = FeeHistory {
+        base_fee_per_gas: Vec::with_capacity(blocks.len() + 1),
+        gas_used_ratio: Vec::with_capacity(blocks.len()),
+        oldest_block: blocks
+            .first()
+            .and_then(|block| block.number)
+            .map(|number| eU256::from(number.as_u64()))
+            .unwrap_or_default(),
+        reward: Vec::new(),
+    };
+    for block in blocks {
+        let base_fee = block.base_fee_per_gas.unwrap_or_default();
+        history.base_fee_per_gas.push(base_fee);
+        history
+            .gas_used_ratio
+            .push(match block.gas_limit.is_zero() {
+                true => 0.0,
+                false => to_f64(block.gas_used) / to_f64(block.gas_limit),
+            });
+        if reward_percentiles.is_empty() {
+            continue;
+        }
+        let mut paid: Vec<(eU256, eU256)> = block
+            .transactions
+            .iter()
+            .filter_map(|transaction_hash| receipts.get(transaction_hash))
+            .map(|receipt| {
+                let priority_fee = receipt
+                    .effective_gas_price
+                    .unwrap_or_default()
+                    .saturating_sub(base_fee);
+                (priority_fee, receipt.gas_used.unwrap_or_default())
+            })
+            .collect();
+        paid.sort_unstable_by_key(|(priority_fee, _)| *priority_fee);
+        let gas_used = to_f64(
+            paid.iter()
+                .map(|(_, gas_used)| *gas_used)
+                .fold(eU256::zero(), |total, gas_used| total + gas_used),
+        );
+        let mut rewards = Vec::with_capacity(reward_percentiles.len());
+        let mut index = 0;
+        let mut cumulative_gas = paid.first().map_or(0.0, |(_, gas_used)| to_f64(*gas_used));
+        for percentile in reward_percentiles {
+            let threshold = gas_used * percentile / 100.0;
+            while cumulative_gas < threshold && index + 1 < paid.len() {
+                index += 1;
+                cumulative_gas += to_f64(paid[index].1);
+            }
+            rewards.push(
+                paid.get(index)
+                    .map(|(priority_fee, _)| *priority_fee)
+                    .unwrap_or_default(),
+            );
+        }
+        history.reward.push(rewards);
+    }
+    history
+        .base_fee_per_gas
+        .push(eU256::from_little_endian(next_base_fee.as_le_slice()));
+    Ok(history)
+}
+
+/// Returns `value` as a floating point number, which loses the precision
+/// beyond its most significant 53 bits.
+fn to_f64(value: eU256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |total, limb| total * 2f64.powi(64) + *limb as f64)
+}
+
+/// The counters of the current block that are returned on receipts.
+struct BlockCounters {
+    /// The number of the block.
+    number: U256,
+
+    /// The index of the next transaction in the block.
+    transaction_index: U64,
+
+    /// The gas used by the transactions of the block so far.
+    cumulative_gas: eU256,
+
+    /// The fees paid by the transactions of the block so far.
+    fees: BlockFees,
+
+    /// The hashes of the transactions of the block so far.
+    transactions: Vec<H256>,
+}
+
+impl BlockCounters {
+    /// Creates the counters of a new `block`.
+    fn new(block: &BlockEnv) -> Self {
+        Self {
+            number: block.number,
+            transaction_index: U64::from(0),
+            cumulative_gas: eU256::from(0),
+            fees: BlockFees {
+                coinbase: block.coinbase.into_array().into(),
+                base_fee: eU256::from_little_endian(block.basefee.as_le_slice()),
+                ..Default::default()
+            },
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Records the fees of a transaction that used `gas_used` at
+    /// `gas_price`, which is split into the base fee and the priority fee the
+    /// coinbase is credited with, along with its `settlement_fee`.
+    fn pay(&mut self, gas_used: u64, gas_price: U256, settlement_fee: Option<U256>) {
+        let base_fee = U256::from_limbs(self.fees.base_fee.0);
+        let gas_used = U256::from(gas_used);
+        let base_fees = base_fee * gas_used;
+        let priority_fees = gas_price.saturating_sub(base_fee) * gas_used;
+        self.fees.base_fees += eU256::from_little_endian(base_fees.as_le_slice());
+        self.fees.priority_fees += eU256::from_little_endian(priority_fees.as_le_slice());
+        if let Some(settlement_fee) = settlement_fee {
+            self.fees.settlement_fees += eU256::from_little_endian(settlement_fee.as_le_slice());
+        }
+    }
+}
+
+/// Returns the gas price a transaction actually pays in a block with the given
+/// `base_fee`.
+fn effective_gas_price(tx_env: &TxEnv, base_fee: U256) -> U256 {
+    match tx_env.gas_priority_fee {
+        Some(priority_fee) => tx_env.gas_price.min(base_fee.saturating_add(priority_fee)),
+        None => tx_env.gas_price,
+    }
+}
+
+/// Returns the [EIP-2718](https://eips.ethereum.org/EIPS/eip-2718) type of a
+/// transaction, which is told apart by the fields of its `tx_env` that are
+/// set: a priority fee makes it an EIP-1559 transaction, and an access list
+/// without one an EIP-2930 transaction.
+pub(crate) fn transaction_type(tx_env: &TxEnv) -> U64 {
+    if tx_env.gas_priority_fee.is_some() {
+        U64::from(2)
+    } else if !tx_env.access_list.is_empty() {
+        U64::from(1)
+    } else {
+        U64::zero()
+    }
+}
+
+/// A transaction that was executed and is ready to be committed.
+struct Executed {
+    /// The result of the transaction.
+    result: ExecutionResult,
+
+    /// The hash the transaction is known by.
+    transaction_hash: H256,
+
+    /// The sender of the transaction.
+    caller: Address,
+
+    /// The recipient of the transaction, or `None` for a deployment.
+    to: Option<Address>,
+
+    /// The changes of the transaction if state diffs are recorded.
+    state_diff: Option<StateDiff>,
+
+    /// The fee the sender paid to settle the transaction if there is a
+    /// [`SettlementModel`].
+    settlement_fee: Option<U256>,
+}
+
+/// Applies the fees and bookkeeping configured in the
+/// [`EnvironmentParameters`] to the changes of each executed transaction
+/// before they are committed.
+#[derive(Clone, Debug)]
+struct Finalizer {
+    state_diffs: bool,
+    base_fee_recipient: Option<Address>,
+    settlement_model: Option<Arc<dyn SettlementModel>>,
+    settlement_fee_recipient: Option<Address>,
+}
+
+impl Finalizer {
+    fn new(parameters: &EnvironmentParameters) -> Self {
+        Self {
+            state_diffs: parameters.state_diffs,
+            base_fee_recipient: parameters.base_fee_recipient,
+            settlement_model: parameters.settlement_model.clone(),
+            settlement_fee_recipient: parameters.settlement_fee_recipient,
+        }
+    }
+
+    /// Charges the sender of `tx_env` its settlement fee, credits the fees of
+    /// the transaction to their recipients, and records its hash and state
+    /// diff, all
+    /// within the changes in `state` that it made on top of `db` in a block
+    /// with the given `base_fee`.
+    ///
+    /// Returns [`ArbiterCoreError::InsufficientSettlementFunds`] if the sender
+    /// cannot pay the settlement fee, in which case `state` must not be
+    /// committed.
+    fn finalize(
+        &self,
+        tx_env: &TxEnv,
+        result: ExecutionResult,
+        state: &mut State,
+        db: &CacheDB<Backend>,
+        base_fee: U256,
+    ) -> Result<Executed, ArbiterCoreError> {
+        let settlement_fee = match &self.settlement_model {
+            Some(model) => {
+                let fee = model.fee(tx_env);
+                let sender = account_mut(state, db, tx_env.caller);
+                if sender.info.balance < fee {
+                    return Err(ArbiterCoreError::InsufficientSettlementFunds {
+                        fee,
+                        balance: sender.info.balance,
+                    });
+                }
+                sender.info.balance -= fee;
+                sender.mark_touch();
+                if let Some(recipient) = self.settlement_fee_recipient {
+                    credit(state, db, recipient, fee);
+                }
+                Some(fee)
+            }
+            None => None,
+        };
+        if let Some(recipient) = self.base_fee_recipient {
+            credit(
+                state,
+                db,
+                recipient,
+                base_fee * U256::from(result.gas_used()),
+            );
+        }
+        let state_diff = self.state_diffs.then(|| StateDiff::new(state, db));
+        Ok(Executed {
+            result,
+            transaction_hash: transaction_hash(tx_env, db),
+            caller: tx_env.caller,
+            to: match tx_env.transact_to {
+                TransactTo::Call(to) => Some(to),
+                TransactTo::Create(_) => None,
+            },
+            state_diff,
+            settlement_fee,
+        })
+    }
+
+    /// Returns the accounts that are credited with fees on top of the changes
+    /// a transaction makes.
+    fn recipients(&self) -> impl Iterator<Item = Address> {
+        self.base_fee_recipient
+            .into_iter()
+            .chain(self.settlement_fee_recipient)
+    }
+}
+
+/// Returns the account at `address` among the changes in `state`, adding it
+/// with its info from `db` if the changes do not touch it yet.
+fn account_mut<'a>(
+    state: &'a mut State,
+    db: &CacheDB<Backend>,
+    address: Address,
+) -> &'a mut revm::primitives::Account {
+    state.entry(address).or_insert_with(|| {
+        db.basic_ref(address)
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+            .into()
+    })
+}
+
+/// Credits the `recipient` with `amount` by adding it to the changes in
+/// `state` that are about to be committed on top of `db`.
+fn credit(state: &mut State, db: &CacheDB<Backend>, recipient: Address, amount: U256) {
+    if amount.is_zero() {
+        return;
+    }
+    let account = account_mut(state, db, recipient);
+    account.info.balance = account.info.balance.saturating_add(amount);
+    account.mark_touch();
+}
+
+/// Executes the transaction in `tx_env` on top of the current state and
+/// commits its changes once the `finalizer` has applied the fees of the
+/// transaction to them.
+///
+/// A transaction that cannot be executed (e.g., one with an invalid nonce)
+/// returns an error that is handed back to its sender instead of stopping the
+/// [`Environment`].
+fn execute(
+    evm: &mut Evm<'_, ArbiterInspector, ArbiterDB>,
+    tx_env: TxEnv,
+    finalizer: &Finalizer,
+) -> Result<Executed, ArbiterCoreError> {
+    *evm.tx_mut() = tx_env;
+    let ResultAndState { result, mut state } = transact(evm)?;
+    let executed = finalizer.finalize(
+        evm.tx(),
+        result,
+        &mut state,
+        &*evm.db().state.read()?,
+        evm.block().basefee,
+    );
+    if let Some(console_log) = &mut evm.context.external.console_log {
+        trace_console_logs(console_log.0.drain(..));
+    };
+    let executed = executed?;
+    evm.db_mut().commit(state);
+    Ok(executed)
+}
+
+/// Executes the transaction of `evm` without committing it.
+///
+/// `revm` leaves the accounts that a transaction it rejected before executing
+/// it (e.g., one with an invalid nonce) loaded in its journal, where they would
+/// hide any later change to them, so the journal is cleared.
+fn transact(
+    evm: &mut Evm<'_, ArbiterInspector, ArbiterDB>,
+) -> Result<ResultAndState, EVMError<Infallible>> {
+    evm.transact().inspect_err(|_| {
+        evm.context.evm.journaled_state.finalize();
+    })
+}
+
+/// The instructions held back while the environment is paused, in the order
+/// they were sent, whose number is shared with the [`Environment`].
+struct Held {
+    instructions: VecDeque<Instruction>,
+    count: Arc<AtomicUsize>,
+}
+
+impl Held {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        Self {
+            instructions: VecDeque::new(),
+            count,
+        }
+    }
+
+    fn push_back(&mut self, instruction: Instruction) {
+        self.instructions.push_back(instruction);
+        self.count.store(self.instructions.len(), Ordering::Release);
+    }
+
+    fn pop_front(&mut self) -> Option<Instruction> {
+        let instruction = self.instructions.pop_front();
+        self.count.store(self.instructions.len(), Ordering::Release);
+        instruction
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = Instruction> + '_ {
+        self.count.store(0, Ordering::Release);
+        self.instructions.drain(..)
+    }
+}
+
+/// Returns the next [`Instruction`] that is already waiting to be executed, if
+/// any, without waiting for one. The instructions that were held back were
+/// sent before any that are still in the socket, so they come first.
+fn next_queued(held: &mut Held, instruction_receiver: &InstructionReceiver) -> Option<Instruction> {
+    held.pop_front()
+        .or_else(|| instruction_receiver.try_recv().ok())
+}
+
+/// Returns the next [`Instruction`] to execute or `None` once every client is
+/// gone.
+///
+/// [`Control`]s are applied as soon as they arrive. While the environment is
+/// paused, instructions that advance the simulation are moved into `held` and
+/// are returned in order once it is resumed. A stop resumes the environment
+/// so that everything sent before it is still executed.
+fn next_instruction(
+    deferred: &mut Option<Instruction>,
+    held: &mut Held,
+    paused: &mut bool,
+    instruction_receiver: &InstructionReceiver,
+    control_receiver: &mut Receiver<Control>,
+) -> Option<Instruction> {
+    loop {
+        let instruction = match deferred.take() {
+            Some(instruction) => instruction,
+            None => match (!*paused).then(|| held.pop_front()).flatten() {
+                Some(instruction) => instruction,
+                None => select! {
+                    recv(control_receiver) -> control => {
+                        let ack_sender = match control {
+                            Ok(Control::Pause(ack_sender)) => {
+                                *paused = true;
+                                ack_sender
+                            }
+                            Ok(Control::Resume(ack_sender)) => {
+                                *paused = false;
+                                ack_sender
+                            }
+                            // Nobody is left to send controls, so stop listening for them.
+                            Err(_) => {
+                                *control_receiver = never();
+                                continue;
+                            }
+                        };
+                        let _ = ack_sender.send(());
+                        continue;
+                    }
+                    recv(instruction_receiver) -> instruction => instruction.ok()?,
+                },
+            },
+        };
+        if *paused {
+            if let Instruction::Stop(_) = instruction {
+                *paused = false;
+                held.push_back(instruction);
+                continue;
+            }
+            if instruction.is_held_while_paused() {
+                held.push_back(instruction);
+                continue;
+            }
+        }
+        return Some(instruction);
+    }
+}
+
+/// Sends the `outcome` of an [`Instruction`] back to the client that sent it.
+///
+/// A client that stopped listening for its outcome must not bring down the
+/// [`Environment`] for every other client, so an undeliverable outcome is
+/// only logged.
+fn reply(outcome_sender: &OutcomeSender, outcome: Result<Outcome, ArbiterCoreError>) {
+    if outcome_sender.send(outcome).is_err() {
+        warn!("Outcome was not delivered since its receiver was dropped.");
+    }
+}
+
+/// Returns the stored `logs` that match the `filter` ordered by block number
+/// and then by the order in which they were emitted. Blocks whose bloom in
+/// `blooms` rules out the `filter` are skipped without inspecting their logs.
+///
+/// Following `eth_getLogs`, a missing, `latest`, `pending`, `safe`, or
+/// `finalized` block bound refers to the `current_block` and `earliest` refers
+/// to block zero. Filtering by block hash is not supported since the
+/// [`Environment`] does not produce block hashes.
+fn filter_logs(
+    logs: &std::collections::HashMap<U256, Vec<eLog>>,
+    blooms: &std::collections::HashMap<U256, Bloom>,
+    filter: &Filter,
+    current_block: U256,
+) -> Result<Vec<eLog>, ArbiterCoreError> {
+    let resolve = |block: Option<BlockNumber>| match block {
+        Some(BlockNumber::Number(number)) => U256::from(number.as_u64()),
+        Some(BlockNumber::Earliest) => U256::ZERO,
+        _ => current_block,
+    };
+    let (from_block, to_block) = match filter.block_option {
+        FilterBlockOption::Range {
+            from_block,
+            to_block,
+        } => (resolve(from_block), resolve(to_block)),
+        FilterBlockOption::AtBlockHash(_) => return Err(ArbiterCoreError::InvalidQueryError),
+    };
+
+    let filtered_params = FilteredParams::new(Some(filter.clone()));
+    let mut block_numbers: Vec<&U256> = logs
+        .keys()
+        .filter(|block_number| (from_block..=to_block).contains(*block_number))
+        .filter(|block_number| {
+            blooms
+                .get(*block_number)
+                .is_none_or(|bloom| bloom_matches(bloom, filter))
+        })
+        .collect();
+    block_numbers.sort();
+    Ok(block_numbers
+        .into_iter()
+        .flat_map(|block_number| &logs[block_number])
+        .filter(|log| filtered_params.filter_address(log) && filtered_params.filter_topics(log))
+        .cloned()
+        .collect())
+}
+
+/// Executes the call in `env` against `state`, e.g., the state of a past
+/// block, without changing it, and records its
+/// [`CallTrace`](crate::trace::CallTrace) if `trace` is set.
+fn call_at(
+    state: &CacheDB<Backend>,
+    env: Env,
+    console_logs: bool,
+    pay_gas: bool,
+    trace: bool,
+) -> Result<Outcome, ArbiterCoreError> {
+    let mut inspector = ArbiterInspector::new(console_logs, pay_gas, false);
+    if trace {
+        inspector.call_tracer = Some(CallTracer::default());
+    }
+    let mut evm = Evm::builder()
+        .with_ref_db(state)
+        .with_env(Box::new(env))
+        .with_external_context(inspector)
+        .append_handler_register(inspector_handle_register)
+        .build();
+    let result_and_state = evm.transact()?;
+    if let Some(console_log) = &mut evm.context.external.console_log {
+        trace_console_logs(console_log.0.drain(..));
+    };
+    let result = result_and_state.result;
+    match evm.context.external.call_tracer.take() {
+        Some(call_tracer) => {
+            // The outermost frame always finishes once the call has been executed.
+            let trace = call_tracer.into_trace().unwrap();
+            Ok(Outcome::CallTraced(result, Box::new(trace)))
+        }
+        None => Ok(Outcome::CallCompleted(result)),
+    }
+}
+
+/// Emits the `console2.log`s collected by the [`ArbiterInspector`] as `trace`
+/// level logs.
+fn trace_console_logs(logs: impl IntoIterator<Item = Bytes>) {
+    logs.into_iter()
+        .for_each(|log| match HardhatConsoleCalls::decode(&log) {
+            Ok(call) => trace!("Console logs: {:?}", call.to_string()),
+            Err(_) => trace!("Console logs could not be decoded: {:?}", log),
+        });
 }
 
 /// Convert a U256 to a U64, discarding the higher bits if the number is larger
@@ -728,6 +2100,123 @@ mod tests {
         );
     }
 
+    #[test]
+    fn held_instructions_are_queued_first() {
+        let transaction = |nonce| Instruction::Transaction {
+            tx_env: TxEnv {
+                nonce: Some(nonce),
+                ..Default::default()
+            },
+            outcome_sender: bounded(1).0,
+        };
+        let (instruction_sender, instruction_receiver) = unbounded();
+        let mut held = Held::new(Arc::default());
+        held.push_back(transaction(0));
+        held.push_back(transaction(1));
+        instruction_sender.send(transaction(2)).unwrap();
+        assert_eq!(held.count.load(Ordering::Acquire), 2);
+
+        let mut nonces = vec![];
+        while let Some(instruction) = next_queued(&mut held, &instruction_receiver) {
+            let Instruction::Transaction { tx_env, .. } = instruction else {
+                panic!("expected a transaction");
+            };
+            nonces.push(tx_env.nonce.unwrap());
+        }
+        assert_eq!(nonces, [0, 1, 2]);
+        assert_eq!(held.count.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn misbehaving_clients_do_not_stop_the_environment() {
+        let environment = Environment::builder().build();
+        let instruction_sender = &environment.socket.instruction_sender;
+
+        // A client that drops its receiver before the outcome is delivered.
+        let (outcome_sender, outcome_receiver) = bounded(1);
+        drop(outcome_receiver);
+        instruction_sender
+            .send(Instruction::Query {
+                environment_data: EnvironmentData::BlockNumber,
+                outcome_sender,
+            })
+            .unwrap();
+
+        // A call that cannot be executed since the caller cannot pay for gas.
+        let (outcome_sender, outcome_receiver) = bounded(1);
+        instruction_sender
+            .send(Instruction::Call {
+                block_number: None,
+                trace: false,
+                tx_env: TxEnv {
+                    gas_limit: 1_000_000,
+                    gas_price: U256::from(1),
+                    ..TxEnv::default()
+                },
+                outcome_sender,
+            })
+            .unwrap();
+        assert!(matches!(
+            outcome_receiver.recv().unwrap(),
+            Err(ArbiterCoreError::EVMError(_))
+        ));
+
+        let (outcome_sender, outcome_receiver) = bounded(1);
+        instruction_sender
+            .send(Instruction::Query {
+                environment_data: EnvironmentData::BlockNumber,
+                outcome_sender,
+            })
+            .unwrap();
+        assert!(matches!(
+            outcome_receiver.recv().unwrap(),
+            Ok(Outcome::QueryReturn(_))
+        ));
+        environment.stop().unwrap();
+    }
+
+    #[test]
+    fn instructions_after_stop_are_rejected() {
+        let mut environment = Environment::builder().build();
+        let instruction_sender = environment.socket.instruction_sender.clone();
+
+        let (stop_sender, stop_receiver) = bounded(1);
+        let (query_sender, query_receiver) = bounded(1);
+        instruction_sender
+            .send(Instruction::Stop(stop_sender))
+            .unwrap();
+        instruction_sender
+            .send(Instruction::Query {
+                environment_data: EnvironmentData::BlockNumber,
+                outcome_sender: query_sender,
+            })
+            .unwrap();
+
+        assert!(matches!(
+            stop_receiver.recv().unwrap(),
+            Ok(Outcome::StopCompleted(_))
+        ));
+        assert!(matches!(
+            query_receiver.recv().unwrap(),
+            Err(ArbiterCoreError::EnvironmentStoppedError)
+        ));
+        environment.handle.take().unwrap().join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn dropping_stops_the_environment() {
+        let environment = Environment::builder().build();
+        let mut receiver = environment
+            .socket
+            .event_broadcaster
+            .subscribe_with(delivery::DeliveryPolicy::default());
+        drop(environment);
+        assert!(matches!(
+            receiver.try_recv(),
+            Some(Ok(Broadcast::StopSignal))
+        ));
+    }
+
     #[test]
     fn conversion() {
         // Test with a value that fits in u64.
@@ -0,0 +1,274 @@
+//! Optimistic concurrency for batches of [`Instruction::Transaction`]s.
+//!
+//! When [`EnvironmentParameters::parallel_execution`] is enabled, the
+//! [`Environment`] drains every transaction that is already queued on the
+//! [`Socket`] and executes them speculatively in parallel against a snapshot
+//! of the current state. The results are then committed in submission order.
+//! A transaction that read state written by an earlier transaction in the same
+//! batch is discarded and re-executed serially on top of the up to date state,
+//! so the final state is always identical to serial execution.
+
+use std::collections::HashSet;
+
+use revm::{
+    interpreter::{opcode, Interpreter},
+    primitives::{db::DatabaseRef, ResultAndState, State, TransactTo, B256},
+};
+
+use super::*;
+use crate::{coverage::Coverage, database::backend::Backend};
+
+/// The maximum number of transactions that are pulled off of the instruction
+/// channel and executed together as a single batch.
+pub(crate) const MAX_BATCH_SIZE: usize = 64;
+
+/// The set of accounts and storage slots that a transaction either read from
+/// or wrote to.
+#[derive(Debug, Default)]
+pub(crate) struct AccessSet {
+    /// Accounts whose [`AccountInfo`] was accessed.
+    accounts: HashSet<Address>,
+
+    /// Storage slots that were accessed.
+    slots: HashSet<(Address, U256)>,
+
+    /// Accounts that were created or self destructed, which invalidates any
+    /// read of their storage.
+    wiped: HashSet<Address>,
+}
+
+impl AccessSet {
+    /// Returns `true` if any of the reads in `self` observe a write in
+    /// `writes`.
+    pub(crate) fn conflicts_with(&self, writes: &AccessSet) -> bool {
+        self.accounts
+            .iter()
+            .any(|address| writes.accounts.contains(address) || writes.wiped.contains(address))
+            || self.slots.iter().any(|slot| writes.slots.contains(slot))
+    }
+
+    /// Adds a write to the [`AccountInfo`] of the account at `address`.
+    pub(crate) fn insert_account(&mut self, address: Address) {
+        self.accounts.insert(address);
+    }
+
+    /// Merges the accesses of `other` into `self`.
+    pub(crate) fn extend(&mut self, other: AccessSet) {
+        self.accounts.extend(other.accounts);
+        self.slots.extend(other.slots);
+        self.wiped.extend(other.wiped);
+    }
+}
+
+/// Records whether a transaction read the balance or code of the block
+/// beneficiary, e.g., through `block.coinbase.balance`, which is changed by
+/// the fees of every other transaction in its batch.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CoinbaseReads {
+    read: bool,
+}
+
+impl CoinbaseReads {
+    /// Checks whether the instruction the `interp` is about to execute reads
+    /// the `coinbase`.
+    pub(crate) fn step(&mut self, interp: &Interpreter, coinbase: Address) {
+        let target = match interp.current_opcode() {
+            opcode::BALANCE | opcode::EXTCODESIZE | opcode::EXTCODECOPY | opcode::EXTCODEHASH => {
+                match interp.stack().peek(0) {
+                    Ok(word) => Address::from_word(B256::from(word)),
+                    Err(_) => return,
+                }
+            }
+            opcode::SELFBALANCE => interp.contract.address,
+            _ => return,
+        };
+        self.read |= target == coinbase;
+    }
+}
+
+/// The outcome of speculatively executing a transaction against a snapshot.
+pub(crate) struct Speculation {
+    /// The result of the execution along with the state it would commit.
+    pub(crate) result_and_state: ResultAndState,
+
+    /// Everything the transaction read from the snapshot.
+    pub(crate) reads: AccessSet,
+
+    /// Everything the transaction would write.
+    pub(crate) writes: AccessSet,
+
+    /// The amount the block beneficiary was credited by the transaction.
+    pub(crate) coinbase_reward: U256,
+
+    /// The `console2.log`s collected while executing the transaction.
+    pub(crate) console_logs: Vec<Bytes>,
+
+    /// The coverage collected while executing the transaction.
+    pub(crate) coverage: Option<Coverage>,
+}
+
+/// Builds the read and write [`AccessSet`]s for the `state` produced by a
+/// transaction that was executed on top of `pre`.
+///
+/// The block beneficiary is credited by every transaction that pays for gas,
+/// so it is left out of the read set unless `coinbase_sensitive` is set, i.e.,
+/// the transaction is sent from or to it or read it, in order to not
+/// serialize every transaction in a batch.
+pub(crate) fn access_sets<DB: DatabaseRef>(
+    state: &State,
+    pre: &DB,
+    coinbase: Address,
+    coinbase_sensitive: bool,
+) -> (AccessSet, AccessSet) {
+    let mut reads = AccessSet::default();
+    let mut writes = AccessSet::default();
+    for (address, account) in state {
+        if *address != coinbase || coinbase_sensitive {
+            reads.accounts.insert(*address);
+        }
+        reads
+            .slots
+            .extend(account.storage.keys().map(|slot| (*address, *slot)));
+
+        if !account.is_touched() {
+            continue;
+        }
+        if account.is_created() || account.is_selfdestructed() {
+            writes.wiped.insert(*address);
+        }
+        let info_changed = match pre.basic_ref(*address) {
+            Ok(Some(info)) => {
+                info.balance != account.info.balance
+                    || info.nonce != account.info.nonce
+                    || info.code_hash != account.info.code_hash
+            }
+            _ => true,
+        };
+        if info_changed {
+            writes.accounts.insert(*address);
+        }
+        writes.slots.extend(
+            account
+                .storage
+                .iter()
+                .filter(|(_, slot)| slot.is_changed())
+                .map(|(key, _)| (*address, *key)),
+        );
+    }
+    (reads, writes)
+}
+
+/// Returns `true` if the transaction is sent from or to the block beneficiary,
+/// in which case the beneficiary's balance must be tracked like any other
+/// account.
+pub(crate) fn is_coinbase_sensitive(tx_env: &TxEnv, coinbase: Address) -> bool {
+    tx_env.caller == coinbase || tx_env.transact_to == TransactTo::Call(coinbase)
+}
+
+/// Executes each of the `tx_envs` in parallel against the `snapshot` without
+/// committing any of the changes.
+///
+/// A transaction that fails to execute returns `None` so that it can be
+/// executed again serially and report its error in the usual way.
+pub(crate) fn speculate(
+    snapshot: &CacheDB<Backend>,
+    env: &Env,
+    tx_envs: &[TxEnv],
+    console_logs: bool,
+    pay_gas: bool,
+    coverage: bool,
+) -> Vec<Option<Speculation>> {
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(tx_envs.len())
+        .max(1);
+    let chunk_size = tx_envs.len().div_ceil(workers);
+    let coinbase = env.block.coinbase;
+    let coinbase_balance = snapshot
+        .basic_ref(coinbase)
+        .ok()
+        .flatten()
+        .map(|info| info.balance)
+        .unwrap_or_default();
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = tx_envs
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let handle = scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|tx_env| {
+                            let mut env = env.clone();
+                            env.tx = tx_env.clone();
+                            let mut inspector =
+                                ArbiterInspector::new(console_logs, pay_gas, coverage);
+                            inspector.coinbase_reads = Some(CoinbaseReads::default());
+                            let mut evm = Evm::builder()
+                                .with_ref_db(snapshot)
+                                .with_env(Box::new(env))
+                                .with_external_context(inspector)
+                                .append_handler_register(inspector_handle_register)
+                                .build();
+                            let result_and_state = evm.transact().ok()?;
+                            let coinbase_read = evm
+                                .context
+                                .external
+                                .coinbase_reads
+                                .take()
+                                .is_some_and(|reads| reads.read);
+                            let (reads, writes) = access_sets(
+                                &result_and_state.state,
+                                snapshot,
+                                coinbase,
+                                coinbase_read || is_coinbase_sensitive(tx_env, coinbase),
+                            );
+                            let coinbase_reward = result_and_state
+                                .state
+                                .get(&coinbase)
+                                .map(|account| {
+                                    account.info.balance.saturating_sub(coinbase_balance)
+                                })
+                                .unwrap_or_default();
+                            let console_logs = evm
+                                .context
+                                .external
+                                .console_log
+                                .take()
+                                .map(|logs| logs.0)
+                                .unwrap_or_default();
+                            let coverage = evm.context.external.coverage.take();
+                            Some(Speculation {
+                                result_and_state,
+                                reads,
+                                writes,
+                                coinbase_reward,
+                                console_logs,
+                                coverage,
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                });
+                (handle, chunk.len())
+            })
+            .collect();
+        // A worker that panicked leaves its whole chunk to be executed serially.
+        handles
+            .into_iter()
+            .flat_map(|(handle, len)| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| (0..len).map(|_| None).collect())
+            })
+            .collect()
+    })
+}
+
+/// Credits the block beneficiary in `state` with `reward` on top of its
+/// `current` balance instead of the balance seen in the snapshot.
+pub(crate) fn rebase_coinbase(state: &mut State, coinbase: Address, current: U256, reward: U256) {
+    if let Some(account) = state.get_mut(&coinbase) {
+        account.info.balance = current.saturating_add(reward);
+    }
+}
@@ -1,7 +1,7 @@
 //! Errors that can occur when managing or interfacing with Arbiter's sandboxed
 //! Ethereum environment.
 
-use std::sync::{PoisonError, RwLockWriteGuard};
+use std::sync::{PoisonError, RwLockReadGuard, RwLockWriteGuard};
 
 // use crossbeam_channel::SendError;
 use crossbeam_channel::{RecvError, SendError};
@@ -42,6 +42,51 @@ pub enum ArbiterCoreError {
     #[error("Invalid data used for a query request!")]
     InvalidQueryError,
 
+    /// Tried to query coverage from an environment that does not collect it.
+    #[error("Coverage is not enabled for this environment!")]
+    CoverageDisabledError,
+
+    /// Failed to parse a metric definition.
+    #[error("{0}")]
+    MetricError(String),
+
+    /// Failed to load a contract artifact.
+    #[error("{0}")]
+    ArtifactError(String),
+
+    /// Failed to deploy a contract.
+    #[error("Failed to deploy contract: {0}")]
+    DeploymentError(String),
+
+    /// Failed to read or write the cache of a forked database.
+    #[error("{0}")]
+    ForkCacheError(String),
+
+    /// Failed to write or read a database stored on disk.
+    #[error("{0}")]
+    DiskDBError(String),
+
+    /// Sent an instruction to an environment that has already been stopped.
+    #[error("The environment has been stopped!")]
+    EnvironmentStoppedError,
+
+    /// Made a call against the state of a block that the environment does not
+    /// keep.
+    #[error("The state at block {0} is not kept by the environment!")]
+    StateUnavailableError(u64),
+
+    /// A subscriber fell behind the environment and missed events.
+    #[error("Fell behind the environment and missed {0} events!")]
+    EventsLaggedError(u64),
+
+    /// The environment did not reply to an instruction in time.
+    #[error("The environment did not reply within {0:?}!")]
+    TimeoutError(std::time::Duration),
+
+    /// Failed to serve an environment over JSON-RPC.
+    #[error("{0}")]
+    RpcError(String),
+
     /// Failed to join environment thread on stop.
     #[error("Failed to join environment thread on stop!")]
     JoinError,
@@ -64,6 +109,24 @@ pub enum ArbiterCoreError {
         gas_used: u64,
     },
 
+    /// Execution ran out of gas.
+    #[error("Execution ran out of gas: {gas_used} gas used of a {gas_limit} gas limit")]
+    OutOfGas {
+        /// The gas limit of the transaction.
+        gas_limit: u64,
+        /// The amount of gas used.
+        gas_used: u64,
+    },
+
+    /// The sender of a transaction cannot pay its settlement fee.
+    #[error("Insufficient funds to pay a settlement fee of {fee} with a balance of {balance}")]
+    InsufficientSettlementFunds {
+        /// The settlement fee of the transaction.
+        fee: U256,
+        /// The balance of the sender after the transaction.
+        balance: U256,
+    },
+
     /// Failed to parse integer.
     #[error(transparent)]
     ParseIntError(#[from] std::num::ParseIntError),
@@ -80,13 +143,9 @@ pub enum ArbiterCoreError {
     #[error(transparent)]
     WalletError(#[from] WalletError),
 
-    /// Send error.
+    /// Send error, which is boxed as the instruction it returns is large.
     #[error(transparent)]
-    SendError(
-        #[from]
-        #[allow(private_interfaces)]
-        SendError<Instruction>,
-    ),
+    SendError(#[allow(private_interfaces)] Box<SendError<Instruction>>),
 
     /// Recv error.
     #[error(transparent)]
@@ -109,6 +168,12 @@ pub enum ArbiterCoreError {
     RwLockError(String),
 }
 
+impl From<SendError<Instruction>> for ArbiterCoreError {
+    fn from(e: SendError<Instruction>) -> Self {
+        ArbiterCoreError::SendError(Box::new(e))
+    }
+}
+
 impl From<SendError<Result<Outcome, ArbiterCoreError>>> for ArbiterCoreError {
     fn from(e: SendError<Result<Outcome, ArbiterCoreError>>) -> Self {
         ArbiterCoreError::ReplyError(e.to_string())
@@ -121,6 +186,12 @@ impl<T> From<PoisonError<RwLockWriteGuard<'_, T>>> for ArbiterCoreError {
     }
 }
 
+impl<T> From<PoisonError<RwLockReadGuard<'_, T>>> for ArbiterCoreError {
+    fn from(e: PoisonError<RwLockReadGuard<'_, T>>) -> Self {
+        ArbiterCoreError::RwLockError(e.to_string())
+    }
+}
+
 impl MiddlewareError for ArbiterCoreError {
     type Inner = ProviderError;
 
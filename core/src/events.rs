@@ -7,7 +7,8 @@
 //!
 //! This module also provides the implementation of the `EventLogger` struct,
 //! including methods for constructing a new `EventLogger`, adding an event to
-//! the `EventLogger`, and writing the event logs to a file.
+//! the `EventLogger`, and writing the event logs to a file. Derived series can
+//! be computed from the logged events with [`metrics::Metric`]s.
 //!
 //! # Type Parameters
 //!
@@ -18,7 +19,15 @@
 //! * `E` - Type that implements the `EthLogDecode`, `Debug`, `Serialize`
 //!   traits, and has a static lifetime.
 
-use std::{io::BufWriter, marker::PhantomData, mem::transmute, pin::Pin};
+use std::{
+    fs::File,
+    io::BufWriter,
+    marker::PhantomData,
+    mem::transmute,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use ethers::{
     abi::RawLog,
@@ -35,10 +44,18 @@ use polars::{
 };
 use serde::Serialize;
 use serde_json::Value;
-use tokio::{sync::broadcast::Receiver as BroadcastReceiver, task::JoinHandle};
+use tokio::task::JoinHandle;
 
 use super::*;
-use crate::middleware::{connection::revm_logs_to_ethers_logs, ArbiterMiddleware};
+use crate::{
+    database::bloom::bloom_matches,
+    environment::{delivery::DeliveryPolicy, instruction::ReceiptData, EventReceiver},
+    middleware::{connection::revm_logs_to_ethers_logs, ArbiterMiddleware},
+    shutdown::ShutdownCoordinator,
+};
+
+pub mod metrics;
+use metrics::{Metric, MetricCollector};
 
 pub(crate) type FilterDecoder =
     BTreeMap<String, (FilteredParams, Box<dyn Fn(&RawLog) -> String + Send + Sync>)>;
@@ -58,21 +75,25 @@ pub(crate) type FilterDecoder =
 ///   traits, and has a static lifetime.
 pub struct Logger {
     decoder: FilterDecoder,
-    receiver: Option<BroadcastReceiver<Broadcast>>,
+    receiver: Option<EventReceiver>,
+    delivery_policy: Option<DeliveryPolicy>,
     output_file_type: Option<OutputFileType>,
     directory: Option<String>,
     file_name: Option<String>,
     metadata: Option<Value>,
+    metrics: Vec<Metric>,
 }
 
 impl Debug for Logger {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("EventLogger")
             .field("receiver", &self.receiver)
+            .field("delivery_policy", &self.delivery_policy)
             .field("output_file_type", &self.output_file_type)
             .field("directory", &self.directory)
             .field("file_name", &self.file_name)
             .field("metadata", &self.metadata)
+            .field("metrics", &self.metrics)
             .finish()
     }
 }
@@ -109,9 +130,11 @@ impl Logger {
             file_name: None,
             decoder: BTreeMap::new(),
             receiver: None,
+            delivery_policy: None,
             // shutdown_sender: None,
             output_file_type: None,
             metadata: None,
+            metrics: Vec::new(),
         }
     }
 
@@ -144,7 +167,10 @@ impl Logger {
         );
         let connection = middleware.provider().as_ref();
         if self.receiver.is_none() {
-            self.receiver = Some(connection.event_sender.subscribe());
+            let policy = self
+                .delivery_policy
+                .unwrap_or(*connection.delivery_policy.lock().unwrap());
+            self.receiver = Some(connection.event_sender.subscribe_with(policy));
         }
         debug!("`EventLogger` now provided with event labeled: {:?}", name);
         self
@@ -213,6 +239,48 @@ impl Logger {
         Ok(self)
     }
 
+    /// Sets the [`DeliveryPolicy`] used when the `EventLogger` falls behind
+    /// the [`Environment`]. Defaults to the policy of the client of the first
+    /// event added.
+    ///
+    /// With [`DeliveryPolicy::BoundedWithError`] the `EventLogger` stops at the
+    /// first missed event and writes the events collected so far along with a
+    /// truncation marker.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The delivery policy to use.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLogger` instance with the specified delivery policy.
+    pub fn with_delivery_policy(mut self, policy: DeliveryPolicy) -> Self {
+        self.delivery_policy = Some(policy);
+        self.receiver = self.receiver.map(|receiver| receiver.with_policy(policy));
+        self
+    }
+
+    /// Adds derived metrics to the `EventLogger`.
+    ///
+    /// Each [`Metric`] is evaluated at the end of every block over the latest
+    /// values of the logged event fields. Metrics are evaluated in order, so a
+    /// metric may refer to any metric added before it. Since [`Metric`]s can
+    /// be deserialized from strings like `"pool_price = reserve1 / reserve0"`,
+    /// they can be read directly from a configuration file.
+    ///
+    /// # Arguments
+    ///
+    /// * `metrics` - The metrics to compute.
+    ///
+    /// # Returns
+    ///
+    /// The `EventLogger` instance with the added metrics.
+    pub fn with_metrics(mut self, metrics: impl IntoIterator<Item = Metric>) -> Self {
+        self.metrics.extend(metrics);
+        debug!("`EventLogger` metrics set to: {:?}", self.metrics);
+        self
+    }
+
     /// Executes the `EventLogger`.
     ///
     /// This function starts the event logging process. It first deletes the
@@ -239,65 +307,39 @@ impl Logger {
         let file_name = self.file_name.unwrap_or("output".into());
         let file_type = self.output_file_type.unwrap_or(OutputFileType::JSON);
         let metadata = self.metadata.clone();
+        let mut metrics = MetricCollector::new(self.metrics.clone());
+        let mut shutdown = ShutdownCoordinator::global().register();
         let task = tokio::spawn(async move {
             let mut events: BTreeMap<String, BTreeMap<String, Vec<Value>>> = BTreeMap::new();
-            while let Ok(broadcast) = receiver.recv().await {
+            let truncated = loop {
+                // Events that were already broadcast are still logged before
+                // honoring a shutdown.
+                let broadcast = tokio::select! {
+                    biased;
+                    broadcast = receiver.recv() => broadcast,
+                    _ = shutdown.requested() => {
+                        warn!("`EventLogger` was interrupted, writing the events collected so far");
+                        break true;
+                    }
+                };
+                let broadcast = match broadcast {
+                    Some(Ok(broadcast)) => broadcast,
+                    Some(Err(e)) => {
+                        error!("`EventLogger` is stopping early: {}", e);
+                        break true;
+                    }
+                    None => return,
+                };
                 match broadcast {
                     Broadcast::StopSignal => {
                         debug!("`EventLogger` has seen a stop signal");
-                        // create new directory with path
-                        let output_dir = std::env::current_dir().unwrap().join(dir);
-                        std::fs::create_dir_all(&output_dir).unwrap();
-                        let file_path = output_dir.join(format!("{}.json", file_name));
-                        debug!(
-                            "`EventLogger` dumping event data into: {:?}",
-                            file_path.to_str().unwrap().to_owned()
-                        );
-                        // match the file output type and write to correct file using the right file
-                        // type
-                        match file_type {
-                            OutputFileType::JSON => {
-                                let file_path = output_dir.join(format!("{}.json", file_name));
-                                let file = std::fs::File::create(file_path).unwrap();
-                                let writer = BufWriter::new(file);
-
-                                #[derive(Serialize, Clone)]
-                                struct OutputData<T> {
-                                    events: BTreeMap<String, BTreeMap<String, Vec<Value>>>,
-                                    metadata: Option<T>,
-                                }
-                                let data = OutputData { events, metadata };
-                                serde_json::to_writer(writer, &data).expect("Unable to write data");
-                            }
-                            OutputFileType::CSV => {
-                                // Write the DataFrame to a CSV file
-                                let mut df = flatten_to_data_frame(events);
-                                let file_path = output_dir.join(format!("{}.csv", file_name));
-                                let file = std::fs::File::create(file_path).unwrap_or_else(|_| {
-                                    panic!("Error creating csv file");
-                                });
-                                let mut writer = CsvWriter::new(file);
-                                writer.finish(&mut df).unwrap_or_else(|_| {
-                                    panic!("Error writing to csv file");
-                                });
-                            }
-                            OutputFileType::Parquet => {
-                                // Write the DataFrame to a parquet file
-                                let mut df = flatten_to_data_frame(events);
-                                let file_path = output_dir.join(format!("{}.parquet", file_name));
-                                let file = std::fs::File::create(file_path).unwrap_or_else(|_| {
-                                    panic!("Error creating parquet file");
-                                });
-                                let writer = ParquetWriter::new(file);
-                                writer.finish(&mut df).unwrap_or_else(|_| {
-                                    panic!("Error writing to parquet file");
-                                });
-                            }
-                        }
-                        break;
+                        break false;
                     }
                     Broadcast::Event(event, receipt_data) => {
                         trace!("`EventLogger` received an event");
+                        if !may_match(&self.decoder, &receipt_data) {
+                            continue;
+                        }
                         let ethers_logs = revm_logs_to_ethers_logs(event, &receipt_data);
                         for log in ethers_logs {
                             for (contract_name, (filter, decoder)) in self.decoder.iter() {
@@ -326,6 +368,11 @@ impl Logger {
                                     let event = contract.get_mut(&event_name).unwrap();
 
                                     for (_key, value) in event_as_object {
+                                        metrics.record(
+                                            receipt_data.block_number.as_u64(),
+                                            contract_name,
+                                            value,
+                                        );
                                         event.push(value.clone());
                                     }
                                     trace!(
@@ -336,12 +383,147 @@ impl Logger {
                         }
                     }
                 }
-            }
+            };
+            let metrics = std::mem::take(&mut metrics).finish();
+            write_output(
+                &dir,
+                &file_name,
+                file_type,
+                Output {
+                    events,
+                    metadata,
+                    metrics,
+                    truncated,
+                },
+            );
+            drop(shutdown);
         });
         Ok(task)
     }
 }
 
+/// Everything the [`Logger`] writes out once it stops.
+#[derive(Serialize)]
+struct Output {
+    events: BTreeMap<String, BTreeMap<String, Vec<Value>>>,
+    metadata: Option<Value>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    metrics: BTreeMap<String, Vec<(u64, f64)>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+}
+
+/// Writes the `output` into `dir` using the given `file_type`.
+///
+/// Every file is first written next to its destination and then renamed so an
+/// interrupted write never leaves a corrupt artifact behind. If the run was
+/// interrupted, a `{file_name}.truncated` marker is written alongside the
+/// output.
+fn write_output(dir: &str, file_name: &str, file_type: OutputFileType, output: Output) {
+    // create new directory with path
+    let output_dir = std::env::current_dir().unwrap().join(dir);
+    std::fs::create_dir_all(&output_dir).unwrap();
+    let marker_path = output_dir.join(format!("{}.truncated", file_name));
+    if output.truncated {
+        std::fs::write(
+            &marker_path,
+            "The simulation was interrupted before it finished, so this output is partial.\n",
+        )
+        .unwrap_or_else(|_| panic!("Error writing truncation marker"));
+    } else if marker_path.exists() {
+        std::fs::remove_file(&marker_path).unwrap();
+    }
+    // match the file output type and write to correct file using the right file
+    // type
+    match file_type {
+        OutputFileType::JSON => {
+            let file_path = output_dir.join(format!("{}.json", file_name));
+            debug!("`EventLogger` dumping event data into: {:?}", file_path);
+            persist(&file_path, |file| {
+                let writer = BufWriter::new(file);
+                serde_json::to_writer(writer, &output).expect("Unable to write data");
+            });
+        }
+        OutputFileType::CSV => {
+            // Write the DataFrame to a CSV file
+            let mut df = flatten_to_data_frame(output.events);
+            let file_path = output_dir.join(format!("{}.csv", file_name));
+            debug!("`EventLogger` dumping event data into: {:?}", file_path);
+            persist(&file_path, |file| {
+                CsvWriter::new(file).finish(&mut df).unwrap_or_else(|_| {
+                    panic!("Error writing to csv file");
+                });
+            });
+            if !output.metrics.is_empty() {
+                let mut df = metrics_to_data_frame(output.metrics);
+                let file_path = output_dir.join(format!("{}_metrics.csv", file_name));
+                persist(&file_path, |file| {
+                    CsvWriter::new(file).finish(&mut df).unwrap_or_else(|_| {
+                        panic!("Error writing to metrics csv file");
+                    });
+                });
+            }
+        }
+        OutputFileType::Parquet => {
+            // Write the DataFrame to a parquet file
+            let mut df = flatten_to_data_frame(output.events);
+            let file_path = output_dir.join(format!("{}.parquet", file_name));
+            debug!("`EventLogger` dumping event data into: {:?}", file_path);
+            persist(&file_path, |file| {
+                ParquetWriter::new(file)
+                    .finish(&mut df)
+                    .unwrap_or_else(|_| {
+                        panic!("Error writing to parquet file");
+                    });
+            });
+            if !output.metrics.is_empty() {
+                let mut df = metrics_to_data_frame(output.metrics);
+                let file_path = output_dir.join(format!("{}_metrics.parquet", file_name));
+                persist(&file_path, |file| {
+                    ParquetWriter::new(file)
+                        .finish(&mut df)
+                        .unwrap_or_else(|_| {
+                            panic!("Error writing to metrics parquet file");
+                        });
+                });
+            }
+        }
+    }
+}
+
+/// Calls `write` with a temporary file next to `path` and moves it into place
+/// once it has been written completely.
+fn persist(path: &Path, write: impl FnOnce(File)) {
+    // Several loggers may write into the same directory, so every temporary
+    // file gets its own name.
+    static PARTIAL_ID: AtomicUsize = AtomicUsize::new(0);
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(format!(
+        ".{}.{}.partial",
+        std::process::id(),
+        PARTIAL_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    let partial = PathBuf::from(partial);
+    let file = File::create(&partial).unwrap_or_else(|_| {
+        panic!("Error creating file {:?}", partial);
+    });
+    write(file);
+    std::fs::rename(&partial, path).unwrap_or_else(|_| {
+        panic!("Error moving {:?} into place", partial);
+    });
+}
+
+/// Returns `false` if the bloom of the transaction rules out every filter in
+/// the `decoder` so that its logs do not need to be decoded.
+fn may_match(decoder: &FilterDecoder, receipt_data: &ReceiptData) -> bool {
+    decoder.values().any(|(filtered_params, _)| {
+        filtered_params
+            .filter
+            .as_ref()
+            .is_none_or(|filter| bloom_matches(&receipt_data.logs_bloom, filter))
+    })
+}
+
 fn flatten_to_data_frame(events: BTreeMap<String, BTreeMap<String, Vec<Value>>>) -> DataFrame {
     // 1. Flatten the BTreeMap
     let mut contract_names = Vec::new();
@@ -366,6 +548,27 @@ fn flatten_to_data_frame(events: BTreeMap<String, BTreeMap<String, Vec<Value>>>)
     ])
     .unwrap()
 }
+fn metrics_to_data_frame(metrics: BTreeMap<String, Vec<(u64, f64)>>) -> DataFrame {
+    let mut metric_names = Vec::new();
+    let mut block_numbers = Vec::new();
+    let mut metric_values = Vec::new();
+
+    for (metric, values) in metrics {
+        for (block_number, value) in values {
+            metric_names.push(metric.clone());
+            block_numbers.push(block_number);
+            metric_values.push(value);
+        }
+    }
+
+    DataFrame::new(vec![
+        Series::new("metric_name", metric_names),
+        Series::new("block_number", block_numbers),
+        Series::new("metric_value", metric_values),
+    ])
+    .unwrap()
+}
+
 pub(crate) struct EventTransmuted<B, M, D> {
     /// The event filter's state
     pub filter: Filter,
@@ -389,7 +592,14 @@ pub fn stream_event<D: EthLogDecode + Debug + Serialize + 'static>(
 
     if let Some(mut receiver) = logger.receiver.take() {
         let stream = async_stream::stream! {
-            while let Ok(broadcast) = receiver.recv().await {
+            while let Some(broadcast) = receiver.recv().await {
+                let broadcast = match broadcast {
+                    Ok(broadcast) => broadcast,
+                    Err(e) => {
+                        error!("Ending the event stream: {}", e);
+                        break;
+                    }
+                };
                 match broadcast {
                     Broadcast::StopSignal => {
                         trace!("`EventLogger` has seen a stop signal");
@@ -397,6 +607,9 @@ pub fn stream_event<D: EthLogDecode + Debug + Serialize + 'static>(
                     }
                     Broadcast::Event(event, receipt_data) => {
                         trace!("`EventLogger` received an event");
+                        if !may_match(&logger.decoder, &receipt_data) {
+                            continue;
+                        }
                         let ethers_logs = revm_logs_to_ethers_logs(event, &receipt_data);
                         for log in &ethers_logs {
                             for (_id, (filter, _)) in logger.decoder.iter() {
@@ -0,0 +1,456 @@
+//! Derived metrics that are computed by the [`Logger`] from the events it
+//! collects.
+//!
+//! A [`Metric`] is defined by a string of the form `name = expression`, e.g.,
+//! `"pool_price = reserve1 / reserve0"`, so that metrics can be listed in a
+//! configuration file instead of requiring a custom analyzer to be written.
+//! Expressions support numbers, variables, `+`, `-`, `*`, `/`, `^`,
+//! parentheses, and the functions `abs`, `sqrt`, `ln`, `exp`, `min`, and
+//! `max`.
+//!
+//! Variables refer to the most recent value of an event field that the
+//! [`Logger`] has seen, either by the field name alone (`reserve0`) or
+//! prefixed by the name the event was registered with (`pool.reserve0`).
+//! Metrics can also refer to any metric that is defined before them.
+//!
+//! [`Logger`]: super::Logger
+
+use std::{fmt, str::FromStr};
+
+use serde_json::Value;
+
+use super::*;
+
+/// A derived metric that is evaluated over the collected event data at the end
+/// of every block.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Metric {
+    /// The name of the series the metric is stored under.
+    pub name: String,
+
+    /// The expression as it was written.
+    source: String,
+
+    /// The parsed expression.
+    expression: Expression,
+}
+
+impl Metric {
+    /// Evaluates the metric with the given `variables`. Returns `None` if any
+    /// of the variables the expression uses have not been seen yet.
+    pub fn evaluate(&self, variables: &HashMap<String, f64>) -> Option<f64> {
+        self.expression.evaluate(variables)
+    }
+}
+
+impl FromStr for Metric {
+    type Err = ArbiterCoreError;
+
+    fn from_str(definition: &str) -> Result<Self, Self::Err> {
+        let (name, source) = definition.split_once('=').ok_or_else(|| {
+            ArbiterCoreError::MetricError(format!(
+                "Metric `{}` must be of the form `name = expression`",
+                definition
+            ))
+        })?;
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(is_identifier_char) {
+            return Err(ArbiterCoreError::MetricError(format!(
+                "Invalid metric name `{}`",
+                name
+            )));
+        }
+        let source = source.trim();
+        let expression = Parser::new(source).parse()?;
+        Ok(Self {
+            name: name.to_owned(),
+            source: source.to_owned(),
+            expression,
+        })
+    }
+}
+
+impl TryFrom<String> for Metric {
+    type Error = ArbiterCoreError;
+
+    fn try_from(definition: String) -> Result<Self, Self::Error> {
+        definition.parse()
+    }
+}
+
+impl From<Metric> for String {
+    fn from(metric: Metric) -> Self {
+        metric.to_string()
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} = {}", self.name, self.source)
+    }
+}
+
+/// The latest values of the event fields seen by the [`Logger`](super::Logger)
+/// along with the series of every [`Metric`].
+#[derive(Debug, Default)]
+pub(crate) struct MetricCollector {
+    metrics: Vec<Metric>,
+    variables: HashMap<String, f64>,
+    block: Option<u64>,
+    series: BTreeMap<String, Vec<(u64, f64)>>,
+}
+
+impl MetricCollector {
+    pub(crate) fn new(metrics: Vec<Metric>) -> Self {
+        Self {
+            metrics,
+            ..Default::default()
+        }
+    }
+
+    /// Records the numeric fields of an event registered under `name`. If the
+    /// event belongs to a new block, the metrics are evaluated for the
+    /// previous block first.
+    pub(crate) fn record(&mut self, block: u64, name: &str, fields: &Value) {
+        if self.block.is_some_and(|current| current != block) {
+            self.evaluate();
+        }
+        self.block = Some(block);
+        if let Some(fields) = fields.as_object() {
+            for (field, value) in fields {
+                if let Some(value) = as_f64(value) {
+                    self.variables.insert(field.clone(), value);
+                    self.variables.insert(format!("{}.{}", name, field), value);
+                }
+            }
+        }
+    }
+
+    /// Evaluates the metrics for the current block and returns every series.
+    pub(crate) fn finish(mut self) -> BTreeMap<String, Vec<(u64, f64)>> {
+        self.evaluate();
+        self.series
+    }
+
+    fn evaluate(&mut self) {
+        let Some(block) = self.block else {
+            return;
+        };
+        for metric in &self.metrics {
+            match metric.evaluate(&self.variables) {
+                Some(value) => {
+                    self.variables.insert(metric.name.clone(), value);
+                    self.series
+                        .entry(metric.name.clone())
+                        .or_default()
+                        .push((block, value));
+                }
+                None => trace!("Metric {} could not be evaluated yet", metric.name),
+            }
+        }
+    }
+}
+
+/// Converts a JSON value emitted by a decoded event into a number. Integers
+/// are serialized as hex strings by `ethers`.
+fn as_f64(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::Bool(boolean) => Some(if *boolean { 1.0 } else { 0.0 }),
+        Value::String(string) => match string.strip_prefix("0x") {
+            Some(hex) => eU256::from_str_radix(hex, 16)
+                .ok()
+                .and_then(|number| number.to_string().parse().ok()),
+            None => string.parse().ok(),
+        },
+        _ => None,
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expression {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Function(Function, Vec<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Exp,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(Self::Abs),
+            "sqrt" => Some(Self::Sqrt),
+            "ln" => Some(Self::Ln),
+            "exp" => Some(Self::Exp),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Self::Min | Self::Max => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, variables: &HashMap<String, f64>) -> Option<f64> {
+        Some(match self {
+            Self::Number(number) => *number,
+            Self::Variable(name) => *variables.get(name)?,
+            Self::Negate(inner) => -inner.evaluate(variables)?,
+            Self::Binary(operator, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.evaluate(variables)?, rhs.evaluate(variables)?);
+                match operator {
+                    Operator::Add => lhs + rhs,
+                    Operator::Subtract => lhs - rhs,
+                    Operator::Multiply => lhs * rhs,
+                    Operator::Divide => lhs / rhs,
+                    Operator::Power => lhs.powf(rhs),
+                }
+            }
+            Self::Function(function, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.evaluate(variables))
+                    .collect::<Option<Vec<f64>>>()?;
+                match function {
+                    Function::Abs => arguments[0].abs(),
+                    Function::Sqrt => arguments[0].sqrt(),
+                    Function::Ln => arguments[0].ln(),
+                    Function::Exp => arguments[0].exp(),
+                    Function::Min => arguments[0].min(arguments[1]),
+                    Function::Max => arguments[0].max(arguments[1]),
+                }
+            }
+        })
+    }
+}
+
+/// A recursive descent parser for metric expressions.
+struct Parser<'a> {
+    source: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            position: 0,
+        }
+    }
+
+    fn parse(mut self) -> Result<Expression, ArbiterCoreError> {
+        let expression = self.expression()?;
+        match self.peek() {
+            None => Ok(expression),
+            Some(c) => Err(self.error(&format!("unexpected `{}`", c))),
+        }
+    }
+
+    fn error(&self, message: &str) -> ArbiterCoreError {
+        ArbiterCoreError::MetricError(format!(
+            "Failed to parse `{}` at position {}: {}",
+            self.source, self.position, message
+        ))
+    }
+
+    /// Returns the next non-whitespace character without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.source[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+        self.source[self.position..].chars().next()
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += expected.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// `expression = term (("+" | "-") term)*`
+    fn expression(&mut self) -> Result<Expression, ArbiterCoreError> {
+        let mut lhs = self.term()?;
+        loop {
+            let operator = if self.eat('+') {
+                Operator::Add
+            } else if self.eat('-') {
+                Operator::Subtract
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expression::Binary(operator, Box::new(lhs), Box::new(self.term()?));
+        }
+    }
+
+    /// `term = unary (("*" | "/") unary)*`
+    fn term(&mut self) -> Result<Expression, ArbiterCoreError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let operator = if self.eat('*') {
+                Operator::Multiply
+            } else if self.eat('/') {
+                Operator::Divide
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expression::Binary(operator, Box::new(lhs), Box::new(self.unary()?));
+        }
+    }
+
+    /// `unary = "-" unary | power`
+    fn unary(&mut self) -> Result<Expression, ArbiterCoreError> {
+        if self.eat('-') {
+            Ok(Expression::Negate(Box::new(self.unary()?)))
+        } else {
+            self.power()
+        }
+    }
+
+    /// `power = atom ("^" unary)?`, which is right associative.
+    fn power(&mut self) -> Result<Expression, ArbiterCoreError> {
+        let base = self.atom()?;
+        if self.eat('^') {
+            Ok(Expression::Binary(
+                Operator::Power,
+                Box::new(base),
+                Box::new(self.unary()?),
+            ))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// `atom = number | identifier | function "(" arguments ")" | "("
+    /// expression ")"`
+    fn atom(&mut self) -> Result<Expression, ArbiterCoreError> {
+        match self.peek() {
+            Some('(') => {
+                self.eat('(');
+                let expression = self.expression()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+                Ok(expression)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let literal = self.take_while(|c| c.is_ascii_digit() || c == '.' || c == '_');
+                literal
+                    .replace('_', "")
+                    .parse()
+                    .map(Expression::Number)
+                    .map_err(|_| self.error(&format!("invalid number `{}`", literal)))
+            }
+            Some(c) if is_identifier_char(c) => {
+                let identifier = self.take_while(is_identifier_char);
+                match Function::from_name(identifier) {
+                    Some(function) if self.peek() == Some('(') => self.call(function),
+                    _ => Ok(Expression::Variable(identifier.to_owned())),
+                }
+            }
+            Some(c) => Err(self.error(&format!("unexpected `{}`", c))),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+
+    fn call(&mut self, function: Function) -> Result<Expression, ArbiterCoreError> {
+        self.eat('(');
+        let mut arguments = vec![self.expression()?];
+        while self.eat(',') {
+            arguments.push(self.expression()?);
+        }
+        if !self.eat(')') {
+            return Err(self.error("expected `)`"));
+        }
+        if arguments.len() != function.arity() {
+            return Err(self.error(&format!(
+                "expected {} argument(s) but found {}",
+                function.arity(),
+                arguments.len()
+            )));
+        }
+        Ok(Expression::Function(function, arguments))
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> &'a str {
+        let start = self.position;
+        let rest = &self.source[start..];
+        let length = rest.find(|c| !predicate(c)).unwrap_or(rest.len());
+        self.position += length;
+        &self.source[start..start + length]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evaluate_expressions() {
+        let variables = HashMap::from([
+            ("reserve0".to_owned(), 4.0),
+            ("pool.reserve1".to_owned(), 8.0),
+        ]);
+        let evaluate = |definition: &str| {
+            Metric::from_str(definition)
+                .unwrap()
+                .evaluate(&variables)
+                .unwrap()
+        };
+        assert_eq!(evaluate("price = pool.reserve1 / reserve0"), 2.0);
+        assert_eq!(evaluate("x = 1 + 2 * 3 - 4"), 3.0);
+        assert_eq!(evaluate("x = -2 ^ 2"), -4.0);
+        assert_eq!(evaluate("x = 2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(evaluate("x = (1 + 2) * 3"), 9.0);
+        assert_eq!(evaluate("x = sqrt(reserve0) + max(1, min(2, 3))"), 4.0);
+        assert_eq!(evaluate("x = 1_000 / 10"), 100.0);
+    }
+
+    #[test]
+    fn invalid_metrics() {
+        assert!(Metric::from_str("no_equals").is_err());
+        assert!(Metric::from_str(" = 1").is_err());
+        assert!(Metric::from_str("x = (1 + 2").is_err());
+        assert!(Metric::from_str("x = 1 +").is_err());
+        assert!(Metric::from_str("x = max(1)").is_err());
+        assert!(Metric::from_str("x = 1 $ 2").is_err());
+    }
+
+    #[test]
+    fn missing_variables() {
+        let metric = Metric::from_str("x = y + 1").unwrap();
+        assert_eq!(metric.evaluate(&HashMap::new()), None);
+    }
+}
@@ -30,12 +30,24 @@
 #![warn(missing_docs)]
 
 pub mod console;
+pub mod conversions;
 pub mod coprocessor;
+pub mod coverage;
+pub mod create2;
 pub mod database;
 pub mod environment;
 pub mod errors;
 pub mod events;
 pub mod middleware;
+pub mod multicall;
+pub mod profiler;
+pub mod replay;
+pub mod rpc;
+pub mod settlement;
+pub mod shutdown;
+pub mod state_diff;
+pub mod trace;
+pub mod websocket;
 
 use std::{
     collections::{BTreeMap, HashMap},
@@ -0,0 +1,238 @@
+//! The `artifact` module loads contract artifacts (ABI and bytecode JSON) at
+//! runtime so that contracts can be deployed and called without generating
+//! bindings for them first.
+//!
+//! The artifact formats emitted by Foundry (`{"abi": [..], "bytecode":
+//! {"object": "0x.."}}`), Hardhat (`{"abi": [..], "bytecode": "0x.."}`), and
+//! `solc --combined-json abi,bin` (`{"contracts": {"<file>:<Name>": {"abi":
+//! [..], "bin": ".."}}}`) are understood. The contract of a combined output
+//! that holds more than one is selected by its name with
+//! [`ArtifactSource::contract`].
+//!
+//! Main components:
+//! - [`ArtifactSource`]: Where an artifact is read from.
+//! - [`Artifact`]: The ABI and creation bytecode of a contract.
+//! - [`ArbiterMiddleware::deploy_artifact`]: Deploys an artifact and returns a
+//!   dynamic [`Contract`] handle.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ethers::{
+    abi::{Abi, Tokenize},
+    contract::{Contract, ContractFactory},
+};
+
+use super::*;
+
+/// Where an [`Artifact`] is read from.
+#[derive(Debug, Clone)]
+pub enum ArtifactSource {
+    /// A path to an artifact JSON file.
+    Path(PathBuf),
+
+    /// The contents of an artifact JSON file.
+    Json(Vec<u8>),
+
+    /// The contract `name` of the `solc --combined-json` output read from
+    /// `source`, see [`ArtifactSource::contract`].
+    Contract {
+        /// Where the combined output is read from.
+        source: Box<ArtifactSource>,
+
+        /// The name of the contract, e.g., `"Counter"`, or its full name,
+        /// e.g., `"src/Counter.sol:Counter"`.
+        name: String,
+    },
+}
+
+impl ArtifactSource {
+    /// Selects the contract `name` from the `solc --combined-json` output read
+    /// from this source, e.g., `"Counter"` or `"src/Counter.sol:Counter"`.
+    pub fn contract(self, name: impl Into<String>) -> Self {
+        Self::Contract {
+            source: Box::new(self),
+            name: name.into(),
+        }
+    }
+}
+
+impl From<&str> for ArtifactSource {
+    fn from(path: &str) -> Self {
+        Self::Path(path.into())
+    }
+}
+
+impl From<String> for ArtifactSource {
+    fn from(path: String) -> Self {
+        Self::Path(path.into())
+    }
+}
+
+impl From<&Path> for ArtifactSource {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.into())
+    }
+}
+
+impl From<PathBuf> for ArtifactSource {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&[u8]> for ArtifactSource {
+    fn from(json: &[u8]) -> Self {
+        Self::Json(json.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for ArtifactSource {
+    fn from(json: Vec<u8>) -> Self {
+        Self::Json(json)
+    }
+}
+
+/// The ABI and creation bytecode of a contract read from an artifact.
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    /// The ABI of the contract.
+    pub abi: Abi,
+
+    /// The creation bytecode of the contract.
+    pub bytecode: eBytes,
+}
+
+impl Artifact {
+    /// Reads an [`Artifact`] from the given `source`.
+    ///
+    /// The only contract of a `solc --combined-json` output is read unless
+    /// one is selected with [`ArtifactSource::contract`].
+    pub fn load(source: impl Into<ArtifactSource>) -> Result<Self, ArbiterCoreError> {
+        let (source, name) = match source.into() {
+            ArtifactSource::Contract { source, name } => (*source, Some(name)),
+            source => (source, None),
+        };
+        let json = match source {
+            ArtifactSource::Path(path) => fs::read(&path).map_err(|e| {
+                ArbiterCoreError::ArtifactError(format!(
+                    "Failed to read artifact {:?}: {}",
+                    path, e
+                ))
+            })?,
+            ArtifactSource::Json(json) => json,
+            ArtifactSource::Contract { .. } => {
+                return Err(ArbiterCoreError::ArtifactError(
+                    "Only one contract can be selected from an artifact!".to_string(),
+                ))
+            }
+        };
+        let mut artifact: serde_json::Value = serde_json::from_slice(&json)?;
+        if let Some(contracts) = artifact.get_mut("contracts") {
+            artifact = select(contracts.take(), name.as_deref())?;
+        } else if let Some(name) = name {
+            return Err(ArbiterCoreError::ArtifactError(format!(
+                "The artifact is not a `solc --combined-json` output to select `{}` from!",
+                name
+            )));
+        }
+
+        let abi = match artifact.get_mut("abi").map(serde_json::Value::take) {
+            // Some tools store the ABI as a JSON encoded string.
+            Some(serde_json::Value::String(abi)) => serde_json::from_str(&abi)?,
+            Some(abi) => serde_json::from_value(abi)?,
+            None => {
+                return Err(ArbiterCoreError::ArtifactError(
+                    "The artifact does not contain an `abi`!".to_string(),
+                ))
+            }
+        };
+
+        let bytecode = artifact
+            .get("bytecode")
+            .map(|bytecode| bytecode.get("object").unwrap_or(bytecode))
+            .or_else(|| artifact.get("bin"))
+            .and_then(serde_json::Value::as_str)
+            .ok_or(ArbiterCoreError::ArtifactError(
+                "The artifact does not contain a `bytecode`!".to_string(),
+            ))?;
+        let bytecode = eBytes::from_str(bytecode).map_err(|e| {
+            ArbiterCoreError::ArtifactError(format!(
+                "The bytecode of the artifact is not valid hex, it may have unlinked libraries: {}",
+                e
+            ))
+        })?;
+        if bytecode.is_empty() {
+            return Err(ArbiterCoreError::ArtifactError(
+                "The artifact has no bytecode, it may be an interface or abstract contract!"
+                    .to_string(),
+            ));
+        }
+
+        Ok(Self { abi, bytecode })
+    }
+}
+
+/// Returns the contract `name` of the `contracts` of a `solc --combined-json`
+/// output, which are keyed by `<file>:<Name>`, or the only contract if no
+/// `name` is given.
+fn select(
+    contracts: serde_json::Value,
+    name: Option<&str>,
+) -> Result<serde_json::Value, ArbiterCoreError> {
+    let serde_json::Value::Object(mut contracts) = contracts else {
+        return Err(ArbiterCoreError::ArtifactError(
+            "The `contracts` of the artifact are not an object!".to_string(),
+        ));
+    };
+    let matching: Vec<_> = contracts
+        .keys()
+        .filter(|key| name.is_none_or(|name| *key == name || key.ends_with(&format!(":{}", name))))
+        .cloned()
+        .collect();
+    match matching.as_slice() {
+        [key] => Ok(contracts.remove(key).unwrap_or_default()),
+        [] => Err(ArbiterCoreError::ArtifactError(format!(
+            "The artifact does not contain a contract `{}`!",
+            name.unwrap_or_default()
+        ))),
+        _ => Err(ArbiterCoreError::ArtifactError(format!(
+            "The artifact contains more than one contract, select one of {} with \
+             `ArtifactSource::contract`!",
+            matching.join(", ")
+        ))),
+    }
+}
+
+impl ArbiterMiddleware {
+    /// Deploys the contract in the artifact read from `artifact` with the
+    /// given `constructor_args` and returns a [`Contract`] handle that calls
+    /// its functions through the ABI of the artifact.
+    ///
+    /// This is useful for quick experiments since no bindings have to be
+    /// generated for the contract.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let counter = client.deploy_artifact("out/Counter.sol/Counter.json", ()).await?;
+    /// counter.method::<_, ()>("increment", ())?.send().await?.await?;
+    /// let number: U256 = counter.method("number", ())?.call().await?;
+    /// ```
+    pub async fn deploy_artifact(
+        self: &Arc<Self>,
+        artifact: impl Into<ArtifactSource>,
+        constructor_args: impl Tokenize,
+    ) -> Result<Contract<Self>, ArbiterCoreError> {
+        let Artifact { abi, bytecode } = Artifact::load(artifact)?;
+        let contract = ContractFactory::new(abi, bytecode, self.clone())
+            .deploy(constructor_args)
+            .map_err(|e| ArbiterCoreError::DeploymentError(e.to_string()))?
+            .send()
+            .await
+            .map_err(|e| ArbiterCoreError::DeploymentError(e.to_string()))?;
+        debug!("Deployed artifact at {:?}", contract.address());
+        Ok(contract)
+    }
+}
@@ -0,0 +1,81 @@
+//! The `client` module abstracts over the clients that agents send their
+//! transactions with, so that a behavior written against an [`ArbiterClient`]
+//! runs against the [`Environment`] in a simulation and against a real network
+//! without changes.
+//!
+//! Main components:
+//! - [`ArbiterClient`]: What a behavior needs from its client.
+//! - [`LiveClient`]: A client that signs its transactions and sends them to a
+//!   node over a websocket, created with [`connect`].
+
+use ethers::{
+    middleware::SignerMiddleware,
+    providers::Ws,
+    signers::{LocalWallet, Signer},
+};
+
+use super::*;
+
+/// A client that a behavior can send transactions and calls with, i.e., an
+/// ethers [`Middleware`] that knows the address it sends from.
+///
+/// It is implemented by the [`ArbiterMiddleware`] that runs against an
+/// [`Environment`] and by any [`SignerMiddleware`], e.g., a [`LiveClient`]
+/// that runs against a real network. A behavior that only uses what its client
+/// has in common with every other client is generic over it:
+/// ```ignore
+/// #[async_trait::async_trait]
+/// impl<M: ArbiterClient> Behavior<(), M> for Arbitrageur {
+///     async fn startup(&mut self, client: Arc<M>, messager: Messager) -> Result<Option<EventStream<()>>> {
+///         let pool = Pool::new(self.pool, client.clone());
+///         // ...
+///     }
+/// }
+/// ```
+pub trait ArbiterClient: Middleware + 'static {
+    /// The address that the transactions of the client are sent from.
+    fn address(&self) -> eAddress;
+
+    /// The [`Profiler`] that the time spent by the behaviors using the client
+    /// is recorded into. Clients that do not run against an [`Environment`]
+    /// have a disabled profiler.
+    fn profiler(&self) -> Profiler {
+        Profiler::default()
+    }
+}
+
+impl ArbiterClient for ArbiterMiddleware {
+    fn address(&self) -> eAddress {
+        ArbiterMiddleware::address(self)
+    }
+
+    fn profiler(&self) -> Profiler {
+        ArbiterMiddleware::profiler(self)
+    }
+}
+
+impl<M, S> ArbiterClient for SignerMiddleware<M, S>
+where
+    M: Middleware + 'static,
+    S: Signer + 'static,
+{
+    fn address(&self) -> eAddress {
+        SignerMiddleware::address(self)
+    }
+}
+
+/// A client that signs its transactions with a [`LocalWallet`] and sends them
+/// to a node over a websocket.
+pub type LiveClient = SignerMiddleware<Provider<Ws>, LocalWallet>;
+
+/// Connects to the node at the websocket `url`, e.g., of a testnet, and
+/// returns a [`LiveClient`] that signs with `wallet` for the chain of the
+/// node.
+pub async fn connect(url: &str, wallet: LocalWallet) -> Result<Arc<LiveClient>, ArbiterCoreError> {
+    let provider = Provider::<Ws>::connect(url).await?;
+    let chain_id = provider.get_chainid().await?;
+    Ok(Arc::new(SignerMiddleware::new(
+        provider,
+        wallet.with_chain_id(chain_id.as_u64()),
+    )))
+}
@@ -1,8 +1,19 @@
 //! Messengers/connections to the underlying EVM in the environment.
-use std::sync::Weak;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Weak,
+};
+
+use crossbeam_channel::{RecvError, RecvTimeoutError};
 
 use super::*;
-use crate::environment::{InstructionSender, OutcomeReceiver, OutcomeSender};
+use crate::{
+    database::bloom::bloom_matches,
+    environment::{
+        delivery::DeliveryPolicy, EventBroadcaster, EventReceiver, InstructionSender, MiningMode,
+        OutcomeReceiver, OutcomeSender,
+    },
+};
 
 /// Represents a connection to the EVM contained in the corresponding
 /// [`Environment`].
@@ -13,33 +24,296 @@ pub struct Connection {
     pub(crate) instruction_sender: Weak<InstructionSender>,
 
     /// Used to send results back to a client that made a call/transaction with
-    /// the [`Environment`]. This [`ResultSender`] is passed along with a
-    /// call/transaction so the [`Environment`] can reply back with the
-    /// [`ExecutionResult`].
-    pub(crate) outcome_sender: OutcomeSender,
+    /// the [`Environment`] and to receive them. The [`OutcomeSender`] is
+    /// passed along with a call/transaction so the [`Environment`] can reply
+    /// back with the [`ExecutionResult`]. The channel is replaced when a reply
+    /// times out, so that the late reply is not mistaken for the reply to the
+    /// next instruction.
+    outcome_channel: Mutex<(OutcomeSender, OutcomeReceiver)>,
+
+    /// How long this client waits for the reply of the [`Environment`] before
+    /// giving up, or `None` to wait for as long as it takes.
+    pub(crate) response_timeout: Mutex<Option<Duration>>,
+
+    /// The [`RetryPolicy`] of the transactions sent by this client.
+    pub(crate) retry_policy: Mutex<RetryPolicy>,
+
+    /// The [`MiddlewareHooks`] run around the transactions sent by this
+    /// client, in the order they were added.
+    pub(crate) hooks: Mutex<Vec<Arc<dyn MiddlewareHooks>>>,
+
+    /// Used to subscribe to the [`Broadcast`]s of the [`Environment`].
+    pub(crate) event_sender: EventBroadcaster,
+
+    /// The [`DeliveryPolicy`] of the filters created by this client.
+    pub(crate) delivery_policy: Mutex<DeliveryPolicy>,
+
+    /// The gas limit of the transactions and calls sent by this client.
+    pub(crate) transaction_gas_limit: Mutex<Option<u64>>,
 
-    /// Used to receive the [`ExecutionResult`] from the [`Environment`] upon
-    /// call/transact.
-    pub(crate) outcome_receiver: OutcomeReceiver,
+    /// The percentage that the gas estimates of this client are padded with.
+    pub(crate) gas_estimate_buffer: Mutex<u64>,
 
-    pub(crate) event_sender: BroadcastSender<Broadcast>,
+    /// The [`MiningMode`] of the [`Environment`], which decides when the
+    /// transactions sent by this client resolve to their receipt.
+    pub(crate) mining_mode: MiningMode,
 
     /// A collection of `FilterReceiver`s that will receive outgoing logs
     /// generated by `revm` and output by the [`Environment`].
     pub(crate) filter_receivers: Arc<Mutex<HashMap<ethers::types::U256, FilterReceiver>>>,
+
+    /// The filters installed with `eth_newFilter`, keyed by their ID, whose
+    /// changes are polled with `eth_getFilterChanges`.
+    pub(crate) polling_filters: Mutex<HashMap<ethers::types::U256, PollingFilter>>,
+
+    /// The ID of the next filter created by this client.
+    next_filter_id: AtomicU64,
+
+    /// The [`Profiler`] shared with the [`Environment`].
+    pub(crate) profiler: Profiler,
 }
 
 impl From<&Environment> for Connection {
     fn from(environment: &Environment) -> Self {
         let instruction_sender = &Arc::clone(&environment.socket.instruction_sender);
-        let (outcome_sender, outcome_receiver) = crossbeam_channel::unbounded();
         Self {
             instruction_sender: Arc::downgrade(instruction_sender),
-            outcome_sender,
-            outcome_receiver,
+            outcome_channel: Mutex::new(crossbeam_channel::unbounded()),
+            response_timeout: Mutex::new(None),
+            retry_policy: Mutex::new(RetryPolicy::default()),
+            hooks: Mutex::new(Vec::new()),
             event_sender: environment.socket.event_broadcaster.clone(),
+            delivery_policy: Mutex::new(environment.parameters.delivery_policy),
+            transaction_gas_limit: Mutex::new(environment.parameters.transaction_gas_limit),
+            gas_estimate_buffer: Mutex::new(environment.parameters.gas_estimate_buffer),
+            mining_mode: environment.parameters.mining_mode,
             filter_receivers: Arc::new(Mutex::new(HashMap::new())),
+            polling_filters: Mutex::new(HashMap::new()),
+            next_filter_id: AtomicU64::new(1),
+            profiler: environment.profiler(),
+        }
+    }
+}
+
+impl Connection {
+    /// Creates a new [`Connection`] to the same [`Environment`] as `self` with
+    /// its own outcome channel and filters.
+    pub(crate) fn sibling(&self) -> Self {
+        Self {
+            instruction_sender: self.instruction_sender.clone(),
+            outcome_channel: Mutex::new(crossbeam_channel::unbounded()),
+            response_timeout: Mutex::new(*self.response_timeout.lock().unwrap()),
+            retry_policy: Mutex::new(*self.retry_policy.lock().unwrap()),
+            hooks: Mutex::new(self.hooks.lock().unwrap().clone()),
+            event_sender: self.event_sender.clone(),
+            delivery_policy: Mutex::new(*self.delivery_policy.lock().unwrap()),
+            transaction_gas_limit: Mutex::new(*self.transaction_gas_limit.lock().unwrap()),
+            gas_estimate_buffer: Mutex::new(*self.gas_estimate_buffer.lock().unwrap()),
+            mining_mode: self.mining_mode,
+            filter_receivers: Arc::new(Mutex::new(HashMap::new())),
+            polling_filters: Mutex::new(HashMap::new()),
+            next_filter_id: AtomicU64::new(1),
+            profiler: self.profiler.clone(),
+        }
+    }
+
+    /// Returns the sender that the [`Environment`] replies to an instruction of
+    /// this client with.
+    pub(crate) fn outcome_sender(&self) -> OutcomeSender {
+        self.outcome_channel.lock().unwrap().0.clone()
+    }
+
+    /// Waits for the reply of the [`Environment`] to the last instruction of
+    /// this client for at most its
+    /// [`response_timeout`](ArbiterMiddleware::response_timeout).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterCoreError::TimeoutError`] if the reply did not arrive
+    /// in time. The reply is then dropped whenever it arrives. Whatever the
+    /// instruction does is still done by the [`Environment`], e.g., a
+    /// transaction that timed out may still be executed.
+    pub(crate) fn receive(&self) -> Result<Outcome, ArbiterCoreError> {
+        let outcome_receiver = self.outcome_channel.lock().unwrap().1.clone();
+        let Some(timeout) = *self.response_timeout.lock().unwrap() else {
+            return outcome_receiver.recv()?;
+        };
+        match outcome_receiver.recv_timeout(timeout) {
+            Ok(outcome) => outcome,
+            Err(RecvTimeoutError::Timeout) => {
+                *self.outcome_channel.lock().unwrap() = crossbeam_channel::unbounded();
+                Err(ArbiterCoreError::TimeoutError(timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(RecvError.into()),
+        }
+    }
+
+    /// Creates the account at `address` in the [`Environment`].
+    pub(crate) fn add_account(&self, address: eAddress) -> Result<(), ArbiterCoreError> {
+        self.instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::AddAccount {
+                address,
+                outcome_sender: self.outcome_sender(),
+            })?;
+        self.receive()?;
+        Ok(())
+    }
+
+    /// Applies `cheatcode` to the [`Environment`] and returns what it returned.
+    pub(crate) fn apply_cheatcode(
+        &self,
+        cheatcode: Cheatcodes,
+    ) -> Result<CheatcodesReturn, ArbiterCoreError> {
+        self.instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::Cheatcode {
+                cheatcode,
+                outcome_sender: self.outcome_sender(),
+            })?;
+        match self.receive()? {
+            Outcome::CheatcodeReturn(outcome) => Ok(outcome),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Queries the [`Environment`] for `environment_data` and returns its
+    /// answer.
+    pub(crate) fn query(
+        &self,
+        environment_data: EnvironmentData,
+    ) -> Result<String, ArbiterCoreError> {
+        self.instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::Query {
+                environment_data,
+                outcome_sender: self.outcome_sender(),
+            })?;
+        match self.receive()? {
+            Outcome::QueryReturn(outcome) => Ok(outcome),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Creates a filter that receives the logs matching `filter` from now on
+    /// with the [`DeliveryPolicy`] of this client and returns its ID. Every
+    /// filter gets its own ID, even if the same filter was created before.
+    pub(crate) fn new_filter(&self, filter: Filter) -> ethers::types::U256 {
+        let id = ethers::types::U256::from(self.next_filter_id.fetch_add(1, Ordering::Relaxed));
+        let event_receiver = self
+            .event_sender
+            .subscribe_with(*self.delivery_policy.lock().unwrap());
+        let filter_receiver = FilterReceiver {
+            filter,
+            receiver: Some(event_receiver),
+        };
+        self.filter_receivers
+            .lock()
+            .unwrap()
+            .insert(id, filter_receiver);
+        debug!("Filter created with ID: {:?}", id);
+        id
+    }
+
+    /// Installs a filter that is polled for the logs matching `filter` that
+    /// were emitted since it was installed or last polled, and returns its ID.
+    pub(crate) fn install_filter(
+        &self,
+        filter: Filter,
+    ) -> Result<ethers::types::U256, ArbiterCoreError> {
+        let block_number = self.block_number()?;
+        let from_block = match filter.get_from_block() {
+            Some(from_block) if from_block.as_u64() > block_number => from_block.as_u64(),
+            _ => block_number,
+        };
+        // Logs that were emitted in the current block before the filter was
+        // installed are not new to it.
+        let mut polling_filter = PollingFilter {
+            filter,
+            cursor: (from_block, ethers::types::U256::zero()),
+        };
+        if from_block == block_number {
+            self.poll(&mut polling_filter, block_number)?;
+        }
+        let id = ethers::types::U256::from(self.next_filter_id.fetch_add(1, Ordering::Relaxed));
+        self.polling_filters
+            .lock()
+            .unwrap()
+            .insert(id, polling_filter);
+        debug!("Polling filter installed with ID: {:?}", id);
+        Ok(id)
+    }
+
+    /// Returns the logs matching the polling filter with the given `id` that
+    /// were emitted since it was last polled, or `None` if this client has no
+    /// polling filter with that ID.
+    pub(crate) fn filter_changes(
+        &self,
+        id: ethers::types::U256,
+    ) -> Result<Option<Vec<eLog>>, ArbiterCoreError> {
+        // The filter is taken out while the environment is queried so that
+        // other filters of this client can be used in the meantime.
+        let Some(mut polling_filter) = self.polling_filters.lock().unwrap().remove(&id) else {
+            return Ok(None);
+        };
+        let logs = self
+            .block_number()
+            .and_then(|block_number| self.poll(&mut polling_filter, block_number));
+        self.polling_filters
+            .lock()
+            .unwrap()
+            .insert(id, polling_filter);
+        logs.map(Some)
+    }
+
+    /// Returns the logs matching `polling_filter` from its cursor up to and
+    /// including the block with the given `block_number`, and moves its cursor
+    /// past them.
+    fn poll(
+        &self,
+        polling_filter: &mut PollingFilter,
+        block_number: u64,
+    ) -> Result<Vec<eLog>, ArbiterCoreError> {
+        let (cursor_block, cursor_index) = polling_filter.cursor;
+        let to_block = match polling_filter.filter.get_to_block() {
+            Some(to_block) => to_block.as_u64().min(block_number),
+            None => block_number,
+        };
+        if cursor_block > to_block {
+            return Ok(vec![]);
         }
+        let filter = polling_filter
+            .filter
+            .clone()
+            .from_block(cursor_block)
+            .to_block(to_block);
+        let logs: Vec<eLog> = serde_json::from_str(&self.query(EnvironmentData::Logs { filter })?)?;
+        let logs: Vec<eLog> = logs
+            .into_iter()
+            .filter(|log| {
+                log.block_number.map(|number| number.as_u64()) != Some(cursor_block)
+                    || log.log_index.unwrap_or_default() >= cursor_index
+            })
+            .collect();
+        // Logs that are emitted later on come after the last one, or in a later
+        // block if there was none.
+        polling_filter.cursor = match logs.last() {
+            Some(log) => (
+                log.block_number.unwrap_or_default().as_u64(),
+                log.log_index.unwrap_or_default() + 1,
+            ),
+            None if to_block > cursor_block => (to_block, ethers::types::U256::zero()),
+            None => polling_filter.cursor,
+        };
+        Ok(logs)
+    }
+
+    /// Returns the number of the current block of the [`Environment`].
+    fn block_number(&self) -> Result<u64, ArbiterCoreError> {
+        Ok(U64::from_str_radix(&self.query(EnvironmentData::BlockNumber)?, 10)?.as_u64())
     }
 }
 
@@ -49,7 +323,13 @@ impl JsonRpcClient for Connection {
 
     /// Processes a JSON-RPC request and returns the response.
     /// Currently only handles the `eth_getFilterChanges` call since this is
-    /// used for polling events emitted from the [`Environment`].
+    /// used for polling events emitted from the [`Environment`], along with
+    /// along with `eth_uninstallFilter` for the filters that are polled,
+    /// `eth_subscribe` to `logs` and `eth_unsubscribe` so that the
+    /// subscriptions of a [`Provider`] work like they do on a live network,
+    /// and `eth_blockNumber` and `eth_getTransactionReceipt` so that a
+    /// [`PendingTransaction`] can wait for its block to be mined and
+    /// confirmed.
     async fn request<T: Serialize + Send + Sync, R: DeserializeOwned>(
         &self,
         method: &str,
@@ -80,6 +360,15 @@ impl JsonRpcClient for Connection {
                         format!("The `str` representation of the filter ID could not be cast into `U256` due to: {:?}!", 
                         e)))?;
 
+                // Polling filters read the logs they have not returned yet from the
+                // `Environment`.
+                if let Some(logs) = self
+                    .filter_changes(id)
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?
+                {
+                    return Ok(serde_json::from_value(serde_json::to_value(logs)?)?);
+                }
+
                 // Get the corresponding `filter_receiver` and await for logs to appear.
                 let mut filter_receivers = self.filter_receivers.lock().unwrap();
                 let filter_receiver =
@@ -92,8 +381,18 @@ impl JsonRpcClient for Connection {
                 let mut logs = vec![];
                 let filtered_params = FilteredParams::new(Some(filter_receiver.filter.clone()));
                 if let Some(receiver) = filter_receiver.receiver.as_mut() {
-                    if let Ok(broadcast) = receiver.try_recv() {
+                    // Drain everything that arrived since the last poll so that a
+                    // slowly polling client does not fall further behind.
+                    while let Some(broadcast) = receiver.try_recv() {
+                        let broadcast =
+                            broadcast.map_err(|e| ProviderError::CustomError(e.to_string()))?;
                         match broadcast {
+                            // Skip the conversion entirely when the bloom rules out the filter.
+                            Broadcast::Event(_, receipt_data)
+                                if !bloom_matches(
+                                    &receipt_data.logs_bloom,
+                                    &filter_receiver.filter,
+                                ) => {}
                             Broadcast::Event(received_logs, receipt_data) => {
                                 let ethers_logs =
                                     revm_logs_to_ethers_logs(received_logs, &receipt_data);
@@ -118,6 +417,67 @@ impl JsonRpcClient for Connection {
                 let logs_deserializeowned: R = serde_json::from_str(&logs_str)?;
                 Ok(logs_deserializeowned)
             }
+            "eth_subscribe" => {
+                let params = serde_json::to_value(&params)?;
+                let (kind, filter) = match params.as_array().map(Vec::as_slice) {
+                    Some([kind]) => (kind.as_str(), Filter::default()),
+                    Some([kind, filter]) => {
+                        (kind.as_str(), serde_json::from_value(filter.clone())?)
+                    }
+                    _ => (None, Filter::default()),
+                };
+                match kind {
+                    Some("logs") => Ok(serde_json::from_value(serde_json::to_value(
+                        self.new_filter(filter),
+                    )?)?),
+                    kind => Err(ProviderError::CustomError(format!(
+                        "The subscription `{}` is not supported by the `Connection`!",
+                        kind.unwrap_or_default()
+                    ))),
+                }
+            }
+            "eth_unsubscribe" => {
+                let params = serde_json::to_value(&params)?;
+                let id: ethers::types::U256 = serde_json::from_value(
+                    params.get(0).cloned().ok_or(ProviderError::CustomError(
+                        "No subscription ID was passed to `eth_unsubscribe`!".to_string(),
+                    ))?,
+                )?;
+                let removed = self.filter_receivers.lock().unwrap().remove(&id).is_some();
+                Ok(serde_json::from_value(serde_json::Value::Bool(removed))?)
+            }
+            "eth_uninstallFilter" => {
+                let params = serde_json::to_value(&params)?;
+                let id: ethers::types::U256 = serde_json::from_value(
+                    params.get(0).cloned().ok_or(ProviderError::CustomError(
+                        "No filter ID was passed to `eth_uninstallFilter`!".to_string(),
+                    ))?,
+                )?;
+                let removed = self.polling_filters.lock().unwrap().remove(&id).is_some()
+                    || self.filter_receivers.lock().unwrap().remove(&id).is_some();
+                Ok(serde_json::from_value(serde_json::Value::Bool(removed))?)
+            }
+            "eth_blockNumber" => {
+                let block_number = self
+                    .query(EnvironmentData::BlockNumber)
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+                let block_number = U64::from_str_radix(&block_number, 10)
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+                Ok(serde_json::from_value(serde_json::to_value(block_number)?)?)
+            }
+            "eth_getTransactionReceipt" => {
+                let params = serde_json::to_value(&params)?;
+                let transaction_hash: H256 = serde_json::from_value(
+                    params.get(0).cloned().ok_or(ProviderError::CustomError(
+                        "No transaction hash was passed to `eth_getTransactionReceipt`!"
+                            .to_string(),
+                    ))?,
+                )?;
+                let receipt = self
+                    .query(EnvironmentData::TransactionReceipt(transaction_hash))
+                    .map_err(|e| ProviderError::CustomError(e.to_string()))?;
+                Ok(serde_json::from_str(&receipt)?)
+            }
             val => Err(ProviderError::CustomError(format!(
                 "The method `{}` is not supported by the `Connection`!",
                 val
@@ -136,21 +496,31 @@ impl PubsubClient for Connection {
         let id = id.into();
         debug!("Subscribing to filter with ID: {:?}", id);
 
-        let mut filter_receiver = self
-            .filter_receivers
-            .lock()
-            .unwrap()
-            .remove(&id)
-            .take()
-            .unwrap();
+        let mut filter_receiver =
+            self.filter_receivers
+                .lock()
+                .unwrap()
+                .remove(&id)
+                .ok_or(ProviderError::CustomError(
+                    "The filter ID does not seem to match any that this client owns!".to_string(),
+                ))?;
 
         let mut receiver = filter_receiver.receiver.take().unwrap();
         let stream = async_stream::stream! {
-                    while let Ok(broadcast) = receiver.recv().await {
+                    while let Some(broadcast) = receiver.recv().await {
+                        let broadcast = match broadcast {
+                            Ok(broadcast) => broadcast,
+                            Err(e) => {
+                                error!("Ending the subscription to filter {:?}: {}", id, e);
+                                break;
+                            }
+                        };
                         match broadcast {
                             Broadcast::StopSignal => {
                                 break;
                             }
+                        Broadcast::Event(_, receipt_data)
+                            if !bloom_matches(&receipt_data.logs_bloom, &filter_receiver.filter) => {}
                         Broadcast::Event(logs, receipt_data) => {
                             let filtered_params =
                                 FilteredParams::new(Some(filter_receiver.filter.clone()));
@@ -200,6 +570,45 @@ impl PubsubClient for Connection {
     }
 }
 
+/// How a client retries a transaction that the [`Environment`] rejected
+/// because of its nonce, e.g., when the nonce of its sender was changed by a
+/// cheatcode or by another client of the same account, or when a transaction
+/// of the same account that was sent concurrently took its place.
+///
+/// Only transactions whose nonce was assigned by the client are retried, and
+/// each retry reads the nonce of the sender from the [`Environment`] again.
+/// A transaction that was executed, even if it reverted, or whose reply timed
+/// out is never retried, since it may have changed the state already.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many times a transaction is sent again before its error is
+    /// returned.
+    pub max_retries: u32,
+
+    /// How long the client waits before each retry.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Retries once without waiting.
+    fn default() -> Self {
+        Self {
+            max_retries: 1,
+            backoff: Duration::ZERO,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+}
+
 /// Packages together a [`crossbeam_channel::Receiver<Vec<Log>>`] along with a
 /// [`Filter`] for events. Allows the client to have a stream of filtered
 /// events.
@@ -211,7 +620,21 @@ pub(crate) struct FilterReceiver {
 
     /// The receiver for the channel that receives logs from the broadcaster.
     /// These are filtered upon reception.
-    pub(crate) receiver: Option<BroadcastReceiver<Broadcast>>,
+    pub(crate) receiver: Option<EventReceiver>,
+}
+
+/// A filter installed with `eth_newFilter` that is polled for its changes with
+/// `eth_getFilterChanges`. Rather than holding a receiver of the broadcasts of
+/// the [`Environment`], it keeps a cursor into the logs stored by the
+/// [`Environment`], so it never falls behind however rarely it is polled.
+#[derive(Debug)]
+pub(crate) struct PollingFilter {
+    /// The filter definition used for this filter.
+    pub(crate) filter: Filter,
+
+    /// The block number and the index in that block of the first log that has
+    /// not been returned yet.
+    pub(crate) cursor: (u64, ethers::types::U256),
 }
 
 // TODO: The logs below could have the block number, transaction index, and
@@ -226,7 +649,7 @@ pub(crate) struct FilterReceiver {
 #[inline]
 pub fn revm_logs_to_ethers_logs(revm_logs: Vec<Log>, receipt_data: &ReceiptData) -> Vec<eLog> {
     let mut logs: Vec<eLog> = vec![];
-    for revm_log in revm_logs {
+    for (transaction_log_index, revm_log) in revm_logs.into_iter().enumerate() {
         let topics = revm_log.topics().iter().map(recast_b256).collect();
         let data = eBytes::from(revm_log.data.data.0);
         let log = eLog {
@@ -235,10 +658,10 @@ pub fn revm_logs_to_ethers_logs(revm_logs: Vec<Log>, receipt_data: &ReceiptData)
             data,
             block_hash: Some(H256::default()),
             block_number: Some(receipt_data.block_number),
-            transaction_hash: Some(H256::default()),
+            transaction_hash: Some(receipt_data.transaction_hash),
             transaction_index: Some(receipt_data.transaction_index),
             log_index: Some(eU256::from(0)),
-            transaction_log_index: None,
+            transaction_log_index: Some(eU256::from(transaction_log_index)),
             log_type: None,
             removed: None,
         };
@@ -247,6 +670,63 @@ pub fn revm_logs_to_ethers_logs(revm_logs: Vec<Log>, receipt_data: &ReceiptData)
     logs
 }
 
+/// Builds the [`TransactionReceipt`] of a transaction sent `from` an account
+/// `to` another one, or to deploy a contract if `to` is `None`, that executed
+/// with the given `result` and `logs`.
+pub(crate) fn transaction_receipt(
+    from: eAddress,
+    to: Option<eAddress>,
+    result: &ExecutionResult,
+    logs: Vec<eLog>,
+    receipt_data: &ReceiptData,
+) -> Result<TransactionReceipt, ArbiterCoreError> {
+    let contract_address = match result {
+        ExecutionResult::Success {
+            output: Output::Create(_, address),
+            ..
+        } => address.map(recast_address),
+        _ => None,
+    };
+    Ok(TransactionReceipt {
+        transaction_hash: receipt_data.transaction_hash,
+        transaction_index: receipt_data.transaction_index,
+        block_hash: None,
+        block_number: Some(receipt_data.block_number),
+        from,
+        to,
+        cumulative_gas_used: receipt_data.cumulative_gas_per_block,
+        gas_used: Some(result.gas_used().into()),
+        contract_address,
+        logs,
+        status: Some(u64::from(result.is_success()).into()),
+        root: None,
+        logs_bloom: receipt_data.logs_bloom,
+        transaction_type: receipt_data.transaction_type,
+        effective_gas_price: receipt_data.effective_gas_price,
+        other: receipt_fields(receipt_data)?,
+    })
+}
+
+/// Returns the extra fields of the receipt of a transaction, which hold its
+/// [`StateDiff`](crate::state_diff::StateDiff) if one was recorded and its
+/// settlement fee if it paid one.
+fn receipt_fields(receipt_data: &ReceiptData) -> Result<OtherFields, ArbiterCoreError> {
+    let mut fields = OtherFields::default();
+    if let Some(state_diff) = &receipt_data.state_diff {
+        fields.insert(
+            state_diff::RECEIPT_FIELD.to_owned(),
+            serde_json::to_value(state_diff)?,
+        );
+    }
+    if let Some(settlement_fee) = &receipt_data.settlement_fee {
+        fields.insert(
+            settlement::RECEIPT_FIELD.to_owned(),
+            serde_json::to_value(settlement_fee)?,
+        );
+    }
+    Ok(fields)
+}
+
 /// Recast a B256 into an H256 type
 /// # Arguments
 /// * `input` - B256 to recast. (B256)
@@ -0,0 +1,56 @@
+//! The `hooks` module lets users instrument the transactions of an
+//! [`ArbiterMiddleware`] without forking it, e.g., to model the latency of a
+//! network, to record every transaction for an audit, or to change the gas
+//! parameters of every transaction of a client.
+//!
+//! Main components:
+//! - [`MiddlewareHooks`]: What is done before a transaction is sent and after
+//!   its receipt is known.
+
+use super::*;
+
+/// Hooks that an [`ArbiterMiddleware`] runs around each of its transactions,
+/// added with [`ArbiterMiddleware::add_hooks`].
+///
+/// Every hook does nothing by default, so an implementation only overrides
+/// the ones it needs. The hooks of a client run in the order they were added.
+///
+/// # Examples
+/// ```ignore
+/// /// Delays every transaction as if it had to travel to a node first.
+/// #[derive(Debug)]
+/// struct Latency(Duration);
+///
+/// #[async_trait::async_trait]
+/// impl MiddlewareHooks for Latency {
+///     async fn before_send(&self, _tx: &mut TypedTransaction) {
+///         tokio::time::sleep(self.0).await;
+///     }
+/// }
+///
+/// client.add_hooks(Latency(Duration::from_millis(50)));
+/// ```
+#[async_trait::async_trait]
+pub trait MiddlewareHooks: Debug + Send + Sync {
+    /// Runs before `tx` is sent, which can be changed, e.g., to set its gas
+    /// price, or be held back, e.g., to model latency. The sender and nonce of
+    /// `tx` are only filled in afterwards if they are not set.
+    async fn before_send(&self, _tx: &mut TypedTransaction) {}
+
+    /// Runs once `tx` was executed successfully with its `receipt`.
+    /// Transactions that revert or halt fail before they have a receipt.
+    async fn after_receipt(&self, _tx: &TypedTransaction, _receipt: &TransactionReceipt) {}
+}
+
+/// Hooks that are shared, e.g., to read what they recorded while they are
+/// added to a client.
+#[async_trait::async_trait]
+impl<H: MiddlewareHooks + ?Sized> MiddlewareHooks for Arc<H> {
+    async fn before_send(&self, tx: &mut TypedTransaction) {
+        self.as_ref().before_send(tx).await
+    }
+
+    async fn after_receipt(&self, tx: &TypedTransaction, receipt: &TransactionReceipt) {
+        self.as_ref().after_receipt(tx, receipt).await
+    }
+}
@@ -5,6 +5,10 @@
 //!
 //! Main components:
 //! - [`ArbiterMiddleware`]: The core middleware implementation.
+//! - [`artifact::Artifact`]: Contract ABI and bytecode loaded at runtime.
+//! - [`client::ArbiterClient`]: The clients that behaviors can run against.
+//! - [`hooks::MiddlewareHooks`]: Instrumentation around the transactions of a
+//!   client.
 //! - [`Connection`]: Handles communication with the Ethereum VM.
 //! - [`FilterReceiver`]: Facilitates event watching based on certain filters.
 
@@ -12,7 +16,7 @@
 use std::{future::Future, pin::Pin, sync::Mutex, time::Duration};
 
 use ethers::{
-    abi::ethereum_types::BloomInput,
+    contract::Multicall,
     prelude::{
         k256::{
             ecdsa::SigningKey,
@@ -27,23 +31,42 @@ use ethers::{
     signers::{Signer, Wallet},
     types::{
         transaction::{eip2718::TypedTransaction, eip712::Eip712},
-        Address as eAddress, BlockId, Bloom, Bytes as eBytes, FilteredParams, NameOrAddress,
-        Signature, Transaction, TransactionReceipt,
+        Address as eAddress, Block, BlockId, BlockNumber, Bytes as eBytes, FeeHistory,
+        FilteredParams, NameOrAddress, OtherFields, Signature, Transaction, TransactionReceipt,
+        TransactionRequest,
     },
 };
 use futures_timer::Delay;
 use futures_util::Stream;
 use rand::{rngs::StdRng, SeedableRng};
-use revm::primitives::{CreateScheme, Output, TransactTo};
+use revm::primitives::{
+    CreateScheme, EVMError, HaltReason, InvalidTransaction, Output, TransactTo,
+};
 use serde::de::DeserializeOwned;
 use serde_json::value::RawValue;
 
 use super::*;
-use crate::environment::{instruction::*, Broadcast, Environment};
+use crate::{
+    conversions::ToAlloy,
+    coverage::Coverage,
+    create2::{self, CREATE2_FACTORY_ADDRESS},
+    environment::{delivery::DeliveryPolicy, instruction::*, Broadcast, Environment, MiningMode},
+    multicall::MULTICALL_ADDRESS,
+    profiler::Profiler,
+    settlement, state_diff,
+    trace::CallTrace,
+};
+
+pub mod artifact;
+
+pub mod client;
 
 pub mod connection;
 use connection::*;
 
+pub mod hooks;
+use hooks::MiddlewareHooks;
+
 pub mod nonce_middleware;
 /// A middleware structure that integrates with `revm`.
 ///
@@ -71,15 +94,97 @@ pub mod nonce_middleware;
 /// Use a seed like `Some("test_label")` for maintaining a
 /// consistent address across simulations and client labeling. Seeding is be
 /// useful for debugging and post-processing.
+///
+/// A client can own more accounts than the one it was created with, see
+/// [`ArbiterMiddleware::add_account`] and [`ArbiterMiddleware::with_sender`].
 #[derive(Debug)]
 pub struct ArbiterMiddleware {
     provider: Provider<Connection>,
     wallet: EOA,
+    /// Every account owned by the client, shared with the clients returned by
+    /// [`ArbiterMiddleware::with_sender`].
+    accounts: Arc<Mutex<Vec<EOA>>>,
+    /// The nonce of the next transaction of each account the client sent from.
+    nonces: Arc<Mutex<HashMap<eAddress, u64>>>,
     /// An optional label for the middleware instance
     #[allow(unused)]
     pub label: Option<String>,
 }
 
+/// A builder for creating an [`ArbiterMiddleware`].
+///
+/// The builder creates the account of the client in the [`Environment`] and
+/// funds it, so that a client is ready to send transactions without setting
+/// up its account by hand.
+#[derive(Clone, Debug, Default)]
+pub struct ArbiterMiddlewareBuilder {
+    label: Option<String>,
+    seed: Option<String>,
+    initial_balance: Option<eU256>,
+}
+
+impl ArbiterMiddlewareBuilder {
+    /// Builds the [`ArbiterMiddleware`] and creates its account in the
+    /// `environment` with its initial balance.
+    ///
+    /// The signer of the client is generated from its seed, or from its label
+    /// if it has no seed, so that the client has the same address in every
+    /// simulation. Without either, the signer is random.
+    pub fn build(
+        self,
+        environment: &Environment,
+    ) -> Result<Arc<ArbiterMiddleware>, ArbiterCoreError> {
+        let connection = Connection::from(environment);
+        let wallet = wallet(self.seed.as_deref().or(self.label.as_deref()));
+        connection.add_account(wallet.address())?;
+        if let Some(amount) = self.initial_balance {
+            connection.apply_cheatcode(Cheatcodes::SetBalance {
+                address: wallet.address(),
+                amount,
+            })?;
+        }
+
+        let provider = Provider::new(connection);
+        info!(
+            "Created new `ArbiterMiddleware` instance attached to environment labeled:
+        {:?}",
+            environment.parameters.label
+        );
+        Ok(Arc::new(ArbiterMiddleware {
+            accounts: Arc::new(Mutex::new(vec![EOA::Wallet(wallet.clone())])),
+            nonces: Default::default(),
+            wallet: EOA::Wallet(wallet),
+            provider,
+            label: self.label,
+        }))
+    }
+
+    /// Sets the label of the client, which also seeds its signer unless it
+    /// has a seed of its own.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    /// Sets the seed that the signer of the client is generated from, e.g.,
+    /// to keep the address of a client when its label changes.
+    pub fn with_seed(mut self, seed: impl ToString) -> Self {
+        self.seed = Some(seed.to_string());
+        self
+    }
+
+    /// Sets the initial balance of the client in ETH.
+    pub fn with_initial_eth(self, eth: u64) -> Self {
+        self.with_initial_balance(eU256::from(eth) * eU256::exp10(18))
+    }
+
+    /// Sets the initial balance of the client in wei.
+    pub fn with_initial_balance(mut self, wei: impl Into<eU256>) -> Self {
+        self.initial_balance = Some(wei.into());
+        self
+    }
+}
+
 #[async_trait]
 impl Signer for ArbiterMiddleware {
     type Error = ArbiterCoreError;
@@ -112,6 +217,14 @@ impl Signer for ArbiterMiddleware {
 
     /// Encodes and signs the typed data according EIP-712.
     /// Payload must implement Eip712 trait.
+    ///
+    /// This is how signatures are made for contracts that verify them
+    /// on-chain, e.g., for an ERC-2612 `permit`, where the domain of
+    /// `payload` has to hold the chain ID of the [`Environment`] and the
+    /// address of the contract. Other accounts of the client sign through the
+    /// client returned by [`ArbiterMiddleware::with_sender`]. A payload that
+    /// is only known at runtime can be signed as a
+    /// [`TypedData`](ethers::types::transaction::eip712::TypedData).
     async fn sign_typed_data<T: Eip712 + Send + Sync>(
         &self,
         payload: &T,
@@ -127,10 +240,7 @@ impl Signer for ArbiterMiddleware {
 
     /// Returns the signer's Ethereum Address
     fn address(&self) -> eAddress {
-        match &self.wallet {
-            EOA::Forked(address) => *address,
-            EOA::Wallet(wallet) => wallet.address(),
-        }
+        self.wallet.address()
     }
 
     /// Returns the signer's chain id
@@ -192,6 +302,31 @@ pub enum EOA {
     Wallet(Wallet<SigningKey>),
 }
 
+impl EOA {
+    /// Returns the address of the account.
+    pub fn address(&self) -> eAddress {
+        match self {
+            EOA::Forked(address) => *address,
+            EOA::Wallet(wallet) => wallet.address(),
+        }
+    }
+}
+
+/// Returns a procedurally generated wallet if provided a seed and a random one
+/// if not.
+fn wallet(seed: Option<&str>) -> Wallet<SigningKey> {
+    if let Some(seed) = seed {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        let hashed = hasher.finalize();
+        let mut rng: StdRng = SeedableRng::from_seed(hashed.into());
+        Wallet::new(&mut rng)
+    } else {
+        let mut rng = rand::thread_rng();
+        Wallet::new(&mut rng)
+    }
+}
+
 impl ArbiterMiddleware {
     /// Creates a new instance of `ArbiterMiddleware` with procedurally
     /// generated signer/address if provided a seed/label and otherwise a
@@ -212,42 +347,37 @@ impl ArbiterMiddleware {
     /// ```
     /// Use a seed if you want to have a constant address across simulations as
     /// well as a label for a client. This can be useful for debugging.
+    ///
+    /// See [`ArbiterMiddleware::builder`] to also fund the account of the
+    /// client or to seed it separately from its label.
     pub fn new(
         environment: &Environment,
         seed_and_label: Option<&str>,
     ) -> Result<Arc<Self>, ArbiterCoreError> {
-        let connection = Connection::from(environment);
-        let wallet = if let Some(seed) = seed_and_label {
-            let mut hasher = Sha256::new();
-            hasher.update(seed);
-            let hashed = hasher.finalize();
-            let mut rng: StdRng = SeedableRng::from_seed(hashed.into());
-            Wallet::new(&mut rng)
-        } else {
-            let mut rng = rand::thread_rng();
-            Wallet::new(&mut rng)
-        };
-        connection
-            .instruction_sender
-            .upgrade()
-            .ok_or(ArbiterCoreError::UpgradeSenderError)?
-            .send(Instruction::AddAccount {
-                address: wallet.address(),
-                outcome_sender: connection.outcome_sender.clone(),
-            })?;
-        connection.outcome_receiver.recv()??;
+        let mut builder = Self::builder();
+        if let Some(label) = seed_and_label {
+            builder = builder.with_label(label);
+        }
+        builder.build(environment)
+    }
 
-        let provider = Provider::new(connection);
-        info!(
-            "Created new `ArbiterMiddleware` instance attached to environment labeled:
-        {:?}",
-            environment.parameters.label
-        );
-        Ok(Arc::new(Self {
-            wallet: EOA::Wallet(wallet),
-            provider,
-            label: seed_and_label.map(|s| s.to_string()),
-        }))
+    /// Returns an [`ArbiterMiddlewareBuilder`] to configure the label, seed,
+    /// and initial balance of a new client.
+    ///
+    /// # Examples
+    /// ```
+    /// use arbiter_core::{environment::Environment, middleware::ArbiterMiddleware};
+    ///
+    /// let environment = Environment::builder().build();
+    /// let arbitrageur = ArbiterMiddleware::builder()
+    ///     .with_label("arbitrageur")
+    ///     .with_seed(42)
+    ///     .with_initial_eth(10)
+    ///     .build(&environment)
+    ///     .unwrap();
+    /// ```
+    pub fn builder() -> ArbiterMiddlewareBuilder {
+        ArbiterMiddlewareBuilder::default()
     }
 
     // TODO: This needs to have the label retrieved from the fork config.
@@ -256,34 +386,229 @@ impl ArbiterMiddleware {
         environment: &Environment,
         forked_eoa: eAddress,
     ) -> Result<Arc<Self>, ArbiterCoreError> {
-        let instruction_sender = &Arc::clone(&environment.socket.instruction_sender);
-        let (outcome_sender, outcome_receiver) = crossbeam_channel::unbounded();
-
-        let connection = Connection {
-            instruction_sender: Arc::downgrade(instruction_sender),
-            outcome_sender,
-            outcome_receiver: outcome_receiver.clone(),
-            event_sender: environment.socket.event_broadcaster.clone(),
-            filter_receivers: Arc::new(Mutex::new(HashMap::new())),
-        };
-        let provider = Provider::new(connection);
+        let provider = Provider::new(Connection::from(environment));
         info!(
             "Created new `ArbiterMiddleware` instance from a fork -- attached to environment labeled: {:?}",
             environment.parameters.label
         );
         Ok(Arc::new(Self {
             wallet: EOA::Forked(forked_eoa),
+            accounts: Arc::new(Mutex::new(vec![EOA::Forked(forked_eoa)])),
+            nonces: Default::default(),
             provider,
             label: None,
         }))
     }
 
+    /// Creates a new instance of `ArbiterMiddleware` attached to the same
+    /// [`Environment`] that sends transactions "as" the given `address`
+    /// without needing its private key, similar to anvil's
+    /// `impersonateAccount`.
+    ///
+    /// This is useful for driving accounts from forked state (e.g., whales or
+    /// governance multisigs) from within an agent that only holds a client.
+    /// Like [`EOA::Forked`] accounts, the impersonated client cannot sign
+    /// messages.
+    pub fn impersonate(&self, address: eAddress) -> Arc<Self> {
+        let provider = Provider::new(self.provider.as_ref().sibling());
+        info!("Impersonating account {:?}", address);
+        Arc::new(Self {
+            wallet: EOA::Forked(address),
+            accounts: Arc::new(Mutex::new(vec![EOA::Forked(address)])),
+            nonces: Default::default(),
+            provider,
+            label: self.label.clone(),
+        })
+    }
+
+    /// Creates a new account in the [`Environment`] that is owned by this
+    /// client, with a procedurally generated signer/address if provided a seed
+    /// and a random signer if not, and returns its address.
+    ///
+    /// This lets a single agent operate a fleet of wallets, e.g., the
+    /// inventory wallets of a market maker. Transactions are sent from an
+    /// added account by setting it as their `from` address or through the
+    /// client returned by [`ArbiterMiddleware::with_sender`].
+    pub fn add_account(&self, seed: Option<&str>) -> Result<eAddress, ArbiterCoreError> {
+        let wallet = wallet(seed);
+        let address = wallet.address();
+        self.provider.as_ref().add_account(address)?;
+        self.accounts.lock().unwrap().push(EOA::Wallet(wallet));
+        debug!("Added account {:?} to the client", address);
+        Ok(address)
+    }
+
+    /// Returns the addresses of every account owned by this client, starting
+    /// with the one it was created with.
+    pub fn accounts(&self) -> Vec<eAddress> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(EOA::address)
+            .collect()
+    }
+
+    /// Returns a client attached to the same [`Environment`] that sends
+    /// transactions and signs with the account at `address`, which has to be
+    /// owned by this client. The returned client owns the same accounts as
+    /// this one, so it can be used wherever a client is expected, e.g., to
+    /// bind a contract to one of the wallets of an agent.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterCoreError::AccountDoesNotExistError`] if the account
+    /// is not owned by this client.
+    pub fn with_sender(&self, address: eAddress) -> Result<Arc<Self>, ArbiterCoreError> {
+        let wallet = self.account(address)?;
+        Ok(Arc::new(Self {
+            wallet,
+            accounts: self.accounts.clone(),
+            nonces: self.nonces.clone(),
+            provider: Provider::new(self.provider.as_ref().sibling()),
+            label: self.label.clone(),
+        }))
+    }
+
+    /// Returns a [`Multicall`] that batches calls through the Multicall3
+    /// contract deployed in the [`Environment`], so that dozens of view calls
+    /// are made in a single round trip to the [`Environment`].
+    ///
+    /// Calls are added with [`Multicall::add_call`] and made with
+    /// [`Multicall::call`] or [`Multicall::call_array`]. Sending the batch as a
+    /// transaction with [`Multicall::send`] is supported as long as none of
+    /// its calls send value.
+    pub fn multicall(self: &Arc<Self>) -> Multicall<Self> {
+        // The address is given, so the chain does not have to be checked and
+        // this cannot fail.
+        Multicall::new_with_chain_id(self.clone(), Some(MULTICALL_ADDRESS), None::<u64>).unwrap()
+    }
+
+    /// Deploys the creation bytecode `init_code`, i.e., the bytecode of a
+    /// contract followed by its ABI encoded constructor arguments, with `salt`
+    /// through the deterministic deployment proxy and returns the address of
+    /// the contract.
+    ///
+    /// The address only depends on `init_code` and `salt`, so it can be
+    /// predicted before the deployment with
+    /// [`create2::deterministic_address`], e.g., to wire up contracts that
+    /// reference each other. Deploying the same `init_code` with the same
+    /// `salt` twice fails.
+    ///
+    /// # Examples
+    /// ```ignore
+    /// let deployer = ArbiterToken::deploy(client.clone(), args)?;
+    /// let init_code = deployer.deployer.tx.data().unwrap().clone();
+    /// let predicted = create2::deterministic_address(init_code.clone(), salt);
+    /// let address = client.deploy_deterministic(init_code, salt).await?;
+    /// assert_eq!(address, predicted);
+    /// ```
+    pub async fn deploy_deterministic(
+        &self,
+        init_code: impl Into<eBytes>,
+        salt: H256,
+    ) -> Result<eAddress, ArbiterCoreError> {
+        let init_code = init_code.into();
+        let address = create2::deterministic_address(init_code.clone(), salt);
+        let mut data = salt.as_bytes().to_vec();
+        data.extend_from_slice(&init_code);
+        let tx = TransactionRequest::new()
+            .to(CREATE2_FACTORY_ADDRESS)
+            .data(data);
+        self.send_transaction(tx, None)
+            .await
+            .map_err(|e| ArbiterCoreError::DeploymentError(e.to_string()))?
+            .await
+            .map_err(|e| ArbiterCoreError::DeploymentError(e.to_string()))?;
+        debug!("Deployed deterministically at {:?}", address);
+        Ok(address)
+    }
+
+    /// Returns the nonce that the next transaction sent by this client from
+    /// `address` is assigned, without using it up.
+    ///
+    /// Nonces are tracked per sender by the client so that transactions do not
+    /// have to wait for the receipt of the previous one to get the next nonce.
+    /// The first nonce of each sender is read from the [`Environment`].
+    pub async fn next_nonce(&self, address: eAddress) -> Result<u64, ArbiterCoreError> {
+        if let Some(nonce) = self.nonces.lock().unwrap().get(&address) {
+            return Ok(*nonce);
+        }
+        // An account that does not exist yet starts at nonce zero.
+        let nonce = match self.get_transaction_count(address, None).await {
+            Ok(nonce) => nonce.as_u64(),
+            Err(ArbiterCoreError::AccountDoesNotExistError) => 0,
+            Err(e) => return Err(e),
+        };
+        Ok(*self.nonces.lock().unwrap().entry(address).or_insert(nonce))
+    }
+
+    /// Forgets the nonce tracked for `address` so that it is read from the
+    /// [`Environment`] again, e.g., after another client sent a transaction
+    /// from it. A mismatched nonce is also read again when a transaction is
+    /// rejected because of it.
+    pub fn reset_nonce(&self, address: eAddress) {
+        self.nonces.lock().unwrap().remove(&address);
+    }
+
+    /// Returns the next nonce of `address` and uses it up.
+    async fn assign_nonce(&self, address: eAddress) -> Result<u64, ArbiterCoreError> {
+        let nonce = self.next_nonce(address).await?;
+        let mut nonces = self.nonces.lock().unwrap();
+        let next = nonces.entry(address).or_insert(nonce);
+        let assigned = *next;
+        *next += 1;
+        Ok(assigned)
+    }
+
+    /// Sends the transaction in `tx_env` to the [`Environment`] and returns
+    /// its [`Outcome`].
+    fn transact(&self, tx_env: TxEnv) -> Result<Outcome, ArbiterCoreError> {
+        let provider = self.provider.as_ref();
+        provider
+            .instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::Transaction {
+                tx_env,
+                outcome_sender: provider.outcome_sender(),
+            })?;
+        provider.receive()
+    }
+
+    /// Returns the account at `address` if it is owned by this client.
+    fn account(&self, address: eAddress) -> Result<EOA, ArbiterCoreError> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|account| account.address() == address)
+            .cloned()
+            .ok_or(ArbiterCoreError::AccountDoesNotExistError)
+    }
+
     /// Allows the user to update the block number and timestamp of the
     /// [`Environment`] to whatever they may choose at any time.
     pub fn update_block(
         &self,
         block_number: impl Into<eU256>,
         block_timestamp: impl Into<eU256>,
+    ) -> Result<ReceiptData, ArbiterCoreError> {
+        self.update_block_with(block_number, block_timestamp, BlockSettings::default())
+    }
+
+    /// Updates the block number and timestamp of the [`Environment`] like
+    /// [`ArbiterMiddleware::update_block`] and applies the coinbase and base
+    /// fee in the `settings` to the new block.
+    ///
+    /// The returned [`ReceiptData`] describes the previous block, including
+    /// the priority fees its coinbase was credited with and the base fees its
+    /// transactions paid.
+    pub fn update_block_with(
+        &self,
+        block_number: impl Into<eU256>,
+        block_timestamp: impl Into<eU256>,
+        settings: BlockSettings,
     ) -> Result<ReceiptData, ArbiterCoreError> {
         let provider = self.provider().as_ref();
         provider
@@ -293,10 +618,11 @@ impl ArbiterMiddleware {
             .send(Instruction::BlockUpdate {
                 block_number: block_number.into(),
                 block_timestamp: block_timestamp.into(),
-                outcome_sender: provider.outcome_sender.clone(),
+                settings,
+                outcome_sender: provider.outcome_sender(),
             })?;
 
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::BlockUpdateCompleted(receipt_data) => Ok(receipt_data),
             _ => unreachable!(),
         }
@@ -311,10 +637,10 @@ impl ArbiterMiddleware {
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
             .send(Instruction::Query {
                 environment_data: EnvironmentData::BlockTimestamp,
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
 
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::QueryReturn(outcome) => {
                 Ok(ethers::types::U256::from_str_radix(outcome.as_ref(), 10)?)
             }
@@ -327,31 +653,333 @@ impl ArbiterMiddleware {
         &self,
         cheatcode: Cheatcodes,
     ) -> Result<CheatcodesReturn, ArbiterCoreError> {
+        self.provider.as_ref().apply_cheatcode(cheatcode)
+    }
+
+    /// Sets the ETH balance of the account at `address`, creating the account
+    /// if it does not exist yet.
+    pub async fn set_balance(
+        &self,
+        address: eAddress,
+        amount: impl Into<ethers::types::U256>,
+    ) -> Result<(), ArbiterCoreError> {
+        self.apply_cheatcode(Cheatcodes::SetBalance {
+            address,
+            amount: amount.into(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Adds `amount` to the ETH balance of the account at `address`. The
+    /// account must already exist.
+    pub async fn add_balance(
+        &self,
+        address: eAddress,
+        amount: impl Into<ethers::types::U256>,
+    ) -> Result<(), ArbiterCoreError> {
+        self.apply_cheatcode(Cheatcodes::Deal {
+            address,
+            amount: amount.into(),
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Sets the nonce of the account at `address`, creating the account if it
+    /// does not exist yet.
+    pub async fn set_nonce(&self, address: eAddress, nonce: u64) -> Result<(), ArbiterCoreError> {
+        self.apply_cheatcode(Cheatcodes::SetNonce { address, nonce })
+            .await?;
+        self.reset_nonce(address);
+        Ok(())
+    }
+
+    /// Returns the [`AccountInfo`] of the account at `address`, i.e., its
+    /// balance, nonce, and code.
+    pub async fn get_account(&self, address: eAddress) -> Result<AccountInfo, ArbiterCoreError> {
+        match self.apply_cheatcode(Cheatcodes::Access { address }).await? {
+            CheatcodesReturn::Access { info, .. } => Ok(info),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the instruction and branch [`Coverage`] collected by the
+    /// [`Environment`] so far. This requires the [`Environment`] to be built
+    /// with coverage enabled.
+    pub async fn coverage(&self) -> Result<Coverage, ArbiterCoreError> {
         let provider = self.provider.as_ref();
         provider
             .instruction_sender
             .upgrade()
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
-            .send(Instruction::Cheatcode {
-                cheatcode,
-                outcome_sender: provider.outcome_sender.clone(),
+            .send(Instruction::Query {
+                environment_data: EnvironmentData::Coverage,
+                outcome_sender: provider.outcome_sender(),
             })?;
 
-        match provider.outcome_receiver.recv()?? {
-            Outcome::CheatcodeReturn(outcome) => Ok(outcome),
+        match provider.receive()? {
+            Outcome::QueryReturn(outcome) => Ok(serde_json::from_str(outcome.as_ref())?),
             _ => unreachable!(),
         }
     }
 
+    /// Returns the [`Profiler`] shared with the [`Environment`] so that the
+    /// time spent in sections of an agent can be recorded alongside the
+    /// execution time of the [`Environment`].
+    pub fn profiler(&self) -> Profiler {
+        self.provider.as_ref().profiler.clone()
+    }
+
+    /// Sets the [`DeliveryPolicy`] of the filters and event streams created by
+    /// this client from now on. Defaults to the policy of the
+    /// [`Environment`].
+    pub fn set_delivery_policy(&self, policy: DeliveryPolicy) {
+        *self.provider.as_ref().delivery_policy.lock().unwrap() = policy;
+    }
+
+    /// Sets the gas limit of the transactions and calls sent by this client
+    /// from now on. A transaction that sets its own gas overrides it and
+    /// `None` lifts the limit. Defaults to the limit of the [`Environment`].
+    pub fn set_transaction_gas_limit(&self, gas_limit: Option<u64>) {
+        *self.provider.as_ref().transaction_gas_limit.lock().unwrap() = gas_limit;
+    }
+
+    /// Returns the gas limit of the transactions and calls sent by this
+    /// client, if there is one.
+    pub fn transaction_gas_limit(&self) -> Option<u64> {
+        *self.provider.as_ref().transaction_gas_limit.lock().unwrap()
+    }
+
+    /// Sets the percentage that the gas estimates of this client are padded
+    /// with from now on, e.g., `20` to estimate 20% more gas than a
+    /// transaction needs. Defaults to the buffer of the [`Environment`].
+    pub fn set_gas_estimate_buffer(&self, percent: u64) {
+        *self.provider.as_ref().gas_estimate_buffer.lock().unwrap() = percent;
+    }
+
+    /// Returns the percentage that the gas estimates of this client are
+    /// padded with.
+    pub fn gas_estimate_buffer(&self) -> u64 {
+        *self.provider.as_ref().gas_estimate_buffer.lock().unwrap()
+    }
+
+    /// Sets how long this client waits for the [`Environment`] to reply to
+    /// each of its transactions, calls, and queries from now on, or `None` to
+    /// wait for as long as it takes, which is the default.
+    ///
+    /// A reply that does not arrive in time fails with
+    /// [`ArbiterCoreError::TimeoutError`] instead of hanging the client, e.g.,
+    /// when the [`Environment`] is paused or stuck on another instruction.
+    /// What timed out may still be done by the [`Environment`] later.
+    pub fn set_response_timeout(&self, timeout: Option<Duration>) {
+        *self.provider.as_ref().response_timeout.lock().unwrap() = timeout;
+    }
+
+    /// Returns how long this client waits for the [`Environment`] to reply, if
+    /// it does not wait for as long as it takes.
+    pub fn response_timeout(&self) -> Option<Duration> {
+        *self.provider.as_ref().response_timeout.lock().unwrap()
+    }
+
+    /// Sets the [`RetryPolicy`] of the transactions sent by this client from
+    /// now on. Defaults to a single retry.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        *self.provider.as_ref().retry_policy.lock().unwrap() = policy;
+    }
+
+    /// Returns the [`RetryPolicy`] of the transactions sent by this client.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        *self.provider.as_ref().retry_policy.lock().unwrap()
+    }
+
+    /// Adds `hooks` that run around every transaction sent by this client
+    /// from now on, after the hooks that were added before. The clients
+    /// returned by [`ArbiterMiddleware::with_sender`] and
+    /// [`ArbiterMiddleware::impersonate`] start out with the hooks of this
+    /// client.
+    pub fn add_hooks(&self, hooks: impl MiddlewareHooks + 'static) {
+        self.provider
+            .as_ref()
+            .hooks
+            .lock()
+            .unwrap()
+            .push(Arc::new(hooks));
+    }
+
+    /// Removes every hook of this client.
+    pub fn clear_hooks(&self) {
+        self.provider.as_ref().hooks.lock().unwrap().clear();
+    }
+
+    /// Returns the hooks of this client, which are cloned so that they can be
+    /// run without holding the lock.
+    fn hooks(&self) -> Vec<Arc<dyn MiddlewareHooks>> {
+        self.provider.as_ref().hooks.lock().unwrap().clone()
+    }
+
+    /// The gas limit used to execute `tx`, i.e., its own gas if it is set, the
+    /// limit of this client otherwise, and no limit at all without either.
+    fn gas_limit(&self, tx: &TypedTransaction) -> u64 {
+        tx.gas()
+            .map(|gas| gas.min(&eU256::from(u64::MAX)).as_u64())
+            .or(self.transaction_gas_limit())
+            .unwrap_or(u64::MAX)
+    }
+
+    /// Returns the total number of events that subscribers of the
+    /// [`Environment`] missed because they fell behind it.
+    pub fn lagged_events(&self) -> u64 {
+        self.provider.as_ref().event_sender.lagged()
+    }
+
     /// Returns the address of the wallet/signer given to a client.
     /// Matches on the [`EOA`] variant of the [`ArbiterMiddleware`] struct.
     pub fn address(&self) -> eAddress {
-        match &self.wallet {
-            EOA::Forked(address) => *address,
-            EOA::Wallet(wallet) => wallet.address(),
+        self.wallet.address()
+    }
+
+    /// Calls a contract method against the state the [`Environment`] had at
+    /// the end of the block `block_number`, e.g., to read what the reserves
+    /// of a pool were a few blocks ago. Making the call at the current block
+    /// is the same as [`Middleware::call`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterCoreError::StateUnavailableError`] unless the
+    /// [`Environment`] keeps the state of the block, which it only does for
+    /// the blocks set with
+    /// [`EnvironmentBuilder::with_state_history`](crate::environment::EnvironmentBuilder::with_state_history).
+    pub async fn call_at(
+        &self,
+        tx: &TypedTransaction,
+        block_number: u64,
+    ) -> Result<eBytes, ArbiterCoreError> {
+        self.call(tx, Some(BlockId::Number(block_number.into())))
+            .await
+    }
+
+    /// Executes `tx` as a call on the current state of the [`Environment`]
+    /// without committing it and returns its [`CallTrace`], i.e., what it
+    /// returned and every call it made along with the logs each of them
+    /// emitted, e.g., to see how much a swap would get out of a pool before
+    /// sending it.
+    ///
+    /// A call that reverts is traced like any other. Its revert data is the
+    /// `output` of the trace and `success` is `false`.
+    ///
+    /// Through an `Arc<ArbiterMiddleware>` this has to be called as
+    /// `client.as_ref().trace_call(tx)`, since [`Middleware::trace_call`] is
+    /// found on the `Arc` first.
+    pub async fn trace_call(&self, tx: &TypedTransaction) -> Result<CallTrace, ArbiterCoreError> {
+        match self.send_call(tx, None, true)?.0 {
+            Outcome::CallTraced(_, trace) => Ok(*trace),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Executes `tx` as a call on the [`Environment`] from its `from` address,
+    /// or the address of this client if it has none, against the state at the
+    /// end of the given `block`, and returns the output and the gas it spent
+    /// before refunds if it succeeded.
+    fn execute_call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<(Output, u64), ArbiterCoreError> {
+        let (outcome, gas_limit) = self.send_call(tx, block, false)?;
+        match outcome {
+            Outcome::CallCompleted(execution_result) => match execution_result {
+                ExecutionResult::Revert { gas_used, output } => {
+                    Err(ArbiterCoreError::ExecutionRevert {
+                        gas_used,
+                        output: output.to_vec(),
+                    })
+                }
+                ExecutionResult::Halt {
+                    reason: HaltReason::OutOfGas(_),
+                    gas_used,
+                } => Err(ArbiterCoreError::OutOfGas {
+                    gas_limit,
+                    gas_used,
+                }),
+                ExecutionResult::Halt { reason, gas_used } => {
+                    Err(ArbiterCoreError::ExecutionHalt { reason, gas_used })
+                }
+                ExecutionResult::Success {
+                    output,
+                    gas_used,
+                    gas_refunded,
+                    ..
+                } => Ok((output, gas_used + gas_refunded)),
+            },
+            _ => unreachable!(),
         }
     }
 
+    /// Sends `tx` as a call to the [`Environment`] from its `from` address, or
+    /// the address of this client if it has none, to be executed against the
+    /// state at the end of the given `block`, and returns its [`Outcome`]
+    /// along with the gas limit it was executed with.
+    fn send_call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+        trace: bool,
+    ) -> Result<(Outcome, u64), ArbiterCoreError> {
+        let block_number = match block {
+            Some(BlockId::Number(BlockNumber::Number(number))) => {
+                Some(eU256::from(number.as_u64()))
+            }
+            Some(BlockId::Number(BlockNumber::Earliest)) => Some(eU256::zero()),
+            Some(BlockId::Hash(_)) => return Err(ArbiterCoreError::InvalidQueryError),
+            _ => None,
+        };
+        // Check the `to` field of the transaction to determine if it is a call or a
+        // deploy. If there is no `to` field, then it is a `Deploy` else it is a
+        // `Call`.
+        let transact_to = match tx.to_addr() {
+            Some(&to) => TransactTo::Call(to.to_fixed_bytes().into()),
+            None => TransactTo::Create(CreateScheme::Create),
+        };
+        let gas_limit = self.gas_limit(tx);
+        let tx_env = TxEnv {
+            caller: tx
+                .from()
+                .copied()
+                .unwrap_or_else(|| self.address())
+                .to_fixed_bytes()
+                .into(),
+            gas_limit,
+            gas_price: U256::ZERO,
+            gas_priority_fee: None,
+            transact_to,
+            value: U256::from_limbs(tx.value().copied().unwrap_or_default().0),
+            data: revm_primitives::Bytes(bytes::Bytes::from(
+                tx.data()
+                    .ok_or(ArbiterCoreError::MissingDataError)?
+                    .to_vec(),
+            )),
+            chain_id: None,
+            nonce: None,
+            access_list: access_list(tx),
+            blob_hashes: Vec::new(),
+            max_fee_per_blob_gas: None,
+        };
+        let provider = self.provider.as_ref();
+        provider
+            .instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::Call {
+                tx_env,
+                block_number,
+                trace,
+                outcome_sender: provider.outcome_sender(),
+            })?;
+        Ok((provider.receive()?, gas_limit))
+    }
+
     /// Allows a client to set a gas price for transactions.
     /// This can only be done if the [`Environment`] has
     /// [`EnvironmentParameters`] `gas_settings` field set to
@@ -367,9 +995,9 @@ impl ArbiterMiddleware {
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
             .send(Instruction::SetGasPrice {
                 gas_price,
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::SetGasPriceCompleted => {
                 debug!("Gas price set");
                 Ok(())
@@ -411,13 +1039,32 @@ impl Middleware for ArbiterMiddleware {
     /// transaction environment used for `revm`-based transactions.
     /// It then sends this transaction for execution and returns the
     /// corresponding pending transaction.
+    ///
+    /// The transaction is sent from its `from` address if it has one, which
+    /// has to be one of the [`ArbiterMiddleware::accounts`] of the client, and
+    /// from the address of the client otherwise. Unless the transaction has a
+    /// nonce, it is assigned the [`ArbiterMiddleware::next_nonce`] of its
+    /// sender.
+    ///
+    /// The pending transaction resolves to the receipt of the transaction once
+    /// it is mined according to the [`MiningMode`] of the [`Environment`].
+    /// With more than one confirmation, it also waits until that many blocks
+    /// are ended by block updates from the block of the transaction on.
     async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
         &self,
         tx: T,
         _block: Option<BlockId>,
     ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
         trace!("Building transaction");
-        let tx: TypedTransaction = tx.into();
+        let mut tx: TypedTransaction = tx.into();
+        let hooks = self.hooks();
+        for hook in &hooks {
+            hook.before_send(&mut tx).await;
+        }
+        let sender = match tx.from() {
+            Some(&from) if from != self.address() => self.account(from)?.address(),
+            _ => self.address(),
+        };
 
         // Check the `to` field of the transaction to determine if it is a call or a
         // deploy. If there is no `to` field, then it is a `Deploy` else it is a
@@ -426,13 +1073,15 @@ impl Middleware for ArbiterMiddleware {
             Some(&to) => TransactTo::Call(to.to_fixed_bytes().into()),
             None => TransactTo::Create(CreateScheme::Create),
         };
-        let tx_env = TxEnv {
-            caller: self.address().to_fixed_bytes().into(),
-            gas_limit: u64::MAX,
-            gas_price: revm::primitives::U256::from_limbs(self.get_gas_price().await?.0),
-            gas_priority_fee: None,
+        let gas_limit = self.gas_limit(&tx);
+        let (gas_price, gas_priority_fee) = fees(&tx, self.get_gas_price().await?.to_alloy());
+        let mut tx_env = TxEnv {
+            caller: sender.to_fixed_bytes().into(),
+            gas_limit,
+            gas_price,
+            gas_priority_fee,
             transact_to,
-            value: U256::ZERO,
+            value: U256::from_limbs(tx.value().copied().unwrap_or_default().0),
             data: revm_primitives::Bytes(bytes::Bytes::from(
                 tx.data()
                     .ok_or(ArbiterCoreError::MissingDataError)?
@@ -440,23 +1089,50 @@ impl Middleware for ArbiterMiddleware {
             )),
             chain_id: None,
             nonce: None,
-            access_list: Vec::new(),
+            access_list: access_list(&tx),
             blob_hashes: Vec::new(),
             max_fee_per_blob_gas: None,
         };
-        let instruction = Instruction::Transaction {
-            tx_env: tx_env.clone(),
-            outcome_sender: self.provider.as_ref().outcome_sender.clone(),
+        let assigned = tx.nonce().is_none();
+        tx_env.nonce = Some(match tx.nonce() {
+            Some(nonce) => nonce.as_u64(),
+            None => self.assign_nonce(sender).await?,
+        });
+
+        let retry_policy = self.retry_policy();
+        let mut retries = 0;
+        let outcome = loop {
+            match self.transact(tx_env.clone()) {
+                Err(ArbiterCoreError::EVMError(EVMError::Transaction(
+                    InvalidTransaction::NonceTooHigh { .. }
+                    | InvalidTransaction::NonceTooLow { .. },
+                ))) if assigned && retries < retry_policy.max_retries => {
+                    // The nonce of the sender was changed behind the back of this client, e.g.,
+                    // by a cheatcode or another client of the same account, so it is read again.
+                    retries += 1;
+                    debug!(
+                        "Retrying transaction with a new nonce ({}/{})",
+                        retries, retry_policy.max_retries
+                    );
+                    if !retry_policy.backoff.is_zero() {
+                        Delay::new(retry_policy.backoff).await;
+                    }
+                    self.reset_nonce(sender);
+                    tx_env.nonce = Some(self.assign_nonce(sender).await?);
+                }
+                outcome => break outcome,
+            }
+        };
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                // A transaction that was not executed did not use up its nonce.
+                if assigned {
+                    self.reset_nonce(sender);
+                }
+                return Err(e);
+            }
         };
-
-        let provider = self.provider.as_ref();
-        provider
-            .instruction_sender
-            .upgrade()
-            .ok_or(ArbiterCoreError::UpgradeSenderError)?
-            .send(instruction)?;
-
-        let outcome = provider.outcome_receiver.recv()??;
 
         if let Outcome::TransactionCompleted(execution_result, receipt_data) = outcome {
             match execution_result {
@@ -466,131 +1142,59 @@ impl Middleware for ArbiterMiddleware {
                         output: output.to_vec(),
                     });
                 }
+                ExecutionResult::Halt {
+                    reason: HaltReason::OutOfGas(_),
+                    gas_used,
+                } => {
+                    return Err(ArbiterCoreError::OutOfGas {
+                        gas_limit,
+                        gas_used,
+                    });
+                }
                 ExecutionResult::Halt { reason, gas_used } => {
                     return Err(ArbiterCoreError::ExecutionHalt { reason, gas_used });
                 }
-                ExecutionResult::Success {
-                    output,
-                    gas_used,
-                    logs,
-                    ..
-                } => {
-                    // TODO: This is why we need the signer middleware
-                    // Note that this is technically not the correct construction on the tx hash
-                    // but until we increment the nonce correctly this will do
-                    let sender = self.address();
-
-                    let logs = revm_logs_to_ethers_logs(logs, &receipt_data);
+                ExecutionResult::Success { .. } => {
                     let to: Option<eAddress> = match tx_env.transact_to {
                         TransactTo::Call(address) => Some(address.into_array().into()),
                         TransactTo::Create(_) => None,
                     };
+                    let logs =
+                        revm_logs_to_ethers_logs(execution_result.logs().to_vec(), &receipt_data);
+                    let tx_receipt =
+                        transaction_receipt(sender, to, &execution_result, logs, &receipt_data)?;
+                    debug!(
+                        "Sent transaction {:?} from {:?} to {:?} using {} gas",
+                        receipt_data.transaction_hash,
+                        sender,
+                        to,
+                        execution_result.gas_used()
+                    );
+                    for hook in &hooks {
+                        hook.after_receipt(&tx, &tx_receipt).await;
+                    }
+
+                    let mut pending_tx =
+                        PendingTransaction::new(receipt_data.transaction_hash, self.provider())
+                            .interval(Duration::ZERO);
+
+                    // The transaction is known to the `Environment`, so the pending
+                    // transaction starts with its receipt if it has been mined already, and
+                    // waits for its block to be mined otherwise. Further confirmations are
+                    // awaited by polling the block number.
+                    let state = match self.provider.as_ref().mining_mode {
+                        MiningMode::Instant => PendingTxState::CheckingReceipt(Some(tx_receipt)),
+                        MiningMode::OnBlockUpdate => PendingTxState::PausedGettingReceipt,
+                    };
+                    let state_ptr: *mut PendingTxState =
+                        &mut pending_tx as *mut _ as *mut PendingTxState;
 
-                    match output {
-                        Output::Create(_, address) => {
-                            let tx_receipt = TransactionReceipt {
-                                block_hash: None,
-                                block_number: Some(receipt_data.block_number),
-                                contract_address: Some(recast_address(address.unwrap())),
-                                logs: logs.clone(),
-                                from: sender,
-                                gas_used: Some(gas_used.into()),
-                                effective_gas_price: Some(
-                                    tx_env.clone().gas_price.to_be_bytes().into(),
-                                ),
-                                transaction_hash: H256::default(),
-                                to,
-                                cumulative_gas_used: receipt_data.cumulative_gas_per_block,
-                                status: Some(1.into()),
-                                root: None,
-                                logs_bloom: {
-                                    let mut bloom = Bloom::default();
-                                    for log in &logs {
-                                        bloom.accrue(BloomInput::Raw(&log.address.0));
-                                        for topic in log.topics.iter() {
-                                            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
-                                        }
-                                    }
-                                    bloom
-                                },
-                                transaction_type: match tx {
-                                    TypedTransaction::Eip2930(_) => Some(1.into()),
-                                    _ => None,
-                                },
-                                transaction_index: receipt_data.transaction_index,
-                                ..Default::default()
-                            };
-
-                            // TODO: I'm not sure we need to set the confirmations.
-                            let mut pending_tx = PendingTransaction::new(
-                                ethers::types::H256::zero(),
-                                self.provider(),
-                            )
-                            .interval(Duration::ZERO)
-                            .confirmations(0);
-
-                            let state_ptr: *mut PendingTxState =
-                                &mut pending_tx as *mut _ as *mut PendingTxState;
-
-                            // Modify the value (this assumes you have access to the enum variants)
-                            unsafe {
-                                *state_ptr = PendingTxState::CheckingReceipt(Some(tx_receipt));
-                            }
-
-                            Ok(pending_tx)
-                        }
-                        Output::Call(_) => {
-                            let tx_receipt = TransactionReceipt {
-                                block_hash: None,
-                                block_number: Some(receipt_data.block_number),
-                                contract_address: None,
-                                logs: logs.clone(),
-                                from: sender,
-                                gas_used: Some(gas_used.into()),
-                                effective_gas_price: Some(
-                                    tx_env.clone().gas_price.to_be_bytes().into(),
-                                ),
-                                transaction_hash: H256::default(),
-                                to,
-                                cumulative_gas_used: receipt_data.cumulative_gas_per_block,
-                                status: Some(1.into()),
-                                root: None,
-                                logs_bloom: {
-                                    let mut bloom = Bloom::default();
-                                    for log in &logs {
-                                        bloom.accrue(BloomInput::Raw(&log.address.0));
-                                        for topic in log.topics.iter() {
-                                            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
-                                        }
-                                    }
-                                    bloom
-                                },
-                                transaction_type: match tx {
-                                    TypedTransaction::Eip2930(_) => Some(1.into()),
-                                    _ => None,
-                                },
-                                transaction_index: receipt_data.transaction_index,
-                                ..Default::default()
-                            };
-
-                            let mut pending_tx = PendingTransaction::new(
-                                ethers::types::H256::zero(),
-                                self.provider(),
-                            )
-                            .interval(Duration::ZERO)
-                            .confirmations(0);
-
-                            let state_ptr: *mut PendingTxState =
-                                &mut pending_tx as *mut _ as *mut PendingTxState;
-
-                            // Modify the value (this assumes you have access to the enum variants)
-                            unsafe {
-                                *state_ptr = PendingTxState::CheckingReceipt(Some(tx_receipt));
-                            }
-
-                            Ok(pending_tx)
-                        }
+                    // Modify the value (this assumes you have access to the enum variants)
+                    unsafe {
+                        *state_ptr = state;
                     }
+
+                    Ok(pending_tx)
                 }
             }
         } else {
@@ -606,74 +1210,48 @@ impl Middleware for ArbiterMiddleware {
     /// targeting an existing contract or deploying a new one. After
     /// executing the call, it returns the output, but no worldstate change will
     /// be documented in the `revm` DB.
+    ///
+    /// The call is made against the state at the end of `block` if it is a
+    /// past block number, like [`ArbiterMiddleware::call_at`], and against the
+    /// current state otherwise.
     async fn call(
         &self,
         tx: &TypedTransaction,
-        _block: Option<BlockId>,
+        block: Option<BlockId>,
     ) -> Result<eBytes, Self::Error> {
         trace!("Building call");
-        let tx = tx.clone();
-
-        // Check the `to` field of the transaction to determine if it is a call or a
-        // deploy. If there is no `to` field, then it is a `Deploy` else it is a
-        // `Call`.
-        let transact_to = match tx.to_addr() {
-            Some(&to) => TransactTo::Call(to.to_fixed_bytes().into()),
-            None => TransactTo::Create(CreateScheme::Create),
-        };
-        let tx_env = TxEnv {
-            caller: self.address().to_fixed_bytes().into(),
-            gas_limit: u64::MAX,
-            gas_price: U256::ZERO,
-            gas_priority_fee: None,
-            transact_to,
-            value: U256::ZERO,
-            data: revm_primitives::Bytes(bytes::Bytes::from(
-                tx.data()
-                    .ok_or(ArbiterCoreError::MissingDataError)?
-                    .to_vec(),
-            )),
-            chain_id: None,
-            nonce: None,
-            access_list: Vec::new(),
-            blob_hashes: Vec::new(),
-            max_fee_per_blob_gas: None,
-        };
-        let instruction = Instruction::Call {
-            tx_env,
-            outcome_sender: self.provider().as_ref().outcome_sender.clone(),
-        };
-        self.provider()
-            .as_ref()
-            .instruction_sender
-            .upgrade()
-            .ok_or(ArbiterCoreError::UpgradeSenderError)?
-            .send(instruction)?;
-
-        let outcome = self.provider().as_ref().outcome_receiver.recv()??;
+        let (output, _) = self.execute_call(tx, block)?;
+        Ok(eBytes::from(output.data().to_vec()))
+    }
 
-        if let Outcome::CallCompleted(execution_result) = outcome {
-            match execution_result {
-                ExecutionResult::Revert { gas_used, output } => {
-                    return Err(ArbiterCoreError::ExecutionRevert {
-                        gas_used,
-                        output: output.to_vec(),
-                    });
-                }
-                ExecutionResult::Halt { reason, gas_used } => {
-                    return Err(ArbiterCoreError::ExecutionHalt { reason, gas_used });
-                }
-                ExecutionResult::Success { output, .. } => {
-                    return Ok(eBytes::from(output.data().to_vec()));
-                }
-            }
-        } else {
-            unreachable!()
-        }
+    /// Estimates the gas a transaction needs by executing it as a call on the
+    /// current state of the [`Environment`], i.e., without committing it.
+    ///
+    /// A transaction needs the gas it spends before its refunds are paid out,
+    /// which is padded by the
+    /// [`gas_estimate_buffer`](ArbiterMiddleware::gas_estimate_buffer) of
+    /// this client.
+    ///
+    /// Returns the error of the transaction if it would revert or halt.
+    async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<eU256, Self::Error> {
+        trace!("Estimating gas");
+        let (_, gas_spent) = self.execute_call(tx, block)?;
+        let buffer = gas_spent as u128 * self.gas_estimate_buffer() as u128 / 100;
+        Ok(eU256::from(gas_spent) + eU256::from(buffer))
     }
 
-    /// Creates a new filter for incoming Ethereum logs based on certain
-    /// criteria.
+    /// Installs a filter for the logs matching `filter` whose changes are
+    /// polled with [`Middleware::get_filter_changes`], which returns the logs
+    /// emitted since the filter was installed or last polled, and removed with
+    /// [`Middleware::uninstall_filter`].
+    ///
+    /// The filter keeps a cursor into the logs stored by the [`Environment`]
+    /// instead of receiving its events, so a behavior that polls in a loop
+    /// never misses a log however rarely it polls.
     ///
     /// Currently, this method supports log filters. Other filters like
     /// `NewBlocks` and `PendingTransactions` are not yet implemented.
@@ -690,23 +1268,7 @@ impl Middleware for ArbiterMiddleware {
             }
             FilterKind::Logs(filter) => ("eth_newFilter", filter),
         };
-        let filter = args.clone();
-        let mut hasher = Sha256::new();
-        hasher.update(serde_json::to_string(&args)?);
-        let hash = hasher.finalize();
-        let id = ethers::types::U256::from(ethers::types::H256::from_slice(&hash).as_bytes());
-        let event_receiver = provider.event_sender.subscribe();
-        let filter_receiver = FilterReceiver {
-            filter,
-            receiver: Some(event_receiver),
-        };
-        provider
-            .filter_receivers
-            .lock()
-            .unwrap()
-            .insert(id, filter_receiver);
-        debug!("Filter created with ID: {:?}", id);
-        Ok(id)
+        provider.install_filter(args.clone())
     }
 
     async fn get_logs(&self, filter: &Filter) -> Result<Vec<eLog>, Self::Error> {
@@ -719,9 +1281,9 @@ impl Middleware for ArbiterMiddleware {
                 environment_data: EnvironmentData::Logs {
                     filter: filter.clone(),
                 },
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
-        let outcome = provider.outcome_receiver.recv()??;
+        let outcome = provider.receive()?;
         match outcome {
             Outcome::QueryReturn(outcome) => {
                 let logs: Vec<eLog> = serde_json::from_str(outcome.as_ref())?;
@@ -739,7 +1301,9 @@ impl Middleware for ArbiterMiddleware {
         &'b self,
         filter: &Filter,
     ) -> Result<FilterWatcher<'b, Self::Provider, eLog>, Self::Error> {
-        let id = self.new_filter(FilterKind::Logs(filter)).await?;
+        // The watcher receives the events of the environment as they are
+        // broadcast, since it is polled continuously.
+        let id = self.provider.as_ref().new_filter(filter.clone());
         Ok(FilterWatcher::new(id, self.provider()).interval(Duration::ZERO))
     }
 
@@ -751,10 +1315,10 @@ impl Middleware for ArbiterMiddleware {
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
             .send(Instruction::Query {
                 environment_data: EnvironmentData::GasPrice,
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
 
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::QueryReturn(outcome) => {
                 Ok(ethers::types::U256::from_str_radix(outcome.as_ref(), 10)?)
             }
@@ -762,6 +1326,13 @@ impl Middleware for ArbiterMiddleware {
         }
     }
 
+    /// Returns the chain ID of the [`Environment`], which is what the
+    /// `CHAINID` opcode returns to contracts.
+    async fn get_chainid(&self) -> Result<eU256, Self::Error> {
+        let chain_id = self.provider.as_ref().query(EnvironmentData::ChainId)?;
+        Ok(eU256::from_str_radix(&chain_id, 10)?)
+    }
+
     async fn get_block_number(&self) -> Result<U64, Self::Error> {
         let provider = self.provider().as_ref();
         provider
@@ -770,9 +1341,9 @@ impl Middleware for ArbiterMiddleware {
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
             .send(Instruction::Query {
                 environment_data: EnvironmentData::BlockNumber,
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::QueryReturn(outcome) => {
                 Ok(ethers::types::U64::from_str_radix(outcome.as_ref(), 10)?)
             }
@@ -780,6 +1351,72 @@ impl Middleware for ArbiterMiddleware {
         }
     }
 
+    /// Returns the block with the hashes of its transactions. Blocks can only
+    /// be looked up by number since the [`Environment`] does not produce block
+    /// hashes, and past blocks are only known once a block update ended them.
+    async fn get_block<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<H256>>, Self::Error> {
+        let block_number = match block_hash_or_number.into() {
+            BlockId::Number(block_number) => block_number,
+            BlockId::Hash(_) => return Err(ArbiterCoreError::InvalidQueryError),
+        };
+        let provider = self.provider.as_ref();
+        provider
+            .instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::Query {
+                environment_data: EnvironmentData::Block(block_number),
+                outcome_sender: provider.outcome_sender(),
+            })?;
+        match provider.receive()? {
+            Outcome::QueryReturn(outcome) => Ok(serde_json::from_str(outcome.as_ref())?),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the base fees, gas used ratios, and priority fees paid at the
+    /// `reward_percentiles` of the gas used in the `block_count` blocks up to
+    /// `last_block`, which is the current block unless given by its number.
+    /// Since the [`Environment`] only knows past blocks once a block update
+    /// ended them, the history skips the block numbers that were jumped over.
+    async fn fee_history<T: Into<eU256> + serde::Serialize + Send + Sync>(
+        &self,
+        block_count: T,
+        last_block: BlockNumber,
+        reward_percentiles: &[f64],
+    ) -> Result<FeeHistory, Self::Error> {
+        let fee_history = self.provider.as_ref().query(EnvironmentData::FeeHistory {
+            block_count: block_count.into().low_u64(),
+            newest_block: last_block,
+            reward_percentiles: reward_percentiles.to_vec(),
+        })?;
+        Ok(serde_json::from_str(&fee_history)?)
+    }
+
+    /// Returns the receipt of a transaction that was executed by the
+    /// [`Environment`], including transactions that reverted.
+    async fn get_transaction_receipt<T: Send + Sync + Into<H256>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<TransactionReceipt>, Self::Error> {
+        let provider = self.provider.as_ref();
+        provider
+            .instruction_sender
+            .upgrade()
+            .ok_or(ArbiterCoreError::UpgradeSenderError)?
+            .send(Instruction::Query {
+                environment_data: EnvironmentData::TransactionReceipt(transaction_hash.into()),
+                outcome_sender: provider.outcome_sender(),
+            })?;
+        match provider.receive()? {
+            Outcome::QueryReturn(outcome) => Ok(serde_json::from_str(outcome.as_ref())?),
+            _ => unreachable!(),
+        }
+    }
+
     async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
         &self,
         from: T,
@@ -801,10 +1438,10 @@ impl Middleware for ArbiterMiddleware {
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
             .send(Instruction::Query {
                 environment_data: EnvironmentData::Balance(ethers::types::Address::from(address)),
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
 
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::QueryReturn(outcome) => {
                 Ok(ethers::types::U256::from_str_radix(outcome.as_ref(), 10)?)
             }
@@ -830,10 +1467,10 @@ impl Middleware for ArbiterMiddleware {
             .ok_or(ArbiterCoreError::UpgradeSenderError)?
             .send(Instruction::Query {
                 environment_data: EnvironmentData::TransactionCount(address),
-                outcome_sender: provider.outcome_sender.clone(),
+                outcome_sender: provider.outcome_sender(),
             })?;
 
-        match provider.outcome_receiver.recv()?? {
+        match provider.receive()? {
             Outcome::QueryReturn(outcome) => {
                 Ok(ethers::types::U256::from_str_radix(outcome.as_ref(), 10)?)
             }
@@ -867,6 +1504,21 @@ impl Middleware for ArbiterMiddleware {
 
         Ok(())
     }
+
+    /// Signs `data` as an [EIP-191](https://eips.ethereum.org/EIPS/eip-191)
+    /// personal message, like `eth_sign`, with the account at `from`, which
+    /// has to be owned by this client.
+    async fn sign<T: Into<eBytes> + Send + Sync>(
+        &self,
+        data: T,
+        from: &eAddress,
+    ) -> Result<Signature, Self::Error> {
+        match self.account(*from)? {
+            EOA::Forked(_) => Err(ArbiterCoreError::ForkedEOASignError),
+            EOA::Wallet(wallet) => Ok(wallet.sign_message(data.into()).await?),
+        }
+    }
+
     /// Fetches the value stored at the storage slot `key` for an account at
     /// `address`. todo: implement the storage at a specific block feature.
     async fn get_storage_at<T: Into<NameOrAddress> + Send + Sync>(
@@ -915,14 +1567,14 @@ impl Middleware for ArbiterMiddleware {
 
     async fn subscribe<T, R>(
         &self,
-        _params: T,
+        params: T,
     ) -> Result<SubscriptionStream<'_, Self::Provider, R>, Self::Error>
     where
         T: Debug + Serialize + Send + Sync,
         R: DeserializeOwned + Send + Sync,
         <Self as Middleware>::Provider: PubsubClient,
     {
-        todo!("This is not implemented yet, but `subscribe_logs` is.")
+        Ok(self.provider().subscribe(params).await?)
     }
 }
 
@@ -967,6 +1619,45 @@ pub enum PendingTxState<'a> {
     Completed,
 }
 
+/// Returns the gas price and the priority fee that `tx` is executed with,
+/// mapped from the fields of its envelope. Fees that are not set default to
+/// `gas_price`, the gas price of the [`Environment`].
+///
+/// The priority fee of an EIP-1559 transaction defaults to its maximum fee,
+/// so that a transaction without any fees pays the gas price of the
+/// [`Environment`] in full, like a legacy transaction does.
+fn fees(tx: &TypedTransaction, gas_price: U256) -> (U256, Option<U256>) {
+    match tx {
+        TypedTransaction::Legacy(tx) => (tx.gas_price.to_alloy().unwrap_or(gas_price), None),
+        TypedTransaction::Eip2930(tx) => (tx.tx.gas_price.to_alloy().unwrap_or(gas_price), None),
+        TypedTransaction::Eip1559(tx) => {
+            let max_fee = tx.max_fee_per_gas.to_alloy().unwrap_or(gas_price);
+            let priority_fee = tx.max_priority_fee_per_gas.to_alloy().unwrap_or(max_fee);
+            (max_fee, Some(priority_fee))
+        }
+    }
+}
+
+/// Returns the access list of `tx` as it is given to revm.
+fn access_list(tx: &TypedTransaction) -> Vec<(Address, Vec<U256>)> {
+    tx.access_list()
+        .map(|access_list| {
+            access_list
+                .0
+                .iter()
+                .map(|item| {
+                    let keys = item
+                        .storage_keys
+                        .iter()
+                        .map(|key| U256::from_be_bytes(key.0))
+                        .collect();
+                    (item.address.to_alloy(), keys)
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
 // Certainly will go away with alloy-types
 /// Recast a B160 into an Address type
 /// # Arguments
@@ -0,0 +1,100 @@
+//! The [`multicall`] module holds the Multicall3 contract that every
+//! [`Environment`] has deployed at its canonical address,
+//! [`MULTICALL_ADDRESS`], so that a behavior can batch many view calls into a
+//! single call with [`ArbiterMiddleware::multicall`].
+//!
+//! The contract is a minimal implementation of the interface of
+//! [Multicall3](https://github.com/mds1/multicall) that supports `aggregate3`
+//! along with the getters of the block, e.g., `getEthBalance` and
+//! `getBlockNumber`, which are everything an [`ethers::contract::Multicall`]
+//! uses unless its calls send value. A call that is not allowed to fail reverts
+//! the whole batch without any return data.
+//!
+//! An [`Environment`] that already has code at [`MULTICALL_ADDRESS`], e.g., one
+//! forked from a chain that has Multicall3 deployed, keeps that code instead.
+//!
+//! [`Environment`]: crate::environment::Environment
+//! [`ArbiterMiddleware::multicall`]: crate::middleware::ArbiterMiddleware::multicall
+
+pub use ethers::contract::MULTICALL_ADDRESS;
+use revm::{interpreter::opcode::*, primitives::Bytecode, DatabaseRef};
+
+use super::*;
+
+/// The runtime bytecode of the Multicall3 contract.
+#[rustfmt::skip]
+pub const MULTICALL_CODE: &[u8] = &[
+    PUSH1, 0x00, CALLDATALOAD, PUSH1, 0xe0, SHR, // [selector]
+    DUP1, PUSH4, 0x82, 0xad, 0x56, 0xcb, EQ, PUSH2, 0x00, 0xd2, JUMPI, // aggregate3((address,bool,bytes)[]); jump to AGGREGATE3
+    DUP1, PUSH4, 0x3e, 0x64, 0xa6, 0x96, EQ, PUSH2, 0x00, 0x8c, JUMPI, // getBasefee(); jump to BASEFEE
+    DUP1, PUSH4, 0xee, 0x82, 0xac, 0x5e, EQ, PUSH2, 0x00, 0x92, JUMPI, // getBlockHash(uint256); jump to BLOCK_HASH
+    DUP1, PUSH4, 0x42, 0xcb, 0xb1, 0x5c, EQ, PUSH2, 0x00, 0x9b, JUMPI, // getBlockNumber(); jump to NUMBER
+    DUP1, PUSH4, 0x34, 0x08, 0xe4, 0x70, EQ, PUSH2, 0x00, 0xa1, JUMPI, // getChainId(); jump to CHAINID
+    DUP1, PUSH4, 0xa8, 0xb0, 0x57, 0x4e, EQ, PUSH2, 0x00, 0xa7, JUMPI, // getCurrentBlockCoinbase(); jump to COINBASE
+    DUP1, PUSH4, 0x72, 0x42, 0x5d, 0x9d, EQ, PUSH2, 0x00, 0xad, JUMPI, // getCurrentBlockDifficulty(); jump to DIFFICULTY
+    DUP1, PUSH4, 0x86, 0xd5, 0x16, 0xe8, EQ, PUSH2, 0x00, 0xb3, JUMPI, // getCurrentBlockGasLimit(); jump to GASLIMIT
+    DUP1, PUSH4, 0x0f, 0x28, 0xc9, 0x7d, EQ, PUSH2, 0x00, 0xb9, JUMPI, // getCurrentBlockTimestamp(); jump to TIMESTAMP
+    DUP1, PUSH4, 0x4d, 0x23, 0x01, 0xcc, EQ, PUSH2, 0x00, 0xbf, JUMPI, // getEthBalance(address); jump to ETH_BALANCE
+    DUP1, PUSH4, 0x27, 0xe8, 0x6d, 0x6e, EQ, PUSH2, 0x00, 0xc8, JUMPI, // getLastBlockHash(); jump to LAST_BLOCK_HASH
+    PUSH1, 0x00, DUP1, REVERT, // unknown function
+    // RETURN_WORD
+    JUMPDEST, PUSH1, 0x00, MSTORE, PUSH1, 0x20, PUSH1, 0x00, RETURN, // return the word on top of the stack
+    // BASEFEE
+    JUMPDEST, BASEFEE, PUSH2, 0x00, 0x83, JUMP, // getBasefee(); jump to RETURN_WORD
+    // BLOCK_HASH
+    JUMPDEST, PUSH1, 0x04, CALLDATALOAD, BLOCKHASH, PUSH2, 0x00, 0x83, JUMP, // getBlockHash(uint256); jump to RETURN_WORD
+    // NUMBER
+    JUMPDEST, NUMBER, PUSH2, 0x00, 0x83, JUMP, // getBlockNumber(); jump to RETURN_WORD
+    // CHAINID
+    JUMPDEST, CHAINID, PUSH2, 0x00, 0x83, JUMP, // getChainId(); jump to RETURN_WORD
+    // COINBASE
+    JUMPDEST, COINBASE, PUSH2, 0x00, 0x83, JUMP, // getCurrentBlockCoinbase(); jump to RETURN_WORD
+    // DIFFICULTY
+    JUMPDEST, DIFFICULTY, PUSH2, 0x00, 0x83, JUMP, // getCurrentBlockDifficulty(); jump to RETURN_WORD
+    // GASLIMIT
+    JUMPDEST, GASLIMIT, PUSH2, 0x00, 0x83, JUMP, // getCurrentBlockGasLimit(); jump to RETURN_WORD
+    // TIMESTAMP
+    JUMPDEST, TIMESTAMP, PUSH2, 0x00, 0x83, JUMP, // getCurrentBlockTimestamp(); jump to RETURN_WORD
+    // ETH_BALANCE
+    JUMPDEST, PUSH1, 0x04, CALLDATALOAD, BALANCE, PUSH2, 0x00, 0x83, JUMP, // getEthBalance(address); jump to RETURN_WORD
+    // LAST_BLOCK_HASH
+    JUMPDEST, PUSH1, 0x01, NUMBER, SUB, BLOCKHASH, PUSH2, 0x00, 0x83, JUMP, // getLastBlockHash(); jump to RETURN_WORD
+    // AGGREGATE3
+    JUMPDEST, POP, PUSH1, 0x04, CALLDATALOAD, PUSH1, 0x04, ADD, // [base] of the calls
+    DUP1, CALLDATALOAD, SWAP1, PUSH1, 0x20, ADD, // [n, E] the number of calls and the start of their offsets
+    PUSH1, 0x20, PUSH1, 0x00, MSTORE, DUP2, PUSH1, 0x20, MSTORE, // the results are an array of n elements
+    DUP2, PUSH1, 0x05, SHL, PUSH1, 0x40, ADD, PUSH1, 0x00, // [n, E, P, i] P is where the next result is written
+    // LOOP
+    JUMPDEST, DUP4, DUP2, LT, ISZERO, PUSH2, 0x01, 0x7a, JUMPI, // jump to DONE
+    PUSH1, 0x40, DUP3, SUB, DUP2, PUSH1, 0x05, SHL, PUSH1, 0x40, ADD, MSTORE, // offset of the i-th result
+    DUP1, PUSH1, 0x05, SHL, DUP4, ADD, CALLDATALOAD, DUP4, ADD, // [n, E, P, i, T] the i-th call
+    DUP1, PUSH1, 0x40, ADD, CALLDATALOAD, DUP2, ADD, DUP1, CALLDATALOAD, // [n, E, P, i, T, D, len] its calldata
+    SWAP1, PUSH1, 0x20, ADD, DUP2, SWAP1, DUP6, PUSH1, 0x60, ADD, CALLDATACOPY, // [n, E, P, i, T, len] copied to P + 0x60
+    PUSH1, 0x00, PUSH1, 0x00, DUP3, DUP7, PUSH1, 0x60, ADD, PUSH1, 0x00, DUP7, CALLDATALOAD, GAS, CALL, // [n, E, P, i, T, len, success]
+    SWAP1, POP, DUP1, ISZERO, DUP3, PUSH1, 0x20, ADD, CALLDATALOAD, ISZERO, AND, PUSH2, 0x01, 0x82, JUMPI, // revert unless the call may fail; jump to FAIL
+    SWAP1, POP, DUP3, MSTORE, // [n, E, P, i] success
+    PUSH1, 0x40, DUP3, PUSH1, 0x20, ADD, MSTORE, // offset of the return data
+    RETURNDATASIZE, DUP3, PUSH1, 0x40, ADD, MSTORE, // length of the return data
+    RETURNDATASIZE, PUSH1, 0x00, DUP4, PUSH1, 0x60, ADD, RETURNDATACOPY, // the return data
+    PUSH1, 0x00, RETURNDATASIZE, DUP4, PUSH1, 0x60, ADD, ADD, MSTORE, // zero the padding, which may still hold calldata
+    RETURNDATASIZE, PUSH1, 0x1f, ADD, PUSH1, 0x1f, NOT, AND, PUSH1, 0x60, ADD, // size of the result
+    DUP3, ADD, SWAP2, POP, PUSH1, 0x01, ADD, PUSH2, 0x00, 0xf2, JUMP, // [n, E, P, i + 1]; jump to LOOP
+    // DONE
+    JUMPDEST, POP, SWAP2, POP, POP, PUSH1, 0x00, RETURN, // return everything up to P
+    // FAIL
+    JUMPDEST, PUSH1, 0x00, DUP1, REVERT,
+];
+
+/// Deploys the Multicall3 contract at [`MULTICALL_ADDRESS`] in `db` unless
+/// there already is code at that address.
+pub(crate) fn deploy(db: &ArbiterDB) {
+    let address = Address::from(MULTICALL_ADDRESS.to_fixed_bytes());
+    let mut info = db.basic_ref(address).unwrap().unwrap_or_default();
+    if !info.is_empty_code_hash() {
+        return;
+    }
+    let code = Bytecode::new_raw(Bytes::from_static(MULTICALL_CODE));
+    info.code_hash = code.hash_slow();
+    info.code = Some(code);
+    db.state.write().unwrap().insert_account_info(address, info);
+}
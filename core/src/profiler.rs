@@ -0,0 +1,184 @@
+//! The [`profiler`] module attributes the wall-clock time of a simulation to
+//! the sections that spent it, e.g., the [`Environment`] executing
+//! instructions or an agent processing events.
+//!
+//! A [`Profiler`] is a cheap to clone handle that is shared by an
+//! [`Environment`] and every client connected to it. Recording is a no-op
+//! until the profiler is enabled, either through
+//! [`EnvironmentBuilder::with_profiling`] or [`Profiler::enable`].
+//!
+//! [`Environment`]: crate::environment::Environment
+//! [`EnvironmentBuilder::with_profiling`]: crate::environment::EnvironmentBuilder::with_profiling
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use super::*;
+
+/// A shared handle used to record how long each section of a simulation took.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    inner: Arc<ProfilerInner>,
+}
+
+#[derive(Debug, Default)]
+struct ProfilerInner {
+    enabled: AtomicBool,
+    started: Mutex<Option<Instant>>,
+    timings: Mutex<BTreeMap<String, Timing>>,
+}
+
+/// The time spent in a single section.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timing {
+    /// The number of times the section was entered.
+    pub count: u64,
+
+    /// The total time spent in the section.
+    pub total: Duration,
+}
+
+/// A snapshot of the time recorded by a [`Profiler`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    /// The wall-clock time since the profiler was enabled.
+    pub elapsed: Duration,
+
+    /// The time spent in each section keyed by its name.
+    pub timings: BTreeMap<String, Timing>,
+}
+
+/// Records the time from its creation until it is dropped into a [`Profiler`].
+#[derive(Debug)]
+pub struct Timer<'a> {
+    profiler: &'a Profiler,
+    section: &'a str,
+    started: Instant,
+}
+
+impl Profiler {
+    /// Starts recording. The wall-clock time of the [`Profile`] is measured
+    /// from the first time this is called.
+    pub fn enable(&self) {
+        self.inner
+            .started
+            .lock()
+            .unwrap()
+            .get_or_insert_with(Instant::now);
+        self.inner.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the profiler is recording.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Adds `elapsed` to the time spent in `section`.
+    pub fn record(&self, section: &str, elapsed: Duration) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut timings = self.inner.timings.lock().unwrap();
+        let timing = match timings.get_mut(section) {
+            Some(timing) => timing,
+            None => timings.entry(section.to_owned()).or_default(),
+        };
+        timing.count += 1;
+        timing.total += elapsed;
+    }
+
+    /// Returns a [`Timer`] that records into `section` when it is dropped, or
+    /// `None` if the profiler is not recording.
+    pub fn timer<'a>(&'a self, section: &'a str) -> Option<Timer<'a>> {
+        self.is_enabled().then(|| Timer {
+            profiler: self,
+            section,
+            started: Instant::now(),
+        })
+    }
+
+    /// Returns everything that has been recorded so far.
+    pub fn profile(&self) -> Profile {
+        Profile {
+            elapsed: self
+                .inner
+                .started
+                .lock()
+                .unwrap()
+                .map(|started| started.elapsed())
+                .unwrap_or_default(),
+            timings: self.inner.timings.lock().unwrap().clone(),
+        }
+    }
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        self.profiler.record(self.section, self.started.elapsed());
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Profile of {:.2?} of wall-clock time:", self.elapsed)?;
+        writeln!(
+            f,
+            "{:<48} {:>10} {:>12} {:>12} {:>8}",
+            "section", "calls", "total", "mean", "share"
+        )?;
+        let mut timings: Vec<_> = self.timings.iter().collect();
+        timings.sort_by_key(|(_, timing)| std::cmp::Reverse(timing.total));
+        for (section, timing) in timings {
+            let mean = timing.total.div_f64(timing.count.max(1) as f64);
+            let share = if self.elapsed.is_zero() {
+                0.0
+            } else {
+                100.0 * timing.total.as_secs_f64() / self.elapsed.as_secs_f64()
+            };
+            writeln!(
+                f,
+                "{:<48} {:>10} {:>12} {:>12} {:>7.1}%",
+                section,
+                timing.count,
+                format!("{:.2?}", timing.total),
+                format!("{:.2?}", mean),
+                share
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_only_when_enabled() {
+        let profiler = Profiler::default();
+        profiler.record("ignored", Duration::from_millis(1));
+        assert!(profiler.timer("ignored").is_none());
+
+        profiler.enable();
+        profiler.record("section", Duration::from_millis(1));
+        profiler.record("section", Duration::from_millis(3));
+        drop(profiler.timer("timed"));
+
+        let profile = profiler.profile();
+        assert!(!profile.timings.contains_key("ignored"));
+        assert_eq!(
+            profile.timings["section"],
+            Timing {
+                count: 2,
+                total: Duration::from_millis(4)
+            }
+        );
+        assert_eq!(profile.timings["timed"].count, 1);
+    }
+}
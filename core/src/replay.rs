@@ -0,0 +1,329 @@
+//! The `replay` module compares two versions of a protocol under the same
+//! inputs, e.g., to see how an upgrade changes prices, PnL, and gas before it
+//! is shipped.
+//!
+//! A [`Recording`] holds every transaction an [`Environment`] executed in the
+//! order it executed them, as recorded by its [`Tracer`]. [`replay`] sends the
+//! same transactions from the same senders in the same blocks to another
+//! [`Environment`], with the deployments of the contracts swapped for their
+//! new versions by an [`Upgrade`], and measures the state with a [`Probe`] at
+//! the end of every block. [`compare`] replays a recording as it was recorded
+//! and with an upgrade, and reports how the metrics and the gas of each
+//! transaction changed in a [`Comparison`].
+//!
+//! Only transactions are replayed. State that was set up with cheatcodes, e.g.,
+//! balances given to agents, has to be set up again before calling [`replay`].
+//!
+//! [`Environment`]: crate::environment::Environment
+
+use std::{
+    collections::BTreeSet,
+    fmt, fs,
+    io::{self, Read, Write},
+};
+
+use ethers::{
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Bytes as eBytes, TransactionRequest},
+};
+
+use super::*;
+use crate::{
+    environment::Environment,
+    middleware::ArbiterMiddleware,
+    state_diff::Change,
+    trace::{Tracer, TransactionTrace},
+};
+
+/// The transactions of a run in the order they were executed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    /// The recorded transactions.
+    pub transactions: Vec<TransactionTrace>,
+}
+
+/// Swaps the deployments of contracts for their new versions when a
+/// [`Recording`] is replayed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Upgrade {
+    /// The creation bytecode of each contract before the upgrade along with
+    /// the creation bytecode it is replaced with.
+    pub deployments: Vec<(eBytes, eBytes)>,
+}
+
+/// Measures the state of an [`Environment`] at the end of each block of a
+/// replay.
+#[async_trait]
+pub trait Probe: Send {
+    /// Returns the metrics of the current state keyed by name, e.g., the price
+    /// of a pool or the PnL of an agent, read through `client`.
+    async fn measure(
+        &mut self,
+        client: &Arc<ArbiterMiddleware>,
+    ) -> Result<BTreeMap<String, f64>, ArbiterCoreError>;
+}
+
+/// What happened when a [`Recording`] was replayed.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    /// The metrics measured at the end of each block, keyed by block number.
+    pub blocks: BTreeMap<u64, BTreeMap<String, f64>>,
+
+    /// The outcome of each transaction of the recording, or `None` if the
+    /// [`Environment`] rejected it without executing it.
+    pub transactions: Vec<Option<Replayed>>,
+}
+
+/// The outcome of a replayed transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Replayed {
+    /// Whether the transaction succeeded.
+    pub success: bool,
+
+    /// The amount of gas the transaction used.
+    pub gas_used: u64,
+}
+
+/// How an [`Upgrade`] changed a [`Run`]. Only what changed is listed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Comparison {
+    /// The metrics that changed at the end of each block, keyed by block
+    /// number and name. A metric is `None` in a run that did not measure it.
+    pub metrics: BTreeMap<u64, BTreeMap<String, Change<Option<f64>>>>,
+
+    /// The transactions whose outcome changed, keyed by their index in the
+    /// [`Recording`].
+    pub transactions: BTreeMap<usize, Change<Option<Replayed>>>,
+
+    /// The total gas used by the transactions of each run.
+    pub gas_used: Change<u64>,
+}
+
+impl Recording {
+    /// Creates a [`Recording`] of every transaction recorded by `tracer` so
+    /// far.
+    pub fn new(tracer: &Tracer) -> Self {
+        Self {
+            transactions: tracer.transactions(),
+        }
+    }
+
+    /// Write the [`Recording`] to a file at the given path.
+    pub fn write_to_file(&self, path: &str) -> io::Result<()> {
+        let serialized = serde_json::to_string(self)?;
+        let mut file = fs::File::create(path)?;
+        file.write_all(serialized.as_bytes())?;
+        Ok(())
+    }
+
+    /// Read a [`Recording`] from a file at the given path.
+    pub fn read_from_file(path: &str) -> io::Result<Self> {
+        let mut file = fs::File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+impl Upgrade {
+    /// Creates an [`Upgrade`] that does not change anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces every deployment of the contract with the creation bytecode
+    /// `before` by a deployment of `after` with the same constructor
+    /// arguments.
+    pub fn with_deployment(mut self, before: impl Into<eBytes>, after: impl Into<eBytes>) -> Self {
+        self.deployments.push((before.into(), after.into()));
+        self
+    }
+
+    /// Returns the calldata `transaction` is replayed with.
+    fn apply(&self, transaction: &TransactionTrace) -> eBytes {
+        if transaction.to.is_none() {
+            for (before, after) in &self.deployments {
+                if let Some(arguments) = transaction.input.strip_prefix(before.as_ref()) {
+                    return [after.as_ref(), arguments].concat().into();
+                }
+            }
+        }
+        transaction.input.clone()
+    }
+}
+
+impl Run {
+    /// Returns everything that changed from this run to the `other` one.
+    pub fn compare(&self, other: &Run) -> Comparison {
+        let mut comparison = Comparison {
+            gas_used: Change {
+                from: self.gas_used(),
+                to: other.gas_used(),
+            },
+            metrics: BTreeMap::new(),
+            transactions: BTreeMap::new(),
+        };
+
+        let empty = BTreeMap::new();
+        let blocks: BTreeSet<_> = self.blocks.keys().chain(other.blocks.keys()).collect();
+        for block in blocks {
+            let ours = self.blocks.get(block).unwrap_or(&empty);
+            let theirs = other.blocks.get(block).unwrap_or(&empty);
+            let names: BTreeSet<_> = ours.keys().chain(theirs.keys()).collect();
+            for name in names {
+                let change = Change {
+                    from: ours.get(name).copied(),
+                    to: theirs.get(name).copied(),
+                };
+                if change.from != change.to {
+                    comparison
+                        .metrics
+                        .entry(*block)
+                        .or_default()
+                        .insert(name.clone(), change);
+                }
+            }
+        }
+
+        for index in 0..self.transactions.len().max(other.transactions.len()) {
+            let change = Change {
+                from: self.transactions.get(index).copied().flatten(),
+                to: other.transactions.get(index).copied().flatten(),
+            };
+            if change.from != change.to {
+                comparison.transactions.insert(index, change);
+            }
+        }
+        comparison
+    }
+
+    /// Returns the total gas used by the transactions of the run.
+    pub fn gas_used(&self) -> u64 {
+        self.transactions
+            .iter()
+            .flatten()
+            .map(|replayed| replayed.gas_used)
+            .sum()
+    }
+}
+
+impl Comparison {
+    /// Returns `true` if the upgrade did not change any metric or
+    /// transaction.
+    pub fn is_unchanged(&self) -> bool {
+        self.metrics.is_empty() && self.transactions.is_empty()
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "gas used: {} -> {} ({:+})",
+            self.gas_used.from,
+            self.gas_used.to,
+            self.gas_used.to as i128 - self.gas_used.from as i128
+        )?;
+        for (block, metrics) in &self.metrics {
+            for (name, Change { from, to }) in metrics {
+                writeln!(f, "block {}: {}: {:?} -> {:?}", block, name, from, to)?;
+            }
+        }
+        for (index, Change { from, to }) in &self.transactions {
+            writeln!(f, "transaction {}: {:?} -> {:?}", index, from, to)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replays the transactions of `recording` on `environment` with the given
+/// `upgrade` and measures the state with `probe` at the end of every block.
+///
+/// Each transaction is sent from its recorded sender with its recorded value
+/// in a block with its recorded number and timestamp. Transactions that revert
+/// are part of the [`Run`] like any other.
+pub async fn replay(
+    recording: &Recording,
+    environment: &Environment,
+    upgrade: &Upgrade,
+    probe: &mut impl Probe,
+) -> Result<Run, ArbiterCoreError> {
+    let client = ArbiterMiddleware::new_from_forked_eoa(environment, eAddress::zero())?;
+    let mut senders = HashMap::new();
+    let mut run = Run::default();
+    let mut block = None;
+    for transaction in &recording.transactions {
+        if block != Some(transaction.block_number) {
+            if let Some(number) = block {
+                run.blocks.insert(number, probe.measure(&client).await?);
+            }
+            client.update_block(transaction.block_number, transaction.block_timestamp)?;
+            block = Some(transaction.block_number);
+        }
+
+        let sender = senders
+            .entry(transaction.caller)
+            .or_insert_with(|| client.impersonate(transaction.caller));
+        let mut request = TransactionRequest::new()
+            .from(transaction.caller)
+            .data(upgrade.apply(transaction))
+            .value(transaction.value);
+        if let Some(to) = transaction.to {
+            request = request.to(to);
+        }
+        let replayed = match sender
+            .send_transaction(TypedTransaction::Legacy(request), None)
+            .await
+        {
+            Ok(pending) => pending.await?.map(|receipt| Replayed {
+                success: true,
+                gas_used: receipt.gas_used.unwrap_or_default().as_u64(),
+            }),
+            Err(
+                ArbiterCoreError::ExecutionRevert { gas_used, .. }
+                | ArbiterCoreError::ExecutionHalt { gas_used, .. }
+                | ArbiterCoreError::OutOfGas { gas_used, .. },
+            ) => Some(Replayed {
+                success: false,
+                gas_used,
+            }),
+            Err(ArbiterCoreError::EnvironmentStoppedError) => {
+                return Err(ArbiterCoreError::EnvironmentStoppedError)
+            }
+            Err(e) => {
+                warn!("Replayed transaction was rejected: {:?}", e);
+                None
+            }
+        };
+        run.transactions.push(replayed);
+    }
+    if let Some(number) = block {
+        run.blocks.insert(number, probe.measure(&client).await?);
+    }
+    Ok(run)
+}
+
+/// Replays `recording` on an [`Environment`] created by `build` as it was
+/// recorded and on another one with the given `upgrade`, and returns
+/// everything the upgrade changed.
+///
+/// Use [`replay`] directly if the environments have to be set up with
+/// cheatcodes before the recording is replayed.
+pub async fn compare(
+    recording: &Recording,
+    upgrade: &Upgrade,
+    mut build: impl FnMut() -> Environment,
+    probe: &mut impl Probe,
+) -> Result<Comparison, ArbiterCoreError> {
+    let mut runs = Vec::with_capacity(2);
+    for upgrade in [&Upgrade::new(), upgrade] {
+        let environment = build();
+        runs.push(replay(recording, &environment, upgrade, probe).await?);
+        environment.stop()?;
+    }
+    debug!(
+        "Replayed {} transactions twice.",
+        recording.transactions.len()
+    );
+    Ok(runs[0].compare(&runs[1]))
+}
@@ -0,0 +1,654 @@
+//! The [`rpc`] module serves an [`Environment`] over Ethereum JSON-RPC so that
+//! tools that are not written in Rust, e.g., foundry scripts, frontends, or
+//! TypeScript bots, can connect to a simulation as if it were Anvil.
+//!
+//! An [`RpcServer`] answers JSON-RPC requests over HTTP and WebSocket on the
+//! same address. Every request is served by an [`ArbiterMiddleware`] attached
+//! to the [`Environment`], so the server sees the same state and blocks as the
+//! agents of the simulation, and the transactions it sends are executed
+//! alongside theirs.
+//!
+//! The server supports the methods that tooling usually needs:
+//! - `web3_clientVersion`, `net_version`, `eth_chainId`, and `eth_accounts`;
+//! - `eth_blockNumber`, `eth_gasPrice`, `eth_feeHistory`, `eth_getBalance`,
+//!   `eth_getTransactionCount`, `eth_getCode`, and `eth_getStorageAt`;
+//! - `eth_call`, `eth_estimateGas`, `eth_sendTransaction`, and
+//!   `eth_sendRawTransaction`;
+//! - `eth_sign`, `eth_signTypedData`, and `eth_signTypedData_v4` with the
+//!   accounts of the server;
+//! - `eth_getTransactionByHash`, `eth_getTransactionReceipt`,
+//!   `eth_getBlockByNumber`, and `eth_getLogs`;
+//! - `eth_newFilter`, `eth_getFilterChanges`, and `eth_uninstallFilter` for
+//!   log filters;
+//! - `eth_subscribe` to `logs` and `eth_unsubscribe` over WebSocket;
+//! - `evm_mine`, `anvil_setBalance`, `anvil_setNonce`, and
+//!   `anvil_setStorageAt`, along with their `hardhat_` aliases.
+//!
+//! Like on Anvil with auto-impersonation, `eth_sendTransaction` sends from any
+//! `from` address without a signature, and defaults to the account of the
+//! server. Raw transactions are sent from the address that signed them. The
+//! hashes of transactions are the hashes the [`Environment`] assigns to them,
+//! not the hashes of their signed encoding. The transactions returned by
+//! `eth_getTransactionByHash` are read from their receipts, so they hold where
+//! and by whom a transaction was mined but not its input, value, or signature.
+//!
+//! [`Environment`]: crate::environment::Environment
+
+use std::{net::SocketAddr, sync::Mutex};
+
+use ethers::{
+    providers::{FilterKind, Middleware},
+    signers::Signer,
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::TypedData},
+        Address as eAddress, BlockId, BlockNumber, Bytes as eBytes, Eip1559TransactionRequest,
+        RecoveryMessage, Transaction, TransactionReceipt, TransactionRequest,
+    },
+    utils::rlp::Rlp,
+};
+use futures_util::{SinkExt, StreamExt};
+use hyper::{
+    header::{self, HeaderValue},
+    upgrade::Upgraded,
+    Body, Method, Request, Response, StatusCode,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+use tokio_tungstenite::{tungstenite::Message, WebSocketStream};
+
+use super::*;
+use crate::{
+    environment::{instruction::Cheatcodes, Environment},
+    middleware::ArbiterMiddleware,
+    websocket,
+};
+
+/// The JSON-RPC error code of a call or transaction that reverted.
+const EXECUTION_REVERTED: i64 = 3;
+
+/// The JSON-RPC error code of a request that could not be parsed.
+const PARSE_ERROR: i64 = -32700;
+
+/// The JSON-RPC error code of a request for a method that is not supported.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The JSON-RPC error code of a request with invalid parameters.
+const INVALID_PARAMS: i64 = -32602;
+
+/// The JSON-RPC error code of a request that failed in the [`Environment`].
+const SERVER_ERROR: i64 = -32000;
+
+/// A JSON-RPC server over HTTP and WebSocket backed by an [`Environment`].
+///
+/// The server runs on the Tokio runtime it was started on until it is stopped
+/// or dropped.
+#[derive(Debug)]
+pub struct RpcServer {
+    /// The address the server listens on.
+    address: SocketAddr,
+
+    /// The task that accepts connections.
+    handle: JoinHandle<()>,
+}
+
+impl RpcServer {
+    /// Starts serving `environment` on `address`, e.g., `"127.0.0.1:8545"`.
+    /// Binding to port `0` picks a free port, which can be read from
+    /// [`RpcServer::local_addr`].
+    ///
+    /// The requests are served by a client of the `environment` with an
+    /// account labeled `rpc`, which is the default sender of
+    /// `eth_sendTransaction`.
+    pub async fn serve(
+        environment: &Environment,
+        address: impl ToSocketAddrs,
+    ) -> Result<Self, ArbiterCoreError> {
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| ArbiterCoreError::RpcError(e.to_string()))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| ArbiterCoreError::RpcError(e.to_string()))?;
+        let rpc = Arc::new(Rpc::new(ArbiterMiddleware::new(environment, Some("rpc"))?));
+        let handle = websocket::serve(listener, "JSON-RPC", move |request| {
+            serve_http(rpc.clone(), request)
+        });
+        info!("Serving JSON-RPC on {}", address);
+        Ok(Self { address, handle })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting connections.
+    pub fn stop(self) {
+        self.handle.abort();
+    }
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Serves a single HTTP request, upgrading it to a WebSocket connection if it
+/// asks for one.
+async fn serve_http(rpc: Arc<Rpc>, mut request: Request<Body>) -> Response<Body> {
+    if websocket::is_upgrade(&request) {
+        return websocket::upgrade(&mut request, |websocket| serve_websocket(rpc, websocket));
+    }
+    let mut response = if request.method() == Method::OPTIONS {
+        // Answer the preflight requests of browsers.
+        Response::new(Body::empty())
+    } else {
+        let body = match hyper::body::to_bytes(request.into_body()).await {
+            Ok(body) => body,
+            Err(e) => return websocket::respond(StatusCode::BAD_REQUEST, e.to_string()),
+        };
+        let mut response = Response::new(Body::from(rpc.handle_message(&body, None).await));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        response
+    };
+    let headers = response.headers_mut();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_ORIGIN,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_HEADERS,
+        HeaderValue::from_static("*"),
+    );
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_METHODS,
+        HeaderValue::from_static("POST, OPTIONS"),
+    );
+    response
+}
+
+/// Serves the JSON-RPC messages of a WebSocket connection until it is closed.
+async fn serve_websocket(rpc: Arc<Rpc>, websocket: WebSocketStream<Upgraded>) {
+    let (mut sink, mut stream) = websocket.split();
+
+    // Responses and notifications are written by a single task so that
+    // subscriptions can notify while requests are being served.
+    let (sender, mut receiver) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            if sink.send(Message::Text(message)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        sender: sender.clone(),
+        subscriptions: HashMap::new(),
+    };
+    while let Some(Ok(message)) = stream.next().await {
+        let response = match message {
+            Message::Text(text) => {
+                rpc.handle_message(text.as_bytes(), Some(&mut session))
+                    .await
+            }
+            Message::Binary(bytes) => rpc.handle_message(&bytes, Some(&mut session)).await,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        if sender.send(response).is_err() {
+            break;
+        }
+    }
+    for subscription in session.subscriptions.values() {
+        subscription.abort();
+    }
+    writer.abort();
+}
+
+/// The subscriptions of a WebSocket connection.
+struct Session {
+    /// Sends messages to the WebSocket connection.
+    sender: mpsc::UnboundedSender<String>,
+
+    /// The task that forwards the notifications of each subscription, keyed
+    /// by its ID.
+    subscriptions: HashMap<eU256, JoinHandle<()>>,
+}
+
+/// The error of a JSON-RPC request.
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+}
+
+impl From<ArbiterCoreError> for RpcError {
+    fn from(error: ArbiterCoreError) -> Self {
+        match error {
+            ArbiterCoreError::ExecutionRevert { output, .. } => Self {
+                code: EXECUTION_REVERTED,
+                message: "execution reverted".to_owned(),
+                data: Some(json!(eBytes::from(output))),
+            },
+            error => Self::new(SERVER_ERROR, error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for RpcError {
+    fn from(error: serde_json::Error) -> Self {
+        Self::new(SERVER_ERROR, error)
+    }
+}
+
+/// Answers JSON-RPC requests with a client of the [`Environment`].
+struct Rpc {
+    /// The client of the server.
+    client: Arc<ArbiterMiddleware>,
+
+    /// The clients that send the transactions of other addresses, keyed by
+    /// address, which keep track of the nonces of each address.
+    senders: Mutex<HashMap<eAddress, Arc<ArbiterMiddleware>>>,
+
+    /// Serves one request at a time, since the clients wait for the
+    /// [`Environment`] to answer each of their instructions in turn.
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl Rpc {
+    fn new(client: Arc<ArbiterMiddleware>) -> Self {
+        Self {
+            client,
+            senders: Mutex::new(HashMap::new()),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Answers a single request or a batch of requests.
+    async fn handle_message(&self, message: &[u8], mut session: Option<&mut Session>) -> String {
+        let response = match serde_json::from_slice::<Value>(message) {
+            Ok(Value::Array(requests)) => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in requests {
+                    responses.push(self.handle_request(request, session.as_deref_mut()).await);
+                }
+                Value::Array(responses)
+            }
+            Ok(request) => self.handle_request(request, session).await,
+            Err(e) => response(Value::Null, Err(RpcError::new(PARSE_ERROR, e))),
+        };
+        response.to_string()
+    }
+
+    /// Answers a single request.
+    async fn handle_request(&self, request: Value, session: Option<&mut Session>) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let params = request.get("params").cloned().unwrap_or(json!([]));
+        trace!("Serving JSON-RPC request `{}`", method);
+        let _guard = self.lock.lock().await;
+        response(id, self.dispatch(method, &params, session).await)
+    }
+
+    async fn dispatch(
+        &self,
+        method: &str,
+        params: &Value,
+        session: Option<&mut Session>,
+    ) -> Result<Value, RpcError> {
+        let client = &self.client;
+        let result = match method {
+            "web3_clientVersion" => json!(format!("arbiter/v{}", env!("CARGO_PKG_VERSION"))),
+            "net_version" => json!(client.get_chainid().await?.to_string()),
+            "eth_chainId" => json!(U64::from(client.get_chainid().await?.as_u64())),
+            "eth_accounts" => json!(client.accounts()),
+            "eth_blockNumber" => json!(client.get_block_number().await?),
+            "eth_gasPrice" => json!(client.get_gas_price().await?),
+            "eth_feeHistory" => {
+                let block_count: eU256 = param(params, 0)?;
+                let newest_block: BlockNumber = param(params, 1)?;
+                let reward_percentiles = param::<Option<Vec<f64>>>(params, 2)?.unwrap_or_default();
+                json!(
+                    client
+                        .fee_history(block_count, newest_block, &reward_percentiles)
+                        .await?
+                )
+            }
+            "eth_getBalance" => {
+                let address: eAddress = param(params, 0)?;
+                json!(or_default(client.get_balance(address, None).await)?)
+            }
+            "eth_getTransactionCount" => {
+                let address: eAddress = param(params, 0)?;
+                json!(or_default(
+                    client.get_transaction_count(address, None).await
+                )?)
+            }
+            "eth_getCode" => {
+                let address: eAddress = param(params, 0)?;
+                let code = or_default(client.get_account(address).await.map(|info| {
+                    info.code
+                        .map(|code| eBytes::from(code.original_bytes().to_vec()))
+                        .unwrap_or_default()
+                }))?;
+                json!(code)
+            }
+            "eth_getStorageAt" => {
+                let address: eAddress = param(params, 0)?;
+                let slot: eU256 = param(params, 1)?;
+                let mut key = [0; 32];
+                slot.to_big_endian(&mut key);
+                json!(client.get_storage_at(address, H256(key), None).await?)
+            }
+            "eth_call" => {
+                let tx = transaction(params)?;
+                let block = param::<Option<BlockNumber>>(params, 1)?.map(BlockId::Number);
+                json!(client.call(&tx, block).await?)
+            }
+            "eth_estimateGas" => {
+                let tx = transaction(params)?;
+                let block = param::<Option<BlockNumber>>(params, 1)?.map(BlockId::Number);
+                json!(client.estimate_gas(&tx, block).await?)
+            }
+            "eth_sendTransaction" => {
+                let tx = transaction(params)?;
+                let sender = self.sender(tx.from().copied().unwrap_or(client.address()));
+                let transaction_hash = *sender.send_transaction(tx, None).await?;
+                json!(transaction_hash)
+            }
+            "eth_sendRawTransaction" => {
+                let raw: eBytes = param(params, 0)?;
+                let (mut tx, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw))
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                let from = signature
+                    .recover(RecoveryMessage::Hash(tx.sighash()))
+                    .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                tx.set_from(from);
+                if tx.data().is_none() {
+                    tx.set_data(eBytes::default());
+                }
+                let sender = self.sender(from);
+                let transaction_hash = *sender.send_transaction(tx, None).await?;
+                json!(transaction_hash)
+            }
+            "eth_sign" => {
+                let address: eAddress = param(params, 0)?;
+                let data: eBytes = param(params, 1)?;
+                json!(eBytes::from(client.sign(data, &address).await?.to_vec()))
+            }
+            "eth_signTypedData" | "eth_signTypedData_v4" => {
+                let address: eAddress = param(params, 0)?;
+                // Wallets send the typed data either as an object or as a string
+                // that holds its JSON.
+                let typed_data: TypedData = match param(params, 1)? {
+                    Value::String(typed_data) => serde_json::from_str(&typed_data),
+                    typed_data => serde_json::from_value(typed_data),
+                }
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+                let signature = client
+                    .with_sender(address)?
+                    .sign_typed_data(&typed_data)
+                    .await?;
+                json!(eBytes::from(signature.to_vec()))
+            }
+            "eth_getTransactionByHash" => {
+                let transaction_hash: H256 = param(params, 0)?;
+                let receipt = client.get_transaction_receipt(transaction_hash).await?;
+                json!(receipt.map(mined_transaction))
+            }
+            "eth_getTransactionReceipt" => {
+                let transaction_hash: H256 = param(params, 0)?;
+                json!(client.get_transaction_receipt(transaction_hash).await?)
+            }
+            "eth_getBlockByNumber" => {
+                let block_number: BlockNumber = param(params, 0)?;
+                json!(client.get_block(block_number).await?)
+            }
+            "eth_getLogs" => {
+                let filter: Filter = param(params, 0)?;
+                json!(client.get_logs(&filter).await?)
+            }
+            "eth_newFilter" => {
+                let filter: Filter = param(params, 0)?;
+                json!(client.new_filter(FilterKind::Logs(&filter)).await?)
+            }
+            "eth_getFilterChanges" => {
+                let id: eU256 = param(params, 0)?;
+                json!(client.get_filter_changes::<_, eLog>(id).await?)
+            }
+            "eth_uninstallFilter" => {
+                let id: eU256 = param(params, 0)?;
+                json!(client.uninstall_filter(id).await?)
+            }
+            "eth_subscribe" => {
+                let session = session.ok_or(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    "Subscriptions are only supported over WebSocket",
+                ))?;
+                match param::<String>(params, 0)?.as_str() {
+                    "logs" => {
+                        let filter = param::<Option<Filter>>(params, 1)?.unwrap_or_default();
+                        json!(self.subscribe_logs(filter, session).await?)
+                    }
+                    kind => {
+                        return Err(RpcError::new(
+                            INVALID_PARAMS,
+                            format!("The subscription `{}` is not supported", kind),
+                        ))
+                    }
+                }
+            }
+            "eth_unsubscribe" => {
+                let id: eU256 = param(params, 0)?;
+                let subscription = session.and_then(|session| session.subscriptions.remove(&id));
+                if let Some(subscription) = &subscription {
+                    subscription.abort();
+                }
+                json!(subscription.is_some())
+            }
+            "evm_mine" => {
+                let block_number = client.get_block_number().await?.as_u64() + 1;
+                let block_timestamp = match param::<Option<eU256>>(params, 0)? {
+                    Some(block_timestamp) => block_timestamp,
+                    None => client.get_block_timestamp().await? + 1,
+                };
+                client.update_block(block_number, block_timestamp)?;
+                json!("0x0")
+            }
+            "anvil_setBalance" | "hardhat_setBalance" => {
+                let address: eAddress = param(params, 0)?;
+                let amount: eU256 = param(params, 1)?;
+                client.set_balance(address, amount).await?;
+                Value::Null
+            }
+            "anvil_setNonce" | "hardhat_setNonce" => {
+                let address: eAddress = param(params, 0)?;
+                let nonce: eU256 = param(params, 1)?;
+                client.set_nonce(address, nonce.as_u64()).await?;
+                if let Some(sender) = self.senders.lock().unwrap().get(&address) {
+                    sender.reset_nonce(address);
+                }
+                Value::Null
+            }
+            "anvil_setStorageAt" | "hardhat_setStorageAt" => {
+                let account: eAddress = param(params, 0)?;
+                let key: eU256 = param(params, 1)?;
+                let value: H256 = param(params, 2)?;
+                let mut slot = [0; 32];
+                key.to_big_endian(&mut slot);
+                client
+                    .apply_cheatcode(Cheatcodes::Store {
+                        account,
+                        key: H256(slot),
+                        value,
+                    })
+                    .await?;
+                json!(true)
+            }
+            // Every address can send transactions already.
+            "anvil_impersonateAccount"
+            | "anvil_stopImpersonatingAccount"
+            | "hardhat_impersonateAccount"
+            | "hardhat_stopImpersonatingAccount" => Value::Null,
+            method => {
+                return Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("The method `{}` is not supported", method),
+                ))
+            }
+        };
+        Ok(result)
+    }
+
+    /// Returns the client that sends the transactions of `address`.
+    fn sender(&self, address: eAddress) -> Arc<ArbiterMiddleware> {
+        if let Ok(sender) = self.client.with_sender(address) {
+            return sender;
+        }
+        self.senders
+            .lock()
+            .unwrap()
+            .entry(address)
+            .or_insert_with(|| self.client.impersonate(address))
+            .clone()
+    }
+
+    /// Forwards the logs matching `filter` to the WebSocket connection of
+    /// `session` as `eth_subscription` notifications and returns the ID of the
+    /// subscription.
+    async fn subscribe_logs(
+        &self,
+        filter: Filter,
+        session: &mut Session,
+    ) -> Result<eU256, RpcError> {
+        let client = self.client.clone();
+        let sender = session.sender.clone();
+        let (id_sender, id_receiver) = oneshot::channel();
+        let subscription = tokio::spawn(async move {
+            let mut stream = match client.subscribe_logs(&filter).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    let _ = id_sender.send(Err(e));
+                    return;
+                }
+            };
+            let id = stream.id;
+            let _ = id_sender.send(Ok(id));
+            while let Some(log) = stream.next().await {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "eth_subscription",
+                    "params": { "subscription": id, "result": log },
+                });
+                if sender.send(notification.to_string()).is_err() {
+                    break;
+                }
+            }
+        });
+        let id = id_receiver
+            .await
+            .map_err(|e| RpcError::new(SERVER_ERROR, e))??;
+        session.subscriptions.insert(id, subscription);
+        Ok(id)
+    }
+}
+
+/// Returns the transaction that was mined with `receipt`.
+fn mined_transaction(receipt: TransactionReceipt) -> Transaction {
+    Transaction {
+        hash: receipt.transaction_hash,
+        block_hash: receipt.block_hash,
+        block_number: receipt.block_number,
+        transaction_index: Some(receipt.transaction_index),
+        from: receipt.from,
+        to: receipt.to,
+        gas_price: receipt.effective_gas_price,
+        transaction_type: receipt.transaction_type,
+        ..Default::default()
+    }
+}
+
+/// Builds the JSON-RPC response to the request with the given `id`.
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(RpcError {
+            code,
+            message,
+            data,
+        }) => {
+            let mut error = json!({ "code": code, "message": message });
+            if let Some(data) = data {
+                error["data"] = data;
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": error })
+        }
+    }
+}
+
+/// Deserializes the parameter at `index`, which is `null` if it is missing.
+fn param<T: DeserializeOwned>(params: &Value, index: usize) -> Result<T, RpcError> {
+    let param = params.get(index).cloned().unwrap_or(Value::Null);
+    serde_json::from_value(param).map_err(|e| RpcError::new(INVALID_PARAMS, e))
+}
+
+/// Deserializes the transaction request that is the first parameter.
+fn transaction(params: &Value) -> Result<TypedTransaction, RpcError> {
+    let mut request: Value = param(params, 0)?;
+    if let Some(request) = request.as_object_mut() {
+        // Newer tools send the calldata as `input` and older ones as `data`.
+        if request.contains_key("data") {
+            request.remove("input");
+        }
+    }
+    let dynamic_fee = request.get("maxFeePerGas").is_some();
+    let mut tx = if dynamic_fee {
+        TypedTransaction::Eip1559(
+            serde_json::from_value::<Eip1559TransactionRequest>(request)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?,
+        )
+    } else {
+        TypedTransaction::Legacy(
+            serde_json::from_value::<TransactionRequest>(request)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?,
+        )
+    };
+    // Plain transfers of ether do not have calldata.
+    if tx.data().is_none() {
+        tx.set_data(eBytes::default());
+    }
+    Ok(tx)
+}
+
+/// Answers queries about accounts that do not exist yet with the default
+/// value, like a node does.
+fn or_default<T: Default>(result: Result<T, ArbiterCoreError>) -> Result<T, ArbiterCoreError> {
+    match result {
+        Err(ArbiterCoreError::AccountDoesNotExistError) => Ok(T::default()),
+        result => result,
+    }
+}
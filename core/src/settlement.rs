@@ -0,0 +1,107 @@
+//! The [`settlement`] module lets an [`Environment`] charge transactions for
+//! the cost of settling them on another chain, e.g., the L1 data fee a rollup
+//! pays to post its transactions to Ethereum.
+//!
+//! A [`SettlementModel`] prices each transaction on top of the gas it uses.
+//! The fee is deducted from the sender once the transaction has executed and
+//! is burned, or credited to the
+//! [`EnvironmentParameters::settlement_fee_recipient`] if there is one. A
+//! sender that cannot pay the fee has its transaction rejected with
+//! [`ArbiterCoreError::InsufficientSettlementFunds`] without any of its
+//! changes being committed. The fee is reported on the receipt of the
+//! transaction under [`RECEIPT_FIELD`].
+//!
+//! [`Environment`]: crate::environment::Environment
+//! [`EnvironmentParameters::settlement_fee_recipient`]: crate::environment::EnvironmentParameters::settlement_fee_recipient
+
+use super::*;
+
+/// The key under which the settlement fee of a transaction is stored in the
+/// `other` fields of its
+/// [`TransactionReceipt`](ethers::types::TransactionReceipt).
+pub const RECEIPT_FIELD: &str = "settlementFee";
+
+/// Prices the cost of settling a transaction on top of the gas it uses.
+pub trait SettlementModel: Debug + Send + Sync {
+    /// Returns the fee the sender of `tx_env` pays to settle it.
+    fn fee(&self, tx_env: &TxEnv) -> U256;
+}
+
+/// An L1 data fee in the style of the OP Stack before Ecotone: the calldata
+/// of a transaction is priced in L1 gas per byte, plus a fixed overhead, and
+/// paid for at the L1 base fee scaled by `scalar`.
+///
+/// Only the calldata of a transaction is known to the [`Environment`], so the
+/// default `overhead` includes the L1 gas of the 68 bytes of a signature and
+/// transaction envelope on top of the OP Stack's fixed overhead of 188 gas.
+///
+/// [`Environment`]: crate::environment::Environment
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct L1DataFee {
+    /// The base fee per gas of the L1 the transactions are posted to.
+    pub l1_base_fee: U256,
+
+    /// The L1 gas of each zero byte of calldata.
+    pub zero_byte_gas: u64,
+
+    /// The L1 gas of each non-zero byte of calldata.
+    pub non_zero_byte_gas: u64,
+
+    /// The L1 gas every transaction pays regardless of its calldata.
+    pub overhead: u64,
+
+    /// The factor the fee is scaled by in millionths, e.g., `1_000_000` to
+    /// charge the L1 cost as is.
+    pub scalar: u64,
+}
+
+impl L1DataFee {
+    /// Creates an [`L1DataFee`] at the given `l1_base_fee` with the calldata
+    /// pricing of Ethereum and an unscaled fee.
+    pub fn new(l1_base_fee: U256) -> Self {
+        Self {
+            l1_base_fee,
+            zero_byte_gas: 4,
+            non_zero_byte_gas: 16,
+            overhead: 188 + 68 * 16,
+            scalar: 1_000_000,
+        }
+    }
+
+    /// Returns the L1 gas used to post a transaction with the calldata
+    /// `data`.
+    pub fn l1_gas(&self, data: &[u8]) -> u64 {
+        let zero_bytes = data.iter().filter(|byte| **byte == 0).count() as u64;
+        let non_zero_bytes = data.len() as u64 - zero_bytes;
+        zero_bytes * self.zero_byte_gas + non_zero_bytes * self.non_zero_byte_gas + self.overhead
+    }
+}
+
+impl SettlementModel for L1DataFee {
+    fn fee(&self, tx_env: &TxEnv) -> U256 {
+        U256::from(self.l1_gas(&tx_env.data)) * self.l1_base_fee * U256::from(self.scalar)
+            / U256::from(1_000_000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn l1_data_fee() {
+        let model = L1DataFee::new(U256::from(10));
+        let tx_env = TxEnv {
+            data: Bytes::from(vec![0, 0, 1, 2]),
+            ..Default::default()
+        };
+        assert_eq!(model.l1_gas(&tx_env.data), 2 * 4 + 2 * 16 + 188 + 68 * 16);
+        assert_eq!(model.fee(&tx_env), U256::from((40 + 188 + 68 * 16) * 10));
+
+        let scaled = L1DataFee {
+            scalar: 500_000,
+            ..model
+        };
+        assert_eq!(scaled.fee(&tx_env), U256::from((40 + 188 + 68 * 16) * 5));
+    }
+}
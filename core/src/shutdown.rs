@@ -0,0 +1,186 @@
+//! The [`shutdown`] module coordinates flushing output artifacts when a
+//! simulation is interrupted.
+//!
+//! Every output sink that buffers what it writes (e.g., the [`Logger`])
+//! registers with the global [`ShutdownCoordinator`] and receives a
+//! [`ShutdownHandle`]. Once a shutdown is requested, every registered sink is
+//! notified so it can write what it has collected so far, and the shutdown
+//! waits until every sink has dropped its handle, or until
+//! [`FINALIZE_TIMEOUT`] has passed.
+//!
+//! The coordinator never handles signals on its own, so that an application
+//! keeps its own signal handling. An application that wants `SIGINT` (Ctrl-C)
+//! and `SIGTERM` to finalize the sinks and then exit opts in from within its
+//! tokio runtime:
+//! ```ignore
+//! ShutdownCoordinator::global().install_signal_handlers();
+//! ```
+//!
+//! [`Logger`]: crate::events::Logger
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        OnceLock,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{watch, Notify};
+
+use super::*;
+
+/// The longest amount of time the sinks are given to finalize their artifacts
+/// after a signal before the process exits anyway.
+pub const FINALIZE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The exit code used after `SIGINT`.
+const SIGINT_EXIT_CODE: i32 = 130;
+
+/// The exit code used after `SIGTERM`.
+#[cfg(unix)]
+const SIGTERM_EXIT_CODE: i32 = 143;
+
+static COORDINATOR: OnceLock<ShutdownCoordinator> = OnceLock::new();
+
+/// Tracks the output sinks that need to be finalized before the process exits.
+#[derive(Debug)]
+pub struct ShutdownCoordinator {
+    /// Flips to `true` once a shutdown is requested.
+    requested: watch::Sender<bool>,
+
+    /// The number of sinks that still hold a [`ShutdownHandle`].
+    pending: AtomicUsize,
+
+    /// Notified whenever a [`ShutdownHandle`] is dropped.
+    finalized: Notify,
+
+    /// Whether the signal handlers have been installed.
+    installed: AtomicBool,
+}
+
+/// A registration of an output sink with the [`ShutdownCoordinator`].
+///
+/// Dropping the handle tells the coordinator that the sink has finalized its
+/// artifacts.
+#[derive(Debug)]
+pub struct ShutdownHandle {
+    receiver: watch::Receiver<bool>,
+}
+
+impl ShutdownCoordinator {
+    /// Returns the process wide [`ShutdownCoordinator`].
+    pub fn global() -> &'static Self {
+        COORDINATOR.get_or_init(|| Self {
+            requested: watch::channel(false).0,
+            pending: AtomicUsize::new(0),
+            finalized: Notify::new(),
+            installed: AtomicBool::new(false),
+        })
+    }
+
+    /// Registers a new output sink.
+    pub fn register(&'static self) -> ShutdownHandle {
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        ShutdownHandle {
+            receiver: self.requested.subscribe(),
+        }
+    }
+
+    /// Returns `true` if a shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        *self.requested.borrow()
+    }
+
+    /// Resolves once a shutdown has been requested, without registering a
+    /// sink, e.g., to stop a simulation whose sinks write their artifacts
+    /// once it stops.
+    pub async fn wait_for_request(&self) {
+        let mut receiver = self.requested.subscribe();
+        // The sender lives in a static so it is never dropped.
+        let _ = receiver.wait_for(|requested| *requested).await;
+    }
+
+    /// Notifies every registered sink to finalize its artifacts as if the
+    /// process had been interrupted. This does not exit the process.
+    pub fn trigger(&self) {
+        self.requested.send_replace(true);
+    }
+
+    /// Waits until every registered sink has finalized its artifacts or the
+    /// `timeout` has passed. Returns `false` on timeout.
+    pub async fn wait_for_sinks(&self, timeout: Duration) -> bool {
+        tokio::time::timeout(timeout, async {
+            loop {
+                let finalized = self.finalized.notified();
+                if self.pending.load(Ordering::SeqCst) == 0 {
+                    break;
+                }
+                finalized.await;
+            }
+        })
+        .await
+        .is_ok()
+    }
+
+    /// Installs the `SIGINT` and `SIGTERM` handlers that trigger a shutdown,
+    /// wait for the sinks, and then exit the process, which replaces the
+    /// default handling of those signals. Nothing in the crate installs them,
+    /// so an application calls this to opt in. Calling this more than once
+    /// has no effect. Must be called from within a tokio runtime.
+    pub fn install_signal_handlers(&'static self) {
+        if self.installed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        tokio::spawn(async move {
+            let exit_code = wait_for_signal().await;
+            warn!("Received a termination signal, finalizing output artifacts");
+            self.trigger();
+            if !self.wait_for_sinks(FINALIZE_TIMEOUT).await {
+                error!("Timed out waiting for output artifacts to be finalized");
+            }
+            std::process::exit(exit_code);
+        });
+    }
+}
+
+impl ShutdownHandle {
+    /// Resolves once a shutdown has been requested.
+    pub async fn requested(&mut self) {
+        // The sender lives in a static so it is never dropped.
+        let _ = self.receiver.wait_for(|requested| *requested).await;
+    }
+}
+
+impl Drop for ShutdownHandle {
+    fn drop(&mut self) {
+        let coordinator = ShutdownCoordinator::global();
+        coordinator.pending.fetch_sub(1, Ordering::SeqCst);
+        coordinator.finalized.notify_waiters();
+    }
+}
+
+/// Waits for `SIGINT` or `SIGTERM` and returns the matching exit code.
+#[cfg(unix)]
+async fn wait_for_signal() -> i32 {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!("Unable to listen for SIGTERM: {:?}", e);
+            let _ = tokio::signal::ctrl_c().await;
+            return SIGINT_EXIT_CODE;
+        }
+    };
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => SIGINT_EXIT_CODE,
+        _ = terminate.recv() => SIGTERM_EXIT_CODE,
+    }
+}
+
+/// Waits for Ctrl-C and returns the matching exit code.
+#[cfg(not(unix))]
+async fn wait_for_signal() -> i32 {
+    let _ = tokio::signal::ctrl_c().await;
+    SIGINT_EXIT_CODE
+}
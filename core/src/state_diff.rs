@@ -0,0 +1,134 @@
+//! The [`state_diff`] module describes how a single transaction changed the
+//! state of an [`Environment`].
+//!
+//! When an [`Environment`] is built with
+//! [`EnvironmentBuilder::with_state_diffs`], every transaction it executes
+//! records the balance, nonce, and storage of each account before and after
+//! the transaction. The resulting [`StateDiff`] is attached to the
+//! [`TransactionReceipt`] under [`RECEIPT_FIELD`] and can be read back with
+//! [`StateDiff::from_receipt`], which makes it easy to assert that a
+//! transaction only touched what it was supposed to.
+//!
+//! [`Environment`]: crate::environment::Environment
+//! [`EnvironmentBuilder::with_state_diffs`]: crate::environment::EnvironmentBuilder::with_state_diffs
+
+use ethers::types::TransactionReceipt;
+use revm::primitives::{db::DatabaseRef, State};
+
+use super::*;
+use crate::database::backend::Backend;
+
+/// The key under which the [`StateDiff`] of a transaction is stored in the
+/// `other` fields of its [`TransactionReceipt`].
+pub const RECEIPT_FIELD: &str = "stateDiff";
+
+/// The changes a transaction made to the state, keyed by account. Accounts
+/// that the transaction touched without changing them are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateDiff {
+    /// The changes made to each account.
+    pub accounts: BTreeMap<eAddress, AccountDiff>,
+}
+
+/// The changes a transaction made to a single account.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountDiff {
+    /// The change of the balance, or `None` if it did not change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub balance: Option<Change<eU256>>,
+
+    /// The change of the nonce, or `None` if it did not change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<Change<u64>>,
+
+    /// The change of each storage slot that was written to a new value.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<H256, Change<H256>>,
+}
+
+/// A value before and after a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change<T> {
+    /// The value before the transaction.
+    pub from: T,
+
+    /// The value after the transaction.
+    pub to: T,
+}
+
+impl StateDiff {
+    /// Computes the diff between the changes in `state` that a transaction
+    /// produced and the committed state in `db`. This has to be called before
+    /// `state` is committed.
+    pub(crate) fn new(state: &State, db: &CacheDB<Backend>) -> Self {
+        let mut accounts = BTreeMap::new();
+        for (address, account) in state {
+            if !account.is_touched() {
+                continue;
+            }
+            let before = db.basic_ref(*address).ok().flatten().unwrap_or_default();
+            // A self-destructed account is wiped when it is committed.
+            let after = if account.is_selfdestructed() {
+                AccountInfo::default()
+            } else {
+                account.info.clone()
+            };
+
+            let mut diff = AccountDiff {
+                balance: change(before.balance, after.balance).map(|Change { from, to }| Change {
+                    from: eU256::from_little_endian(from.as_le_slice()),
+                    to: eU256::from_little_endian(to.as_le_slice()),
+                }),
+                nonce: change(before.nonce, after.nonce),
+                storage: BTreeMap::new(),
+            };
+            for (index, slot) in &account.storage {
+                let to = if account.is_selfdestructed() {
+                    U256::ZERO
+                } else {
+                    slot.present_value
+                };
+                if let Some(Change { from, to }) = change(slot.previous_or_original_value, to) {
+                    diff.storage.insert(
+                        index.to_be_bytes().into(),
+                        Change {
+                            from: from.to_be_bytes().into(),
+                            to: to.to_be_bytes().into(),
+                        },
+                    );
+                }
+            }
+
+            if diff != AccountDiff::default() {
+                accounts.insert(address.into_array().into(), diff);
+            }
+        }
+        Self { accounts }
+    }
+
+    /// Reads the [`StateDiff`] attached to `receipt`. Returns `None` if the
+    /// receipt does not carry one, e.g., because the [`Environment`] was not
+    /// built with
+    /// [`EnvironmentBuilder::with_state_diffs`](crate::environment::EnvironmentBuilder::with_state_diffs).
+    ///
+    /// [`Environment`]: crate::environment::Environment
+    pub fn from_receipt(receipt: &TransactionReceipt) -> Option<Self> {
+        receipt.other.get_deserialized(RECEIPT_FIELD)?.ok()
+    }
+
+    /// Returns the changes made to the account at `address`, or `None` if it
+    /// did not change.
+    pub fn account(&self, address: eAddress) -> Option<&AccountDiff> {
+        self.accounts.get(&address)
+    }
+
+    /// Returns `true` if the transaction did not change anything.
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+}
+
+/// Returns the [`Change`] from `from` to `to`, or `None` if they are equal.
+fn change<T: PartialEq>(from: T, to: T) -> Option<Change<T>> {
+    (from != to).then_some(Change { from, to })
+}
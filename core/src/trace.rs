@@ -0,0 +1,305 @@
+//! The [`trace`] module records every transaction an [`Environment`] executes
+//! in the order it executed them, so that two runs of the same simulation can
+//! be compared transaction by transaction.
+//!
+//! A [`Tracer`] is a cheap to clone handle that is shared by an
+//! [`Environment`] and anyone who wants to read its trace. Recording is a
+//! no-op until the tracer is enabled, either through
+//! [`EnvironmentBuilder::with_tracing`] or [`Tracer::enable`].
+//!
+//! The module also records the [`CallTrace`] of a single call with the
+//! [`CallTracer`], which is what
+//! [`ArbiterMiddleware::trace_call`](crate::middleware::ArbiterMiddleware::trace_call)
+//! returns.
+//!
+//! [`Environment`]: crate::environment::Environment
+//! [`EnvironmentBuilder::with_tracing`]: crate::environment::EnvironmentBuilder::with_tracing
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use ethers::types::Bytes as eBytes;
+use revm::{
+    interpreter::{CallScheme, CreateInputs, CreateOutcome},
+    primitives::{BlockEnv, CreateScheme, TransactTo},
+};
+
+use super::*;
+use crate::conversions::ToEthers;
+
+/// A shared handle used to record the transactions executed by an
+/// [`Environment`](crate::environment::Environment).
+#[derive(Debug, Clone, Default)]
+pub struct Tracer {
+    inner: Arc<TracerInner>,
+}
+
+#[derive(Debug, Default)]
+struct TracerInner {
+    enabled: AtomicBool,
+    transactions: Mutex<Vec<TransactionTrace>>,
+}
+
+/// A transaction as it was executed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionTrace {
+    /// The number of the block the transaction was executed in.
+    pub block_number: u64,
+
+    /// The timestamp of the block the transaction was executed in.
+    #[serde(default)]
+    pub block_timestamp: u64,
+
+    /// The sender of the transaction.
+    pub caller: eAddress,
+
+    /// The recipient of the transaction, or `None` for a deployment.
+    pub to: Option<eAddress>,
+
+    /// The calldata of the transaction.
+    pub input: eBytes,
+
+    /// The value sent with the transaction.
+    #[serde(default)]
+    pub value: eU256,
+
+    /// Whether the transaction succeeded.
+    pub success: bool,
+
+    /// The amount of gas the transaction used.
+    pub gas_used: u64,
+
+    /// The data returned by the transaction or the revert data.
+    pub output: eBytes,
+}
+
+impl Tracer {
+    /// Starts recording.
+    pub fn enable(&self) {
+        self.inner.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if the tracer is recording.
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Records the transaction `tx_env` that was executed in `block` with the
+    /// given `result`.
+    pub(crate) fn record(&self, tx_env: &TxEnv, block: &BlockEnv, result: &ExecutionResult) {
+        let output = match result {
+            ExecutionResult::Success { output, .. } => output.data().clone(),
+            ExecutionResult::Revert { output, .. } => output.clone(),
+            ExecutionResult::Halt { .. } => Bytes::new(),
+        };
+        let trace = TransactionTrace {
+            block_number: block.number.saturating_to(),
+            block_timestamp: block.timestamp.saturating_to(),
+            caller: tx_env.caller.into_array().into(),
+            to: match tx_env.transact_to {
+                TransactTo::Call(to) => Some(to.into_array().into()),
+                TransactTo::Create(_) => None,
+            },
+            input: tx_env.data.to_vec().into(),
+            value: eU256::from_little_endian(tx_env.value.as_le_slice()),
+            success: result.is_success(),
+            gas_used: result.gas_used(),
+            output: output.to_vec().into(),
+        };
+        self.inner.transactions.lock().unwrap().push(trace);
+    }
+
+    /// Returns every transaction that has been recorded so far in the order
+    /// they were executed.
+    pub fn transactions(&self) -> Vec<TransactionTrace> {
+        self.inner.transactions.lock().unwrap().clone()
+    }
+}
+
+/// How a frame of a [`CallTrace`] was entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CallKind {
+    /// A `CALL`, or the call of a transaction.
+    Call,
+
+    /// A `STATICCALL`.
+    StaticCall,
+
+    /// A `DELEGATECALL`.
+    DelegateCall,
+
+    /// A `CALLCODE`.
+    CallCode,
+
+    /// A `CREATE`, or the deployment of a transaction.
+    Create,
+
+    /// A `CREATE2`.
+    Create2,
+}
+
+/// A frame of a call as it was executed, along with every call it made in
+/// turn.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallTrace {
+    /// How the frame was entered.
+    pub kind: CallKind,
+
+    /// The account that made the call.
+    pub from: eAddress,
+
+    /// The account whose code was executed, or the deployed contract for a
+    /// creation, which is zero if the creation failed.
+    pub to: eAddress,
+
+    /// The value sent with the call.
+    pub value: eU256,
+
+    /// The calldata of the call, or the init code of a creation.
+    pub input: eBytes,
+
+    /// The data returned by the call or its revert data.
+    pub output: eBytes,
+
+    /// The amount of gas the frame spent, including the calls it made.
+    pub gas_used: u64,
+
+    /// Whether the frame succeeded.
+    pub success: bool,
+
+    /// The logs emitted by the frame itself, in the order they were
+    /// emitted. The logs of a frame that did not succeed are discarded by
+    /// the EVM.
+    pub logs: Vec<eLog>,
+
+    /// The calls the frame made, in the order it made them.
+    pub calls: Vec<CallTrace>,
+}
+
+/// An [`Inspector`] that records the [`CallTrace`] of what it executes.
+#[derive(Clone, Debug, Default)]
+pub struct CallTracer {
+    /// The frames that are being executed, starting with the outermost one.
+    stack: Vec<CallTrace>,
+
+    /// The outermost frame once it has finished.
+    root: Option<CallTrace>,
+}
+
+impl CallTracer {
+    /// Returns the trace of the outermost frame, if it has finished.
+    pub fn into_trace(self) -> Option<CallTrace> {
+        self.root
+    }
+
+    fn enter(&mut self, kind: CallKind, from: Address, to: Address, value: U256, input: &Bytes) {
+        self.stack.push(CallTrace {
+            kind,
+            from: from.to_ethers(),
+            to: to.to_ethers(),
+            value: value.to_ethers(),
+            input: input.clone().to_ethers(),
+            output: eBytes::new(),
+            gas_used: 0,
+            success: false,
+            logs: Vec::new(),
+            calls: Vec::new(),
+        });
+    }
+
+    fn exit(&mut self, output: &Bytes, gas_used: u64, success: bool, created: Option<Address>) {
+        let Some(mut frame) = self.stack.pop() else {
+            return;
+        };
+        frame.output = output.clone().to_ethers();
+        frame.gas_used = gas_used;
+        frame.success = success;
+        if let Some(created) = created {
+            frame.to = created.to_ethers();
+        }
+        match self.stack.last_mut() {
+            Some(parent) => parent.calls.push(frame),
+            None => self.root = Some(frame),
+        }
+    }
+}
+
+impl<DB: Database> Inspector<DB> for CallTracer {
+    fn log(&mut self, _context: &mut EvmContext<DB>, log: &Log) {
+        if let Some(frame) = self.stack.last_mut() {
+            frame.logs.push(log.clone().to_ethers());
+        }
+    }
+
+    fn call(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &mut CallInputs,
+    ) -> Option<CallOutcome> {
+        let kind = match inputs.context.scheme {
+            CallScheme::Call => CallKind::Call,
+            CallScheme::StaticCall => CallKind::StaticCall,
+            CallScheme::DelegateCall => CallKind::DelegateCall,
+            CallScheme::CallCode => CallKind::CallCode,
+        };
+        self.enter(
+            kind,
+            inputs.context.caller,
+            inputs.contract,
+            inputs.context.apparent_value,
+            &inputs.input,
+        );
+        None
+    }
+
+    fn call_end(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        _inputs: &CallInputs,
+        outcome: CallOutcome,
+    ) -> CallOutcome {
+        self.exit(
+            &outcome.result.output,
+            outcome.result.gas.spent(),
+            outcome.result.is_ok(),
+            None,
+        );
+        outcome
+    }
+
+    fn create(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        inputs: &mut CreateInputs,
+    ) -> Option<CreateOutcome> {
+        let kind = match inputs.scheme {
+            CreateScheme::Create => CallKind::Create,
+            CreateScheme::Create2 { .. } => CallKind::Create2,
+        };
+        self.enter(
+            kind,
+            inputs.caller,
+            Address::ZERO,
+            inputs.value,
+            &inputs.init_code,
+        );
+        None
+    }
+
+    fn create_end(
+        &mut self,
+        _context: &mut EvmContext<DB>,
+        _inputs: &CreateInputs,
+        outcome: CreateOutcome,
+    ) -> CreateOutcome {
+        self.exit(
+            &outcome.result.output,
+            outcome.result.gas.spent(),
+            outcome.result.is_ok(),
+            Some(outcome.address.unwrap_or_default()),
+        );
+        outcome
+    }
+}
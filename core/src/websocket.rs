@@ -0,0 +1,124 @@
+//! The [`websocket`](self) module holds the HTTP server that the
+//! [`RpcServer`](crate::rpc::RpcServer) and the remote agents of
+//! `arbiter-engine` are served on.
+//!
+//! [`serve`] accepts the connections of a listener and answers their requests
+//! with a handler, which hands the requests that ask for a WebSocket
+//! connection to [`upgrade`]:
+//! ```ignore
+//! let listener = TcpListener::bind("127.0.0.1:9000").await?;
+//! let handle = websocket::serve(listener, "echo", |mut request| async move {
+//!     websocket::upgrade(&mut request, |websocket| async move {
+//!         let (sink, stream) = websocket.split();
+//!         let _ = stream.forward(sink).await;
+//!     })
+//! });
+//! ```
+
+use std::{convert::Infallible as NeverError, future::Future};
+
+use hyper::{
+    header::{self, HeaderValue},
+    server::conn::Http,
+    service::service_fn,
+    upgrade::Upgraded,
+    Body, Request, Response, StatusCode,
+};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role},
+    WebSocketStream,
+};
+
+use super::*;
+
+/// Accepts connections on `listener` and answers each of their requests with
+/// `handler` on the Tokio runtime it is called on, until the returned task is
+/// aborted. The connections are called `name` in the logs, e.g.,
+/// `"JSON-RPC"`.
+pub fn serve<H, F>(listener: TcpListener, name: &'static str, handler: H) -> JoinHandle<()>
+where
+    H: Fn(Request<Body>) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = Response<Body>> + Send + 'static,
+{
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a {} connection: {}", name, e);
+                    continue;
+                }
+            };
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let response = handler(request);
+                    async move { Ok::<_, NeverError>(response.await) }
+                });
+                if let Err(e) = Http::new()
+                    .http1_only(true)
+                    .serve_connection(stream, service)
+                    .with_upgrades()
+                    .await
+                {
+                    debug!("{} connection closed: {}", name, e);
+                }
+            });
+        }
+    })
+}
+
+/// Returns `true` if `request` asks to be upgraded to a WebSocket connection.
+pub fn is_upgrade(request: &Request<Body>) -> bool {
+    request
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|upgrade| upgrade.to_str().ok())
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+}
+
+/// Upgrades `request` to a WebSocket connection, which is handed to `serve`
+/// on a new task once the handshake completes, and returns the `101` response
+/// that completes it, or a `400` if the request has no `Sec-WebSocket-Key`.
+pub fn upgrade<S, F>(request: &mut Request<Body>, serve: S) -> Response<Body>
+where
+    S: FnOnce(WebSocketStream<Upgraded>) -> F + Send + 'static,
+    F: Future<Output = ()> + Send,
+{
+    let Some(key) = request
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .map(|key| derive_accept_key(key.as_bytes()))
+    else {
+        return respond(
+            StatusCode::BAD_REQUEST,
+            "A WebSocket upgrade needs a `Sec-WebSocket-Key` header.",
+        );
+    };
+    let upgrade = hyper::upgrade::on(request);
+    tokio::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => {
+                serve(WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await).await
+            }
+            Err(e) => warn!("Failed to upgrade a WebSocket connection: {}", e),
+        }
+    });
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    let headers = response.headers_mut();
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    if let Ok(key) = HeaderValue::from_str(&key) {
+        headers.insert(header::SEC_WEBSOCKET_ACCEPT, key);
+    }
+    response
+}
+
+/// Returns a response with the `status` whose body is the `reason`.
+pub fn respond(status: StatusCode, reason: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(reason.into());
+    *response.status_mut() = status;
+    response
+}
@@ -1,10 +1,14 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Barrier};
 
 use arbiter_bindings::bindings::{self, weth::weth};
-use arbiter_core::database::fork::Fork;
+use arbiter_core::{
+    database::{backend::StateBackend, bloom::bloom_matches, disk::DiskDB, fork::Fork},
+    environment::instruction::{BlockFees, BlockSettings, Cheatcodes, CheatcodesReturn},
+    settlement::{self, L1DataFee},
+};
 use ethers::{
     prelude::Middleware,
-    types::{Address, U256 as eU256, U64},
+    types::{Address, Bytes, TransactionRequest, U256 as eU256, U64},
 };
 include!("common.rs");
 
@@ -113,6 +117,52 @@ async fn fork_into_arbiter() {
     assert_eq!(eth_balance, eU256::from(934034962177715175765_u128));
 }
 
+#[tokio::test]
+async fn fork_on_disk() {
+    let fork = Fork::from_disk("tests/fork.json").unwrap();
+    let path = std::env::temp_dir().join(format!("arbiter_fork_{}.db", std::process::id()));
+    let disk_db = DiskDB::write(&path, &fork.db).unwrap();
+    assert_eq!(disk_db.accounts(), fork.db.accounts.len());
+
+    // The forked state is only read from disk, nothing is loaded up front.
+    let environment = Environment::builder().with_backend(disk_db).build();
+    let client = ArbiterMiddleware::new(&environment, Some("name")).unwrap();
+
+    let weth_meta = fork.contracts_meta.get("weth").unwrap();
+    let weth = weth::WETH::new(weth_meta.address, client.clone());
+    let address_to_check_balance =
+        Address::from_str(&weth_meta.mappings.get("balanceOf").unwrap()[0]).unwrap();
+    let balance = weth
+        .balance_of(address_to_check_balance)
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(balance, eU256::from(34890707020710109111_u128));
+
+    let eoa = fork.eoa.get("vitalik").unwrap();
+    let eth_balance = client.get_balance(*eoa, None).await.unwrap();
+    assert_eq!(eth_balance, eU256::from(934034962177715175765_u128));
+
+    // Writes stay in memory and never reach the file.
+    let arbx = deploy_arbx(client.clone()).await;
+    arbx.mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let arbx_address = revm::primitives::Address::from(arbx.address().to_fixed_bytes());
+    let db = environment.stop().unwrap();
+    assert!(db
+        .state
+        .read()
+        .unwrap()
+        .accounts
+        .contains_key(&arbx_address));
+    assert!(DiskDB::open(&path).unwrap().basic(arbx_address).is_none());
+    std::fs::remove_file(path).unwrap();
+}
+
 #[tokio::test]
 async fn middleware_from_forked_eo() {
     let fork = Fork::from_disk("tests/fork.json").unwrap();
@@ -187,3 +237,472 @@ async fn block_logs() {
         1
     );
 }
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn parallel_execution_matches_serial() {
+    let environment = Environment::builder().with_parallel_execution().build();
+    let admin = ArbiterMiddleware::new(&environment, Some("admin")).unwrap();
+    let counter = bindings::counter::Counter::deploy(admin, ())
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+
+    let mut tasks = vec![];
+    for i in 0..8 {
+        let client = ArbiterMiddleware::new(&environment, Some(&format!("client_{}", i))).unwrap();
+        let counter = bindings::counter::Counter::new(counter.address(), client.clone());
+        tasks.push(tokio::spawn(async move {
+            // Writes to independent state.
+            let own_token = deploy_arby(client.clone()).await;
+            own_token
+                .mint(client.address(), eU256::from(TEST_MINT_AMOUNT))
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+            // Writes to shared state so the transactions conflict.
+            counter.increment().send().await.unwrap().await.unwrap();
+            (client.address(), own_token)
+        }));
+    }
+
+    for task in tasks {
+        let (address, own_token) = task.await.unwrap();
+        assert_eq!(
+            own_token.balance_of(address).call().await.unwrap(),
+            eU256::from(TEST_MINT_AMOUNT)
+        );
+        assert_eq!(
+            own_token.total_supply().call().await.unwrap(),
+            eU256::from(TEST_MINT_AMOUNT)
+        );
+    }
+    assert_eq!(counter.number().call().await.unwrap(), eU256::from(8));
+}
+
+#[tokio::test]
+async fn parallel_coinbase_reads_match_serial() {
+    let serial = stored_coinbase_balance(false).await;
+    assert!(serial > eU256::zero());
+    assert_eq!(stored_coinbase_balance(true).await, serial);
+}
+
+/// Batches a transfer that pays a fee to the block beneficiary with a call to
+/// a contract that stores `block.coinbase.balance`, and returns what the
+/// contract stored.
+async fn stored_coinbase_balance(parallel: bool) -> eU256 {
+    let coinbase = Address::from_low_u64_be(0xc0ffee);
+    let mut builder = Environment::builder()
+        .with_pay_gas()
+        .with_transaction_gas_limit(10_000_000)
+        .with_coinbase(coinbase.to_fixed_bytes().into());
+    if parallel {
+        builder = builder.with_parallel_execution();
+    }
+    let environment = builder.build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    client.set_gas_price(eU256::from(15)).await.unwrap();
+    let payer = Address::from_low_u64_be(1);
+    let reader = Address::from_low_u64_be(2);
+    for account in [client.address(), payer, reader] {
+        client
+            .set_balance(account, parse_ether(1).unwrap())
+            .await
+            .unwrap();
+    }
+
+    // `SSTORE(0, BALANCE(COINBASE))` behind the code that deploys it.
+    let deployment = TransactionRequest::new()
+        .data(Bytes::from_str("0x6006600c60003960066000f3413160005500").unwrap());
+    let probe = client
+        .send_transaction(deployment, None)
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap()
+        .contract_address
+        .unwrap();
+
+    // The transfer and the call are held back so they are executed together.
+    environment.pause().unwrap();
+    let transfer = TransactionRequest::new()
+        .to(Address::zero())
+        .value(1)
+        .data(Bytes::new());
+    let call = TransactionRequest::new().to(probe).data(Bytes::new());
+    let mut sent = vec![];
+    for (index, (sender, request)) in [(payer, transfer), (reader, call)].into_iter().enumerate() {
+        let client = client.impersonate(sender);
+        sent.push(std::thread::spawn(move || {
+            futures::executor::block_on(async {
+                client
+                    .send_transaction(request, None)
+                    .await
+                    .unwrap()
+                    .await
+                    .unwrap();
+            })
+        }));
+        wait_until_held(&environment, index + 1);
+    }
+    environment.resume().unwrap();
+    for sent in sent {
+        sent.join().unwrap();
+    }
+
+    let stored = client
+        .get_storage_at(probe, ethers::types::H256::zero(), None)
+        .await
+        .unwrap();
+    eU256::from_big_endian(stored.as_bytes())
+}
+
+#[tokio::test]
+async fn coverage_is_collected() {
+    let environment = Environment::builder().with_coverage().build();
+    let client = ArbiterMiddleware::new(&environment, Some("coverage")).unwrap();
+    let counter = bindings::counter::Counter::deploy(client.clone(), ())
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    counter.increment().send().await.unwrap().await.unwrap();
+
+    let code_hash = match client
+        .apply_cheatcode(Cheatcodes::Access {
+            address: counter.address(),
+        })
+        .await
+        .unwrap()
+    {
+        CheatcodesReturn::Access { info, .. } => info.code_hash,
+        _ => unreachable!(),
+    };
+    let coverage = client.coverage().await.unwrap();
+    let runtime = coverage.contracts.get(&code_hash).unwrap();
+    let (executed, total) = runtime.instructions_hit();
+    assert!(executed > 0 && executed < total);
+    let (branches, total_branches) = runtime.branches_hit();
+    assert!(branches > 0 && branches < total_branches);
+
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, None).unwrap();
+    assert!(client.coverage().await.is_err());
+}
+
+#[tokio::test]
+async fn block_blooms() {
+    let (_environment, client) = startup();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let receipt = arbiter_token
+        .approve(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let filter = arbiter_token.approval_filter().filter;
+    assert!(bloom_matches(&receipt.logs_bloom, &filter));
+
+    let block_data = client.update_block(1, 1).unwrap();
+    assert_eq!(block_data.logs_bloom, receipt.logs_bloom);
+    assert!(bloom_matches(&block_data.logs_bloom, &filter));
+    assert!(!bloom_matches(
+        &block_data.logs_bloom,
+        &filter.address(Address::zero())
+    ));
+
+    // The new block has no logs yet.
+    let block_data = client.update_block(2, 2).unwrap();
+    assert!(!bloom_matches(
+        &block_data.logs_bloom,
+        &arbiter_token.approval_filter().filter
+    ));
+}
+
+#[tokio::test]
+async fn pause_and_resume() {
+    let (environment, client) = startup();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let address = client.address();
+
+    environment.pause().unwrap();
+
+    // The mint is sent from another thread since it blocks until it is executed.
+    // It uses its own connection so that its outcome is not mixed up with the
+    // calls made in the mean time.
+    let token = ArbiterToken::new(arbiter_token.address(), client.impersonate(address));
+    let mint = std::thread::spawn(move || {
+        futures::executor::block_on(async {
+            token
+                .mint(address, eU256::from(TEST_MINT_AMOUNT))
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+        })
+    });
+    wait_until_held(&environment, 1);
+    assert!(!mint.is_finished());
+
+    // The state can still be inspected and modified while paused.
+    assert_eq!(
+        arbiter_token.balance_of(address).call().await.unwrap(),
+        eU256::zero()
+    );
+    client.set_balance(address, eU256::from(1)).await.unwrap();
+    assert_eq!(
+        client.get_balance(address, None).await.unwrap(),
+        eU256::from(1)
+    );
+
+    environment.resume().unwrap();
+    assert!(mint.join().unwrap().is_some());
+    assert_eq!(
+        arbiter_token.balance_of(address).call().await.unwrap(),
+        eU256::from(TEST_MINT_AMOUNT)
+    );
+
+    // Stopping a paused environment still executes what was held back.
+    environment.pause().unwrap();
+    let token = ArbiterToken::new(arbiter_token.address(), client.impersonate(address));
+    let mint = std::thread::spawn(move || {
+        futures::executor::block_on(async {
+            token
+                .mint(address, eU256::from(TEST_MINT_AMOUNT))
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap()
+        })
+    });
+    wait_until_held(&environment, 1);
+    let db = environment.stop().unwrap();
+    assert!(mint.join().unwrap().is_some());
+    assert!(!db.state.read().unwrap().accounts.is_empty());
+}
+
+/// Waits until the paused `environment` holds back `count` instructions.
+fn wait_until_held(environment: &Environment, count: usize) {
+    while environment.held_instructions() < count {
+        std::thread::yield_now();
+    }
+}
+
+/// Sends a transfer from `sender` from another thread, as the transfer
+/// blocks until it is executed, once every thread waiting on `start` is ready.
+fn transfer_in_background(
+    client: &ArbiterMiddleware,
+    sender: Address,
+    start: Arc<Barrier>,
+) -> std::thread::JoinHandle<()> {
+    let client = client.impersonate(sender);
+    std::thread::spawn(move || {
+        start.wait();
+        futures::executor::block_on(async {
+            let transfer = TransactionRequest::new()
+                .to(Address::zero())
+                .value(1)
+                .data(Bytes::new());
+            client
+                .send_transaction(transfer, None)
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+        })
+    })
+}
+
+#[tokio::test]
+async fn resume_in_order_with_parallel_execution() {
+    const HELD: usize = 32;
+    const LATER: usize = 16;
+    let environment = Environment::builder()
+        .with_parallel_execution()
+        .with_tracing()
+        .build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let senders: Vec<_> = (1..=(HELD + LATER) as u64)
+        .map(Address::from_low_u64_be)
+        .collect();
+    for sender in &senders {
+        client.set_balance(*sender, eU256::from(1)).await.unwrap();
+    }
+
+    // The first transfers are held back one after the other, so their order
+    // is the order they were sent in.
+    environment.pause().unwrap();
+    let mut transfers = vec![];
+    for (index, sender) in senders[..HELD].iter().enumerate() {
+        transfers.push(transfer_in_background(
+            &client,
+            *sender,
+            Arc::new(Barrier::new(1)),
+        ));
+        wait_until_held(&environment, index + 1);
+    }
+
+    // The later transfers are all sent as the held back ones are resumed, and
+    // must not be batched ahead of them.
+    let start = Arc::new(Barrier::new(LATER + 1));
+    for sender in &senders[HELD..] {
+        transfers.push(transfer_in_background(&client, *sender, start.clone()));
+    }
+    environment.resume().unwrap();
+    start.wait();
+    for transfer in transfers {
+        transfer.join().unwrap();
+    }
+
+    let executed: Vec<_> = environment
+        .tracer()
+        .transactions()
+        .into_iter()
+        .map(|transaction| transaction.caller)
+        .collect();
+    assert_eq!(executed.len(), senders.len());
+    assert_eq!(executed[..HELD], senders[..HELD]);
+}
+
+#[tokio::test]
+async fn fee_routing() {
+    let vault = Address::from_low_u64_be(0xfee);
+    let builder = Address::from_low_u64_be(0xb1d);
+    let environment = Environment::builder()
+        .with_base_fee(revm::primitives::U256::from(10))
+        .with_base_fee_recipient(vault.to_fixed_bytes().into())
+        .with_transaction_gas_limit(10_000_000)
+        .build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    client
+        .set_balance(client.address(), parse_ether(1).unwrap())
+        .await
+        .unwrap();
+    client.set_gas_price(eU256::from(15)).await.unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+
+    let block_data = client
+        .update_block_with(
+            1,
+            1,
+            BlockSettings {
+                coinbase: Some(builder),
+                base_fee: None,
+            },
+        )
+        .unwrap();
+    let deploy_fees = block_data.fees.unwrap();
+    assert_eq!(deploy_fees.coinbase, Address::zero());
+    assert_eq!(deploy_fees.base_fee, eU256::from(10));
+
+    let receipt = arbiter_token
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let gas_used = receipt.gas_used.unwrap();
+
+    let fees = client.update_block(2, 2).unwrap().fees.unwrap();
+    assert_eq!(
+        fees,
+        BlockFees {
+            coinbase: builder,
+            base_fee: eU256::from(10),
+            priority_fees: gas_used * 5,
+            base_fees: gas_used * 10,
+            settlement_fees: eU256::zero(),
+        }
+    );
+    // The coinbase is credited with the priority fees and the vault with the
+    // base fees of both blocks instead of burning them.
+    assert_eq!(
+        client.get_balance(builder, None).await.unwrap(),
+        fees.priority_fees
+    );
+    assert_eq!(
+        client.get_balance(vault, None).await.unwrap(),
+        deploy_fees.base_fees + fees.base_fees
+    );
+    assert_eq!(
+        client.get_balance(client.address(), None).await.unwrap(),
+        parse_ether(1).unwrap()
+            - (deploy_fees.base_fees + deploy_fees.priority_fees)
+            - gas_used * 15
+    );
+}
+
+#[tokio::test]
+async fn settlement_fees() {
+    let vault = Address::from_low_u64_be(0x5e7);
+    let model = L1DataFee::new(revm::primitives::U256::from(1_000));
+    let environment = Environment::builder()
+        .with_settlement_model(model.clone())
+        .with_settlement_fee_recipient(vault.to_fixed_bytes().into())
+        .build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    client
+        .set_balance(client.address(), parse_ether(1).unwrap())
+        .await
+        .unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    client.update_block(1, 1).unwrap();
+
+    let mint = arbiter_token.mint(client.address(), eU256::from(1));
+    let expected = eU256::from(model.l1_gas(&mint.calldata().unwrap()) * 1_000);
+    let balance = client.get_balance(client.address(), None).await.unwrap();
+    let receipt = mint.send().await.unwrap().await.unwrap().unwrap();
+    assert_eq!(
+        receipt
+            .other
+            .get_deserialized::<eU256>(settlement::RECEIPT_FIELD)
+            .unwrap()
+            .unwrap(),
+        expected
+    );
+    assert_eq!(
+        client.get_balance(client.address(), None).await.unwrap(),
+        balance - expected
+    );
+    assert_eq!(
+        client
+            .update_block(2, 2)
+            .unwrap()
+            .fees
+            .unwrap()
+            .settlement_fees,
+        expected
+    );
+
+    // A sender that cannot pay the fee has its transaction rejected.
+    client
+        .set_balance(client.address(), expected - 1)
+        .await
+        .unwrap();
+    assert!(arbiter_token
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .is_err());
+    assert_eq!(
+        client.get_balance(client.address(), None).await.unwrap(),
+        expected - 1
+    );
+    assert_eq!(
+        arbiter_token
+            .balance_of(client.address())
+            .call()
+            .await
+            .unwrap(),
+        eU256::from(1)
+    );
+}
@@ -2,7 +2,7 @@ use std::path::Path;
 
 use arbiter_core::{
     errors::ArbiterCoreError,
-    events::{Logger, OutputFileType},
+    events::{metrics::Metric, Logger, OutputFileType},
 };
 use ethers::types::U256 as eU256;
 use serde::Serialize;
@@ -98,3 +98,51 @@ async fn data_capture() {
     assert!(Path::new("./data/output.json").exists());
     std::fs::remove_dir_all("./data").unwrap();
 }
+
+#[tokio::test]
+async fn data_capture_with_metrics() {
+    let (env, client) = startup();
+    let (_arbx, _arby, lex) = deploy_liquid_exchange(client.clone()).await;
+
+    #[derive(serde::Deserialize)]
+    struct Config {
+        metrics: Vec<Metric>,
+    }
+    let config: Config = serde_json::from_str(
+        r#"{ "metrics": ["price = lex.price / 10^18", "double_price = 2 * price"] }"#,
+    )
+    .unwrap();
+
+    let logger_task = Logger::builder()
+        .with_event(lex.events(), "lex")
+        .with_metrics(config.metrics)
+        .directory("./metrics_data")
+        .run()
+        .unwrap();
+
+    for block in 1..=3u64 {
+        client.update_block(block, block).unwrap();
+        lex.set_price(eU256::from(block) * eU256::from(10u128.pow(18)))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    let _ = env.stop();
+    logger_task.await.unwrap();
+
+    let output: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./metrics_data/output.json").unwrap())
+            .unwrap();
+    std::fs::remove_dir_all("./metrics_data").unwrap();
+    assert_eq!(
+        output["metrics"]["price"],
+        serde_json::json!([[1, 1.0], [2, 2.0], [3, 3.0]])
+    );
+    assert_eq!(
+        output["metrics"]["double_price"],
+        serde_json::json!([[1, 2.0], [2, 4.0], [3, 6.0]])
+    );
+}
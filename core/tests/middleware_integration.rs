@@ -1,16 +1,26 @@
 use std::str::FromStr;
 
-use arbiter_bindings::bindings::arbiter_token::ApprovalFilter;
+use arbiter_bindings::bindings::{
+    arbiter_token::{ApprovalFilter, ARBITERTOKEN_ABI, ARBITERTOKEN_BYTECODE},
+    counter::{COUNTER_ABI, COUNTER_BYTECODE},
+};
 use arbiter_core::{
-    environment::instruction::{Cheatcodes, CheatcodesReturn},
-    middleware::nonce_middleware::NonceManagerMiddleware,
+    environment::{
+        delivery::{DeliveryPolicy, EVENT_CHANNEL_CAPACITY},
+        instruction::{Cheatcodes, CheatcodesReturn},
+        MiningMode,
+    },
+    errors::ArbiterCoreError,
+    middleware::{artifact::ArtifactSource, nonce_middleware::NonceManagerMiddleware},
+    state_diff::{Change, StateDiff},
 };
 use ethers::{
-    prelude::{EthLogDecode, Middleware},
+    prelude::{ContractError, EthLogDecode, Middleware},
     providers::ProviderError,
+    signers::Signer,
     types::{
-        transaction::eip2718::TypedTransaction, Address as eAddress, Bytes as eBytes, Filter, Log,
-        ValueOrArray, H256, U256 as eU256,
+        transaction::eip2718::TypedTransaction, Address as eAddress, BlockNumber, Bytes as eBytes,
+        Filter, Log, ValueOrArray, H256, U256 as eU256, U64,
     },
 };
 use futures::StreamExt;
@@ -302,6 +312,99 @@ async fn deal_missing_account() {
     assert!(balance.is_err());
 }
 
+#[tokio::test]
+async fn balance_and_nonce_helpers() {
+    let (_environment, client) = startup();
+    client.set_balance(client.address(), 10).await.unwrap();
+    client.add_balance(client.address(), 5).await.unwrap();
+    let balance = client.get_balance(client.address(), None).await.unwrap();
+    assert_eq!(balance, 15.into());
+
+    // Both setters create the account when it does not exist yet.
+    let fresh = eAddress::random();
+    client.set_balance(fresh, 1).await.unwrap();
+    client.set_nonce(fresh, 7).await.unwrap();
+    let account = client.get_account(fresh).await.unwrap();
+    assert_eq!(account.balance, revm::primitives::U256::from(1));
+    assert_eq!(account.nonce, 7);
+    let nonce = client.get_transaction_count(fresh, None).await.unwrap();
+    assert_eq!(nonce, 7.into());
+}
+
+#[tokio::test]
+async fn impersonate() {
+    let (environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+
+    // Only the admin may mint, so another client has to impersonate it.
+    let other = ArbiterMiddleware::new(&environment, Some("other")).unwrap();
+    let impersonated = other.impersonate(client.address());
+    assert_eq!(impersonated.address(), client.address());
+    assert!(impersonated.sign_message("hello").await.is_err());
+
+    ArbiterToken::new(arbx.address(), impersonated)
+        .mint(other.address(), eU256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let balance = arbx.balance_of(other.address()).call().await.unwrap();
+    assert_eq!(balance, eU256::from(TEST_MINT_AMOUNT));
+}
+
+#[tokio::test]
+async fn multiple_accounts() {
+    let (environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let inventory = client.add_account(Some("inventory")).unwrap();
+    assert_eq!(client.accounts(), vec![client.address(), inventory]);
+    arbx.mint(inventory, eU256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // Send from the inventory wallet through a client bound to it.
+    let sender = client.with_sender(inventory).unwrap();
+    assert_eq!(sender.address(), inventory);
+    assert_eq!(sender.accounts(), client.accounts());
+    let receipt = ArbiterToken::new(arbx.address(), sender)
+        .transfer(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.from, inventory);
+
+    // Select the sender of a single call instead.
+    arbx.transfer(client.address(), eU256::from(2))
+        .from(inventory)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let balance = arbx.balance_of(client.address()).call().await.unwrap();
+    assert_eq!(balance, eU256::from(3));
+
+    // Accounts of other clients cannot be used.
+    let other = ArbiterMiddleware::new(&environment, Some("other")).unwrap();
+    assert!(matches!(
+        client.with_sender(other.address()),
+        Err(ArbiterCoreError::AccountDoesNotExistError)
+    ));
+    assert!(arbx
+        .transfer(client.address(), eU256::from(1))
+        .from(other.address())
+        .send()
+        .await
+        .is_err());
+}
+
 #[tokio::test]
 async fn set_gas_price() {
     let (_environment, client) = startup();
@@ -422,6 +525,51 @@ async fn send_nonce_middleware() {
     assert_eq!(receipt.to, None);
 }
 
+#[tokio::test]
+async fn automatic_nonces() {
+    let (environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 1);
+
+    // Fire several transactions at once without awaiting each receipt.
+    let mints: Vec<_> = (0..3)
+        .map(|_| arbx.mint(client.address(), eU256::from(1)))
+        .collect();
+    let pending = futures::future::join_all(mints.iter().map(|mint| mint.send())).await;
+    let mut hashes = vec![];
+    for pending in pending {
+        let receipt = pending.unwrap().await.unwrap().unwrap();
+        hashes.push(receipt.transaction_hash);
+    }
+    hashes.dedup();
+    assert_eq!(hashes.len(), 3);
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 4);
+
+    // A transaction with the wrong nonce is rejected and does not use one up.
+    assert!(arbx
+        .mint(client.address(), eU256::from(1))
+        .nonce(10)
+        .send()
+        .await
+        .is_err());
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 4);
+
+    // A nonce changed by another client is read again.
+    let other = ArbiterMiddleware::new(&environment, Some("other")).unwrap();
+    other.set_nonce(client.address(), 7).await.unwrap();
+    arbx.mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 8);
+
+    other.set_nonce(client.address(), 9).await.unwrap();
+    client.reset_nonce(client.address());
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 9);
+}
+
 #[tokio::test]
 async fn test_cheatcodes_store() {
     let (_environment, client) = startup();
@@ -504,6 +652,158 @@ async fn pubsubclient() {
     assert_eq!(items.len(), 4);
 }
 
+#[tokio::test]
+async fn lagging_subscribers() {
+    let (environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let filter = arbx.events().filter;
+
+    let dropping = client.subscribe_logs(&filter).await.unwrap();
+    client.set_delivery_policy(DeliveryPolicy::BoundedWithError);
+    let erroring = client.subscribe_logs(&filter).await.unwrap();
+    client.set_delivery_policy(DeliveryPolicy::Unbounded);
+    let unbounded = client.subscribe_logs(&filter).await.unwrap();
+
+    // None of the subscribers are read until every event has been emitted.
+    let transactions = EVENT_CHANNEL_CAPACITY + 8;
+    for _ in 0..transactions {
+        arbx.approve(client.address(), eU256::from(1))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    environment.stop().unwrap();
+
+    let unbounded: Vec<Log> = unbounded.collect().await;
+    assert_eq!(unbounded.len(), transactions);
+    let erroring: Vec<Log> = erroring.collect().await;
+    assert!(erroring.is_empty());
+    let dropping: Vec<Log> = dropping.collect().await;
+    assert_eq!(dropping.len(), EVENT_CHANNEL_CAPACITY - 1);
+
+    // Along with the stop signal there were 9 more broadcasts than the channel
+    // holds, so each lossy subscriber missed the 9 oldest ones.
+    assert_eq!(client.lagged_events(), 2 * 9);
+}
+
+#[tokio::test]
+async fn deploy_artifact() {
+    let (_environment, client) = startup();
+    let args = (
+        ARBITER_TOKEN_X_NAME.to_string(),
+        ARBITER_TOKEN_X_SYMBOL.to_string(),
+        ARBITER_TOKEN_X_DECIMALS,
+    );
+
+    // A Foundry style artifact given as bytes.
+    let foundry = serde_json::json!({
+        "abi": *ARBITERTOKEN_ABI,
+        "bytecode": { "object": ARBITERTOKEN_BYTECODE },
+    });
+    let token = client
+        .deploy_artifact(serde_json::to_vec(&foundry).unwrap(), args.clone())
+        .await
+        .unwrap();
+    let symbol: String = token.method("symbol", ()).unwrap().call().await.unwrap();
+    assert_eq!(symbol, ARBITER_TOKEN_X_SYMBOL);
+
+    // A Hardhat style artifact given as a path.
+    let hardhat = serde_json::json!({
+        "abi": *ARBITERTOKEN_ABI,
+        "bytecode": ARBITERTOKEN_BYTECODE,
+    });
+    let path = std::env::temp_dir().join(format!("arbiter_artifact_{}.json", std::process::id()));
+    std::fs::write(&path, serde_json::to_vec(&hardhat).unwrap()).unwrap();
+    let token = client
+        .deploy_artifact(path.as_path(), args.clone())
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    token
+        .method::<_, bool>("mint", (client.address(), eU256::from(TEST_MINT_AMOUNT)))
+        .unwrap()
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let balance: eU256 = token
+        .method("balanceOf", client.address())
+        .unwrap()
+        .call()
+        .await
+        .unwrap();
+    assert_eq!(balance, eU256::from(TEST_MINT_AMOUNT));
+
+    // An artifact without bytecode, e.g., of an interface, cannot be deployed.
+    let interface = serde_json::json!({ "abi": *ARBITERTOKEN_ABI, "bytecode": "0x" });
+    assert!(matches!(
+        client
+            .deploy_artifact(serde_json::to_vec(&interface).unwrap(), args)
+            .await,
+        Err(ArbiterCoreError::ArtifactError(_))
+    ));
+}
+
+#[tokio::test]
+async fn deploy_combined_json_artifact() {
+    let (_environment, client) = startup();
+    let args = (
+        ARBITER_TOKEN_X_NAME.to_string(),
+        ARBITER_TOKEN_X_SYMBOL.to_string(),
+        ARBITER_TOKEN_X_DECIMALS,
+    );
+
+    // The output of `solc --combined-json abi,bin`, which keys the contracts
+    // by their file and name and leaves out the `0x` of the bytecode.
+    let combined = serde_json::json!({
+        "contracts": {
+            "contracts/ArbiterToken.sol:ArbiterToken": {
+                "abi": *ARBITERTOKEN_ABI,
+                "bin": ARBITERTOKEN_BYTECODE.to_string().trim_start_matches("0x"),
+            },
+            "contracts/Counter.sol:Counter": {
+                "abi": *COUNTER_ABI,
+                "bin": COUNTER_BYTECODE.to_string().trim_start_matches("0x"),
+            },
+        },
+        "version": "0.8.19+commit.7dd6d404.Linux.g++",
+    });
+    let combined = serde_json::to_vec(&combined).unwrap();
+    let token = client
+        .deploy_artifact(
+            ArtifactSource::from(combined.clone()).contract("ArbiterToken"),
+            args.clone(),
+        )
+        .await
+        .unwrap();
+    let symbol: String = token.method("symbol", ()).unwrap().call().await.unwrap();
+    assert_eq!(symbol, ARBITER_TOKEN_X_SYMBOL);
+    let counter = client
+        .deploy_artifact(
+            ArtifactSource::from(combined.clone()).contract("contracts/Counter.sol:Counter"),
+            (),
+        )
+        .await
+        .unwrap();
+    let number: eU256 = counter.method("number", ()).unwrap().call().await.unwrap();
+    assert_eq!(number, eU256::zero());
+
+    // The contract has to be selected when there is more than one, and it
+    // has to be there.
+    for source in [
+        ArtifactSource::from(combined.clone()),
+        ArtifactSource::from(combined).contract("Missing"),
+    ] {
+        assert!(matches!(
+            client.deploy_artifact(source, args.clone()).await,
+            Err(ArbiterCoreError::ArtifactError(_))
+        ));
+    }
+}
+
 #[test]
 fn simulation_signer() {
     let (_, client) = startup();
@@ -621,9 +921,9 @@ async fn stream_with_meta() {
         .unwrap()
         .await
         .unwrap();
-    assert_eq!(format!("{:?}", stream.next().await), "Some(Ok((ApprovalFilter(ApprovalFilter { owner: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, spender: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, amount: 1 }), LogMeta { address: 0x067ea9e44c76a2620f10b39a1b51d5124a299192, block_number: 0, block_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_index: 1, log_index: 0 })))");
-    assert_eq!(format!("{:?}", stream.next().await), "Some(Ok((ApprovalFilter(ApprovalFilter { owner: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, spender: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, amount: 1 }), LogMeta { address: 0x067ea9e44c76a2620f10b39a1b51d5124a299192, block_number: 0, block_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_index: 2, log_index: 0 })))");
-    assert_eq!(format!("{:?}", stream.next().await), "Some(Ok((ApprovalFilter(ApprovalFilter { owner: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, spender: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, amount: 1 }), LogMeta { address: 0x067ea9e44c76a2620f10b39a1b51d5124a299192, block_number: 1, block_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_index: 0, log_index: 0 })))");
+    assert_eq!(format!("{:?}", stream.next().await), "Some(Ok((ApprovalFilter(ApprovalFilter { owner: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, spender: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, amount: 1 }), LogMeta { address: 0x067ea9e44c76a2620f10b39a1b51d5124a299192, block_number: 0, block_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_hash: 0x56af3f12d683cab345df2e6a0a957ff1e55f34d25b06efce31130ff606cdb72c, transaction_index: 1, log_index: 0 })))");
+    assert_eq!(format!("{:?}", stream.next().await), "Some(Ok((ApprovalFilter(ApprovalFilter { owner: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, spender: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, amount: 1 }), LogMeta { address: 0x067ea9e44c76a2620f10b39a1b51d5124a299192, block_number: 0, block_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_hash: 0x0349acbc27c23fcf17bd44a8efec31c512cdd864ec1d47f30fd22a1f0632440f, transaction_index: 2, log_index: 0 })))");
+    assert_eq!(format!("{:?}", stream.next().await), "Some(Ok((ApprovalFilter(ApprovalFilter { owner: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, spender: 0x2efdc9eecfee3a776209fcb8e9a83a6b221d74f5, amount: 1 }), LogMeta { address: 0x067ea9e44c76a2620f10b39a1b51d5124a299192, block_number: 1, block_hash: 0x0000000000000000000000000000000000000000000000000000000000000000, transaction_hash: 0xdfa7dd19f27ebae3ee912204e1fadc33d5cd95b241c61446ae6e54c255c44d99, transaction_index: 0, log_index: 0 })))");
 }
 
 #[tokio::test]
@@ -661,3 +961,1118 @@ async fn get_logs() {
     println!("logs: {:#?}", logs);
     assert_eq!(logs.len(), 3);
 }
+
+#[tokio::test]
+async fn get_logs_by_block_range() {
+    let (_environment, client) = startup();
+
+    let arbx = deploy_arbx(client.clone()).await;
+    for block in 0..3 {
+        client.update_block(block, block).unwrap();
+        for _ in 0..2 {
+            arbx.approve(client.address(), eU256::from(block))
+                .send()
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+        }
+    }
+
+    // Without any bounds, only the logs of the current block are returned.
+    let logs = client.get_logs(&Filter::new()).await.unwrap();
+    assert_eq!(logs.len(), 2);
+    assert!(logs
+        .iter()
+        .all(|log| log.block_number == Some(U64::from(2))));
+
+    // Historical logs are returned in the order they were emitted.
+    let logs = client
+        .get_logs(&Filter::new().from_block(BlockNumber::Earliest))
+        .await
+        .unwrap();
+    assert_eq!(logs.len(), 6);
+    for (index, log) in logs.iter().enumerate() {
+        assert_eq!(log.block_number, Some(U64::from(index / 2)));
+        assert_eq!(log.log_index, Some(eU256::from(index % 2)));
+    }
+
+    let logs = client
+        .get_logs(
+            &arbx
+                .approval_filter()
+                .filter
+                .from_block(1)
+                .to_block(1)
+                .address(arbx.address()),
+        )
+        .await
+        .unwrap();
+    assert_eq!(logs.len(), 2);
+
+    let logs = client
+        .get_logs(&Filter::new().from_block(0).address(client.address()))
+        .await
+        .unwrap();
+    assert!(logs.is_empty());
+
+    assert!(client
+        .get_logs(&Filter::new().at_block_hash(H256::zero()))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn transaction_gas_limit() {
+    let (_environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    client.set_transaction_gas_limit(Some(25_000));
+    assert_eq!(client.transaction_gas_limit(), Some(25_000));
+
+    let mint = arbx.mint(client.address(), eU256::from(1));
+    match mint.send().await.unwrap_err() {
+        ContractError::MiddlewareError {
+            e:
+                ArbiterCoreError::OutOfGas {
+                    gas_limit,
+                    gas_used,
+                },
+        } => {
+            assert_eq!(gas_limit, 25_000);
+            assert!(gas_used > 21_000 && gas_used <= gas_limit);
+        }
+        e => panic!("Expected ArbiterCoreError::OutOfGas, got {:?}", e),
+    }
+
+    // The gas of the transaction itself takes precedence over the client's.
+    mint.clone()
+        .gas(200_000)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.set_transaction_gas_limit(None);
+    mint.send().await.unwrap().await.unwrap();
+    assert_eq!(
+        arbx.balance_of(client.address()).call().await.unwrap(),
+        eU256::from(2)
+    );
+}
+
+#[tokio::test]
+async fn state_diff() {
+    let environment = Environment::builder().with_state_diffs().build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbx = deploy_arbx(client.clone()).await;
+    let to = eAddress::from_str(TEST_MINT_TO).unwrap();
+    let nonce = client
+        .get_transaction_count(client.address(), None)
+        .await
+        .unwrap();
+
+    let receipt = arbx
+        .mint(to, eU256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    let state_diff = StateDiff::from_receipt(&receipt).unwrap();
+
+    // Only the sender's nonce and the token's balance and supply slots change.
+    assert_eq!(state_diff.accounts.keys().copied().collect::<Vec<_>>(), {
+        let mut accounts = vec![client.address(), arbx.address()];
+        accounts.sort();
+        accounts
+    });
+    let sender = state_diff.account(client.address()).unwrap();
+    assert_eq!(
+        sender.nonce,
+        Some(Change {
+            from: nonce.as_u64(),
+            to: nonce.as_u64() + 1
+        })
+    );
+    assert_eq!(sender.balance, None);
+    assert!(sender.storage.is_empty());
+
+    let token = state_diff.account(arbx.address()).unwrap();
+    assert_eq!(token.storage.len(), 2);
+    let minted = H256::from_low_u64_be(TEST_MINT_AMOUNT as u64);
+    for change in token.storage.values() {
+        assert_eq!(
+            *change,
+            Change {
+                from: H256::zero(),
+                to: minted
+            }
+        );
+    }
+
+    // Receipts carry no state diff unless the environment records them.
+    let (_environment, client) = startup();
+    let receipt = deploy_arbx(client.clone())
+        .await
+        .mint(to, eU256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(StateDiff::from_receipt(&receipt), None);
+}
+
+#[tokio::test]
+async fn estimate_gas() {
+    let (_environment, client) = startup();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+
+    let mint = arbiter_token.mint(client.address(), eU256::from(1));
+    let estimate = client.estimate_gas(&mint.tx, None).await.unwrap();
+    let receipt = mint.send().await.unwrap().await.unwrap().unwrap();
+    assert_eq!(estimate, receipt.gas_used.unwrap());
+
+    // Estimating a transaction that reverts returns the revert.
+    let transfer = arbiter_token.transfer(eAddress::from_low_u64_be(1), eU256::from(2));
+    assert!(matches!(
+        client.estimate_gas(&transfer.tx, None).await,
+        Err(ArbiterCoreError::ExecutionRevert { .. })
+    ));
+}
+
+#[tokio::test]
+async fn estimate_gas_with_refunds_and_buffer() {
+    let (_environment, client) = startup();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    arbiter_token
+        .mint(client.address(), eU256::from(10))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // Transferring the whole balance clears its storage slot, which is refunded,
+    // so the transaction needs more gas than it ends up using.
+    let transfer = arbiter_token.transfer(eAddress::from_low_u64_be(1), eU256::from(10));
+    let estimate = client.estimate_gas(&transfer.tx, None).await.unwrap();
+    client.set_gas_estimate_buffer(20);
+    assert_eq!(client.gas_estimate_buffer(), 20);
+    let padded = client.estimate_gas(&transfer.tx, None).await.unwrap();
+    assert_eq!(padded, estimate + estimate * 20 / 100);
+
+    let receipt = transfer
+        .clone()
+        .gas(estimate)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(receipt.gas_used.unwrap() < estimate);
+}
+
+#[tokio::test]
+async fn get_transaction_receipt() {
+    let (_environment, client) = startup();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let receipt = arbiter_token
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(receipt.transaction_hash, H256::zero());
+    assert_eq!(
+        receipt.logs[0].transaction_hash,
+        Some(receipt.transaction_hash)
+    );
+
+    let fetched = client
+        .get_transaction_receipt(receipt.transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(fetched.transaction_hash, receipt.transaction_hash);
+    assert_eq!(fetched.from, client.address());
+    assert_eq!(fetched.to, Some(arbiter_token.address()));
+    assert_eq!(fetched.gas_used, receipt.gas_used);
+    assert_eq!(fetched.status, Some(U64::from(1)));
+    assert_eq!(fetched.logs.len(), receipt.logs.len());
+
+    assert!(client
+        .get_transaction_receipt(H256::repeat_byte(1))
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn get_block() {
+    let (_environment, client) = startup();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let receipt = arbiter_token
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    client.update_block(1, 10).unwrap();
+
+    let latest = client
+        .get_block(BlockNumber::Latest)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(latest.number, Some(U64::from(1)));
+    assert_eq!(latest.timestamp, eU256::from(10));
+    assert!(latest.transactions.is_empty());
+
+    // The first block was ended by the block update and holds both
+    // transactions.
+    let first = client.get_block(0u64).await.unwrap().unwrap();
+    assert_eq!(first.number, Some(U64::from(0)));
+    assert_eq!(first.transactions.len(), 2);
+    assert_eq!(first.transactions[1], receipt.transaction_hash);
+    assert_eq!(first.gas_used, receipt.cumulative_gas_used);
+
+    assert!(client.get_block(5u64).await.unwrap().is_none());
+    assert!(client.get_block(H256::zero()).await.is_err());
+}
+
+#[tokio::test]
+async fn subscribe() {
+    let (environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+
+    // Contract event streams, subscriptions through the provider, and raw
+    // `eth_subscribe` calls all receive the same logs.
+    let events = arbx.approval_filter();
+    let mut event_stream = events.subscribe().await.unwrap();
+    let mut provider_stream = client
+        .provider()
+        .subscribe_logs(&events.filter)
+        .await
+        .unwrap();
+    let mut raw_stream = client
+        .subscribe::<_, Log>(("logs", events.filter.clone()))
+        .await
+        .unwrap();
+    assert!(client.subscribe::<_, Log>(["newHeads"]).await.is_err());
+
+    arbx.approve(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let approval = event_stream.next().await.unwrap().unwrap();
+    assert_eq!(approval.amount, eU256::from(1));
+    let log = provider_stream.next().await.unwrap();
+    assert_eq!(log.address, arbx.address());
+    assert_eq!(raw_stream.next().await.unwrap(), log);
+
+    environment.stop().unwrap();
+    assert!(provider_stream.next().await.is_none());
+}
+
+#[tokio::test]
+async fn watch_same_filter() {
+    let (_environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let filter = arbx.approval_filter().filter;
+
+    // Filters created from the same filter get their own IDs and each receive
+    // every log.
+    let mut first = client.watch(&filter).await.unwrap();
+    let mut second = client.watch(&filter).await.unwrap();
+    assert_ne!(first.id, second.id);
+
+    arbx.approve(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(first.next().await.unwrap(), second.next().await.unwrap());
+}
+
+#[tokio::test]
+async fn multicall() {
+    let (_environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let holders: Vec<eAddress> = (1..=20).map(eAddress::from_low_u64_be).collect();
+    for (amount, holder) in holders.iter().enumerate() {
+        arbx.mint(*holder, eU256::from(amount))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    client.update_block(7, 84).unwrap();
+
+    // Batch the balance of every holder along with the getters of the block.
+    let mut multicall = client.multicall();
+    for holder in &holders {
+        multicall.add_call(arbx.balance_of(*holder), false);
+    }
+    multicall
+        .add_get_eth_balance(client.address(), false)
+        .add_get_block_number();
+    let results = multicall.call_raw().await.unwrap();
+    assert_eq!(results.len(), 22);
+    for (amount, result) in results[..20].iter().enumerate() {
+        assert_eq!(
+            result.clone().unwrap().into_uint().unwrap(),
+            eU256::from(amount)
+        );
+    }
+    let balance = client.get_balance(client.address(), None).await.unwrap();
+    assert_eq!(results[20].clone().unwrap().into_uint().unwrap(), balance);
+    assert_eq!(
+        results[21].clone().unwrap().into_uint().unwrap(),
+        eU256::from(7)
+    );
+
+    // The multicall contract holds no tokens, so a transfer from it reverts.
+    let mut multicall = client.multicall();
+    multicall
+        .add_call(arbx.balance_of(holders[3]), false)
+        .add_call(arbx.transfer(client.address(), eU256::from(1)), true);
+    let results = multicall.call_raw().await.unwrap();
+    assert_eq!(
+        results[0].clone().unwrap().into_uint().unwrap(),
+        eU256::from(3)
+    );
+    assert!(results[1].is_err());
+
+    // A call that is not allowed to fail reverts the batch.
+    multicall.clear_calls();
+    multicall.add_call(arbx.transfer(client.address(), eU256::from(1)), false);
+    assert!(multicall.call_raw().await.is_err());
+}
+
+#[tokio::test]
+async fn pending_transactions_wait_for_their_block() {
+    let environment = Environment::builder()
+        .with_mining_mode(MiningMode::OnBlockUpdate)
+        .build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+
+    // The deployment only resolves once its block has been mined.
+    let (arbx, _) = tokio::join!(deploy_arbx(client.clone()), async {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        client.update_block(1, 12).unwrap();
+    });
+
+    let mint = arbx.mint(client.address(), eU256::from(TEST_MINT_AMOUNT));
+    let pending = mint.send().await.unwrap();
+    let transaction_hash = *pending;
+    tokio::pin!(pending);
+    let waiting = tokio::time::timeout(std::time::Duration::from_millis(50), &mut pending).await;
+    assert!(waiting.is_err());
+    assert!(client
+        .get_transaction_receipt(transaction_hash)
+        .await
+        .unwrap()
+        .is_none());
+
+    client.update_block(2, 24).unwrap();
+    let receipt = pending.await.unwrap().unwrap();
+    assert_eq!(receipt.transaction_hash, transaction_hash);
+    assert_eq!(receipt.block_number, Some(U64::from(1)));
+    assert_eq!(
+        client
+            .get_transaction_receipt(transaction_hash)
+            .await
+            .unwrap(),
+        Some(receipt)
+    );
+}
+
+#[tokio::test]
+async fn pending_transactions_wait_for_confirmations() {
+    let (_environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let mint = arbx.mint(client.address(), eU256::from(TEST_MINT_AMOUNT));
+    let pending = mint.send().await.unwrap().confirmations(3);
+    tokio::pin!(pending);
+
+    // Each block that is ended from the one of the transaction on confirms it.
+    client.update_block(1, 12).unwrap();
+    client.update_block(2, 24).unwrap();
+    let waiting = tokio::time::timeout(std::time::Duration::from_millis(50), &mut pending).await;
+    assert!(waiting.is_err());
+
+    client.update_block(3, 36).unwrap();
+    let receipt = pending.await.unwrap().unwrap();
+    assert_eq!(receipt.block_number, Some(U64::from(0)));
+}
+
+#[tokio::test]
+async fn call_at_past_blocks() {
+    let environment = Environment::builder().with_state_history(2).build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbx = deploy_arbx(client.clone()).await;
+    for block_number in 1..=3 {
+        arbx.mint(client.address(), eU256::from(1))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+        client
+            .update_block(block_number, block_number * 12)
+            .unwrap();
+    }
+    arbx.mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // The last two blocks are kept along with the current one.
+    let balance_of = arbx.balance_of(client.address());
+    let output = client.call_at(&balance_of.tx, 1).await.unwrap();
+    assert_eq!(eU256::from_big_endian(&output), eU256::from(2));
+    let balance = balance_of.clone().block(2).call().await.unwrap();
+    assert_eq!(balance, eU256::from(3));
+    let balance = balance_of.clone().block(3).call().await.unwrap();
+    assert_eq!(balance, eU256::from(4));
+    assert!(matches!(
+        client.call_at(&balance_of.tx, 0).await,
+        Err(ArbiterCoreError::StateUnavailableError(0))
+    ));
+
+    // The call sees the block it is made at.
+    let mut multicall = client.multicall().block(1);
+    multicall.add_get_current_block_timestamp();
+    let (timestamp,): (eU256,) = multicall.call().await.unwrap();
+    assert_eq!(timestamp, eU256::from(12));
+}
+
+#[tokio::test]
+async fn typed_transactions() {
+    use ethers::types::{
+        transaction::eip2930::{AccessList, AccessListItem},
+        Eip1559TransactionRequest, Eip2930TransactionRequest, TransactionRequest,
+    };
+
+    let environment = Environment::builder()
+        .with_base_fee(revm::primitives::U256::from(10))
+        .with_transaction_gas_limit(1_000_000)
+        .build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    client
+        .set_balance(client.address(), parse_ether(1).unwrap())
+        .await
+        .unwrap();
+    client.set_gas_price(eU256::from(15)).await.unwrap();
+    let to = eAddress::from_low_u64_be(1);
+
+    let legacy = TransactionRequest::new()
+        .to(to)
+        .value(1)
+        .data(eBytes::new());
+    let access_list = AccessList(vec![AccessListItem {
+        address: to,
+        storage_keys: vec![H256::zero()],
+    }]);
+    let transactions = [
+        // A legacy transaction pays its gas price.
+        (
+            TypedTransaction::Legacy(legacy.clone().gas_price(20)),
+            0,
+            20,
+        ),
+        // And so does an EIP-2930 transaction, which also has an access list.
+        (
+            TypedTransaction::Eip2930(Eip2930TransactionRequest::new(
+                legacy.clone(),
+                access_list.clone(),
+            )),
+            1,
+            15,
+        ),
+        // An EIP-1559 transaction pays the base fee plus its priority fee.
+        (
+            TypedTransaction::Eip1559(
+                Eip1559TransactionRequest::new()
+                    .to(to)
+                    .value(1)
+                    .data(eBytes::new())
+                    .max_fee_per_gas(30)
+                    .max_priority_fee_per_gas(2),
+            ),
+            2,
+            12,
+        ),
+        // Without any fees it pays the gas price of the environment.
+        (
+            TypedTransaction::Eip1559(
+                Eip1559TransactionRequest::new()
+                    .to(to)
+                    .value(1)
+                    .data(eBytes::new()),
+            ),
+            2,
+            15,
+        ),
+    ];
+    for (tx, transaction_type, effective_gas_price) in transactions {
+        let receipt = client
+            .send_transaction(tx, None)
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.transaction_type, Some(U64::from(transaction_type)));
+        assert_eq!(
+            receipt.effective_gas_price,
+            Some(eU256::from(effective_gas_price))
+        );
+        let stored = client
+            .get_transaction_receipt(receipt.transaction_hash)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.transaction_type, receipt.transaction_type);
+        assert_eq!(stored.effective_gas_price, receipt.effective_gas_price);
+    }
+
+    // Calls do not change the gas price of the environment.
+    assert_eq!(client.get_gas_price().await.unwrap(), eU256::from(15));
+
+    // The access list is charged for, so the transaction uses more gas.
+    let (_environment, client) = startup();
+    let call = TransactionRequest::new().to(to).data(eBytes::new());
+    let plain = client
+        .estimate_gas(&TypedTransaction::Legacy(call.clone()), None)
+        .await
+        .unwrap();
+    let with_access_list = client
+        .estimate_gas(
+            &TypedTransaction::Eip2930(Eip2930TransactionRequest::new(call, access_list)),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(with_access_list - plain, eU256::from(2400 + 1900));
+}
+
+#[tokio::test]
+async fn trace_call() {
+    use arbiter_core::trace::CallKind;
+
+    let (_environment, client) = startup();
+    let (arbx, arby, liquid_exchange) = deploy_liquid_exchange(client.clone()).await;
+    for (token, holder) in [
+        (&arbx, client.address()),
+        (&arby, liquid_exchange.address()),
+    ] {
+        token
+            .mint(holder, parse_ether(1000).unwrap())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    arbx.approve(liquid_exchange.address(), eU256::MAX)
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // The swap pulls the tokens in and sends the tokens out, which are traced
+    // along with their transfers, but nothing is committed.
+    let swap = liquid_exchange.swap(arbx.address(), parse_ether(1).unwrap());
+    let trace = client.as_ref().trace_call(&swap.tx).await.unwrap();
+    assert_eq!(trace.kind, CallKind::Call);
+    assert_eq!(trace.from, client.address());
+    assert_eq!(trace.to, liquid_exchange.address());
+    assert!(trace.success);
+    let tokens: Vec<eAddress> = trace.calls.iter().map(|call| call.to).collect();
+    assert_eq!(tokens, vec![arbx.address(), arby.address()]);
+    assert!(trace.calls.iter().all(|call| call.success));
+    assert_eq!(trace.calls[1].logs.len(), 1);
+    assert_eq!(trace.calls[1].logs[0].address, arby.address());
+    assert_eq!(trace.logs.len(), 1);
+    assert_eq!(
+        arby.balance_of(client.address()).call().await.unwrap(),
+        eU256::zero()
+    );
+
+    // A swap that cannot be paid for is traced as well.
+    let swap = liquid_exchange.swap(arbx.address(), parse_ether(2000).unwrap());
+    let trace = client.as_ref().trace_call(&swap.tx).await.unwrap();
+    assert!(!trace.success);
+    assert!(!trace.calls[0].success);
+    assert!(!trace.output.is_empty());
+}
+
+#[tokio::test]
+async fn response_timeout_and_retry_policy() {
+    use arbiter_core::middleware::connection::RetryPolicy;
+
+    let (environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    assert_eq!(client.response_timeout(), None);
+    assert_eq!(client.retry_policy(), RetryPolicy::default());
+
+    // A paused environment does not reply to transactions, so they time out
+    // instead of hanging the client.
+    client.set_response_timeout(Some(std::time::Duration::from_millis(50)));
+    environment.pause().unwrap();
+    let error = arbx
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("did not reply"));
+
+    // The late reply to the transaction is not mistaken for the reply to the
+    // next instruction.
+    environment.resume().unwrap();
+    assert_eq!(
+        arbx.balance_of(client.address()).call().await.unwrap(),
+        eU256::from(1)
+    );
+
+    // Without retries, a nonce changed behind the back of the client fails the
+    // transaction.
+    arbx.mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let other = ArbiterMiddleware::new(&environment, Some("other")).unwrap();
+    client.set_retry_policy(RetryPolicy::none());
+    other.set_nonce(client.address(), 7).await.unwrap();
+    assert!(arbx
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .is_err());
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 7);
+
+    // With retries, the nonce is read again and the transaction sent again.
+    client.set_retry_policy(RetryPolicy {
+        max_retries: 2,
+        backoff: std::time::Duration::from_millis(1),
+    });
+    other.set_nonce(client.address(), 9).await.unwrap();
+    arbx.mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(client.next_nonce(client.address()).await.unwrap(), 10);
+}
+
+#[tokio::test]
+async fn middleware_builder() {
+    let environment = Environment::builder().build();
+    let arbitrageur = ArbiterMiddleware::builder()
+        .with_label("arbitrageur")
+        .with_seed(42)
+        .with_initial_eth(10)
+        .build(&environment)
+        .unwrap();
+    assert_eq!(arbitrageur.label.as_deref(), Some("arbitrageur"));
+    assert_eq!(
+        arbitrageur
+            .get_balance(arbitrageur.address(), None)
+            .await
+            .unwrap(),
+        parse_ether(10).unwrap()
+    );
+
+    // The seed decides the address, and the label does without a seed.
+    let other_environment = Environment::builder().build();
+    let reseeded = ArbiterMiddleware::builder()
+        .with_label("renamed")
+        .with_seed(42)
+        .build(&other_environment)
+        .unwrap();
+    assert_eq!(reseeded.address(), arbitrageur.address());
+    let labeled = ArbiterMiddleware::builder()
+        .with_label("alice")
+        .build(&other_environment)
+        .unwrap();
+    let created = ArbiterMiddleware::new(&environment, Some("alice")).unwrap();
+    assert_eq!(labeled.address(), created.address());
+    assert_eq!(
+        labeled.get_balance(labeled.address(), None).await.unwrap(),
+        eU256::zero()
+    );
+}
+
+#[tokio::test]
+async fn fee_history() {
+    use arbiter_core::environment::instruction::BlockSettings;
+    use ethers::types::Eip1559TransactionRequest;
+
+    let environment = Environment::builder()
+        .with_base_fee(revm::primitives::U256::from(10))
+        .with_gas_limit(revm::primitives::U256::from(1_000_000))
+        .with_transaction_gas_limit(1_000_000)
+        .build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    client
+        .set_balance(client.address(), parse_ether(1).unwrap())
+        .await
+        .unwrap();
+    for priority_fee in [5, 1] {
+        let tx = Eip1559TransactionRequest::new()
+            .to(eAddress::from_low_u64_be(100))
+            .value(1)
+            .data(eBytes::new())
+            .max_fee_per_gas(100)
+            .max_priority_fee_per_gas(priority_fee);
+        client
+            .send_transaction(tx, None)
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    client
+        .update_block_with(
+            1,
+            12,
+            BlockSettings {
+                base_fee: Some(eU256::from(20)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+    // The first block holds both transfers, and the current block is empty.
+    let history = client
+        .fee_history(2u64, BlockNumber::Latest, &[0.0, 50.0, 100.0])
+        .await
+        .unwrap();
+    assert_eq!(history.oldest_block, eU256::from(0));
+    assert_eq!(
+        history.base_fee_per_gas,
+        vec![eU256::from(10), eU256::from(20), eU256::from(20)]
+    );
+    assert_eq!(history.gas_used_ratio, vec![0.042, 0.0]);
+    assert_eq!(
+        history.reward,
+        vec![
+            vec![eU256::from(1), eU256::from(1), eU256::from(5)],
+            vec![eU256::zero(); 3],
+        ]
+    );
+
+    // A past block is followed by the base fee of the next block.
+    let history = client
+        .fee_history(1u64, BlockNumber::Number(U64::from(0)), &[])
+        .await
+        .unwrap();
+    assert_eq!(history.oldest_block, eU256::from(0));
+    assert_eq!(
+        history.base_fee_per_gas,
+        vec![eU256::from(10), eU256::from(20)]
+    );
+    assert!(history.reward.is_empty());
+
+    // Percentiles have to be increasing.
+    assert!(client
+        .fee_history(1u64, BlockNumber::Latest, &[50.0, 10.0])
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn deploy_deterministic() {
+    use arbiter_core::create2;
+
+    let (_environment, client) = startup();
+    let deployer = ArbiterToken::deploy(
+        client.clone(),
+        (
+            ARBITER_TOKEN_X_NAME.to_string(),
+            ARBITER_TOKEN_X_SYMBOL.to_string(),
+            ARBITER_TOKEN_X_DECIMALS,
+        ),
+    )
+    .unwrap();
+    let init_code = deployer.deployer.tx.data().unwrap().clone();
+    let salt = H256::from_low_u64_be(1);
+
+    // The address is known before the deployment and does not depend on the
+    // sender or its nonce.
+    let predicted = create2::deterministic_address(init_code.clone(), salt);
+    assert!(client.get_account(predicted).await.is_err());
+    let address = client
+        .deploy_deterministic(init_code.clone(), salt)
+        .await
+        .unwrap();
+    assert_eq!(address, predicted);
+    let arbiter_token = ArbiterToken::new(address, client.clone());
+    assert_eq!(
+        arbiter_token.name().call().await.unwrap(),
+        ARBITER_TOKEN_X_NAME
+    );
+
+    // Another salt deploys another contract, and the same salt fails.
+    let other = client
+        .deploy_deterministic(init_code.clone(), H256::from_low_u64_be(2))
+        .await
+        .unwrap();
+    assert_ne!(other, address);
+    assert!(matches!(
+        client.deploy_deterministic(init_code, salt).await,
+        Err(ArbiterCoreError::DeploymentError(_))
+    ));
+}
+
+#[tokio::test]
+async fn polling_filters() {
+    use ethers::providers::FilterKind;
+
+    let (_environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let approve = || async {
+        arbx.approve(client.address(), eU256::from(1))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    };
+
+    // Logs emitted before the filter was installed are not changes.
+    approve().await;
+    let filter = arbx.approval_filter().filter.address(arbx.address());
+    let id = client.new_filter(FilterKind::Logs(&filter)).await.unwrap();
+    let changes: Vec<Log> = client.get_filter_changes(id).await.unwrap();
+    assert!(changes.is_empty());
+
+    // Every log is returned once, across blocks, however rarely the filter is
+    // polled.
+    approve().await;
+    client.update_block(1, 1).unwrap();
+    approve().await;
+    approve().await;
+    let changes: Vec<Log> = client.get_filter_changes(id).await.unwrap();
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0].block_number, Some(U64::from(0)));
+    assert_eq!(changes[2].block_number, Some(U64::from(1)));
+    let changes: Vec<Log> = client.get_filter_changes(id).await.unwrap();
+    assert!(changes.is_empty());
+    client.update_block(2, 2).unwrap();
+    approve().await;
+    let changes: Vec<Log> = client.get_filter_changes(id).await.unwrap();
+    assert_eq!(changes.len(), 1);
+
+    // Logs that do not match the filter are skipped.
+    let other = client
+        .new_filter(FilterKind::Logs(&arbx.transfer_filter().filter))
+        .await
+        .unwrap();
+    approve().await;
+    let changes: Vec<Log> = client.get_filter_changes(other).await.unwrap();
+    assert!(changes.is_empty());
+
+    assert!(client.uninstall_filter(id).await.unwrap());
+    assert!(!client.uninstall_filter(id).await.unwrap());
+    assert!(client.get_filter_changes::<_, Log>(id).await.is_err());
+}
+
+#[tokio::test]
+async fn hooks() {
+    use std::sync::Mutex;
+
+    use arbiter_core::middleware::hooks::MiddlewareHooks;
+    use ethers::types::TransactionReceipt;
+
+    /// Records every transaction and sends it at a fixed gas price.
+    #[derive(Debug, Default)]
+    struct Audit {
+        sent: Mutex<Vec<TypedTransaction>>,
+        receipts: Mutex<Vec<TransactionReceipt>>,
+    }
+
+    #[async_trait::async_trait]
+    impl MiddlewareHooks for Audit {
+        async fn before_send(&self, tx: &mut TypedTransaction) {
+            tx.set_gas_price(7);
+            self.sent.lock().unwrap().push(tx.clone());
+        }
+
+        async fn after_receipt(&self, _tx: &TypedTransaction, receipt: &TransactionReceipt) {
+            self.receipts.lock().unwrap().push(receipt.clone());
+        }
+    }
+
+    let (_environment, client) = startup();
+    client
+        .set_balance(client.address(), parse_ether(1000).unwrap())
+        .await
+        .unwrap();
+    let audit = Arc::new(Audit::default());
+    client.add_hooks(audit.clone());
+    let arbx = deploy_arbx(client.clone()).await;
+    let receipt = arbx
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.effective_gas_price, Some(eU256::from(7)));
+    assert_eq!(audit.sent.lock().unwrap().len(), 2);
+    assert_eq!(
+        audit
+            .receipts
+            .lock()
+            .unwrap()
+            .last()
+            .unwrap()
+            .transaction_hash,
+        receipt.transaction_hash
+    );
+
+    // A transaction that reverts is sent but has no receipt.
+    assert!(arbx
+        .transfer(eAddress::from_low_u64_be(1), eU256::from(2))
+        .send()
+        .await
+        .is_err());
+    assert_eq!(audit.sent.lock().unwrap().len(), 3);
+    assert_eq!(audit.receipts.lock().unwrap().len(), 2);
+
+    // Clients sending from the same accounts start out with the same hooks.
+    let other = client.with_sender(client.address()).unwrap();
+    other.clear_hooks();
+    arbx.mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(audit.sent.lock().unwrap().len(), 4);
+    ArbiterToken::new(arbx.address(), other)
+        .mint(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    assert_eq!(audit.sent.lock().unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn sign_permit() {
+    use ethers::types::transaction::eip712::{Eip712, TypedData};
+
+    let (_environment, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+    let owner = client.add_account(Some("owner")).unwrap();
+    let value = eU256::from(TEST_MINT_AMOUNT);
+    let deadline = eU256::from(u64::MAX);
+    let permit: TypedData = serde_json::from_value(serde_json::json!({
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" },
+            ],
+            "Permit": [
+                { "name": "owner", "type": "address" },
+                { "name": "spender", "type": "address" },
+                { "name": "value", "type": "uint256" },
+                { "name": "nonce", "type": "uint256" },
+                { "name": "deadline", "type": "uint256" },
+            ],
+        },
+        "primaryType": "Permit",
+        "domain": {
+            "name": ARBITER_TOKEN_X_NAME,
+            "version": "1",
+            "chainId": client.get_chainid().await.unwrap(),
+            "verifyingContract": arbx.address(),
+        },
+        "message": {
+            "owner": owner,
+            "spender": client.address(),
+            "value": value,
+            "nonce": 0,
+            "deadline": deadline,
+        },
+    }))
+    .unwrap();
+    assert_eq!(
+        permit.domain_separator().unwrap(),
+        arbx.domain_separator().call().await.unwrap()
+    );
+
+    // The owner signs the permit without sending a transaction and the spender
+    // submits it.
+    let signature = client
+        .with_sender(owner)
+        .unwrap()
+        .sign_typed_data(&permit)
+        .await
+        .unwrap();
+    let mut r = [0; 32];
+    let mut s = [0; 32];
+    signature.r.to_big_endian(&mut r);
+    signature.s.to_big_endian(&mut s);
+    let permit_call = arbx.permit(
+        owner,
+        client.address(),
+        value,
+        deadline,
+        signature.v as u8,
+        r,
+        s,
+    );
+    permit_call.send().await.unwrap().await.unwrap();
+    assert_eq!(
+        arbx.allowance(owner, client.address())
+            .call()
+            .await
+            .unwrap(),
+        value
+    );
+    assert_eq!(arbx.nonces(owner).call().await.unwrap(), eU256::from(1));
+
+    // The nonce of the owner was used up, so the permit cannot be replayed.
+    assert!(permit_call.send().await.is_err());
+}
+
+#[tokio::test]
+async fn sign_with_account() {
+    let (_environment, client) = startup();
+    let account = client.add_account(Some("account")).unwrap();
+
+    let signature = client.sign(b"hello".to_vec(), &account).await.unwrap();
+    assert_eq!(signature.recover("hello").unwrap(), account);
+    assert!(matches!(
+        client
+            .sign(b"hello".to_vec(), &eAddress::from_low_u64_be(100))
+            .await,
+        Err(ArbiterCoreError::AccountDoesNotExistError)
+    ));
+}
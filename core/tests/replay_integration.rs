@@ -0,0 +1,131 @@
+use arbiter_bindings::bindings::{
+    arbiter_token::ARBITERTOKEN_BYTECODE,
+    weth::{WETH, WETH_BYTECODE},
+};
+use arbiter_core::{
+    errors::ArbiterCoreError,
+    replay::{self, Probe, Recording, Upgrade},
+};
+use ethers::types::{Address, U256 as eU256};
+include!("common.rs");
+
+/// Measures the token balance of a single holder.
+struct Balance {
+    token: Address,
+    holder: Address,
+}
+
+#[async_trait::async_trait]
+impl Probe for Balance {
+    async fn measure(
+        &mut self,
+        client: &Arc<ArbiterMiddleware>,
+    ) -> Result<std::collections::BTreeMap<String, f64>, ArbiterCoreError> {
+        // WETH shares the `balanceOf` of the token, so both versions can be
+        // measured the same way.
+        let balance = WETH::new(self.token, client.clone())
+            .balance_of(self.holder)
+            .call()
+            .await
+            .unwrap();
+        Ok([("balance".to_owned(), balance.as_u128() as f64)].into())
+    }
+}
+
+/// Records a run that deploys a token, mints some of it, and transfers part of
+/// it in the next block.
+async fn record() -> (Recording, Balance) {
+    let environment = Environment::builder().with_tracing().build();
+    let client = ArbiterMiddleware::new(&environment, Some(TEST_SIGNER_SEED_AND_LABEL)).unwrap();
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    arbiter_token
+        .mint(client.address(), eU256::from(10))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.update_block(1, 12).unwrap();
+    arbiter_token
+        .transfer(Address::from_low_u64_be(1), eU256::from(3))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let recording = Recording::new(&environment.tracer());
+    let probe = Balance {
+        token: arbiter_token.address(),
+        holder: client.address(),
+    };
+    (recording, probe)
+}
+
+#[tokio::test]
+async fn replay() {
+    let (recording, mut probe) = record().await;
+    assert_eq!(recording.transactions.len(), 3);
+    assert_eq!(recording.transactions[2].block_number, 1);
+    assert_eq!(recording.transactions[2].block_timestamp, 12);
+
+    let environment = Environment::builder().build();
+    let run = replay::replay(&recording, &environment, &Upgrade::new(), &mut probe)
+        .await
+        .unwrap();
+    assert_eq!(run.blocks[&0]["balance"], 10.0);
+    assert_eq!(run.blocks[&1]["balance"], 7.0);
+    assert!(run
+        .transactions
+        .iter()
+        .all(|replayed| replayed.unwrap().success));
+}
+
+#[tokio::test]
+async fn compare_upgrade() {
+    let (recording, mut probe) = record().await;
+
+    let unchanged = replay::compare(
+        &recording,
+        &Upgrade::new(),
+        || Environment::builder().build(),
+        &mut probe,
+    )
+    .await
+    .unwrap();
+    assert!(unchanged.is_unchanged());
+
+    // Deploying WETH in place of the token makes the mint and the transfer
+    // revert.
+    let upgrade =
+        Upgrade::new().with_deployment(ARBITERTOKEN_BYTECODE.clone(), WETH_BYTECODE.clone());
+    let comparison = replay::compare(
+        &recording,
+        &upgrade,
+        || Environment::builder().build(),
+        &mut probe,
+    )
+    .await
+    .unwrap();
+    assert_eq!(comparison.metrics[&0]["balance"].from, Some(10.0));
+    assert_eq!(comparison.metrics[&0]["balance"].to, Some(0.0));
+    assert_eq!(comparison.metrics[&1]["balance"].to, Some(0.0));
+    assert_eq!(comparison.transactions.len(), 3);
+    assert!(!comparison.transactions[&1].to.unwrap().success);
+    assert!(!comparison.transactions[&2].to.unwrap().success);
+    assert_ne!(comparison.gas_used.from, comparison.gas_used.to);
+
+    // The report starts with the gas and then lists the changes from the
+    // first block that diverged on.
+    let report = comparison.to_string();
+    let lines: Vec<_> = report.lines().collect();
+    assert!(lines[0].starts_with(&format!(
+        "gas used: {} -> {}",
+        comparison.gas_used.from, comparison.gas_used.to
+    )));
+    assert_eq!(lines[1], "block 0: balance: Some(10.0) -> Some(0.0)");
+    assert_eq!(lines[2], "block 1: balance: Some(7.0) -> Some(0.0)");
+    assert!(lines[3].starts_with("transaction 0: "));
+    assert!(lines[4].starts_with("transaction 1: "));
+    assert!(lines[5].starts_with("transaction 2: "));
+}
@@ -0,0 +1,230 @@
+use arbiter_core::rpc::RpcServer;
+use ethers::{
+    middleware::SignerMiddleware,
+    providers::{FilterKind, Http, Middleware, Provider, StreamExt, Ws},
+    signers::{LocalWallet, Signer},
+    types::{Address, Log, TransactionRequest, U256 as eU256},
+};
+include!("common.rs");
+
+async fn serve() -> (Environment, Arc<ArbiterMiddleware>, RpcServer) {
+    let (environment, client) = startup();
+    let server = RpcServer::serve(&environment, "127.0.0.1:0").await.unwrap();
+    (environment, client, server)
+}
+
+#[tokio::test]
+async fn rpc_queries() {
+    let (_environment, client, server) = serve().await;
+    let provider = Provider::<Http>::try_from(format!("http://{}", server.local_addr())).unwrap();
+
+    assert_eq!(provider.get_chainid().await.unwrap(), eU256::from(1));
+    assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 0);
+    client.update_block(3, 36).unwrap();
+    assert_eq!(provider.get_block_number().await.unwrap().as_u64(), 3);
+
+    let address = Address::from_low_u64_be(1);
+    assert_eq!(provider.get_balance(address, None).await.unwrap(), 0.into());
+    client.set_balance(address, 100).await.unwrap();
+    assert_eq!(
+        provider.get_balance(address, None).await.unwrap(),
+        100.into()
+    );
+
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let remote_token = ArbiterToken::new(arbiter_token.address(), Arc::new(provider.clone()));
+    assert_eq!(
+        remote_token.name().call().await.unwrap(),
+        ARBITER_TOKEN_X_NAME
+    );
+    assert!(!provider
+        .get_code(arbiter_token.address(), None)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let error = provider
+        .request::<_, String>("eth_getProof", ())
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("not supported"));
+}
+
+#[tokio::test]
+async fn rpc_transactions() {
+    let (_environment, client, server) = serve().await;
+    let provider = Provider::<Http>::try_from(format!("http://{}", server.local_addr())).unwrap();
+
+    // Transactions are sent from any address without a signature.
+    let sender = Address::from_low_u64_be(1);
+    let recipient = Address::from_low_u64_be(2);
+    provider
+        .request::<_, ()>(
+            "anvil_setBalance",
+            (sender, eU256::from(1_000_000_000_000_000_u64)),
+        )
+        .await
+        .unwrap();
+    let tx = TransactionRequest::new()
+        .from(sender)
+        .to(recipient)
+        .value(100);
+    let transaction_hash = *provider.send_transaction(tx, None).await.unwrap();
+    let receipt = provider
+        .get_transaction_receipt(transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.from, sender);
+    assert_eq!(receipt.status, Some(1.into()));
+    let transaction = provider
+        .get_transaction(transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(transaction.from, sender);
+    assert_eq!(transaction.block_number, receipt.block_number);
+    assert_eq!(
+        client.get_balance(recipient, None).await.unwrap(),
+        100.into()
+    );
+
+    // Signed transactions are sent from the address that signed them.
+    let wallet = LocalWallet::new(&mut rand::thread_rng()).with_chain_id(1_u64);
+    client
+        .set_balance(wallet.address(), parse_ether(1).unwrap())
+        .await
+        .unwrap();
+    let signer = SignerMiddleware::new(provider.clone(), wallet.clone());
+    let tx = TransactionRequest::new().to(recipient).value(100);
+    let transaction_hash = *signer.send_transaction(tx, None).await.unwrap();
+    let receipt = provider
+        .get_transaction_receipt(transaction_hash)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(receipt.from, wallet.address());
+    assert_eq!(
+        client.get_balance(recipient, None).await.unwrap(),
+        200.into()
+    );
+    assert_eq!(
+        provider
+            .get_transaction_count(wallet.address(), None)
+            .await
+            .unwrap(),
+        1.into()
+    );
+}
+
+#[tokio::test]
+async fn rpc_subscriptions() {
+    let (_environment, client, server) = serve().await;
+    let provider = Provider::<Ws>::connect(format!("ws://{}", server.local_addr()))
+        .await
+        .unwrap();
+
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let filter = arbiter_token.transfer_filter().filter;
+    let mut stream = provider.subscribe_logs(&filter).await.unwrap();
+    arbiter_token
+        .mint(client.address(), eU256::from(TEST_MINT_AMOUNT))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let log = stream.next().await.unwrap();
+    assert_eq!(log.address, arbiter_token.address());
+}
+
+#[tokio::test]
+async fn rpc_filters() {
+    let (_environment, client, server) = serve().await;
+    let provider = Provider::<Http>::try_from(format!("http://{}", server.local_addr())).unwrap();
+
+    let arbiter_token = deploy_arbx(client.clone()).await;
+    let filter = arbiter_token.transfer_filter().filter;
+    let id = provider
+        .new_filter(FilterKind::Logs(&filter))
+        .await
+        .unwrap();
+    for _ in 0..2 {
+        arbiter_token
+            .mint(client.address(), eU256::from(TEST_MINT_AMOUNT))
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    let changes: Vec<Log> = provider.get_filter_changes(id).await.unwrap();
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].address, arbiter_token.address());
+    let changes: Vec<Log> = provider.get_filter_changes(id).await.unwrap();
+    assert!(changes.is_empty());
+    assert!(provider.uninstall_filter(id).await.unwrap());
+}
+
+#[tokio::test]
+async fn rpc_signing() {
+    use ethers::types::{
+        transaction::eip712::{Eip712, TypedData},
+        Bytes as eBytes, Signature, H256,
+    };
+
+    let (_environment, _client, server) = serve().await;
+    let provider = Provider::<Http>::try_from(format!("http://{}", server.local_addr())).unwrap();
+    let account = provider.get_accounts().await.unwrap()[0];
+
+    let signature = provider.sign(b"hello".to_vec(), &account).await.unwrap();
+    assert_eq!(signature.recover("hello").unwrap(), account);
+
+    let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+        "types": {
+            "EIP712Domain": [{ "name": "name", "type": "string" }],
+            "Greeting": [{ "name": "text", "type": "string" }],
+        },
+        "primaryType": "Greeting",
+        "domain": { "name": "arbiter" },
+        "message": { "text": "hello" },
+    }))
+    .unwrap();
+    let hash = H256(typed_data.encode_eip712().unwrap());
+    // The typed data is sent both as an object and as a string holding its JSON.
+    for typed_data in [
+        serde_json::to_value(&typed_data).unwrap(),
+        serde_json::to_string(&typed_data).unwrap().into(),
+    ] {
+        let signature: eBytes = provider
+            .request("eth_signTypedData_v4", (account, typed_data))
+            .await
+            .unwrap();
+        let signature = Signature::try_from(signature.as_ref()).unwrap();
+        assert_eq!(signature.recover(hash).unwrap(), account);
+    }
+
+    // Impersonated accounts cannot sign.
+    assert!(provider
+        .sign(b"hello".to_vec(), &Address::from_low_u64_be(1))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn rpc_rejects_upgrades_without_a_key() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let (_environment, _client, server) = serve().await;
+    let mut stream = tokio::net::TcpStream::connect(server.local_addr())
+        .await
+        .unwrap();
+    stream
+        .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n")
+        .await
+        .unwrap();
+    let mut response = vec![0; 64];
+    let read = stream.read(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response[..read]);
+    assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+}
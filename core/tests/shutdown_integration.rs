@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use arbiter_core::{events::Logger, shutdown::ShutdownCoordinator};
+use ethers::types::U256 as eU256;
+include!("common.rs");
+
+#[tokio::test]
+async fn interrupted_logger_writes_truncated_output() {
+    let (_env, client) = startup();
+    let arbx = deploy_arbx(client.clone()).await;
+
+    let logger_task = Logger::builder()
+        .with_event(arbx.events(), "arbx")
+        .directory("./interrupted_data")
+        .run()
+        .unwrap();
+
+    arbx.approve(client.address(), eU256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    // Simulate a Ctrl-C before the environment is stopped.
+    ShutdownCoordinator::global().trigger();
+    logger_task.await.unwrap();
+    assert!(
+        ShutdownCoordinator::global()
+            .wait_for_sinks(std::time::Duration::from_secs(1))
+            .await
+    );
+
+    let output: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string("./interrupted_data/output.json").unwrap())
+            .unwrap();
+    let marker = Path::new("./interrupted_data/output.truncated").exists();
+    std::fs::remove_dir_all("./interrupted_data").unwrap();
+    assert!(marker);
+    assert_eq!(output["truncated"], serde_json::Value::Bool(true));
+    assert_eq!(
+        output["events"]["arbx"]["ApprovalFilter"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+}
@@ -3,11 +3,13 @@
 use std::{fmt::Debug, sync::Arc};
 
 use arbiter_core::middleware::ArbiterMiddleware;
+use ethers::types::U256;
 use serde::{de::DeserializeOwned, Serialize};
 
 use super::*;
 use crate::{
-    machine::{Behavior, Engine, StateMachine},
+    errors::AgentBuildError,
+    machine::{Behavior, Chain, Engine, StateMachine},
     messager::Messager,
 };
 
@@ -21,6 +23,15 @@ use crate::{
 /// each of its [`Behavior`]s `startup()` methods. The [`Behavior`]s themselves
 /// will return a stream of events that then let the [`Behavior`] move into the
 /// `State::Processing` stage.
+///
+/// # Sub-agents
+/// An [`Agent`] can own sub-agents, e.g., a fund that runs several strategies
+/// from one wallet. Sub-agents share the client of their parent and are
+/// started along with it, but the [`World`] only knows about the parent.
+/// Their messagers are connected to a private scope that only the parent and
+/// its other sub-agents can see, which the parent reaches through
+/// [`Messager::scope`]. A sub-agent `strategy` of the agent `fund` has the id
+/// `"fund/strategy"`.
 #[derive(Debug)]
 pub struct Agent {
     /// Identifier for this agent.
@@ -37,6 +48,9 @@ pub struct Agent {
     /// The engines/behaviors that the agent uses to sync, startup, and process
     /// events.
     pub(crate) behavior_engines: Vec<Box<dyn StateMachine>>,
+
+    /// The sub-agents owned by the agent, keyed by their full id.
+    pub sub_agents: HashMap<String, Agent>,
 }
 
 impl Agent {
@@ -60,7 +74,28 @@ impl Agent {
         AgentBuilder {
             id: id.to_owned(),
             behavior_engines: None,
+            transaction_gas_limit: None,
+            initial_balance: None,
+            sub_agents: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+
+    /// Takes the behavior engines of the agent and its sub-agents along with
+    /// the client and a separate messager for each of them to be started
+    /// with.
+    pub(crate) fn engines(
+        &mut self,
+    ) -> Vec<(Box<dyn StateMachine>, Arc<ArbiterMiddleware>, Messager)> {
+        let mut engines: Vec<_> = self
+            .behavior_engines
+            .drain(..)
+            .map(|engine| (engine, self.client.clone(), self.messager.clone()))
+            .collect();
+        for sub_agent in self.sub_agents.values_mut() {
+            engines.extend(sub_agent.engines());
         }
+        engines
     }
 }
 
@@ -73,6 +108,15 @@ pub struct AgentBuilder {
     /// The engines/behaviors that the agent uses to sync, startup, and process
     /// events.
     behavior_engines: Option<Vec<Box<dyn StateMachine>>>,
+    /// The gas limit of the transactions sent by the agent, overriding the one
+    /// of the environment.
+    transaction_gas_limit: Option<u64>,
+    /// The balance in wei the client of the agent starts with.
+    pub(crate) initial_balance: Option<U256>,
+    /// The sub-agents owned by the agent.
+    sub_agents: Vec<AgentBuilder>,
+    /// The groups the agent is in.
+    groups: Vec<String>,
 }
 
 impl AgentBuilder {
@@ -82,7 +126,7 @@ impl AgentBuilder {
         mut self,
         behavior: impl Behavior<E> + 'static,
     ) -> Self {
-        let engine = Engine::new(behavior);
+        let engine: Engine<_, E> = Engine::new(behavior);
         if let Some(engines) = &mut self.behavior_engines {
             engines.push(Box::new(engine));
         } else {
@@ -91,6 +135,66 @@ impl AgentBuilder {
         self
     }
 
+    /// Appends a behavior that starts once the behavior added before it is
+    /// done, i.e., it halted or its event stream ended, so that multi-phase
+    /// agents, e.g., one that deploys contracts and then trades on them, are
+    /// written as a behavior per phase. See [`Chain`] for details.
+    ///
+    /// If no behavior was added before it, the behavior starts right away as
+    /// with [`AgentBuilder::with_behavior`].
+    pub fn then_behavior<E: DeserializeOwned + Serialize + Send + Sync + Debug + 'static>(
+        mut self,
+        behavior: impl Behavior<E> + 'static,
+    ) -> Self {
+        let engine: Box<dyn StateMachine> = Box::new(Engine::<_, E>::new(behavior));
+        let engines = self.behavior_engines.get_or_insert_with(Vec::new);
+        let chain = match engines.pop() {
+            Some(previous) => Box::new(Chain::new(previous).then(engine)),
+            None => engine,
+        };
+        engines.push(chain);
+        self
+    }
+
+    /// Caps the gas of every transaction and call the agent sends so that one
+    /// that uses more fails with
+    /// [`ArbiterCoreError::OutOfGas`](arbiter_core::errors::ArbiterCoreError::OutOfGas).
+    pub fn with_transaction_gas_limit(mut self, gas_limit: u64) -> Self {
+        self.transaction_gas_limit = Some(gas_limit);
+        self
+    }
+
+    /// Sets the ETH balance the client of the agent starts with once the
+    /// agent is added to a [`World`](crate::world::World).
+    pub fn with_initial_eth(self, eth: u64) -> Self {
+        self.with_initial_balance(U256::from(eth) * U256::exp10(18))
+    }
+
+    /// Sets the balance in wei the client of the agent starts with once the
+    /// agent is added to a [`World`](crate::world::World).
+    pub fn with_initial_balance(mut self, wei: impl Into<U256>) -> Self {
+        self.initial_balance = Some(wei.into());
+        self
+    }
+
+    /// Adds the agent to the `group`, e.g., `"lps"`, so that it receives the
+    /// messages sent [`To::Group`](crate::messager::To::Group) and is paused
+    /// and resumed with the other agents of the group, see
+    /// [`Pauser::pause_group`](crate::world::Pauser::pause_group). An agent
+    /// can be in any number of groups.
+    pub fn with_group(mut self, group: &str) -> Self {
+        self.groups.push(group.to_owned());
+        self
+    }
+
+    /// Adds a sub-agent that is owned by the agent. The sub-agent sends its
+    /// transactions with the agent's client and only exchanges messages with
+    /// the agent and its other sub-agents. See [`Agent`] for details.
+    pub fn with_sub_agent(mut self, sub_agent: AgentBuilder) -> Self {
+        self.sub_agents.push(sub_agent);
+        self
+    }
+
     /// Adds a state machine engine to the agent builder.
     ///
     /// This method allows for the addition of a custom state machine engine to
@@ -137,34 +241,84 @@ impl AgentBuilder {
     /// # Returns
     ///
     /// Returns a `Result` that, on success, contains the newly created
-    /// [`Agent`] instance. On failure, it returns an
-    /// [`AgentBuildError::MissingBehaviorEngines`] error indicating that the
-    /// agent was attempted to be built without any behavior engines
-    /// configured.
+    /// [`Agent`] instance. On failure, it returns the [`AgentBuildError`]
+    /// that the agent or one of its sub-agents was built with, e.g.,
+    /// [`AgentBuildError::NoBehaviors`] if it has neither behaviors nor
+    /// sub-agents, [`AgentBuildError::UnregisteredMessager`] if `messager` is
+    /// not the one of its id, see [`Messager::for_agent`], or
+    /// [`AgentBuildError::SharedClient`] if a sub-agent configures the client
+    /// it shares with its parent.
     ///
     /// # Examples
     ///
     /// ```ignore
     /// let agent_builder = AgentBuilder::new("agent_id");
     /// let client = Arc::new(RevmMiddleware::new(...));
-    /// let messager = Messager::new(...);
+    /// let messager = world.messager.for_agent("agent_id");
     /// let agent = agent_builder.build(client, messager).expect("Failed to build agent");
     /// ```
     pub fn build(
         self,
         client: Arc<ArbiterMiddleware>,
-        messager: Messager,
+        mut messager: Messager,
     ) -> Result<Agent, ArbiterEngineError> {
-        match self.behavior_engines {
-            Some(engines) => Ok(Agent {
-                id: self.id,
-                messager,
-                client,
-                behavior_engines: engines,
-            }),
-            None => Err(ArbiterEngineError::AgentBuildError(
-                "Missing behavior engines".to_owned(),
-            )),
+        if self.behavior_engines.is_none() && self.sub_agents.is_empty() {
+            return Err(AgentBuildError::NoBehaviors(self.id).into());
+        }
+        if messager.id.as_deref() != Some(self.id.as_str()) {
+            return Err(AgentBuildError::UnregisteredMessager {
+                agent: self.id,
+                messager: messager.id,
+            }
+            .into());
+        }
+        for sub_agent in &self.sub_agents {
+            let id = format!("{}/{}", self.id, sub_agent.id);
+            let setting = if sub_agent.transaction_gas_limit.is_some() {
+                "a transaction gas limit"
+            } else if sub_agent.initial_balance.is_some() {
+                "an initial balance"
+            } else {
+                continue;
+            };
+            return Err(AgentBuildError::SharedClient {
+                sub_agent: id,
+                agent: self.id,
+                setting,
+            }
+            .into());
+        }
+        if let Some(gas_limit) = self.transaction_gas_limit {
+            client.set_transaction_gas_limit(Some(gas_limit));
         }
+        for group in &self.groups {
+            messager.pauser.groups.join(&self.id, group);
+        }
+
+        let mut sub_agents = HashMap::new();
+        if !self.sub_agents.is_empty() {
+            let scope = messager.new_scope();
+            for sub_agent in self.sub_agents {
+                let id = format!("{}/{}", self.id, sub_agent.id);
+                if sub_agents.contains_key(&id) {
+                    return Err(AgentBuildError::DuplicateSubAgent(id).into());
+                }
+                let sub_agent = AgentBuilder {
+                    id: id.clone(),
+                    ..sub_agent
+                }
+                .build(client.clone(), scope.for_agent(&id))?;
+                sub_agents.insert(id, sub_agent);
+            }
+            messager.set_scope(&scope);
+        }
+
+        Ok(Agent {
+            id: self.id,
+            messager,
+            client,
+            behavior_engines: self.behavior_engines.unwrap_or_default(),
+            sub_agents,
+        })
     }
 }
@@ -0,0 +1,218 @@
+//! A [`BlockAdmin`] owns the advancement of the blocks of a simulation, so
+//! that when the other behaviors act "per block", e.g., with
+//! [`Trigger::Blocks`](crate::trigger::Trigger::Blocks), it is explicit how
+//! fast blocks are produced.
+//!
+//! It mines a block every [`BlockAdmin::interval`] of wall-clock time, and on
+//! demand when it receives a [`BlockAdminQuery`] through its [`Messager`]:
+//! ```toml
+//! [[block_admin]]
+//! [block_admin.BlockAdmin]
+//! interval = { secs = 0, nanos = 100000000 }
+//! block_time = 12
+//! ```
+//! Another behavior mines blocks by sending the query to the agent of the
+//! block admin, which replies with the [`BlockInfo`] of the current block:
+//! ```ignore
+//! messager
+//!     .send_payload(To::Agent("block_admin".to_owned()), &BlockAdminQuery::Mine(10))
+//!     .await?;
+//! ```
+
+use std::time::Duration;
+
+use anyhow::Result;
+use arbiter_core::middleware::ArbiterMiddleware;
+use ethers::providers::Middleware;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, EventStreams, HaltReason},
+    messager::{Message, Payload, To},
+};
+
+/// A query that a [`BlockAdmin`] answers with the [`BlockInfo`] of the current
+/// block, sent as the data of a message to its agent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BlockAdminQuery {
+    /// Mines the given number of blocks.
+    Mine(u64),
+
+    /// Asks for the current block.
+    Current,
+}
+
+impl Payload for BlockAdminQuery {
+    const TAG: &'static str = "BlockAdminQuery";
+}
+
+/// The number and timestamp of a block, which a [`BlockAdmin`] replies to a
+/// [`BlockAdminQuery`] with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockInfo {
+    /// The number of the block.
+    pub number: u64,
+
+    /// The timestamp of the block in seconds.
+    pub timestamp: u64,
+}
+
+impl Payload for BlockInfo {
+    const TAG: &'static str = "BlockInfo";
+}
+
+/// The events a [`BlockAdmin`] processes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum BlockAdminEvent {
+    /// It is time to mine the next block.
+    Tick,
+
+    /// A message that may hold a [`BlockAdminQuery`].
+    Message(Message),
+}
+
+/// Mines blocks on an interval and on demand, see the
+/// [module documentation](self).
+///
+/// Each mined block has the number of the previous one plus one and its
+/// timestamp plus [`BlockAdmin::block_time`].
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BlockAdmin {
+    /// How often a block is mined, if blocks are mined on an interval rather
+    /// than only on demand.
+    #[serde(default)]
+    pub interval: Option<Duration>,
+
+    /// The number of seconds the timestamp advances by with each block.
+    #[serde(default = "default_block_time")]
+    pub block_time: u64,
+
+    /// The number of blocks after which the block admin halts, if it does.
+    #[serde(default)]
+    pub max_blocks: Option<u64>,
+
+    #[serde(skip)]
+    state: Option<BlockAdminState>,
+}
+
+#[derive(Debug)]
+struct BlockAdminState {
+    client: Arc<ArbiterMiddleware>,
+    messager: Messager,
+    block: BlockInfo,
+    mined: u64,
+}
+
+fn default_block_time() -> u64 {
+    12
+}
+
+impl BlockAdmin {
+    /// Creates a [`BlockAdmin`] that only mines blocks on demand, each
+    /// `block_time` seconds after the previous one.
+    pub fn new(block_time: u64) -> Self {
+        Self {
+            interval: None,
+            block_time,
+            max_blocks: None,
+            state: None,
+        }
+    }
+
+    /// Mines a block every `interval`.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Halts the block admin once it mined `max_blocks` blocks.
+    pub fn with_max_blocks(mut self, max_blocks: u64) -> Self {
+        self.max_blocks = Some(max_blocks);
+        self
+    }
+
+    /// Mines `blocks` blocks unless that exceeds
+    /// [`BlockAdmin::max_blocks`], and returns whether the block admin is
+    /// done.
+    fn mine(&mut self, blocks: u64) -> Result<bool> {
+        let state = self.state.as_mut().unwrap();
+        for _ in 0..blocks {
+            if self.max_blocks.is_some_and(|max| state.mined >= max) {
+                break;
+            }
+            state.block.number += 1;
+            state.block.timestamp += self.block_time;
+            state
+                .client
+                .update_block(state.block.number, state.block.timestamp)?;
+            state.mined += 1;
+            trace!("Mined block {:?}", state.block);
+        }
+        Ok(self.max_blocks.is_some_and(|max| state.mined >= max))
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior<BlockAdminEvent> for BlockAdmin {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<BlockAdminEvent>>> {
+        let block = BlockInfo {
+            number: client.get_block_number().await?.as_u64(),
+            timestamp: client.get_block_timestamp().await?.as_u64(),
+        };
+        let mut streams =
+            EventStreams::new().with(messager.clone().stream()?, BlockAdminEvent::Message);
+        if let Some(period) = self.interval {
+            // Unlike a `Trigger::Interval`, the ticks do not read the block
+            // number, since the client waits for the outcome of each of its
+            // calls on the same channel that mining the blocks does.
+            let ticks: EventStream<()> = Box::pin(async_stream::stream! {
+                let mut interval = interval_at(Instant::now() + period, period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                loop {
+                    interval.tick().await;
+                    yield;
+                }
+            });
+            streams = streams.with(ticks, |_| BlockAdminEvent::Tick);
+        }
+        self.state = Some(BlockAdminState {
+            client,
+            messager,
+            block,
+            mined: 0,
+        });
+        Ok(Some(streams.merge()))
+    }
+
+    async fn process(&mut self, event: BlockAdminEvent) -> Result<ControlFlow, BehaviorError> {
+        let done = match event {
+            BlockAdminEvent::Tick => self.mine(1)?,
+            BlockAdminEvent::Message(message) => {
+                let Ok(query) = message.decode::<BlockAdminQuery>() else {
+                    return Ok(ControlFlow::Continue);
+                };
+                let done = match query.data {
+                    BlockAdminQuery::Mine(blocks) => self.mine(blocks)?,
+                    BlockAdminQuery::Current => false,
+                };
+                let state = self.state.as_ref().unwrap();
+                state
+                    .messager
+                    .send_payload(To::Agent(message.from), &state.block)
+                    .await?;
+                done
+            }
+        };
+        if done {
+            let mined = self.state.as_ref().unwrap().mined;
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(mined)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
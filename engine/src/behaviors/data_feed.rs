@@ -0,0 +1,290 @@
+//! A [`DataFeed`] replays a time series from a CSV file into a world on the
+//! schedule of a [`Trigger`], e.g., historical market prices, so that the
+//! other agents of the world trade against real data rather than a simulated
+//! price path:
+//! ```toml
+//! [[feed]]
+//! [feed.DataFeed]
+//! path = "data/eth_usd.csv"
+//! column = "close"
+//! trigger = { Blocks = 1 }
+//! schedule = "Timestamps"
+//! target = { Contract = { address = "0x5FbDB2315678afecb367f032d93F642f64180aa3" } }
+//! ```
+//! The file has a header with the names of its columns, among them a
+//! `timestamp` column of Unix timestamps in seconds and the column of the
+//! values:
+//! ```csv
+//! timestamp,open,close
+//! 1704067200,2281.87,2352.04
+//! 1704153600,2352.04,2355.34
+//! ```
+//! Each value is either pushed into a contract, e.g., a mock oracle or a
+//! `LiquidExchange`, or sent to all agents as a [`FeedUpdate`] through the
+//! [`Messager`], which the other behaviors stream with
+//! [`Messager::payloads`].
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use arbiter_core::middleware::{client::ArbiterClient, ArbiterMiddleware};
+use ethers::{
+    abi::parse_abi,
+    contract::Contract,
+    providers::Middleware,
+    types::{Address, BlockNumber},
+};
+
+use super::*;
+use crate::{
+    behaviors::price_changer::{default_set_price_function, set_price},
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, HaltReason},
+    messager::{Payload, To},
+    trigger::{Tick, Trigger},
+};
+
+/// Where a [`DataFeed`] puts the values of its time series.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeedTarget {
+    /// Sends each value to all agents as a [`FeedUpdate`].
+    #[default]
+    Messager,
+
+    /// Sets each value in a contract, as a fixed point number with 18
+    /// decimals.
+    Contract {
+        /// The address of the contract.
+        address: Address,
+
+        /// The name of the function of the contract that sets the value,
+        /// which takes it as its only `uint256` argument.
+        #[serde(default = "default_set_price_function")]
+        set_price_function: String,
+    },
+}
+
+/// When the values of a [`DataFeed`] are put into the world.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FeedSchedule {
+    /// The value of one row on startup and on every tick, regardless of its
+    /// timestamp.
+    #[default]
+    Rows,
+
+    /// The values of the rows whose timestamps are at most the timestamp of
+    /// the current block, on startup and on every tick, so the time series
+    /// follows the block time of the world. The rows have to be sorted by
+    /// their timestamps.
+    Timestamps,
+}
+
+/// A value of the time series of a [`DataFeed`], sent to all agents if its
+/// target is [`FeedTarget::Messager`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeedUpdate {
+    /// The name of the feed, see [`DataFeed::name`].
+    pub feed: String,
+
+    /// The timestamp of the value.
+    pub timestamp: u64,
+
+    /// The value.
+    pub value: f64,
+}
+
+impl Payload for FeedUpdate {
+    const TAG: &'static str = "FeedUpdate";
+}
+
+/// Replays a time series from a CSV file on the schedule of a [`Trigger`],
+/// see the [module documentation](self).
+///
+/// The file is read on startup. On startup and on every tick of
+/// [`DataFeed::trigger`], the values that are due by
+/// [`DataFeed::schedule`] are put into [`DataFeed::target`] in the order of
+/// the rows, and the feed halts once every row was put.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct DataFeed<M: ArbiterClient = ArbiterMiddleware> {
+    /// The path of the CSV file.
+    pub path: PathBuf,
+
+    /// The name of the column of the values.
+    #[serde(default = "default_column")]
+    pub column: String,
+
+    /// The name of the feed in its [`FeedUpdate`]s, which is the name of the
+    /// column if it is not given.
+    #[serde(default)]
+    pub name: Option<String>,
+
+    /// The schedule the values are put on.
+    pub trigger: Trigger,
+
+    /// Which of the values are put on each tick.
+    #[serde(default)]
+    pub schedule: FeedSchedule,
+
+    /// Where the values are put.
+    #[serde(default)]
+    pub target: FeedTarget,
+
+    #[serde(skip)]
+    state: Option<DataFeedState<M>>,
+}
+
+#[derive(Debug)]
+struct DataFeedState<M> {
+    client: Arc<M>,
+    messager: Messager,
+
+    /// The target contract and the name of its function that sets the value.
+    contract: Option<(Contract<M>, String)>,
+
+    /// The timestamps and values of the rows.
+    rows: Vec<(u64, f64)>,
+
+    /// The index of the next row to put.
+    next: usize,
+}
+
+fn default_column() -> String {
+    "value".to_owned()
+}
+
+impl<M: ArbiterClient> DataFeed<M> {
+    /// Reads the timestamps and values of the rows of the file, reporting a
+    /// row that cannot be read along with its line.
+    fn read(&self) -> Result<Vec<(u64, f64)>> {
+        let path = self.path.display();
+        let contents =
+            std::fs::read_to_string(&self.path).map_err(|e| anyhow::anyhow!("{}: {}", path, e))?;
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            anyhow::bail!("{}: the file is empty", path);
+        };
+        let columns: Vec<_> = header.split(',').map(str::trim).collect();
+        let position = |column: &str| {
+            columns
+                .iter()
+                .position(|name| *name == column)
+                .ok_or_else(|| anyhow::anyhow!("{}:1: there is no `{}` column", path, column))
+        };
+        let (timestamp, value) = (position("timestamp")?, position(&self.column)?);
+        let mut rows = vec![];
+        for (index, line) in lines {
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            let field = |position: usize| {
+                fields.get(position).copied().ok_or_else(|| {
+                    anyhow::anyhow!("{}:{}: the row has too few columns", path, index + 1)
+                })
+            };
+            let row = (
+                field(timestamp)?.parse().map_err(|e| {
+                    anyhow::anyhow!("{}:{}: invalid timestamp: {}", path, index + 1, e)
+                })?,
+                field(value)?
+                    .parse()
+                    .map_err(|e| anyhow::anyhow!("{}:{}: invalid value: {}", path, index + 1, e))?,
+            );
+            if self.schedule == FeedSchedule::Timestamps
+                && rows.last().is_some_and(|(last, _)| row.0 < *last)
+            {
+                anyhow::bail!(
+                    "{}:{}: the rows are not sorted by their timestamps",
+                    path,
+                    index + 1
+                );
+            }
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    /// Puts the values that are due into the target, returning whether every
+    /// row was put.
+    async fn put_due(&mut self) -> Result<bool> {
+        let state = self.state.as_mut().unwrap();
+        let due = match self.schedule {
+            FeedSchedule::Rows => (state.next + 1).min(state.rows.len()),
+            FeedSchedule::Timestamps => {
+                let now = state
+                    .client
+                    .get_block(BlockNumber::Latest)
+                    .await
+                    .map_err(|e| anyhow::anyhow!("could not read the block: {}", e))?
+                    .ok_or_else(|| anyhow::anyhow!("there is no latest block"))?
+                    .timestamp
+                    .as_u64();
+                state.next
+                    + state.rows[state.next..]
+                        .iter()
+                        .take_while(|(timestamp, _)| *timestamp <= now)
+                        .count()
+            }
+        };
+        let feed = self.name.as_ref().unwrap_or(&self.column);
+        for &(timestamp, value) in &state.rows[state.next..due] {
+            match &state.contract {
+                Some((contract, function)) => set_price(contract, function, value).await?,
+                None => {
+                    let update = FeedUpdate {
+                        feed: feed.clone(),
+                        timestamp,
+                        value,
+                    };
+                    state.messager.send_payload(To::All, &update).await?;
+                }
+            }
+            trace!("Put the value {} of {}", value, timestamp);
+        }
+        state.next = due;
+        Ok(state.next == state.rows.len())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<Tick, M> for DataFeed<M> {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        let rows = self.read()?;
+        debug!("Read {} rows from {}", rows.len(), self.path.display());
+        let contract = match &self.target {
+            FeedTarget::Messager => None,
+            FeedTarget::Contract {
+                address,
+                set_price_function,
+            } => {
+                let abi = parse_abi(&[&format!("function {}(uint256)", set_price_function)])?;
+                let contract = Contract::new(*address, abi, client.clone());
+                Some((contract, set_price_function.clone()))
+            }
+        };
+        self.state = Some(DataFeedState {
+            client: client.clone(),
+            messager,
+            contract,
+            rows,
+            next: 0,
+        });
+        if self.put_due().await? {
+            return Ok(None);
+        }
+        Ok(Some(self.trigger.stream(client)))
+    }
+
+    async fn process(&mut self, _tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        if self.put_due().await? {
+            let rows = self.state.as_ref().unwrap().rows.len();
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(rows as u64)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
@@ -0,0 +1,226 @@
+//! A [`Liquidator`] watches the health factors a lending contract emits and
+//! liquidates the accounts whose health factor falls below a threshold, as
+//! long as that pays for itself.
+//!
+//! The lending contract is described by its ABI and the names of its event
+//! and functions, so the liquidator works with any lending protocol that emits
+//! the health factor of an account, e.g.:
+//! ```toml
+//! [[liquidator]]
+//! [liquidator.Liquidator]
+//! lending = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+//! abi = "contracts/LendingPool.json"
+//! health_event = "HealthFactorUpdated"
+//! account_param = "account"
+//! health_factor_param = "healthFactor"
+//! liquidate_function = "liquidate"
+//! liquidate_args = [{ Param = "account" }]
+//! profit_function = "liquidationBonus"
+//! min_profit = "0xde0b6b3a7640000"
+//! max_gas_cost = "0x2386f26fc10000"
+//! ```
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use arbiter_core::middleware::{client::ArbiterClient, ArbiterMiddleware};
+use ethers::{
+    abi::{Event, RawLog, Token},
+    contract::Contract,
+    providers::Middleware,
+    types::{Address, Filter, Log, U256},
+};
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    events::{log_stream, read_abi},
+    machine::{Behavior, ControlFlow, EventStream, Supervision},
+};
+
+/// The health factor below which an account can be liquidated by default,
+/// i.e., `1.0` with 18 decimals.
+pub const DEFAULT_LIQUIDATION_THRESHOLD: U256 = U256([1_000_000_000_000_000_000, 0, 0, 0]);
+
+/// An argument of the liquidation call of a [`Liquidator`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Argument {
+    /// The parameter of the health factor event with the given name, e.g.,
+    /// the account.
+    Param(String),
+
+    /// A fixed address, e.g., of the collateral to seize.
+    Address(Address),
+
+    /// A fixed unsigned integer, e.g., the amount of debt to cover.
+    Uint(U256),
+
+    /// A fixed boolean.
+    Bool(bool),
+}
+
+/// Liquidates the accounts of a lending contract whose health factor falls
+/// below [`Liquidator::liquidation_threshold`].
+///
+/// Each time the contract emits [`Liquidator::health_event`], the liquidator
+/// reads the account and its health factor from the event. If the account is
+/// unhealthy, it estimates the gas cost of calling
+/// [`Liquidator::liquidate_function`] and only sends the call if the cost is
+/// at most [`Liquidator::max_gas_cost`] and, when a
+/// [`Liquidator::profit_function`] is given, the expected profit exceeds the
+/// cost by at least [`Liquidator::min_profit`].
+///
+/// The events an account cannot be liquidated on, e.g., because another
+/// liquidator was first, are skipped by default, see
+/// [`Liquidator::supervision`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Liquidator<M: ArbiterClient = ArbiterMiddleware> {
+    /// The address of the lending contract.
+    pub lending: Address,
+
+    /// The path to the ABI of the lending contract, either as a JSON array or
+    /// as the `abi` of a compiler artifact.
+    pub abi: PathBuf,
+
+    /// The name of the event the lending contract emits the health factor of
+    /// an account with.
+    pub health_event: String,
+
+    /// The name of the parameter of the event that holds the account.
+    pub account_param: String,
+
+    /// The name of the parameter of the event that holds the health factor.
+    pub health_factor_param: String,
+
+    /// The health factor below which an account is liquidated.
+    #[serde(default = "default_liquidation_threshold")]
+    pub liquidation_threshold: U256,
+
+    /// The name of the function that liquidates an account.
+    pub liquidate_function: String,
+
+    /// The arguments of the liquidation call. By default, the function is
+    /// called with the account alone.
+    #[serde(default)]
+    pub liquidate_args: Vec<Argument>,
+
+    /// The name of a view function that takes the account and returns the
+    /// profit of liquidating it in wei, if the profit is checked.
+    #[serde(default)]
+    pub profit_function: Option<String>,
+
+    /// The least profit in wei, net of the gas cost, that a liquidation must
+    /// make when a [`Liquidator::profit_function`] is given.
+    #[serde(default)]
+    pub min_profit: U256,
+
+    /// The most the gas of a liquidation may cost in wei, if it is capped.
+    #[serde(default)]
+    pub max_gas_cost: Option<U256>,
+
+    /// How the failures to liquidate an account are handled.
+    #[serde(default = "default_supervision")]
+    pub supervision: Supervision,
+
+    #[serde(skip)]
+    state: Option<(Contract<M>, Event)>,
+}
+
+fn default_liquidation_threshold() -> U256 {
+    DEFAULT_LIQUIDATION_THRESHOLD
+}
+
+fn default_supervision() -> Supervision {
+    Supervision::Skip
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<Log, M> for Liquidator<M> {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<Log>>> {
+        let abi = read_abi(&self.abi)?;
+        let event = abi.event(&self.health_event)?.clone();
+        let filter = Filter::new()
+            .address(self.lending)
+            .topic0(event.signature());
+        let logs = log_stream(client.clone(), filter).await?;
+        self.state = Some((Contract::new(self.lending, abi, client), event));
+        Ok(Some(logs))
+    }
+
+    async fn process(&mut self, log: Log) -> Result<ControlFlow, BehaviorError> {
+        let (contract, event) = self.state.as_ref().unwrap();
+        let params = event.parse_log(RawLog::from(log))?.params;
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|param| param.name == name)
+                .map(|param| param.value.clone())
+                .ok_or_else(|| {
+                    BehaviorError::msg(format!(
+                        "`{}` has no parameter `{}`",
+                        self.health_event, name
+                    ))
+                })
+        };
+        let account = param(&self.account_param)?
+            .into_address()
+            .ok_or_else(|| BehaviorError::msg("the account is not an address"))?;
+        let health_factor = param(&self.health_factor_param)?
+            .into_uint()
+            .ok_or_else(|| BehaviorError::msg("the health factor is not an integer"))?;
+        if health_factor >= self.liquidation_threshold {
+            return Ok(ControlFlow::Continue);
+        }
+
+        let args = if self.liquidate_args.is_empty() {
+            vec![Token::Address(account)]
+        } else {
+            self.liquidate_args
+                .iter()
+                .map(|argument| match argument {
+                    Argument::Param(name) => param(name),
+                    Argument::Address(address) => Ok(Token::Address(*address)),
+                    Argument::Uint(value) => Ok(Token::Uint(*value)),
+                    Argument::Bool(value) => Ok(Token::Bool(*value)),
+                })
+                .collect::<Result<_, _>>()?
+        };
+        let call = contract.method::<_, ()>(&self.liquidate_function, args.as_slice())?;
+        let gas = call.estimate_gas().await?;
+        let gas_cost = gas * contract.client().get_gas_price().await?;
+        if self.max_gas_cost.is_some_and(|max| gas_cost > max) {
+            debug!(
+                "Not liquidating {:?} as the gas would cost {} wei",
+                account, gas_cost
+            );
+            return Ok(ControlFlow::Continue);
+        }
+        if let Some(profit_function) = &self.profit_function {
+            let profit: U256 = contract.method(profit_function, account)?.call().await?;
+            if profit < gas_cost.saturating_add(self.min_profit) {
+                debug!(
+                    "Not liquidating {:?} as it would only make {} wei for {} wei of gas",
+                    account, profit, gas_cost
+                );
+                return Ok(ControlFlow::Continue);
+            }
+        }
+
+        info!(
+            "Liquidating {:?} with a health factor of {}",
+            account, health_factor
+        );
+        call.gas(gas).send().await?.await?;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn supervision(&self) -> Supervision {
+        self.supervision
+    }
+}
@@ -0,0 +1,17 @@
+//! The behaviors module contains ready-made [`Behavior`]s for common roles in
+//! simulations that are configured rather than written, e.g., from the
+//! configuration file of a [`World`](crate::world::World).
+//!
+//! [`Behavior`]: crate::machine::Behavior
+
+use super::*;
+
+pub mod block_admin;
+pub mod data_feed;
+pub mod liquidator;
+pub mod price_changer;
+pub mod probabilistic;
+pub mod stablecoin_keeper;
+pub mod token_admin;
+pub mod tracker;
+pub mod v3_swapper;
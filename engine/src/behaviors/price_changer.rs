@@ -0,0 +1,206 @@
+//! A [`PriceChanger`] draws a price path from a stochastic [`PriceProcess`] and
+//! pushes each price into a contract on the schedule of a [`Trigger`], e.g.,
+//! into a mock oracle or a `LiquidExchange`, so that the other agents of a
+//! world trade against an exogenous price:
+//! ```toml
+//! [[price_changer]]
+//! [price_changer.PriceChanger]
+//! target = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+//! process = { Gbm = { drift = 0.05, volatility = 0.2 } }
+//! initial_price = 1000.0
+//! time_step = 0.0027
+//! trigger = { Blocks = 1 }
+//! steps = 365
+//! ```
+//!
+//! The draws of the process come from the random number generator of the
+//! agent, see [`Messager::rng`], so the price path is the same in every run of
+//! a world with the same seed.
+
+use anyhow::Result;
+use arbiter_core::middleware::{client::ArbiterClient, ArbiterMiddleware};
+use ethers::{
+    abi::parse_abi,
+    contract::Contract,
+    types::{Address, U256},
+    utils::parse_ether,
+};
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, HaltReason},
+    trigger::{Tick, Trigger},
+};
+
+/// A stochastic process that prices are drawn from, whose parameters are per
+/// unit of time, e.g., per year.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PriceProcess {
+    /// Geometric Brownian motion, `dS = drift * S * dt + volatility * S * dW`,
+    /// whose log returns are normally distributed.
+    Gbm {
+        /// The expected rate of return.
+        drift: f64,
+        /// The standard deviation of the log returns.
+        volatility: f64,
+    },
+
+    /// The Ornstein-Uhlenbeck process,
+    /// `dS = mean_reversion * (mean - S) * dt + volatility * dW`, which reverts
+    /// to its mean, e.g., for the price of a stablecoin.
+    OrnsteinUhlenbeck {
+        /// How fast the price reverts to its mean.
+        mean_reversion: f64,
+        /// The mean the price reverts to.
+        mean: f64,
+        /// The standard deviation of the changes of the price.
+        volatility: f64,
+    },
+}
+
+impl PriceProcess {
+    /// Returns the price `time_step` after `price`, drawing the noise of the
+    /// process from `rng`.
+    ///
+    /// The price is sampled from the exact distribution of the process, so the
+    /// path does not depend on the size of the time step.
+    pub fn step(&self, price: f64, time_step: f64, rng: &mut impl Rng) -> f64 {
+        let noise: f64 = rng.sample(StandardNormal);
+        match *self {
+            PriceProcess::Gbm { drift, volatility } => {
+                price
+                    * ((drift - volatility.powi(2) / 2.0) * time_step
+                        + volatility * time_step.sqrt() * noise)
+                        .exp()
+            }
+            PriceProcess::OrnsteinUhlenbeck {
+                mean_reversion,
+                mean,
+                volatility,
+            } => {
+                if mean_reversion == 0.0 {
+                    return price + volatility * time_step.sqrt() * noise;
+                }
+                let decay = (-mean_reversion * time_step).exp();
+                let deviation =
+                    volatility * ((1.0 - decay.powi(2)) / (2.0 * mean_reversion)).sqrt();
+                mean + (price - mean) * decay + deviation * noise
+            }
+        }
+    }
+}
+
+/// Pushes the prices of a [`PriceProcess`] into a contract on the schedule of
+/// a [`Trigger`].
+///
+/// On startup, the price of the contract is set to
+/// [`PriceChanger::initial_price`]. On every tick of
+/// [`PriceChanger::trigger`], the process moves
+/// [`PriceChanger::time_step`] forward and the new price is set with
+/// [`PriceChanger::set_price_function`], as a fixed point number with 18
+/// decimals. The price changer halts once it made
+/// [`PriceChanger::steps`] steps, or fails if the price becomes negative.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct PriceChanger<M: ArbiterClient = ArbiterMiddleware> {
+    /// The address of the contract whose price is changed.
+    pub target: Address,
+
+    /// The name of the function of the contract that sets the price, which
+    /// takes it as its only `uint256` argument.
+    #[serde(default = "default_set_price_function")]
+    pub set_price_function: String,
+
+    /// The process the prices are drawn from.
+    pub process: PriceProcess,
+
+    /// The price the path starts at.
+    pub initial_price: f64,
+
+    /// The time the process moves forward on every tick, in the unit of time
+    /// of its parameters.
+    pub time_step: f64,
+
+    /// The schedule the price is changed on.
+    pub trigger: Trigger,
+
+    /// The number of steps after which the price changer halts, if it does.
+    #[serde(default)]
+    pub steps: Option<u64>,
+
+    #[serde(skip)]
+    state: Option<PriceChangerState<M>>,
+}
+
+#[derive(Debug)]
+struct PriceChangerState<M> {
+    contract: Contract<M>,
+    messager: Messager,
+    price: f64,
+    steps: u64,
+}
+
+pub(crate) fn default_set_price_function() -> String {
+    "setPrice".to_owned()
+}
+
+/// Sets the price of `contract` to `price` with its function `function`, as a
+/// fixed point number with 18 decimals.
+pub(crate) async fn set_price<M: ArbiterClient>(
+    contract: &Contract<M>,
+    function: &str,
+    price: f64,
+) -> Result<()> {
+    if !(price >= 0.0 && price.is_finite()) {
+        anyhow::bail!("the price {} cannot be set", price);
+    }
+    let price: U256 = parse_ether(format!("{:.18}", price))?;
+    contract
+        .method::<_, ()>(function, price)?
+        .send()
+        .await?
+        .await?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<Tick, M> for PriceChanger<M> {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        let abi = parse_abi(&[&format!("function {}(uint256)", self.set_price_function)])?;
+        let contract = Contract::new(self.target, abi, client.clone());
+        set_price(&contract, &self.set_price_function, self.initial_price).await?;
+        self.state = Some(PriceChangerState {
+            contract,
+            messager,
+            price: self.initial_price,
+            steps: 0,
+        });
+        if self.steps == Some(0) {
+            return Ok(None);
+        }
+        Ok(Some(self.trigger.stream(client)))
+    }
+
+    async fn process(&mut self, _tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        let state = self.state.as_mut().unwrap();
+        let price = {
+            let mut rng = state.messager.rng();
+            self.process.step(state.price, self.time_step, &mut *rng)
+        };
+        set_price(&state.contract, &self.set_price_function, price).await?;
+        trace!("Changed the price to {}", price);
+        state.price = price;
+        state.steps += 1;
+        if self.steps.is_some_and(|steps| state.steps >= steps) {
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(state.steps)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
@@ -0,0 +1,117 @@
+//! A [`Probabilistic`] behavior wraps another behavior and only lets it
+//! process each event with a given probability, e.g., to model a noisy trader
+//! that acts on some blocks of a [`Trigger`](crate::trigger::Trigger) and sits
+//! the others out:
+//! ```toml
+//! [[noise_trader]]
+//! [noise_trader.NoiseTrader]
+//! probability = 0.25
+//! behavior = { size = 10 }
+//! ```
+//! where the variant of the `Behaviors` enum is a `Probabilistic<Trader>`.
+//!
+//! Whether an event is processed is drawn from the random number generator of
+//! the agent, see the [`random`](crate::random) module, so the events that are
+//! processed are the same in every run of a world with the same seed.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use arbiter_core::middleware::client::ArbiterClient;
+use rand::Rng;
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    machine::{Backpressure, Behavior, ControlFlow, EventStream, Supervision},
+};
+
+/// Lets the behavior it wraps process each event with
+/// [`Probabilistic::probability`], see the [module documentation](self).
+///
+/// Everything else, e.g., the startup, the supervision, and the dependencies
+/// of the behavior, is the wrapped behavior's own.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Probabilistic<B> {
+    /// The probability that an event is processed, between `0.0` and `1.0`.
+    pub probability: f64,
+
+    /// The behavior that processes the events.
+    pub behavior: B,
+
+    #[serde(skip)]
+    messager: Option<Messager>,
+}
+
+impl<B> Probabilistic<B> {
+    /// Lets `behavior` process each event with `probability`.
+    pub fn new(probability: f64, behavior: B) -> Self {
+        Self {
+            probability,
+            behavior,
+            messager: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<B, E, M> Behavior<E, M> for Probabilistic<B>
+where
+    B: Behavior<E, M>,
+    E: Send + 'static,
+    M: ArbiterClient,
+{
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<E>>> {
+        if !(0.0..=1.0).contains(&self.probability) {
+            anyhow::bail!(
+                "The probability {} is not between 0 and 1",
+                self.probability
+            );
+        }
+        self.messager = Some(messager.clone());
+        self.behavior.startup(client, messager).await
+    }
+
+    async fn process(&mut self, event: E) -> Result<ControlFlow, BehaviorError> {
+        let draw = self
+            .messager
+            .as_ref()
+            .unwrap()
+            .rng()
+            .gen_bool(self.probability);
+        if !draw {
+            trace!("Skipped an event with probability {}", self.probability);
+            return Ok(ControlFlow::Continue);
+        }
+        self.behavior.process(event).await
+    }
+
+    fn supervision(&self) -> Supervision {
+        self.behavior.supervision()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.behavior.timeout()
+    }
+
+    fn backpressure(&self) -> Option<Backpressure> {
+        self.behavior.backpressure()
+    }
+
+    fn reload(&mut self, reconfigured: Self) {
+        self.probability = reconfigured.probability;
+        self.behavior.reload(reconfigured.behavior);
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        self.behavior.dependencies()
+    }
+
+    async fn teardown(&mut self) {
+        self.behavior.teardown().await
+    }
+}
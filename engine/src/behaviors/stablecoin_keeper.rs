@@ -0,0 +1,347 @@
+//! A [`StablecoinKeeper`] defends the peg of a stablecoin: on the schedule of
+//! a [`Trigger`], it compares the market price of the stablecoin with its
+//! peg and mints more of it when it trades above the peg or redeems it when
+//! it trades below, optionally arbitraging the difference on an exchange.
+//! It serves as a reference for the keepers of worlds that stress-test
+//! algorithmic stablecoins:
+//! ```toml
+//! [[keeper]]
+//! [keeper.StablecoinKeeper]
+//! stablecoin = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+//! price = { Oracle = { address = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512" } }
+//! peg = 1.0
+//! band = 0.005
+//! amount = "0x8ac7230489e80000"
+//! trigger = { Blocks = 1 }
+//! mint = { function = "mint", args = ["Keeper", "Amount"] }
+//! redeem = { function = "redeem", args = ["Amount"] }
+//! arbitrage = { exchange = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512", collateral = "0x9fE46736679d2D9a65F0992F2272dE9f3c7fa6e0" }
+//! ```
+//! The mint and redeem calls are described by the names and arguments of
+//! their functions, so the keeper works with any stablecoin that can be
+//! minted and redeemed by an account, e.g., through a peg stability module.
+
+use anyhow::Result;
+use arbiter_core::middleware::{client::ArbiterClient, ArbiterMiddleware};
+use ethers::{
+    abi::{parse_abi, Token},
+    contract::Contract,
+    types::{Address, U256},
+    utils::{format_units, parse_ether},
+};
+
+use super::*;
+use crate::{
+    behaviors::tracker::PriceSource,
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, Supervision},
+    trigger::{Tick, Trigger},
+};
+
+/// An argument of a call of a [`StablecoinKeeper`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeeperArgument {
+    /// The amount of the stablecoin that is minted or redeemed, see
+    /// [`StablecoinKeeper::amount`].
+    Amount,
+
+    /// The address of the keeper, e.g., the recipient of a mint.
+    Keeper,
+
+    /// A fixed address.
+    Address(Address),
+
+    /// A fixed unsigned integer.
+    Uint(U256),
+}
+
+/// A call that mints or redeems the stablecoin of a [`StablecoinKeeper`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeeperCall {
+    /// The address of the contract that is called, which is the stablecoin
+    /// unless it is given, e.g., a peg stability module.
+    #[serde(default)]
+    pub contract: Option<Address>,
+
+    /// The name of the function.
+    pub function: String,
+
+    /// The arguments of the function, by default the amount alone.
+    #[serde(default = "default_args")]
+    pub args: Vec<KeeperArgument>,
+}
+
+impl KeeperCall {
+    fn new(function: &str) -> Self {
+        Self {
+            contract: None,
+            function: function.to_owned(),
+            args: default_args(),
+        }
+    }
+
+    /// Returns the contract of the call for `client`, whose ABI only holds
+    /// the function of the call.
+    fn contract<M: ArbiterClient>(
+        &self,
+        stablecoin: Address,
+        client: Arc<M>,
+    ) -> Result<Contract<M>> {
+        let types: Vec<_> = self
+            .args
+            .iter()
+            .map(|argument| match argument {
+                KeeperArgument::Amount | KeeperArgument::Uint(_) => "uint256",
+                KeeperArgument::Keeper | KeeperArgument::Address(_) => "address",
+            })
+            .collect();
+        let abi = parse_abi(&[&format!("function {}({})", self.function, types.join(","))])?;
+        Ok(Contract::new(
+            self.contract.unwrap_or(stablecoin),
+            abi,
+            client,
+        ))
+    }
+
+    /// Returns the arguments of the call for a keeper at `keeper` that mints
+    /// or redeems `amount`.
+    fn tokens(&self, keeper: Address, amount: U256) -> Vec<Token> {
+        self.args
+            .iter()
+            .map(|argument| match argument {
+                KeeperArgument::Amount => Token::Uint(amount),
+                KeeperArgument::Keeper => Token::Address(keeper),
+                KeeperArgument::Address(address) => Token::Address(*address),
+                KeeperArgument::Uint(value) => Token::Uint(*value),
+            })
+            .collect()
+    }
+}
+
+/// An exchange that a [`StablecoinKeeper`] arbitrages the stablecoin on, with
+/// the `swap(address,uint256)` function of a `LiquidExchange`, which takes
+/// the token that is sold and its amount.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Arbitrage {
+    /// The address of the exchange.
+    pub exchange: Address,
+
+    /// The address of the token that the stablecoin is traded against.
+    pub collateral: Address,
+}
+
+/// Mints the stablecoin when its price is above the peg and redeems it when
+/// its price is below, see the [module documentation](self).
+///
+/// On every tick of [`StablecoinKeeper::trigger`], the keeper reads the price
+/// of the stablecoin. If it is more than [`StablecoinKeeper::band`] above
+/// [`StablecoinKeeper::peg`], relative to the peg, the keeper mints
+/// [`StablecoinKeeper::amount`] of the stablecoin and, if it arbitrages,
+/// sells it on the exchange. If it is as far below, the keeper, if it
+/// arbitrages, buys the amount on the exchange, and redeems it. Both move the
+/// price back towards the peg as long as the market reacts to the supply of
+/// the stablecoin.
+///
+/// The ticks the keeper fails to act on, e.g., because it ran out of
+/// collateral, are skipped by default, see
+/// [`StablecoinKeeper::supervision`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct StablecoinKeeper<M: ArbiterClient = ArbiterMiddleware> {
+    /// The address of the stablecoin, an ERC-20 token.
+    pub stablecoin: Address,
+
+    /// Where the price of the stablecoin is read from.
+    pub price: PriceSource,
+
+    /// The price the stablecoin is pegged to.
+    #[serde(default = "default_peg")]
+    pub peg: f64,
+
+    /// How far the price can be from the peg, relative to the peg, before the
+    /// keeper acts.
+    #[serde(default = "default_band")]
+    pub band: f64,
+
+    /// The amount of the stablecoin that is minted or redeemed on a tick.
+    pub amount: U256,
+
+    /// The schedule the price is checked on.
+    pub trigger: Trigger,
+
+    /// The call that mints the stablecoin, by default `mint(amount)`.
+    #[serde(default = "default_mint")]
+    pub mint: KeeperCall,
+
+    /// The call that redeems the stablecoin, by default `redeem(amount)`.
+    #[serde(default = "default_redeem")]
+    pub redeem: KeeperCall,
+
+    /// The exchange the stablecoin is arbitraged on, if it is.
+    #[serde(default)]
+    pub arbitrage: Option<Arbitrage>,
+
+    /// How the failures to act on a tick are handled.
+    #[serde(default = "default_supervision")]
+    pub supervision: Supervision,
+
+    #[serde(skip)]
+    state: Option<StablecoinKeeperState<M>>,
+}
+
+#[derive(Debug)]
+struct StablecoinKeeperState<M> {
+    keeper: Address,
+    oracle: Option<Contract<M>>,
+    mint: Contract<M>,
+    redeem: Contract<M>,
+    exchange: Option<Contract<M>>,
+}
+
+fn default_args() -> Vec<KeeperArgument> {
+    vec![KeeperArgument::Amount]
+}
+
+fn default_peg() -> f64 {
+    1.0
+}
+
+fn default_band() -> f64 {
+    0.005
+}
+
+fn default_mint() -> KeeperCall {
+    KeeperCall::new("mint")
+}
+
+fn default_redeem() -> KeeperCall {
+    KeeperCall::new("redeem")
+}
+
+fn default_supervision() -> Supervision {
+    Supervision::Skip
+}
+
+impl<M: ArbiterClient> StablecoinKeeper<M> {
+    /// Reads the price of the stablecoin as a fixed point number with 18
+    /// decimals.
+    async fn read_price(&self) -> Result<U256> {
+        let state = self.state.as_ref().unwrap();
+        match (&self.price, &state.oracle) {
+            (PriceSource::Fixed(price), _) => Ok(parse_ether(format!("{:.18}", price))?),
+            (PriceSource::Oracle { function, .. }, Some(oracle)) => {
+                Ok(oracle.method(function, ())?.call().await?)
+            }
+            (PriceSource::Oracle { .. }, None) => unreachable!(),
+        }
+    }
+
+    /// Sends the `call` of `contract`.
+    async fn send(&self, call: &KeeperCall, contract: &Contract<M>) -> Result<()> {
+        let keeper = self.state.as_ref().unwrap().keeper;
+        contract
+            .method::<_, ()>(&call.function, call.tokens(keeper, self.amount).as_slice())?
+            .send()
+            .await?
+            .await?;
+        Ok(())
+    }
+
+    /// Sells `amount_in` of `token` on the exchange.
+    async fn swap(&self, token: Address, amount_in: U256) -> Result<()> {
+        let exchange = self.state.as_ref().unwrap().exchange.as_ref().unwrap();
+        exchange
+            .method::<_, ()>("swap", (token, amount_in))?
+            .send()
+            .await?
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<Tick, M> for StablecoinKeeper<M> {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        if !(self.band >= 0.0 && self.peg > 0.0) {
+            anyhow::bail!("The peg {} or the band {} is invalid", self.peg, self.band);
+        }
+        let oracle = match &self.price {
+            PriceSource::Fixed(_) => None,
+            PriceSource::Oracle { address, function } => {
+                let abi = parse_abi(&[&format!(
+                    "function {}() external view returns (uint256)",
+                    function
+                )])?;
+                Some(Contract::new(*address, abi, client.clone()))
+            }
+        };
+
+        // The exchange takes the tokens it is sold, so it is approved to take
+        // any amount of both.
+        let exchange = match &self.arbitrage {
+            Some(arbitrage) => {
+                let erc20 = parse_abi(&["function approve(address,uint256) returns (bool)"])?;
+                for token in [self.stablecoin, arbitrage.collateral] {
+                    Contract::new(token, erc20.clone(), client.clone())
+                        .method::<_, bool>("approve", (arbitrage.exchange, U256::MAX))?
+                        .send()
+                        .await?
+                        .await?;
+                }
+                let abi = parse_abi(&["function swap(address,uint256)"])?;
+                Some(Contract::new(arbitrage.exchange, abi, client.clone()))
+            }
+            None => None,
+        };
+
+        self.state = Some(StablecoinKeeperState {
+            keeper: client.address(),
+            oracle,
+            mint: self.mint.contract(self.stablecoin, client.clone())?,
+            redeem: self.redeem.contract(self.stablecoin, client.clone())?,
+            exchange,
+        });
+        Ok(Some(self.trigger.stream(client)))
+    }
+
+    async fn process(&mut self, _tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        let price = self.read_price().await?;
+        let deviation = format_units(price, 18)?.parse::<f64>()? / self.peg - 1.0;
+        if deviation.abs() <= self.band {
+            trace!("The price {} is within the band of the peg", price);
+            return Ok(ControlFlow::Continue);
+        }
+        let state = self.state.as_ref().unwrap();
+        if deviation > 0.0 {
+            info!(
+                "Minting {} as the price is {} above the peg",
+                self.amount, deviation
+            );
+            self.send(&self.mint, &state.mint).await?;
+            if self.arbitrage.is_some() {
+                self.swap(self.stablecoin, self.amount).await?;
+            }
+        } else {
+            info!(
+                "Redeeming {} as the price is {} below the peg",
+                self.amount, -deviation
+            );
+            if let Some(arbitrage) = &self.arbitrage {
+                // The collateral that buys the amount at the price, rounded up.
+                let wad = U256::exp10(18);
+                let amount_in = (self.amount * price + wad - 1) / wad;
+                self.swap(arbitrage.collateral, amount_in).await?;
+            }
+            self.send(&self.redeem, &state.redeem).await?;
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn supervision(&self) -> Supervision {
+        self.supervision
+    }
+}
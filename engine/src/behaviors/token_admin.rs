@@ -0,0 +1,500 @@
+//! A [`TokenAdmin`] bootstraps the tokens of a simulation: it deploys them,
+//! airdrops them to the agents that start out with a balance, and then serves
+//! the other agents as a faucet, so that every simulation does not have to
+//! write its own token admin:
+//! ```toml
+//! [[admin]]
+//! [admin.TokenAdmin]
+//! mint_limit = { amount = "0x3635c9adc5dea00000", period = 86400 }
+//!
+//! [admin.TokenAdmin.tokens.USDC]
+//! name = "US Dollar Coin"
+//! symbol = "USDC"
+//! decimals = 6
+//!
+//! [admin.TokenAdmin.airdrop.arbitrageur]
+//! USDC = "0xe8d4a51000"
+//!
+//! [admin.TokenAdmin.mint_limits]
+//! market_maker = { amount = "0xd3c21bcecceda1000000" }
+//! ```
+//! Another behavior asks for the address of a token or for tokens by sending
+//! a [`TokenAdminQuery`] to the agent of the token admin, which replies with
+//! the [`TokenAddress`] of the token or with a [`TokenAdminReply`]:
+//! ```ignore
+//! let query = TokenAdminQuery::MintRequest(MintRequest {
+//!     token: "USDC".to_owned(),
+//!     mint_to: client.address(),
+//!     mint_amount: parse_units(1000, 6)?.into(),
+//! });
+//! messager
+//!     .send_payload(To::Agent("admin".to_owned()), &query)
+//!     .await?;
+//! ```
+//! The reply is awaited by sending the query with [`Messager::request`]
+//! instead:
+//! ```ignore
+//! let query = TokenAdminQuery::AddressOf("USDC".to_owned());
+//! let reply = messager
+//!     .request::<_, TokenAddress>(To::Agent("admin".to_owned()), &query, timeout)
+//!     .await?;
+//! ```
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use arbiter_bindings::bindings::arbiter_token::ArbiterToken;
+use arbiter_core::middleware::ArbiterMiddleware;
+use arbiter_macros::Query;
+use ethers::types::{Address, U256};
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, HaltReason},
+    messager::{Message, Payload},
+};
+
+/// A token that a [`TokenAdmin`] deploys, or administers if it is already
+/// deployed.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenData {
+    /// The name of the token.
+    pub name: String,
+
+    /// The symbol of the token.
+    pub symbol: String,
+
+    /// The number of decimals of the token.
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+
+    /// The address of the token, which is set once the token admin deployed
+    /// it.
+    #[serde(default)]
+    pub address: Option<Address>,
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+/// How much of a token an agent can have minted by a [`TokenAdmin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintLimit {
+    /// The amount of the token that can be minted per period.
+    pub amount: U256,
+
+    /// The number of seconds of block time after which the amount can be
+    /// minted again, or `None` if it can only be minted once.
+    #[serde(default)]
+    pub period: Option<u64>,
+}
+
+/// A query that a [`TokenAdmin`] answers, sent as the data of a message to
+/// its agent.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Query)]
+pub enum TokenAdminQuery {
+    /// Asks for the address of the token with the given key, which is replied
+    /// to with a [`TokenAddress`].
+    #[reply(TokenAddress)]
+    AddressOf(String),
+
+    /// Mints tokens.
+    #[reply(TokenAdminReply)]
+    MintRequest(MintRequest),
+
+    /// Burns tokens.
+    #[reply(TokenAdminReply)]
+    BurnRequest(BurnRequest),
+
+    /// Approves a spender on behalf of an owner.
+    #[reply(TokenAdminReply)]
+    ApproveRequest(ApproveRequest),
+}
+
+/// The address of a token, or `None` if the [`TokenAdmin`] has no token with
+/// the key it was asked for, which is serialized as an `Option<Address>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct TokenAddress(pub Option<Address>);
+
+impl Payload for TokenAddress {
+    const TAG: &'static str = "TokenAddress";
+}
+
+/// A request to mint tokens.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintRequest {
+    /// The key of the token to mint.
+    pub token: String,
+
+    /// The address to mint to.
+    pub mint_to: Address,
+
+    /// The amount to mint.
+    pub mint_amount: U256,
+}
+
+/// A request to burn tokens.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BurnRequest {
+    /// The key of the token to burn.
+    pub token: String,
+
+    /// The address to burn from.
+    pub burn_from: Address,
+
+    /// The amount to burn.
+    pub burn_amount: U256,
+}
+
+/// A request to approve a spender of tokens on behalf of their owner, e.g.,
+/// to set up the allowances of an exchange before a simulation starts.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApproveRequest {
+    /// The key of the token to approve.
+    pub token: String,
+
+    /// The owner of the tokens.
+    pub owner: Address,
+
+    /// The spender that is approved.
+    pub spender: Address,
+
+    /// The amount the spender is approved to spend.
+    pub amount: U256,
+}
+
+/// What a [`TokenAdmin`] replies to a mint, burn, or approve request with.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TokenAdminReply {
+    /// The request was carried out.
+    Done,
+
+    /// The mint request exceeds the [`MintLimit`] of the agent, which can
+    /// have at most the given amount minted for the rest of the period.
+    RateLimited {
+        /// The amount the agent can still have minted.
+        remaining: U256,
+    },
+
+    /// The request failed, e.g., because the token is unknown or the burn
+    /// exceeds the balance.
+    Failed(String),
+}
+
+impl Payload for TokenAdminReply {
+    const TAG: &'static str = "TokenAdminReply";
+}
+
+/// Deploys tokens, airdrops them, and mints, burns, and approves them on
+/// request, see the [module documentation](self).
+///
+/// The tokens are [`ArbiterToken`]s keyed by the name requests refer to
+/// them with. A token that has an address is not deployed but administered
+/// as it is, so the client of the token admin must be its admin, e.g., when
+/// the token admin is resumed from a checkpoint.
+///
+/// Since an [`ArbiterToken`] cannot be burned, tokens are burned by sending
+/// them to the zero address on behalf of their holder, which leaves the total
+/// supply of the token as it is. Likewise, approvals are sent on behalf of
+/// the owner, so both can be requested for any account.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TokenAdmin {
+    /// The tokens, keyed by the name requests refer to them with.
+    pub tokens: BTreeMap<String, TokenData>,
+
+    /// The amounts of the tokens, by key, minted to agents or addresses when
+    /// the tokens are deployed, keyed by the id of the agent or the address.
+    #[serde(default)]
+    pub airdrop: BTreeMap<String, BTreeMap<String, U256>>,
+
+    /// How much of each token every agent can have minted, if it is limited.
+    #[serde(default)]
+    pub mint_limit: Option<MintLimit>,
+
+    /// The mint limits of single agents, keyed by the id of the agent, which
+    /// override [`TokenAdmin::mint_limit`].
+    #[serde(default)]
+    pub mint_limits: BTreeMap<String, MintLimit>,
+
+    /// The number of requests after which the token admin halts, if it does.
+    #[serde(default)]
+    pub max_count: Option<u64>,
+
+    #[serde(skip)]
+    state: Option<TokenAdminState>,
+}
+
+#[derive(Clone, Debug)]
+struct TokenAdminState {
+    client: Arc<ArbiterMiddleware>,
+    messager: Messager,
+    contracts: BTreeMap<String, ArbiterToken<ArbiterMiddleware>>,
+
+    /// The start of the current period of each agent and token, and the
+    /// amount minted in it.
+    minted: HashMap<(String, String), (u64, U256)>,
+
+    count: u64,
+}
+
+impl TokenAdmin {
+    /// Creates a [`TokenAdmin`] without tokens.
+    pub fn new() -> Self {
+        Self {
+            tokens: BTreeMap::new(),
+            airdrop: BTreeMap::new(),
+            mint_limit: None,
+            mint_limits: BTreeMap::new(),
+            max_count: None,
+            state: None,
+        }
+    }
+
+    /// Deploys the token `data` under the key `token`.
+    pub fn with_token(mut self, token: &str, data: TokenData) -> Self {
+        self.tokens.insert(token.to_owned(), data);
+        self
+    }
+
+    /// Mints `amount` of the token `token` to `recipient`, the id of an agent
+    /// or an address, once the token is deployed.
+    pub fn with_airdrop(mut self, recipient: &str, token: &str, amount: U256) -> Self {
+        self.airdrop
+            .entry(recipient.to_owned())
+            .or_default()
+            .insert(token.to_owned(), amount);
+        self
+    }
+
+    /// Limits how much of each token every agent can have minted.
+    pub fn with_mint_limit(mut self, limit: MintLimit) -> Self {
+        self.mint_limit = Some(limit);
+        self
+    }
+
+    /// Limits how much of each token the agent `agent` can have minted,
+    /// instead of [`TokenAdmin::mint_limit`].
+    pub fn with_agent_mint_limit(mut self, agent: &str, limit: MintLimit) -> Self {
+        self.mint_limits.insert(agent.to_owned(), limit);
+        self
+    }
+
+    /// Halts the token admin once it served `max_count` requests.
+    pub fn with_max_count(mut self, max_count: u64) -> Self {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    fn contract(&self, token: &str) -> Result<&ArbiterToken<ArbiterMiddleware>, String> {
+        self.state
+            .as_ref()
+            .unwrap()
+            .contracts
+            .get(token)
+            .ok_or_else(|| format!("`{}` is not a token of the token admin", token))
+    }
+
+    /// Returns how much more of `token` the agent `agent` can have minted in
+    /// its current period, if it is limited, starting a new period if the
+    /// last one is over.
+    async fn remaining(&mut self, agent: &str, token: &str) -> Result<Option<U256>> {
+        let Some(limit) = self.mint_limits.get(agent).or(self.mint_limit.as_ref()) else {
+            return Ok(None);
+        };
+        let state = self.state.as_mut().unwrap();
+        let now = state.client.get_block_timestamp().await?.as_u64();
+        let (start, minted) = state
+            .minted
+            .entry((agent.to_owned(), token.to_owned()))
+            .or_insert((now, U256::zero()));
+        if limit.period.is_some_and(|period| now >= *start + period) {
+            *start = now;
+            *minted = U256::zero();
+        }
+        Ok(Some(limit.amount.saturating_sub(*minted)))
+    }
+}
+
+impl Default for TokenAdmin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl TokenAdminQueryHandler for TokenAdmin {
+    async fn address_of(
+        &mut self,
+        _from: &str,
+        token: String,
+    ) -> Result<TokenAddress, BehaviorError> {
+        Ok(TokenAddress(
+            self.tokens.get(&token).and_then(|data| data.address),
+        ))
+    }
+
+    async fn mint_request(
+        &mut self,
+        agent: &str,
+        request: MintRequest,
+    ) -> Result<TokenAdminReply, BehaviorError> {
+        let contract = match self.contract(&request.token) {
+            Ok(contract) => contract.clone(),
+            Err(e) => return Ok(TokenAdminReply::Failed(e)),
+        };
+        let remaining = self.remaining(agent, &request.token).await?;
+        if let Some(remaining) = remaining.filter(|remaining| request.mint_amount > *remaining) {
+            debug!("Rate limited a mint of {:?} by `{}`", request, agent);
+            return Ok(TokenAdminReply::RateLimited { remaining });
+        }
+        if let Err(e) = send(contract.mint(request.mint_to, request.mint_amount)).await {
+            return Ok(TokenAdminReply::Failed(e));
+        }
+        if remaining.is_some() {
+            let state = self.state.as_mut().unwrap();
+            let key = (agent.to_owned(), request.token);
+            state.minted.get_mut(&key).unwrap().1 += request.mint_amount;
+        }
+        Ok(TokenAdminReply::Done)
+    }
+
+    async fn burn_request(
+        &mut self,
+        _from: &str,
+        request: BurnRequest,
+    ) -> Result<TokenAdminReply, BehaviorError> {
+        let contract = match self.contract(&request.token) {
+            Ok(contract) => contract,
+            Err(e) => return Ok(TokenAdminReply::Failed(e)),
+        };
+        let client = self
+            .state
+            .as_ref()
+            .unwrap()
+            .client
+            .impersonate(request.burn_from);
+        let holder = ArbiterToken::new(contract.address(), client);
+        Ok(
+            match send(holder.transfer(Address::zero(), request.burn_amount)).await {
+                Ok(()) => TokenAdminReply::Done,
+                Err(e) => TokenAdminReply::Failed(e),
+            },
+        )
+    }
+
+    async fn approve_request(
+        &mut self,
+        _from: &str,
+        request: ApproveRequest,
+    ) -> Result<TokenAdminReply, BehaviorError> {
+        let contract = match self.contract(&request.token) {
+            Ok(contract) => contract,
+            Err(e) => return Ok(TokenAdminReply::Failed(e)),
+        };
+        let client = self
+            .state
+            .as_ref()
+            .unwrap()
+            .client
+            .impersonate(request.owner);
+        let owner = ArbiterToken::new(contract.address(), client);
+        Ok(
+            match send(owner.approve(request.spender, request.amount)).await {
+                Ok(()) => TokenAdminReply::Done,
+                Err(e) => TokenAdminReply::Failed(e),
+            },
+        )
+    }
+}
+
+/// Sends the transaction of `call` and waits for it to be mined.
+async fn send<D: ethers::abi::Detokenize>(
+    call: ethers::contract::ContractCall<ArbiterMiddleware, D>,
+) -> Result<(), String> {
+    call.send()
+        .await
+        .map_err(|e| e.to_string())?
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for TokenAdmin {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        let mut contracts = BTreeMap::new();
+        let mut deployed = vec![];
+        for (key, data) in self.tokens.iter_mut() {
+            let contract = match data.address {
+                Some(address) => ArbiterToken::new(address, client.clone()),
+                None => {
+                    let contract = ArbiterToken::deploy(
+                        client.clone(),
+                        (data.name.clone(), data.symbol.clone(), data.decimals),
+                    )?
+                    .send()
+                    .await?;
+                    data.address = Some(contract.address());
+                    deployed.push(key.clone());
+                    contract
+                }
+            };
+            debug!("Token `{}` is at {:?}", key, contract.address());
+            contracts.insert(key.clone(), contract);
+        }
+
+        // Only the tokens deployed now are airdropped, since the others were
+        // bootstrapped before, e.g., by the token admin that was checkpointed.
+        for (recipient, amounts) in &self.airdrop {
+            let address = match recipient.parse::<Address>() {
+                Ok(address) => address,
+                Err(_) => messager.recorder.address(recipient).ok_or_else(|| {
+                    anyhow::anyhow!("`{}` is neither an address nor an agent", recipient)
+                })?,
+            };
+            for (token, amount) in amounts {
+                let Some(contract) = contracts.get(token) else {
+                    anyhow::bail!("`{}` is not a token of the token admin", token);
+                };
+                if deployed.contains(token) {
+                    contract.mint(address, *amount).send().await?.await?;
+                }
+            }
+        }
+
+        self.state = Some(TokenAdminState {
+            client,
+            messager: messager.clone(),
+            contracts,
+            minted: HashMap::new(),
+            count: 0,
+        });
+        Ok(Some(messager.stream()?))
+    }
+
+    async fn process(&mut self, message: Message) -> Result<ControlFlow, BehaviorError> {
+        let messager = self.state.as_ref().unwrap().messager.clone();
+        if !TokenAdminQuery::dispatch(self, &messager, &message).await? {
+            return Ok(ControlFlow::Continue);
+        }
+        let state = self.state.as_mut().unwrap();
+        state.count += 1;
+        if self.max_count.is_some_and(|max| state.count >= max) {
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(state.count)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn reload(&mut self, reconfigured: Self) {
+        self.mint_limit = reconfigured.mint_limit;
+        self.mint_limits = reconfigured.mint_limits;
+        self.max_count = reconfigured.max_count;
+    }
+}
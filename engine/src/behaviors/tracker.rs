@@ -0,0 +1,342 @@
+//! A [`Tracker`] follows the balances of a set of agents in a set of tokens
+//! and marks them to market, so that the profit and loss of every agent over
+//! a simulation can be analyzed afterwards:
+//! ```toml
+//! [[tracker]]
+//! [tracker.Tracker]
+//! agents = ["arbitrageur", "liquidity_provider"]
+//! exchanges = ["0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512"]
+//! output = "output/pnl.csv"
+//!
+//! [[tracker.Tracker.tokens]]
+//! name = "USDC"
+//! address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+//! decimals = 6
+//!
+//! [[tracker.Tracker.tokens]]
+//! name = "WETH"
+//! address = "0x9fE46736679d2D9a65F0992F2272dE9f3c7fa6e0"
+//! price = { Oracle = { address = "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512" } }
+//! ```
+//!
+//! The time series is written to [`Tracker::output`] as a CSV file with a row
+//! per account each time the balances or prices may have changed, with the
+//! columns `block_number`, `account`, the balance in each token by name,
+//! `value`, and `pnl`.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use arbiter_bindings::bindings::{arbiter_token::TransferFilter, liquid_exchange::SwapFilter};
+use arbiter_core::{
+    middleware::{client::ArbiterClient, ArbiterMiddleware},
+    shutdown::{ShutdownCoordinator, ShutdownHandle},
+};
+use ethers::{
+    abi::{parse_abi, RawLog},
+    contract::{Contract, EthEvent},
+    types::{Address, Filter, Log, U256},
+    utils::format_units,
+};
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    events::log_stream,
+    machine::{Behavior, ControlFlow, EventStream, EventStreams},
+};
+
+/// A token whose balances a [`Tracker`] follows.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackedToken {
+    /// The name of the column of the balances in the token.
+    pub name: String,
+
+    /// The address of the token, which is an ERC-20.
+    pub address: Address,
+
+    /// The number of decimals of the token.
+    #[serde(default = "default_decimals")]
+    pub decimals: u32,
+
+    /// Where the price of the token comes from, by default a price of `1.0`,
+    /// e.g., for the token the other prices are quoted in.
+    #[serde(default = "default_price")]
+    pub price: PriceSource,
+}
+
+/// Where a [`Tracker`] reads the price of a token from.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PriceSource {
+    /// A fixed price.
+    Fixed(f64),
+
+    /// A view function of a contract that returns the price as a fixed point
+    /// number with 18 decimals, e.g., the `price` of a `LiquidExchange`.
+    Oracle {
+        /// The address of the contract.
+        address: Address,
+        /// The name of the function, which takes no arguments.
+        #[serde(default = "default_price_function")]
+        function: String,
+    },
+}
+
+/// The events a [`Tracker`] processes.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum TrackerEvent {
+    /// A tracked token was transferred.
+    Transfer(Log),
+
+    /// A swap on one of the exchanges, which may have moved the prices.
+    Swap(Log),
+}
+
+/// Follows the balances of agents and accounts in a set of tokens and writes
+/// their value and profit and loss to a CSV file, see the
+/// [module documentation](self).
+///
+/// The balances are read on startup and again for the accounts involved in
+/// each `Transfer` of a token. The accounts are marked to market on startup,
+/// after each transfer, and after each `Swap` of [`Tracker::exchanges`], and
+/// the profit and loss of an account is its value less its value on startup.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct Tracker<M: ArbiterClient = ArbiterMiddleware> {
+    /// The tokens that are tracked.
+    pub tokens: Vec<TrackedToken>,
+
+    /// The ids of the agents of the world that are tracked.
+    #[serde(default)]
+    pub agents: Vec<String>,
+
+    /// Other accounts that are tracked, by the name they are written with.
+    #[serde(default)]
+    pub accounts: BTreeMap<String, Address>,
+
+    /// The `LiquidExchange`s whose swaps the accounts are marked to market
+    /// on.
+    #[serde(default)]
+    pub exchanges: Vec<Address>,
+
+    /// The path of the CSV file the time series is written to.
+    pub output: PathBuf,
+
+    #[serde(skip)]
+    state: Option<TrackerState<M>>,
+}
+
+#[derive(Debug)]
+struct TrackerState<M> {
+    tokens: Vec<Contract<M>>,
+    oracles: Vec<Option<Contract<M>>>,
+    accounts: Vec<(String, Address)>,
+    /// The balances of each account in each token.
+    balances: Vec<Vec<f64>>,
+    /// The value of each account on startup.
+    initial: Vec<f64>,
+    writer: BufWriter<File>,
+    /// Held until the file is flushed on teardown.
+    _shutdown: ShutdownHandle,
+}
+
+fn default_decimals() -> u32 {
+    18
+}
+
+fn default_price() -> PriceSource {
+    PriceSource::Fixed(1.0)
+}
+
+fn default_price_function() -> String {
+    "price".to_owned()
+}
+
+impl<M: ArbiterClient> Tracker<M> {
+    /// Reads the balance of `account` in the token at `index`.
+    async fn balance(&self, index: usize, account: Address) -> Result<f64> {
+        let state = self.state.as_ref().unwrap();
+        let balance: U256 = state.tokens[index]
+            .method("balanceOf", account)?
+            .call()
+            .await?;
+        Ok(format_units(balance, self.tokens[index].decimals)?.parse()?)
+    }
+
+    /// Reads the current price of each token.
+    async fn prices(&self) -> Result<Vec<f64>> {
+        let state = self.state.as_ref().unwrap();
+        let mut prices = Vec::with_capacity(self.tokens.len());
+        for (token, oracle) in self.tokens.iter().zip(&state.oracles) {
+            let price = match (&token.price, oracle) {
+                (PriceSource::Fixed(price), _) => *price,
+                (PriceSource::Oracle { function, .. }, Some(oracle)) => {
+                    let price: U256 = oracle.method(function, ())?.call().await?;
+                    format_units(price, 18)?.parse()?
+                }
+                (PriceSource::Oracle { .. }, None) => unreachable!(),
+            };
+            prices.push(price);
+        }
+        Ok(prices)
+    }
+
+    /// Returns the value of each account at the current prices.
+    async fn values(&self) -> Result<Vec<f64>> {
+        let prices = self.prices().await?;
+        let state = self.state.as_ref().unwrap();
+        Ok(state
+            .balances
+            .iter()
+            .map(|balances| balances.iter().zip(&prices).map(|(b, p)| b * p).sum())
+            .collect())
+    }
+
+    /// Marks the accounts to market and writes a row for each of them.
+    async fn mark(&mut self, block_number: u64) -> Result<()> {
+        let values = self.values().await?;
+        let state = self.state.as_mut().unwrap();
+        for (index, (name, _)) in state.accounts.iter().enumerate() {
+            write!(state.writer, "{},{}", block_number, name)?;
+            for balance in &state.balances[index] {
+                write!(state.writer, ",{}", balance)?;
+            }
+            let value = values[index];
+            writeln!(state.writer, ",{},{}", value, value - state.initial[index])?;
+        }
+        // The rows are flushed as they are written, so the file is complete
+        // whenever the simulation ends.
+        state.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<TrackerEvent, M> for Tracker<M> {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<TrackerEvent>>> {
+        let mut accounts = vec![];
+        for agent in &self.agents {
+            let Some(address) = messager.recorder.address(agent) else {
+                anyhow::bail!("`{}` is not an agent of the world", agent);
+            };
+            accounts.push((agent.clone(), address));
+        }
+        accounts.extend(self.accounts.clone());
+
+        let erc20 = parse_abi(&["function balanceOf(address) external view returns (uint256)"])?;
+        let tokens = self
+            .tokens
+            .iter()
+            .map(|token| Contract::new(token.address, erc20.clone(), client.clone()))
+            .collect();
+        let oracles = self
+            .tokens
+            .iter()
+            .map(|token| match &token.price {
+                PriceSource::Fixed(_) => Ok(None),
+                PriceSource::Oracle { address, function } => {
+                    let abi = parse_abi(&[&format!(
+                        "function {}() external view returns (uint256)",
+                        function
+                    )])?;
+                    Ok(Some(Contract::new(*address, abi, client.clone())))
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        // The events are watched for before the balances are read so that no
+        // transfer is missed.
+        let filter = Filter::new()
+            .address(
+                self.tokens
+                    .iter()
+                    .map(|token| token.address)
+                    .collect::<Vec<_>>(),
+            )
+            .topic0(TransferFilter::signature());
+        let mut streams = EventStreams::new().with(
+            log_stream(client.clone(), filter).await?,
+            TrackerEvent::Transfer,
+        );
+        if !self.exchanges.is_empty() {
+            let filter = Filter::new()
+                .address(self.exchanges.clone())
+                .topic0(SwapFilter::signature());
+            streams = streams.with(
+                log_stream(client.clone(), filter).await?,
+                TrackerEvent::Swap,
+            );
+        }
+
+        let mut writer = BufWriter::new(File::create(&self.output)?);
+        write!(writer, "block_number,account")?;
+        for token in &self.tokens {
+            write!(writer, ",{}", token.name)?;
+        }
+        writeln!(writer, ",value,pnl")?;
+        self.state = Some(TrackerState {
+            tokens,
+            oracles,
+            balances: vec![vec![0.0; self.tokens.len()]; accounts.len()],
+            initial: vec![0.0; accounts.len()],
+            accounts,
+            writer,
+            _shutdown: ShutdownCoordinator::global().register(),
+        });
+
+        let accounts = self.state.as_ref().unwrap().accounts.clone();
+        for (account, (_, address)) in accounts.iter().enumerate() {
+            for token in 0..self.tokens.len() {
+                let balance = self.balance(token, *address).await?;
+                self.state.as_mut().unwrap().balances[account][token] = balance;
+            }
+        }
+        let initial = self.values().await?;
+        self.state.as_mut().unwrap().initial = initial;
+        let block_number = client.get_block_number().await?.as_u64();
+        self.mark(block_number).await?;
+        Ok(Some(streams.merge()))
+    }
+
+    async fn process(&mut self, event: TrackerEvent) -> Result<ControlFlow, BehaviorError> {
+        let log = match event {
+            TrackerEvent::Transfer(log) => {
+                let token = self
+                    .tokens
+                    .iter()
+                    .position(|token| token.address == log.address)
+                    .unwrap();
+                let transfer = TransferFilter::decode_log(&RawLog::from(log.clone()))?;
+                let accounts = self.state.as_ref().unwrap().accounts.clone();
+                for (account, (_, address)) in accounts.iter().enumerate() {
+                    if *address == transfer.from || *address == transfer.to {
+                        let balance = self.balance(token, *address).await?;
+                        self.state.as_mut().unwrap().balances[account][token] = balance;
+                    }
+                }
+                log
+            }
+            TrackerEvent::Swap(log) => log,
+        };
+        let block_number = log.block_number.unwrap_or_default().as_u64();
+        self.mark(block_number).await?;
+        Ok(ControlFlow::Continue)
+    }
+
+    async fn teardown(&mut self) {
+        if let Some(mut state) = self.state.take() {
+            if let Err(e) = state.writer.flush() {
+                warn!("Could not flush {}: {}", self.output.display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,352 @@
+//! A [`V3Swapper`] trades a Uniswap V3 pool toward a target price on the
+//! schedule of a [`Trigger`], e.g., to stand in for the arbitrageurs that
+//! keep a pool in line with the price of a reference market:
+//! ```toml
+//! [[arbitrageur]]
+//! [arbitrageur.V3Swapper]
+//! pool = "0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8"
+//! quoter = "0x61fFE014bA17989E743c5F6cB21bF9697530B21e"
+//! router = "0xE592427A0AEce92De3Edee1F18E0157C05861564"
+//! target_price = 2000.0
+//! max_amount_in = "0xde0b6b3a7640000"
+//! slippage = 0.005
+//! max_ticks_crossed = 10
+//! trigger = { Blocks = 1 }
+//! ```
+//!
+//! The pool, the `QuoterV2`, and the `SwapRouter` of the Uniswap V3 periphery
+//! are called through their ABIs, so the swapper works with any deployment of
+//! them, including forks of Uniswap V3 that kept their interfaces.
+
+use anyhow::Result;
+use arbiter_core::middleware::{client::ArbiterClient, ArbiterMiddleware};
+use ethers::{
+    abi::{parse_abi, Abi, Token},
+    contract::Contract,
+    types::{Address, U256},
+};
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, Supervision},
+    trigger::{Tick, Trigger},
+};
+
+/// The lowest square root price a Uniswap V3 pool can be at, as a Q64.96
+/// number.
+pub const MIN_SQRT_RATIO: U256 = U256([4_295_128_739, 0, 0, 0]);
+
+/// The highest square root price a Uniswap V3 pool can be at, as a Q64.96
+/// number.
+pub const MAX_SQRT_RATIO: U256 = U256([
+    6_743_328_256_752_651_558,
+    17_280_870_778_742_802_505,
+    4_294_805_859,
+    0,
+]);
+
+const POOL_ABI: &[&str] = &[
+    "function slot0() external view returns (uint160, int24)",
+    "function token0() external view returns (address)",
+    "function token1() external view returns (address)",
+    "function fee() external view returns (uint24)",
+];
+
+// The parameters of the quoter and the router are structs, which the human
+// readable ABIs cannot express.
+const QUOTER_ABI: &str = r#"[{"type":"function","name":"quoteExactInputSingle","stateMutability":"nonpayable","inputs":[{"name":"params","type":"tuple","components":[{"name":"tokenIn","type":"address"},{"name":"tokenOut","type":"address"},{"name":"amountIn","type":"uint256"},{"name":"fee","type":"uint24"},{"name":"sqrtPriceLimitX96","type":"uint160"}]}],"outputs":[{"name":"amountOut","type":"uint256"},{"name":"sqrtPriceX96After","type":"uint160"},{"name":"initializedTicksCrossed","type":"uint32"},{"name":"gasEstimate","type":"uint256"}]}]"#;
+
+const ROUTER_ABI: &str = r#"[{"type":"function","name":"exactInputSingle","stateMutability":"payable","inputs":[{"name":"params","type":"tuple","components":[{"name":"tokenIn","type":"address"},{"name":"tokenOut","type":"address"},{"name":"fee","type":"uint24"},{"name":"recipient","type":"address"},{"name":"deadline","type":"uint256"},{"name":"amountIn","type":"uint256"},{"name":"amountOutMinimum","type":"uint256"},{"name":"sqrtPriceLimitX96","type":"uint160"}]}],"outputs":[{"name":"amountOut","type":"uint256"}]}]"#;
+
+const ERC20_ABI: &[&str] = &[
+    "function decimals() external view returns (uint8)",
+    "function approve(address, uint256) external returns (bool)",
+    "function balanceOf(address) external view returns (uint256)",
+];
+
+/// Trades a Uniswap V3 pool toward [`V3Swapper::target_price`], see the
+/// [module documentation](self).
+///
+/// On every tick of [`V3Swapper::trigger`], the swapper reads the price of
+/// `token0` of the pool in `token1` from its `slot0`, adjusted for the
+/// decimals of both tokens. If it is off the target by more than
+/// [`V3Swapper::tolerance`], the swapper sells the token that is too
+/// expensive for the other with the square root price of the target as the
+/// price limit, so a swap stops at the target rather than overshoot it.
+///
+/// Each swap spends at most [`V3Swapper::max_amount_in`] and is quoted with
+/// the quoter first. While the quote crosses more than
+/// [`V3Swapper::max_ticks_crossed`] initialized ticks, the amount is halved,
+/// and the swap must return at least the quote less
+/// [`V3Swapper::slippage`]. The swapper approves the router to spend both
+/// tokens on startup.
+///
+/// The swaps that fail, e.g., because the price moved past the slippage, are
+/// skipped by default, see [`V3Swapper::supervision`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct V3Swapper<M: ArbiterClient = ArbiterMiddleware> {
+    /// The address of the pool.
+    pub pool: Address,
+
+    /// The address of the `QuoterV2` the swaps are quoted with.
+    pub quoter: Address,
+
+    /// The address of the `SwapRouter` the swaps are sent through.
+    pub router: Address,
+
+    /// The price of `token0` of the pool in `token1` the swapper trades
+    /// toward, adjusted for their decimals.
+    pub target_price: f64,
+
+    /// The relative deviation from the target price within which the
+    /// swapper does not trade.
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+
+    /// The most a single swap spends of the token sold.
+    pub max_amount_in: U256,
+
+    /// The relative shortfall from the quote that a swap tolerates.
+    #[serde(default = "default_slippage")]
+    pub slippage: f64,
+
+    /// The most initialized ticks a single swap may cross, if it is limited.
+    #[serde(default)]
+    pub max_ticks_crossed: Option<u32>,
+
+    /// The schedule the price is checked on.
+    pub trigger: Trigger,
+
+    /// How the failures to swap are handled.
+    #[serde(default = "default_supervision")]
+    pub supervision: Supervision,
+
+    #[serde(skip)]
+    state: Option<V3SwapperState<M>>,
+}
+
+#[derive(Debug)]
+struct V3SwapperState<M> {
+    pool: Contract<M>,
+    quoter: Contract<M>,
+    router: Contract<M>,
+    tokens: [Contract<M>; 2],
+    decimals: [u8; 2],
+    fee: u32,
+}
+
+fn default_tolerance() -> f64 {
+    0.001
+}
+
+fn default_slippage() -> f64 {
+    0.005
+}
+
+fn default_supervision() -> Supervision {
+    Supervision::Skip
+}
+
+/// Returns the price of `token0` in `token1` of a pool at the square root
+/// price `sqrt_price_x96`, adjusted for the `decimals` of both tokens.
+pub fn price(sqrt_price_x96: U256, decimals: [u8; 2]) -> f64 {
+    let sqrt_price_x96 = sqrt_price_x96
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |value, limb| value * 2f64.powi(64) + *limb as f64);
+    let sqrt_price = sqrt_price_x96 / 2f64.powi(96);
+    sqrt_price * sqrt_price * 10f64.powi(decimals[0] as i32 - decimals[1] as i32)
+}
+
+/// Returns the square root price of a pool at which `token0` is worth `price`
+/// in `token1`, adjusted for the `decimals` of both tokens, within the prices
+/// a pool can be at.
+pub fn sqrt_price_x96(price: f64, decimals: [u8; 2]) -> U256 {
+    let raw = price / 10f64.powi(decimals[0] as i32 - decimals[1] as i32);
+    let sqrt_price_x96 = raw.sqrt() * 2f64.powi(96);
+    if sqrt_price_x96.is_nan() || sqrt_price_x96 >= 2f64.powi(160) {
+        return MAX_SQRT_RATIO - 1;
+    }
+    // The integral part of a float is printed with all of its digits.
+    let sqrt_price_x96 = U256::from_dec_str(&format!("{:.0}", sqrt_price_x96)).unwrap();
+    sqrt_price_x96.clamp(MIN_SQRT_RATIO + 1, MAX_SQRT_RATIO - 1)
+}
+
+/// Returns the least amount out a swap quoted at `quote` accepts with
+/// `slippage`.
+pub fn min_amount_out(quote: U256, slippage: f64) -> U256 {
+    let slippage = U256::from((slippage.clamp(0.0, 1.0) * 1e6) as u64);
+    quote - quote * slippage / U256::from(1_000_000)
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<Tick, M> for V3Swapper<M> {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        let pool = Contract::new(self.pool, parse_abi(POOL_ABI)?, client.clone());
+        let quoter = Contract::new(
+            self.quoter,
+            serde_json::from_str::<Abi>(QUOTER_ABI)?,
+            client.clone(),
+        );
+        let router = Contract::new(
+            self.router,
+            serde_json::from_str::<Abi>(ROUTER_ABI)?,
+            client.clone(),
+        );
+        let erc20 = parse_abi(ERC20_ABI)?;
+        let mut tokens = vec![];
+        let mut decimals = [0; 2];
+        for (index, function) in ["token0", "token1"].into_iter().enumerate() {
+            let address: Address = pool.method(function, ())?.call().await?;
+            let token = Contract::new(address, erc20.clone(), client.clone());
+            decimals[index] = token.method("decimals", ())?.call().await?;
+            token
+                .method::<_, bool>("approve", (self.router, U256::MAX))?
+                .send()
+                .await?
+                .await?;
+            tokens.push(token);
+        }
+        let fee: u32 = pool.method("fee", ())?.call().await?;
+        self.state = Some(V3SwapperState {
+            pool,
+            quoter,
+            router,
+            tokens: tokens.try_into().unwrap(),
+            decimals,
+            fee,
+        });
+        Ok(Some(self.trigger.stream(client)))
+    }
+
+    async fn process(&mut self, _tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        let state = self.state.as_ref().unwrap();
+        let (sqrt_price, _tick): (U256, i32) = state.pool.method("slot0", ())?.call().await?;
+        let current = price(sqrt_price, state.decimals);
+        if (current / self.target_price - 1.0).abs() <= self.tolerance {
+            return Ok(ControlFlow::Continue);
+        }
+
+        // Selling `token0` lowers its price in `token1`.
+        let zero_for_one = current > self.target_price;
+        let (token_in, token_out) = if zero_for_one {
+            (&state.tokens[0], &state.tokens[1])
+        } else {
+            (&state.tokens[1], &state.tokens[0])
+        };
+        let limit = sqrt_price_x96(self.target_price, state.decimals);
+        let address = state.pool.client().address();
+        let balance: U256 = token_in.method("balanceOf", address)?.call().await?;
+        let mut amount_in = self.max_amount_in.min(balance);
+        let quote = loop {
+            if amount_in.is_zero() {
+                debug!("Not swapping as there is nothing to swap");
+                return Ok(ControlFlow::Continue);
+            }
+            let params = Token::Tuple(vec![
+                Token::Address(token_in.address()),
+                Token::Address(token_out.address()),
+                Token::Uint(amount_in),
+                Token::Uint(state.fee.into()),
+                Token::Uint(limit),
+            ]);
+            let (amount_out, _, ticks_crossed, _): (U256, U256, u32, U256) = state
+                .quoter
+                .method("quoteExactInputSingle", [params].as_slice())?
+                .call()
+                .await?;
+            if self
+                .max_ticks_crossed
+                .is_some_and(|max| ticks_crossed > max)
+            {
+                amount_in /= 2;
+                continue;
+            }
+            break amount_out;
+        };
+
+        let params = Token::Tuple(vec![
+            Token::Address(token_in.address()),
+            Token::Address(token_out.address()),
+            Token::Uint(state.fee.into()),
+            Token::Address(address),
+            // The swap is sent right away, so it needs no deadline.
+            Token::Uint(U256::MAX),
+            Token::Uint(amount_in),
+            Token::Uint(min_amount_out(quote, self.slippage)),
+            Token::Uint(limit),
+        ]);
+        info!(
+            "Swapping {} of {:?} at a price of {} toward {}",
+            amount_in,
+            token_in.address(),
+            current,
+            self.target_price
+        );
+        state
+            .router
+            .method::<_, U256>("exactInputSingle", [params].as_slice())?
+            .send()
+            .await?
+            .await?;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn supervision(&self) -> Supervision {
+        self.supervision
+    }
+
+    fn reload(&mut self, reconfigured: Self) {
+        self.target_price = reconfigured.target_price;
+        self.tolerance = reconfigured.tolerance;
+        self.slippage = reconfigured.slippage;
+        self.max_ticks_crossed = reconfigured.max_ticks_crossed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_periphery() {
+        let quoter: Abi = serde_json::from_str(QUOTER_ABI).unwrap();
+        let quote = quoter.function("quoteExactInputSingle").unwrap();
+        assert_eq!(quote.short_signature(), [0xc6, 0xa5, 0x02, 0x6a]);
+        let router: Abi = serde_json::from_str(ROUTER_ABI).unwrap();
+        let swap = router.function("exactInputSingle").unwrap();
+        assert_eq!(swap.short_signature(), [0x41, 0x4b, 0xf3, 0x89]);
+        parse_abi(POOL_ABI).unwrap();
+        parse_abi(ERC20_ABI).unwrap();
+    }
+
+    #[test]
+    fn converts_prices() {
+        // A price of 1 between tokens of the same decimals is a square root
+        // price of 2^96.
+        let one = U256::from(2).pow(96.into());
+        assert_eq!(sqrt_price_x96(1.0, [18, 18]), one);
+        assert_eq!(price(one, [18, 18]), 1.0);
+
+        // WETH with 18 decimals at 2000 USDC with 6 decimals.
+        let sqrt_price = sqrt_price_x96(2000.0, [18, 6]);
+        assert!((price(sqrt_price, [18, 6]) - 2000.0).abs() < 1e-6);
+
+        assert_eq!(sqrt_price_x96(0.0, [18, 18]), MIN_SQRT_RATIO + 1);
+        assert_eq!(sqrt_price_x96(f64::MAX, [18, 18]), MAX_SQRT_RATIO - 1);
+    }
+
+    #[test]
+    fn applies_slippage() {
+        assert_eq!(min_amount_out(U256::from(1000), 0.005), U256::from(995));
+        assert_eq!(min_amount_out(U256::from(1000), 0.0), U256::from(1000));
+        assert_eq!(min_amount_out(U256::from(1000), 2.0), U256::zero());
+    }
+}
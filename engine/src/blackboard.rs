@@ -0,0 +1,186 @@
+//! The blackboard module contains a typed store of values shared by every
+//! agent in a [`World`](crate::world::World).
+//!
+//! Values that many agents need, e.g., the current index price or the
+//! inventory aggregated over all agents, can be published once on the
+//! [`Blackboard`] instead of being requested from their owner with messages.
+//! Each type `T` has a single slot that is read and written through a
+//! [`Shared<T>`] handle. Every publication is recorded along with the agent
+//! that made it so that the flow of shared values can be audited afterwards.
+
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::hash_map::Entry,
+    fmt,
+    marker::PhantomData,
+    sync::{Mutex, RwLock},
+};
+
+use super::*;
+
+/// A typed, concurrency-safe store of values shared by the agents of a
+/// [`World`](crate::world::World). Cloning a [`Blackboard`] returns a handle to
+/// the same store.
+#[derive(Clone, Debug, Default)]
+pub struct Blackboard {
+    inner: Arc<BlackboardInner>,
+}
+
+#[derive(Default)]
+struct BlackboardInner {
+    slots: RwLock<HashMap<TypeId, Slot>>,
+    audit: Mutex<Vec<Publication>>,
+}
+
+struct Slot {
+    value: Box<dyn Any + Send + Sync>,
+    version: u64,
+}
+
+/// A record of a value that was published on a [`Blackboard`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Publication {
+    /// The agent that published the value, or `None` if it was published by
+    /// the world itself.
+    pub publisher: Option<String>,
+
+    /// The name of the type of the value.
+    pub state: String,
+
+    /// The version of the slot after the publication, starting at `1`.
+    pub version: u64,
+}
+
+/// A handle to the slot of type `T` on a [`Blackboard`].
+pub struct Shared<T> {
+    blackboard: Blackboard,
+    publisher: Option<String>,
+    _state: PhantomData<fn() -> T>,
+}
+
+impl Blackboard {
+    /// Returns a handle to the slot of type `T` that publishes on behalf of
+    /// the world.
+    pub fn state<T: Clone + Send + Sync + 'static>(&self) -> Shared<T> {
+        self.state_for(None)
+    }
+
+    /// Returns a handle to the slot of type `T` that publishes on behalf of
+    /// the given `publisher`.
+    pub(crate) fn state_for<T: Clone + Send + Sync + 'static>(
+        &self,
+        publisher: Option<String>,
+    ) -> Shared<T> {
+        Shared {
+            blackboard: self.clone(),
+            publisher,
+            _state: PhantomData,
+        }
+    }
+
+    /// Returns every publication made on the [`Blackboard`] so far in the
+    /// order they were made.
+    pub fn audit(&self) -> Vec<Publication> {
+        self.inner.audit.lock().unwrap().clone()
+    }
+}
+
+impl<T: Clone + Send + Sync + 'static> Shared<T> {
+    /// Returns the current value, or `None` if nothing has been published.
+    pub fn get(&self) -> Option<T> {
+        self.blackboard
+            .inner
+            .slots
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<T>())
+            .and_then(|slot| slot.value.downcast_ref::<T>())
+            .cloned()
+    }
+
+    /// Returns how many times a value has been published, which is `0` if
+    /// nothing has been published yet.
+    pub fn version(&self) -> u64 {
+        self.blackboard
+            .inner
+            .slots
+            .read()
+            .unwrap()
+            .get(&TypeId::of::<T>())
+            .map_or(0, |slot| slot.version)
+    }
+
+    /// Replaces the current value with `value` and returns the new version.
+    pub fn publish(&self, value: T) -> u64 {
+        self.update(|_| value)
+    }
+
+    /// Replaces the current value with the one returned by `f` and returns the
+    /// new version. No other value can be published while `f` runs, so this
+    /// can be used to update a value that is aggregated over many agents.
+    pub fn update(&self, f: impl FnOnce(Option<&T>) -> T) -> u64 {
+        let mut slots = self.blackboard.inner.slots.write().unwrap();
+        let version = match slots.entry(TypeId::of::<T>()) {
+            Entry::Occupied(mut slot) => {
+                let slot = slot.get_mut();
+                slot.value = Box::new(f(slot.value.downcast_ref::<T>()));
+                slot.version += 1;
+                slot.version
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(Slot {
+                    value: Box::new(f(None)),
+                    version: 1,
+                });
+                1
+            }
+        };
+        trace!(
+            "{} published version {} of `{}`.",
+            self.publisher.as_deref().unwrap_or("The world"),
+            version,
+            type_name::<T>()
+        );
+        // The audit is written while the slot is still locked so that it
+        // lists the publications of a slot in the order of their versions.
+        self.blackboard
+            .inner
+            .audit
+            .lock()
+            .unwrap()
+            .push(Publication {
+                publisher: self.publisher.clone(),
+                state: type_name::<T>().to_owned(),
+                version,
+            });
+        version
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            blackboard: self.blackboard.clone(),
+            publisher: self.publisher.clone(),
+            _state: PhantomData,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("state", &type_name::<T>())
+            .field("publisher", &self.publisher)
+            .finish()
+    }
+}
+
+impl fmt::Debug for BlackboardInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Blackboard")
+            .field("states", &self.slots.read().unwrap().len())
+            .field("publications", &self.audit.lock().unwrap().len())
+            .finish()
+    }
+}
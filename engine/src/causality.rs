@@ -0,0 +1,87 @@
+//! The causality module follows the chains of cause and effect of a
+//! simulation, e.g., a price tick that makes an arbitrageur swap, which makes
+//! a lender liquidate, through the messages and transactions of its agents.
+//!
+//! Every event a behavior processes belongs to a trace. An event that is a
+//! [`Message`] carrying a trace id continues that trace, and any other event,
+//! e.g., a [`Tick`](crate::trigger::Tick) or a contract event, starts a new
+//! one, whose id is made of the behavior and the position of the event among
+//! its events, e.g., `oracle/0/PriceChanger#3`. The startup of a behavior
+//! starts a trace of its own as well, numbered `#0`. The messages the
+//! behavior sends while it processes the event carry its trace id, and so do
+//! the messages scheduled with
+//! [`Messager::send_after`](crate::messager::Messager::send_after), so the
+//! behaviors that receive them continue the trace in turn.
+//!
+//! The `event` span a behavior processes an event in records the trace id in
+//! its `trace` field, see the [`logging`](crate::logging) module, so the
+//! transactions sent while processing the event are logged along with it,
+//! and the messages of a trace are found in the log of a world with
+//! [`MessageLog::trace`](crate::journal::MessageLog::trace). A whole chain is
+//! reconstructed by searching the logs for its id, e.g., with
+//! `grep 'trace=oracle/0/PriceChanger#3'`.
+//!
+//! A behavior whose events only wrap messages, e.g., the decoded messages of
+//! [`Messager::payloads`](crate::messager::Messager::payloads) or an enum of
+//! several kinds of events, continues the trace of a message with [`follow`]:
+//! ```ignore
+//! async fn process(&mut self, event: Event) -> Result<ControlFlow, BehaviorError> {
+//!     if let Event::Quote(quote) = &event {
+//!         causality::follow(quote);
+//!     }
+//!     // ...
+//! }
+//! ```
+
+use std::{any::Any, cell::RefCell, future::Future};
+
+use super::*;
+use crate::messager::Message;
+
+tokio::task_local! {
+    /// The trace of the event that the behavior whose task is running
+    /// processes.
+    static CURRENT: RefCell<Option<String>>;
+}
+
+/// Returns the trace id that the `event`, the `sequence`th event of the
+/// behavior `key`, belongs to.
+pub(crate) fn origin<E: 'static>(event: &E, key: &str, sequence: u64) -> String {
+    (event as &dyn Any)
+        .downcast_ref::<Message>()
+        .and_then(|message| message.trace_id.clone())
+        .unwrap_or_else(|| format!("{}#{}", key, sequence))
+}
+
+/// Runs `future` in the trace `trace_id`.
+pub(crate) async fn scope<F: Future>(trace_id: String, future: F) -> F::Output {
+    CURRENT.scope(RefCell::new(Some(trace_id)), future).await
+}
+
+/// Returns the id of the trace of the event being processed by the behavior
+/// whose task is running, if any.
+pub fn current() -> Option<String> {
+    CURRENT
+        .try_with(|trace_id| trace_id.borrow().clone())
+        .ok()
+        .flatten()
+}
+
+/// Continues the trace of `message`, if it has one, for the rest of the
+/// processing of the current event, so that the messages sent from now on
+/// carry its trace id.
+///
+/// The `event` span keeps the trace id the event started with, so the switch
+/// is logged to link the two traces.
+pub fn follow<T>(message: &Message<T>) {
+    let Some(followed) = &message.trace_id else {
+        return;
+    };
+    let _ = CURRENT.try_with(|trace_id| {
+        let mut trace_id = trace_id.borrow_mut();
+        if trace_id.as_ref() != Some(followed) {
+            debug!("Following the trace {} of `{}`.", followed, message.from);
+            *trace_id = Some(followed.clone());
+        }
+    });
+}
@@ -0,0 +1,144 @@
+//! The checkpoint module saves the state of a running [`World`] so that a long
+//! simulation can be resumed after a crash or branched off into several
+//! variations.
+//!
+//! With [`World::with_checkpoints`], the state of every behavior is kept after
+//! each event it processed, and a [`Checkpoint`] of the behaviors along with
+//! the block and the [`ArbiterDB`] of the environment is written to a file on
+//! an interval and once more when the world has run. A world built the same
+//! way, e.g., from the same configuration, continues from the checkpoint with
+//! [`World::resume`]:
+//! ```ignore
+//! let mut world = World::from_config::<Behaviors>("config.toml")?;
+//! world.resume(Checkpoint::load("output/checkpoint.json")?)?;
+//! world.run().await?;
+//! ```
+//!
+//! A behavior is restored by deserializing it from the state it was
+//! checkpointed with, after which [`Behavior::startup`] is run on it again.
+//! Since everything a behavior skips when it is serialized, e.g., its client,
+//! messager, or contract handles, is lost, its startup should reconnect to
+//! what it set up before, e.g., to the contracts whose addresses it kept in
+//! its state, rather than set it up again. The random number generators of
+//! the agents start over from their seeds.
+//!
+//! [`World`]: crate::world::World
+//! [`World::with_checkpoints`]: crate::world::World::with_checkpoints
+//! [`World::resume`]: crate::world::World::resume
+//! [`Behavior::startup`]: crate::machine::Behavior::startup
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use arbiter_core::database::ArbiterDB;
+
+use super::*;
+
+/// The latest state of every behavior of a world, keyed by the slot and type
+/// of the behavior, e.g., `agent/0/Behavior`, which is shared by the messagers
+/// of the world.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Checkpoints {
+    inner: Arc<CheckpointsInner>,
+}
+
+#[derive(Debug, Default)]
+struct CheckpointsInner {
+    enabled: AtomicBool,
+    states: Mutex<BTreeMap<String, serde_json::Value>>,
+    restored: Mutex<BTreeMap<String, serde_json::Value>>,
+}
+
+/// The state of a [`World`](crate::world::World) at a point of a run.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The id of the world.
+    pub world: String,
+
+    /// The number of the block the environment was in.
+    pub block_number: u64,
+
+    /// The timestamp of the block the environment was in.
+    pub block_timestamp: u64,
+
+    /// The state of each behavior after the last event it processed, keyed by
+    /// its agent, its position among the behaviors of the agent, and its type,
+    /// e.g., `agent/0/Behavior`.
+    pub behaviors: BTreeMap<String, serde_json::Value>,
+
+    /// The state of the environment.
+    pub db: ArbiterDB,
+}
+
+impl Checkpoints {
+    /// Starts keeping the state of the behaviors.
+    pub(crate) fn enable(&self) {
+        self.inner.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Keeps the state of the `behavior` under `key` if checkpoints are
+    /// enabled.
+    pub(crate) fn save<B: Serialize>(&self, key: &str, behavior: &B) {
+        if !self.inner.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        match serde_json::to_value(behavior) {
+            Ok(state) => {
+                self.inner
+                    .states
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_owned(), state);
+            }
+            Err(e) => warn!("Could not checkpoint the behavior `{}`: {}", key, e),
+        }
+    }
+
+    /// Takes the state the behavior under `key` is restored with, if the world
+    /// was resumed from a checkpoint that has one.
+    pub(crate) fn restored(&self, key: &str) -> Option<serde_json::Value> {
+        self.inner.restored.lock().unwrap().remove(key)
+    }
+
+    /// Sets the states the behaviors are restored with once they start.
+    pub(crate) fn restore(&self, states: BTreeMap<String, serde_json::Value>) {
+        self.inner.states.lock().unwrap().clone_from(&states);
+        *self.inner.restored.lock().unwrap() = states;
+    }
+
+    /// Returns the latest state of every behavior.
+    pub(crate) fn states(&self) -> BTreeMap<String, serde_json::Value> {
+        self.inner.states.lock().unwrap().clone()
+    }
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint to `path` as JSON. The file is replaced at once,
+    /// so a crash while writing leaves the previous checkpoint in place.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ArbiterEngineError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let partial = path.with_extension("partial");
+        let mut file = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut file, self)?;
+        file.flush()?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    /// Reads a checkpoint written with [`Checkpoint::save`] from `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ArbiterEngineError> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
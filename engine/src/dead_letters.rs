@@ -0,0 +1,161 @@
+//! The dead letters module keeps the messages of a [`World`] that cannot be
+//! delivered, so that a world that is wired wrong, e.g., whose agents message
+//! an agent by a misspelled id, is caught rather than left to wait on replies
+//! that never come.
+//!
+//! While the world runs, a message sent to an agent that is not in the world,
+//! to a group without agents, or to agents that have all halted or been
+//! despawned is kept as a [`DeadLetter`] along with the reason it is
+//! undeliverable, and logged. The dead letters are read with
+//! [`World::dead_letters`] and are counted in a warning once the world has
+//! run. The messages sent to all agents are never dead letters.
+//!
+//! [`World`]: crate::world::World
+//! [`World::dead_letters`]: crate::world::World::dead_letters
+
+use std::{fmt, sync::Mutex};
+
+use super::*;
+use crate::{
+    messager::{Message, To},
+    world::Groups,
+};
+
+/// The agents of a world that are running and the messages that could not be
+/// delivered to them, which is shared by the messagers of the world.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DeadLetters {
+    inner: Arc<Mutex<DeadLettersInner>>,
+}
+
+#[derive(Debug, Default)]
+struct DeadLettersInner {
+    /// Whether the world is running, before which every message is taken as
+    /// deliverable.
+    enabled: bool,
+
+    /// The number of behaviors of each agent of the world that are running.
+    running: HashMap<String, usize>,
+
+    letters: Vec<DeadLetter>,
+}
+
+/// A message that could not be delivered.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The message.
+    pub message: Message,
+
+    /// Why the message could not be delivered.
+    pub reason: Undeliverable,
+}
+
+/// Why a [`DeadLetter`] could not be delivered.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Undeliverable {
+    /// The recipient is not an agent of the world.
+    UnknownAgent,
+
+    /// The recipient is a group without agents.
+    EmptyGroup,
+
+    /// The behaviors of the recipient, or of every agent of the group it is
+    /// sent to, have halted or were despawned.
+    Halted,
+}
+
+impl fmt::Display for Undeliverable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Undeliverable::UnknownAgent => write!(f, "the agent is not in the world"),
+            Undeliverable::EmptyGroup => write!(f, "the group has no agents"),
+            Undeliverable::Halted => write!(f, "the agents have halted"),
+        }
+    }
+}
+
+/// Counts a running behavior of an agent until it is dropped, e.g., when the
+/// task of the behavior finishes or is aborted.
+#[derive(Debug)]
+pub(crate) struct Running {
+    inner: Arc<Mutex<DeadLettersInner>>,
+    id: String,
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        if let Some(count) = self.inner.lock().unwrap().running.get_mut(&self.id) {
+            *count = count.saturating_sub(1);
+        }
+    }
+}
+
+impl DeadLetters {
+    /// Counts a behavior of the agent `id` as running until the returned
+    /// guard is dropped, and starts keeping the dead letters of the world.
+    pub(crate) fn run(&self, id: &str) -> Running {
+        let mut inner = self.inner.lock().unwrap();
+        inner.enabled = true;
+        *inner.running.entry(id.to_owned()).or_default() += 1;
+        Running {
+            inner: self.inner.clone(),
+            id: id.to_owned(),
+        }
+    }
+
+    /// Counts an agent that runs outside of the world, e.g., a remote agent,
+    /// as running until the returned guard is dropped, without starting to
+    /// keep the dead letters of the world as it may not run yet.
+    pub(crate) fn connect(&self, id: &str) -> Running {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .running
+            .entry(id.to_owned())
+            .or_default() += 1;
+        Running {
+            inner: self.inner.clone(),
+            id: id.to_owned(),
+        }
+    }
+
+    /// Keeps the `message` as a dead letter if none of its recipients, whose
+    /// groups are `groups`, are running.
+    pub(crate) fn check(&self, message: &Message, groups: &Groups) {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.enabled {
+            return;
+        }
+        let running = |id: &String| inner.running.get(id).is_some_and(|count| *count > 0);
+        let reason = match &message.to {
+            To::All => return,
+            To::Agent(id) if !inner.running.contains_key(id) => Undeliverable::UnknownAgent,
+            To::Agent(id) if !running(id) => Undeliverable::Halted,
+            To::Agent(_) => return,
+            To::Group(group) => {
+                let members = groups.members(group);
+                if members.is_empty() {
+                    Undeliverable::EmptyGroup
+                } else if !members.iter().any(running) {
+                    Undeliverable::Halted
+                } else {
+                    return;
+                }
+            }
+        };
+        warn!(
+            "Could not deliver a message from `{}` to {:?}: {}",
+            message.from, message.to, reason
+        );
+        inner.letters.push(DeadLetter {
+            message: message.clone(),
+            reason,
+        });
+    }
+
+    /// Returns the dead letters so far, in the order they were sent.
+    pub(crate) fn letters(&self) -> Vec<DeadLetter> {
+        self.inner.lock().unwrap().letters.clone()
+    }
+}
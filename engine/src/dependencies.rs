@@ -0,0 +1,232 @@
+//! The dependencies module starts the behaviors of a [`World`] in the order of
+//! their dependencies, e.g., so that a swapper starts once the token admin has
+//! deployed the tokens it swaps rather than sleeping or polling until they
+//! are deployed.
+//!
+//! A behavior declares the agents it depends on with
+//! [`Behavior::dependencies`]. An agent is ready once every one of its
+//! behaviors has finished [`Behavior::startup`], and a behavior is only
+//! started up, and so only processes events, once all of the agents it
+//! depends on are ready. A behavior whose dependency fails to start up halts
+//! with [`HaltReason::Error`] without starting up.
+//!
+//! [`World::run`] checks that every dependency is an agent of the world and
+//! that no agent depends on itself, directly or through other agents, before
+//! it starts any behavior.
+//!
+//! A world can also hold back every behavior at a [`Barrier`] with
+//! [`World::with_startup_barrier`]: each behavior starts up right away, but
+//! only begins to process its events once all of the agents of the world, or
+//! a quorum of them, are ready, while the events it receives in the meantime
+//! wait in its stream. Unlike a dependency, the barrier does not need to be
+//! declared by every behavior, e.g., so that no swapper acts before the token
+//! admin finished deploying the tokens.
+//!
+//! [`World`]: crate::world::World
+//! [`World::run`]: crate::world::World::run
+//! [`World::with_startup_barrier`]: crate::world::World::with_startup_barrier
+//! [`Behavior::dependencies`]: crate::machine::Behavior::dependencies
+//! [`Behavior::startup`]: crate::machine::Behavior::startup
+//! [`HaltReason::Error`]: crate::machine::HaltReason::Error
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+};
+
+use tokio::sync::watch;
+
+use super::*;
+
+/// How many agents of a world have to be ready before its behaviors begin to
+/// process their events, see the [module documentation](self).
+///
+/// The agents that failed to start up are not waited for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Barrier {
+    /// Every agent of the world.
+    All,
+
+    /// The given number of agents, or every agent if the world has fewer.
+    Quorum(usize),
+}
+
+/// Which agents of a world are ready, which is shared by the messagers of the
+/// world.
+#[derive(Clone, Debug)]
+pub(crate) struct Readiness {
+    inner: Arc<ReadinessInner>,
+}
+
+#[derive(Debug)]
+struct ReadinessInner {
+    /// The number of behaviors of each agent that have not started up yet,
+    /// or `None` if one of them failed to.
+    pending: Mutex<HashMap<String, Option<usize>>>,
+
+    /// The barrier the behaviors wait at before processing their events, if
+    /// there is one.
+    barrier: Mutex<Option<Barrier>>,
+
+    /// Bumped whenever a behavior starts up so that the behaviors waiting for
+    /// their dependencies check again.
+    changed: watch::Sender<u64>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(ReadinessInner {
+                pending: Mutex::default(),
+                barrier: Mutex::default(),
+                changed: watch::Sender::new(0),
+            }),
+        }
+    }
+}
+
+impl Readiness {
+    /// Registers a behavior of the agent with the id `agent_id` that has yet
+    /// to start up.
+    pub(crate) fn register(&self, agent_id: &str) {
+        let mut pending = self.inner.pending.lock().unwrap();
+        if let Some(count) = pending.entry(agent_id.to_owned()).or_insert(Some(0)) {
+            *count += 1;
+        }
+    }
+
+    /// Records that a behavior of the agent with the id `agent_id` started up.
+    pub(crate) fn ready(&self, agent_id: &str) {
+        if let Some(Some(count)) = self.inner.pending.lock().unwrap().get_mut(agent_id) {
+            *count = count.saturating_sub(1);
+        }
+        self.inner.changed.send_modify(|count| *count += 1);
+    }
+
+    /// Records that a behavior of the agent with the id `agent_id` failed to
+    /// start up.
+    pub(crate) fn failed(&self, agent_id: &str) {
+        if let Some(count) = self.inner.pending.lock().unwrap().get_mut(agent_id) {
+            *count = None;
+        }
+        self.inner.changed.send_modify(|count| *count += 1);
+    }
+
+    /// Sets the barrier the behaviors wait at before processing their events.
+    pub(crate) fn set_barrier(&self, barrier: Barrier) {
+        *self.inner.barrier.lock().unwrap() = Some(barrier);
+    }
+
+    /// Waits until enough agents are ready to pass the barrier, if there is
+    /// one.
+    pub(crate) async fn barrier(&self) {
+        let Some(barrier) = *self.inner.barrier.lock().unwrap() else {
+            return;
+        };
+        let mut changed = self.inner.changed.subscribe();
+        loop {
+            changed.borrow_and_update();
+            let passed = {
+                let pending = self.inner.pending.lock().unwrap();
+                let ready = pending.values().filter(|count| **count == Some(0)).count();
+                let started = pending.values().filter(|count| count.is_some()).count();
+                match barrier {
+                    Barrier::All => ready == started,
+                    Barrier::Quorum(quorum) => ready >= quorum.min(started),
+                }
+            };
+            if passed || changed.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Waits until every agent of the `dependencies` is ready, or returns the
+    /// first of them that failed to start up. An agent that was never
+    /// registered, e.g., outside of a world, is taken to be ready.
+    pub(crate) async fn wait(&self, dependencies: &[String]) -> Result<(), String> {
+        let mut changed = self.inner.changed.subscribe();
+        loop {
+            changed.borrow_and_update();
+            let mut ready = true;
+            {
+                let pending = self.inner.pending.lock().unwrap();
+                for dependency in dependencies {
+                    match pending.get(dependency) {
+                        Some(None) => return Err(dependency.clone()),
+                        Some(Some(count)) if *count > 0 => ready = false,
+                        _ => {}
+                    }
+                }
+            }
+            if ready {
+                return Ok(());
+            }
+            if changed.changed().await.is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Checks the `dependencies` of each agent, keyed by the agent, and returns
+/// the first dependency on an agent that is not one of them or the first
+/// agent that depends on itself, directly or through other agents.
+pub(crate) fn check(
+    dependencies: &BTreeMap<String, BTreeSet<String>>,
+) -> Result<(), ArbiterEngineError> {
+    for (agent, depends_on) in dependencies {
+        if let Some(unknown) = depends_on
+            .iter()
+            .find(|dependency| !dependencies.contains_key(*dependency))
+        {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "Agent `{}` depends on the unknown agent `{}`",
+                agent, unknown
+            )));
+        }
+    }
+    // Every agent is visited depth first, and an agent that is reached again
+    // while it is still being visited closes a cycle.
+    let mut done = BTreeSet::new();
+    for agent in dependencies.keys() {
+        let mut path = vec![];
+        if let Some(cycle) = visit(agent, dependencies, &mut path, &mut done) {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "The agents depend on each other in a cycle: {}",
+                cycle.join(" -> ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Visits the `agent` and its dependencies that are not `done` yet, and
+/// returns the agents of the first cycle found on the `path` to them.
+fn visit<'a>(
+    agent: &'a String,
+    dependencies: &'a BTreeMap<String, BTreeSet<String>>,
+    path: &mut Vec<&'a String>,
+    done: &mut BTreeSet<&'a String>,
+) -> Option<Vec<String>> {
+    if done.contains(agent) {
+        return None;
+    }
+    if let Some(start) = path.iter().position(|visited| *visited == agent) {
+        let mut cycle: Vec<String> = path[start..]
+            .iter()
+            .map(|agent| agent.to_string())
+            .collect();
+        cycle.push(agent.clone());
+        return Some(cycle);
+    }
+    path.push(agent);
+    for dependency in &dependencies[agent] {
+        if let Some(cycle) = visit(dependency, dependencies, path, done) {
+            return Some(cycle);
+        }
+    }
+    path.pop();
+    done.insert(agent);
+    None
+}
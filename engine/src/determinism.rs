@@ -0,0 +1,283 @@
+//! The determinism module helps track down simulations that give different
+//! results for the same seed.
+//!
+//! [`audit`] builds and runs the same [`World`] twice with tracing turned on
+//! and compares what every agent did in both runs: the messages it sent and
+//! the transactions it executed, each in the order they happened. The first
+//! point at which the runs disagree is reported as a [`Divergence`].
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use arbiter_core::trace::{Tracer, TransactionTrace};
+use ethers::types::Address;
+
+use super::*;
+use crate::{messager::Message, world::World};
+
+/// Records the messages of a [`World`] and which agent owns which address so
+/// that the transactions recorded by the [`Tracer`] of its environment can be
+/// attributed to agents.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Recorder {
+    inner: Arc<RecorderInner>,
+}
+
+#[derive(Debug, Default)]
+struct RecorderInner {
+    enabled: AtomicBool,
+    tracer: Tracer,
+    messages: Mutex<Vec<Message>>,
+    agents: Mutex<HashMap<Address, String>>,
+}
+
+/// Everything the agents of a [`World`] did during a run, keyed by agent.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldTrace {
+    /// The trace of each agent. Transactions sent from addresses that do not
+    /// belong to an agent are keyed by the address.
+    pub agents: BTreeMap<String, AgentTrace>,
+}
+
+/// Everything a single agent did during a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentTrace {
+    /// The messages the agent sent in the order it sent them.
+    pub messages: Vec<Message>,
+
+    /// The transactions the agent sent in the order they were executed.
+    pub transactions: Vec<TransactionTrace>,
+}
+
+/// The first point at which two runs of the same [`World`] disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Divergence {
+    /// The agent sent a different message, or only sent one in one of the
+    /// runs.
+    Message {
+        /// The agent that sent the message.
+        agent: String,
+        /// The position of the message among the messages of the agent.
+        index: usize,
+        /// The message in the first run.
+        first: Option<Message>,
+        /// The message in the second run.
+        second: Option<Message>,
+    },
+
+    /// The agent executed a different transaction, or only executed one in
+    /// one of the runs.
+    Transaction {
+        /// The agent that sent the transaction.
+        agent: String,
+        /// The position of the transaction among the transactions of the
+        /// agent.
+        index: usize,
+        /// The transaction in the first run.
+        first: Option<TransactionTrace>,
+        /// The transaction in the second run.
+        second: Option<TransactionTrace>,
+    },
+}
+
+impl Recorder {
+    /// Creates a [`Recorder`] that reads the transactions recorded by
+    /// `tracer`.
+    pub(crate) fn new(tracer: Tracer) -> Self {
+        Self {
+            inner: Arc::new(RecorderInner {
+                tracer,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Starts recording messages and transactions.
+    pub(crate) fn enable(&self) {
+        self.inner.tracer.enable();
+        self.inner.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Records a `message` that is being sent.
+    pub(crate) fn record(&self, message: &Message) {
+        if self.inner.enabled.load(Ordering::Relaxed) {
+            self.inner.messages.lock().unwrap().push(message.clone());
+        }
+    }
+
+    /// Attributes the transactions sent from `address` to the agent `id`.
+    pub(crate) fn register(&self, address: Address, id: &str) {
+        self.inner
+            .agents
+            .lock()
+            .unwrap()
+            .insert(address, id.to_owned());
+    }
+
+    /// Returns the address of the agent `id`, if it was registered.
+    pub(crate) fn address(&self, id: &str) -> Option<Address> {
+        self.inner
+            .agents
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, agent)| *agent == id)
+            .map(|(address, _)| *address)
+    }
+
+    /// Returns everything that has been recorded so far grouped by agent.
+    pub(crate) fn trace(&self) -> WorldTrace {
+        let mut trace = WorldTrace::default();
+        for message in self.inner.messages.lock().unwrap().iter() {
+            trace
+                .agents
+                .entry(message.from.clone())
+                .or_default()
+                .messages
+                .push(message.clone());
+        }
+        let agents = self.inner.agents.lock().unwrap();
+        for transaction in self.inner.tracer.transactions() {
+            let agent = agents
+                .get(&transaction.caller)
+                .cloned()
+                .unwrap_or_else(|| format!("{:?}", transaction.caller));
+            trace
+                .agents
+                .entry(agent)
+                .or_default()
+                .transactions
+                .push(transaction);
+        }
+        trace
+    }
+}
+
+impl WorldTrace {
+    /// Returns the first point at which the `other` run disagrees with this
+    /// one, or `None` if every agent did the same in both runs.
+    ///
+    /// The agents are compared independently of each other since their
+    /// actions can interleave differently in a deterministic simulation. Of
+    /// the divergences of all agents, the one earliest in its agent's trace is
+    /// reported.
+    pub fn first_divergence(&self, other: &WorldTrace) -> Option<Divergence> {
+        let empty = AgentTrace::default();
+        let mut agents: Vec<_> = self.agents.keys().chain(other.agents.keys()).collect();
+        agents.sort();
+        agents.dedup();
+
+        let mut first: Option<(usize, Divergence)> = None;
+        for agent in agents {
+            let ours = self.agents.get(agent).unwrap_or(&empty);
+            let theirs = other.agents.get(agent).unwrap_or(&empty);
+            let divergences = [
+                diverges_at(&ours.messages, &theirs.messages).map(|index| {
+                    (
+                        index,
+                        Divergence::Message {
+                            agent: agent.clone(),
+                            index,
+                            first: ours.messages.get(index).cloned(),
+                            second: theirs.messages.get(index).cloned(),
+                        },
+                    )
+                }),
+                diverges_at(&ours.transactions, &theirs.transactions).map(|index| {
+                    (
+                        index,
+                        Divergence::Transaction {
+                            agent: agent.clone(),
+                            index,
+                            first: ours.transactions.get(index).cloned(),
+                            second: theirs.transactions.get(index).cloned(),
+                        },
+                    )
+                }),
+            ];
+            for divergence in divergences.into_iter().flatten() {
+                if first
+                    .as_ref()
+                    .is_none_or(|(index, _)| divergence.0 < *index)
+                {
+                    first = Some(divergence);
+                }
+            }
+        }
+        first.map(|(_, divergence)| divergence)
+    }
+}
+
+/// Returns the first index at which `first` and `second` differ.
+fn diverges_at<T: PartialEq>(first: &[T], second: &[T]) -> Option<usize> {
+    (0..first.len().max(second.len())).find(|&index| first.get(index) != second.get(index))
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Message {
+                agent,
+                index,
+                first,
+                second,
+            } => write!(
+                f,
+                "message {} sent by `{}` differs: {:?} in the first run and {:?} in the second run",
+                index, agent, first, second
+            ),
+            Divergence::Transaction {
+                agent,
+                index,
+                first,
+                second,
+            } => write!(
+                f,
+                "transaction {} sent by `{}` differs: {:?} in the first run and {:?} in the second run",
+                index, agent, first, second
+            ),
+        }
+    }
+}
+
+/// Builds a [`World`] with `build` and runs it twice with tracing turned on,
+/// then returns the first point at which the two runs disagree, or `None` if
+/// they did the same.
+///
+/// `build` must set the world up identically on both calls, e.g., by reading
+/// the same configuration file with the same seeds for every behavior.
+///
+/// The messages of an agent are compared in the order it sent them, so the
+/// behaviors of a single agent that send messages at the same time can show up
+/// as a divergence even if each of them is deterministic.
+///
+/// # Examples
+/// ```ignore
+/// let divergence = determinism::audit(|| World::from_config::<Behaviors>("config.toml")).await?;
+/// if let Some(divergence) = divergence {
+///     println!("The simulation is not deterministic: {}", divergence);
+/// }
+/// ```
+pub async fn audit(
+    mut build: impl FnMut() -> Result<World, ArbiterEngineError>,
+) -> Result<Option<Divergence>, ArbiterEngineError> {
+    let mut traces = Vec::with_capacity(2);
+    for run in 1..=2 {
+        let mut world = build()?.with_tracing();
+        debug!("Running world `{}` for run {} of the audit.", world.id, run);
+        world.run().await?;
+        traces.push(world.trace());
+    }
+    let divergence = traces[0].first_divergence(&traces[1]);
+    match &divergence {
+        Some(divergence) => warn!("The simulation is not deterministic: {}", divergence),
+        None => info!("Both runs of the simulation were identical."),
+    }
+    Ok(divergence)
+}
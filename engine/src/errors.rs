@@ -1,5 +1,7 @@
 //! Error types for the arbiter engine.
 
+use std::fmt::Display;
+
 use thiserror::Error;
 
 use super::*;
@@ -11,9 +13,9 @@ pub enum ArbiterEngineError {
     #[error("MessagerError: {0}")]
     MessagerError(String),
 
-    /// Error occurred with the [`crate::agent::Agent`].
+    /// Error occurred in building a [`crate::agent::Agent`].
     #[error("AgentBuildError: {0}")]
-    AgentBuildError(String),
+    AgentBuildError(#[from] AgentBuildError),
 
     /// Error occurred with the [`crate::world::World`].
     #[error("WorldError: {0}")]
@@ -23,13 +25,24 @@ pub enum ArbiterEngineError {
     #[error("UniverseError: {0}")]
     UniverseError(String),
 
+    /// Error occurred in configuring the log levels with the
+    /// [`crate::logging`] module.
+    #[error("LoggingError: {0}")]
+    LoggingError(String),
+
+    /// Error occurred in streaming the events of a contract with
+    /// [`crate::events::event_stream`].
+    #[error("EventStreamError: {0}")]
+    EventStreamError(String),
+
     /// Error occurred in joining a task.
     #[error(transparent)]
     JoinError(#[from] tokio::task::JoinError),
 
-    /// Error occurred in sending a message.
+    /// Error occurred in sending a message, which is boxed as the message it
+    /// returns is large.
     #[error(transparent)]
-    SendError(#[from] tokio::sync::broadcast::error::SendError<crate::messager::Message>),
+    SendError(#[from] Box<tokio::sync::broadcast::error::SendError<crate::messager::Message>>),
 
     /// Error occurred in deserializing json.
     #[error(transparent)]
@@ -47,3 +60,113 @@ pub enum ArbiterEngineError {
     #[error(transparent)]
     ArbiterCoreError(#[from] arbiter_core::errors::ArbiterCoreError),
 }
+
+/// The reasons an [`AgentBuilder`](crate::agent::AgentBuilder) fails to build
+/// an [`Agent`](crate::agent::Agent) or to add it to a
+/// [`World`](crate::world::World).
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum AgentBuildError {
+    /// The agent has neither behaviors nor sub-agents, so it would do
+    /// nothing.
+    #[error("agent `{0}` has no behaviors")]
+    NoBehaviors(String),
+
+    /// The world already has an agent with the id.
+    #[error("the world already has an agent `{0}`")]
+    DuplicateId(String),
+
+    /// A sub-agent has the same id as another sub-agent of its parent.
+    #[error("agent `{0}` has more than one sub-agent with its id")]
+    DuplicateSubAgent(String),
+
+    /// The agent was built with a messager that is not registered for it,
+    /// e.g., one without an id, so it could neither send messages nor receive
+    /// the ones sent to it.
+    #[error("agent `{agent}` is built with a messager registered for {messager:?}")]
+    UnregisteredMessager {
+        /// The id of the agent.
+        agent: String,
+        /// The id the messager is registered for, if any.
+        messager: Option<String>,
+    },
+
+    /// A sub-agent configures its client, which it shares with its parent.
+    #[error("sub-agent `{sub_agent}` cannot set {setting} as it shares the client of `{agent}`")]
+    SharedClient {
+        /// The full id of the sub-agent, e.g., `fund/strategy`.
+        sub_agent: String,
+        /// The id of the parent.
+        agent: String,
+        /// What the sub-agent sets, e.g., `a transaction gas limit`.
+        setting: &'static str,
+    },
+}
+
+/// The error a [`Behavior`](crate::machine::Behavior) failed to process an
+/// event with, which its [`Engine`](crate::machine::Engine) handles according
+/// to the [`Supervision`](crate::machine::Supervision) of the behavior.
+///
+/// Any error converts into a [`BehaviorError`], so `?` can be used in
+/// [`Behavior::process`](crate::machine::Behavior::process) as in the rest of a
+/// behavior. Panics while processing are turned into a [`BehaviorError`] as
+/// well.
+#[derive(Debug)]
+pub struct BehaviorError(anyhow::Error);
+
+impl BehaviorError {
+    /// Creates a [`BehaviorError`] with the given message.
+    pub fn msg(message: impl Display + Debug + Send + Sync + 'static) -> Self {
+        Self(anyhow::Error::msg(message))
+    }
+
+    /// Returns the underlying error.
+    pub fn into_inner(self) -> anyhow::Error {
+        self.0
+    }
+
+    /// Returns the message of the error followed by those of its causes.
+    pub fn chain(&self) -> Vec<String> {
+        self.0.chain().map(ToString::to_string).collect()
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for BehaviorError {
+    fn from(error: E) -> Self {
+        Self(error.into())
+    }
+}
+
+impl Display for BehaviorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+/// An event published on the [`Messager`] of a world whenever a
+/// [`Behavior`](crate::machine::Behavior) fails to process an event, whatever
+/// its [`Supervision`](crate::machine::Supervision) does about it, so that
+/// other agents can react to the errors of a run instead of them only being
+/// logged. The events are streamed with [`Messager::errors`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorEvent {
+    /// The id of the agent of the behavior.
+    pub agent: String,
+
+    /// The type of the behavior, e.g., `Swapper`.
+    pub behavior: String,
+
+    /// The message of the error followed by those of its causes.
+    pub chain: Vec<String>,
+}
+
+impl Display for ErrorEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}/{} failed: {}",
+            self.agent,
+            self.behavior,
+            self.chain.join(": ")
+        )
+    }
+}
@@ -0,0 +1,274 @@
+//! The events module turns the events emitted by a contract into a typed
+//! [`EventStream`] that a [`Behavior`] returns from [`Behavior::startup`].
+//!
+//! The events are the structs that `abigen` generates for the events of a
+//! contract, e.g., the `TransferFilter` of the bindings of an ERC-20, so a
+//! behavior processes decoded events instead of raw logs:
+//! ```ignore
+//! let transfers = event_stream::<TransferFilter>(&token).await?;
+//! Ok(Some(transfers))
+//! ```
+//!
+//!
+//! A behavior that watches a whole protocol, e.g., to analyze it, merges the
+//! events of any number of contracts that are described by their ABIs, e.g.,
+//! in its configuration, into a single stream with
+//! [`contract_event_stream`], in which every event is tagged with the
+//! contract that emitted it:
+//! ```ignore
+//! let events = contract_event_stream(client, &self.contracts).await?;
+//! Ok(Some(events))
+//! ```
+//!
+//! [`Behavior`]: crate::machine::Behavior
+//! [`Behavior::startup`]: crate::machine::Behavior::startup
+
+use std::{fs::File, ops::Deref, path::Path, path::PathBuf};
+
+use ethers::{
+    abi::{Abi, Event, LogParam, RawLog, Token},
+    contract::{Contract, EthEvent},
+    providers::Middleware,
+    types::{Address, Filter, Log, H256},
+};
+use futures_util::StreamExt;
+use tokio::sync::{mpsc, oneshot};
+
+use super::*;
+use crate::machine::EventStream;
+
+/// A contract whose events can be streamed, which is any contract binding
+/// generated by `abigen`, since each of them dereferences to a [`Contract`].
+pub trait EventSource {
+    /// The client the contract is bound to.
+    type Client: Middleware + 'static;
+
+    /// Returns the contract the events are emitted by.
+    fn contract(&self) -> &Contract<Self::Client>;
+}
+
+impl<C, M> EventSource for C
+where
+    C: Deref<Target = Contract<M>>,
+    M: Middleware + 'static,
+{
+    type Client = M;
+
+    fn contract(&self) -> &Contract<M> {
+        self
+    }
+}
+
+/// Returns the stream of the events of type `D` emitted by `contract`, i.e.,
+/// of the logs emitted at its address whose first topic is the signature of
+/// `D`, decoded into `D`.
+///
+/// The events are watched for as soon as this returns, so none that are
+/// emitted afterwards are missed. Logs that match the filter but cannot be
+/// decoded into `D` are skipped.
+pub async fn event_stream<D>(
+    contract: &impl EventSource,
+) -> Result<EventStream<D>, ArbiterEngineError>
+where
+    D: EthEvent + Send + Sync + 'static,
+{
+    let contract = contract.contract();
+    // Unlike `Contract::event`, this filters for the address of the contract.
+    let filter = contract
+        .event_with_filter::<D>(Filter::new().event(&D::abi_signature()))
+        .filter;
+    let logs = log_stream(contract.client(), filter).await?;
+    Ok(Box::pin(logs.filter_map(|log| async move {
+        match D::decode_log(&RawLog::from(log)) {
+            Ok(event) => Some(event),
+            Err(e) => {
+                warn!("Could not decode the event `{}`: {}", D::name(), e);
+                None
+            }
+        }
+    })))
+}
+
+/// A contract whose events are merged into the stream of
+/// [`contract_event_stream`], e.g., from the configuration of a behavior:
+/// ```toml
+/// [[watcher.Watcher.contracts]]
+/// name = "pool"
+/// address = "0x5FbDB2315678afecb367f032d93F642f64180aa3"
+/// abi = "contracts/Pool.json"
+/// events = ["Swap", "Mint"]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchedContract {
+    /// The name the events of the contract are tagged with.
+    pub name: String,
+
+    /// The address of the contract.
+    pub address: Address,
+
+    /// The path to the ABI of the contract, either as a JSON array or as the
+    /// `abi` of a compiler artifact.
+    pub abi: PathBuf,
+
+    /// The names of the events that are watched, or every event of the ABI
+    /// if there are none.
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+/// An event of one of the contracts of a [`contract_event_stream`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ContractEvent {
+    /// The name of the contract that emitted the event.
+    pub contract: String,
+
+    /// The name of the event.
+    pub event: String,
+
+    /// The decoded parameters of the event.
+    pub params: Vec<LogParam>,
+
+    /// The log the event was decoded from, e.g., with its block number and
+    /// its index in the block.
+    pub log: Log,
+}
+
+impl ContractEvent {
+    /// Returns the value of the parameter named `name`, if the event has one.
+    pub fn param(&self, name: &str) -> Option<&Token> {
+        self.params
+            .iter()
+            .find(|param| param.name == name)
+            .map(|param| &param.value)
+    }
+}
+
+/// Returns the stream of the events of all of the `contracts`, decoded with
+/// their ABIs and tagged with the contract that emitted them.
+///
+/// The events are watched for with a single filter, so they are streamed in
+/// the order they were emitted in, across all of the contracts. Logs that
+/// cannot be decoded are skipped, as are anonymous events, which cannot be
+/// told apart by their first topic.
+///
+/// # Errors
+///
+/// Returns an error if an ABI cannot be read or has none of the events that
+/// are watched.
+pub async fn contract_event_stream<M: Middleware + 'static>(
+    client: Arc<M>,
+    contracts: &[WatchedContract],
+) -> Result<EventStream<ContractEvent>, ArbiterEngineError> {
+    let mut events: HashMap<(Address, H256), (String, Event)> = HashMap::new();
+    for contract in contracts {
+        let abi = read_abi(&contract.abi)?;
+        let watched: Vec<&Event> = if contract.events.is_empty() {
+            abi.events().collect()
+        } else {
+            contract
+                .events
+                .iter()
+                .map(|name| {
+                    abi.event(name).map_err(|_| {
+                        ArbiterEngineError::EventStreamError(format!(
+                            "The ABI of `{}` has no event `{}`",
+                            contract.name, name
+                        ))
+                    })
+                })
+                .collect::<Result<_, _>>()?
+        };
+        for event in watched.into_iter().filter(|event| !event.anonymous) {
+            events.insert(
+                (contract.address, event.signature()),
+                (contract.name.clone(), event.clone()),
+            );
+        }
+    }
+    let filter = Filter::new()
+        .address(contracts.iter().map(|c| c.address).collect::<Vec<_>>())
+        .topic0(events.keys().map(|(_, topic)| *topic).collect::<Vec<_>>());
+    let logs = log_stream(client, filter).await?;
+    Ok(Box::pin(logs.filter_map(move |log| {
+        let topic = log.topics.first().copied();
+        let decoded = topic.and_then(|topic| {
+            let (contract, event) = events.get(&(log.address, topic))?;
+            match event.parse_log(RawLog::from(log.clone())) {
+                Ok(parsed) => Some(ContractEvent {
+                    contract: contract.clone(),
+                    event: event.name.clone(),
+                    params: parsed.params,
+                    log,
+                }),
+                Err(e) => {
+                    warn!("Could not decode the event `{}`: {}", event.name, e);
+                    None
+                }
+            }
+        });
+        async move { decoded }
+    })))
+}
+
+/// Reads the ABI at `path`, either a JSON array or a compiler artifact with an
+/// `abi` key.
+pub(crate) fn read_abi(path: &Path) -> Result<Abi, ArbiterEngineError> {
+    let json: serde_json::Value = serde_json::from_reader(File::open(path)?)?;
+    let abi = match json {
+        serde_json::Value::Object(mut artifact) if artifact.contains_key("abi") => {
+            artifact.remove("abi").unwrap()
+        }
+        json => json,
+    };
+    Ok(serde_json::from_value(abi)?)
+}
+
+/// Returns the stream of the raw logs that `client` sees which match
+/// `filter`, for events that are only known at runtime, e.g., from an ABI in
+/// the configuration of a behavior.
+///
+/// The logs are watched for as soon as this returns, so none that are emitted
+/// afterwards are missed.
+pub async fn log_stream<M: Middleware + 'static>(
+    client: Arc<M>,
+    filter: Filter,
+) -> Result<EventStream<Log>, ArbiterEngineError> {
+    let (ready_sender, ready_receiver) = oneshot::channel();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    // The logs are watched for on a task since the watchers of a client borrow
+    // it and are not `Sync`, which the stream has to be.
+    spawn(async move {
+        let mut watcher = match client.watch(&filter).await {
+            Ok(watcher) => {
+                let _ = ready_sender.send(Ok(()));
+                watcher
+            }
+            Err(e) => {
+                let _ = ready_sender.send(Err(e.to_string()));
+                return;
+            }
+        };
+        loop {
+            // The logs stop being watched for once the stream is dropped.
+            let log = tokio::select! {
+                log = watcher.next() => log,
+                _ = sender.closed() => break,
+            };
+            let Some(log) = log else {
+                break;
+            };
+            if sender.send(log).is_err() {
+                break;
+            }
+        }
+    });
+    ready_receiver
+        .await
+        .map_err(|e| ArbiterEngineError::EventStreamError(e.to_string()))?
+        .map_err(ArbiterEngineError::EventStreamError)?;
+    Ok(Box::pin(async_stream::stream! {
+        while let Some(log) = receiver.recv().await {
+            yield log;
+        }
+    }))
+}
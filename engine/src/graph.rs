@@ -0,0 +1,231 @@
+//! The graph module exports how a [`World`] is wired as a DOT graph with
+//! [`World::to_dot`], so that the wiring of a complex world can be checked
+//! visually before it runs, e.g., after rendering it with Graphviz:
+//! ```sh
+//! dot -Tsvg world.dot -o world.svg
+//! ```
+//!
+//! Every agent is drawn as a cluster of its behaviors, each labeled with its
+//! type and the type of the events it processes, e.g., `Swapper (Tick)`. The
+//! behaviors that process [`Message`]s are connected to the messager they
+//! receive them from, which is the one of the world or, for sub-agents, the
+//! private scope of their parent, whose cluster they are drawn in. The
+//! phases of a [`Chain`] are connected in the order they start, the
+//! behaviors are connected to the agents they depend on, and the groups are
+//! connected to the agents in them.
+//!
+//! The event streams of the behaviors are only created once they start up,
+//! so where their events come from is described by their type alone.
+//!
+//! [`World`]: crate::world::World
+//! [`World::to_dot`]: crate::world::World::to_dot
+//! [`Message`]: crate::messager::Message
+//! [`Chain`]: crate::machine::Chain
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use super::*;
+use crate::{agent::Agent, messager::Message};
+
+/// A behavior as it is drawn in the graph of its world, returned by
+/// [`StateMachine::behaviors`](crate::machine::StateMachine::behaviors).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BehaviorNode {
+    /// The type of the behavior, e.g., `Swapper`.
+    pub name: String,
+
+    /// The type of the events the behavior processes, e.g., `Tick`.
+    pub event: String,
+
+    /// Whether the events are the messages of the behavior's messager.
+    pub messages: bool,
+
+    /// The ids of the agents the behavior depends on.
+    pub dependencies: Vec<String>,
+}
+
+impl BehaviorNode {
+    /// Describes a behavior of type `B` that processes events of type `E`
+    /// and depends on the agents `dependencies`.
+    pub(crate) fn new<B, E: 'static>(dependencies: Vec<String>) -> Self {
+        Self {
+            name: short_type_name(std::any::type_name::<B>()),
+            event: short_type_name(std::any::type_name::<E>()),
+            messages: std::any::TypeId::of::<E>() == std::any::TypeId::of::<Message>(),
+            dependencies,
+        }
+    }
+}
+
+/// Renders the `agents` of the world `id`, whose `groups` are keyed by the
+/// group, as a DOT graph.
+pub(crate) fn render(
+    id: &str,
+    agents: &HashMap<String, Agent>,
+    groups: &BTreeMap<String, Vec<String>>,
+) -> String {
+    let mut dot = String::new();
+    writeln!(dot, "digraph {} {{", quote(id)).unwrap();
+    writeln!(dot, "  compound=true;").unwrap();
+    writeln!(dot, "  rankdir=LR;").unwrap();
+    writeln!(dot, "  node [shape=box];").unwrap();
+    writeln!(dot, "  \"messager\" [shape=ellipse];").unwrap();
+    let mut edges = vec![];
+    let mut ids: Vec<_> = agents.keys().collect();
+    ids.sort();
+    for id in ids {
+        render_agent(&mut dot, &mut edges, &agents[id], "messager", 1);
+    }
+    for (group, members) in groups {
+        let node = format!("group/{}", group);
+        writeln!(
+            dot,
+            "  {} [shape=diamond, label={}];",
+            quote(&node),
+            quote(group)
+        )
+        .unwrap();
+        for member in members {
+            edges.push(format!(
+                "{} -> {} [lhead={}, style=dotted]",
+                quote(&node),
+                quote(member),
+                quote(&format!("cluster_{}", member))
+            ));
+        }
+    }
+    for edge in edges {
+        writeln!(dot, "  {};", edge).unwrap();
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+/// Renders the cluster of `agent`, whose behaviors receive their messages
+/// from the node `messager`, at the given `depth` of nesting, and collects
+/// its edges into `edges`.
+fn render_agent(
+    dot: &mut String,
+    edges: &mut Vec<String>,
+    agent: &Agent,
+    messager: &str,
+    depth: usize,
+) {
+    let indent = "  ".repeat(depth);
+    writeln!(
+        dot,
+        "{}subgraph {} {{",
+        indent,
+        quote(&format!("cluster_{}", agent.id))
+    )
+    .unwrap();
+    writeln!(dot, "{}  label={};", indent, quote(&agent.id)).unwrap();
+    // The edges to the agent end at the border of its cluster, so they need a
+    // node in it even if the agent only has sub-agents.
+    writeln!(
+        dot,
+        "{}  {} [shape=point, style=invis];",
+        indent,
+        quote(&agent.id)
+    )
+    .unwrap();
+    let mut position = 0;
+    for engine in &agent.behavior_engines {
+        let mut previous = None;
+        for behavior in engine.behaviors() {
+            let node = format!("{}/{}", agent.id, position);
+            position += 1;
+            writeln!(
+                dot,
+                "{}  {} [label={}];",
+                indent,
+                quote(&node),
+                quote(&format!("{}\n({})", behavior.name, behavior.event))
+            )
+            .unwrap();
+            if behavior.messages {
+                edges.push(format!("{} -> {}", quote(messager), quote(&node)));
+            }
+            for dependency in &behavior.dependencies {
+                edges.push(format!(
+                    "{} -> {} [lhead={}, style=dashed, label=\"depends on\"]",
+                    quote(&node),
+                    quote(dependency),
+                    quote(&format!("cluster_{}", dependency))
+                ));
+            }
+            // The phases of a chain start one after the other.
+            if let Some(previous) = previous.replace(node.clone()) {
+                edges.push(format!(
+                    "{} -> {} [label=\"then\"]",
+                    quote(&previous),
+                    quote(&node)
+                ));
+            }
+        }
+    }
+    if !agent.sub_agents.is_empty() {
+        let scope = format!("{}/scope", agent.id);
+        writeln!(
+            dot,
+            "{}  {} [shape=ellipse, label=\"scope\"];",
+            indent,
+            quote(&scope)
+        )
+        .unwrap();
+        let mut ids: Vec<_> = agent.sub_agents.keys().collect();
+        ids.sort();
+        for id in ids {
+            render_agent(dot, edges, &agent.sub_agents[id], &scope, depth + 1);
+        }
+    }
+    writeln!(dot, "{}}}", indent).unwrap();
+}
+
+/// Quotes `id` as a DOT identifier.
+fn quote(id: &str) -> String {
+    format!(
+        "\"{}\"",
+        id.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+/// Strips the module paths from the full name of a type, including those of
+/// its generic parameters, e.g., `Probabilistic<Trader>`.
+pub(crate) fn short_type_name(name: &str) -> String {
+    let mut short = String::new();
+    let mut path = String::new();
+    for c in name.chars().chain(std::iter::once(' ')) {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            path.push(c);
+            continue;
+        }
+        short.push_str(path.rsplit("::").next().unwrap_or_default());
+        path.clear();
+        short.push(c);
+    }
+    short.pop();
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shortens_type_names() {
+        assert_eq!(
+            short_type_name(
+                "arbiter_engine::behaviors::probabilistic::Probabilistic<my_crate::Trader>"
+            ),
+            "Probabilistic<Trader>"
+        );
+        assert_eq!(
+            short_type_name("(u64, alloc::string::String)"),
+            "(u64, String)"
+        );
+        assert_eq!(quote("a \"b\"\nc"), "\"a \\\"b\\\"\\nc\"");
+    }
+}
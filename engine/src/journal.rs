@@ -0,0 +1,245 @@
+//! The journal module persists every message sent in a [`World`] so that the
+//! communication of its agents can be audited once it has run.
+//!
+//! With [`World::with_message_log`], or with a top level
+//! `message_log = "path"` in the configuration of [`World::from_config`],
+//! every message is appended to a file as a line of JSON as soon as it is
+//! sent, along with its position among the messages of the world and the
+//! time it was sent, so that the log of a run that crashed is kept up to its
+//! last message. The log is read back with [`MessageLog::load`] and queried,
+//! e.g., for what an agent sent or for the replies to a request:
+//! ```ignore
+//! let log = MessageLog::load("output/messages.jsonl")?;
+//! for message in log.sent_by("alice") {
+//!     println!("{} -> {:?}: {}", message.from, message.to, message.data);
+//! }
+//! let quotes: Vec<Message<Quote>> = log.payloads::<Quote>().collect();
+//! ```
+//! The messages of the log are turned back into [`Message`]s with
+//! [`LoggedMessage::message`], e.g., to replay them to a behavior with the
+//! [`testing`](crate::testing) harness.
+//!
+//! [`World`]: crate::world::World
+//! [`World::with_message_log`]: crate::world::World::with_message_log
+//! [`World::from_config`]: crate::world::World::from_config
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use arbiter_core::shutdown::{ShutdownCoordinator, ShutdownHandle};
+
+use super::*;
+use crate::messager::{Message, Payload, Priority, To};
+
+/// Appends the messages sent through the messagers of a world to a file
+/// while it is open, which is shared by the messagers of the world.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Journal {
+    inner: Arc<Mutex<Option<JournalFile>>>,
+}
+
+#[derive(Debug)]
+struct JournalFile {
+    file: BufWriter<File>,
+    sequence: u64,
+    /// Held until the file is flushed on close.
+    _shutdown: ShutdownHandle,
+}
+
+/// A message sent during a run as it is written to the log of a
+/// [`World`](crate::world::World).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoggedMessage {
+    /// The position of the message among the messages sent in the world.
+    pub sequence: u64,
+
+    /// When the message was sent, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+
+    /// The sender of the message.
+    pub from: String,
+
+    /// The recipient of the message.
+    pub to: To,
+
+    /// The [`Payload::TAG`] of the data, if it has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+
+    /// The correlation id of the message, if it is a request or a reply.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
+    /// The lane the message was sent in.
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+
+    /// The id of the trace of the event the message was sent for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+
+    /// The data of the message as JSON.
+    pub data: String,
+}
+
+/// The messages sent in a [`World`](crate::world::World) during a run, in the
+/// order they were sent, read from its log with [`MessageLog::load`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MessageLog {
+    /// The messages in the order they were sent.
+    pub messages: Vec<LoggedMessage>,
+}
+
+impl Journal {
+    /// Starts appending the messages that are sent to a new file at `path`.
+    pub(crate) fn open(&self, path: &Path) -> Result<(), ArbiterEngineError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        *self.inner.lock().unwrap() = Some(JournalFile {
+            file: BufWriter::new(File::create(path)?),
+            sequence: 0,
+            _shutdown: ShutdownCoordinator::global().register(),
+        });
+        Ok(())
+    }
+
+    /// Appends a `message` that is being sent if the journal is open.
+    pub(crate) fn record(&self, message: &Message) {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(journal) = inner.as_mut() else {
+            return;
+        };
+        let logged = LoggedMessage {
+            sequence: journal.sequence,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            from: message.from.clone(),
+            to: message.to.clone(),
+            tag: message.tag.clone(),
+            correlation_id: message.correlation_id.clone(),
+            priority: message.priority,
+            trace_id: message.trace_id.clone(),
+            data: message.data.clone(),
+        };
+        journal.sequence += 1;
+        let written = serde_json::to_writer(&mut journal.file, &logged)
+            .map_err(std::io::Error::from)
+            .and_then(|()| writeln!(journal.file));
+        if let Err(e) = written {
+            warn!("Could not log a message from `{}`: {}", message.from, e);
+        }
+    }
+
+    /// Writes out the messages appended so far and stops appending them.
+    pub(crate) fn close(&self) -> Result<(), ArbiterEngineError> {
+        if let Some(mut journal) = self.inner.lock().unwrap().take() {
+            journal.file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl LoggedMessage {
+    /// Returns the message as it was sent.
+    pub fn message(&self) -> Message {
+        Message {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            data: self.data.clone(),
+            tag: self.tag.clone(),
+            correlation_id: self.correlation_id.clone(),
+            priority: self.priority,
+            trace_id: self.trace_id.clone(),
+        }
+    }
+}
+
+impl MessageLog {
+    /// Reads the log written by a world run with
+    /// [`World::with_message_log`](crate::world::World::with_message_log)
+    /// from `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::MessagerError`] along with the line of
+    /// the first message that cannot be read.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ArbiterEngineError> {
+        let path = path.as_ref();
+        let mut messages = vec![];
+        for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let message = serde_json::from_str(&line).map_err(|e| {
+                ArbiterEngineError::MessagerError(format!(
+                    "{}:{}: invalid message: {}",
+                    path.display(),
+                    index + 1,
+                    e
+                ))
+            })?;
+            messages.push(message);
+        }
+        Ok(Self { messages })
+    }
+
+    /// Returns the messages sent by the agent `id`.
+    pub fn sent_by<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a LoggedMessage> {
+        self.messages
+            .iter()
+            .filter(move |message| message.from == id)
+    }
+
+    /// Returns the messages sent to the agent `id` by its id, or to all
+    /// agents. The messages sent to the groups of the agent are left out, as
+    /// the log does not know its groups.
+    pub fn sent_to<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a LoggedMessage> {
+        self.messages
+            .iter()
+            .filter(move |message| match &message.to {
+                To::All => true,
+                To::Agent(to) => to == id,
+                To::Group(_) => false,
+            })
+    }
+
+    /// Returns the messages that were sent with the tag of the payload `T`,
+    /// decoded, see [`Messager::send_payload`](crate::messager::Messager::send_payload).
+    pub fn payloads<T: Payload>(&self) -> impl Iterator<Item = Message<T>> + '_ {
+        self.messages
+            .iter()
+            .filter(|message| message.tag.as_deref() == Some(T::TAG))
+            .filter_map(|message| message.message().decode().ok())
+    }
+
+    /// Returns the messages of the trace `trace_id`, i.e., those sent for
+    /// the events that descend from the event the trace started with, see
+    /// the [`causality`](crate::causality) module.
+    pub fn trace<'a>(&'a self, trace_id: &'a str) -> impl Iterator<Item = &'a LoggedMessage> {
+        self.messages
+            .iter()
+            .filter(move |message| message.trace_id.as_deref() == Some(trace_id))
+    }
+
+    /// Returns the replies to the `request`, i.e., the messages that carry
+    /// its correlation id and were sent to its sender.
+    pub fn replies<'a>(
+        &'a self,
+        request: &'a LoggedMessage,
+    ) -> impl Iterator<Item = &'a LoggedMessage> {
+        self.messages.iter().filter(move |message| {
+            request.correlation_id.is_some()
+                && message.correlation_id == request.correlation_id
+                && message.to == To::Agent(request.from.clone())
+        })
+    }
+}
@@ -5,6 +5,10 @@
 //! distributed fashion where each agent is running in its own process and
 //! communicating with other agents via a messaging layer.
 
+// The code generated by `arbiter_macros` refers to the crate by its name, also
+// when it is used by the built-in behaviors.
+extern crate self as arbiter_engine;
+
 use std::{collections::HashMap, fmt::Debug, sync::Arc};
 
 use futures_util::future::join_all;
@@ -15,8 +19,29 @@ use tracing::{debug, info, trace, warn};
 use crate::{errors::ArbiterEngineError, messager::Messager};
 
 pub mod agent;
+pub mod behaviors;
+pub mod blackboard;
+pub mod causality;
+pub mod checkpoint;
+pub mod dead_letters;
+pub mod dependencies;
+pub mod determinism;
 pub mod errors;
+pub mod events;
+pub mod graph;
+pub mod journal;
+pub mod logging;
 pub mod machine;
 pub mod messager;
+pub mod metrics;
+pub mod random;
+pub mod relay;
+pub mod reload;
+pub mod remote;
+pub mod replay;
+pub mod scheduler;
+pub mod schema;
+pub mod testing;
+pub mod trigger;
 pub mod universe;
 pub mod world;
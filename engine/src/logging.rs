@@ -0,0 +1,184 @@
+//! The [`logging`] module sets the log level of each subsystem and agent of a
+//! simulation from the `logging` table of a world configuration, so that a
+//! single misbehaving agent can be traced without drowning in the logs of
+//! everything else.
+//!
+//! Every behavior engine that a [`World`](crate::world::World) runs is
+//! instrumented with an `agent` span whose `id` field is the identifier of
+//! the agent, which is what the levels under `logging.agents` are matched
+//! against, and whose `world` field is the identifier of the world. Inside
+//! of it, each behavior runs in a `behavior` span whose `name` field is the
+//! type of the behavior, and processes each event in an `event` span whose
+//! `seq` field counts the events of the behavior from 1 and whose `trace`
+//! field is the trace the event belongs to, see the
+//! [`causality`](crate::causality) module. Everything a
+//! behavior does while processing an event is logged in these spans,
+//! including the transactions its client sends, so the logs of a run can be
+//! searched for the whole history of a single agent, e.g., with
+//! `grep 'agent{world="world" id="alice"}'`.
+//!
+//! # Examples
+//!
+//! ```toml
+//! [logging]
+//! level = "warn"
+//!
+//! [logging.targets]
+//! environment = "info"
+//! "my_crate::behaviors" = "debug"
+//!
+//! [logging.agents]
+//! alice = "trace"
+//! ```
+
+use std::{collections::BTreeMap, fs};
+
+use tracing::{level_filters::LevelFilter, Level};
+use tracing_subscriber::EnvFilter;
+
+use super::*;
+
+/// The name of the span that every behavior engine of an agent runs in.
+pub const AGENT_SPAN: &str = "agent";
+
+/// The name of the span that a behavior runs in, inside of the span of its
+/// agent.
+pub const BEHAVIOR_SPAN: &str = "behavior";
+
+/// The name of the span that a behavior processes an event in, inside of the
+/// span of the behavior.
+pub const EVENT_SPAN: &str = "event";
+
+/// The subsystems that can be configured by name along with the module they
+/// log from.
+const SUBSYSTEMS: [(&str, &str); 6] = [
+    ("environment", "arbiter_core::environment"),
+    ("middleware", "arbiter_core::middleware"),
+    ("messager", "arbiter_engine::messager"),
+    ("machine", "arbiter_engine::machine"),
+    ("world", "arbiter_engine::world"),
+    ("universe", "arbiter_engine::universe"),
+];
+
+/// The log levels of a simulation.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogConfig {
+    /// The level of everything that is not configured otherwise, e.g.,
+    /// `"warn"`.
+    #[serde(default)]
+    pub level: Option<String>,
+
+    /// The level of each subsystem, keyed by its name, e.g., `environment` or
+    /// `messager`, or by the path of any module, e.g., `my_crate::behaviors`.
+    #[serde(default)]
+    pub targets: BTreeMap<String, String>,
+
+    /// The level of everything logged by the behaviors of each agent, keyed by
+    /// the identifier of the agent.
+    #[serde(default)]
+    pub agents: BTreeMap<String, String>,
+}
+
+impl LogConfig {
+    /// Reads the `logging` table of the world configuration at
+    /// `config_path`. Returns the default [`LogConfig`] if the configuration
+    /// does not have one.
+    pub fn from_config(config_path: &str) -> Result<Self, ArbiterEngineError> {
+        let path = std::env::current_dir()?.join(config_path);
+        let mut config: toml::Table = toml::from_str(&fs::read_to_string(path)?)?;
+        match config.remove("logging") {
+            Some(logging) => Ok(Self::deserialize(logging)?),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Returns the [`EnvFilter`] directives of the configuration, using
+    /// `default` as the level of everything that is not configured otherwise
+    /// when `level` is not set.
+    pub fn directives(&self, default: Level) -> String {
+        let mut directives = vec![self
+            .level
+            .clone()
+            .unwrap_or_else(|| default.to_string().to_lowercase())];
+        for (target, level) in &self.targets {
+            let target = SUBSYSTEMS
+                .iter()
+                .find(|(name, _)| name == target)
+                .map_or(target.as_str(), |(_, module)| module);
+            directives.push(format!("{}={}", target, level));
+        }
+        for (agent, level) in &self.agents {
+            directives.push(format!("[{}{{id={}}}]={}", AGENT_SPAN, agent, level));
+        }
+        directives.join(",")
+    }
+
+    /// Builds the [`EnvFilter`] of the configuration, using `default` as the
+    /// level of everything that is not configured otherwise when `level` is
+    /// not set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the levels is not a valid level.
+    pub fn filter(&self, default: Level) -> Result<EnvFilter, ArbiterEngineError> {
+        let levels = self
+            .level
+            .iter()
+            .chain(self.targets.values())
+            .chain(self.agents.values());
+        for level in levels {
+            if level.parse::<LevelFilter>().is_err() {
+                return Err(ArbiterEngineError::LoggingError(format!(
+                    "`{}` is not a valid log level",
+                    level
+                )));
+            }
+        }
+        EnvFilter::builder()
+            .parse(self.directives(default))
+            .map_err(|e| ArbiterEngineError::LoggingError(e.to_string()))
+    }
+
+    /// Installs a global `tracing` subscriber that prints the logs allowed by
+    /// the configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if any of the levels is not a valid level or a global
+    /// subscriber has already been installed.
+    pub fn init(&self, default: Level) -> Result<(), ArbiterEngineError> {
+        tracing_subscriber::fmt()
+            .with_env_filter(self.filter(default)?)
+            .try_init()
+            .map_err(|e| ArbiterEngineError::LoggingError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directives() {
+        let config = LogConfig {
+            level: None,
+            targets: [
+                ("environment".to_owned(), "info".to_owned()),
+                ("my_crate::behaviors".to_owned(), "debug".to_owned()),
+            ]
+            .into(),
+            agents: [("alice".to_owned(), "trace".to_owned())].into(),
+        };
+        assert_eq!(
+            config.directives(Level::WARN),
+            "warn,arbiter_core::environment=info,my_crate::behaviors=debug,[agent{id=alice}]=trace"
+        );
+        assert!(config.filter(Level::WARN).is_ok());
+
+        let invalid = LogConfig {
+            level: Some("loud".to_owned()),
+            ..Default::default()
+        };
+        assert!(invalid.filter(Level::WARN).is_err());
+    }
+}
@@ -1,15 +1,39 @@
 //! The [`StateMachine`] trait, [`Behavior`] trait, and the [`Engine`] that runs
 //! [`Behavior`]s.
 
-use std::pin::Pin;
+use std::{
+    collections::VecDeque,
+    marker::PhantomData,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use anyhow::Result;
-use arbiter_core::middleware::ArbiterMiddleware;
-use futures_util::{Stream, StreamExt};
-use tokio::task::JoinHandle;
-use tracing::error;
+use arbiter_core::{
+    middleware::{client::ArbiterClient, ArbiterMiddleware},
+    profiler::Profiler,
+};
+use futures_util::{FutureExt, Stream, StreamExt};
+use tokio::{
+    sync::{broadcast::Sender, watch, Notify},
+    task::JoinHandle,
+};
+use tracing::{error, info_span, Instrument};
 
 use super::*;
+use crate::{
+    causality,
+    checkpoint::Checkpoints,
+    errors::{BehaviorError, ErrorEvent},
+    graph::{self, BehaviorNode},
+    logging::{BEHAVIOR_SPAN, EVENT_SPAN},
+    metrics::Counters,
+    replay::Inputs,
+    scheduler::Scheduler,
+    world::Stopper,
+};
 
 /// A type alias for a pinned, boxed stream of events.
 ///
@@ -22,11 +46,70 @@ use super::*;
 /// * `E`: The type of the items in the stream.
 pub type EventStream<E> = Pin<Box<dyn Stream<Item = E> + Send + Sync>>;
 
-/// The instructions that can be sent to a [`StateMachine`].
-#[derive(Clone, Debug)]
-pub enum MachineInstruction {
+/// Several [`EventStream`]s of different types merged into a single stream of
+/// an event enum `E`, so that a [`Behavior`] can react to, e.g., the events of
+/// a contract, its messages, and a [`Trigger`](crate::trigger::Trigger) at
+/// once.
+///
+/// Each stream is added along with the function that wraps its events into
+/// `E`, usually the variant of `E` that holds them:
+/// ```ignore
+/// #[derive(Debug, Serialize, Deserialize)]
+/// enum Event {
+///     Transfer(TransferFilter),
+///     Message(Message),
+///     Tick(Tick),
+/// }
+///
+/// let streams = EventStreams::new()
+///     .with(event_stream::<TransferFilter>(&token).await?, Event::Transfer)
+///     .with(messager.stream()?, Event::Message)
+///     .with(Trigger::Blocks(1).stream(client), Event::Tick);
+/// Ok(Some(streams.merge()))
+/// ```
+/// The events of the streams are delivered in the order they arrive, and the
+/// merged stream ends once all of them have ended.
+pub struct EventStreams<E> {
+    streams: Vec<EventStream<E>>,
+}
+
+impl<E> Default for EventStreams<E> {
+    fn default() -> Self {
+        Self {
+            streams: Vec::new(),
+        }
+    }
+}
+
+impl<E: Send + Sync + 'static> EventStreams<E> {
+    /// Creates an empty [`EventStreams`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `stream`, whose events are wrapped into `E` with `into`.
+    pub fn with<T: 'static>(
+        mut self,
+        stream: EventStream<T>,
+        into: impl Fn(T) -> E + Send + Sync + 'static,
+    ) -> Self {
+        self.streams.push(Box::pin(stream.map(into)));
+        self
+    }
+
+    /// Returns the single stream of the events of every added stream.
+    pub fn merge(self) -> EventStream<E> {
+        Box::pin(futures_util::stream::select_all(self.streams))
+    }
+}
+
+/// The instructions that can be sent to a [`StateMachine`] that runs with a
+/// client of type `M`.
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum MachineInstruction<M = ArbiterMiddleware> {
     /// Used to make a [`StateMachine`] start up.
-    Start(Arc<ArbiterMiddleware>, Messager),
+    Start(Arc<M>, Messager),
 
     /// Used to make a [`StateMachine`] process events.
     /// This will offload the process into a task that can be halted by sending
@@ -35,17 +118,137 @@ pub enum MachineInstruction {
     Process,
 }
 
+impl<M> Clone for MachineInstruction<M> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Start(client, messager) => Self::Start(client.clone(), messager.clone()),
+            Self::Process => Self::Process,
+        }
+    }
+}
+
 /// The message that is used in a [`StateMachine`] to continue or halt its
 /// processing.
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ControlFlow {
-    /// Used to halt the processing of a [`StateMachine`].
-    Halt,
+    /// Used to halt the processing of a [`StateMachine`] for the given reason.
+    Halt(HaltReason),
 
     /// Used to continue on the processing of a [`StateMachine`].
     Continue,
 }
 
+/// Why a [`Behavior`] halted, either as returned with [`ControlFlow::Halt`] or
+/// as recorded by its [`Engine`] when it stopped processing for another
+/// reason. The reasons of every behavior of a world are collected in its
+/// [`WorldMetrics`](crate::metrics::WorldMetrics).
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HaltReason {
+    /// The behavior is done with what it set out to do, along with an
+    /// optional payload, e.g., a summary of its results.
+    Done(Option<serde_json::Value>),
+
+    /// The behavior reached the maximum count it was configured with, e.g.,
+    /// of messages it replies to or blocks it mines.
+    MaxCount(u64),
+
+    /// The behavior failed with an error that its [`Supervision`] gave up on.
+    Error(String),
+
+    /// The world of the behavior was stopped.
+    Stopped,
+
+    /// The event stream of the behavior ended.
+    StreamEnded,
+}
+
+impl HaltReason {
+    /// Returns [`HaltReason::Done`] without a payload.
+    pub fn done() -> Self {
+        Self::Done(None)
+    }
+
+    /// Returns [`HaltReason::Done`] with the `payload` serialized to JSON, or
+    /// without a payload if it fails to serialize.
+    pub fn done_with(payload: impl Serialize) -> Self {
+        Self::Done(serde_json::to_value(payload).ok())
+    }
+}
+
+impl std::fmt::Display for HaltReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HaltReason::Done(None) => write!(f, "done"),
+            HaltReason::Done(Some(payload)) => write!(f, "done: {}", payload),
+            HaltReason::MaxCount(count) => write!(f, "reached the maximum count of {}", count),
+            HaltReason::Error(error) => write!(f, "failed: {}", error),
+            HaltReason::Stopped => write!(f, "stopped"),
+            HaltReason::StreamEnded => write!(f, "event stream ended"),
+        }
+    }
+}
+
+/// How the [`Engine`] of a [`Behavior`] handles the [`BehaviorError`]s that
+/// the behavior fails to process events with, returned by
+/// [`Behavior::supervision`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Supervision {
+    /// Halts the behavior, which stops processing events.
+    #[default]
+    Halt,
+
+    /// Skips the event that failed and carries on with the next one.
+    Skip,
+
+    /// Processes the event that failed again up to `attempts` times, waiting
+    /// `backoff` before the first retry and twice as long before each
+    /// following one. The behavior halts if the last attempt fails too.
+    Retry {
+        /// The number of times an event is retried.
+        attempts: u32,
+        /// How long to wait before the first retry.
+        backoff: Duration,
+    },
+
+    /// Restarts the behavior from the configuration it was started with,
+    /// i.e., runs [`Behavior::startup`] again on the behavior as it was before
+    /// its first startup and processes the stream it returns in place of the
+    /// old one. The event that failed is dropped. The behavior halts once it
+    /// fails after `attempts` restarts.
+    Restart {
+        /// The number of times the behavior is restarted.
+        attempts: u32,
+    },
+}
+
+/// How the [`Engine`] of a [`Behavior`] buffers the events of its stream while
+/// the behavior is busy processing, returned by [`Behavior::backpressure`].
+///
+/// The events are pulled from the stream into a buffer of `capacity` events
+/// as soon as they arrive, so that a behavior that processes slower than its
+/// events arrive at times, e.g., in bursts of messages, does not miss them.
+/// What happens once the buffer is full is up to the variant. The events
+/// dropped are counted in the metrics of the behavior, see the
+/// [`metrics`](crate::metrics) module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Backpressure {
+    /// Stops pulling events from the stream until the behavior made room in
+    /// the buffer, so that the behavior processes every event the stream
+    /// yields, at the risk of the stream itself falling behind, e.g., the
+    /// channel of a [`Messager`].
+    Block {
+        /// The number of events buffered.
+        capacity: usize,
+    },
+
+    /// Drops the oldest event in the buffer to make room for the next one, so
+    /// that the behavior keeps up with the latest events, e.g., prices.
+    DropOldest {
+        /// The number of events buffered.
+        capacity: usize,
+    },
+}
+
 /// The state used by any entity implementing [`StateMachine`].
 #[derive(Clone, Copy, Debug)]
 pub enum State {
@@ -70,8 +273,21 @@ pub enum State {
 
 /// The [`Behavior`] trait is the lowest level functionality that will be used
 /// by a [`StateMachine`]. This constitutes what each state transition will do.
+///
+/// A behavior runs with a client of type `M`, which is the
+/// [`ArbiterMiddleware`] of a simulation unless stated otherwise. A behavior
+/// that is generic over any [`ArbiterClient`] also runs against a real
+/// network, e.g., by starting an [`Engine`] with a
+/// [`LiveClient`](arbiter_core::middleware::client::LiveClient).
+///
+/// A behavior processes the events of the stream it returns on startup, e.g.,
+/// the messages of its [`Messager`] or the decoded events of a contract from
+/// [`event_stream`](crate::events::event_stream). To process on a schedule,
+/// e.g., every block, it returns the stream of a
+/// [`Trigger`](crate::trigger::Trigger) instead. A behavior that reacts to
+/// several kinds of events merges their streams with [`EventStreams`].
 #[async_trait::async_trait]
-pub trait Behavior<E: Send + 'static>:
+pub trait Behavior<E: Send + 'static, M: ArbiterClient = ArbiterMiddleware>:
     Serialize + DeserializeOwned + Send + Sync + Debug + 'static
 {
     /// Used to start the agent.
@@ -79,16 +295,71 @@ pub trait Behavior<E: Send + 'static>:
     /// that it can do given the current state of the world.
     async fn startup(
         &mut self,
-        client: Arc<ArbiterMiddleware>,
+        client: Arc<M>,
         messager: Messager,
     ) -> Result<Option<EventStream<E>>>;
 
     /// Used to process events.
     /// This is where the agent can engage in its specific processing
     /// of events that can lead to actions being taken.
-    async fn process(&mut self, _event: E) -> Result<ControlFlow> {
-        Ok(ControlFlow::Halt)
+    ///
+    /// An event that fails to be processed, including by a panic or by taking
+    /// longer than the [`Behavior::timeout`] of the behavior, is handled
+    /// according to the [`Behavior::supervision`] of the behavior.
+    async fn process(&mut self, _event: E) -> Result<ControlFlow, BehaviorError> {
+        Ok(ControlFlow::Halt(HaltReason::done()))
+    }
+
+    /// Returns how the [`Engine`] handles the errors of
+    /// [`Behavior::process`], which is to halt the behavior by default.
+    fn supervision(&self) -> Supervision {
+        Supervision::Halt
+    }
+
+    /// Returns how long [`Behavior::process`] may take to process a single
+    /// event, which is not limited by default. Processing that takes longer is
+    /// cancelled, i.e., the future of [`Behavior::process`] is dropped
+    /// wherever it was waiting, and is handled as an error according to the
+    /// [`Behavior::supervision`] of the behavior, so that a call that never
+    /// returns does not hang the world.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Returns how the events of the stream of the behavior are buffered while
+    /// it is busy processing, which they are not by default, i.e., an event is
+    /// only pulled from the stream once the previous one was processed. See
+    /// [`Backpressure`].
+    fn backpressure(&self) -> Option<Backpressure> {
+        None
+    }
+
+    /// Applies the parameters of the behavior that were changed in the
+    /// configuration of its world while it runs, see the
+    /// [`reload`](crate::reload) module. `reconfigured` is the behavior
+    /// deserialized from its new configuration, from which the behavior takes
+    /// the parameters it allows to change, e.g., its trade size. Changes are
+    /// ignored by default.
+    fn reload(&mut self, _reconfigured: Self) {}
+
+    /// Returns the ids of the agents the behavior depends on, e.g., the agent
+    /// that deploys the tokens the behavior trades, which is none by default.
+    /// The behavior is only started up once every behavior of those agents has
+    /// finished [`Behavior::startup`], see the
+    /// [`dependencies`](crate::dependencies) module.
+    fn dependencies(&self) -> Vec<String> {
+        vec![]
     }
+
+    /// Used to shut the behavior down once it is done processing events,
+    /// because it halted, its event stream ended, it failed, or its world was
+    /// stopped with a [`Stopper`](crate::world::Stopper). This is where the
+    /// behavior can flush files, emit summaries, or settle its positions.
+    ///
+    /// A behavior that returned no event stream from [`Behavior::startup`] is
+    /// torn down right after it. The behaviors of a despawned agent are not
+    /// torn down since their tasks are aborted.
+    async fn teardown(&mut self) {}
 }
 /// A trait for creating a state machine.
 ///
@@ -116,7 +387,33 @@ pub trait CreateStateMachine {
     /// flexibility and reuse of the state machine logic across
     /// different contexts.
     fn create_state_machine(self) -> Box<dyn StateMachine>;
+
+    /// Returns the names the behaviors are configured with, e.g., the names of
+    /// the variants of an enum that derives `Behaviors`. No names are returned
+    /// by default, in which case any name is accepted by
+    /// [`World::validate_config`](crate::world::World::validate_config).
+    fn behavior_names() -> &'static [&'static str]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+
+    /// Checks that `config` is a valid configuration of the behavior `name`,
+    /// returning why it is not otherwise, e.g., because a required field is
+    /// missing. Every configuration is accepted by default.
+    fn validate_behavior(_name: &str, _config: BehaviorConfig) -> Result<(), String>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
 }
+
+/// The configuration of a single behavior as it is read from the configuration
+/// file of a [`World`](crate::world::World).
+pub type BehaviorConfig = toml::Value;
+
 #[async_trait::async_trait]
 /// A trait defining the capabilities of a state machine within the system.
 ///
@@ -128,7 +425,7 @@ pub trait CreateStateMachine {
 /// Implementers of this trait must be able to be sent across threads and shared
 /// among threads safely, hence the `Send`, `Sync`, and `'static` bounds. They
 /// should also support debugging through the `Debug` trait.
-pub trait StateMachine: Send + Sync + Debug + 'static {
+pub trait StateMachine<M = ArbiterMiddleware>: Send + Sync + Debug + 'static {
     /// Executes a given instruction asynchronously.
     ///
     /// This method takes a mutable reference to self, allowing the state
@@ -145,7 +442,21 @@ pub trait StateMachine: Send + Sync + Debug + 'static {
     /// This method does not return a value, but it may result in state changes
     /// within the implementing type or the generation of further instructions
     /// or events.
-    async fn execute(&mut self, _instruction: MachineInstruction) -> Result<()>;
+    async fn execute(&mut self, _instruction: MachineInstruction<M>) -> Result<()>;
+
+    /// Returns the ids of the agents the state machine waits for before it
+    /// starts, which is none by default.
+    fn dependencies(&self) -> Vec<String> {
+        vec![]
+    }
+
+    /// Describes the behaviors the state machine runs in the order they
+    /// start, which are drawn in the graph of its world, see the
+    /// [`graph`](crate::graph) module. A state machine describes no behavior
+    /// by default.
+    fn behaviors(&self) -> Vec<BehaviorNode> {
+        vec![]
+    }
 }
 
 /// The `Engine` struct represents the core logic unit of a state machine-based
@@ -153,8 +464,9 @@ pub trait StateMachine: Send + Sync + Debug + 'static {
 /// of events to and from this behavior, effectively driving the entity's
 /// response to external stimuli.
 ///
-/// The `Engine` is generic over a behavior type `B` and an event type `E`,
-/// allowing it to be used with a wide variety of behaviors and event sources.
+/// The `Engine` is generic over a behavior type `B`, an event type `E`, and the
+/// type `M` of the client the behavior runs with, allowing it to be used with a
+/// wide variety of behaviors and event sources.
 /// It is itself a state machine, capable of executing instructions that
 /// manipulate its behavior or react to events.
 ///
@@ -163,10 +475,11 @@ pub trait StateMachine: Send + Sync + Debug + 'static {
 /// - `behavior`: An optional behavior that the engine is currently managing.
 ///   This is where the engine's logic is primarily executed in response to
 ///   events.
-pub struct Engine<B, E>
+pub struct Engine<B, E, M = ArbiterMiddleware>
 where
-    B: Behavior<E>,
+    B: Behavior<E, M>,
     E: Send + 'static,
+    M: ArbiterClient,
 {
     /// The behavior the `Engine` runs.
     behavior: Option<B>,
@@ -178,12 +491,60 @@ where
     /// The [`State::Processing`] stage will attempt a decode of the [`String`]s
     /// into the event type `<E>`.
     event_stream: Option<EventStream<E>>,
+
+    /// The profiler of the environment along with the name of the section the
+    /// [`Engine`] records into, e.g., `agent/Behavior`.
+    profiler: Option<(Profiler, String)>,
+
+    /// Counts what the behavior does, see the [`metrics`](crate::metrics)
+    /// module.
+    counters: Arc<Counters>,
+
+    /// The key the state and events of the behavior are kept under, e.g.,
+    /// `agent/0/Behavior`, which is its slot in its world and its type.
+    key: String,
+
+    /// Keeps the state of the behavior for checkpoints of its world.
+    checkpoints: Checkpoints,
+
+    /// Records or replays the events delivered to the behavior.
+    inputs: Inputs,
+
+    /// The client, messager, and configuration that a behavior with
+    /// [`Supervision::Restart`] is restarted with.
+    restart: Option<(Arc<M>, Messager, serde_json::Value)>,
+
+    /// The scheduler that orders the [`Engine`] with the other behaviors of
+    /// its world.
+    scheduler: Scheduler,
+
+    /// Whether the agent of the [`Engine`] is paused.
+    paused: Option<watch::Receiver<bool>>,
+
+    /// Stops the [`Engine`] along with the rest of its world.
+    stopper: Stopper,
+
+    /// The parameters the behavior is reloaded with, see the
+    /// [`reload`](crate::reload) module.
+    reloads: Option<watch::Receiver<Option<serde_json::Value>>>,
+
+    /// Publishes the errors the behavior fails to process events with, along
+    /// with the id of its agent and its type.
+    errors: Option<(Sender<ErrorEvent>, String, String)>,
+
+    /// The span the behavior runs in, see the [`logging`](crate::logging)
+    /// module. Its task enters the span of its agent as well, whose log level
+    /// applies to it.
+    span: tracing::Span,
+
+    client: PhantomData<fn() -> M>,
 }
 
-impl<B, E> Debug for Engine<B, E>
+impl<B, E, M> Debug for Engine<B, E, M>
 where
-    B: Behavior<E>,
+    B: Behavior<E, M>,
     E: DeserializeOwned + Send + Sync + 'static,
+    M: ArbiterClient,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Engine")
@@ -193,10 +554,11 @@ where
     }
 }
 
-impl<B, E> Engine<B, E>
+impl<B, E, M> Engine<B, E, M>
 where
-    B: Behavior<E> + Debug,
+    B: Behavior<E, M> + Debug,
     E: DeserializeOwned + Send + Sync + 'static,
+    M: ArbiterClient,
 {
     /// Creates a new [`Engine`] with the given [`Behavior`] and [`Receiver`].
     pub fn new(behavior: B) -> Self {
@@ -204,17 +566,31 @@ where
             behavior: Some(behavior),
             state: State::Uninitialized,
             event_stream: None,
+            profiler: None,
+            counters: Arc::default(),
+            key: String::new(),
+            checkpoints: Checkpoints::default(),
+            inputs: Inputs::default(),
+            restart: None,
+            scheduler: Scheduler::default(),
+            paused: None,
+            stopper: Stopper::default(),
+            reloads: None,
+            errors: None,
+            span: tracing::Span::none(),
+            client: PhantomData,
         }
     }
 }
 
 #[async_trait::async_trait]
-impl<B, E> StateMachine for Engine<B, E>
+impl<B, E, M> StateMachine<M> for Engine<B, E, M>
 where
-    B: Behavior<E> + Debug + Serialize + DeserializeOwned,
+    B: Behavior<E, M> + Debug + Serialize + DeserializeOwned,
     E: DeserializeOwned + Serialize + Send + Sync + Debug + 'static,
+    M: ArbiterClient,
 {
-    async fn execute(&mut self, instruction: MachineInstruction) -> Result<()> {
+    async fn execute(&mut self, instruction: MachineInstruction<M>) -> Result<()> {
         // NOTE: The unwraps here are safe because the `Behavior` in an engine is only
         // accessed here and it is private.
         let id: Option<String>;
@@ -223,28 +599,123 @@ where
                 id = messager.id.clone();
                 let id_clone = id.clone();
                 self.state = State::Starting;
+                let name = graph::short_type_name(std::any::type_name::<B>());
+                let section = format!("{}/{}", id.as_deref().unwrap_or("unknown"), name);
+                self.span = info_span!(BEHAVIOR_SPAN, name = name.as_str());
+                self.key = match &messager.slot {
+                    Some(slot) => format!("{}/{}", slot, name),
+                    None => section.clone(),
+                };
+                let key = self.key.clone();
+                let profiler = client.profiler();
+                self.profiler = Some((profiler.clone(), section.clone()));
+                self.counters = messager.metrics.counters(&section);
+                let counters = self.counters.clone();
+                self.checkpoints = messager.checkpoints.clone();
+                let checkpoints = self.checkpoints.clone();
+                self.inputs = messager.inputs.clone();
+                self.scheduler = messager.scheduler.clone();
+                self.paused = messager.paused.clone();
+                self.stopper = messager.stopper.clone();
+                self.reloads = messager
+                    .slot
+                    .as_ref()
+                    .map(|slot| messager.reloader.subscribe(slot));
+                self.errors = Some((
+                    messager.errors.clone(),
+                    id.clone().unwrap_or_else(|| "unknown".to_owned()),
+                    name.to_owned(),
+                ));
                 let mut behavior = self.behavior.take().unwrap();
-                let behavior_task: JoinHandle<Result<(Option<EventStream<E>>, B)>> =
-                    tokio::spawn(async move {
-                        let stream = match behavior.startup(client, messager).await {
-                            Ok(stream) => stream,
-                            Err(e) => {
-                                error!(
-                                    "startup failed for behavior {:?}: \n reason: {:?}",
-                                    id_clone, e
-                                );
-                                // Throw a panic as we cannot recover from this for now.
-                                panic!();
+                if let Some(state) = self.checkpoints.restored(&key) {
+                    debug!("Restoring the behavior from a checkpoint.");
+                    behavior = serde_json::from_value(state)?;
+                }
+                // The behavior starts up once the agents it depends on have.
+                let readiness = messager.readiness.clone();
+                let barrier = readiness.clone();
+                let dependencies = behavior.dependencies();
+                if !dependencies.is_empty() {
+                    debug!("Waiting for the dependencies {:?}.", dependencies);
+                    let waited = tokio::select! {
+                        waited = readiness.wait(&dependencies) => waited.map_err(|dependency| {
+                            HaltReason::Error(format!(
+                                "the dependency `{}` failed to start up",
+                                dependency
+                            ))
+                        }),
+                        _ = self.stopper.stopped() => Err(HaltReason::Stopped),
+                    };
+                    if let Err(reason) = waited {
+                        warn!("Behavior {:?} halted before starting up: {}", id, reason);
+                        if let Some(id) = &id {
+                            readiness.failed(id);
+                        }
+                        self.inputs.skip(&key);
+                        self.counters.halted(reason);
+                        self.behavior = Some(behavior);
+                        return Ok(());
+                    }
+                }
+                if let Supervision::Restart { .. } = behavior.supervision() {
+                    self.restart = Some((
+                        client.clone(),
+                        messager.clone(),
+                        serde_json::to_value(&behavior)?,
+                    ));
+                }
+                let behavior_task: JoinHandle<Result<(Option<EventStream<E>>, B)>> = tokio::spawn(
+                    counters
+                        .clone()
+                        .scope(async move {
+                            let startup_section = format!("{}/startup", section);
+                            let _timer = profiler.timer(&startup_section);
+                            let trace_id = format!("{}#0", key);
+                            let startup =
+                                causality::scope(trace_id, behavior.startup(client, messager));
+                            let stream = match startup.await {
+                                Ok(stream) => stream,
+                                Err(e) => {
+                                    if let Some(id) = &id_clone {
+                                        readiness.failed(id);
+                                    }
+                                    counters.failed();
+                                    counters.halted(HaltReason::Error(e.to_string()));
+                                    error!(
+                                        "startup failed for behavior {:?}: \n reason: {:?}",
+                                        id_clone, e
+                                    );
+                                    // Throw a panic as we cannot recover from this for now.
+                                    panic!();
+                                }
+                            };
+                            debug!("startup complete for behavior {:?}", id_clone);
+                            if let Some(id) = &id_clone {
+                                readiness.ready(id);
                             }
-                        };
-                        debug!("startup complete for behavior {:?}", id_clone);
-                        Ok((stream, behavior))
-                    });
-                let (stream, behavior) = behavior_task.await??;
+                            checkpoints.save(&key, &behavior);
+                            Ok((stream, behavior))
+                        })
+                        .instrument(self.span.clone())
+                        .in_current_span(),
+                );
+                let (stream, behavior) = AbortOnDrop(behavior_task).join().await??;
                 match stream {
                     Some(stream) => {
-                        self.event_stream = Some(stream);
+                        // A replayed behavior receives the events of the log
+                        // instead of the ones of its stream.
+                        self.event_stream = Some(if self.inputs.is_replaying() {
+                            self.inputs.replayed(&self.key)
+                        } else {
+                            stream
+                        });
                         self.behavior = Some(behavior);
+                        // The events wait in the stream until the barrier of
+                        // the world is passed.
+                        tokio::select! {
+                            _ = barrier.barrier() => {}
+                            _ = self.stopper.stopped() => {}
+                        }
                         match self.execute(MachineInstruction::Process).await {
                             Ok(_) => {}
                             Err(e) => {
@@ -254,6 +725,10 @@ where
                         Ok(())
                     }
                     None => {
+                        self.inputs.skip(&self.key);
+                        self.counters.halted(HaltReason::done());
+                        let mut behavior = behavior;
+                        behavior.teardown().await;
                         self.behavior = Some(behavior);
                         Ok(())
                     }
@@ -263,22 +738,411 @@ where
                 trace!("Behavior is starting up.");
                 let mut behavior = self.behavior.take().unwrap();
                 let mut stream = self.event_stream.take().unwrap();
-                let behavior_task: JoinHandle<Result<B>> = tokio::spawn(async move {
-                    while let Some(event) = stream.next().await {
-                        match behavior.process(event).await? {
-                            ControlFlow::Halt => {
-                                break;
-                            }
-                            ControlFlow::Continue => {}
+                let (profiler, section) = self.profiler.clone().unwrap_or_default();
+                let supervision = behavior.supervision();
+                let timeout = behavior.timeout();
+                stream = buffered(stream, behavior.backpressure(), &self.counters);
+                let restart = self.restart.take();
+                let scheduler = self.scheduler.clone();
+                let mut paused = self.paused.clone();
+                let stopper = self.stopper.clone();
+                let mut reloads = self.reloads.take();
+                let errors = self.errors.clone();
+                let span = self.span.clone();
+                let counters = self.counters.clone();
+                let checkpoints = self.checkpoints.clone();
+                let inputs = self.inputs.clone();
+                let key = self.key.clone();
+                let behavior_task: JoinHandle<Result<B>> = tokio::spawn(
+                    counters.clone().scope(async move {
+                        // Time spent waiting for the next event is kept apart from the time
+                        // spent processing it.
+                        let wait_section = format!("{}/wait", section);
+                        let process_section = format!("{}/process", section);
+                        let mut restarts = 0;
+                        let mut sequence = 0u64;
+                        let result: Result<HaltReason> = async {
+                            let reason = 'events: loop {
+                                let timer = profiler.timer(&wait_section);
+                                let mut event = tokio::select! {
+                                    event = stream.next() => match event {
+                                        Some(event) => {
+                                            counters.received();
+                                            inputs.delivered(&key, &event);
+                                            sequence += 1;
+                                            event
+                                        }
+                                        None => break HaltReason::StreamEnded,
+                                    },
+                                    _ = stopper.stopped() => break HaltReason::Stopped,
+                                    Some(parameters) = reloaded(&mut reloads) => {
+                                        match serde_json::from_value(parameters) {
+                                            Ok(reconfigured) => {
+                                                debug!("Reloading the parameters of the behavior.");
+                                                behavior.reload(reconfigured);
+                                                checkpoints.save(&key, &behavior);
+                                            }
+                                            Err(e) => warn!("Could not reload the behavior: {}", e),
+                                        }
+                                        continue 'events;
+                                    }
+                                };
+                                // A paused behavior holds on to the event until it is resumed.
+                                if let Some(paused) = paused.as_mut() {
+                                    // The others do not wait for a paused behavior to take its turn.
+                                    let _paused = paused.borrow().then(|| {
+                                        debug!("Paused until resumed.");
+                                        scheduler.pause()
+                                    });
+                                    tokio::select! {
+                                        _ = paused.wait_for(|paused| !paused) => {}
+                                        _ = stopper.stopped() => break HaltReason::Stopped,
+                                    }
+                                }
+                                drop(timer);
+                                // The turn is held until the event is processed, retries included.
+                                let _turn = scheduler.turn().await;
+                                let _timer = profiler.timer(&process_section);
+                                let trace_id = causality::origin(&event, &key, sequence);
+                                let mut retries = 0;
+                                loop {
+                                    // Events are copied through their serialization to be retried.
+                                    let copy = match supervision {
+                                        Supervision::Retry { .. } => Some(serde_json::to_value(&event)?),
+                                        _ => None,
+                                    };
+                                    let processing = causality::scope(
+                                        trace_id.clone(),
+                                        process(&mut behavior, event, timeout),
+                                    )
+                                    .instrument(info_span!(EVENT_SPAN, seq = sequence, trace = %trace_id));
+                                    let error = match processing.await {
+                                        Ok(flow) => {
+                                            counters.processed();
+                                            checkpoints.save(&key, &behavior);
+                                            match flow {
+                                                ControlFlow::Halt(reason) => break 'events reason,
+                                                ControlFlow::Continue => continue 'events,
+                                            }
+                                        }
+                                        Err(error) => error,
+                                    };
+                                    counters.failed();
+                                    if let Some((errors, agent, name)) = &errors {
+                                        // Nobody may be listening for errors.
+                                        let _ = errors.send(ErrorEvent {
+                                            agent: agent.clone(),
+                                            behavior: name.clone(),
+                                            chain: error.chain(),
+                                        });
+                                    }
+                                    match supervision {
+                                        Supervision::Skip => {
+                                            warn!("Skipping an event the behavior failed to process: {}", error);
+                                            continue 'events;
+                                        }
+                                        Supervision::Retry { attempts, backoff } if retries < attempts => {
+                                            let delay = backoff * 2u32.saturating_pow(retries);
+                                            retries += 1;
+                                            warn!(
+                                                "Retrying an event the behavior failed to process in {:?} ({}/{}): {}",
+                                                delay, retries, attempts, error
+                                            );
+                                            tokio::time::sleep(delay).await;
+                                            event = serde_json::from_value(copy.unwrap())?;
+                                        }
+                                        Supervision::Restart { attempts } if restarts < attempts => {
+                                            restarts += 1;
+                                            warn!(
+                                                "Restarting the behavior ({}/{}) as it failed to process an event: {}",
+                                                restarts, attempts, error
+                                            );
+                                            let (client, messager, config) = restart.as_ref().unwrap();
+                                            behavior = serde_json::from_value(config.clone())?;
+                                            match behavior.startup(client.clone(), messager.clone()).await? {
+                                                Some(_) if inputs.is_replaying() => {}
+                                                Some(restarted) => {
+                                                    stream = buffered(restarted, behavior.backpressure(), &counters);
+                                                }
+                                                None => break 'events HaltReason::done(),
+                                            }
+                                            continue 'events;
+                                        }
+                                        _ => return Err(error.into_inner()),
+                                    }
+                                }
+                            };
+                            Ok(reason)
                         }
-                    }
-                    Ok(behavior)
-                });
+                        .await;
+                        let reason = match &result {
+                            Ok(reason) => reason.clone(),
+                            Err(error) => HaltReason::Error(error.to_string()),
+                        };
+                        debug!("Halted: {}", reason);
+                        counters.halted(reason);
+                        // The behavior is torn down however it stopped processing.
+                        behavior.teardown().await;
+                        result.map(|_| behavior)
+                    })
+                    .instrument(span)
+                    .in_current_span(),
+                );
                 // TODO: We don't have to store the behavior again here, we could just discard
                 // it.
-                self.behavior = Some(behavior_task.await??);
+                self.behavior = Some(AbortOnDrop(behavior_task).join().await??);
                 Ok(())
             }
         }
     }
+
+    fn dependencies(&self) -> Vec<String> {
+        self.behavior
+            .as_ref()
+            .map(Behavior::dependencies)
+            .unwrap_or_default()
+    }
+
+    fn behaviors(&self) -> Vec<BehaviorNode> {
+        vec![BehaviorNode::new::<B, E>(self.dependencies())]
+    }
+}
+
+/// A sequence of state machines that run one after the other, e.g., a behavior
+/// that deploys contracts followed by one that trades on them.
+///
+/// Each phase is started once the previous one is done, i.e., its behavior
+/// halted, its event stream ended, or it returned no stream from
+/// [`Behavior::startup`]. Every phase is started with the client of the chain
+/// and a [`Messager`] that only receives the messages sent after it starts, so
+/// a phase hands its results to the next one through its client or the
+/// [`Blackboard`](crate::blackboard::Blackboard) of its messager. Once the
+/// world is stopped, no further phase is started.
+#[derive(Debug)]
+pub struct Chain<M = ArbiterMiddleware> {
+    phases: Vec<Box<dyn StateMachine<M>>>,
+}
+
+impl<M: ArbiterClient> Chain<M> {
+    /// Creates a [`Chain`] that starts with `first`.
+    pub fn new(first: Box<dyn StateMachine<M>>) -> Self {
+        Self {
+            phases: vec![first],
+        }
+    }
+
+    /// Appends `next` to the chain, which starts once the phase before it is
+    /// done.
+    pub fn then(mut self, next: Box<dyn StateMachine<M>>) -> Self {
+        self.phases.push(next);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> StateMachine<M> for Chain<M> {
+    async fn execute(&mut self, instruction: MachineInstruction<M>) -> Result<()> {
+        // The phases process their events while they are started, so there is
+        // nothing left to process afterwards.
+        let MachineInstruction::Start(client, messager) = instruction else {
+            return Ok(());
+        };
+        let count = self.phases.len();
+        for (index, phase) in self.phases.iter_mut().enumerate() {
+            // The phases after the one the world was stopped in are not started.
+            if messager.stopper.is_stopped() {
+                break;
+            }
+            debug!("Starting phase {}/{} of the chain.", index + 1, count);
+            phase
+                .execute(MachineInstruction::Start(client.clone(), messager.clone()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the dependencies of all of the phases, each of which waits for
+    /// its own before it starts.
+    fn dependencies(&self) -> Vec<String> {
+        let mut dependencies = vec![];
+        for phase in &self.phases {
+            for dependency in phase.dependencies() {
+                if !dependencies.contains(&dependency) {
+                    dependencies.push(dependency);
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// Describes the behaviors of all of the phases in the order they start.
+    fn behaviors(&self) -> Vec<BehaviorNode> {
+        self.phases
+            .iter()
+            .flat_map(|phase| phase.behaviors())
+            .collect()
+    }
+}
+
+/// The task of a behavior, which is aborted if the [`Engine`] waiting for it is
+/// dropped, e.g., because its agent was despawned, so that the behavior along
+/// with its event stream, messager, and client are dropped too.
+struct AbortOnDrop<T>(JoinHandle<T>);
+
+impl<T> AbortOnDrop<T> {
+    async fn join(mut self) -> Result<T, tokio::task::JoinError> {
+        (&mut self.0).await
+    }
+}
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The events pulled from the stream of a behavior ahead of it.
+#[derive(Debug)]
+struct Buffer<E> {
+    events: std::sync::Mutex<VecDeque<E>>,
+    ended: AtomicBool,
+    pushed: Notify,
+    popped: Notify,
+}
+
+/// Pulls the events of `stream` into a buffer ahead of the behavior according
+/// to its `backpressure`, if it has any, from a task of its own which is
+/// aborted once the returned stream is dropped.
+fn buffered<E: Send + Sync + 'static>(
+    mut stream: EventStream<E>,
+    backpressure: Option<Backpressure>,
+    counters: &Arc<Counters>,
+) -> EventStream<E> {
+    let Some(backpressure) = backpressure else {
+        return stream;
+    };
+    let capacity = match backpressure {
+        Backpressure::Block { capacity } | Backpressure::DropOldest { capacity } => capacity.max(1),
+    };
+    let buffer = Arc::new(Buffer {
+        events: std::sync::Mutex::new(VecDeque::with_capacity(capacity)),
+        ended: AtomicBool::new(false),
+        pushed: Notify::new(),
+        popped: Notify::new(),
+    });
+    let pump = {
+        let buffer = buffer.clone();
+        let counters = counters.clone();
+        // The messages the stream misses are counted towards the behavior.
+        tokio::spawn(
+            counters
+                .clone()
+                .scope(async move {
+                    while let Some(event) = stream.next().await {
+                        let mut event = Some(event);
+                        while let Some(next) = event.take() {
+                            let full = {
+                                let mut events = buffer.events.lock().unwrap();
+                                if events.len() < capacity {
+                                    events.push_back(next);
+                                    false
+                                } else if let Backpressure::DropOldest { .. } = backpressure {
+                                    events.pop_front();
+                                    events.push_back(next);
+                                    counters.dropped();
+                                    false
+                                } else {
+                                    event = Some(next);
+                                    counters.blocked();
+                                    true
+                                }
+                            };
+                            if full {
+                                buffer.popped.notified().await;
+                            }
+                        }
+                        buffer.pushed.notify_one();
+                    }
+                    buffer.ended.store(true, Ordering::SeqCst);
+                    buffer.pushed.notify_one();
+                })
+                .in_current_span(),
+        )
+    };
+    Box::pin(async_stream::stream! {
+        let _pump = AbortOnDrop(pump);
+        loop {
+            // The buffer is only empty for good once the stream ended before it
+            // was found empty.
+            let ended = buffer.ended.load(Ordering::SeqCst);
+            let next = buffer.events.lock().unwrap().pop_front();
+            match next {
+                Some(event) => {
+                    buffer.popped.notify_one();
+                    yield event;
+                }
+                None if ended => break,
+                None => buffer.pushed.notified().await,
+            }
+        }
+    })
+}
+
+/// Waits for the parameters a behavior is reloaded with, or forever if it is
+/// not reloaded.
+async fn reloaded(
+    reloads: &mut Option<watch::Receiver<Option<serde_json::Value>>>,
+) -> Option<serde_json::Value> {
+    let Some(receiver) = reloads else {
+        return std::future::pending().await;
+    };
+    if receiver.changed().await.is_err() {
+        return std::future::pending().await;
+    }
+    receiver.borrow_and_update().clone()
+}
+
+/// Processes `event` with `behavior`, turning a panic or exceeding the
+/// `timeout` into a [`BehaviorError`].
+async fn process<B, E, M>(
+    behavior: &mut B,
+    event: E,
+    timeout: Option<Duration>,
+) -> Result<ControlFlow, BehaviorError>
+where
+    B: Behavior<E, M>,
+    E: Send + 'static,
+    M: ArbiterClient,
+{
+    let processing = AssertUnwindSafe(behavior.process(event)).catch_unwind();
+    let processed = match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, processing).await {
+            Ok(processed) => processed,
+            Err(_) => {
+                error!(
+                    "Cancelled processing an event after it took longer than {:?}.",
+                    timeout
+                );
+                return Err(BehaviorError::msg(format!(
+                    "the behavior took longer than {:?} to process an event",
+                    timeout
+                )));
+            }
+        },
+        None => processing.await,
+    };
+    match processed {
+        Ok(result) => result,
+        Err(panic) => {
+            let reason = panic
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(BehaviorError::msg(format!(
+                "the behavior panicked: {}",
+                reason
+            )))
+        }
+    }
 }
@@ -1,13 +1,80 @@
 //! The messager module contains the core messager layer for the Arbiter Engine.
+//!
+//! Messages carry their data as JSON, so that agents can exchange any
+//! serializable value. A type that implements [`Payload`] is sent with a tag
+//! of its type by [`Messager::send_payload`], so that its receivers decode it
+//! with [`Message::decode`], or only stream the messages of its type with
+//! [`Messager::payloads`], and a message of another type is caught with an
+//! error rather than mistaken for it:
+//! ```ignore
+//! #[derive(Debug, Serialize, Deserialize)]
+//! struct Quote {
+//!     price: f64,
+//! }
+//!
+//! impl Payload for Quote {
+//!     const TAG: &'static str = "Quote";
+//! }
+//!
+//! messager.send_payload(To::All, &Quote { price: 1.0 }).await?;
+//! // And in the behaviors of the receivers.
+//! let quote: Message<Quote> = message.decode()?;
+//! ```
 
-use tokio::sync::broadcast::{channel, Receiver, Sender};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use arbiter_core::profiler::Profiler;
+use ethers::{providers::Middleware, types::BlockNumber};
+use futures_util::StreamExt;
+use rand::rngs::StdRng;
+use tokio::{
+    sync::{
+        broadcast::{
+            channel,
+            error::{RecvError, SendError, TryRecvError},
+            Receiver, Sender,
+        },
+        watch,
+    },
+    task::AbortHandle,
+};
 
 use super::*;
-use crate::machine::EventStream;
+use crate::{
+    blackboard::{Blackboard, Shared},
+    causality,
+    checkpoint::Checkpoints,
+    dead_letters::DeadLetters,
+    dependencies::Readiness,
+    determinism::Recorder,
+    errors::ErrorEvent,
+    journal::Journal,
+    machine::EventStream,
+    metrics::{Counters, Metrics},
+    random::Randomness,
+    reload::Reloader,
+    replay::Inputs,
+    scheduler::{self, Scheduler},
+    schema::Schemas,
+    trigger::BLOCK_POLL_INTERVAL,
+    world::{Pauser, Stopper},
+};
+
+/// The number of messages each lane of the messagers of a world keeps unless
+/// it is set with
+/// [`World::with_message_capacity`](crate::world::World::with_message_capacity).
+pub const DEFAULT_MESSAGE_CAPACITY: usize = 512;
 
-/// A message that can be sent between agents.
-#[derive(Clone, Debug, Deserialize, Serialize)]
-pub struct Message {
+/// A message that can be sent between agents, whose data is JSON unless it
+/// has been decoded into a [`Payload`] `T` with [`Message::decode`].
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct Message<T = String> {
     /// The sender of the message.
     pub from: String,
 
@@ -16,7 +83,93 @@ pub struct Message {
 
     /// The data of the message.
     /// This can be a struct serialized into JSON.
-    pub data: String,
+    pub data: T,
+
+    /// The [`Payload::TAG`] of the data if it was sent with
+    /// [`Messager::send_payload`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+
+    /// The id that pairs a request sent with [`Messager::request`] with its
+    /// reply, which [`Messager::reply`] copies from the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
+    /// The lane the message is delivered in.
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+
+    /// The id of the trace of the event the message was sent for, see the
+    /// [`causality`](crate::causality) module.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+/// The lane a [`Message`] is delivered in. Every agent receives the messages
+/// of the control lane that are waiting for it before those of the normal
+/// lane, so that commands, e.g., to pause or halt, are not held up behind a
+/// flood of data, see [`Messager::send_control`].
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum Priority {
+    /// The lane of the messages sent with [`Messager::send`] and
+    /// [`Messager::send_payload`].
+    #[default]
+    Normal,
+
+    /// The lane of the messages sent with [`Messager::send_control`].
+    Control,
+}
+
+impl Priority {
+    pub(crate) fn is_normal(&self) -> bool {
+        *self == Priority::Normal
+    }
+}
+
+/// A type of data that is sent in messages with a tag of its type, see the
+/// [module documentation](self).
+pub trait Payload: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// The tag of the type, e.g., its name, which has to differ from the tags
+    /// of the other payloads sent to the same agents.
+    const TAG: &'static str;
+}
+
+impl Message {
+    /// Decodes the data of the message into the payload `T`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::MessagerError`] if the message was sent
+    /// with the tag of another payload, or if its data is not a `T`.
+    pub fn decode<T: Payload>(&self) -> Result<Message<T>, ArbiterEngineError> {
+        if let Some(tag) = self.tag.as_deref().filter(|tag| *tag != T::TAG) {
+            return Err(ArbiterEngineError::MessagerError(format!(
+                "Expected `{}` from `{}` but received `{}`",
+                T::TAG,
+                self.from,
+                tag
+            )));
+        }
+        let data = serde_json::from_str(&self.data).map_err(|e| {
+            ArbiterEngineError::MessagerError(format!(
+                "Could not decode a `{}` from `{}`: {}",
+                T::TAG,
+                self.from,
+                e
+            ))
+        })?;
+        Ok(Message {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            data,
+            tag: self.tag.clone(),
+            correlation_id: self.correlation_id.clone(),
+            priority: self.priority,
+            trace_id: self.trace_id.clone(),
+        })
+    }
 }
 
 /// The recipient of the message.
@@ -27,6 +180,10 @@ pub enum To {
 
     /// Send the message to a specific agent.
     Agent(String),
+
+    /// Send the message to every agent in a group, e.g., all liquidity
+    /// providers, see [`AgentBuilder::with_group`](crate::agent::AgentBuilder::with_group).
+    Group(String),
 }
 
 /// A messager that can be used to send messages between agents.
@@ -35,9 +192,81 @@ pub struct Messager {
     /// The identifier of the entity that is using the messager.
     pub id: Option<String>,
 
-    pub(crate) broadcast_sender: Sender<Message>,
+    pub(crate) broadcast_sender: Lanes,
+
+    broadcast_receiver: Option<LaneReceiver>,
 
-    broadcast_receiver: Option<Receiver<Message>>,
+    /// Records the time spent serializing messages that are sent.
+    pub(crate) profiler: Profiler,
+
+    /// The values shared by every agent connected to the messager.
+    pub(crate) blackboard: Blackboard,
+
+    /// Counts what each behavior of the world does.
+    pub(crate) metrics: Metrics,
+
+    /// Keeps the state of the behavior using the messager for checkpoints of
+    /// the world.
+    pub(crate) checkpoints: Checkpoints,
+
+    /// Records or replays the events delivered to the behavior using the
+    /// messager.
+    pub(crate) inputs: Inputs,
+
+    /// The slot of the behavior using the messager in a running world, e.g.,
+    /// `agent/0`, which is its agent along with its position among the
+    /// behaviors of the agent.
+    pub(crate) slot: Option<Arc<str>>,
+
+    /// Records the messages that are sent when tracing is enabled.
+    pub(crate) recorder: Recorder,
+
+    /// Logs the messages that are sent to a file when it is open.
+    pub(crate) journal: Journal,
+
+    /// Keeps the messages that are sent to agents that cannot receive them.
+    pub(crate) dead_letters: DeadLetters,
+
+    /// Rejects the messages that do not match the schemas of their topic or
+    /// recipient.
+    pub(crate) schemas: Schemas,
+
+    /// Orders the behavior using the messager with the other behaviors of the
+    /// world when scheduling is deterministic.
+    pub(crate) scheduler: Scheduler,
+
+    /// The seed of the world and the random number generator of the agent
+    /// using the messager.
+    pub(crate) randomness: Randomness,
+
+    /// Pauses and resumes the agents connected to the messager.
+    pub(crate) pauser: Pauser,
+
+    /// Stops the world the messager belongs to.
+    pub(crate) stopper: Stopper,
+
+    /// Which agents of the world have started up, which the behaviors wait
+    /// for before starting up after the agents they depend on.
+    pub(crate) readiness: Readiness,
+
+    /// Reloads the parameters of the behaviors from the configuration of the
+    /// world.
+    pub(crate) reloader: Reloader,
+
+    /// Publishes the errors the behaviors of the world fail to process events
+    /// with.
+    pub(crate) errors: Sender<ErrorEvent>,
+
+    /// Whether the agent using the messager is paused, if it has an `id`.
+    pub(crate) paused: Option<watch::Receiver<bool>>,
+
+    /// The private scope shared with the sub-agents of the agent using the
+    /// messager, if it has any.
+    scope: Option<Box<Messager>>,
+
+    /// Counts the requests sent by the agent using the messager, which
+    /// numbers their correlation ids.
+    requests: Arc<AtomicU64>,
 }
 
 impl Clone for Messager {
@@ -46,6 +275,26 @@ impl Clone for Messager {
             broadcast_sender: self.broadcast_sender.clone(),
             broadcast_receiver: Some(self.broadcast_sender.subscribe()),
             id: self.id.clone(),
+            profiler: self.profiler.clone(),
+            blackboard: self.blackboard.clone(),
+            metrics: self.metrics.clone(),
+            checkpoints: self.checkpoints.clone(),
+            inputs: self.inputs.clone(),
+            slot: self.slot.clone(),
+            recorder: self.recorder.clone(),
+            journal: self.journal.clone(),
+            dead_letters: self.dead_letters.clone(),
+            schemas: self.schemas.clone(),
+            scheduler: self.scheduler.clone(),
+            randomness: self.randomness.clone(),
+            pauser: self.pauser.clone(),
+            stopper: self.stopper.clone(),
+            readiness: self.readiness.clone(),
+            reloader: self.reloader.clone(),
+            errors: self.errors.clone(),
+            paused: self.paused.clone(),
+            scope: self.scope.clone(),
+            requests: self.requests.clone(),
         }
     }
 }
@@ -54,11 +303,31 @@ impl Messager {
     /// Creates a new messager with the given capacity.
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let (broadcast_sender, broadcast_receiver) = channel(512);
+        let broadcast_sender = Lanes::new(DEFAULT_MESSAGE_CAPACITY);
         Self {
+            broadcast_receiver: Some(broadcast_sender.subscribe()),
             broadcast_sender,
-            broadcast_receiver: Some(broadcast_receiver),
             id: None,
+            profiler: Profiler::default(),
+            blackboard: Blackboard::default(),
+            metrics: Metrics::default(),
+            checkpoints: Checkpoints::default(),
+            inputs: Inputs::default(),
+            slot: None,
+            recorder: Recorder::default(),
+            journal: Journal::default(),
+            dead_letters: DeadLetters::default(),
+            schemas: Schemas::default(),
+            scheduler: Scheduler::default(),
+            randomness: Randomness::default(),
+            pauser: Pauser::default(),
+            stopper: Stopper::default(),
+            readiness: Readiness::default(),
+            reloader: Reloader::default(),
+            errors: channel(512).0,
+            paused: None,
+            scope: None,
+            requests: Arc::default(),
         }
     }
 
@@ -69,9 +338,160 @@ impl Messager {
             broadcast_sender: self.broadcast_sender.clone(),
             broadcast_receiver: Some(self.broadcast_sender.subscribe()),
             id: Some(id.to_owned()),
+            profiler: self.profiler.clone(),
+            blackboard: self.blackboard.clone(),
+            metrics: self.metrics.clone(),
+            checkpoints: self.checkpoints.clone(),
+            inputs: self.inputs.clone(),
+            slot: None,
+            recorder: self.recorder.clone(),
+            journal: self.journal.clone(),
+            dead_letters: self.dead_letters.clone(),
+            schemas: self.schemas.clone(),
+            scheduler: self.scheduler.clone(),
+            randomness: self.randomness.for_agent(),
+            pauser: self.pauser.clone(),
+            stopper: self.stopper.clone(),
+            readiness: self.readiness.clone(),
+            reloader: self.reloader.clone(),
+            errors: self.errors.clone(),
+            paused: Some(self.pauser.register(id)),
+            scope: None,
+            requests: Arc::default(),
         }
     }
 
+    /// Returns a [`Messager`] without an `id` connected to a new instance that
+    /// only the messagers derived from it are connected to. It shares the
+    /// profiler, blackboard, metrics, checkpoints, inputs, recorder, scheduler, seed,
+    /// pauser, stopper, readiness, reloader, and errors of this messager.
+    pub(crate) fn new_scope(&self) -> Self {
+        let broadcast_sender = Lanes::new(self.broadcast_sender.capacity);
+        Self {
+            broadcast_receiver: Some(broadcast_sender.subscribe()),
+            broadcast_sender,
+            id: None,
+            profiler: self.profiler.clone(),
+            blackboard: self.blackboard.clone(),
+            metrics: self.metrics.clone(),
+            checkpoints: self.checkpoints.clone(),
+            inputs: self.inputs.clone(),
+            slot: None,
+            recorder: self.recorder.clone(),
+            journal: self.journal.clone(),
+            dead_letters: self.dead_letters.clone(),
+            schemas: self.schemas.clone(),
+            scheduler: self.scheduler.clone(),
+            randomness: self.randomness.for_agent(),
+            pauser: self.pauser.clone(),
+            stopper: self.stopper.clone(),
+            readiness: self.readiness.clone(),
+            reloader: self.reloader.clone(),
+            errors: self.errors.clone(),
+            paused: None,
+            scope: None,
+            requests: Arc::default(),
+        }
+    }
+
+    /// Hands the messager, and the scope of the agent's sub-agents if it has
+    /// one, to the behavior with the `scheduler`.
+    pub(crate) fn set_scheduler(&mut self, scheduler: Scheduler) {
+        if let Some(scope) = self.scope.as_mut() {
+            scope.scheduler = scheduler.clone();
+        }
+        self.scheduler = scheduler;
+    }
+
+    /// Replaces the channels of the messager with new ones that keep the last
+    /// `capacity` messages sent in each lane.
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        self.broadcast_sender = Lanes::new(capacity);
+        self.broadcast_receiver = Some(self.broadcast_sender.subscribe());
+    }
+
+    /// Connects the messager to the private `scope` of the agent's
+    /// sub-agents.
+    pub(crate) fn set_scope(&mut self, scope: &Messager) {
+        self.scope = Some(Box::new(Self {
+            id: self.id.clone(),
+            randomness: self.randomness.clone(),
+            paused: self.paused.clone(),
+            requests: self.requests.clone(),
+            ..scope.clone()
+        }));
+    }
+
+    /// Takes the [`Messager`] connected to the private scope of the agent's
+    /// sub-agents. It has the same `id` as this messager, so sub-agents can
+    /// reach their parent with [`To::Agent`] and the parent can reach a
+    /// sub-agent by its full id, e.g., `"fund/strategy"`.
+    ///
+    /// Returns `None` if the agent has no sub-agents or the scope has already
+    /// been taken.
+    pub fn scope(&mut self) -> Option<Messager> {
+        self.scope.take().map(|scope| *scope)
+    }
+
+    /// Returns a handle to the value of type `T` on the [`Blackboard`] shared
+    /// by every agent connected to this messager. Values published through it
+    /// are attributed to the `id` of the messager.
+    pub fn state<T: Clone + Send + Sync + 'static>(&self) -> Shared<T> {
+        self.blackboard.state_for(self.id.clone())
+    }
+
+    /// Returns the random number generator of the agent using the messager,
+    /// which is seeded from the seed of its world and its `id`, see the
+    /// [`random`](crate::random) module.
+    ///
+    /// The generator is shared by the behaviors of the agent, so the returned
+    /// guard should be dropped before awaiting anything.
+    pub fn rng(&self) -> MutexGuard<'_, StdRng> {
+        self.randomness.rng(self.id.as_deref().unwrap_or_default())
+    }
+
+    /// Returns a [`Pauser`] that pauses and resumes the agents connected to
+    /// the messager, e.g., for a behavior that scripts a scenario.
+    pub fn pauser(&self) -> Pauser {
+        self.pauser.clone()
+    }
+
+    /// Returns a [`Stopper`] that stops the world the messager belongs to,
+    /// e.g., for a behavior that ends the simulation once it reached its
+    /// goal.
+    pub fn stopper(&self) -> Stopper {
+        self.stopper.clone()
+    }
+
+    /// Returns a [`Reloader`] that reloads the parameters of the behaviors of
+    /// the world the messager belongs to from its configuration, e.g., for a
+    /// behavior that is told to by a message.
+    pub fn reloader(&self) -> Reloader {
+        self.reloader.clone()
+    }
+
+    /// Returns a stream of the [`ErrorEvent`]s of every behavior of the world
+    /// the messager belongs to, including the sub-agents of its agents, from
+    /// the moment it is called on. A behavior that reacts to both messages
+    /// and errors merges their streams with
+    /// [`EventStreams`](crate::machine::EventStreams).
+    ///
+    /// The last 512 errors are kept, so a stream that falls further behind
+    /// misses the oldest of them.
+    pub fn errors(&self) -> EventStream<ErrorEvent> {
+        let mut receiver = self.errors.subscribe();
+        let id = self.id.clone();
+        Box::pin(async_stream::stream! {
+            loop {
+                match receiver.recv().await {
+                    Ok(error) => yield error,
+                    Err(RecvError::Lagged(count)) => missed(&id, count),
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
     /// utility function for getting the next value from the broadcast_receiver
     /// without streaming
     pub async fn get_next(&mut self) -> Result<Message, ArbiterEngineError> {
@@ -84,26 +504,51 @@ impl Messager {
                 ))
             }
         };
-        while let Ok(message) = receiver.recv().await {
-            match &message.to {
-                To::All => {
-                    return Ok(message);
-                }
-                To::Agent(id) => {
-                    if let Some(self_id) = &self.id {
-                        if id == self_id {
-                            return Ok(message);
-                        }
-                    }
+        loop {
+            let envelope = match receiver.recv().await {
+                Ok(envelope) => envelope,
+                Err(RecvError::Lagged(count)) => {
+                    missed(&self.id, count);
                     continue;
                 }
+                Err(RecvError::Closed) => {
+                    return Err(ArbiterEngineError::MessagerError(
+                        "The channel of the messager is closed.".to_owned(),
+                    ))
+                }
+            };
+            Counters::queued_current(receiver.len());
+            if self.receives(&envelope.message) {
+                self.metrics.delivered(envelope.sent.elapsed());
+                return Ok(envelope.message);
             }
         }
-        unreachable!()
     }
 
-    /// Returns a stream of messages that are either sent to [`To::All`] or to
-    /// the agent via [`To::Agent(id)`].
+    /// Returns whether the `message` is sent to all agents, to the agent of
+    /// the messager, or to a group the agent is in.
+    fn receives(&self, message: &Message) -> bool {
+        match (&message.to, &self.id) {
+            (To::All, _) => true,
+            (To::Agent(id), Some(self_id)) => id == self_id,
+            (To::Group(group), Some(self_id)) => self.pauser.groups.contains(group, self_id),
+            (_, None) => false,
+        }
+    }
+
+    /// Returns a stream of messages that are either sent to [`To::All`], to
+    /// the agent via [`To::Agent(id)`], or to a group the agent is in via
+    /// [`To::Group`].
+    ///
+    /// The messages of the control lane that are waiting are streamed before
+    /// those of the normal lane, see [`Priority`]. Each lane keeps the last
+    /// [`DEFAULT_MESSAGE_CAPACITY`] messages sent in it, or as many as set
+    /// for the world, so a stream that falls further behind misses the oldest
+    /// of them, which are logged and counted in the metrics of its behavior
+    /// before the stream carries on with the next message. How far behind
+    /// the stream has been is counted as the queue depth of the behavior. A
+    /// behavior that expects bursts of messages buffers them with a
+    /// [`Backpressure`](crate::machine::Backpressure).
     pub fn stream(mut self) -> Result<EventStream<Message>, ArbiterEngineError> {
         let mut receiver = match self.broadcast_receiver.take() {
             Some(receiver) => receiver,
@@ -115,22 +560,161 @@ impl Messager {
             }
         };
         Ok(Box::pin(async_stream::stream! {
-            while let Ok(message) = receiver.recv().await {
-                match &message.to {
-                    To::All => {
-                        yield message;
+            // The scheduler follows the stream once the behavior starts taking
+            // its messages.
+            let subscription = self.scheduler.subscribe(self.broadcast_sender.id);
+            loop {
+                subscription.caught_up(|| receiver.len() == 0);
+                let envelope = match receiver.recv().await {
+                    Ok(envelope) => envelope,
+                    Err(RecvError::Lagged(count)) => {
+                        missed(&self.id, count);
+                        continue;
                     }
-                    To::Agent(id) => {
-                        if let Some(self_id) = &self.id {
-                            if id == self_id {
-                                yield message;
-                            }
-                        }
+                    Err(RecvError::Closed) => break,
+                };
+                Counters::queued_current(receiver.len());
+                if self.receives(&envelope.message) {
+                    self.metrics.delivered(envelope.sent.elapsed());
+                    subscription.hold(envelope.sequence);
+                    yield envelope.message;
+                    subscription.release();
+                }
+            }
+        }))
+    }
+
+    /// Returns a stream of the messages that [`Messager::stream`] returns
+    /// which carry the payload `T`, decoded.
+    ///
+    /// The messages sent with the tag of another payload are left out, as
+    /// are those without a tag that are not a `T`. A message with the tag of
+    /// `T` that cannot be decoded is logged and left out.
+    pub fn payloads<T: Payload>(self) -> Result<EventStream<Message<T>>, ArbiterEngineError> {
+        let mut messages = self.stream()?;
+        Ok(Box::pin(async_stream::stream! {
+            while let Some(message) = messages.next().await {
+                match message.decode::<T>() {
+                    Ok(message) => yield message,
+                    Err(e) if message.tag.as_deref() == Some(T::TAG) => {
+                        warn!("{}", e);
                     }
+                    Err(_) => {}
                 }
             }
         }))
     }
+
+    /// Sends the `payload` to the recipient `to` along with the tag of its
+    /// type, see [`Payload`].
+    pub async fn send_payload<T: Payload>(
+        &self,
+        to: To,
+        payload: &T,
+    ) -> Result<(), ArbiterEngineError> {
+        self.send_tagged(to, payload, Some(T::TAG.to_owned()), None, Priority::Normal)
+            .await
+    }
+
+    /// Sends the `payload` to the recipient `to` in the control lane, so that
+    /// it is received before the messages of the normal lane that are waiting
+    /// for the recipient, see [`Priority`].
+    pub async fn send_control<T: Payload>(
+        &self,
+        to: To,
+        payload: &T,
+    ) -> Result<(), ArbiterEngineError> {
+        self.send_tagged(
+            to,
+            payload,
+            Some(T::TAG.to_owned()),
+            None,
+            Priority::Control,
+        )
+        .await
+    }
+
+    /// Sends the `payload` back to the sender of `message`, see
+    /// [`Messager::send_payload`], along with the correlation id of
+    /// `message` if it is a [`Messager::request`].
+    pub async fn reply<T: Payload>(
+        &self,
+        message: &Message,
+        payload: &T,
+    ) -> Result<(), ArbiterEngineError> {
+        self.send_tagged(
+            To::Agent(message.from.clone()),
+            payload,
+            Some(T::TAG.to_owned()),
+            message.correlation_id.clone(),
+            message.priority,
+        )
+        .await
+    }
+
+    /// Sends the `payload` to the recipient `to` as a request and waits for
+    /// the reply to it, which its recipient sends with [`Messager::reply`],
+    /// for at most `timeout`.
+    ///
+    /// The request carries a correlation id that is unique to the agent, so
+    /// the reply is told apart from the other messages the agent receives,
+    /// including the replies to its other requests. The other messages are
+    /// left to the streams of the agent's behaviors.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::MessagerError`] if no reply arrives
+    /// within `timeout`, or if the reply is not an `R`.
+    pub async fn request<Q: Payload, R: Payload>(
+        &self,
+        to: To,
+        payload: &Q,
+        timeout: Duration,
+    ) -> Result<Message<R>, ArbiterEngineError> {
+        let id = self.id.clone().unwrap_or_default();
+        let correlation_id = format!("{}/{}", id, self.requests.fetch_add(1, Ordering::Relaxed));
+        // The receiver is subscribed before the request is sent, so that it
+        // cannot miss a reply that is sent right away.
+        let mut receiver = self.broadcast_sender.subscribe();
+        self.send_tagged(
+            to,
+            payload,
+            Some(Q::TAG.to_owned()),
+            Some(correlation_id.clone()),
+            Priority::Normal,
+        )
+        .await?;
+        let reply = async {
+            loop {
+                match receiver.recv().await {
+                    Ok(Envelope { message, sent, .. })
+                        if message.to == To::Agent(id.clone())
+                            && message.correlation_id.as_ref() == Some(&correlation_id) =>
+                    {
+                        self.metrics.delivered(sent.elapsed());
+                        return Ok(message);
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(count)) => missed(&self.id, count),
+                    Err(RecvError::Closed) => {
+                        return Err(ArbiterEngineError::MessagerError(
+                            "The channel of the messager is closed.".to_owned(),
+                        ))
+                    }
+                }
+            }
+        };
+        match tokio::time::timeout(timeout, reply).await {
+            Ok(reply) => reply?.decode(),
+            Err(_) => Err(ArbiterEngineError::MessagerError(format!(
+                "No reply to the request `{}` for `{}` within {:?}",
+                correlation_id,
+                Q::TAG,
+                timeout
+            ))),
+        }
+    }
+
     /// Asynchronously sends a message to a specified recipient.
     ///
     /// This method constructs a message with the provided data and sends it to
@@ -151,19 +735,326 @@ impl Messager {
     /// - `data`: The data to be sent in the message. This data is serialized
     ///   into JSON format.
     pub async fn send<S: Serialize>(&self, to: To, data: S) -> Result<(), ArbiterEngineError> {
+        self.send_tagged(to, data, None, None, Priority::Normal)
+            .await
+    }
+
+    /// Sends the `data` to the recipient `to` along with its `tag` and
+    /// `correlation_id` in the lane of its `priority`.
+    pub(crate) async fn send_tagged<S: Serialize>(
+        &self,
+        to: To,
+        data: S,
+        tag: Option<String>,
+        correlation_id: Option<String>,
+        priority: Priority,
+    ) -> Result<(), ArbiterEngineError> {
         trace!("Sending message via messager.");
         if let Some(id) = &self.id {
-            let message = Message {
+            let section = format!("{}/serialization", id);
+            let timer = self.profiler.timer(&section);
+            let data = serde_json::to_string(&data)?;
+            drop(timer);
+            self.deliver(Message {
                 from: id.clone(),
                 to,
-                data: serde_json::to_string(&data)?,
-            };
-            self.broadcast_sender.send(message)?;
-            Ok(())
+                data,
+                tag,
+                correlation_id,
+                priority,
+                trace_id: causality::current(),
+            })
         } else {
-            Err(ArbiterEngineError::MessagerError(
-                "Messager has no ID! You must have an ID to send messages!".to_owned(),
-            ))
+            Err(no_id())
         }
     }
+
+    /// Sends the `message`, whose data is already serialized.
+    fn deliver(&self, message: Message) -> Result<(), ArbiterEngineError> {
+        self.dispatch(message, false)
+    }
+
+    /// Sends a `message` relayed from the messager of another world, which
+    /// is not relayed again, see the [`relay`](crate::relay) module.
+    pub(crate) fn relay(&self, message: Message) -> Result<(), ArbiterEngineError> {
+        self.dispatch(message, true)
+    }
+
+    /// Sends the `message`, which was `relayed` from another world or not.
+    fn dispatch(&self, message: Message, relayed: bool) -> Result<(), ArbiterEngineError> {
+        self.schemas.validate(&message)?;
+        self.recorder.record(&message);
+        self.journal.record(&message);
+        self.dead_letters.check(&message, &self.pauser.groups);
+        if let To::Group(group) = &message.to {
+            let members = self.pauser.groups.members(group).len();
+            self.metrics.group_sent(group, members);
+        }
+        self.metrics.sent();
+        self.scheduler
+            .sequence(self.broadcast_sender.id, |sequence| {
+                self.broadcast_sender.send(message, relayed, sequence)
+            })?;
+        Ok(())
+    }
+
+    /// Sends the `payload` to the recipient `to` once `delay` has passed, see
+    /// [`Messager::send_payload`], e.g., for a behavior that closes an
+    /// auction some time after opening it without waiting for it.
+    ///
+    /// The payload is serialized right away and sent from a task, so the
+    /// message is sent even if the behavior has moved on or halted by then,
+    /// unless it is cancelled with the returned [`ScheduledMessage`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::MessagerError`] if the messager has no
+    /// id or the message does not match a [`schema`](crate::schema) of the
+    /// world, or an error if the payload cannot be serialized.
+    pub fn send_after<T: Payload>(
+        &self,
+        delay: Duration,
+        to: To,
+        payload: &T,
+    ) -> Result<ScheduledMessage, ArbiterEngineError> {
+        let message = self.schedule(to, payload)?;
+        let messager = self.sender();
+        let handle = spawn(async move {
+            tokio::time::sleep(delay).await;
+            send_scheduled(&messager, message);
+        });
+        Ok(ScheduledMessage {
+            handle: handle.abort_handle(),
+        })
+    }
+
+    /// Sends the `payload` to the recipient `to` once the timestamp of the
+    /// latest block of `client` is at least `timestamp`, in seconds, e.g., for
+    /// a behavior that settles an option at its expiry in the time of the
+    /// simulation rather than the wall-clock time, see
+    /// [`Messager::send_after`].
+    ///
+    /// The timestamp of the latest block is read every
+    /// [`BLOCK_POLL_INTERVAL`](crate::trigger::BLOCK_POLL_INTERVAL), so the
+    /// message is sent right away if the timestamp has already passed. The
+    /// message is dropped with a warning if the block cannot be read, e.g.,
+    /// because the [`Environment`](arbiter_core::environment::Environment) of
+    /// the client was stopped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::MessagerError`] if the messager has no
+    /// id or the message does not match a [`schema`](crate::schema) of the
+    /// world, or an error if the payload cannot be serialized.
+    pub fn send_at<M: Middleware + 'static, T: Payload>(
+        &self,
+        client: Arc<M>,
+        timestamp: u64,
+        to: To,
+        payload: &T,
+    ) -> Result<ScheduledMessage, ArbiterEngineError> {
+        let message = self.schedule(to, payload)?;
+        let messager = self.sender();
+        let handle = spawn(async move {
+            let mut interval = tokio::time::interval(BLOCK_POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let now = match client.get_block(BlockNumber::Latest).await {
+                    Ok(Some(block)) => block.timestamp.as_u64(),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!(
+                            "Dropping a message scheduled by `{}` as the block could not be read: {}",
+                            message.from, e
+                        );
+                        return;
+                    }
+                };
+                if now >= timestamp {
+                    send_scheduled(&messager, message);
+                    return;
+                }
+            }
+        });
+        Ok(ScheduledMessage {
+            handle: handle.abort_handle(),
+        })
+    }
+
+    /// Returns the message of the `payload` to the recipient `to` that is
+    /// sent later.
+    fn schedule<T: Payload>(&self, to: To, payload: &T) -> Result<Message, ArbiterEngineError> {
+        let Some(id) = &self.id else {
+            return Err(no_id());
+        };
+        let message = Message {
+            from: id.clone(),
+            to,
+            data: serde_json::to_string(payload)?,
+            tag: Some(T::TAG.to_owned()),
+            correlation_id: None,
+            priority: Priority::Normal,
+            trace_id: causality::current(),
+        };
+        self.schemas.validate(&message)?;
+        Ok(message)
+    }
+
+    /// Returns a messager that only sends messages, which, unlike a clone,
+    /// does not keep the messages sent while it waits to send.
+    pub(crate) fn sender(&self) -> Self {
+        Self {
+            broadcast_receiver: None,
+            scope: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// A message that is sent later, returned by [`Messager::send_after`] and
+/// [`Messager::send_at`].
+///
+/// Dropping it does not cancel the message.
+#[derive(Debug)]
+pub struct ScheduledMessage {
+    handle: AbortHandle,
+}
+
+impl ScheduledMessage {
+    /// Cancels the message if it has not been sent yet.
+    pub fn cancel(&self) {
+        self.handle.abort();
+    }
+
+    /// Returns whether the message was sent, was dropped, or was cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+/// Sends a scheduled `message` through `messager`, logging rather than
+/// returning the error if there is no one left to receive it.
+fn send_scheduled(messager: &Messager, message: Message) {
+    trace!("Sending a message scheduled by `{}`.", message.from);
+    if let Err(e) = messager.deliver(message) {
+        debug!("Could not send a scheduled message: {}", e);
+    }
+}
+
+fn no_id() -> ArbiterEngineError {
+    ArbiterEngineError::MessagerError(
+        "Messager has no ID! You must have an ID to send messages!".to_owned(),
+    )
+}
+
+/// The channels of the lanes of the messages of a world, each of which keeps
+/// the last `capacity` messages sent in it.
+#[derive(Clone, Debug)]
+pub(crate) struct Lanes {
+    normal: Sender<Envelope>,
+    control: Sender<Envelope>,
+    capacity: usize,
+
+    /// Tells the channels of the world apart from those of the scopes of its
+    /// agents in the [`Scheduler`].
+    pub(crate) id: u64,
+}
+
+/// A message in one of the [`Lanes`] along with when it was sent, so that its
+/// latency is counted when it is received.
+#[derive(Clone, Debug)]
+pub(crate) struct Envelope {
+    pub(crate) message: Message,
+    pub(crate) sent: Instant,
+
+    /// Whether the message was relayed from another world.
+    pub(crate) relayed: bool,
+
+    /// The number of the message among the messages sent in the world, see
+    /// the [`scheduler`](crate::scheduler) module.
+    pub(crate) sequence: u64,
+}
+
+/// Receives the messages of both [`Lanes`], those of the control lane first.
+#[derive(Debug)]
+pub(crate) struct LaneReceiver {
+    normal: Receiver<Envelope>,
+    control: Receiver<Envelope>,
+}
+
+impl Lanes {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            normal: channel(capacity).0,
+            control: channel(capacity).0,
+            capacity,
+            id: scheduler::channel_id(),
+        }
+    }
+
+    /// Returns a receiver of the messages sent from now on.
+    pub(crate) fn subscribe(&self) -> LaneReceiver {
+        LaneReceiver {
+            normal: self.normal.subscribe(),
+            control: self.control.subscribe(),
+        }
+    }
+
+    /// Sends the `message`, which was `relayed` from another world or not and
+    /// is numbered `sequence`, in the lane of its priority.
+    pub(crate) fn send(
+        &self,
+        message: Message,
+        relayed: bool,
+        sequence: u64,
+    ) -> Result<usize, Box<SendError<Message>>> {
+        let lane = match message.priority {
+            Priority::Normal => &self.normal,
+            Priority::Control => &self.control,
+        };
+        lane.send(Envelope {
+            message,
+            sent: Instant::now(),
+            relayed,
+            sequence,
+        })
+        .map_err(|SendError(envelope)| Box::new(SendError(envelope.message)))
+    }
+}
+
+impl LaneReceiver {
+    /// Receives the next message, which is the oldest message of the control
+    /// lane if one is waiting.
+    pub(crate) async fn recv(&mut self) -> Result<Envelope, RecvError> {
+        tokio::select! {
+            biased;
+            message = self.control.recv() => message,
+            message = self.normal.recv() => message,
+        }
+    }
+
+    /// Returns the number of messages of both lanes that are waiting.
+    pub(crate) fn len(&self) -> usize {
+        self.control.len() + self.normal.len()
+    }
+
+    /// Receives the next message if one is waiting, see
+    /// [`LaneReceiver::recv`].
+    pub(crate) fn try_recv(&mut self) -> Result<Envelope, TryRecvError> {
+        match self.control.try_recv() {
+            Err(TryRecvError::Empty) => self.normal.try_recv(),
+            received => received,
+        }
+    }
+}
+
+/// Logs and counts the `count` messages missed by the messager of the agent
+/// with the id `id` as it fell behind the channel.
+fn missed(id: &Option<String>, count: u64) {
+    warn!(
+        "Agent {:?} fell behind its messages and missed {} of them.",
+        id, count
+    );
+    Counters::lagged_current(count);
 }
@@ -0,0 +1,531 @@
+//! The metrics module counts what each behavior of a [`World`] does while it
+//! runs, so that behaviors that are starved of events or that are overactive
+//! stand out.
+//!
+//! Every [`Engine`](crate::machine::Engine) counts the events its behavior
+//! received from its stream, the events it processed, the transactions it
+//! sent, and the errors it failed with. Behaviors that fall behind their
+//! events stand out by the events they missed, i.e., the messages that
+//! lagged out of their [`Messager`]'s channel before they received them and
+//! the events dropped from their buffer by their
+//! [`Backpressure`](crate::machine::Backpressure), and by how many messages were
+//! waiting for them in the channel of their messager. Runaway producers show
+//! up as the queue depth of the behaviors they flood nearing the message
+//! capacity of the world, and as the times a behavior held up its stream as
+//! its buffer was full. The transactions are counted by a
+//! [`MiddlewareHooks`] the world adds to the client of every agent, which
+//! attributes each transaction to the behavior whose task sends it. A
+//! transaction sent from a task the behavior spawned itself is not
+//! attributed to any behavior. The messages sent to each group of agents are
+//! counted along with the deliveries they made to the agents of the group.
+//!
+//! The [`Messager`]s of the world count the messages sent through them, the
+//! deliveries of the messages to the messagers that received them, and how
+//! long each message waited between being sent and being received, in a
+//! [`LatencyHistogram`]. Along with the time the world ran for, they make up
+//! the throughput of the messages of the world, which helps size the message
+//! capacity of a world, and the latency, which grows as behaviors fall behind
+//! their messages. The behaviors that flood the others are found by the
+//! messages each of them sent.
+//!
+//! The counts are read with [`World::metrics`] and are written along with the
+//! other results of a run to the path set with
+//! [`World::with_metrics_output`], or with a top level `metrics = "path"` in
+//! the configuration of [`World::from_config`].
+//!
+//! [`World`]: crate::world::World
+//! [`World::metrics`]: crate::world::World::metrics
+//! [`World::with_metrics_output`]: crate::world::World::with_metrics_output
+//! [`World::from_config`]: crate::world::World::from_config
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    future::Future,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use arbiter_core::middleware::hooks::MiddlewareHooks;
+use ethers::types::transaction::eip2718::TypedTransaction;
+
+use super::*;
+use crate::machine::HaltReason;
+
+tokio::task_local! {
+    /// The counters of the behavior whose task is running.
+    static CURRENT: Arc<Counters>;
+}
+
+/// The counters of every behavior of a [`World`](crate::world::World) keyed by the agent and type
+/// of the behavior, e.g., `agent/Behavior`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Metrics {
+    counters: Arc<Mutex<BTreeMap<String, Arc<Counters>>>>,
+
+    /// The counters of the messages sent to each group, keyed by the group.
+    groups: Arc<Mutex<BTreeMap<String, GroupMetrics>>>,
+
+    /// The counters of the messages sent through the messagers of the world.
+    messager: Arc<Mutex<MessagerMetrics>>,
+
+    /// When the world started running, while it is running.
+    started: Arc<Mutex<Option<Instant>>>,
+}
+
+/// The counters of a single behavior.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    events_received: AtomicU64,
+    events_processed: AtomicU64,
+    events_lagged: AtomicU64,
+    events_dropped: AtomicU64,
+    events_blocked: AtomicU64,
+    max_queue_depth: AtomicU64,
+    messages_sent: AtomicU64,
+    transactions_sent: AtomicU64,
+    errors: AtomicU64,
+    halts: Mutex<Vec<HaltReason>>,
+}
+
+/// What a single behavior did during a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BehaviorMetrics {
+    /// The number of events the behavior received from its event stream.
+    pub events_received: u64,
+
+    /// The number of events the behavior processed successfully.
+    pub events_processed: u64,
+
+    /// The number of messages the behavior missed as they were sent faster
+    /// than it received them and were overwritten in the channel of its
+    /// messager.
+    pub events_lagged: u64,
+
+    /// The number of events dropped from the buffer of the behavior by
+    /// [`Backpressure::DropOldest`](crate::machine::Backpressure::DropOldest).
+    pub events_dropped: u64,
+
+    /// The number of times the behavior stopped pulling events from its
+    /// stream as the buffer of its
+    /// [`Backpressure::Block`](crate::machine::Backpressure::Block) was full.
+    #[serde(default)]
+    pub events_blocked: u64,
+
+    /// The most messages that were waiting in the channel of the messager of
+    /// the behavior when it received one, which the behavior starts missing
+    /// messages beyond once it reaches the message capacity of the world, see
+    /// [`Messager::stream`].
+    #[serde(default)]
+    pub max_queue_depth: u64,
+
+    /// The number of messages the behavior sent.
+    #[serde(default)]
+    pub messages_sent: u64,
+
+    /// The number of transactions the behavior sent.
+    pub transactions_sent: u64,
+
+    /// The number of times the behavior failed to start up or to process an
+    /// event, retries included.
+    pub errors: u64,
+
+    /// Why the behavior halted, once for each behavior of the type of the
+    /// agent that halted, or nothing while it is running.
+    pub halts: Vec<HaltReason>,
+}
+
+/// The messages sent to a group of agents during a run, see
+/// [`To::Group`](crate::messager::To::Group).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupMetrics {
+    /// The number of messages sent to the group.
+    pub messages_sent: u64,
+
+    /// The number of deliveries of the messages, i.e., the number of agents
+    /// in the group when each message was sent, summed over the messages.
+    pub deliveries: u64,
+}
+
+/// The messages sent through the messagers of a
+/// [`World`](crate::world::World) during a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessagerMetrics {
+    /// The number of messages sent.
+    pub messages_sent: u64,
+
+    /// The number of deliveries of the messages, i.e., the number of times a
+    /// messager received a message sent to it, to its agent, or to a group of
+    /// its agent.
+    pub messages_delivered: u64,
+
+    /// How long the world ran for, in milliseconds, or has been running for.
+    pub elapsed_ms: u64,
+
+    /// How long the deliveries waited between their message being sent and
+    /// being received.
+    pub latency: LatencyHistogram,
+}
+
+/// The upper bounds, in microseconds, of the buckets of a
+/// [`LatencyHistogram`] but its last, which counts the latencies of a second
+/// or more.
+pub const LATENCY_BUCKETS_US: [u64; 6] = [10, 100, 1_000, 10_000, 100_000, 1_000_000];
+
+/// The number of latencies in each bucket of [`LATENCY_BUCKETS_US`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    /// The number of latencies below each bound of [`LATENCY_BUCKETS_US`]
+    /// and not below the previous one, followed by the number of latencies of
+    /// a second or more.
+    pub buckets: Vec<u64>,
+
+    /// The number of latencies.
+    pub count: u64,
+
+    /// The sum of the latencies, in microseconds.
+    pub total_us: u64,
+
+    /// The longest latency, in microseconds.
+    pub max_us: u64,
+}
+
+/// What every behavior of a [`World`](crate::world::World) did during a run
+/// and why it halted, which makes up the report of the run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorldMetrics {
+    /// The metrics of each behavior keyed by the agent and type of the
+    /// behavior, e.g., `agent/Behavior`. The behaviors of the same type of an
+    /// agent are counted together.
+    pub behaviors: BTreeMap<String, BehaviorMetrics>,
+
+    /// The metrics of the messages sent to each group that was messaged,
+    /// keyed by the group.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, GroupMetrics>,
+
+    /// The metrics of the messages sent through the messagers of the world.
+    #[serde(default)]
+    pub messager: MessagerMetrics,
+}
+
+impl Metrics {
+    /// Returns the counters of the behavior `section`, e.g., `agent/Behavior`.
+    pub(crate) fn counters(&self, section: &str) -> Arc<Counters> {
+        self.counters
+            .lock()
+            .unwrap()
+            .entry(section.to_owned())
+            .or_default()
+            .clone()
+    }
+
+    /// Counts a message sent to the `group`, which is delivered to its
+    /// `members` agents.
+    pub(crate) fn group_sent(&self, group: &str, members: usize) {
+        let mut groups = self.groups.lock().unwrap();
+        let metrics = groups.entry(group.to_owned()).or_default();
+        metrics.messages_sent += 1;
+        metrics.deliveries += members as u64;
+    }
+
+    /// Counts a message sent through a messager of the world, towards the
+    /// behavior whose task sends it, if any.
+    pub(crate) fn sent(&self) {
+        self.messager.lock().unwrap().messages_sent += 1;
+        let _ = CURRENT.try_with(|counters| {
+            counters.messages_sent.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    /// Counts a delivery of a message that was sent `latency` ago.
+    pub(crate) fn delivered(&self, latency: Duration) {
+        let mut messager = self.messager.lock().unwrap();
+        messager.messages_delivered += 1;
+        messager.latency.record(latency);
+    }
+
+    /// Starts counting the time the world runs for.
+    pub(crate) fn start(&self) {
+        *self.started.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Stops counting the time the world runs for.
+    pub(crate) fn finish(&self) {
+        if let Some(started) = self.started.lock().unwrap().take() {
+            self.messager.lock().unwrap().elapsed_ms += started.elapsed().as_millis() as u64;
+        }
+    }
+
+    /// Returns what has been counted so far.
+    pub(crate) fn snapshot(&self) -> WorldMetrics {
+        WorldMetrics {
+            behaviors: self
+                .counters
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(section, counters)| (section.clone(), counters.snapshot()))
+                .collect(),
+            groups: self.groups.lock().unwrap().clone(),
+            messager: {
+                let mut messager = self.messager.lock().unwrap().clone();
+                if let Some(started) = *self.started.lock().unwrap() {
+                    messager.elapsed_ms += started.elapsed().as_millis() as u64;
+                }
+                messager
+            },
+        }
+    }
+}
+
+impl Counters {
+    /// Counts an event received from the event stream.
+    pub(crate) fn received(&self) {
+        self.events_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts an event that was processed successfully.
+    pub(crate) fn processed(&self) {
+        self.events_processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the `count` messages the behavior missed in the channel of its
+    /// messager.
+    pub(crate) fn lagged(&self, count: u64) {
+        self.events_lagged.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Counts an event dropped from the buffer of the behavior.
+    pub(crate) fn dropped(&self) {
+        self.events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a time the buffer of the behavior was full and held up its
+    /// stream.
+    pub(crate) fn blocked(&self) {
+        self.events_blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts the `depth` of the channel of the messager of the behavior
+    /// towards its maximum.
+    pub(crate) fn queued(&self, depth: usize) {
+        self.max_queue_depth
+            .fetch_max(depth as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a failure to start up or to process an event.
+    pub(crate) fn failed(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records why the behavior halted.
+    pub(crate) fn halted(&self, reason: HaltReason) {
+        self.halts.lock().unwrap().push(reason);
+    }
+
+    /// Runs `future` with the transactions it sends attributed to these
+    /// counters.
+    pub(crate) async fn scope<F: Future>(self: Arc<Self>, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /// Counts the `count` messages missed by the behavior whose task is
+    /// running, if any.
+    pub(crate) fn lagged_current(count: u64) {
+        let _ = CURRENT.try_with(|counters| counters.lagged(count));
+    }
+
+    /// Counts the `depth` of the channel of the messager of the behavior
+    /// whose task is running, if any.
+    pub(crate) fn queued_current(depth: usize) {
+        let _ = CURRENT.try_with(|counters| counters.queued(depth));
+    }
+
+    fn snapshot(&self) -> BehaviorMetrics {
+        BehaviorMetrics {
+            events_received: self.events_received.load(Ordering::Relaxed),
+            events_processed: self.events_processed.load(Ordering::Relaxed),
+            events_lagged: self.events_lagged.load(Ordering::Relaxed),
+            events_dropped: self.events_dropped.load(Ordering::Relaxed),
+            events_blocked: self.events_blocked.load(Ordering::Relaxed),
+            max_queue_depth: self.max_queue_depth.load(Ordering::Relaxed),
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            transactions_sent: self.transactions_sent.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            halts: self.halts.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// Counts the transactions of a client towards the behavior sending them.
+#[derive(Debug)]
+pub(crate) struct TransactionCounter;
+
+#[async_trait::async_trait]
+impl MiddlewareHooks for TransactionCounter {
+    async fn before_send(&self, _tx: &mut TypedTransaction) {
+        let _ = CURRENT.try_with(|counters| {
+            counters.transactions_sent.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+}
+
+impl MessagerMetrics {
+    /// Returns the number of messages sent per second of the run, or zero if
+    /// the world has not run.
+    pub fn messages_per_second(&self) -> f64 {
+        if self.elapsed_ms == 0 {
+            return 0.0;
+        }
+        self.messages_sent as f64 * 1000.0 / self.elapsed_ms as f64
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS_US.len() + 1],
+            count: 0,
+            total_us: 0,
+            max_us: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Counts a `latency` in its bucket.
+    pub(crate) fn record(&mut self, latency: Duration) {
+        let latency = latency.as_micros() as u64;
+        let bucket = LATENCY_BUCKETS_US
+            .iter()
+            .position(|bound| latency < *bound)
+            .unwrap_or(LATENCY_BUCKETS_US.len());
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total_us += latency;
+        self.max_us = self.max_us.max(latency);
+    }
+
+    /// Returns the mean latency, if any latency was counted.
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_micros(self.total_us / self.count))
+    }
+
+    /// Returns the longest latency, if any latency was counted.
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_micros(self.max_us))
+    }
+
+    /// Returns an upper bound of the `quantile`, e.g., `0.99`, of the
+    /// latencies, which is the bound of the bucket the quantile falls in, or
+    /// the longest latency if it is lower. Returns `None` if no latency was
+    /// counted.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let bound = LATENCY_BUCKETS_US.get(bucket).copied().unwrap_or(u64::MAX);
+                return Some(Duration::from_micros(bound.min(self.max_us)));
+            }
+        }
+        self.max()
+    }
+}
+
+impl WorldMetrics {
+    /// Writes the metrics to `path` as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ArbiterEngineError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for WorldMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<48} {:>10} {:>10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>12} {:>8}  halted",
+            "behavior",
+            "received",
+            "processed",
+            "lagged",
+            "dropped",
+            "blocked",
+            "queued",
+            "sent",
+            "transactions",
+            "errors"
+        )?;
+        for (behavior, metrics) in &self.behaviors {
+            writeln!(
+                f,
+                "{:<48} {:>10} {:>10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>12} {:>8}  {}",
+                behavior,
+                metrics.events_received,
+                metrics.events_processed,
+                metrics.events_lagged,
+                metrics.events_dropped,
+                metrics.events_blocked,
+                metrics.max_queue_depth,
+                metrics.messages_sent,
+                metrics.transactions_sent,
+                metrics.errors,
+                metrics
+                    .halts
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        if !self.groups.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{:<48} {:>10} {:>10}", "group", "messages", "deliveries")?;
+            for (group, metrics) in &self.groups {
+                writeln!(
+                    f,
+                    "{:<48} {:>10} {:>10}",
+                    group, metrics.messages_sent, metrics.deliveries
+                )?;
+            }
+        }
+        let messager = &self.messager;
+        writeln!(f)?;
+        writeln!(
+            f,
+            "messages: {} sent, {} delivered, {:.1}/s",
+            messager.messages_sent,
+            messager.messages_delivered,
+            messager.messages_per_second()
+        )?;
+        if let (Some(mean), Some(p99), Some(max)) = (
+            messager.latency.mean(),
+            messager.latency.quantile(0.99),
+            messager.latency.max(),
+        ) {
+            writeln!(
+                f,
+                "latency: mean {:?}, p99 <= {:?}, max {:?}",
+                mean, p99, max
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! The random module gives every agent of a [`World`](crate::world::World) its
+//! own seeded random number generator, so that stochastic behaviors, e.g., with
+//! random trade sizes or arrival times, are reproducible.
+//!
+//! The generator of an agent is a [`StdRng`] seeded from the seed of its world
+//! and the id of the agent. Agents therefore draw independent numbers from each
+//! other, and adding an agent to a world does not change what the other agents
+//! draw. It is shared by the behaviors of the agent and reached through
+//! [`Messager::rng`]:
+//! ```ignore
+//! let size = messager.rng().gen_range(1..100);
+//! ```
+//! The numbers the behaviors of a single agent draw depend on the order in
+//! which they draw them, which is only reproducible if they draw them in
+//! a [deterministic order](crate::scheduler).
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex, MutexGuard, OnceLock,
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use super::*;
+
+/// The seed of a world that sets none with
+/// [`World::with_seed`](crate::world::World::with_seed).
+pub const DEFAULT_SEED: u64 = 0;
+
+/// The seed of a world along with the generator of the agent using a
+/// [`Messager`], which is created the first time it is used.
+#[derive(Clone, Debug)]
+pub(crate) struct Randomness {
+    seed: Arc<AtomicU64>,
+    rng: Arc<OnceLock<Mutex<StdRng>>>,
+}
+
+impl Default for Randomness {
+    fn default() -> Self {
+        Self {
+            seed: Arc::new(AtomicU64::new(DEFAULT_SEED)),
+            rng: Arc::default(),
+        }
+    }
+}
+
+impl Randomness {
+    /// Sets the seed of the world, which applies to every generator that has
+    /// not been used yet.
+    pub(crate) fn set_seed(&self, seed: u64) {
+        self.seed.store(seed, Ordering::Relaxed);
+    }
+
+    /// Returns the seed of the world.
+    pub(crate) fn seed(&self) -> u64 {
+        self.seed.load(Ordering::Relaxed)
+    }
+
+    /// Returns the randomness of an agent of the same world, with a generator
+    /// of its own.
+    pub(crate) fn for_agent(&self) -> Self {
+        Self {
+            seed: self.seed.clone(),
+            rng: Arc::default(),
+        }
+    }
+
+    /// Returns the generator of the agent with the id `agent_id`.
+    pub(crate) fn rng(&self, agent_id: &str) -> MutexGuard<'_, StdRng> {
+        self.rng
+            .get_or_init(|| Mutex::new(StdRng::seed_from_u64(agent_seed(self.seed(), agent_id))))
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Returns the seed of the generator of the agent with the id `agent_id` in a
+/// world with the seed `world_seed`.
+///
+/// The seed is the 64-bit FNV-1a hash of both, which unlike the hashers of the
+/// standard library is the same on every platform and version of Rust.
+pub fn agent_seed(world_seed: u64, agent_id: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    world_seed
+        .to_le_bytes()
+        .iter()
+        .chain(agent_id.as_bytes())
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+        })
+}
@@ -0,0 +1,203 @@
+//! The relay module connects the messagers of [`World`]s that run in the same
+//! process, e.g., the worlds of a [`Universe`] that each simulate a chain of a
+//! multi-chain setup, so that the agents of one world coordinate with those of
+//! another, e.g., an arbitrageur that watches the prices of both chains.
+//!
+//! A [`Relay`] forwards the messages of the topics it is given, i.e., the
+//! messages sent with the tag of a [`Payload`], from one world to another,
+//! after a latency that models the time a message takes to cross between the
+//! chains, plus a random jitter:
+//! ```ignore
+//! let mainnet = World::new("mainnet");
+//! let rollup = World::new("rollup");
+//! let relay = Relay::new(&mainnet, &rollup)
+//!     .with_topic::<Price>()
+//!     .with_latency(Duration::from_millis(200))
+//!     .with_jitter(Duration::from_millis(50))
+//!     .start();
+//! ```
+//! A relayed message keeps its sender, recipient, and every other field, so it
+//! reaches the agents of the other world it is sent to as if it had been sent
+//! there, and a reply to its sender reaches it back if a relay of the reply's
+//! topic runs the other way. The jitter is drawn from a generator seeded from
+//! the seed of the world the messages are relayed from, so the delays are
+//! reproducible, although messages whose delays overlap may arrive out of
+//! order.
+//!
+//! A relay only goes one way. Two relays of the same topic in opposite
+//! directions relay it both ways without echoing it back, as a relayed
+//! message is never relayed again. A chain of worlds is therefore relayed
+//! with a relay between each pair of them. A relayed message is sent in the
+//! other world like any other message, so it is checked against the
+//! [`schema`](crate::schema)s, logged, and counted in the metrics of that
+//! world. As a world only knows its own agents, a message sent to an agent of
+//! the other world is kept as a [dead letter](crate::dead_letters) of the
+//! world it is sent in.
+//!
+//! [`World`]: crate::world::World
+//! [`Universe`]: crate::universe::Universe
+
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{
+    sync::broadcast::error::RecvError,
+    task::{JoinHandle, JoinSet},
+};
+
+use super::*;
+use crate::{
+    messager::{Envelope, Message, Payload},
+    random::agent_seed,
+    world::World,
+};
+
+/// Forwards the messages of some topics from the messager of a world to the
+/// messager of another, see the [module documentation](self).
+#[derive(Debug)]
+pub struct Relay {
+    /// The id of the relay, which is `from->to` by the ids of the worlds.
+    id: String,
+
+    from: Messager,
+    to: Messager,
+
+    /// The tags of the messages that are relayed.
+    topics: HashSet<String>,
+
+    latency: Duration,
+    jitter: Duration,
+}
+
+/// A [`Relay`] that is running, which stops once it is stopped or dropped.
+#[derive(Debug)]
+pub struct RelayHandle {
+    /// The number of messages relayed so far.
+    relayed: Arc<AtomicU64>,
+
+    /// The task that relays the messages.
+    handle: JoinHandle<()>,
+}
+
+impl Relay {
+    /// Creates a relay of no topics yet from the world `from` to the world
+    /// `to`, without latency.
+    pub fn new(from: &World, to: &World) -> Self {
+        Self {
+            id: format!("{}->{}", from.id, to.id),
+            from: from.messager.sender(),
+            to: to.messager.sender(),
+            topics: HashSet::new(),
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+        }
+    }
+
+    /// Relays the messages of the payload `T`, i.e., those sent with its tag.
+    pub fn with_topic<T: Payload>(self) -> Self {
+        self.with_tag(T::TAG)
+    }
+
+    /// Relays the messages sent with the `tag`.
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.topics.insert(tag.into());
+        self
+    }
+
+    /// Delays every relayed message by `latency`.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Delays every relayed message by up to `jitter` on top of its latency,
+    /// drawn uniformly for each message.
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Starts relaying the messages sent from now on, on the Tokio runtime it
+    /// is called on.
+    pub fn start(self) -> RelayHandle {
+        let mut receiver = self.from.broadcast_sender.subscribe();
+        let mut rng = StdRng::seed_from_u64(agent_seed(self.from.randomness.seed(), &self.id));
+        let relayed = Arc::new(AtomicU64::new(0));
+        let counter = relayed.clone();
+        let handle = tokio::spawn(async move {
+            // The delayed messages are aborted along with the relay.
+            let mut delayed = JoinSet::new();
+            loop {
+                let envelope = tokio::select! {
+                    envelope = receiver.recv() => envelope,
+                    Some(_) = delayed.join_next() => continue,
+                };
+                let message = match envelope {
+                    Ok(Envelope {
+                        message,
+                        relayed: false,
+                        ..
+                    }) => message,
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(count)) => {
+                        warn!("The relay {} missed {} messages.", self.id, count);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if !message
+                    .tag
+                    .as_ref()
+                    .is_some_and(|tag| self.topics.contains(tag))
+                {
+                    continue;
+                }
+                counter.fetch_add(1, Ordering::Relaxed);
+                let delay = self.latency + self.jitter.mul_f64(rng.gen::<f64>());
+                if delay.is_zero() {
+                    relay(&self.id, &self.to, message);
+                    continue;
+                }
+                let id = self.id.clone();
+                let to = self.to.sender();
+                delayed.spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    relay(&id, &to, message);
+                });
+            }
+        });
+        RelayHandle { relayed, handle }
+    }
+}
+
+/// Sends a `message` relayed by the relay `id` through `to`, logging rather
+/// than returning the error if it is rejected.
+fn relay(id: &str, to: &Messager, message: Message) {
+    trace!("Relaying a message from `{}` through {}.", message.from, id);
+    if let Err(e) = to.relay(message) {
+        debug!("The relay {} could not relay a message: {}", id, e);
+    }
+}
+
+impl RelayHandle {
+    /// Returns the number of messages relayed so far, including those that
+    /// are still delayed.
+    pub fn relayed(&self) -> u64 {
+        self.relayed.load(Ordering::Relaxed)
+    }
+
+    /// Stops relaying messages, dropping those that are still delayed.
+    pub fn stop(self) {
+        self.handle.abort();
+    }
+}
+
+impl Drop for RelayHandle {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
@@ -0,0 +1,209 @@
+//! The reload module applies changes to the configuration of a [`World`]
+//! built with [`World::from_config`] to its behaviors while it runs, so that a
+//! long simulation can be tuned interactively, e.g., its trade sizes,
+//! frequencies, or thresholds.
+//!
+//! A [`Reloader`], returned by [`World::reloader`] and [`Messager::reloader`],
+//! reads the configuration again with [`Reloader::reload`], which a world
+//! also does by itself whenever the file changes with
+//! [`World::with_config_watching`]:
+//! ```ignore
+//! let mut world = World::from_config::<Behaviors>("config.toml")?
+//!     .with_config_watching(Duration::from_secs(1));
+//! world.run().await?;
+//! ```
+//!
+//! Only the numeric parameters of a behavior can be changed while it runs.
+//! A behavior whose configuration changed in any other way, e.g., whose type
+//! or name of a parameter changed, is not reloaded and the change is logged.
+//! Each behavior is identified by its agent and its position among the
+//! behaviors of the agent, so behaviors can neither be added nor removed.
+//!
+//! A behavior that was reloaded is handed the behavior deserialized from its
+//! new configuration with [`Behavior::reload`] once it is done processing its
+//! current event, from which it takes the parameters it allows to change.
+//! Behaviors ignore their changes by default.
+//!
+//! [`World`]: crate::world::World
+//! [`World::from_config`]: crate::world::World::from_config
+//! [`World::reloader`]: crate::world::World::reloader
+//! [`World::with_config_watching`]: crate::world::World::with_config_watching
+//! [`Behavior::reload`]: crate::machine::Behavior::reload
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use tokio::sync::watch;
+
+use super::*;
+use crate::world::{agent_behaviors, expand_config};
+
+/// A handle to reload the parameters of the behaviors of a
+/// [`World`](crate::world::World) built from a configuration while it runs,
+/// returned by [`World::reloader`](crate::world::World::reloader) and
+/// [`Messager::reloader`].
+#[derive(Clone, Debug, Default)]
+pub struct Reloader {
+    inner: Arc<ReloaderInner>,
+}
+
+#[derive(Debug, Default)]
+struct ReloaderInner {
+    /// The configuration the world was built from.
+    path: Mutex<Option<PathBuf>>,
+
+    /// The configuration of each behavior, keyed by its slot, e.g.,
+    /// `agent/0`.
+    behaviors: Mutex<BTreeMap<String, toml::Value>>,
+
+    /// The parameters each behavior is reloaded with, keyed by its slot.
+    reloads: Mutex<HashMap<String, watch::Sender<Option<serde_json::Value>>>>,
+}
+
+impl Reloader {
+    /// Sets the configuration at `path` the behaviors are reloaded from,
+    /// along with the `behaviors` of each agent it was read with.
+    pub(crate) fn watch(&self, path: &Path, behaviors: BTreeMap<String, Vec<toml::Value>>) {
+        *self.inner.path.lock().unwrap() = Some(path.to_owned());
+        *self.inner.behaviors.lock().unwrap() = behaviors
+            .into_iter()
+            .flat_map(|(agent, behaviors)| {
+                behaviors
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(position, behavior)| (format!("{}/{}", agent, position), behavior))
+            })
+            .collect();
+    }
+
+    /// Returns when the configuration was last modified, if the world was
+    /// built from one.
+    pub(crate) fn modified(&self) -> Option<SystemTime> {
+        let path = self.inner.path.lock().unwrap().clone()?;
+        std::fs::metadata(path)
+            .and_then(|file| file.modified())
+            .ok()
+    }
+
+    /// Returns a receiver of the parameters the behavior in `slot` is
+    /// reloaded with.
+    pub(crate) fn subscribe(&self, slot: &str) -> watch::Receiver<Option<serde_json::Value>> {
+        self.inner
+            .reloads
+            .lock()
+            .unwrap()
+            .entry(slot.to_owned())
+            .or_insert_with(|| watch::Sender::new(None))
+            .subscribe()
+    }
+
+    /// Reads the configuration of the world again and reloads the behaviors
+    /// whose numeric parameters changed. Returns the slots of the behaviors
+    /// that were reloaded, e.g., `agent/0`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the world was not built from a configuration or if
+    /// the configuration cannot be read.
+    pub fn reload(&self) -> Result<Vec<String>, ArbiterEngineError> {
+        let path = self.inner.path.lock().unwrap().clone().ok_or_else(|| {
+            ArbiterEngineError::WorldError(
+                "Only a world built from a configuration can be reloaded".to_owned(),
+            )
+        })?;
+        let contents = std::fs::read_to_string(&path)?;
+        let config = agent_behaviors(&expand_config(&contents)?);
+        let mut behaviors = self.inner.behaviors.lock().unwrap();
+        let mut reloaded = vec![];
+        for (agent, configured) in config {
+            for (position, behavior) in configured.into_iter().enumerate() {
+                let slot = format!("{}/{}", agent, position);
+                let Some(current) = behaviors.get(&slot) else {
+                    warn!("Cannot add the behavior `{}` to a running world.", slot);
+                    continue;
+                };
+                // A behavior is configured as a table with its name as its
+                // only key, e.g., `{ Trader = { size = 10 } }`.
+                let (Some((name, current)), Some((reloaded_name, parameters))) = (
+                    current.as_table().and_then(|table| table.iter().next()),
+                    behavior.as_table().and_then(|table| table.iter().next()),
+                ) else {
+                    continue;
+                };
+                if name != reloaded_name {
+                    warn!(
+                        "Cannot reload the behavior `{}` as `{}` since it is a `{}`.",
+                        slot, reloaded_name, name
+                    );
+                    continue;
+                }
+                match numeric_changes(current, parameters, "") {
+                    Ok(false) => continue,
+                    Ok(true) => {}
+                    Err(e) => {
+                        warn!("Cannot reload the behavior `{}` as {}.", slot, e);
+                        continue;
+                    }
+                }
+                let parameters = serde_json::to_value(parameters)?;
+                self.inner
+                    .reloads
+                    .lock()
+                    .unwrap()
+                    .entry(slot.clone())
+                    .or_insert_with(|| watch::Sender::new(None))
+                    .send_replace(Some(parameters));
+                behaviors.insert(slot.clone(), behavior);
+                reloaded.push(slot);
+            }
+        }
+        info!("Reloaded the behaviors {:?}.", reloaded);
+        Ok(reloaded)
+    }
+}
+
+/// Returns whether the numeric values of `current` changed in `reloaded`, or
+/// what changed at the parameter `name` otherwise.
+fn numeric_changes(
+    current: &toml::Value,
+    reloaded: &toml::Value,
+    name: &str,
+) -> Result<bool, String> {
+    use toml::Value::{Array, Float, Integer, Table};
+    match (current, reloaded) {
+        (Integer(_) | Float(_), Integer(_) | Float(_)) => Ok(current != reloaded),
+        (Table(current), Table(reloaded)) => {
+            if let Some(key) = current
+                .keys()
+                .chain(reloaded.keys())
+                .find(|key| !current.contains_key(*key) || !reloaded.contains_key(*key))
+            {
+                return Err(format!(
+                    "the parameter `{}{}` was added or removed",
+                    name, key
+                ));
+            }
+            let mut changed = false;
+            for (key, value) in current {
+                changed |= numeric_changes(value, &reloaded[key], &format!("{}{}.", name, key))?;
+            }
+            Ok(changed)
+        }
+        (Array(current), Array(reloaded)) if current.len() == reloaded.len() => {
+            let mut changed = false;
+            for (value, reloaded) in current.iter().zip(reloaded) {
+                changed |= numeric_changes(value, reloaded, name)?;
+            }
+            Ok(changed)
+        }
+        _ if current == reloaded => Ok(false),
+        _ => Err(format!(
+            "the parameter `{}` changed but is not numeric",
+            name.trim_end_matches('.')
+        )),
+    }
+}
@@ -0,0 +1,314 @@
+//! The remote module bridges the [`Messager`] of a [`World`] to agents that
+//! run outside of the Rust binary, e.g., a machine learning agent written in
+//! Python, so that they take part in the messages of the world alongside its
+//! native behaviors.
+//!
+//! A [`RemoteServer`] accepts WebSocket connections, each of which is a remote
+//! agent whose id is the path it connects to. It is started on the messager of
+//! the world before the world runs:
+//! ```ignore
+//! let mut world = World::from_config::<Behaviors>("config.toml")?;
+//! let server = RemoteServer::serve(&world.messager, "127.0.0.1:9000").await?;
+//! world.run().await?;
+//! ```
+//! A remote agent then connects to `ws://127.0.0.1:9000/ml_agent` to be the
+//! agent `ml_agent`:
+//! ```python
+//! async with websockets.connect("ws://127.0.0.1:9000/ml_agent") as agent:
+//!     await agent.send(json.dumps({"to": "All", "data": {"price": 1.0}, "tag": "Quote"}))
+//!     message = json.loads(await agent.recv())
+//! ```
+//! The upgrade is refused with `409 Conflict` if the world has a native agent
+//! with the id, or a remote agent with the id is still connected.
+//!
+//! Every message is a text frame holding a [`RemoteMessage`] as JSON. The
+//! remote agent receives the messages the messager of a native agent with
+//! its id would stream, i.e., those sent to all agents, to its id, or to its
+//! groups, and every message it sends is sent from its id. Its recipient is
+//! `"All"`, `{"Agent": "id"}`, or `{"Group": "group"}`, and its data is any
+//! JSON value, which the native behaviors decode like the data of any other
+//! message, e.g., with [`Message::decode`] if it carries the tag of a
+//! [`Payload`](crate::messager::Payload).
+//!
+//! Other transports, e.g., a gRPC stream or a pair of channels in a test, are
+//! plugged into a messager with [`bridge`], which forwards the messages
+//! between the messager and any [`Sink`] and [`Stream`] of frames.
+//!
+//! [`World`]: crate::world::World
+
+use std::{collections::HashMap, fmt::Display, net::SocketAddr, sync::Mutex};
+
+use arbiter_core::websocket;
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use hyper::{Body, Request, Response, StatusCode};
+use serde_json::Value;
+use tokio::{
+    net::{TcpListener, ToSocketAddrs},
+    task::JoinHandle,
+};
+use tokio_tungstenite::tungstenite;
+
+use super::*;
+use crate::{
+    causality,
+    messager::{Message, Priority, To},
+};
+
+/// A message as it is exchanged with a remote agent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RemoteMessage {
+    /// The sender of the message, which is set by the bridge on the messages
+    /// sent by the remote agent.
+    #[serde(default)]
+    pub from: String,
+
+    /// The recipient of the message.
+    pub to: To,
+
+    /// The data of the message.
+    pub data: Value,
+
+    /// The tag of the data, see [`Message::tag`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+
+    /// The correlation id of the message, see [`Message::correlation_id`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+
+    /// The lane of the message, see [`Message::priority`].
+    #[serde(default, skip_serializing_if = "Priority::is_normal")]
+    pub priority: Priority,
+
+    /// The trace id of the message, see [`Message::trace_id`], which the
+    /// messages a remote agent sends in response to a message copy to
+    /// continue its trace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+}
+
+impl From<Message> for RemoteMessage {
+    fn from(message: Message) -> Self {
+        Self {
+            data: serde_json::from_str(&message.data).unwrap_or(Value::String(message.data)),
+            from: message.from,
+            to: message.to,
+            tag: message.tag,
+            correlation_id: message.correlation_id,
+            priority: message.priority,
+            trace_id: message.trace_id,
+        }
+    }
+}
+
+/// Forwards the messages of the `messager` of a remote agent to the remote
+/// agent through `sink`, and sends the messages of the remote agent read from
+/// `stream` through the `messager`, until either side is closed. The remote
+/// agent counts as a running agent of the world meanwhile, so the messages
+/// sent to it are not kept as [dead letters](crate::dead_letters). Each frame is
+/// a [`RemoteMessage`] as JSON, and the frames that are not, or whose
+/// messages cannot be sent, e.g., as they do not match a
+/// [`schema`](crate::schema) of the world, are logged and skipped.
+///
+/// # Errors
+///
+/// Returns [`ArbiterEngineError::MessagerError`] if the `messager` has no id
+/// or is already streaming.
+pub async fn bridge<S, R>(
+    messager: Messager,
+    mut sink: S,
+    mut stream: R,
+) -> Result<(), ArbiterEngineError>
+where
+    S: Sink<String> + Unpin,
+    S::Error: Display,
+    R: Stream<Item = String> + Unpin,
+{
+    let Some(id) = messager.id.clone() else {
+        return Err(ArbiterEngineError::MessagerError(
+            "A remote agent needs a messager with an ID!".to_owned(),
+        ));
+    };
+    let mut messages = messager.clone().stream()?;
+    let _running = messager.dead_letters.connect(&id);
+    loop {
+        tokio::select! {
+            message = messages.next() => {
+                let Some(message) = message else {
+                    break;
+                };
+                let frame = serde_json::to_string(&RemoteMessage::from(message))?;
+                if let Err(e) = sink.send(frame).await {
+                    debug!("The remote agent `{}` disconnected: {}", id, e);
+                    break;
+                }
+            }
+            frame = stream.next() => {
+                let Some(frame) = frame else {
+                    debug!("The remote agent `{}` disconnected", id);
+                    break;
+                };
+                match serde_json::from_str::<RemoteMessage>(&frame) {
+                    Ok(message) => {
+                        let send = messager.send_tagged(
+                            message.to,
+                            message.data,
+                            message.tag,
+                            message.correlation_id,
+                            message.priority,
+                        );
+                        let sent = match message.trace_id {
+                            Some(trace_id) => causality::scope(trace_id, send).await,
+                            None => send.await,
+                        };
+                        if let Err(e) = sent {
+                            warn!("Skipping a message from `{}`: {}", id, e);
+                        }
+                    }
+                    Err(e) => warn!("Skipping an invalid message from `{}`: {}", id, e),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A WebSocket server that connects remote agents to the messager of a world,
+/// see the [module documentation](self).
+///
+/// The server runs on the Tokio runtime it was started on until it is stopped
+/// or dropped.
+#[derive(Debug)]
+pub struct RemoteServer {
+    /// The address the server listens on.
+    address: SocketAddr,
+
+    /// The task that accepts connections.
+    handle: JoinHandle<()>,
+}
+
+impl RemoteServer {
+    /// Starts accepting remote agents for the world of `messager` on
+    /// `address`, e.g., `"127.0.0.1:9000"`. Binding to port `0` picks a free
+    /// port, which can be read from [`RemoteServer::local_addr`].
+    pub async fn serve(
+        messager: &Messager,
+        address: impl ToSocketAddrs,
+    ) -> Result<Self, ArbiterEngineError> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        let messager = messager.clone();
+        let remotes = Remotes::default();
+        let handle = websocket::serve(listener, "remote agent", move |request| {
+            connect(messager.clone(), remotes.clone(), request)
+        });
+        info!("Accepting remote agents on {}", address);
+        Ok(Self { address, handle })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Stops accepting remote agents.
+    pub fn stop(self) {
+        self.handle.abort();
+    }
+}
+
+impl Drop for RemoteServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// The remote agents a [`RemoteServer`] has connected, by whether they are
+/// still connected.
+#[derive(Clone, Debug, Default)]
+struct Remotes(Arc<Mutex<HashMap<String, bool>>>);
+
+/// A connected remote agent, which is disconnected once it is dropped.
+struct Connected {
+    remotes: Remotes,
+    id: String,
+}
+
+impl Remotes {
+    /// Connects the remote agent `id` unless it is connected already or the
+    /// world of `messager` has a native agent with its id.
+    fn connect(&self, messager: &Messager, id: &str) -> Option<Connected> {
+        let mut remotes = self.0.lock().unwrap();
+        let taken = match remotes.get(id) {
+            Some(connected) => *connected,
+            None => messager.pauser.contains(id),
+        };
+        if taken {
+            return None;
+        }
+        remotes.insert(id.to_owned(), true);
+        Some(Connected {
+            remotes: self.clone(),
+            id: id.to_owned(),
+        })
+    }
+}
+
+impl Drop for Connected {
+    fn drop(&mut self) {
+        self.remotes
+            .0
+            .lock()
+            .unwrap()
+            .insert(self.id.clone(), false);
+    }
+}
+
+/// Upgrades the request of a remote agent, whose id is the path of the
+/// request, to a WebSocket connection bridged to its messager, unless the id
+/// belongs to an agent of the world or a remote agent that is connected.
+async fn connect(
+    messager: Messager,
+    remotes: Remotes,
+    mut request: Request<Body>,
+) -> Response<Body> {
+    let id = request.uri().path().trim_matches('/').to_owned();
+    if id.is_empty() || !websocket::is_upgrade(&request) {
+        return websocket::respond(
+            StatusCode::BAD_REQUEST,
+            "Connect over WebSocket to the path of the agent's id, e.g., `/alice`.",
+        );
+    }
+    let Some(connected) = remotes.connect(&messager, &id) else {
+        return websocket::respond(
+            StatusCode::CONFLICT,
+            format!("The agent `{}` is already in the world.", id),
+        );
+    };
+    // The agent counts as running from the response on, so that the messages
+    // sent to it before it is bridged are not kept as dead letters.
+    let running = messager.dead_letters.connect(&id);
+    websocket::upgrade(&mut request, move |websocket| async move {
+        let _connected = connected;
+        let _running = running;
+        let (sink, stream) = websocket.split();
+        let sink = sink.with(|frame: String| async { Ok::<_, tungstenite::Error>(frame.into()) });
+        let stream = stream
+            .take_while(|frame| {
+                let open = matches!(frame, Ok(frame) if !frame.is_close());
+                async move { open }
+            })
+            .filter_map(|frame| async move {
+                match frame {
+                    Ok(tungstenite::Message::Text(text)) => Some(text),
+                    Ok(tungstenite::Message::Binary(bytes)) => String::from_utf8(bytes).ok(),
+                    _ => None,
+                }
+            });
+        info!("The remote agent `{}` connected", id);
+        let messager = messager.for_agent(&id);
+        if let Err(e) = bridge(messager, Box::pin(sink), Box::pin(stream)).await {
+            warn!("The remote agent `{}` failed: {}", id, e);
+        }
+    })
+}
@@ -0,0 +1,267 @@
+//! The replay module records the events delivered to the behaviors of a
+//! [`World`] so that a run can be reproduced exactly, e.g., to debug an
+//! emergent behavior that only shows up rarely.
+//!
+//! With [`World::with_input_recording`], every event a behavior receives from
+//! its event stream is logged along with its position among the events
+//! received by all behaviors of the world, and the [`InputLog`] is written to
+//! a file once the world has run. A world built the same way, e.g., from the
+//! same configuration, replays the log with [`World::replay`]:
+//! ```ignore
+//! let mut world = World::from_config::<Behaviors>("config.toml")?;
+//! world.replay(InputLog::load("output/inputs.json")?)?;
+//! world.run().await?;
+//! ```
+//!
+//! A replayed behavior is started up as usual, but the events of the stream it
+//! returns are replaced by the events it received in the recorded run, which
+//! are delivered in the order they were delivered across all behaviors. The
+//! messages and transactions it sends still reach the other agents and the
+//! environment, but the events the other behaviors receive from them are
+//! replaced by the log as well. A behavior stops after its last recorded
+//! event.
+//!
+//! [`World`]: crate::world::World
+//! [`World::with_input_recording`]: crate::world::World::with_input_recording
+//! [`World::replay`]: crate::world::World::replay
+
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::sync::watch;
+use tracing::error;
+
+use super::*;
+use crate::machine::EventStream;
+
+/// The events delivered to every behavior of a world, keyed by the slot and
+/// type of the behavior, e.g., `agent/0/Behavior`, which is shared by the
+/// messagers of the world.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Inputs {
+    inner: Arc<InputsInner>,
+}
+
+#[derive(Debug)]
+struct InputsInner {
+    recording: AtomicBool,
+    replaying: AtomicBool,
+    log: Mutex<Vec<Input>>,
+
+    /// The events left to replay for each behavior.
+    replay: Mutex<HashMap<String, VecDeque<Input>>>,
+
+    /// The sequence numbers of the events that have not been replayed yet.
+    pending: Mutex<BTreeSet<u64>>,
+
+    /// Bumped whenever an event is replayed so that the behaviors waiting for
+    /// their turn check again.
+    replayed: watch::Sender<u64>,
+}
+
+/// An event delivered to a behavior.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input {
+    /// The position of the event among the events delivered to all behaviors
+    /// of the world.
+    pub sequence: u64,
+
+    /// The behavior the event was delivered to, keyed by its agent, its
+    /// position among the behaviors of the agent, and its type, e.g.,
+    /// `agent/0/Behavior`.
+    pub behavior: String,
+
+    /// The event.
+    pub event: serde_json::Value,
+}
+
+/// The events delivered to the behaviors of a [`World`](crate::world::World)
+/// during a run, in the order they were delivered.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputLog {
+    /// The id of the world.
+    pub world: String,
+
+    /// The events in the order they were delivered.
+    pub inputs: Vec<Input>,
+}
+
+impl Default for InputsInner {
+    fn default() -> Self {
+        Self {
+            recording: AtomicBool::default(),
+            replaying: AtomicBool::default(),
+            log: Mutex::default(),
+            replay: Mutex::default(),
+            pending: Mutex::default(),
+            replayed: watch::Sender::new(0),
+        }
+    }
+}
+
+impl Inputs {
+    /// Starts recording the events delivered to the behaviors.
+    pub(crate) fn record(&self) {
+        self.inner.recording.store(true, Ordering::Relaxed);
+    }
+
+    /// Replaces the events of the behaviors with the ones of the `log`.
+    pub(crate) fn replay(&self, log: InputLog) {
+        let mut replay = self.inner.replay.lock().unwrap();
+        let mut pending = self.inner.pending.lock().unwrap();
+        replay.clear();
+        pending.clear();
+        for input in log.inputs {
+            pending.insert(input.sequence);
+            replay
+                .entry(input.behavior.clone())
+                .or_default()
+                .push_back(input);
+        }
+        self.inner.replaying.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the events of the behaviors are replayed from a log.
+    pub(crate) fn is_replaying(&self) -> bool {
+        self.inner.replaying.load(Ordering::Relaxed)
+    }
+
+    /// Logs the `event` delivered to the behavior under `key` if recording is
+    /// enabled.
+    pub(crate) fn delivered<E: Serialize>(&self, key: &str, event: &E) {
+        if !self.inner.recording.load(Ordering::Relaxed) {
+            return;
+        }
+        let behavior = key.to_owned();
+        match serde_json::to_value(event) {
+            Ok(event) => {
+                let mut log = self.inner.log.lock().unwrap();
+                let sequence = log.len() as u64;
+                log.push(Input {
+                    sequence,
+                    behavior,
+                    event,
+                });
+            }
+            Err(e) => warn!("Could not record an event of `{}`: {}", behavior, e),
+        }
+    }
+
+    /// Returns the events recorded so far.
+    pub(crate) fn log(&self) -> Vec<Input> {
+        self.inner.log.lock().unwrap().clone()
+    }
+
+    /// Drops the events left to replay for the behavior under `key`, e.g., as
+    /// it returned no event stream.
+    pub(crate) fn skip(&self, key: &str) {
+        drop(Left {
+            inner: self.inner.clone(),
+            key: key.to_owned(),
+        });
+    }
+
+    /// Returns a stream of the events left to replay for the behavior under
+    /// `key`, each of which is delivered once every event before it in the
+    /// log has been delivered.
+    pub(crate) fn replayed<E>(&self, key: &str) -> EventStream<E>
+    where
+        E: DeserializeOwned + Send + Sync + 'static,
+    {
+        let inner = self.inner.clone();
+        let key = key.to_owned();
+        // The events the behavior is not around to replay, e.g., since it was
+        // despawned, do not hold up the other behaviors.
+        let left = Left {
+            inner: inner.clone(),
+            key: key.clone(),
+        };
+        Box::pin(async_stream::stream! {
+            let _left = left;
+            let mut replayed = inner.replayed.subscribe();
+            loop {
+                let next = inner
+                    .replay
+                    .lock()
+                    .unwrap()
+                    .get(&key)
+                    .and_then(|inputs| inputs.front().map(|input| input.sequence));
+                let Some(sequence) = next else {
+                    break;
+                };
+                loop {
+                    replayed.borrow_and_update();
+                    if inner.pending.lock().unwrap().first() == Some(&sequence) {
+                        break;
+                    }
+                    let _ = replayed.changed().await;
+                }
+                let input = inner
+                    .replay
+                    .lock()
+                    .unwrap()
+                    .get_mut(&key)
+                    .and_then(VecDeque::pop_front)
+                    .unwrap();
+                inner.pending.lock().unwrap().remove(&sequence);
+                inner.replayed.send_modify(|count| *count += 1);
+                match serde_json::from_value(input.event) {
+                    Ok(event) => yield event,
+                    Err(e) => error!("Could not replay event {} of `{}`: {}", sequence, key, e),
+                }
+            }
+        })
+    }
+}
+
+/// Drops the events left to replay for a behavior once its stream is dropped,
+/// or right away for a behavior that returns no stream.
+struct Left {
+    inner: Arc<InputsInner>,
+    key: String,
+}
+
+impl Drop for Left {
+    fn drop(&mut self) {
+        let Some(inputs) = self.inner.replay.lock().unwrap().remove(&self.key) else {
+            return;
+        };
+        if inputs.is_empty() {
+            return;
+        }
+        let mut pending = self.inner.pending.lock().unwrap();
+        for input in inputs {
+            pending.remove(&input.sequence);
+        }
+        drop(pending);
+        self.inner.replayed.send_modify(|count| *count += 1);
+    }
+}
+
+impl InputLog {
+    /// Writes the log to `path` as JSON.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ArbiterEngineError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(&mut file, self)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Reads a log written with [`InputLog::save`] from `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ArbiterEngineError> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+}
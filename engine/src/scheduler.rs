@@ -0,0 +1,391 @@
+//! The scheduler module makes the order in which the behaviors of a
+//! [`World`](crate::world::World) process the events they react to
+//! reproducible.
+//!
+//! By default every behavior processes its events as soon as they arrive, so
+//! when several behaviors react to the same event, e.g., a message that
+//! announces a swap on a pool, whichever is scheduled first by the runtime
+//! sends its transactions first and the order changes from run to run. With
+//! [`Scheduling::Deterministic`], the behaviors that receive the same message
+//! process it one after the other in a stable order: by the id of their agent
+//! and then in the order they were added to it, or in a shuffle of that order
+//! that is seeded so that it is the same in every run.
+//!
+//! Every message is numbered as it is sent in the world, and the behaviors
+//! take their turns in the order of the numbers of the messages they received.
+//! A turn is only given once every behavior that streams messages
+//! has caught up with the message it is for, so a behavior that is woken up
+//! later than the others by the runtime still takes its turn in its place.
+//! Any other event, e.g., a [`Tick`](crate::trigger::Tick) or a contract
+//! event, takes its turn after the messages sent before it arrived, as do the
+//! messages of a behavior that buffers its events with a
+//! [`Backpressure`](crate::machine::Backpressure).
+//!
+//! A behavior holds its turn until it has processed its event, so behaviors
+//! must not wait on other behaviors, e.g., for a reply to a message, while
+//! processing with deterministic scheduling. A paused behavior does not hold
+//! up the others.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use tokio::sync::Notify;
+
+use super::*;
+use crate::random::agent_seed;
+
+/// How the behaviors of a [`World`](crate::world::World) are scheduled to
+/// process their events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Scheduling {
+    /// Every behavior processes its events as soon as they arrive,
+    /// concurrently with the other behaviors.
+    #[default]
+    Concurrent,
+
+    /// The behaviors that receive the same message process it one after the
+    /// other, ordered by the id of their agent and the order they were added
+    /// to it, or shuffled with the given `seed`.
+    Deterministic {
+        /// The seed of the shuffles of the order, if the order is shuffled.
+        seed: Option<u64>,
+    },
+}
+
+/// The handle of a single behavior to the scheduler of its world, handed out
+/// through its [`Messager`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Scheduler {
+    inner: Option<Arc<SchedulerInner>>,
+    /// The position of the behavior in the stable order, or `usize::MAX` for
+    /// a handle that is not a behavior's, e.g., that of a remote agent.
+    rank: usize,
+}
+
+#[derive(Debug)]
+struct SchedulerInner {
+    state: Mutex<SchedulerState>,
+    notify: Notify,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    /// The seed of the shuffles of the order if it is shuffled.
+    seed: Option<u64>,
+    /// The number of the last message sent in the world.
+    sequence: u64,
+    /// The number of the last message sent through each channel.
+    sent: HashMap<u64, u64>,
+    /// The message streams of the behaviors by the id of their subscription.
+    subscriptions: HashMap<u64, Subscriber>,
+    /// The id of the next subscription.
+    next_subscription: u64,
+    /// The behaviors that are waiting for their turn along with the number of
+    /// the message their event is ordered by.
+    pending: Vec<(u64, usize)>,
+    /// The behaviors that are paused.
+    paused: HashSet<usize>,
+    /// The behavior whose turn it is.
+    current: Option<usize>,
+}
+
+/// The message stream of a behavior as the scheduler follows it.
+#[derive(Debug)]
+struct Subscriber {
+    /// The rank of the behavior.
+    rank: usize,
+    /// The channel the stream receives messages from.
+    channel: u64,
+    /// The number of the last message sent in the world when the stream had
+    /// no more messages waiting.
+    caught_up: u64,
+    /// The number of the message the stream has handed to the behavior that
+    /// the behavior has yet to take its turn for.
+    holding: Option<u64>,
+}
+
+/// The message stream of a behavior, which is followed by the scheduler until
+/// it is dropped.
+pub(crate) struct Subscription {
+    scheduler: Scheduler,
+    id: u64,
+}
+
+/// The turn of a behavior to process an event, which ends when it is dropped.
+pub(crate) struct Turn(Scheduler);
+
+/// A paused behavior, which is not waited for until it is dropped.
+pub(crate) struct Paused(Scheduler);
+
+/// Numbers the channels of messages so that the scheduler tells them apart.
+static CHANNELS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the id of a new channel of messages.
+pub(crate) fn channel_id() -> u64 {
+    CHANNELS.fetch_add(1, Ordering::Relaxed)
+}
+
+impl Scheduler {
+    /// Creates the scheduler of a world.
+    pub(crate) fn new(scheduling: Scheduling) -> Self {
+        let seed = match scheduling {
+            Scheduling::Concurrent => return Self::default(),
+            Scheduling::Deterministic { seed } => seed,
+        };
+        Self {
+            inner: Some(Arc::new(SchedulerInner {
+                state: Mutex::new(SchedulerState {
+                    seed,
+                    sequence: 0,
+                    sent: HashMap::new(),
+                    subscriptions: HashMap::new(),
+                    next_subscription: 0,
+                    pending: Vec::new(),
+                    paused: HashSet::new(),
+                    current: None,
+                }),
+                notify: Notify::new(),
+            })),
+            rank: usize::MAX,
+        }
+    }
+
+    /// Returns the handle of the behavior at position `rank` in the stable
+    /// order.
+    pub(crate) fn ranked(&self, rank: usize) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            rank,
+        }
+    }
+
+    /// Sends a message through the channel `channel` with `send`, which is
+    /// given the number of the message, or `0` if scheduling is concurrent.
+    pub(crate) fn sequence<T>(&self, channel: u64, send: impl FnOnce(u64) -> T) -> T {
+        let Some(inner) = &self.inner else {
+            return send(0);
+        };
+        // The message is sent while the state is locked, so that a stream
+        // that has no messages waiting has caught up with every message
+        // numbered so far.
+        let mut state = inner.state.lock().unwrap();
+        state.sequence += 1;
+        let sequence = state.sequence;
+        state.sent.insert(channel, sequence);
+        send(sequence)
+    }
+
+    /// Follows a message stream of the behavior from the channel `channel`
+    /// until the returned [`Subscription`] is dropped.
+    pub(crate) fn subscribe(&self, channel: u64) -> Subscription {
+        let mut id = 0;
+        if let Some(inner) = &self.inner {
+            let mut state = inner.state.lock().unwrap();
+            id = state.next_subscription;
+            state.next_subscription += 1;
+            state.subscriptions.insert(
+                id,
+                Subscriber {
+                    rank: self.rank,
+                    channel,
+                    caught_up: 0,
+                    holding: None,
+                },
+            );
+        }
+        Subscription {
+            scheduler: self.clone(),
+            id,
+        }
+    }
+
+    /// Stops waiting for the behavior until the returned [`Paused`] is
+    /// dropped.
+    pub(crate) fn pause(&self) -> Paused {
+        self.update(|state| {
+            state.paused.insert(self.rank);
+        });
+        Paused(self.clone())
+    }
+
+    /// Waits for the turn of the behavior to process the event it received.
+    pub(crate) async fn turn(&self) -> Option<Turn> {
+        let inner = self.inner.as_ref()?;
+        self.update(|state| {
+            // The event is a message the behavior holds, or any other event,
+            // which comes after the messages sent so far.
+            let held = state
+                .subscriptions
+                .values_mut()
+                .filter(|subscriber| subscriber.rank == self.rank)
+                .filter_map(|subscriber| subscriber.holding.take())
+                .min();
+            let sequence = held.unwrap_or(state.sequence);
+            state.pending.push((sequence, self.rank));
+        });
+        // Withdraws the behavior if it stops waiting, e.g., because its agent
+        // was despawned.
+        let waiting = Waiting(self);
+        loop {
+            let notified = inner.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if inner.state.lock().unwrap().current == Some(self.rank) {
+                std::mem::forget(waiting);
+                return Some(Turn(self.clone()));
+            }
+            notified.await;
+        }
+    }
+
+    /// Applies `update` to the state of the scheduler and gives the turn to
+    /// the next behavior if it is free.
+    fn update(&self, update: impl FnOnce(&mut SchedulerState)) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        let mut state = inner.state.lock().unwrap();
+        update(&mut state);
+        if state.next() {
+            drop(state);
+            inner.notify.notify_waiters();
+        }
+    }
+}
+
+impl SchedulerState {
+    /// Returns the position of the behavior at `rank` among the behaviors
+    /// that take their turns for the message numbered `sequence`.
+    fn order(&self, sequence: u64, rank: usize) -> (u64, u64, usize) {
+        let position = match self.seed {
+            Some(seed) => agent_seed(seed, &format!("{}/{}", sequence, rank)),
+            None => rank as u64,
+        };
+        (sequence, position, rank)
+    }
+
+    /// Gives the turn to the pending behavior that comes first once every
+    /// other behavior has caught up with it, and returns whether it did.
+    fn next(&mut self) -> bool {
+        if self.current.is_some() {
+            return false;
+        }
+        let Some(index) = (0..self.pending.len())
+            .min_by_key(|index| self.order(self.pending[*index].0, self.pending[*index].1))
+        else {
+            return false;
+        };
+        let (sequence, rank) = self.pending[index];
+        let first = self.order(sequence, rank);
+        let waiting = self.subscriptions.values().any(|subscriber| {
+            if self.paused.contains(&subscriber.rank)
+                || self
+                    .pending
+                    .iter()
+                    .any(|(_, pending)| *pending == subscriber.rank)
+            {
+                return false;
+            }
+            // The stream handed an earlier message to its behavior, or may
+            // have one waiting that was sent since it caught up.
+            let holding = subscriber
+                .holding
+                .is_some_and(|held| self.order(held, subscriber.rank) < first);
+            let behind = subscriber.caught_up < sequence
+                && self
+                    .sent
+                    .get(&subscriber.channel)
+                    .is_some_and(|sent| *sent > subscriber.caught_up);
+            holding || behind
+        });
+        if waiting {
+            return false;
+        }
+        self.pending.swap_remove(index);
+        trace!("Scheduling the behavior {} for message {}", rank, sequence);
+        self.current = Some(rank);
+        true
+    }
+}
+
+impl Subscription {
+    /// Records that the stream has caught up with every message sent so far
+    /// if `empty` returns `true`, i.e., it has no more messages waiting.
+    pub(crate) fn caught_up(&self, empty: impl FnOnce() -> bool) {
+        let id = self.id;
+        self.scheduler.update(|state| {
+            if empty() {
+                let sequence = state.sequence;
+                if let Some(subscriber) = state.subscriptions.get_mut(&id) {
+                    subscriber.caught_up = sequence;
+                }
+            }
+        });
+    }
+
+    /// Records that the stream hands the message numbered `sequence` to its
+    /// behavior.
+    pub(crate) fn hold(&self, sequence: u64) {
+        let id = self.id;
+        self.scheduler.update(|state| {
+            if let Some(subscriber) = state.subscriptions.get_mut(&id) {
+                subscriber.holding = Some(sequence);
+            }
+        });
+    }
+
+    /// Records that the message the stream handed to its behavior has been
+    /// taken, e.g., because the stream is polled for the next one.
+    pub(crate) fn release(&self) {
+        let id = self.id;
+        self.scheduler.update(|state| {
+            if let Some(subscriber) = state.subscriptions.get_mut(&id) {
+                subscriber.holding = None;
+            }
+        });
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        let id = self.id;
+        self.scheduler.update(|state| {
+            state.subscriptions.remove(&id);
+        });
+    }
+}
+
+impl Drop for Paused {
+    fn drop(&mut self) {
+        let rank = self.0.rank;
+        self.0.update(|state| {
+            state.paused.remove(&rank);
+        });
+    }
+}
+
+/// A behavior that is waiting for its turn.
+struct Waiting<'a>(&'a Scheduler);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let rank = self.0.rank;
+        self.0.update(|state| {
+            state.pending.retain(|(_, pending)| *pending != rank);
+            if state.current == Some(rank) {
+                state.current = None;
+            }
+        });
+    }
+}
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        self.0.update(|state| state.current = None);
+    }
+}
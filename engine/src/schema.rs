@@ -0,0 +1,97 @@
+//! The schema module checks the data of the messages of a [`World`] when they
+//! are sent, so that a message that its receivers cannot decode, e.g., one
+//! sent by a remote agent or with [`Messager::send`], is rejected with an
+//! error in the behavior that sends it rather than failing in the behaviors
+//! that receive it.
+//!
+//! A schema is the [`Payload`] type that the messages of a topic, i.e., the
+//! messages sent with the tag of the payload, or the messages sent to a
+//! recipient have to decode into:
+//! ```ignore
+//! let world = World::new("world")
+//!     .with_schema::<Quote>()
+//!     .with_recipient_schema::<Order>(To::Agent("exchange".to_owned()));
+//! ```
+//! A message sent with the tag `Quote` whose data is not a `Quote`, or a
+//! message sent to the agent `exchange` that is not an `Order`, fails to send
+//! with an [`ArbiterEngineError::MessagerError`] that names the schema and
+//! what is wrong with the data. The message is neither delivered nor logged.
+//! A recipient schema only applies to the messages sent to that exact
+//! recipient, so a message sent to all agents reaches `exchange` unchecked.
+//!
+//! [`World`]: crate::world::World
+//! [`Messager::send`]: crate::messager::Messager::send
+
+use std::sync::RwLock;
+
+use super::*;
+use crate::messager::{Message, Payload, To};
+
+/// The schemas of the messages of a world, which is shared by the messagers
+/// of the world.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Schemas {
+    inner: Arc<RwLock<Vec<Schema>>>,
+}
+
+#[derive(Debug)]
+struct Schema {
+    /// The messages the schema applies to, which are those sent with the tag
+    /// of the schema if there is no recipient.
+    to: Option<To>,
+
+    /// The tag of the payload of the schema.
+    tag: &'static str,
+
+    /// Checks that a message decodes into the payload of the schema.
+    check: fn(&Message) -> Result<(), ArbiterEngineError>,
+}
+
+impl Schemas {
+    /// Requires the messages sent with the tag of `T` to be a `T`.
+    pub(crate) fn register<T: Payload>(&self) {
+        self.push::<T>(None);
+    }
+
+    /// Requires the messages sent to `to` to be a `T`.
+    pub(crate) fn register_recipient<T: Payload>(&self, to: To) {
+        self.push::<T>(Some(to));
+    }
+
+    fn push<T: Payload>(&self, to: Option<To>) {
+        self.inner.write().unwrap().push(Schema {
+            to,
+            tag: T::TAG,
+            check: |message| message.decode::<T>().map(drop),
+        });
+    }
+
+    /// Checks the `message` against every schema that applies to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::MessagerError`] for the first schema
+    /// the message does not match.
+    pub(crate) fn validate(&self, message: &Message) -> Result<(), ArbiterEngineError> {
+        for schema in self.inner.read().unwrap().iter() {
+            let applies = match &schema.to {
+                Some(to) => *to == message.to,
+                None => message.tag.as_deref() == Some(schema.tag),
+            };
+            if !applies {
+                continue;
+            }
+            if let Err(e) = (schema.check)(message) {
+                let reason = match e {
+                    ArbiterEngineError::MessagerError(reason) => reason,
+                    e => e.to_string(),
+                };
+                return Err(ArbiterEngineError::MessagerError(format!(
+                    "Rejected a message from `{}` to {:?} that does not match the schema `{}`: {}",
+                    message.from, message.to, schema.tag, reason
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+//! The testing module helps unit test a [`Behavior`] without building a
+//! [`World`](crate::world::World) or an [`Environment`].
+//!
+//! A behavior is started with a [`MockClient`], whose responses are scripted
+//! on its [`MockProvider`], and the messager of a [`MockMessager`], which
+//! sends it messages and records the messages it sends. Its events are then
+//! scripted with [`scripted`] or [`event_channel`] and processed with [`run`]:
+//! ```ignore
+//! #[tokio::test]
+//! async fn replies_to_pings() {
+//!     let client = Arc::new(MockClient::new());
+//!     let mut messager = MockMessager::new("pinger");
+//!     let mut behavior = Pinger::default();
+//!     behavior.startup(client, messager.messager()).await.unwrap();
+//!
+//!     let ping = messager.message("tester", To::Agent("pinger".to_owned()), "ping");
+//!     run(&mut behavior, scripted([ping])).await.unwrap();
+//!     assert_eq!(messager.next_sent().await.unwrap().data, "\"pong\"");
+//! }
+//! ```
+//!
+//! [`Behavior`]: crate::machine::Behavior
+//! [`Environment`]: arbiter_core::environment::Environment
+
+use arbiter_core::middleware::client::ArbiterClient;
+use ethers::{
+    providers::{Middleware, MockProvider, Provider, ProviderError},
+    types::Address,
+};
+use futures_util::StreamExt;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+
+use super::*;
+use crate::{
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream},
+    messager::{Envelope, LaneReceiver, Message, Priority, To},
+};
+
+/// A client whose responses are scripted on a [`MockProvider`] rather than
+/// coming from an [`Environment`](arbiter_core::environment::Environment).
+///
+/// Each request the behavior makes through the client is answered with the
+/// next response pushed onto [`MockClient::mock`], in the order they were
+/// pushed, e.g., the block number for `get_block_number`:
+/// ```ignore
+/// let client = MockClient::new();
+/// client.mock().push(U64::from(10)).unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockClient {
+    provider: Provider<MockProvider>,
+    address: Address,
+}
+
+impl MockClient {
+    /// Creates a [`MockClient`] with a random address.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self::with_address(Address::random())
+    }
+
+    /// Creates a [`MockClient`] that sends its transactions from `address`.
+    pub fn with_address(address: Address) -> Self {
+        Self {
+            provider: Provider::new(MockProvider::new()),
+            address,
+        }
+    }
+
+    /// Returns the provider the responses of the client are pushed onto and
+    /// its requests are asserted with.
+    pub fn mock(&self) -> &MockProvider {
+        self.provider.as_ref()
+    }
+}
+
+impl Middleware for MockClient {
+    type Error = ProviderError;
+    type Provider = MockProvider;
+    type Inner = Provider<MockProvider>;
+
+    fn inner(&self) -> &Self::Inner {
+        &self.provider
+    }
+}
+
+impl ArbiterClient for MockClient {
+    fn address(&self) -> Address {
+        self.address
+    }
+}
+
+/// A [`Messager`] that is not connected to any other agent, for a behavior
+/// under test.
+///
+/// The behavior is started with [`MockMessager::messager`]. Messages are sent
+/// to it with [`MockMessager::send`] as if another agent sent them, and the
+/// messages it sends itself are read with [`MockMessager::next_sent`] and
+/// [`MockMessager::sent`].
+#[derive(Debug)]
+pub struct MockMessager {
+    agent: String,
+    messager: Messager,
+    receiver: LaneReceiver,
+}
+
+impl MockMessager {
+    /// Creates a [`MockMessager`] for the agent with the id `agent`.
+    pub fn new(agent: &str) -> Self {
+        let messager = Messager::new();
+        let receiver = messager.broadcast_sender.subscribe();
+        Self {
+            agent: agent.to_owned(),
+            messager,
+            receiver,
+        }
+    }
+
+    /// Returns the messager to start the behavior with.
+    pub fn messager(&self) -> Messager {
+        self.messager.for_agent(&self.agent)
+    }
+
+    /// Returns the message with the `data` that the agent `from` sends `to`,
+    /// e.g., for [`scripted`] events.
+    pub fn message<S: Serialize>(&self, from: &str, to: To, data: S) -> Message {
+        Message {
+            from: from.to_owned(),
+            to,
+            data: serde_json::to_string(&data).unwrap(),
+            tag: None,
+            correlation_id: None,
+            priority: Priority::Normal,
+            trace_id: None,
+        }
+    }
+
+    /// Sends the `data` to the agent `to` as if the agent `from` sent it, so
+    /// that it arrives on the streams of the behavior's messager.
+    pub async fn send<S: Serialize>(
+        &self,
+        from: &str,
+        to: To,
+        data: S,
+    ) -> Result<(), ArbiterEngineError> {
+        self.messager.for_agent(from).send(to, data).await
+    }
+
+    /// Waits for the next message the behavior sends, or returns `None` if
+    /// the messager was dropped.
+    pub async fn next_sent(&mut self) -> Option<Message> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(Envelope { message, .. }) if message.from == self.agent => return Some(message),
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Returns the messages the behavior sent since they were last read,
+    /// without waiting for more.
+    pub fn sent(&mut self) -> Vec<Message> {
+        let mut sent = vec![];
+        while let Ok(Envelope { message, .. }) = self.receiver.try_recv() {
+            if message.from == self.agent {
+                sent.push(message);
+            }
+        }
+        sent
+    }
+}
+
+/// Returns a stream of the given `events` that ends after the last of them.
+pub fn scripted<E: Send + Sync + 'static>(events: impl IntoIterator<Item = E>) -> EventStream<E> {
+    Box::pin(futures_util::stream::iter(
+        events.into_iter().collect::<Vec<_>>(),
+    ))
+}
+
+/// Returns a stream of the events sent with the returned sender, which ends
+/// once the sender is dropped, for events that depend on what the behavior
+/// did with the previous ones.
+pub fn event_channel<E: Send + Sync + 'static>() -> (mpsc::UnboundedSender<E>, EventStream<E>) {
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let stream = Box::pin(async_stream::stream! {
+        while let Some(event) = receiver.recv().await {
+            yield event;
+        }
+    });
+    (sender, stream)
+}
+
+/// Processes the events of `stream` with `behavior` in order until it halts
+/// or the stream ends, and returns how it stopped.
+///
+/// Unlike in an [`Engine`](crate::machine::Engine), the first error the
+/// behavior fails to process an event with is returned regardless of its
+/// [`Supervision`](crate::machine::Supervision).
+pub async fn run<B, E, M>(
+    behavior: &mut B,
+    mut stream: EventStream<E>,
+) -> Result<ControlFlow, BehaviorError>
+where
+    B: Behavior<E, M>,
+    E: Send + 'static,
+    M: ArbiterClient,
+{
+    while let Some(event) = stream.next().await {
+        if let ControlFlow::Halt(reason) = behavior.process(event).await? {
+            return Ok(ControlFlow::Halt(reason));
+        }
+    }
+    Ok(ControlFlow::Continue)
+}
@@ -0,0 +1,132 @@
+//! The trigger module contains the schedules on which a [`Behavior`] processes
+//! without waiting for messages or events, e.g., a market maker that re-quotes
+//! every block.
+//!
+//! A [`Trigger`] is turned into an [`EventStream`] of [`Tick`]s that a
+//! behavior returns from [`Behavior::startup`], so that
+//! [`Behavior::process`] runs on every tick. A behavior that also reacts to
+//! other events merges the ticks into their stream with [`EventStreams`]:
+//! ```ignore
+//! #[derive(Debug, Serialize, Deserialize)]
+//! enum Event {
+//!     Tick(Tick),
+//!     Message(Message),
+//! }
+//!
+//! let streams = EventStreams::new()
+//!     .with(Trigger::Blocks(1).stream(client.clone()), Event::Tick)
+//!     .with(messager.stream()?, Event::Message);
+//! Ok(Some(streams.merge()))
+//! ```
+//!
+//! [`EventStreams`]: crate::machine::EventStreams
+//! [`Behavior`]: crate::machine::Behavior
+//! [`Behavior::startup`]: crate::machine::Behavior::startup
+//! [`Behavior::process`]: crate::machine::Behavior::process
+
+use std::time::Duration;
+
+use ethers::providers::Middleware;
+use tokio::{
+    sync::mpsc,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+
+use super::*;
+use crate::machine::EventStream;
+
+/// How often the latest block of a client is read to find out whether a
+/// [`Trigger::Blocks`] or a message sent with
+/// [`Messager::send_at`](crate::messager::Messager::send_at) is due.
+pub const BLOCK_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A schedule on which a behavior processes a [`Tick`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    /// Ticks once every given amount of wall-clock time, starting one period
+    /// after the stream is created. Ticks that are missed because the
+    /// behavior was still processing are not made up for.
+    Interval(Duration),
+
+    /// Ticks once every given number of blocks, starting once that many blocks
+    /// were produced after the stream is created. Blocks are noticed by
+    /// reading the block number of the client every
+    /// [`BLOCK_POLL_INTERVAL`], so a tick is made for the latest block when
+    /// several blocks are produced in between.
+    Blocks(u64),
+}
+
+/// The event made by a [`Trigger`] each time it is due.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tick {
+    /// The number of ticks that were made before this one.
+    pub index: u64,
+
+    /// The block number of the client at the time of the tick.
+    pub block_number: u64,
+}
+
+impl Trigger {
+    /// Returns the [`Tick`]s of this trigger for `client`.
+    ///
+    /// The stream ends if the block number of the client cannot be read,
+    /// e.g., because its [`Environment`] was stopped.
+    ///
+    /// [`Environment`]: arbiter_core::environment::Environment
+    pub fn stream<M: Middleware + 'static>(self, client: Arc<M>) -> EventStream<Tick> {
+        // The ticks are made on a task since the futures of a client are not
+        // `Sync`, which the stream has to be.
+        let (sender, mut receiver) = mpsc::channel(1);
+        spawn(async move {
+            let (period, blocks) = match self {
+                Trigger::Interval(period) => (period, None),
+                Trigger::Blocks(blocks) => (BLOCK_POLL_INTERVAL, Some(blocks.max(1))),
+            };
+            let mut interval = interval_at(Instant::now() + period, period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut last = match client.get_block_number().await {
+                Ok(block_number) => block_number.as_u64(),
+                Err(e) => {
+                    warn!("Trigger could not read the block number: {}", e);
+                    return;
+                }
+            };
+            for index in 0.. {
+                loop {
+                    // The trigger stops once its stream is dropped.
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = sender.closed() => return,
+                    }
+                    let block_number = match client.get_block_number().await {
+                        Ok(block_number) => block_number.as_u64(),
+                        Err(e) => {
+                            warn!("Trigger could not read the block number: {}", e);
+                            return;
+                        }
+                    };
+                    match blocks {
+                        Some(blocks) if block_number < last + blocks => {}
+                        _ => {
+                            last = block_number;
+                            break;
+                        }
+                    }
+                }
+                let tick = Tick {
+                    index,
+                    block_number: last,
+                };
+                trace!("Trigger {:?} made {:?}", self, tick);
+                if sender.send(tick).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Box::pin(async_stream::stream! {
+            while let Some(tick) = receiver.recv().await {
+                yield tick;
+            }
+        })
+    }
+}
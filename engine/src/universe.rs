@@ -7,7 +7,10 @@ use crate::world::World;
 /// The [`Universe`] struct is the primary interface for creating and running
 /// many `World`s in parallel. At the moment, is a wrapper around a
 /// [`HashMap`] of [`World`]s, but can be extended to handle generics inside of
-/// [`World`]s and crosstalk between [`World`]s.
+/// [`World`]s. Worlds can be connected with a
+/// [`Relay`](crate::relay::Relay) before they are added, whose
+/// [`RelayHandle`](crate::relay::RelayHandle) has to be kept alive while they
+/// run.
 #[derive(Debug, Default)]
 pub struct Universe {
     worlds: Option<HashMap<String, World>>,
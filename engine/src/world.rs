@@ -1,16 +1,38 @@
 //! The world module contains the core world abstraction for the Arbiter Engine.
 
-use std::collections::VecDeque;
+use std::{collections::BTreeSet, time::Duration};
 
-use arbiter_core::{database::ArbiterDB, environment::Environment, middleware::ArbiterMiddleware};
+use arbiter_core::{
+    database::ArbiterDB, environment::Environment, middleware::ArbiterMiddleware,
+    profiler::Profile, shutdown::ShutdownCoordinator,
+};
+use ethers::{providers::Middleware, types::Address};
 use futures_util::future::join_all;
-use serde::de::DeserializeOwned;
-use tokio::spawn;
+use serde::{
+    de::{DeserializeOwned, MapAccess, SeqAccess, Visitor},
+    Deserializer,
+};
+use tokio::{spawn, sync::watch, task::AbortHandle};
+use toml::Spanned;
+use tracing::{info_span, Instrument};
 
 use super::*;
 use crate::{
     agent::{Agent, AgentBuilder},
+    blackboard::{Blackboard, Shared},
+    checkpoint::{Checkpoint, Checkpoints},
+    dead_letters::DeadLetter,
+    dependencies::{self, Barrier},
+    determinism::{Recorder, WorldTrace},
+    errors::AgentBuildError,
+    graph,
+    logging::{LogConfig, AGENT_SPAN},
     machine::{CreateStateMachine, MachineInstruction},
+    messager::{Payload, To},
+    metrics::{TransactionCounter, WorldMetrics},
+    reload::Reloader,
+    replay::InputLog,
+    scheduler::{Scheduler, Scheduling},
 };
 
 /// A world is a collection of agents that use the same type of provider, e.g.,
@@ -36,18 +58,573 @@ pub struct World {
 
     /// The messaging layer for the world.
     pub messager: Messager,
+
+    /// The tasks of the behaviors of the agents while the world runs.
+    despawner: Despawner,
+
+    /// Where the metrics of the behaviors are written once the world has run.
+    metrics_output: Option<PathBuf>,
+
+    /// Where checkpoints of the world are written while it runs and how often.
+    checkpointing: Option<(PathBuf, Duration)>,
+
+    /// Where the events delivered to the behaviors are written once the world
+    /// has run.
+    inputs_output: Option<PathBuf>,
+
+    /// Where the messages sent in the world are logged while it runs.
+    message_log: Option<PathBuf>,
+
+    /// How often the configuration the world was built from is checked for
+    /// changes while it runs.
+    config_watching: Option<Duration>,
+}
+
+/// A handle to despawn the agents of a [`World`] while it runs, returned by
+/// [`World::despawner`].
+///
+/// Despawning an agent aborts the tasks of its behaviors and those of its
+/// sub-agents, which drops their event streams, messagers, and client, so
+/// that simulations where agents come and go do not accumulate tasks and
+/// channels.
+#[derive(Clone, Debug, Default)]
+pub struct Despawner {
+    running: Arc<Mutex<HashMap<String, Vec<AbortHandle>>>>,
+}
+
+impl Despawner {
+    /// Despawns the running agent with the id `agent_id` along with its
+    /// sub-agents, or only the sub-agent if `agent_id` is the full id of one,
+    /// e.g., `"fund/strategy"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if no such agent is running.
+    pub fn despawn(&self, agent_id: &str) -> Result<(), ArbiterEngineError> {
+        let mut running = self.running.lock().unwrap();
+        let prefix = format!("{}/", agent_id);
+        let ids: Vec<String> = running
+            .keys()
+            .filter(|id| *id == agent_id || id.starts_with(&prefix))
+            .cloned()
+            .collect();
+        if ids.is_empty() {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "No agent `{}` is running",
+                agent_id
+            )));
+        }
+        for id in ids {
+            for task in running.remove(&id).unwrap_or_default() {
+                task.abort();
+            }
+            info!("Despawned agent `{}`", id);
+        }
+        Ok(())
+    }
+
+    fn register(&self, agent_id: String, task: AbortHandle) {
+        self.running
+            .lock()
+            .unwrap()
+            .entry(agent_id)
+            .or_default()
+            .push(task);
+    }
+}
+
+/// A handle to pause and resume the agents of a [`World`], returned by
+/// [`World::pauser`] and [`Messager::pauser`].
+///
+/// A paused agent finishes processing the event it is processing, if any, and
+/// then holds off processing any other event until it is resumed. The events
+/// it receives in the meantime wait in its event streams, e.g., a paused
+/// market maker stops quoting so that the market can be studied without its
+/// liquidity, and catches up on what it missed once resumed.
+///
+/// The agents of a group, e.g., all liquidity providers, are paused and
+/// resumed together with [`Pauser::pause_group`] and
+/// [`Pauser::resume_group`].
+#[derive(Clone, Debug, Default)]
+pub struct Pauser {
+    agents: Arc<Mutex<HashMap<String, watch::Sender<bool>>>>,
+
+    /// The groups of the agents, which messages are also addressed to.
+    pub(crate) groups: Groups,
 }
 
-use std::{fs::File, io::Read};
+/// The agents in each group of a [`World`], keyed by the group.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Groups {
+    members: Arc<Mutex<HashMap<String, BTreeSet<String>>>>,
+}
+
+impl Groups {
+    /// Adds the agent with the id `agent_id` to the `group`.
+    pub(crate) fn join(&self, agent_id: &str, group: &str) {
+        self.members
+            .lock()
+            .unwrap()
+            .entry(group.to_owned())
+            .or_default()
+            .insert(agent_id.to_owned());
+    }
+
+    /// Returns whether the agent with the id `agent_id` is in the `group`.
+    pub(crate) fn contains(&self, group: &str, agent_id: &str) -> bool {
+        self.members
+            .lock()
+            .unwrap()
+            .get(group)
+            .is_some_and(|members| members.contains(agent_id))
+    }
+
+    /// Returns the ids of the agents in each group, keyed by the group.
+    pub(crate) fn all(&self) -> BTreeMap<String, Vec<String>> {
+        self.members
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(group, members)| (group.clone(), members.iter().cloned().collect()))
+            .collect()
+    }
+
+    /// Returns the ids of the agents in the `group`.
+    pub(crate) fn members(&self, group: &str) -> Vec<String> {
+        self.members
+            .lock()
+            .unwrap()
+            .get(group)
+            .map(|members| members.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl Pauser {
+    /// Pauses the agent with the id `agent_id` along with its sub-agents, or
+    /// only the sub-agent if `agent_id` is the full id of one, e.g.,
+    /// `"fund/strategy"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if there is no such agent.
+    pub fn pause(&self, agent_id: &str) -> Result<(), ArbiterEngineError> {
+        self.set_paused(agent_id, true)
+    }
+
+    /// Resumes the agent with the id `agent_id` along with its sub-agents, or
+    /// only the sub-agent if `agent_id` is the full id of one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if there is no such agent.
+    pub fn resume(&self, agent_id: &str) -> Result<(), ArbiterEngineError> {
+        self.set_paused(agent_id, false)
+    }
+
+    /// Pauses every agent in the `group` along with their sub-agents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if no agent is in the
+    /// group.
+    pub fn pause_group(&self, group: &str) -> Result<(), ArbiterEngineError> {
+        self.set_group_paused(group, true)
+    }
+
+    /// Resumes every agent in the `group` along with their sub-agents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if no agent is in the
+    /// group.
+    pub fn resume_group(&self, group: &str) -> Result<(), ArbiterEngineError> {
+        self.set_group_paused(group, false)
+    }
+
+    /// Returns the ids of the agents in the `group`.
+    pub fn group(&self, group: &str) -> Vec<String> {
+        self.groups.members(group)
+    }
+
+    /// Returns whether the agent with the id `agent_id` is paused.
+    pub fn is_paused(&self, agent_id: &str) -> bool {
+        self.agents
+            .lock()
+            .unwrap()
+            .get(agent_id)
+            .is_some_and(|paused| *paused.borrow())
+    }
+
+    fn set_paused(&self, agent_id: &str, paused: bool) -> Result<(), ArbiterEngineError> {
+        let agents = self.agents.lock().unwrap();
+        let prefix = format!("{}/", agent_id);
+        let mut found = false;
+        for (id, sender) in agents.iter() {
+            if id == agent_id || id.starts_with(&prefix) {
+                sender.send_replace(paused);
+                found = true;
+                info!(
+                    "{} agent `{}`",
+                    if paused { "Paused" } else { "Resumed" },
+                    id
+                );
+            }
+        }
+        if !found {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "No agent `{}` to {}",
+                agent_id,
+                if paused { "pause" } else { "resume" }
+            )));
+        }
+        Ok(())
+    }
+
+    fn set_group_paused(&self, group: &str, paused: bool) -> Result<(), ArbiterEngineError> {
+        let members = self.groups.members(group);
+        if members.is_empty() {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "No agent in group `{}` to {}",
+                group,
+                if paused { "pause" } else { "resume" }
+            )));
+        }
+        for agent_id in members {
+            self.set_paused(&agent_id, paused)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the agent with the id `agent_id` is paused, as it
+    /// changes.
+    pub(crate) fn register(&self, agent_id: &str) -> watch::Receiver<bool> {
+        self.agents
+            .lock()
+            .unwrap()
+            .entry(agent_id.to_owned())
+            .or_insert_with(|| watch::channel(false).0)
+            .subscribe()
+    }
+
+    /// Returns whether the agent with the id `agent_id` is an agent of the
+    /// world.
+    pub(crate) fn contains(&self, agent_id: &str) -> bool {
+        self.agents.lock().unwrap().contains_key(agent_id)
+    }
+}
+
+/// A handle to stop a [`World`] while it runs, returned by [`World::stopper`]
+/// and [`Messager::stopper`].
+///
+/// Stopping the world stops every behavior once it is done processing its
+/// current event, after which the behavior is torn down with
+/// [`Behavior::teardown`](crate::machine::Behavior::teardown) and
+/// [`World::run`] returns once all of them are, e.g., so that the behaviors
+/// flush their files at the end of a simulation that would otherwise run
+/// forever.
+#[derive(Clone, Debug)]
+pub struct Stopper {
+    stopped: Arc<watch::Sender<bool>>,
+}
+
+impl Default for Stopper {
+    fn default() -> Self {
+        Self {
+            stopped: Arc::new(watch::channel(false).0),
+        }
+    }
+}
+
+impl Stopper {
+    /// Stops the world.
+    pub fn stop(&self) {
+        if !self.stopped.send_replace(true) {
+            info!("Stopping the world");
+        }
+    }
+
+    /// Returns whether the world was stopped.
+    pub fn is_stopped(&self) -> bool {
+        *self.stopped.borrow()
+    }
+
+    /// Waits until the world is stopped.
+    pub(crate) async fn stopped(&self) {
+        let mut stopped = self.stopped.subscribe();
+        // The sender is held by `self`, so the wait cannot fail.
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    }
+}
+
+use std::{collections::BTreeMap, fmt, fs::File, io::Read, path::PathBuf, sync::Mutex};
 impl World {
     /// Creates a new [`World`] with the given identifier and provider.
     pub fn new(id: &str) -> Self {
+        Self::with_environment(id, Environment::builder().build())
+    }
+
+    /// Creates a new [`World`] with the given identifier around an already
+    /// built [`Environment`].
+    fn with_environment(id: &str, environment: Environment) -> Self {
+        let mut messager = Messager::new();
+        messager.profiler = environment.profiler();
+        messager.recorder = Recorder::new(environment.tracer());
         Self {
             id: id.to_owned(),
             agents: Some(HashMap::new()),
-            environment: Some(Environment::builder().build()),
-            messager: Messager::new(),
+            environment: Some(environment),
+            messager,
+            despawner: Despawner::default(),
+            metrics_output: None,
+            checkpointing: None,
+            inputs_output: None,
+            message_log: None,
+            config_watching: None,
+        }
+    }
+
+    /// Turns on the [`Profiler`](arbiter_core::profiler::Profiler) of the
+    /// world's [`Environment`] so that the wall-clock time of
+    /// [`World::run`] is attributed to the environment
+    /// executing instructions and to each agent's behaviors starting up,
+    /// processing events, waiting for events, and serializing messages. The
+    /// breakdown is logged at the `info` level once the run finishes.
+    pub fn with_profiling(self) -> Self {
+        self.messager.profiler.enable();
+        self
+    }
+
+    /// Turns on tracing so that the messages every agent sends and the
+    /// transactions every agent executes are recorded. The recording can be
+    /// retrieved with [`World::trace`] and compared to another run with
+    /// [`WorldTrace::first_divergence`].
+    pub fn with_tracing(self) -> Self {
+        self.messager.recorder.enable();
+        self
+    }
+
+    /// Sets how the behaviors of the agents are scheduled to process their
+    /// events, see the [`scheduler`](crate::scheduler) module. By default they
+    /// are [`Scheduling::Concurrent`].
+    pub fn with_scheduling(mut self, scheduling: Scheduling) -> Self {
+        self.messager.scheduler = Scheduler::new(scheduling);
+        self
+    }
+
+    /// Holds back the behaviors of the world from processing their events
+    /// until the agents of the `barrier` are ready, i.e., have started up,
+    /// see the [`dependencies`](crate::dependencies) module. By default the
+    /// behaviors process their events as soon as they have started up.
+    pub fn with_startup_barrier(self, barrier: Barrier) -> Self {
+        self.messager.readiness.set_barrier(barrier);
+        self
+    }
+
+    /// Sets the seed that the random number generator of every agent is
+    /// derived from along with the id of the agent, see the
+    /// [`random`](crate::random) module. By default it is
+    /// [`DEFAULT_SEED`](crate::random::DEFAULT_SEED).
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.messager.randomness.set_seed(seed);
+        self
+    }
+
+    /// Returns everything the agents have done so far if tracing is enabled.
+    pub fn trace(&self) -> WorldTrace {
+        self.messager.recorder.trace()
+    }
+
+    /// Returns a handle to the value of type `T` on the world's
+    /// [`Blackboard`], which agents can read and publish through their
+    /// [`Messager::state`].
+    pub fn state<T: Clone + Send + Sync + 'static>(&self) -> Shared<T> {
+        self.messager.blackboard.state()
+    }
+
+    /// Returns the [`Blackboard`] shared by the agents of the world, e.g., to
+    /// [`audit`](Blackboard::audit) what they published.
+    pub fn blackboard(&self) -> &Blackboard {
+        &self.messager.blackboard
+    }
+
+    /// Returns the time recorded by the world's profiler so far.
+    pub fn profile(&self) -> Profile {
+        self.messager.profiler.profile()
+    }
+
+    /// Returns what each behavior of the world has done so far, see the
+    /// [`metrics`](crate::metrics) module.
+    pub fn metrics(&self) -> WorldMetrics {
+        self.messager.metrics.snapshot()
+    }
+
+    /// Returns the agents of the world, their behaviors along with the events
+    /// they process, and how their messages are routed as a DOT graph, so
+    /// that the wiring of the world can be checked before it runs, see the
+    /// [`graph`](crate::graph) module.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if the world has already
+    /// been run.
+    pub fn to_dot(&self) -> Result<String, ArbiterEngineError> {
+        let agents = self.agents.as_ref().ok_or_else(|| {
+            ArbiterEngineError::WorldError(
+                "No agents found. Has the world already been ran?".to_owned(),
+            )
+        })?;
+        Ok(graph::render(
+            &self.id,
+            agents,
+            &self.messager.pauser.groups.all(),
+        ))
+    }
+
+    /// Writes the [`World::metrics`] to `path` as JSON once [`World::run`]
+    /// finishes.
+    pub fn with_metrics_output(mut self, path: impl Into<PathBuf>) -> Self {
+        self.metrics_output = Some(path.into());
+        self
+    }
+
+    /// Writes a [`Checkpoint`] of the world to `path` every `interval` of
+    /// wall-clock time while it runs and once more when it has run, see the
+    /// [`checkpoint`](crate::checkpoint) module.
+    pub fn with_checkpoints(mut self, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.messager.checkpoints.enable();
+        self.checkpointing = Some((path.into(), interval));
+        self
+    }
+
+    /// Resumes the world from a `checkpoint`: the environment is moved to the
+    /// block of the checkpoint and its state is replaced with the one of the
+    /// checkpoint, and the behaviors of the agents are restored with their
+    /// checkpointed state once [`World::run`] starts them.
+    ///
+    /// The world has to be built with the same agents and behaviors as the
+    /// world the checkpoint was written from. A behavior that has no state in
+    /// the checkpoint starts from its configuration instead.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if the world has already
+    /// been run.
+    pub fn resume(&mut self, checkpoint: Checkpoint) -> Result<(), ArbiterEngineError> {
+        let environment = self.environment.as_ref().ok_or_else(|| {
+            ArbiterEngineError::WorldError(
+                "Cannot resume a world that has already been ran.".to_owned(),
+            )
+        })?;
+        let client = ArbiterMiddleware::new_from_forked_eoa(environment, Address::zero())?;
+        client.update_block(checkpoint.block_number, checkpoint.block_timestamp)?;
+        environment.db().restore(&checkpoint.db);
+        self.messager.checkpoints.restore(checkpoint.behaviors);
+        Ok(())
+    }
+
+    /// Records every event delivered to the behaviors of the world and writes
+    /// the [`InputLog`] to `path` once [`World::run`] finishes, see the
+    /// [`replay`](crate::replay) module.
+    pub fn with_input_recording(mut self, path: impl Into<PathBuf>) -> Self {
+        self.messager.inputs.record();
+        self.inputs_output = Some(path.into());
+        self
+    }
+
+    /// Logs every message sent in the world to `path` while [`World::run`]
+    /// runs, which is read back with
+    /// [`MessageLog::load`](crate::journal::MessageLog::load), see the
+    /// [`journal`](crate::journal) module.
+    pub fn with_message_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.message_log = Some(path.into());
+        self
+    }
+
+    /// Sets how many messages the channel of each lane of the messagers of
+    /// the world keeps,
+    /// [`DEFAULT_MESSAGE_CAPACITY`](crate::messager::DEFAULT_MESSAGE_CAPACITY)
+    /// by default, rounded up to a power of two. An agent
+    /// whose stream falls further behind misses the oldest of them, which is
+    /// counted along with the depth of its queue in the
+    /// [`metrics`](crate::metrics) of its behaviors.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if agents have already been added to the world,
+    /// as they would be left on the previous channels, so the capacity is set
+    /// before adding the agents.
+    pub fn with_message_capacity(mut self, capacity: usize) -> Self {
+        if self
+            .agents
+            .as_ref()
+            .is_some_and(|agents| !agents.is_empty())
+        {
+            panic!("The message capacity must be set before adding agents to the world.");
         }
+        self.messager.set_capacity(capacity);
+        self
+    }
+
+    /// Rejects the messages sent with the tag of the payload `T` whose data
+    /// is not a `T` when they are sent, see the [`schema`](crate::schema)
+    /// module.
+    pub fn with_schema<T: Payload>(self) -> Self {
+        self.messager.schemas.register::<T>();
+        self
+    }
+
+    /// Rejects the messages sent to `to` that are not a `T` when they are
+    /// sent, see the [`schema`](crate::schema) module.
+    pub fn with_recipient_schema<T: Payload>(self, to: To) -> Self {
+        self.messager.schemas.register_recipient::<T>(to);
+        self
+    }
+
+    /// Checks the configuration the world was built from with
+    /// [`World::from_config`] for changes every `interval` while it runs, and
+    /// reloads the behaviors whose numeric parameters changed, see the
+    /// [`reload`](crate::reload) module.
+    pub fn with_config_watching(mut self, interval: Duration) -> Self {
+        self.config_watching = Some(interval);
+        self
+    }
+
+    /// Returns the messages sent in the world so far that could not be
+    /// delivered, along with why, see the
+    /// [`dead_letters`](crate::dead_letters) module.
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.messager.dead_letters.letters()
+    }
+
+    /// Returns the events delivered to the behaviors of the world so far if
+    /// they are recorded with [`World::with_input_recording`].
+    pub fn inputs(&self) -> InputLog {
+        InputLog {
+            world: self.id.clone(),
+            inputs: self.messager.inputs.log(),
+        }
+    }
+
+    /// Replays the events of the `log` to the behaviors of the world once
+    /// [`World::run`] starts them, in place of the events of their streams.
+    ///
+    /// The world has to be built with the same agents and behaviors as the
+    /// world the log was recorded from, and the behaviors have to start up
+    /// the same way, e.g., with the same seeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if the world has already
+    /// been run.
+    pub fn replay(&mut self, log: InputLog) -> Result<(), ArbiterEngineError> {
+        if self.agents.is_none() {
+            return Err(ArbiterEngineError::WorldError(
+                "Cannot replay a world that has already been ran.".to_owned(),
+            ));
+        }
+        self.messager.inputs.replay(log);
+        Ok(())
     }
 
     /// Builds and adds agents to the world from a configuration file.
@@ -80,19 +657,120 @@ impl World {
     /// - The contents of the configuration file cannot be deserialized into the
     ///   expected `HashMap<String, Vec<C>>` format.
     ///
+    /// # Templates
+    ///
+    /// Agents that only differ in a couple of parameters can be instantiated
+    /// from a template defined under the `templates` table. An agent that is a
+    /// table with a `template` key gets a copy of the template's behaviors, and
+    /// every other key of that table is an override that is merged into the
+    /// template's behavior of the same name.
+    ///
+    /// # Instances
+    ///
+    /// A behavior that is the same but for a couple of parameters, e.g., a
+    /// swapper for each of a number of pools, is configured once with a
+    /// `for_each` key, which names a list of parameter tables under the `sets`
+    /// table or is such a list itself. The behavior is instantiated once for
+    /// every table of the list, which is merged into its configuration.
+    ///
+    /// # Gas limits
+    ///
+    /// A top level `transaction_gas_limit` caps the gas of every transaction
+    /// sent by the agents in the world, and the `transaction_gas_limits` table
+    /// overrides it per agent. A transaction that runs out of gas fails with
+    /// [`ArbiterCoreError::OutOfGas`](arbiter_core::errors::ArbiterCoreError::OutOfGas)
+    /// instead of silently using the block gas limit.
+    ///
+    /// # Groups
+    ///
+    /// The `groups` table puts agents in groups as with
+    /// [`AgentBuilder::with_group`], keyed by the agent, e.g., `alice = "lps"`
+    /// or `alice = ["lps", "traders"]`.
+    ///
+    /// # Randomness
+    ///
+    /// A top level `seed` sets the seed of the random number generators of
+    /// the agents as with [`World::with_seed`].
+    ///
+    /// # Scheduling
+    ///
+    /// A top level `scheduling` sets the [`Scheduling`] of the world as with
+    /// [`World::with_scheduling`], e.g., `scheduling = "Concurrent"` or
+    /// `scheduling = { Deterministic = { seed = 7 } }`.
+    ///
+    /// # Startup barrier
+    ///
+    /// A top level `startup_barrier` holds back the behaviors until enough
+    /// agents are ready as with [`World::with_startup_barrier`], e.g.,
+    /// `startup_barrier = "All"` or `startup_barrier = { Quorum = 3 }`.
+    ///
+    /// # Metrics
+    ///
+    /// A top level `metrics` sets the path the metrics of the behaviors are
+    /// written to once the world has run, as with
+    /// [`World::with_metrics_output`].
+    ///
+    /// # Message log
+    ///
+    /// A top level `message_log` sets the path the messages sent in the world
+    /// are logged to while it runs, as with [`World::with_message_log`].
+    ///
+    /// # Message capacity
+    ///
+    /// A top level `message_capacity` sets how many messages the channels of
+    /// the world keep, as with [`World::with_message_capacity`].
+    ///
+    /// # Logging
+    ///
+    /// The `logging` table sets the log levels of the simulation as described
+    /// in the [`logging`](crate::logging) module. It is not applied by this
+    /// method since the global subscriber is usually installed before the
+    /// world is built, see
+    /// [`LogConfig::init`](crate::logging::LogConfig::init).
+    ///
     /// # Examples
     ///
     /// Assuming a TOML file named `agents_config.toml` exists in the current
     /// working directory with the following content:
     ///
     /// ```toml
+    /// transaction_gas_limit = 1_000_000
+    /// seed = 42
+    /// scheduling = { Deterministic = { seed = 7 } }
+    /// startup_barrier = "All"
+    ///
     /// [[agent1]]
     /// BehaviorTypeA = { ... } ,
     /// [[agent1]]
     /// BehaviorTypeB = { ... }
     ///
-    /// [agent2]
+    /// [[agent2]]
     /// BehaviorTypeC = { ... }
+    ///
+    /// [[sets.pools]]
+    /// pool = "0x..."
+    /// [[sets.pools]]
+    /// pool = "0x..."
+    ///
+    /// [[agent4]]
+    /// BehaviorTypeA = { some_parameter = 3, for_each = "pools" }
+    ///
+    /// [[templates.trader]]
+    /// BehaviorTypeA = { ... }
+    ///
+    /// [agent3]
+    /// template = "trader"
+    /// BehaviorTypeA = { some_parameter = 2 }
+    ///
+    /// [transaction_gas_limits]
+    /// agent2 = 5_000_000
+    ///
+    /// [groups]
+    /// agent1 = "traders"
+    /// agent3 = ["traders", "lps"]
+    ///
+    /// [logging.agents]
+    /// agent2 = "debug"
     /// ```
     pub fn from_config<C: CreateStateMachine + Serialize + DeserializeOwned + Debug>(
         config_path: &str,
@@ -100,33 +778,176 @@ impl World {
         let cwd = std::env::current_dir()?;
         let path = cwd.join(config_path);
         info!("Reading from path: {:?}", path);
-        let mut file = File::open(path)?;
+        let mut file = File::open(&path)?;
 
         let mut contents = String::new();
         file.read_to_string(&mut contents)?;
+        validate::<C>(&contents, config_path)?;
 
         #[derive(Deserialize)]
         struct Config<C> {
             id: Option<String>,
+            transaction_gas_limit: Option<u64>,
+            #[serde(default)]
+            transaction_gas_limits: HashMap<String, u64>,
+            #[serde(default)]
+            groups: HashMap<String, AgentGroups>,
+            seed: Option<u64>,
+            metrics: Option<PathBuf>,
+            message_log: Option<PathBuf>,
+            message_capacity: Option<usize>,
+            #[serde(default)]
+            scheduling: Scheduling,
+            startup_barrier: Option<Barrier>,
+            #[serde(default)]
+            logging: LogConfig,
             #[serde(flatten)]
             agents_map: HashMap<String, Vec<C>>,
         }
 
-        let config: Config<C> = toml::from_str(&contents)?;
+        /// The group or groups of an agent.
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum AgentGroups {
+            One(String),
+            Many(Vec<String>),
+        }
 
-        let mut world = World::new(&config.id.unwrap_or_else(|| "world".to_owned()));
+        let table = expand_config(&contents)?;
+        let behaviors = agent_behaviors(&table);
+        let config = Config::<C>::deserialize(toml::Value::Table(table))?;
+        config.logging.filter(tracing::Level::ERROR)?;
+
+        if let Some(agent) = config
+            .transaction_gas_limits
+            .keys()
+            .find(|agent| !config.agents_map.contains_key(*agent))
+        {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "`transaction_gas_limits` sets a limit for the unknown agent `{}`",
+                agent
+            )));
+        }
+        if let Some(agent) = config
+            .groups
+            .keys()
+            .find(|agent| !config.agents_map.contains_key(*agent))
+        {
+            return Err(ArbiterEngineError::WorldError(format!(
+                "`groups` puts the unknown agent `{}` in a group",
+                agent
+            )));
+        }
+
+        let mut environment = Environment::builder();
+        if let Some(gas_limit) = config.transaction_gas_limit {
+            environment = environment.with_transaction_gas_limit(gas_limit);
+        }
+        let mut world = World::with_environment(
+            &config.id.unwrap_or_else(|| "world".to_owned()),
+            environment.build(),
+        )
+        .with_scheduling(config.scheduling);
+        if let Some(seed) = config.seed {
+            world = world.with_seed(seed);
+        }
+        if let Some(path) = config.metrics {
+            world = world.with_metrics_output(path);
+        }
+        if let Some(path) = config.message_log {
+            world = world.with_message_log(path);
+        }
+        if let Some(capacity) = config.message_capacity {
+            world = world.with_message_capacity(capacity);
+        }
+        if let Some(barrier) = config.startup_barrier {
+            world = world.with_startup_barrier(barrier);
+        }
 
         for (agent, behaviors) in config.agents_map {
             let mut next_agent = Agent::builder(&agent);
+            if let Some(&gas_limit) = config.transaction_gas_limits.get(&agent) {
+                next_agent = next_agent.with_transaction_gas_limit(gas_limit);
+            }
+            let groups = match config.groups.get(&agent) {
+                Some(AgentGroups::One(group)) => std::slice::from_ref(group),
+                Some(AgentGroups::Many(groups)) => groups.as_slice(),
+                None => &[],
+            };
+            for group in groups {
+                next_agent = next_agent.with_group(group);
+            }
             for behavior in behaviors {
                 let engine = behavior.create_state_machine();
                 next_agent = next_agent.with_engine(engine);
             }
             world.add_agent(next_agent);
         }
+        world.messager.reloader.watch(&path, behaviors);
         Ok(world)
     }
 
+    /// Checks the behaviors configured in the file at `config_path` as read by
+    /// [`World::from_config`] without building a world, and returns every
+    /// behavior whose name is not one of [`CreateStateMachine::behavior_names`]
+    /// or whose configuration is rejected by
+    /// [`CreateStateMachine::validate_behavior`], along with the file, line,
+    /// and column it is configured at.
+    ///
+    /// [`World::from_config`] runs the same checks before it deserializes the
+    /// configuration, so that it fails with these errors rather than with the
+    /// first error of the deserialization, which does not say where it is.
+    pub fn validate_config<C: CreateStateMachine>(
+        config_path: &str,
+    ) -> Result<(), ArbiterEngineError> {
+        let contents = std::fs::read_to_string(std::env::current_dir()?.join(config_path))?;
+        validate::<C>(&contents, config_path)
+    }
+
+    /// Returns a [`Despawner`] that despawns the agents of the world while it
+    /// runs, e.g., from a task spawned before [`World::run`] is awaited.
+    pub fn despawner(&self) -> Despawner {
+        self.despawner.clone()
+    }
+
+    /// Returns a [`Pauser`] that pauses and resumes the agents of the world,
+    /// e.g., from a task spawned before [`World::run`] is awaited.
+    pub fn pauser(&self) -> Pauser {
+        self.messager.pauser()
+    }
+
+    /// Returns a [`Stopper`] that stops the world, e.g., from a task spawned
+    /// before [`World::run`] is awaited.
+    pub fn stopper(&self) -> Stopper {
+        self.messager.stopper()
+    }
+
+    /// Returns a [`Reloader`] that reloads the parameters of the behaviors
+    /// from the configuration the world was built from while it runs, see the
+    /// [`reload`](crate::reload) module.
+    pub fn reloader(&self) -> Reloader {
+        self.messager.reloader()
+    }
+
+    /// Removes the agent with the id `agent_id` from the world along with its
+    /// sub-agents, or only the sub-agent if `agent_id` is the full id of one.
+    /// An agent that is already running is despawned as with
+    /// [`Despawner::despawn`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ArbiterEngineError::WorldError`] if the world has no such
+    /// agent.
+    pub fn despawn(&mut self, agent_id: &str) -> Result<(), ArbiterEngineError> {
+        if let Some(agents) = self.agents.as_mut() {
+            if remove_agent(agents, agent_id) {
+                info!("Despawned agent `{}`", agent_id);
+                return Ok(());
+            }
+        }
+        self.despawner.despawn(agent_id)
+    }
+
     /// Adds an agent, constructed from the provided `AgentBuilder`, to the
     /// world.
     ///
@@ -139,10 +960,8 @@ impl World {
     ///
     /// # Panics
     ///
-    /// This method will panic if:
-    /// - It fails to create a `RevmMiddleware` client for the agent.
-    /// - The `AgentBuilder` fails to build the `Agent`.
-    /// - The world's internal collection of agents is not initialized.
+    /// This method will panic if the agent cannot be added, see
+    /// [`World::try_add_agent`], which returns the error instead.
     ///
     /// # Examples
     ///
@@ -154,18 +973,42 @@ impl World {
     ///
     /// This will add the agent defined by `agent_builder` to the world.
     pub fn add_agent(&mut self, agent_builder: AgentBuilder) {
+        if let Err(e) = self.try_add_agent(agent_builder) {
+            panic!("Failed to add the agent to the world: {}", e);
+        }
+    }
+
+    /// Adds an agent, constructed from the provided `AgentBuilder`, to the
+    /// world like [`World::add_agent`], whose client starts with the initial
+    /// balance of the builder, if it has one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AgentBuildError::DuplicateId`] if the world already has an
+    /// agent with the id of the builder, any other [`AgentBuildError`] the
+    /// agent fails to be built with, see [`AgentBuilder::build`], or an error
+    /// if the world has already been run or its client cannot be created.
+    pub fn try_add_agent(&mut self, agent_builder: AgentBuilder) -> Result<(), ArbiterEngineError> {
         let id = agent_builder.id.clone();
-        let client = ArbiterMiddleware::new(self.environment.as_ref().unwrap(), Some(&id))
-            .expect("Failed to create RevmMiddleware client for agent");
+        let agents = self.agents.as_ref().ok_or_else(|| {
+            ArbiterEngineError::WorldError(
+                "No agents found. Has the world already been ran?".to_owned(),
+            )
+        })?;
+        if agents.contains_key(&id) {
+            return Err(AgentBuildError::DuplicateId(id).into());
+        }
+        let mut client = ArbiterMiddleware::builder().with_label(&id);
+        if let Some(wei) = agent_builder.initial_balance {
+            client = client.with_initial_balance(wei);
+        }
+        let client = client.build(self.environment.as_ref().unwrap())?;
+        self.messager.recorder.register(client.address(), &id);
+        client.add_hooks(TransactionCounter);
         let messager = self.messager.for_agent(&id);
-        let agent = agent_builder
-            .build(client, messager)
-            .expect("Failed to build agent from AgentBuilder");
-        let agents = self
-            .agents
-            .as_mut()
-            .expect("Agents collection not initialized");
-        agents.insert(id.to_owned(), agent);
+        let agent = agent_builder.build(client, messager)?;
+        self.agents.as_mut().unwrap().insert(id, agent);
+        Ok(())
     }
 
     /// Executes all agents and their behaviors concurrently within the world.
@@ -177,6 +1020,11 @@ impl World {
     /// simultaneously, leveraging asynchronous execution to manage concurrent
     /// operations.
     ///
+    /// The world is stopped as with a [`Stopper`] once a shutdown is requested
+    /// from the [`ShutdownCoordinator`], e.g., by its signal handlers, which
+    /// waits for the message log, the metrics, and the final checkpoint to be
+    /// written.
+    ///
     /// # Errors
     ///
     /// Returns an error if no agents are found in the world, possibly
@@ -192,31 +1040,551 @@ impl World {
             }
         };
         let mut tasks = vec![];
-        // Prepare a queue for messagers corresponding to each behavior engine.
-        let mut messagers = VecDeque::new();
-        // Populate the messagers queue.
-        for (_, agent) in agents.iter() {
-            for _ in &agent.behavior_engines {
-                messagers.push_back(agent.messager.clone());
-            }
-        }
-        // For each agent, spawn a task for each of its behavior engines.
-        // Unwrap here is safe as we just built the dang thing.
-        for (_, mut agent) in agents {
-            for mut engine in agent.behavior_engines.drain(..) {
-                let client = agent.client.clone();
-                let messager = messagers.pop_front().unwrap();
-                tasks.push(spawn(async move {
+        // Give each behavior engine of the agents and their sub-agents its own
+        // messager before any of them starts so that no message is missed.
+        let mut engines = vec![];
+        let mut agent_dependencies: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+        for (id, mut agent) in agents {
+            agent_dependencies.entry(id).or_default();
+            engines.extend(agent.engines());
+        }
+        // Check that the behaviors only depend on agents of the world, and not
+        // on each other in a cycle, before any of them starts.
+        for (engine, _, messager) in &engines {
+            let id = messager.id.clone().unwrap_or_default();
+            self.messager.readiness.register(&id);
+            agent_dependencies
+                .entry(id)
+                .or_default()
+                .extend(engine.dependencies());
+        }
+        dependencies::check(&agent_dependencies)?;
+        // Rank the behavior engines by the id of their agent and then by the
+        // order they were added to it so that their scheduling is stable.
+        engines.sort_by(|(_, _, a), (_, _, b)| a.id.cmp(&b.id));
+        // Checkpoint and record each behavior engine in the slot of its
+        // position among the behaviors of its agent.
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for (rank, (_, _, messager)) in engines.iter_mut().enumerate() {
+            messager.set_scheduler(self.messager.scheduler.ranked(rank));
+            let id = messager.id.clone().unwrap_or_default();
+            let position = positions.entry(id.clone()).or_default();
+            messager.slot = Some(format!("{}/{}", id, position).into());
+            *position += 1;
+        }
+        // Count every behavior as running before any of them starts, so that
+        // the messages sent to agents that have yet to start are not taken as
+        // dead letters.
+        let running: Vec<_> = engines
+            .iter()
+            .map(|(_, _, messager)| {
+                let id = messager.id.clone().unwrap_or_default();
+                self.messager.dead_letters.run(&id)
+            })
+            .collect();
+        if let Some(path) = &self.message_log {
+            self.messager.journal.open(path)?;
+        }
+        // Register the artifacts that are written once the world stops with
+        // the shutdown coordinator, and stop the world once a shutdown is
+        // requested so that they are written before the process exits.
+        let coordinator = ShutdownCoordinator::global();
+        let metrics_output = self.metrics_output.as_ref().map(|_| coordinator.register());
+        let final_checkpoint = self.checkpointing.as_ref().map(|_| coordinator.register());
+        let stopper = self.stopper();
+        let world = self.id.clone();
+        let interrupting = spawn(async move {
+            coordinator.wait_for_request().await;
+            warn!("The world `{}` was interrupted, writing its output", world);
+            stopper.stop();
+        });
+        self.messager.metrics.start();
+        // Write checkpoints from a client of its own, as a client's calls
+        // cannot be shared between tasks, which does not add an account to
+        // the environment so that the state of the world is left untouched.
+        let mut checkpointer = None;
+        let mut checkpointing = None;
+        if let Some((path, interval)) = self.checkpointing.clone() {
+            let environment = self.environment.as_ref().unwrap();
+            let writer = Checkpointer {
+                world: self.id.clone(),
+                path,
+                client: ArbiterMiddleware::new_from_forked_eoa(environment, Address::zero())?,
+                db: environment.db(),
+                checkpoints: self.messager.checkpoints.clone(),
+            };
+            let periodic = writer.clone();
+            checkpointing = Some(spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    if let Err(e) = periodic.write().await {
+                        warn!("Could not write a checkpoint: {}", e);
+                    }
+                }
+            }));
+            checkpointer = Some(writer);
+        }
+        let watching = self.config_watching.map(|interval| {
+            let reloader = self.messager.reloader();
+            spawn(async move {
+                let mut modified = reloader.modified();
+                loop {
+                    tokio::time::sleep(interval).await;
+                    let last = reloader.modified();
+                    if last == modified {
+                        continue;
+                    }
+                    modified = last;
+                    if let Err(e) = reloader.reload() {
+                        warn!("Could not reload the configuration: {}", e);
+                    }
+                }
+            })
+        });
+        // Spawn a task for each of the behavior engines, in the span of its
+        // agent so that the agent's log level applies to it.
+        for ((mut engine, client, messager), running) in engines.into_iter().zip(running) {
+            let id = messager.id.clone().unwrap_or_else(|| "unknown".to_owned());
+            let span = info_span!(AGENT_SPAN, world = self.id.as_str(), id = id.as_str());
+            let task = spawn(
+                async move {
+                    let _running = running;
                     engine
                         .execute(MachineInstruction::Start(client, messager))
                         .await
-                }));
-            }
+                }
+                .instrument(span),
+            );
+            self.despawner.register(id, task.abort_handle());
+            tasks.push(task);
         }
-        // Await the completion of all tasks.
+        // Await the completion of all tasks, including the ones of despawned
+        // agents, which are aborted.
         join_all(tasks).await;
+        self.messager.metrics.finish();
+        self.messager.journal.close()?;
+        self.despawner.running.lock().unwrap().clear();
+        for task in [checkpointing, watching, Some(interrupting)]
+            .into_iter()
+            .flatten()
+        {
+            task.abort();
+        }
+        if let Some(checkpointer) = checkpointer {
+            checkpointer.write().await?;
+        }
+        drop(final_checkpoint);
 
         let db = self.environment.take().unwrap().stop()?;
+        if self.messager.profiler.is_enabled() {
+            info!("{}", self.profile());
+        }
+        if let Some(path) = &self.metrics_output {
+            self.metrics().save(path)?;
+        }
+        drop(metrics_output);
+        if let Some(path) = &self.inputs_output {
+            self.inputs().save(path)?;
+        }
+        let dead_letters = self.dead_letters().len();
+        if dead_letters > 0 {
+            warn!(
+                "{} messages could not be delivered, see `World::dead_letters`",
+                dead_letters
+            );
+        }
         Ok(db)
     }
 }
+
+/// Removes the agent or sub-agent with the id `agent_id` from `agents` and
+/// returns whether it was found.
+fn remove_agent(agents: &mut HashMap<String, Agent>, agent_id: &str) -> bool {
+    if agents.remove(agent_id).is_some() {
+        return true;
+    }
+    agents
+        .values_mut()
+        .any(|agent| remove_agent(&mut agent.sub_agents, agent_id))
+}
+
+/// Parses a world configuration with its instances and templates expanded.
+pub(crate) fn expand_config(contents: &str) -> Result<toml::Table, ArbiterEngineError> {
+    expand_instances(toml::from_str(contents)?)
+        .and_then(expand_templates)
+        .map_err(ArbiterEngineError::WorldError)
+}
+
+/// Returns the behaviors of each agent of an expanded world configuration.
+pub(crate) fn agent_behaviors(config: &toml::Table) -> BTreeMap<String, Vec<toml::Value>> {
+    config
+        .iter()
+        .filter(|(key, _)| !WORLD_SETTINGS.contains(&key.as_str()))
+        .filter_map(|(agent, behaviors)| Some((agent.clone(), behaviors.as_array()?.clone())))
+        .collect()
+}
+
+/// The top level keys of a world configuration that configure the world
+/// rather than define an agent.
+const WORLD_SETTINGS: [&str; 11] = [
+    "id",
+    "transaction_gas_limit",
+    "transaction_gas_limits",
+    "groups",
+    "seed",
+    "metrics",
+    "message_log",
+    "message_capacity",
+    "scheduling",
+    "startup_barrier",
+    "logging",
+];
+
+/// A value of a world configuration that is read along with where each of the
+/// behaviors in it is configured.
+enum ConfigEntry {
+    /// A list of behaviors, e.g., of an agent or a template.
+    Behaviors(Vec<Spanned<toml::Value>>),
+    /// A table, e.g., of the templates.
+    Table(BTreeMap<String, ConfigEntry>),
+    /// Any other value.
+    Other,
+}
+
+impl<'de> Deserialize<'de> for ConfigEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntryVisitor;
+
+        impl<'de> Visitor<'de> for EntryVisitor {
+            type Value = ConfigEntry;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a TOML value")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<ConfigEntry, A::Error> {
+                let mut behaviors = vec![];
+                while let Some(behavior) = seq.next_element()? {
+                    behaviors.push(behavior);
+                }
+                Ok(ConfigEntry::Behaviors(behaviors))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<ConfigEntry, A::Error> {
+                let mut table = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry()? {
+                    table.insert(key, value);
+                }
+                Ok(ConfigEntry::Table(table))
+            }
+
+            fn visit_bool<E>(self, _: bool) -> Result<ConfigEntry, E> {
+                Ok(ConfigEntry::Other)
+            }
+
+            fn visit_i64<E>(self, _: i64) -> Result<ConfigEntry, E> {
+                Ok(ConfigEntry::Other)
+            }
+
+            fn visit_u64<E>(self, _: u64) -> Result<ConfigEntry, E> {
+                Ok(ConfigEntry::Other)
+            }
+
+            fn visit_f64<E>(self, _: f64) -> Result<ConfigEntry, E> {
+                Ok(ConfigEntry::Other)
+            }
+
+            fn visit_str<E>(self, _: &str) -> Result<ConfigEntry, E> {
+                Ok(ConfigEntry::Other)
+            }
+        }
+
+        deserializer.deserialize_any(EntryVisitor)
+    }
+}
+
+/// Checks the behaviors of the agents and templates of the configuration in
+/// `contents`, read from `path`, see [`World::validate_config`].
+fn validate<C: CreateStateMachine>(contents: &str, path: &str) -> Result<(), ArbiterEngineError> {
+    let entries: BTreeMap<String, ConfigEntry> = toml::from_str(contents)?;
+    let sets = match toml::from_str::<toml::Table>(contents)?.remove("sets") {
+        Some(toml::Value::Table(sets)) => sets,
+        _ => toml::Table::new(),
+    };
+    let mut errors = vec![];
+    for (key, entry) in &entries {
+        match entry {
+            ConfigEntry::Behaviors(behaviors) if !WORLD_SETTINGS.contains(&key.as_str()) => {
+                let owner = format!("agent `{}`", key);
+                errors.extend(validate_behaviors::<C>(
+                    contents, path, &sets, &owner, behaviors,
+                ));
+            }
+            ConfigEntry::Table(templates) if key == "templates" => {
+                for (name, template) in templates {
+                    if let ConfigEntry::Behaviors(behaviors) = template {
+                        let owner = format!("template `{}`", name);
+                        errors.extend(validate_behaviors::<C>(
+                            contents, path, &sets, &owner, behaviors,
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if !errors.is_empty() {
+        return Err(ArbiterEngineError::WorldError(errors.join("\n")));
+    }
+    Ok(())
+}
+
+/// Returns the errors in the configuration of the `behaviors` of `owner`.
+fn validate_behaviors<C: CreateStateMachine>(
+    contents: &str,
+    path: &str,
+    sets: &toml::Table,
+    owner: &str,
+    behaviors: &[Spanned<toml::Value>],
+) -> Vec<String> {
+    let names = C::behavior_names();
+    let mut errors = vec![];
+    for behavior in behaviors {
+        let toml::Value::Table(table) = behavior.get_ref() else {
+            continue;
+        };
+        // Every behavior is located at the start of its table, e.g., at the
+        // `[[agent]]` header of an array of tables.
+        let before = &contents[..behavior.span().start];
+        let line = before.matches('\n').count() + 1;
+        let column = before[before.rfind('\n').map_or(0, |i| i + 1)..]
+            .chars()
+            .count()
+            + 1;
+        for (name, config) in table {
+            if !names.is_empty() && !names.contains(&name.as_str()) {
+                errors.push(format!(
+                    "{}:{}:{}: {} has the unknown behavior `{}`, expected one of `{}`",
+                    path,
+                    line,
+                    column,
+                    owner,
+                    name,
+                    names.join("`, `")
+                ));
+                continue;
+            }
+            let instances = match instances(name, config.clone(), sets) {
+                Ok(instances) => instances,
+                Err(e) => {
+                    errors.push(format!("{}:{}:{}: {} {}", path, line, column, owner, e));
+                    continue;
+                }
+            };
+            let count = instances.len();
+            for (index, instance) in instances.into_iter().enumerate() {
+                if let Err(e) = C::validate_behavior(name, instance) {
+                    let behavior = match config.get("for_each") {
+                        Some(_) => format!("instance {}/{} of `{}`", index + 1, count, name),
+                        None => format!("`{}`", name),
+                    };
+                    errors.push(format!(
+                        "{}:{}:{}: behavior {} of {} is invalid: {}",
+                        path, line, column, behavior, owner, e
+                    ));
+                }
+            }
+        }
+    }
+    errors
+}
+
+/// Replaces every behavior of the agents and templates in the `config` that
+/// has a `for_each` key with its instances, and removes the `sets` they are
+/// instantiated for.
+fn expand_instances(mut config: toml::Table) -> Result<toml::Table, String> {
+    let sets = match config.remove("sets") {
+        Some(toml::Value::Table(sets)) => sets,
+        Some(_) => return Err("`sets` must be a table of parameter lists".to_owned()),
+        None => toml::Table::new(),
+    };
+    let mut lists = vec![];
+    for (key, value) in config.iter_mut() {
+        match value {
+            toml::Value::Array(behaviors) if !WORLD_SETTINGS.contains(&key.as_str()) => {
+                lists.push((format!("Agent `{}`", key), behaviors));
+            }
+            toml::Value::Table(templates) if key == "templates" => {
+                for (name, template) in templates.iter_mut() {
+                    if let toml::Value::Array(behaviors) = template {
+                        lists.push((format!("Template `{}`", name), behaviors));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for (owner, behaviors) in lists {
+        let mut expanded = vec![];
+        for behavior in behaviors.drain(..) {
+            let toml::Value::Table(behavior) = behavior else {
+                expanded.push(behavior);
+                continue;
+            };
+            for (name, config) in behavior {
+                for instance in
+                    instances(&name, config, &sets).map_err(|e| format!("{} {}", owner, e))?
+                {
+                    expanded.push(toml::Value::Table(toml::Table::from_iter([(
+                        name.clone(),
+                        instance,
+                    )])));
+                }
+            }
+        }
+        *behaviors = expanded;
+    }
+    Ok(config)
+}
+
+/// Returns the configurations of the instances of the behavior `name` that is
+/// configured with `config`, which is the configuration itself unless it has
+/// a `for_each` key.
+fn instances(
+    name: &str,
+    mut config: toml::Value,
+    sets: &toml::Table,
+) -> Result<Vec<toml::Value>, String> {
+    let entries = match config
+        .as_table_mut()
+        .and_then(|config| config.remove("for_each"))
+    {
+        None => return Ok(vec![config]),
+        Some(toml::Value::String(set)) => match sets.get(&set) {
+            Some(toml::Value::Array(entries)) => entries.clone(),
+            _ => {
+                return Err(format!(
+                    "instantiates `{}` for the unknown set `{}`",
+                    name, set
+                ))
+            }
+        },
+        Some(toml::Value::Array(entries)) => entries,
+        Some(_) => {
+            return Err(format!(
+                "instantiates `{}` for a `for_each` that is neither the name of a set nor a list",
+                name
+            ))
+        }
+    };
+    entries
+        .into_iter()
+        .map(|entry| {
+            if !entry.is_table() {
+                return Err(format!(
+                    "instantiates `{}` for a set whose entries are not tables",
+                    name
+                ));
+            }
+            let mut instance = config.clone();
+            merge(&mut instance, entry);
+            Ok(instance)
+        })
+        .collect()
+}
+
+/// Replaces every agent in the `config` that refers to a template with a copy
+/// of the template's behaviors that has the agent's overrides merged in.
+fn expand_templates(mut config: toml::Table) -> Result<toml::Table, String> {
+    let templates = match config.remove("templates") {
+        Some(toml::Value::Table(templates)) => templates,
+        Some(_) => return Err("`templates` must be a table of behavior lists".to_owned()),
+        None => return Ok(config),
+    };
+    for (agent, value) in config.iter_mut() {
+        if WORLD_SETTINGS.contains(&agent.as_str()) {
+            continue;
+        }
+        let toml::Value::Table(instance) = value else {
+            continue;
+        };
+        let mut instance = std::mem::take(instance);
+        let name = match instance.remove("template") {
+            Some(toml::Value::String(name)) => name,
+            _ => {
+                return Err(format!(
+                    "Agent `{}` must either be a list of behaviors or name a `template`",
+                    agent
+                ))
+            }
+        };
+        let Some(toml::Value::Array(mut behaviors)) = templates.get(&name).cloned() else {
+            return Err(format!(
+                "Agent `{}` uses the unknown template `{}`",
+                agent, name
+            ));
+        };
+        for (behavior_name, overrides) in instance {
+            let mut matched = false;
+            for behavior in behaviors.iter_mut() {
+                if let Some(parameters) = behavior
+                    .as_table_mut()
+                    .and_then(|behavior| behavior.get_mut(&behavior_name))
+                {
+                    merge(parameters, overrides.clone());
+                    matched = true;
+                }
+            }
+            if !matched {
+                return Err(format!(
+                    "Agent `{}` overrides `{}` which is not a behavior of template `{}`",
+                    agent, behavior_name, name
+                ));
+            }
+        }
+        *value = toml::Value::Array(behaviors);
+    }
+    Ok(config)
+}
+
+/// Recursively merges the tables in `overrides` into `base`, replacing any
+/// other values.
+fn merge(base: &mut toml::Value, overrides: toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Writes the checkpoints of a running [`World`].
+#[derive(Clone, Debug)]
+struct Checkpointer {
+    world: String,
+    path: PathBuf,
+    client: Arc<ArbiterMiddleware>,
+    db: ArbiterDB,
+    checkpoints: Checkpoints,
+}
+
+impl Checkpointer {
+    /// Writes a checkpoint of the world as it is now.
+    async fn write(&self) -> Result<(), ArbiterEngineError> {
+        let checkpoint = Checkpoint {
+            world: self.world.clone(),
+            block_number: self.client.get_block_number().await?.as_u64(),
+            block_timestamp: self.client.get_block_timestamp().await?.as_u64(),
+            behaviors: self.checkpoints.states(),
+            db: self.db.clone(),
+        };
+        checkpoint.save(&self.path)
+    }
+}
@@ -0,0 +1,730 @@
+use std::time::Duration;
+
+use arbiter_bindings::bindings::{
+    arbiter_token::{ArbiterToken, ARBITERTOKEN_ABI},
+    liquid_exchange::LiquidExchange,
+};
+use arbiter_core::environment::Environment;
+use arbiter_engine::{
+    agent::Agent,
+    behaviors::{
+        block_admin::{BlockAdmin, BlockAdminEvent, BlockAdminQuery, BlockInfo},
+        data_feed::{DataFeed, FeedUpdate},
+        liquidator::Liquidator,
+        price_changer::{PriceChanger, PriceProcess},
+        probabilistic::Probabilistic,
+        stablecoin_keeper::StablecoinKeeper,
+        token_admin::{
+            ApproveRequest, BurnRequest, MintLimit, MintRequest, TokenAdmin, TokenAdminQuery,
+            TokenAdminReply, TokenData,
+        },
+        tracker::{Tracker, TrackerEvent},
+    },
+    machine::{Engine, MachineInstruction, StateMachine},
+    random::{agent_seed, DEFAULT_SEED},
+    trigger::{Tick, Trigger},
+    world::World,
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, Log, U256},
+    utils::parse_ether,
+};
+use futures_util::StreamExt;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde_json::json;
+
+include!("common.rs");
+
+/// Runs a liquidator configured with `overrides` against a token that stands
+/// in for a lending contract: the amount of a transfer is the health factor of
+/// its recipient, and an account is liquidated by minting it 2 tokens. Returns
+/// the balances of two accounts that were minted 0.5 and 3 tokens.
+async fn run_liquidator(gas_price: u64, overrides: serde_json::Value) -> (U256, U256) {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("liquidator")).unwrap();
+    client
+        .set_balance(client.address(), parse_ether(1000).unwrap())
+        .await
+        .unwrap();
+    client.set_gas_price(gas_price.into()).await.unwrap();
+    let token = ArbiterToken::deploy(
+        client.clone(),
+        ("Collateral".to_owned(), "COL".to_owned(), 18u8),
+    )
+    .unwrap()
+    .send()
+    .await
+    .unwrap();
+
+    let abi = std::env::temp_dir().join(format!("liquidator_{}.json", token.address()));
+    serde_json::to_writer(std::fs::File::create(&abi).unwrap(), &*ARBITERTOKEN_ABI).unwrap();
+    let mut config = json!({
+        "lending": token.address(),
+        "abi": abi,
+        "health_event": "Transfer",
+        "account_param": "to",
+        "health_factor_param": "amount",
+        "liquidate_function": "mint",
+        "liquidate_args": [{ "Param": "to" }, { "Uint": parse_ether(2).unwrap() }],
+    });
+    config
+        .as_object_mut()
+        .unwrap()
+        .extend(overrides.as_object().unwrap().clone());
+    let liquidator: Liquidator = serde_json::from_value(config).unwrap();
+
+    let engine_client = client.clone();
+    let engine = tokio::spawn(async move {
+        let mut engine: Engine<_, Log> = Engine::new(liquidator);
+        engine
+            .execute(MachineInstruction::Start(engine_client, Messager::new()))
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let (alice, bob) = (Address::random(), Address::random());
+    for (account, amount) in [(alice, parse_ether(0.5)), (bob, parse_ether(3))] {
+        token
+            .mint(account, amount.unwrap())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    engine.abort();
+    std::fs::remove_file(abi).unwrap();
+    (
+        token.balance_of(alice).call().await.unwrap(),
+        token.balance_of(bob).call().await.unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn liquidator_liquidates_unhealthy_accounts() {
+    let liquidated = (parse_ether(2.5).unwrap(), parse_ether(3).unwrap());
+    let spared = (parse_ether(0.5).unwrap(), parse_ether(3).unwrap());
+
+    assert_eq!(run_liquidator(0, json!({})).await, liquidated);
+
+    // The profit of liquidating the account is its balance before.
+    let profitable = json!({
+        "profit_function": "balanceOf",
+        "min_profit": parse_ether(0.1).unwrap(),
+    });
+    assert_eq!(run_liquidator(0, profitable).await, liquidated);
+    let unprofitable = json!({
+        "profit_function": "balanceOf",
+        "min_profit": parse_ether(1).unwrap(),
+    });
+    assert_eq!(run_liquidator(0, unprofitable).await, spared);
+
+    let cheap = json!({ "max_gas_cost": parse_ether(0.01).unwrap() });
+    assert_eq!(run_liquidator(1, cheap).await, liquidated);
+    let expensive = json!({ "max_gas_cost": U256::from(1000) });
+    assert_eq!(run_liquidator(1, expensive).await, spared);
+}
+
+/// Runs a price changer of the price of a `LiquidExchange` for 5 steps of
+/// `process` and returns the prices it set, starting at 1000.
+async fn run_price_changer(process: PriceProcess) -> Vec<U256> {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("oracle")).unwrap();
+    let exchange = LiquidExchange::deploy(
+        client.clone(),
+        (Address::random(), Address::random(), U256::zero()),
+    )
+    .unwrap()
+    .send()
+    .await
+    .unwrap();
+    let price_change = exchange.price_change_filter();
+    let price_changes = price_change.stream().await.unwrap();
+
+    let price_changer: PriceChanger = serde_json::from_value(json!({
+        "target": exchange.address(),
+        "process": process,
+        "initial_price": 1000.0,
+        "time_step": 0.1,
+        "trigger": Trigger::Interval(Duration::from_millis(1)),
+        "steps": 5,
+    }))
+    .unwrap();
+    let mut engine: Engine<_, Tick> = Engine::new(price_changer);
+    let messager = Messager::new().for_agent("oracle");
+    engine
+        .execute(MachineInstruction::Start(client, messager))
+        .await
+        .unwrap();
+    let prices = price_changes
+        .take(6)
+        .map(|change| change.unwrap().price)
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(exchange.price().call().await.unwrap(), prices[5]);
+    prices
+}
+
+#[tokio::test]
+async fn price_changer_follows_process() {
+    let process = PriceProcess::Gbm {
+        drift: 0.5,
+        volatility: 0.2,
+    };
+    let prices = run_price_changer(process).await;
+    assert_eq!(prices, run_price_changer(process).await);
+
+    // The price path is drawn from the random number generator of the agent.
+    let mut rng = StdRng::seed_from_u64(agent_seed(DEFAULT_SEED, "oracle"));
+    let mut price = 1000.0;
+    let mut expected = vec![parse_ether(1000).unwrap()];
+    for _ in 0..5 {
+        price = process.step(price, 0.1, &mut rng);
+        expected.push(parse_ether(format!("{:.18}", price)).unwrap());
+    }
+    assert_eq!(prices, expected);
+
+    // Without noise, the Ornstein-Uhlenbeck process reverts to its mean.
+    let reverting = PriceProcess::OrnsteinUhlenbeck {
+        mean_reversion: 10.0,
+        mean: 1.0,
+        volatility: 0.0,
+    };
+    let prices = run_price_changer(reverting).await;
+    assert!(prices.windows(2).all(|pair| pair[1] < pair[0]));
+    assert!(prices[5] < parse_ether(10).unwrap());
+}
+
+/// Writes `contents` to a CSV file named after `name` and returns a data feed
+/// of the file configured with `overrides`.
+fn data_feed(name: &str, contents: &str, overrides: serde_json::Value) -> DataFeed {
+    let path = std::env::temp_dir().join(format!("data_feed_{}.csv", name));
+    std::fs::write(&path, contents).unwrap();
+    let mut config = json!({
+        "path": path,
+        "trigger": Trigger::Interval(Duration::from_millis(1)),
+    });
+    config
+        .as_object_mut()
+        .unwrap()
+        .extend(overrides.as_object().unwrap().clone());
+    serde_json::from_value(config).unwrap()
+}
+
+#[tokio::test]
+async fn data_feed_sends_rows() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("feed")).unwrap();
+    let feed = data_feed(
+        "rows",
+        "timestamp,open,close\n100,1.0,1.5\n200,1.5,2.5\n\n50,2.5,0.5\n",
+        json!({ "column": "close" }),
+    );
+    let messager = Messager::new();
+    let updates = messager
+        .for_agent("trader")
+        .payloads::<FeedUpdate>()
+        .unwrap();
+    let mut engine: Engine<_, Tick> = Engine::new(feed);
+    engine
+        .execute(MachineInstruction::Start(
+            client,
+            messager.for_agent("feed"),
+        ))
+        .await
+        .unwrap();
+
+    // One row is sent per tick, in the order of the file.
+    let updates: Vec<_> = updates.take(3).map(|update| update.data).collect().await;
+    let update = |timestamp, value| FeedUpdate {
+        feed: "close".to_owned(),
+        timestamp,
+        value,
+    };
+    assert_eq!(
+        updates,
+        [update(100, 1.5), update(200, 2.5), update(50, 0.5)]
+    );
+}
+
+#[tokio::test]
+async fn data_feed_follows_block_time() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("feed")).unwrap();
+    let exchange = LiquidExchange::deploy(
+        client.clone(),
+        (Address::random(), Address::random(), U256::zero()),
+    )
+    .unwrap()
+    .send()
+    .await
+    .unwrap();
+    let price_change = exchange.price_change_filter();
+    let mut price_changes = price_change.stream().await.unwrap();
+
+    let start = client.get_block_timestamp().await.unwrap().as_u64();
+    let rows = format!(
+        "timestamp,value\n{},10\n{},20\n{},30\n{},40\n",
+        start,
+        start + 10,
+        start + 20,
+        start + 30
+    );
+    let overrides = json!({
+        "schedule": "Timestamps",
+        "target": { "Contract": { "address": exchange.address() } },
+    });
+    let feed = data_feed("blocks", &rows, overrides);
+    let feed_client = client.clone();
+    let engine = tokio::spawn(async move {
+        let mut engine: Engine<_, Tick> = Engine::new(feed);
+        engine
+            .execute(MachineInstruction::Start(
+                feed_client,
+                Messager::new().for_agent("feed"),
+            ))
+            .await
+    });
+
+    // The row of the current block is set on startup, and the rows of a block
+    // whose timestamp passed several of them are all set in order.
+    client.update_block(1, start + 25).unwrap();
+    let prices: Vec<_> = price_changes
+        .by_ref()
+        .take(3)
+        .map(|change| change.unwrap().price)
+        .collect()
+        .await;
+    assert_eq!(
+        prices,
+        [10, 20, 30].map(|price| parse_ether(price).unwrap())
+    );
+    assert_eq!(
+        exchange.price().call().await.unwrap(),
+        parse_ether(30).unwrap()
+    );
+    engine.abort();
+
+    // A file that cannot be read fails the startup along with the line.
+    let mut unsorted = data_feed(
+        "unsorted",
+        "timestamp,value\n2,1\n1,1\n",
+        json!({ "schedule": "Timestamps" }),
+    );
+    let Err(error) = unsorted
+        .startup(client, Messager::new().for_agent("feed"))
+        .await
+    else {
+        panic!("the startup did not fail");
+    };
+    assert!(error
+        .to_string()
+        .contains("data_feed_unsorted.csv:3: the rows are not sorted by their timestamps"));
+}
+
+#[tokio::test]
+async fn stablecoin_keeper_defends_peg() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("keeper")).unwrap();
+    let deploy = |name: &str| {
+        ArbiterToken::deploy(client.clone(), (name.to_owned(), name.to_owned(), 18u8))
+            .unwrap()
+            .send()
+    };
+    let stablecoin = deploy("USD").await.unwrap();
+    let collateral = deploy("COL").await.unwrap();
+    let exchange = LiquidExchange::deploy(
+        client.clone(),
+        (
+            stablecoin.address(),
+            collateral.address(),
+            parse_ether(1).unwrap(),
+        ),
+    )
+    .unwrap()
+    .send()
+    .await
+    .unwrap();
+    for token in [&stablecoin, &collateral] {
+        token
+            .mint(exchange.address(), parse_ether(1000).unwrap())
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+    collateral
+        .mint(client.address(), parse_ether(100).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    let swaps = exchange.swap_filter();
+    let mut swaps = swaps.stream().await.unwrap();
+
+    // The stablecoin is minted to the keeper and redeemed by burning it.
+    let keeper: StablecoinKeeper = serde_json::from_value(json!({
+        "stablecoin": stablecoin.address(),
+        "price": { "Oracle": { "address": exchange.address() } },
+        "amount": parse_ether(10).unwrap(),
+        "trigger": Trigger::Blocks(1),
+        "mint": { "function": "mint", "args": ["Keeper", "Amount"] },
+        "redeem": {
+            "function": "transfer",
+            "args": [{ "Address": Address::zero() }, "Amount"],
+        },
+        "arbitrage": { "exchange": exchange.address(), "collateral": collateral.address() },
+    }))
+    .unwrap();
+    let keeper_client = client.clone();
+    let engine = tokio::spawn(async move {
+        let mut engine: Engine<_, Tick> = Engine::new(keeper);
+        engine
+            .execute(MachineInstruction::Start(
+                keeper_client,
+                Messager::new().for_agent("keeper"),
+            ))
+            .await
+    });
+    let balance = |token: &ArbiterToken<ArbiterMiddleware>| token.balance_of(client.address());
+    // Lets the keeper approve the exchange before the blocks are produced.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // Above the peg, the keeper mints and sells the stablecoin.
+    exchange
+        .set_price(parse_ether(1.02).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.update_block(1, 1).unwrap();
+    let swap = swaps.next().await.unwrap().unwrap();
+    assert_eq!(swap.token_in, stablecoin.address());
+    assert_eq!(swap.amount_out, parse_ether(10.2).unwrap());
+    assert_eq!(balance(&stablecoin).call().await.unwrap(), U256::zero());
+
+    // Below the peg, the keeper buys the stablecoin with collateral and
+    // redeems it.
+    exchange
+        .set_price(parse_ether(0.97).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    client.update_block(2, 2).unwrap();
+    let swap = swaps.next().await.unwrap().unwrap();
+    assert_eq!(swap.token_in, collateral.address());
+    assert_eq!(swap.amount_in, parse_ether(9.7).unwrap());
+    assert_eq!(swap.amount_out, parse_ether(10).unwrap());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(balance(&stablecoin).call().await.unwrap(), U256::zero());
+    assert_eq!(
+        balance(&collateral).call().await.unwrap(),
+        parse_ether(100.5).unwrap()
+    );
+    assert_eq!(
+        stablecoin.total_supply().call().await.unwrap(),
+        parse_ether(1010).unwrap()
+    );
+    engine.abort();
+}
+
+#[tokio::test]
+async fn block_admin_mines_blocks() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("block_admin")).unwrap();
+    let start = client.get_block_timestamp().await.unwrap().as_u64();
+
+    // On an interval, until it mined as many blocks as it may.
+    let admin = BlockAdmin::new(12)
+        .with_interval(Duration::from_millis(1))
+        .with_max_blocks(3);
+    let mut engine: Engine<_, BlockAdminEvent> = Engine::new(admin);
+    engine
+        .execute(MachineInstruction::Start(client.clone(), Messager::new()))
+        .await
+        .unwrap();
+    assert_eq!(client.get_block_number().await.unwrap().as_u64(), 3);
+    assert_eq!(
+        client.get_block_timestamp().await.unwrap().as_u64(),
+        start + 36
+    );
+
+    // On demand, replying with the current block.
+    let messager = Messager::new();
+    let admin_messager = messager.for_agent("block_admin");
+    let tester = messager.for_agent("tester");
+    let mut replies = tester.clone().stream().unwrap();
+    let admin_client = client.clone();
+    let admin = tokio::spawn(async move {
+        let mut engine: Engine<_, BlockAdminEvent> = Engine::new(BlockAdmin::new(1));
+        engine
+            .execute(MachineInstruction::Start(admin_client, admin_messager))
+            .await
+    });
+    // Lets the block admin start listening before it is sent queries.
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let to = To::Agent("block_admin".to_owned());
+    tester
+        .send(to.clone(), BlockAdminQuery::Mine(5))
+        .await
+        .unwrap();
+    tester.send(to, BlockAdminQuery::Current).await.unwrap();
+    let mut blocks = vec![];
+    while blocks.len() < 2 {
+        let reply = replies.next().await.unwrap();
+        if reply.from == "block_admin" {
+            blocks.push(serde_json::from_str::<BlockInfo>(&reply.data).unwrap());
+        }
+    }
+    let expected = BlockInfo {
+        number: 8,
+        timestamp: start + 41,
+    };
+    assert_eq!(blocks, [expected, expected]);
+    assert_eq!(client.get_block_number().await.unwrap().as_u64(), 8);
+    admin.abort();
+}
+
+#[tokio::test]
+async fn tracker_writes_pnl() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("admin")).unwrap();
+    let alice = ArbiterMiddleware::new(&environment, Some("alice")).unwrap();
+    let token = ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    let bob = Address::random();
+    token
+        .mint(alice.address(), parse_ether(10).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let output = std::env::temp_dir().join(format!("tracker_{}.csv", token.address()));
+    let tracker: Tracker = serde_json::from_value(json!({
+        "tokens": [{ "name": "TKN", "address": token.address(), "price": { "Fixed": 2.0 } }],
+        "accounts": { "alice": alice.address(), "bob": bob },
+        "output": output,
+    }))
+    .unwrap();
+    let tracker_client = ArbiterMiddleware::new(&environment, Some("tracker")).unwrap();
+    let engine = tokio::spawn(async move {
+        let mut engine: Engine<_, TrackerEvent> = Engine::new(tracker);
+        engine
+            .execute(MachineInstruction::Start(tracker_client, Messager::new()))
+            .await
+    });
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    ArbiterToken::new(token.address(), alice.clone())
+        .transfer(bob, parse_ether(4).unwrap())
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    engine.abort();
+
+    let csv = std::fs::read_to_string(&output).unwrap();
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows[0], "block_number,account,TKN,value,pnl");
+    assert_eq!(&rows[1..3], ["0,alice,10,20,0", "0,bob,0,0,0"]);
+    assert_eq!(&rows[3..], ["0,alice,6,12,-8", "0,bob,4,8,8"]);
+}
+
+/// Sends `query` to the token admin and returns what it replied.
+async fn ask_admin(
+    tester: &Messager,
+    replies: &mut EventStream<Message>,
+    query: TokenAdminQuery,
+) -> String {
+    tester
+        .send(To::Agent("admin".to_owned()), query)
+        .await
+        .unwrap();
+    loop {
+        let reply = replies.next().await.unwrap();
+        if reply.from == "admin" {
+            return reply.data;
+        }
+    }
+}
+
+#[tokio::test]
+async fn token_admin_serves_requests() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("admin")).unwrap();
+    let (alice, bob) = (Address::random(), Address::random());
+    let admin = TokenAdmin::new()
+        .with_token(
+            "TKN",
+            TokenData {
+                name: "Token".to_owned(),
+                symbol: "TKN".to_owned(),
+                decimals: 18,
+                address: None,
+            },
+        )
+        .with_airdrop(&format!("{:?}", alice), "TKN", U256::from(100))
+        .with_mint_limit(MintLimit {
+            amount: U256::from(50),
+            period: Some(12),
+        });
+
+    let messager = Messager::new();
+    let admin_messager = messager.for_agent("admin");
+    let tester = messager.for_agent("tester");
+    let mut replies = tester.clone().stream().unwrap();
+    let admin_client = client.clone();
+    let engine = tokio::spawn(async move {
+        let mut engine: Engine<_, Message> = Engine::new(admin);
+        engine
+            .execute(MachineInstruction::Start(admin_client, admin_messager))
+            .await
+    });
+    // Lets the token admin deploy the token before it is sent queries.
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let address: Option<Address> = serde_json::from_str(
+        &ask_admin(
+            &tester,
+            &mut replies,
+            TokenAdminQuery::AddressOf("TKN".to_owned()),
+        )
+        .await,
+    )
+    .unwrap();
+    let token = ArbiterToken::new(address.unwrap(), client.clone());
+    assert_eq!(
+        token.balance_of(alice).call().await.unwrap(),
+        U256::from(100)
+    );
+
+    // Mints are limited per period of block time.
+    let mint = |amount: u64| {
+        TokenAdminQuery::MintRequest(MintRequest {
+            token: "TKN".to_owned(),
+            mint_to: bob,
+            mint_amount: U256::from(amount),
+        })
+    };
+    let done = serde_json::to_string(&TokenAdminReply::Done).unwrap();
+    assert_eq!(ask_admin(&tester, &mut replies, mint(30)).await, done);
+    let limited = TokenAdminReply::RateLimited {
+        remaining: U256::from(20),
+    };
+    assert_eq!(
+        ask_admin(&tester, &mut replies, mint(30)).await,
+        serde_json::to_string(&limited).unwrap()
+    );
+    client.update_block(1, 1_000_000).unwrap();
+    assert_eq!(ask_admin(&tester, &mut replies, mint(30)).await, done);
+    assert_eq!(token.balance_of(bob).call().await.unwrap(), U256::from(60));
+
+    // Burns and approvals are sent on behalf of the holder.
+    let burn = TokenAdminQuery::BurnRequest(BurnRequest {
+        token: "TKN".to_owned(),
+        burn_from: alice,
+        burn_amount: U256::from(40),
+    });
+    assert_eq!(ask_admin(&tester, &mut replies, burn).await, done);
+    assert_eq!(
+        token.balance_of(alice).call().await.unwrap(),
+        U256::from(60)
+    );
+    let approve = TokenAdminQuery::ApproveRequest(ApproveRequest {
+        token: "TKN".to_owned(),
+        owner: alice,
+        spender: bob,
+        amount: U256::from(10),
+    });
+    assert_eq!(ask_admin(&tester, &mut replies, approve).await, done);
+    assert_eq!(
+        token.allowance(alice, bob).call().await.unwrap(),
+        U256::from(10)
+    );
+
+    let overdrawn = TokenAdminQuery::BurnRequest(BurnRequest {
+        token: "TKN".to_owned(),
+        burn_from: bob,
+        burn_amount: U256::from(1000),
+    });
+    let failed: TokenAdminReply =
+        serde_json::from_str(&ask_admin(&tester, &mut replies, overdrawn).await).unwrap();
+    assert!(matches!(failed, TokenAdminReply::Failed(_)));
+    engine.abort();
+}
+
+/// Replies to each of the numbers from 0 to 49 with the number.
+#[derive(Debug, Serialize, Deserialize)]
+struct Echo {
+    #[serde(skip)]
+    messager: Option<Messager>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Echo {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        self.messager = Some(messager);
+        Ok(Some(Box::pin(futures_util::stream::iter(0..50))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        let messager = self.messager.as_ref().unwrap();
+        messager.send(To::All, event).await?;
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn probabilistic_processes_some_events() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("noisy")).unwrap();
+    let messager = Messager::new().for_agent("noisy");
+    let mut replies = messager.clone().stream().unwrap();
+    let noisy = Probabilistic::new(0.3, Echo { messager: None });
+    let mut engine: Engine<_, u64> = Engine::new(noisy);
+    engine
+        .execute(MachineInstruction::Start(client, messager))
+        .await
+        .unwrap();
+
+    // Whether an event is processed is drawn from the generator of the agent.
+    let mut rng = StdRng::seed_from_u64(agent_seed(DEFAULT_SEED, "noisy"));
+    let expected: Vec<u64> = (0..50).filter(|_| rng.gen_bool(0.3)).collect();
+    let mut processed = vec![];
+    while processed.len() < expected.len() {
+        let reply = replies.next().await.unwrap();
+        processed.push(serde_json::from_str::<u64>(&reply.data).unwrap());
+    }
+    assert_eq!(processed, expected);
+    assert!(!expected.is_empty() && expected.len() < 50);
+}
+
+#[tokio::test]
+async fn probabilistic_is_named_after_its_behavior() {
+    let mut world = World::new("probabilistic");
+    let noisy = Probabilistic::new(0.3, Echo { messager: None });
+    world.add_agent(Agent::builder("noisy").with_behavior(noisy));
+    world.run().await.unwrap();
+
+    // The metrics are keyed by the names of the types without their paths.
+    let behaviors: Vec<_> = world.metrics().behaviors.into_keys().collect();
+    assert_eq!(behaviors, ["noisy/Probabilistic<Echo>"]);
+}
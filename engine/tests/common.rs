@@ -3,7 +3,8 @@ use std::sync::Arc;
 use anyhow::Result;
 use arbiter_core::middleware::ArbiterMiddleware;
 use arbiter_engine::{
-    machine::{Behavior, ControlFlow, EventStream},
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream, HaltReason},
     messager::{Message, Messager, To},
 };
 use serde::{Deserialize, Serialize};
@@ -67,14 +68,14 @@ impl Behavior<Message> for TimedMessage {
         Ok(Some(messager.stream()?))
     }
 
-    async fn process(&mut self, event: Message) -> Result<ControlFlow> {
+    async fn process(&mut self, event: Message) -> Result<ControlFlow, BehaviorError> {
         if event.data == serde_json::to_string(&self.receive_data).unwrap() {
             let messager = self.messager.clone().unwrap();
             messager.send(To::All, self.send_data.clone()).await?;
             self.count += 1;
         }
         if self.count == self.max_count.unwrap_or(u64::MAX) {
-            return Ok(ControlFlow::Halt);
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(self.count)));
         }
         Ok(ControlFlow::Continue)
     }
@@ -1,4 +1,34 @@
-use arbiter_engine::{agent::Agent, world::World};
+use std::{collections::HashMap, time::Duration};
+
+use arbiter_bindings::bindings::arbiter_token::ArbiterToken;
+use arbiter_core::{
+    environment::Environment,
+    middleware::client::{self, ArbiterClient},
+    rpc::RpcServer,
+};
+use arbiter_engine::{
+    agent::Agent,
+    checkpoint::Checkpoint,
+    dependencies::Barrier,
+    errors::ErrorEvent,
+    machine::{Backpressure, Engine, EventStreams, MachineInstruction, StateMachine, Supervision},
+    messager::{Payload, Priority, DEFAULT_MESSAGE_CAPACITY},
+    metrics::{BehaviorMetrics, GroupMetrics, WorldMetrics},
+    random::{agent_seed, DEFAULT_SEED},
+    relay::Relay,
+    remote::{RemoteMessage, RemoteServer},
+    replay::InputLog,
+    scheduler::Scheduling,
+    trigger::{Tick, Trigger},
+    world::World,
+};
+use ethers::{
+    providers::Middleware,
+    signers::{LocalWallet, Signer},
+    types::{Address, U256},
+    utils::get_contract_address,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 include!("common.rs");
 
@@ -26,3 +56,2082 @@ async fn behavior_no_stream() {
 
     world.run().await.unwrap();
 }
+
+/// Deploys a token and mints some of it to a recipient with any client.
+#[derive(Debug, Deserialize, Serialize)]
+struct Minter {
+    recipient: Address,
+}
+
+#[async_trait::async_trait]
+impl<M: ArbiterClient> Behavior<(), M> for Minter {
+    async fn startup(
+        &mut self,
+        client: Arc<M>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        let token =
+            ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))?
+                .legacy()
+                .send()
+                .await?;
+        token
+            .mint(self.recipient, U256::from(100))
+            .legacy()
+            .send()
+            .await?
+            .await?;
+        Ok(None)
+    }
+}
+
+async fn run_minter<M: ArbiterClient>(client: Arc<M>, recipient: Address) -> Address {
+    let token = get_contract_address(client.address(), 0);
+    let mut engine: Engine<_, (), M> = Engine::new(Minter { recipient });
+    engine
+        .execute(MachineInstruction::Start(client, Messager::new()))
+        .await
+        .unwrap();
+    token
+}
+
+#[tokio::test]
+async fn behavior_runs_against_any_client() {
+    let recipient = Address::from_low_u64_be(1);
+
+    // In a simulation.
+    let environment = Environment::builder().build();
+    let simulated = ArbiterMiddleware::new(&environment, Some("minter")).unwrap();
+    let token = run_minter(simulated.clone(), recipient).await;
+    let token = ArbiterToken::new(token, simulated.clone());
+    assert_eq!(
+        token.balance_of(recipient).call().await.unwrap(),
+        100.into()
+    );
+
+    // Against a node, which is an environment served over JSON-RPC here.
+    let node = Environment::builder().build();
+    let server = RpcServer::serve(&node, "127.0.0.1:0").await.unwrap();
+    let wallet: LocalWallet = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+        .parse()
+        .unwrap();
+    let admin = ArbiterMiddleware::new(&node, Some("admin")).unwrap();
+    admin
+        .set_balance(wallet.address(), U256::exp10(18))
+        .await
+        .unwrap();
+    let live = client::connect(&format!("ws://{}", server.local_addr()), wallet)
+        .await
+        .unwrap();
+    let token = run_minter(live, recipient).await;
+    let token = ArbiterToken::new(token, admin);
+    assert_eq!(
+        token.balance_of(recipient).call().await.unwrap(),
+        100.into()
+    );
+}
+
+/// Records the ticks of a trigger until it has seen `count` of them.
+#[derive(Debug, Deserialize, Serialize)]
+struct Ticker {
+    trigger: Trigger,
+    count: usize,
+    #[serde(skip)]
+    ticks: Arc<std::sync::Mutex<Vec<Tick>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Tick> for Ticker {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        Ok(Some(self.trigger.stream(client)))
+    }
+
+    async fn process(&mut self, tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        let mut ticks = self.ticks.lock().unwrap();
+        ticks.push(tick);
+        if ticks.len() == self.count {
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(self.count as u64)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+async fn run_ticker(trigger: Trigger, client: Arc<ArbiterMiddleware>) -> Vec<Tick> {
+    let ticks = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut engine: Engine<_, Tick> = Engine::new(Ticker {
+        trigger,
+        count: 3,
+        ticks: ticks.clone(),
+    });
+    engine
+        .execute(MachineInstruction::Start(client, Messager::new()))
+        .await
+        .unwrap();
+    let ticks = ticks.lock().unwrap().clone();
+    ticks
+}
+
+#[tokio::test]
+async fn behavior_triggered_by_blocks() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("ticker")).unwrap();
+
+    let producer = client.clone();
+    let blocks = tokio::spawn(async move {
+        for block_number in 1.. {
+            tokio::time::sleep(Duration::from_millis(25)).await;
+            if producer.update_block(block_number, block_number).is_err() {
+                break;
+            }
+        }
+    });
+    let ticks = run_ticker(Trigger::Blocks(2), client).await;
+    blocks.abort();
+
+    assert_eq!(
+        ticks.iter().map(|tick| tick.index).collect::<Vec<_>>(),
+        [0, 1, 2]
+    );
+    let mut last = 0;
+    for tick in ticks {
+        assert!(tick.block_number >= last + 2);
+        last = tick.block_number;
+    }
+}
+
+#[tokio::test]
+async fn behavior_triggered_by_interval() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("ticker")).unwrap();
+
+    let start = std::time::Instant::now();
+    let ticks = run_ticker(Trigger::Interval(Duration::from_millis(20)), client).await;
+    assert!(start.elapsed() >= Duration::from_millis(60));
+    assert_eq!(
+        ticks,
+        (0..3)
+            .map(|index| Tick {
+                index,
+                block_number: 0
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn typed_event_stream() {
+    use arbiter_bindings::bindings::arbiter_token::TransferFilter;
+    use arbiter_engine::events::event_stream;
+    use futures_util::StreamExt;
+
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("watcher")).unwrap();
+    let token = ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    let other = ArbiterToken::deploy(client.clone(), ("Other".to_owned(), "OTH".to_owned(), 18u8))
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    let transfers = event_stream::<TransferFilter>(&token).await.unwrap();
+
+    let recipient = Address::from_low_u64_be(100);
+    token
+        .mint(client.address(), U256::from(10))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    // Neither approvals nor the transfers of another token are streamed.
+    token
+        .approve(recipient, U256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    other
+        .mint(client.address(), U256::from(10))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    token
+        .transfer(recipient, U256::from(3))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let transfers: Vec<TransferFilter> = transfers.take(2).collect().await;
+    assert_eq!(
+        transfers,
+        [
+            TransferFilter {
+                from: Address::zero(),
+                to: client.address(),
+                amount: U256::from(10),
+            },
+            TransferFilter {
+                from: client.address(),
+                to: recipient,
+                amount: U256::from(3),
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn merged_contract_events() {
+    use arbiter_bindings::bindings::arbiter_token::ARBITERTOKEN_ABI;
+    use arbiter_engine::events::{contract_event_stream, WatchedContract};
+    use ethers::abi::Token;
+    use futures_util::StreamExt;
+
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("analyst")).unwrap();
+    let token = ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    let other = ArbiterToken::deploy(client.clone(), ("Other".to_owned(), "OTH".to_owned(), 18u8))
+        .unwrap()
+        .send()
+        .await
+        .unwrap();
+    let abi = std::env::temp_dir().join(format!("merged_{}.json", token.address()));
+    serde_json::to_writer(std::fs::File::create(&abi).unwrap(), &*ARBITERTOKEN_ABI).unwrap();
+    let contracts = [
+        WatchedContract {
+            name: "token".to_owned(),
+            address: token.address(),
+            abi: abi.clone(),
+            events: vec!["Transfer".to_owned()],
+        },
+        WatchedContract {
+            name: "other".to_owned(),
+            address: other.address(),
+            abi: abi.clone(),
+            events: vec![],
+        },
+    ];
+    let events = contract_event_stream(client.clone(), &contracts)
+        .await
+        .unwrap();
+
+    let recipient = Address::from_low_u64_be(100);
+    token
+        .mint(client.address(), U256::from(10))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    // Only the transfers of the token are watched, but every event of the
+    // other token.
+    token
+        .approve(recipient, U256::from(1))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    other
+        .approve(recipient, U256::from(2))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+    token
+        .transfer(recipient, U256::from(3))
+        .send()
+        .await
+        .unwrap()
+        .await
+        .unwrap();
+
+    let events: Vec<_> = events.take(3).collect().await;
+    std::fs::remove_file(abi).unwrap();
+    let tagged: Vec<_> = events
+        .iter()
+        .map(|event| (event.contract.as_str(), event.event.as_str()))
+        .collect();
+    assert_eq!(
+        tagged,
+        [
+            ("token", "Transfer"),
+            ("other", "Approval"),
+            ("token", "Transfer")
+        ]
+    );
+    assert_eq!(events[1].param("amount"), Some(&Token::Uint(U256::from(2))));
+    assert_eq!(events[2].log.address, token.address());
+}
+
+/// The events processed by each [`Flaky`] behavior, by name, which survive the
+/// restarts of a behavior.
+static PROCESSED: std::sync::Mutex<Vec<(String, u64)>> = std::sync::Mutex::new(Vec::new());
+
+/// Processes `events` and fails to process `fail_on` the first `fail_times`
+/// times it sees it.
+#[derive(Debug, Deserialize, Serialize)]
+struct Flaky {
+    name: String,
+    events: Vec<u64>,
+    fail_on: u64,
+    fail_times: u32,
+    panic: bool,
+    supervision: Supervision,
+    #[serde(default)]
+    failed: u32,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Flaky {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        Ok(Some(Box::pin(futures_util::stream::iter(
+            self.events.clone(),
+        ))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        if event == self.fail_on && self.failed < self.fail_times {
+            self.failed += 1;
+            if self.panic {
+                panic!("failed to process {}", event);
+            }
+            return Err(BehaviorError::msg(format!("failed to process {}", event)));
+        }
+        PROCESSED.lock().unwrap().push((self.name.clone(), event));
+        Ok(ControlFlow::Continue)
+    }
+
+    fn supervision(&self) -> Supervision {
+        self.supervision
+    }
+}
+
+async fn run_flaky(name: &str, fail_times: u32, panic: bool, supervision: Supervision) -> Vec<u64> {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some(name)).unwrap();
+    let mut engine: Engine<_, u64> = Engine::new(Flaky {
+        name: name.to_owned(),
+        events: vec![1, 2, 3],
+        fail_on: 2,
+        fail_times,
+        panic,
+        supervision,
+        failed: 0,
+    });
+    engine
+        .execute(MachineInstruction::Start(client, Messager::new()))
+        .await
+        .unwrap();
+    PROCESSED
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(processed_by, _)| processed_by == name)
+        .map(|(_, event)| *event)
+        .collect()
+}
+
+#[tokio::test]
+async fn supervision_policies() {
+    // A behavior halts on its first error by default.
+    assert_eq!(run_flaky("halt", 1, false, Supervision::Halt).await, [1]);
+    assert_eq!(run_flaky("skip", 1, true, Supervision::Skip).await, [1, 3]);
+
+    let retry = |attempts| Supervision::Retry {
+        attempts,
+        backoff: Duration::from_millis(1),
+    };
+    assert_eq!(run_flaky("retry", 2, false, retry(2)).await, [1, 2, 3]);
+    assert_eq!(run_flaky("retry_exhausted", 3, false, retry(2)).await, [1]);
+
+    // A restarted behavior starts over from its configuration, so it fails
+    // again until it runs out of restarts.
+    let restart = Supervision::Restart { attempts: 2 };
+    assert_eq!(run_flaky("restart", 1, true, restart).await, [1, 1, 1]);
+}
+
+/// Fails to swap every event with a cause, and skips it.
+#[derive(Debug, Deserialize, Serialize)]
+struct FailingSwapper;
+
+#[async_trait::async_trait]
+impl Behavior<u64> for FailingSwapper {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        Ok(Some(Box::pin(futures_util::stream::iter([1, 2]))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        Err(anyhow::anyhow!("insufficient balance")
+            .context(format!("failed to swap {}", event))
+            .into())
+    }
+
+    fn supervision(&self) -> Supervision {
+        Supervision::Skip
+    }
+}
+
+#[tokio::test]
+async fn publish_errors_on_the_messager() {
+    use futures_util::StreamExt;
+
+    let mut world = World::new("errors");
+    let errors = world.messager.errors();
+    world.add_agent(Agent::builder("swapper").with_behavior(FailingSwapper));
+    world.run().await.unwrap();
+
+    let errors: Vec<_> = errors.take(2).collect().await;
+    assert_eq!(
+        errors[0],
+        ErrorEvent {
+            agent: "swapper".to_owned(),
+            behavior: "FailingSwapper".to_owned(),
+            chain: vec![
+                "failed to swap 1".to_owned(),
+                "insufficient balance".to_owned()
+            ],
+        }
+    );
+    assert_eq!(
+        errors[1].to_string(),
+        "swapper/FailingSwapper failed: failed to swap 2: insufficient balance"
+    );
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Quote {
+    price: u64,
+}
+
+impl Payload for Quote {
+    const TAG: &'static str = "Quote";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Order {
+    price: u64,
+}
+
+impl Payload for Order {
+    const TAG: &'static str = "Order";
+}
+
+#[tokio::test]
+async fn typed_payloads() {
+    use futures_util::StreamExt;
+
+    let messager = Messager::new();
+    let maker = messager.for_agent("maker");
+    let quotes = messager.for_agent("taker").payloads::<Quote>().unwrap();
+    let mut messages = messager.for_agent("auditor").stream().unwrap();
+
+    maker.send(To::All, "hello").await.unwrap();
+    maker
+        .send_payload(To::All, &Order { price: 2 })
+        .await
+        .unwrap();
+    maker
+        .send_payload(To::All, &Quote { price: 1 })
+        .await
+        .unwrap();
+    maker.send(To::All, Quote { price: 3 }).await.unwrap();
+
+    let quotes: Vec<_> = quotes.take(2).collect().await;
+    assert_eq!(quotes[0].data, Quote { price: 1 });
+    assert_eq!(quotes[0].from, "maker");
+    assert_eq!(quotes[0].tag.as_deref(), Some("Quote"));
+    // Untagged messages are decoded if they are of the type.
+    assert_eq!(quotes[1].data, Quote { price: 3 });
+    assert_eq!(quotes[1].tag, None);
+
+    let hello = messages.next().await.unwrap();
+    assert!(hello.decode::<Quote>().is_err());
+    let order = messages.next().await.unwrap();
+    assert_eq!(order.decode::<Order>().unwrap().data, Order { price: 2 });
+    assert_eq!(
+        order.decode::<Quote>().unwrap_err().to_string(),
+        "MessagerError: Expected `Quote` from `maker` but received `Order`"
+    );
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+struct Halt;
+
+impl Payload for Halt {
+    const TAG: &'static str = "Halt";
+}
+
+#[tokio::test]
+async fn control_lane() {
+    use futures_util::StreamExt;
+
+    let messager = Messager::new();
+    let feed = messager.for_agent("feed");
+    let mut messages = messager.for_agent("trader").stream().unwrap();
+
+    for price in 0..5 {
+        feed.send_payload(To::All, &Quote { price }).await.unwrap();
+    }
+    feed.send_control(To::Agent("trader".to_owned()), &Halt)
+        .await
+        .unwrap();
+
+    // The command jumps ahead of the quotes that are waiting.
+    let halt = messages.next().await.unwrap();
+    assert_eq!(halt.priority, Priority::Control);
+    assert_eq!(halt.decode::<Halt>().unwrap().data, Halt);
+    let mut prices = vec![];
+    for _ in 0..5 {
+        let quote = messages.next().await.unwrap();
+        assert_eq!(quote.priority, Priority::Normal);
+        prices.push(quote.decode::<Quote>().unwrap().data.price);
+    }
+    assert_eq!(prices, [0, 1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn scheduled_messages() {
+    use futures_util::StreamExt;
+
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("auctioneer")).unwrap();
+    let messager = Messager::new();
+    let auctioneer = messager.for_agent("auctioneer");
+    let mut quotes = messager.for_agent("bidder").payloads::<Quote>().unwrap();
+
+    // The messages are sent once they are due, in the order they are due.
+    let start = std::time::Instant::now();
+    let cancelled = auctioneer
+        .send_after(Duration::from_millis(20), To::All, &Quote { price: 0 })
+        .unwrap();
+    auctioneer
+        .send_after(Duration::from_millis(40), To::All, &Quote { price: 2 })
+        .unwrap();
+    auctioneer
+        .send_after(Duration::from_millis(30), To::All, &Quote { price: 1 })
+        .unwrap();
+    let expiry = auctioneer
+        .send_at(client.clone(), 100, To::All, &Quote { price: 3 })
+        .unwrap();
+    cancelled.cancel();
+    assert_eq!(quotes.next().await.unwrap().data, Quote { price: 1 });
+    assert!(start.elapsed() >= Duration::from_millis(30));
+    let second = quotes.next().await.unwrap();
+    assert_eq!(
+        (second.from.as_str(), second.data),
+        ("auctioneer", Quote { price: 2 })
+    );
+
+    // The message at a block timestamp waits for the block.
+    client.update_block(1, 50).unwrap();
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert!(!expiry.is_finished());
+    client.update_block(2, 100).unwrap();
+    assert_eq!(quotes.next().await.unwrap().data, Quote { price: 3 });
+    assert!(cancelled.is_finished());
+}
+
+#[tokio::test]
+async fn remote_agents() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message as Frame;
+
+    let messager = Messager::new();
+    let server = RemoteServer::serve(&messager, "127.0.0.1:0").await.unwrap();
+    let native = messager.for_agent("native");
+    let mut messages = native.clone().stream().unwrap();
+    let url = format!("ws://{}/remote", server.local_addr());
+    let (mut remote, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    // Invalid frames are skipped, and valid ones are sent from the remote
+    // agent's id.
+    remote
+        .send(Frame::Text("not a message".to_owned()))
+        .await
+        .unwrap();
+    let quote = r#"{"to": {"Agent": "native"}, "data": {"price": 1}, "tag": "Quote"}"#;
+    remote.send(Frame::Text(quote.to_owned())).await.unwrap();
+    let message = messages.next().await.unwrap();
+    assert_eq!(message.from, "remote");
+    assert_eq!(message.decode::<Quote>().unwrap().data, Quote { price: 1 });
+
+    native
+        .send_payload(To::Agent("remote".to_owned()), &Quote { price: 2 })
+        .await
+        .unwrap();
+    let frame = remote.next().await.unwrap().unwrap();
+    let received: RemoteMessage = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+    assert_eq!(
+        received,
+        RemoteMessage {
+            from: "native".to_owned(),
+            to: To::Agent("remote".to_owned()),
+            data: serde_json::json!({ "price": 2 }),
+            tag: Some("Quote".to_owned()),
+            correlation_id: None,
+            priority: Priority::Normal,
+            trace_id: None,
+        }
+    );
+    server.stop();
+}
+
+#[tokio::test]
+async fn remote_agents_cannot_take_the_id_of_another_agent() {
+    use tokio_tungstenite::tungstenite::Error;
+
+    let messager = Messager::new();
+    let _native = messager.for_agent("native");
+    let server = RemoteServer::serve(&messager, "127.0.0.1:0").await.unwrap();
+    let url = |id: &str| format!("ws://{}/{}", server.local_addr(), id);
+    let status = |result: Result<_, Error>| match result {
+        Err(Error::Http(response)) => Some(response.status().as_u16()),
+        _ => None,
+    };
+
+    // Neither a native agent nor a connected remote agent is taken over.
+    let native = tokio_tungstenite::connect_async(url("native")).await;
+    assert_eq!(status(native), Some(409));
+    let (mut remote, _) = tokio_tungstenite::connect_async(url("remote"))
+        .await
+        .unwrap();
+    let again = tokio_tungstenite::connect_async(url("remote")).await;
+    assert_eq!(status(again), Some(409));
+
+    // The remote agent reconnects once it has disconnected.
+    remote.close(None).await.unwrap();
+    let mut reconnected = false;
+    for _ in 0..100 {
+        if tokio_tungstenite::connect_async(url("remote"))
+            .await
+            .is_ok()
+        {
+            reconnected = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(reconnected);
+    server.stop();
+}
+
+/// Sends a message to each of `to` on startup and halts.
+#[derive(Debug, Deserialize, Serialize)]
+struct Courier {
+    to: Vec<To>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Courier {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        for to in &self.to {
+            messager.send(to.clone(), "hello").await?;
+        }
+        Ok(None)
+    }
+}
+
+#[tokio::test]
+async fn remote_agents_are_not_dead_letters() {
+    use futures_util::StreamExt;
+
+    let mut world = World::new("remote");
+    let to = vec![To::Agent("remote".to_owned())];
+    world.add_agent(Agent::builder("courier").with_behavior(Courier { to }));
+    let server = RemoteServer::serve(&world.messager, "127.0.0.1:0")
+        .await
+        .unwrap();
+    let url = format!("ws://{}/remote", server.local_addr());
+    let (mut remote, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    world.run().await.unwrap();
+
+    let frame = remote.next().await.unwrap().unwrap();
+    let received: RemoteMessage = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+    assert_eq!(received.from, "courier");
+    assert!(world.dead_letters().is_empty());
+    server.stop();
+}
+
+#[tokio::test]
+async fn relay_between_worlds() {
+    use futures_util::StreamExt;
+
+    let mainnet = World::new("mainnet");
+    let rollup = World::new("rollup");
+    let outbound = Relay::new(&mainnet, &rollup)
+        .with_topic::<Quote>()
+        .with_latency(Duration::from_millis(20))
+        .with_jitter(Duration::from_millis(10))
+        .start();
+    let inbound = Relay::new(&rollup, &mainnet)
+        .with_topic::<Quote>()
+        .with_topic::<Order>()
+        .start();
+    let oracle = mainnet.messager.for_agent("oracle");
+    let mut orders = mainnet
+        .messager
+        .for_agent("oracle")
+        .payloads::<Order>()
+        .unwrap();
+    let mut quotes = mainnet
+        .messager
+        .for_agent("watcher")
+        .payloads::<Quote>()
+        .unwrap();
+    let arbitrageur = rollup.messager.for_agent("arbitrageur");
+    let mut messages = rollup.messager.for_agent("arbitrageur").stream().unwrap();
+
+    // Only the messages of the topics of the relay cross, once their latency
+    // has passed.
+    let start = std::time::Instant::now();
+    oracle.send(To::All, "untagged").await.unwrap();
+    oracle
+        .send_payload(To::All, &Quote { price: 1 })
+        .await
+        .unwrap();
+    let quote = messages.next().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(20));
+    assert_eq!(quote.from, "oracle");
+    assert_eq!(quote.decode::<Quote>().unwrap().data, Quote { price: 1 });
+
+    // The reply crosses back to the sender through the other relay.
+    arbitrageur
+        .send_payload(To::Agent("oracle".to_owned()), &Order { price: 1 })
+        .await
+        .unwrap();
+    let order = orders.next().await.unwrap();
+    assert_eq!(
+        (order.from.as_str(), order.data),
+        ("arbitrageur", Order { price: 1 })
+    );
+
+    // The relayed quote is not echoed back to where it came from.
+    assert_eq!(quotes.next().await.unwrap().data, Quote { price: 1 });
+    assert!(
+        tokio::time::timeout(Duration::from_millis(30), quotes.next())
+            .await
+            .is_err()
+    );
+    assert_eq!((outbound.relayed(), inbound.relayed()), (1, 1));
+    outbound.stop();
+}
+
+#[tokio::test]
+async fn despawn_agents() {
+    let interval = Trigger::Interval(Duration::from_millis(5));
+    let ticker = |count| Ticker {
+        trigger: interval,
+        count,
+        ticks: Arc::default(),
+    };
+    let churner = ticker(usize::MAX);
+    let churner_ticks = churner.ticks.clone();
+
+    let mut world = World::new("despawn");
+    world.add_agent(Agent::builder("churner").with_behavior(churner));
+    world.add_agent(Agent::builder("stayer").with_behavior(ticker(20)));
+    world.add_agent(Agent::builder("never").with_behavior(ticker(usize::MAX)));
+    assert!(world.despawner().despawn("churner").is_err());
+    world.despawn("never").unwrap();
+    assert!(world.despawn("never").is_err());
+
+    // The world only finishes running once the agent that never halts is
+    // despawned.
+    let despawner = world.despawner();
+    let despawned = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        despawner.despawn("churner")
+    });
+    world.run().await.unwrap();
+    despawned.await.unwrap().unwrap();
+
+    // The trigger of the despawned agent is stopped along with it.
+    let ticks = churner_ticks.lock().unwrap().len();
+    assert!(ticks > 0);
+    tokio::time::sleep(Duration::from_millis(30)).await;
+    assert_eq!(churner_ticks.lock().unwrap().len(), ticks);
+}
+
+/// Broadcasts `rounds` messages that every [`Arrival`] reacts to at once.
+#[derive(Debug, Deserialize, Serialize)]
+struct Clock {
+    rounds: u64,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Clock {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        for round in 0..self.rounds {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            messager.send(To::All, round).await?;
+        }
+        Ok(None)
+    }
+}
+
+/// Records the id of its agent when it processes a message, after a delay
+/// that is shorter the later the id sorts, and receives its messages `lag`
+/// milliseconds late.
+#[derive(Debug, Deserialize, Serialize)]
+struct Arrival {
+    delay: u64,
+    lag: u64,
+    rounds: u64,
+    #[serde(skip)]
+    id: String,
+    #[serde(skip)]
+    arrivals: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Arrival {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        use futures_util::StreamExt;
+        self.id = messager.id.clone().unwrap();
+        let lag = Duration::from_millis(self.lag);
+        Ok(Some(Box::pin(messager.stream()?.then(
+            move |message| async move {
+                tokio::time::sleep(lag).await;
+                message
+            },
+        ))))
+    }
+
+    async fn process(&mut self, _message: Message) -> Result<ControlFlow, BehaviorError> {
+        tokio::time::sleep(Duration::from_millis(self.delay)).await;
+        let mut arrivals = self.arrivals.lock().unwrap();
+        arrivals.push(self.id.clone());
+        if arrivals.iter().filter(|id| **id == self.id).count() as u64 == self.rounds {
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(self.rounds)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+async fn run_arrivals(scheduling: Scheduling, lags: [u64; 3]) -> Vec<Vec<String>> {
+    let arrivals = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut world = World::new("scheduling").with_scheduling(scheduling);
+    world.add_agent(Agent::builder("clock").with_behavior(Clock { rounds: 3 }));
+    for ((id, delay), lag) in [("a", 6), ("b", 4), ("c", 2)].into_iter().zip(lags) {
+        world.add_agent(Agent::builder(id).with_behavior(Arrival {
+            delay,
+            lag,
+            rounds: 3,
+            id: String::new(),
+            arrivals: arrivals.clone(),
+        }));
+    }
+    world.run().await.unwrap();
+    let arrivals = arrivals.lock().unwrap();
+    arrivals.chunks(3).map(|round| round.to_vec()).collect()
+}
+
+#[tokio::test]
+async fn deterministic_scheduling() {
+    // Concurrently, the behavior with the shortest delay finishes first.
+    let concurrent = run_arrivals(Scheduling::Concurrent, [0; 3]).await;
+    assert_eq!(concurrent, vec![vec!["c", "b", "a"]; 3]);
+
+    // Deterministically, the behaviors take turns in the order of their ids.
+    let deterministic = Scheduling::Deterministic { seed: None };
+    let ordered = run_arrivals(deterministic, [0; 3]).await;
+    assert_eq!(ordered, vec![vec!["a", "b", "c"]; 3]);
+
+    // Even if the first behaviors receive the message well after the last.
+    let late = run_arrivals(deterministic, [10, 5, 0]).await;
+    assert_eq!(late, ordered);
+
+    // A seeded order is shuffled, but the same in every run.
+    let seeded = Scheduling::Deterministic { seed: Some(7) };
+    let shuffled = run_arrivals(seeded, [0; 3]).await;
+    assert_eq!(shuffled, run_arrivals(seeded, [10, 5, 0]).await);
+    for round in &shuffled {
+        let mut sorted = round.clone();
+        sorted.sort();
+        assert_eq!(sorted, ["a", "b", "c"]);
+    }
+    assert_ne!(shuffled, ordered);
+}
+
+/// Draws three numbers from the generator of its agent.
+#[derive(Debug, Deserialize, Serialize)]
+struct Dice {
+    #[serde(skip)]
+    draws: Arc<std::sync::Mutex<HashMap<String, Vec<u64>>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<()> for Dice {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        let draws = (0..3).map(|_| messager.rng().gen()).collect();
+        self.draws
+            .lock()
+            .unwrap()
+            .insert(messager.id.clone().unwrap(), draws);
+        Ok(None)
+    }
+}
+
+async fn roll_dice(seed: Option<u64>, agents: &[&str]) -> HashMap<String, Vec<u64>> {
+    let draws = Arc::new(std::sync::Mutex::new(HashMap::new()));
+    let mut world = World::new("dice");
+    for id in agents {
+        world.add_agent(Agent::builder(id).with_behavior(Dice {
+            draws: draws.clone(),
+        }));
+    }
+    // The seed applies to the agents that were added before it is set.
+    if let Some(seed) = seed {
+        world = world.with_seed(seed);
+    }
+    world.run().await.unwrap();
+    let draws = draws.lock().unwrap().clone();
+    draws
+}
+
+#[tokio::test]
+async fn seeded_agent_rng() {
+    let draws = roll_dice(Some(7), &["alice", "bob"]).await;
+    assert_eq!(draws, roll_dice(Some(7), &["alice", "bob"]).await);
+    assert_ne!(draws["alice"], draws["bob"]);
+
+    // The draws of an agent only depend on the seed and its id.
+    let alone = roll_dice(Some(7), &["alice"]).await;
+    assert_eq!(alone["alice"], draws["alice"]);
+    let mut rng = StdRng::seed_from_u64(agent_seed(7, "alice"));
+    let expected: Vec<u64> = (0..3).map(|_| rng.gen()).collect();
+    assert_eq!(draws["alice"], expected);
+
+    assert_ne!(
+        roll_dice(Some(8), &["alice"]).await["alice"],
+        draws["alice"]
+    );
+    assert_eq!(
+        roll_dice(None, &["alice"]).await,
+        roll_dice(Some(DEFAULT_SEED), &["alice"]).await
+    );
+}
+
+/// Logs when it starts and each of the `events` it processes.
+#[derive(Debug, Deserialize, Serialize)]
+struct Phase {
+    name: String,
+    events: Vec<u64>,
+    #[serde(skip)]
+    log: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Phase {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("{} started", self.name));
+        if self.events.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Box::pin(futures_util::stream::iter(
+            self.events.clone(),
+        ))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", self.name, event));
+        if event == 0 {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn chained_behaviors() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let phase = |name: &str, events: Vec<u64>| Phase {
+        name: name.to_owned(),
+        events,
+        log: log.clone(),
+    };
+    let mut world = World::new("chain");
+    world.add_agent(
+        Agent::builder("agent")
+            // The deployment halts, the trading runs out of events, and the
+            // settlement starts without any.
+            .then_behavior(phase("deploy", vec![1, 0, 2]))
+            .then_behavior(phase("trade", vec![3, 4]))
+            .then_behavior(phase("settle", vec![])),
+    );
+    world.run().await.unwrap();
+    assert_eq!(
+        *log.lock().unwrap(),
+        [
+            "deploy started",
+            "deploy 1",
+            "deploy 0",
+            "trade started",
+            "trade 3",
+            "trade 4",
+            "settle started"
+        ]
+    );
+}
+
+/// The events of the streams merged by a [`Mixed`] behavior.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+enum Mixed {
+    Number(u64),
+    Message(String),
+    Tick(u64),
+}
+
+/// Processes numbers, messages, and ticks until it has seen all of them.
+#[derive(Debug, Deserialize, Serialize)]
+struct Mixer {
+    #[serde(skip)]
+    events: Arc<std::sync::Mutex<Vec<Mixed>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Mixed> for Mixer {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Mixed>>> {
+        let sender = messager.for_agent("sender");
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            sender.send(To::All, "hello").await.unwrap();
+        });
+        let numbers = Box::pin(futures_util::stream::iter(vec![1, 2]));
+        let ticks = Trigger::Interval(Duration::from_millis(5)).stream(client);
+        let streams = EventStreams::new()
+            .with(numbers, Mixed::Number)
+            .with(messager.stream()?, |message| Mixed::Message(message.data))
+            .with(ticks, |tick| Mixed::Tick(tick.index));
+        Ok(Some(streams.merge()))
+    }
+
+    async fn process(&mut self, event: Mixed) -> Result<ControlFlow, BehaviorError> {
+        let mut events = self.events.lock().unwrap();
+        events.push(event);
+        let ticks = events
+            .iter()
+            .filter(|event| matches!(event, Mixed::Tick(_)))
+            .count();
+        let messages = events
+            .iter()
+            .filter(|event| matches!(event, Mixed::Message(_)))
+            .count();
+        if ticks >= 3 && messages == 1 {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn merged_event_streams() {
+    let environment = Environment::builder().build();
+    let client = ArbiterMiddleware::new(&environment, Some("mixer")).unwrap();
+    let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let mut engine: Engine<_, Mixed> = Engine::new(Mixer {
+        events: events.clone(),
+    });
+    engine
+        .execute(MachineInstruction::Start(client, Messager::new()))
+        .await
+        .unwrap();
+
+    let events = events.lock().unwrap();
+    // The numbers are ready right away, while the message and the ticks come
+    // in as they are made.
+    assert_eq!(events[..2], [Mixed::Number(1), Mixed::Number(2)]);
+    assert!(events.contains(&Mixed::Message("\"hello\"".to_owned())));
+    let ticks: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            Mixed::Tick(index) => Some(*index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ticks[..3], [0, 1, 2]);
+}
+
+#[tokio::test]
+async fn pause_and_resume_agents() {
+    let maker = Ticker {
+        trigger: Trigger::Interval(Duration::from_millis(5)),
+        count: 10,
+        ticks: Arc::default(),
+    };
+    let ticks = maker.ticks.clone();
+    let mut world = World::new("pause");
+    world.add_agent(Agent::builder("maker").with_behavior(maker));
+    let pauser = world.pauser();
+    assert!(pauser.pause("taker").is_err());
+
+    let paused = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        pauser.pause("maker").unwrap();
+        assert!(pauser.is_paused("maker"));
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let before = ticks.lock().unwrap().len();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        let after = ticks.lock().unwrap().len();
+        pauser.resume("maker").unwrap();
+        (before, after)
+    });
+    // The world only finishes once the maker is resumed and sees all its ticks.
+    world.run().await.unwrap();
+    let (before, after) = paused.await.unwrap();
+    assert!(before < 10);
+    assert_eq!(before, after);
+}
+
+/// Records the messages it receives until it has received `count` of them.
+#[derive(Debug, Deserialize, Serialize)]
+struct Listener {
+    count: usize,
+    #[serde(skip)]
+    received: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl Listener {
+    fn new(count: usize) -> Self {
+        Self {
+            count,
+            received: Arc::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Listener {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        Ok(Some(messager.stream()?))
+    }
+
+    async fn process(&mut self, message: Message) -> Result<ControlFlow, BehaviorError> {
+        let mut received = self.received.lock().unwrap();
+        received.push(serde_json::from_str(&message.data)?);
+        if received.len() == self.count {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+/// Sends news to the liquidity providers and then to the traders.
+#[derive(Debug, Deserialize, Serialize)]
+struct Announcer;
+
+#[async_trait::async_trait]
+impl Behavior<()> for Announcer {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        messager
+            .send(To::Group("lps".to_owned()), "lp news")
+            .await?;
+        messager
+            .send(To::Group("traders".to_owned()), "trade news")
+            .await?;
+        Ok(None)
+    }
+}
+
+#[tokio::test]
+async fn agent_groups() {
+    let listeners = [Listener::new(1), Listener::new(2), Listener::new(1)];
+    let received =
+        [&listeners[0], &listeners[1], &listeners[2]].map(|listener| listener.received.clone());
+    let [alice, bob, carol] = listeners;
+
+    let mut world = World::new("groups");
+    world.add_agent(
+        Agent::builder("alice")
+            .with_group("lps")
+            .with_behavior(alice),
+    );
+    world.add_agent(
+        Agent::builder("bob")
+            .with_group("lps")
+            .with_group("traders")
+            .with_behavior(bob),
+    );
+    world.add_agent(
+        Agent::builder("carol")
+            .with_group("traders")
+            .with_behavior(carol),
+    );
+    world.add_agent(Agent::builder("announcer").with_behavior(Announcer));
+
+    let pauser = world.pauser();
+    assert_eq!(pauser.group("lps"), ["alice", "bob"]);
+    assert!(pauser.pause_group("whales").is_err());
+    pauser.pause_group("traders").unwrap();
+    assert!(!pauser.is_paused("alice"));
+    assert!(pauser.is_paused("bob"));
+    assert!(pauser.is_paused("carol"));
+    pauser.resume_group("traders").unwrap();
+    assert!(!pauser.is_paused("bob"));
+
+    // The world only finishes once every listener got the news of its groups.
+    world.run().await.unwrap();
+    assert_eq!(*received[0].lock().unwrap(), ["lp news"]);
+    assert_eq!(*received[1].lock().unwrap(), ["lp news", "trade news"]);
+    assert_eq!(*received[2].lock().unwrap(), ["trade news"]);
+
+    // Each group was sent one message, which reached both of its agents.
+    let groups = world.metrics().groups;
+    assert_eq!(groups.keys().collect::<Vec<_>>(), ["lps", "traders"]);
+    for metrics in groups.values() {
+        assert_eq!(
+            *metrics,
+            GroupMetrics {
+                messages_sent: 1,
+                deliveries: 2
+            }
+        );
+    }
+}
+
+/// Ticks until it has ticked `count` times and records how many times it had
+/// ticked when it is torn down.
+#[derive(Debug, Deserialize, Serialize)]
+struct Closer {
+    count: usize,
+    #[serde(skip)]
+    ticks: usize,
+    #[serde(skip)]
+    closed: Arc<std::sync::Mutex<Option<usize>>>,
+}
+
+impl Closer {
+    fn new(count: usize) -> Self {
+        Self {
+            count,
+            ticks: 0,
+            closed: Arc::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior<Tick> for Closer {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        Ok(Some(
+            Trigger::Interval(Duration::from_millis(5)).stream(client),
+        ))
+    }
+
+    async fn process(&mut self, _tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        self.ticks += 1;
+        if self.ticks == self.count {
+            return Ok(ControlFlow::Halt(HaltReason::MaxCount(self.count as u64)));
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    async fn teardown(&mut self) {
+        *self.closed.lock().unwrap() = Some(self.ticks);
+    }
+}
+
+#[tokio::test]
+async fn stop_world_and_tear_down_behaviors() {
+    let halter = Closer::new(2);
+    let runner = Closer::new(usize::MAX);
+    let first = Closer::new(usize::MAX);
+    let second = Closer::new(1);
+    let closed = [&halter, &runner, &first, &second].map(|closer| closer.closed.clone());
+
+    let mut world = World::new("stop");
+    world.add_agent(Agent::builder("halter").with_behavior(halter));
+    world.add_agent(Agent::builder("runner").with_behavior(runner));
+    world.add_agent(
+        Agent::builder("chain")
+            .then_behavior(first)
+            .then_behavior(second),
+    );
+    let stopper = world.stopper();
+    assert!(!stopper.is_stopped());
+    let stopped = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stopper.stop();
+    });
+    // The world only finishes once it is stopped, since the runner never
+    // halts.
+    world.run().await.unwrap();
+    stopped.await.unwrap();
+
+    let closed = closed.map(|closed| *closed.lock().unwrap());
+    // A behavior that halts is torn down right away.
+    assert_eq!(closed[0], Some(2));
+    // The others are torn down when the world stops, without starting the
+    // phases of a chain after the one the world stopped in.
+    assert!(closed[1].is_some_and(|ticks| ticks > 2));
+    assert!(closed[2].is_some_and(|ticks| ticks > 2));
+    assert_eq!(closed[3], None);
+
+    // The world reports why each behavior halted.
+    let metrics = world.metrics();
+    assert_eq!(
+        metrics.behaviors["halter/Closer"].halts,
+        [HaltReason::MaxCount(2)]
+    );
+    assert_eq!(
+        metrics.behaviors["runner/Closer"].halts,
+        [HaltReason::Stopped]
+    );
+    assert_eq!(
+        metrics.behaviors["chain/Closer"].halts,
+        [HaltReason::Stopped]
+    );
+}
+
+/// Deploys a token and mints on every even event, failing on odd events.
+#[derive(Debug, Deserialize, Serialize)]
+struct Busy {
+    events: Vec<u64>,
+    #[serde(skip)]
+    token: Option<ArbiterToken<ArbiterMiddleware>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Busy {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        let token =
+            ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))?
+                .send()
+                .await?;
+        self.token = Some(token);
+        Ok(Some(Box::pin(futures_util::stream::iter(
+            self.events.clone(),
+        ))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        if event % 2 == 1 {
+            return Err(BehaviorError::msg(format!("odd event {}", event)));
+        }
+        let token = self.token.as_ref().unwrap();
+        token
+            .mint(token.client().address(), event.into())
+            .send()
+            .await?
+            .await?;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn supervision(&self) -> Supervision {
+        Supervision::Skip
+    }
+}
+
+#[tokio::test]
+async fn behavior_metrics() {
+    let output = std::env::temp_dir().join("arbiter_behavior_metrics/metrics.json");
+    let mut world = World::new("metrics").with_metrics_output(&output);
+    world.add_agent(Agent::builder("busy").with_behavior(Busy {
+        events: vec![2, 3, 4, 5, 6],
+        token: None,
+    }));
+    world.add_agent(Agent::builder("idle").with_behavior(Busy {
+        events: vec![],
+        token: None,
+    }));
+    world.run().await.unwrap();
+
+    let metrics = world.metrics();
+    assert_eq!(
+        metrics.behaviors["busy/Busy"],
+        BehaviorMetrics {
+            events_received: 5,
+            events_processed: 3,
+            transactions_sent: 4,
+            errors: 2,
+            halts: vec![HaltReason::StreamEnded],
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        metrics.behaviors["idle/Busy"],
+        BehaviorMetrics {
+            transactions_sent: 1,
+            halts: vec![HaltReason::StreamEnded],
+            ..Default::default()
+        }
+    );
+    let saved: WorldMetrics = serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+    assert_eq!(saved, metrics);
+}
+
+#[tokio::test]
+async fn messager_metrics() {
+    let mut world = World::new("messager_metrics");
+    world.add_agent(
+        Agent::builder("alice")
+            .with_group("lps")
+            .with_behavior(Listener::new(1)),
+    );
+    world.add_agent(
+        Agent::builder("bob")
+            .with_group("lps")
+            .with_group("traders")
+            .with_behavior(Listener::new(2)),
+    );
+    world.add_agent(
+        Agent::builder("carol")
+            .with_group("traders")
+            .with_behavior(Listener::new(1)),
+    );
+    world.add_agent(Agent::builder("announcer").with_behavior(Announcer));
+    world.run().await.unwrap();
+
+    // The two messages of the announcer were each delivered to both agents of
+    // their group, and their latencies were counted once per delivery.
+    let metrics = world.metrics();
+    assert_eq!(metrics.behaviors["announcer/Announcer"].messages_sent, 2);
+    assert_eq!(metrics.behaviors["bob/Listener"].messages_sent, 0);
+    let messager = &metrics.messager;
+    assert_eq!(messager.messages_sent, 2);
+    assert_eq!(messager.messages_delivered, 4);
+    assert_eq!(messager.latency.count, 4);
+    assert_eq!(messager.latency.buckets.iter().sum::<u64>(), 4);
+    assert!(messager.latency.mean() <= messager.latency.max());
+    assert!(messager.latency.quantile(0.5) <= messager.latency.quantile(1.0));
+    assert_eq!(messager.latency.quantile(1.0), messager.latency.max());
+    assert!(metrics
+        .to_string()
+        .contains("messages: 2 sent, 4 delivered"));
+}
+
+/// Waits forever on `stuck_on` and records the other events it processes.
+#[derive(Debug, Deserialize, Serialize)]
+struct Stuck {
+    stuck_on: u64,
+    supervision: Supervision,
+    #[serde(skip)]
+    processed: Arc<std::sync::Mutex<Vec<u64>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Stuck {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        Ok(Some(Box::pin(futures_util::stream::iter([1, 2, 3]))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        if event == self.stuck_on {
+            futures_util::future::pending::<()>().await;
+        }
+        self.processed.lock().unwrap().push(event);
+        Ok(ControlFlow::Continue)
+    }
+
+    fn supervision(&self) -> Supervision {
+        self.supervision
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(Duration::from_millis(20))
+    }
+}
+
+#[tokio::test]
+async fn stuck_behaviors_time_out() {
+    let skipper = Stuck {
+        stuck_on: 2,
+        supervision: Supervision::Skip,
+        processed: Arc::default(),
+    };
+    let halter = Stuck {
+        stuck_on: 2,
+        supervision: Supervision::Halt,
+        processed: Arc::default(),
+    };
+    let processed = [&skipper, &halter].map(|stuck| stuck.processed.clone());
+
+    let mut world = World::new("stuck");
+    world.add_agent(Agent::builder("skipper").with_behavior(skipper));
+    world.add_agent(Agent::builder("halter").with_behavior(halter));
+    tokio::time::timeout(Duration::from_secs(5), world.run())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(*processed[0].lock().unwrap(), [1, 3]);
+    assert_eq!(*processed[1].lock().unwrap(), [1]);
+    assert_eq!(world.metrics().behaviors["skipper/Stuck"].errors, 1);
+}
+
+/// Mints the numbers after the last one it saw up to `until` on a token it
+/// deploys once, moving the block along with them.
+#[derive(Debug, Deserialize, Serialize)]
+struct Counter {
+    until: u64,
+    seen: Vec<u64>,
+    token: Option<Address>,
+    balance: U256,
+    started_at: Vec<u64>,
+    #[serde(skip)]
+    client: Option<Arc<ArbiterMiddleware>>,
+}
+
+impl Counter {
+    fn new(until: u64) -> Self {
+        Self {
+            until,
+            seen: vec![],
+            token: None,
+            balance: U256::zero(),
+            started_at: vec![],
+            client: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Counter {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        if self.token.is_none() {
+            let token =
+                ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))?
+                    .send()
+                    .await?;
+            self.token = Some(token.address());
+        }
+        self.started_at
+            .push(client.get_block_number().await?.as_u64());
+        self.client = Some(client);
+        let next = self.seen.last().map_or(1, |last| last + 1);
+        Ok(Some(Box::pin(futures_util::stream::iter(
+            next..=self.until,
+        ))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        let client = self.client.clone().unwrap();
+        client.update_block(event, event)?;
+        let token = ArbiterToken::new(self.token.unwrap(), client.clone());
+        token
+            .mint(client.address(), event.into())
+            .send()
+            .await?
+            .await?;
+        self.balance = token.balance_of(client.address()).call().await?;
+        self.seen.push(event);
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn resume_from_checkpoint() {
+    let output = std::env::temp_dir().join("arbiter_resume_from_checkpoint");
+    let mut world =
+        World::new("counting").with_checkpoints(output.join("first.json"), Duration::from_secs(60));
+    world.add_agent(Agent::builder("counter").with_behavior(Counter::new(3)));
+    world.run().await.unwrap();
+
+    let mut checkpoint = Checkpoint::load(output.join("first.json")).unwrap();
+    assert_eq!(checkpoint.world, "counting");
+    assert_eq!(checkpoint.block_number, 3);
+    let state = checkpoint.behaviors.get_mut("counter/0/Counter").unwrap();
+    assert_eq!(state["seen"], serde_json::json!([1, 2, 3]));
+    assert_eq!(state["started_at"], serde_json::json!([0]));
+    // Branch off into a longer run of the same world.
+    state["until"] = 5.into();
+
+    let mut world = World::new("counting")
+        .with_checkpoints(output.join("second.json"), Duration::from_secs(60));
+    world.add_agent(Agent::builder("counter").with_behavior(Counter::new(3)));
+    world.resume(checkpoint).unwrap();
+    world.run().await.unwrap();
+
+    let checkpoint = Checkpoint::load(output.join("second.json")).unwrap();
+    assert_eq!(checkpoint.block_number, 5);
+    let state: Counter =
+        serde_json::from_value(checkpoint.behaviors["counter/0/Counter"].clone()).unwrap();
+    assert_eq!(state.seen, [1, 2, 3, 4, 5]);
+    assert_eq!(state.started_at, [0, 3]);
+    assert_eq!(state.balance, U256::from(15));
+}
+
+/// Processes a few numbers that are different on every run, arriving at
+/// different times on every run.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Noisy {
+    #[serde(skip)]
+    processed: Arc<std::sync::Mutex<Vec<u64>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Noisy {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        Ok(Some(Box::pin(async_stream::stream! {
+            for _ in 0..5 {
+                let delay = rand::random::<u64>() % 5;
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                yield rand::random::<u64>();
+            }
+        })))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        self.processed.lock().unwrap().push(event);
+        Ok(ControlFlow::Continue)
+    }
+}
+
+async fn run_noisy(replay: Option<InputLog>) -> (InputLog, [Vec<u64>; 2]) {
+    let noisy = [Noisy::default(), Noisy::default()];
+    let processed = [&noisy[0], &noisy[1]].map(|noisy| noisy.processed.clone());
+    let output = std::env::temp_dir().join("arbiter_record_and_replay/inputs.json");
+    let mut world = World::new("noisy").with_input_recording(&output);
+    let [alice, bob] = noisy;
+    world.add_agent(Agent::builder("alice").with_behavior(alice));
+    world.add_agent(Agent::builder("bob").with_behavior(bob));
+    if let Some(log) = replay {
+        world.replay(log).unwrap();
+    }
+    world.run().await.unwrap();
+    let log = InputLog::load(&output).unwrap();
+    assert_eq!(log, world.inputs());
+    (
+        log,
+        processed.map(|processed| processed.lock().unwrap().clone()),
+    )
+}
+
+#[tokio::test]
+async fn record_and_replay_inputs() {
+    let (recorded, processed) = run_noisy(None).await;
+    assert_eq!(recorded.world, "noisy");
+    assert_eq!(recorded.inputs.len(), 10);
+    assert!(recorded
+        .inputs
+        .iter()
+        .enumerate()
+        .all(|(index, input)| input.sequence == index as u64));
+    let alice: Vec<_> = recorded
+        .inputs
+        .iter()
+        .filter(|input| input.behavior == "alice/0/Noisy")
+        .map(|input| serde_json::from_value::<u64>(input.event.clone()).unwrap())
+        .collect();
+    assert_eq!(alice, processed[0]);
+
+    // The replayed run receives the same events in the same order.
+    let (replayed, replayed_processed) = run_noisy(Some(recorded.clone())).await;
+    assert_eq!(replayed, recorded);
+    assert_eq!(replayed_processed, processed);
+}
+
+/// Logs its name once it has started up, which takes `delay` milliseconds,
+/// after the agents it depends on.
+#[derive(Debug, Deserialize, Serialize)]
+struct Deployer {
+    name: String,
+    delay: u64,
+    depends_on: Vec<String>,
+    #[serde(skip)]
+    started: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl Deployer {
+    fn new(
+        name: &str,
+        delay: u64,
+        depends_on: &[&str],
+        started: &Arc<std::sync::Mutex<Vec<String>>>,
+    ) -> Self {
+        Self {
+            name: name.to_owned(),
+            delay,
+            depends_on: depends_on.iter().map(|agent| agent.to_string()).collect(),
+            started: started.clone(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Deployer {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        tokio::time::sleep(Duration::from_millis(self.delay)).await;
+        self.started.lock().unwrap().push(self.name.clone());
+        Ok(None)
+    }
+
+    fn dependencies(&self) -> Vec<String> {
+        self.depends_on.clone()
+    }
+}
+
+#[tokio::test]
+async fn start_behaviors_after_their_dependencies() {
+    let started = Arc::default();
+    let mut world = World::new("dependencies");
+    world.add_agent(Agent::builder("swapper").with_behavior(Deployer::new(
+        "swapper",
+        0,
+        &["token_admin", "exchange_admin"],
+        &started,
+    )));
+    world.add_agent(
+        Agent::builder("exchange_admin").with_behavior(Deployer::new(
+            "exchange",
+            50,
+            &["token_admin"],
+            &started,
+        )),
+    );
+    world.add_agent(
+        Agent::builder("token_admin")
+            .with_behavior(Deployer::new("token", 100, &[], &started))
+            .with_behavior(Deployer::new("other token", 150, &[], &started)),
+    );
+    world.run().await.unwrap();
+    assert_eq!(
+        *started.lock().unwrap(),
+        ["token", "other token", "exchange", "swapper"]
+    );
+
+    // Dependencies on unknown agents and cycles are rejected before running.
+    let started = Arc::default();
+    let mut world = World::new("unknown");
+    world.add_agent(Agent::builder("swapper").with_behavior(Deployer::new(
+        "swapper",
+        0,
+        &["bank"],
+        &started,
+    )));
+    let err = world.run().await.unwrap_err();
+    assert!(err.to_string().contains("unknown agent `bank`"));
+
+    let mut world = World::new("cycle");
+    world.add_agent(Agent::builder("alice").with_behavior(Deployer::new(
+        "alice",
+        0,
+        &["bob"],
+        &started,
+    )));
+    world.add_agent(Agent::builder("bob").with_behavior(Deployer::new(
+        "bob",
+        0,
+        &["alice"],
+        &started,
+    )));
+    let err = world.run().await.unwrap_err();
+    assert!(err.to_string().contains("alice -> bob -> alice"));
+    assert!(started.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn export_the_world_as_dot() {
+    let started = Arc::default();
+    let mut world = World::new("graph");
+    world.add_agent(
+        Agent::builder("swapper")
+            .with_group("traders")
+            .with_behavior(Deployer::new("swapper", 0, &["token_admin"], &started))
+            .then_behavior(MockBehavior),
+    );
+    world.add_agent(Agent::builder("token_admin").with_sub_agent(
+        Agent::builder("minter").with_behavior(Ticker {
+            trigger: Trigger::Blocks(1),
+            count: 1,
+            ticks: Arc::default(),
+        }),
+    ));
+    let dot = world.to_dot().unwrap();
+    assert!(dot.starts_with("digraph \"graph\" {\n"));
+    for line in [
+        "  subgraph \"cluster_swapper\" {",
+        "    \"swapper/0\" [label=\"Deployer\\n(Message)\"];",
+        "    \"swapper/1\" [label=\"MockBehavior\\n(())\"];",
+        "  subgraph \"cluster_token_admin\" {",
+        "    \"token_admin/scope\" [shape=ellipse, label=\"scope\"];",
+        "    subgraph \"cluster_token_admin/minter\" {",
+        "      \"token_admin/minter/0\" [label=\"Ticker\\n(Tick)\"];",
+        "  \"messager\" -> \"swapper/0\";",
+        "  \"swapper/0\" -> \"token_admin\" [lhead=\"cluster_token_admin\", style=dashed, label=\"depends on\"];",
+        "  \"swapper/0\" -> \"swapper/1\" [label=\"then\"];",
+        "  \"group/traders\" -> \"swapper\" [lhead=\"cluster_swapper\", style=dotted];",
+    ] {
+        assert!(dot.contains(line), "{} is missing from\n{}", line, dot);
+    }
+}
+
+/// Logs when it has started up, which takes `delay` milliseconds, and when it
+/// processed its only event.
+#[derive(Debug, Deserialize, Serialize)]
+struct Starter {
+    name: String,
+    delay: u64,
+    #[serde(skip)]
+    log: Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<()> for Starter {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        tokio::time::sleep(Duration::from_millis(self.delay)).await;
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("started {}", self.name));
+        Ok(Some(Box::pin(futures_util::stream::iter([()]))))
+    }
+
+    async fn process(&mut self, _event: ()) -> Result<ControlFlow, BehaviorError> {
+        self.log
+            .lock()
+            .unwrap()
+            .push(format!("processed {}", self.name));
+        Ok(ControlFlow::Halt(HaltReason::done()))
+    }
+}
+
+/// Runs a world of three agents that start up in 0, 50, and 100 milliseconds
+/// with `barrier`, and returns what they logged.
+async fn run_starters(barrier: Option<Barrier>) -> Vec<String> {
+    let log = Arc::default();
+    let mut world = World::new("barrier");
+    if let Some(barrier) = barrier {
+        world = world.with_startup_barrier(barrier);
+    }
+    for (name, delay) in [("swapper", 0), ("exchange_admin", 50), ("token_admin", 100)] {
+        world.add_agent(Agent::builder(name).with_behavior(Starter {
+            name: name.to_owned(),
+            delay,
+            log: Arc::clone(&log),
+        }));
+    }
+    world.run().await.unwrap();
+    let log = log.lock().unwrap().clone();
+    log
+}
+
+#[tokio::test]
+async fn hold_behaviors_at_the_startup_barrier() {
+    // Without a barrier, each behavior processes its event right away.
+    let log = run_starters(None).await;
+    assert_eq!(log[..2], ["started swapper", "processed swapper"]);
+
+    // With a barrier, the behaviors wait for every agent, or for a quorum.
+    let log = run_starters(Some(Barrier::All)).await;
+    assert_eq!(
+        log[..3],
+        [
+            "started swapper",
+            "started exchange_admin",
+            "started token_admin"
+        ]
+    );
+    let log = run_starters(Some(Barrier::Quorum(2))).await;
+    assert_eq!(log[..2], ["started swapper", "started exchange_admin"]);
+    assert_eq!(log[4..], ["started token_admin", "processed token_admin"]);
+}
+
+/// Sends `count` numbered messages to `slow` at once on startup.
+#[derive(Debug, Deserialize, Serialize)]
+struct Burster {
+    count: u64,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Burster {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        for number in 0..self.count {
+            messager.send(To::Agent("slow".to_owned()), number).await?;
+        }
+        Ok(None)
+    }
+}
+
+/// Takes a millisecond to process each numbered message, records the numbers
+/// it processed, and halts on the number `last`.
+#[derive(Debug, Deserialize, Serialize)]
+struct Slow {
+    last: u64,
+    backpressure: Option<Backpressure>,
+    #[serde(skip)]
+    processed: Arc<std::sync::Mutex<Vec<u64>>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Slow {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        Ok(Some(messager.stream()?))
+    }
+
+    async fn process(&mut self, message: Message) -> Result<ControlFlow, BehaviorError> {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        let number: u64 = serde_json::from_str(&message.data)?;
+        self.processed.lock().unwrap().push(number);
+        if number == self.last {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        Ok(ControlFlow::Continue)
+    }
+
+    fn backpressure(&self) -> Option<Backpressure> {
+        self.backpressure
+    }
+}
+
+#[test]
+#[should_panic(expected = "The message capacity must be set before adding agents to the world.")]
+fn message_capacity_after_agents() {
+    let mut world = World::new("capacity");
+    world.add_agent(Agent::builder("burster").with_behavior(Burster { count: 1 }));
+    // The agent would be left on the previous channels.
+    let _ = world.with_message_capacity(4);
+}
+
+async fn run_slow(
+    count: u64,
+    capacity: usize,
+    backpressure: Option<Backpressure>,
+) -> (Vec<u64>, BehaviorMetrics) {
+    let processed = Arc::default();
+    let mut world = World::new("backpressure").with_message_capacity(capacity);
+    world.add_agent(Agent::builder("slow").with_behavior(Slow {
+        last: count - 1,
+        backpressure,
+        processed: Arc::clone(&processed),
+    }));
+    world.add_agent(Agent::builder("burster").with_behavior(Burster { count }));
+    world.run().await.unwrap();
+    let processed = processed.lock().unwrap().clone();
+    (processed, world.metrics().behaviors["slow/Slow"].clone())
+}
+
+#[tokio::test]
+async fn backpressure() {
+    // A burst larger than the channel of the messager is missed in part, but
+    // the stream carries on with the messages that are left.
+    let (processed, metrics) = run_slow(600, DEFAULT_MESSAGE_CAPACITY, None).await;
+    assert_eq!(processed, (88..600).collect::<Vec<_>>());
+    assert_eq!(metrics.events_lagged, 88);
+    assert_eq!(metrics.events_dropped, 0);
+    assert_eq!(metrics.max_queue_depth, 511);
+
+    // The capacity of the channel is set for the world.
+    let (processed, metrics) = run_slow(100, 64, None).await;
+    assert_eq!(processed, (36..100).collect::<Vec<_>>());
+    assert_eq!(metrics.events_lagged, 36);
+    assert_eq!(metrics.max_queue_depth, 63);
+
+    // Blocking on a full buffer processes every event in order.
+    let block = Some(Backpressure::Block { capacity: 4 });
+    let (processed, metrics) = run_slow(100, DEFAULT_MESSAGE_CAPACITY, block).await;
+    assert_eq!(processed, (0..100).collect::<Vec<_>>());
+    assert_eq!(metrics.events_lagged, 0);
+    assert_eq!(metrics.events_dropped, 0);
+    assert!(metrics.events_blocked > 0);
+
+    // Dropping the oldest events keeps up with the latest ones.
+    let drop_oldest = Some(Backpressure::DropOldest { capacity: 4 });
+    let (processed, metrics) = run_slow(100, DEFAULT_MESSAGE_CAPACITY, drop_oldest).await;
+    assert!(metrics.events_dropped > 0);
+    assert_eq!(metrics.events_lagged, 0);
+    assert_eq!(metrics.events_received, processed.len() as u64);
+    assert_eq!(processed.len() as u64 + metrics.events_dropped, 100);
+    assert!(processed.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(processed.last(), Some(&99));
+}
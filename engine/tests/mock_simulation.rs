@@ -1,13 +1,27 @@
 const AGENT_ID: &str = "agent";
 
-use std::time::Duration;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
 
+use arbiter_bindings::bindings::arbiter_token::ArbiterToken;
 use arbiter_engine::{
     agent::Agent,
+    dead_letters::Undeliverable,
+    determinism::{self, Divergence},
+    errors::{AgentBuildError, ArbiterEngineError},
+    journal::MessageLog,
+    logging::LogConfig,
     machine::{CreateStateMachine, Engine, StateMachine},
+    messager::Payload,
     world::World,
 };
-use arbiter_macros::Behaviors;
+use arbiter_macros::{Behaviors, Query};
+use ethers::{providers::Middleware, types::U256};
 use futures_util::StreamExt;
 use tokio::time::timeout;
 include!("common.rs");
@@ -140,5 +154,1108 @@ enum Behaviors {
 async fn config_test() {
     let mut world = World::from_config::<Behaviors>("tests/config.toml").unwrap();
     assert_eq!(world.id, "timed_message_world");
+    let pauser = world.pauser();
+    assert_eq!(pauser.group("players"), ["ping", "pong"]);
+    assert_eq!(pauser.group("responders"), ["pong"]);
+    world.run().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn template_config_test() {
+    let mut world = World::from_config::<Behaviors>("tests/template_config.toml").unwrap();
+    assert_eq!(world.id, "templated_world");
+    let agents = world.agents.as_ref().unwrap();
+    assert_eq!(agents.len(), 4);
+    assert!(!agents.contains_key("templates"));
+    // The run only finishes if `alice` inherited the `startup_message` override.
+    world.run().await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn instances_config_test() {
+    let mut world = World::from_config::<Behaviors>("tests/instances_config.toml").unwrap();
+    let agents = world.agents.as_ref().unwrap();
+    assert_eq!(agents.len(), 2);
+    assert!(!agents.contains_key("sets"));
+    // The run only finishes if both instances of each agent talk to each other.
+    world.run().await.unwrap();
+    let metrics = world.metrics();
+    assert_eq!(
+        metrics.behaviors["talker/TimedMessage"].halts,
+        [HaltReason::MaxCount(3), HaltReason::MaxCount(3)]
+    );
+    assert_eq!(metrics.behaviors["echo/TimedMessage"].halts.len(), 2);
+}
+
+#[test]
+fn validate_config() {
+    assert!(Behaviors::validate("tests/config.toml").is_ok());
+    assert!(Behaviors::validate("tests/template_config.toml").is_ok());
+    assert!(Behaviors::validate("tests/instances_config.toml").is_ok());
+
+    let path = std::env::temp_dir().join("arbiter_invalid_config.toml");
+    std::fs::write(
+        &path,
+        r#"id = "invalid_world"
+
+[[ping]]
+TimedMessage = { delay = 1, send_data = "ping", receive_data = "pong" }
+
+[[ping]]
+TimedMesage = { delay = 1, send_data = "zam", receive_data = "zim" }
+
+[[pong]]
+TimedMessage = { delay = 1, send_data = "pong" }
+"#,
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+    let error = Behaviors::validate(path).unwrap_err().to_string();
+    assert!(error.contains(&format!(
+        "{}:6:1: agent `ping` has the unknown behavior `TimedMesage`, expected one of `TimedMessage`",
+        path
+    )));
+    assert!(error.contains(&format!(
+        "{}:9:1: behavior `TimedMessage` of agent `pong` is invalid: missing field `receive_data`",
+        path
+    )));
+    assert!(!error.contains(":4:"));
+
+    let error = World::from_config::<Behaviors>(path)
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("TimedMesage"));
+
+    let path = std::env::temp_dir().join("arbiter_invalid_instances_config.toml");
+    std::fs::write(
+        &path,
+        r#"[[sets.pings]]
+send_data = "ping"
+
+[[sets.pings]]
+send_data = "ping"
+receive_data = "pong"
+
+[[ping]]
+TimedMessage = { delay = 1, for_each = "pings" }
+
+[[pong]]
+TimedMessage = { delay = 1, for_each = "pongs" }
+"#,
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+    let error = Behaviors::validate(path).unwrap_err().to_string();
+    assert!(error.contains(&format!(
+        "{}:8:1: behavior instance 1/2 of `TimedMessage` of agent `ping` is invalid: missing field `receive_data`",
+        path
+    )));
+    assert!(!error.contains("instance 2/2"));
+    assert!(error.contains(&format!(
+        "{}:11:1: agent `pong` instantiates `TimedMessage` for the unknown set `pongs`",
+        path
+    )));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn gas_limit_config_test() {
+    let mut world = World::from_config::<Behaviors>("tests/gas_limit_config.toml").unwrap();
+    let agents = world.agents.as_ref().unwrap();
+    assert_eq!(
+        agents["alice"].client.transaction_gas_limit(),
+        Some(1_000_000)
+    );
+    assert_eq!(agents["bob"].client.transaction_gas_limit(), Some(50_000));
+    world.run().await.unwrap();
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Inventory(u64);
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Contributor {
+    amount: u64,
+}
+
+#[async_trait::async_trait]
+impl Behavior<()> for Contributor {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        let amount = self.amount;
+        messager
+            .state::<Inventory>()
+            .update(|inventory| Inventory(inventory.map_or(0, |inventory| inventory.0) + amount));
+        Ok(None)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn shared_state() {
+    let mut world = World::new("world");
+    assert_eq!(world.state::<Inventory>().get(), None);
+    world.state::<Inventory>().publish(Inventory(1));
+
+    world.add_agent(Agent::builder("alice").with_behavior(Contributor { amount: 2 }));
+    world.add_agent(Agent::builder("bob").with_behavior(Contributor { amount: 3 }));
+    world.run().await.unwrap();
+
+    let inventory = world.state::<Inventory>();
+    assert_eq!(inventory.get(), Some(Inventory(6)));
+    assert_eq!(inventory.version(), 3);
+
+    let audit = world.blackboard().audit();
+    assert_eq!(audit.len(), 3);
+    assert_eq!(audit[0].publisher, None);
+    let mut publishers: Vec<_> = audit[1..]
+        .iter()
+        .map(|publication| publication.publisher.clone().unwrap())
+        .collect();
+    publishers.sort();
+    assert_eq!(publishers, ["alice", "bob"]);
+    assert!(audit
+        .iter()
+        .enumerate()
+        .all(
+            |(index, publication)| publication.version == index as u64 + 1
+                && publication.state.ends_with("Inventory")
+        ));
+}
+
+/// Collects a report from each of its sub-agents.
+#[derive(Debug, Deserialize, Serialize)]
+struct Fund {
+    strategies: usize,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Fund {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        mut messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        let scope = messager.scope().unwrap();
+        assert!(messager.scope().is_none());
+        Ok(Some(scope.stream()?))
+    }
+
+    async fn process(&mut self, event: Message) -> Result<ControlFlow, BehaviorError> {
+        assert_eq!(event.to, To::Agent("fund".to_owned()));
+        REPORTS.lock().unwrap().push((event.from, event.data));
+        self.strategies -= 1;
+        Ok(match self.strategies {
+            0 => ControlFlow::Halt(HaltReason::done()),
+            _ => ControlFlow::Continue,
+        })
+    }
+}
+
+/// The reports received by [`Fund`]s as pairs of sub-agent and data.
+static REPORTS: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+/// Reports the address it trades from to its parent.
+#[derive(Debug, Deserialize, Serialize)]
+struct Strategy;
+
+#[async_trait::async_trait]
+impl Behavior<()> for Strategy {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        messager
+            .send(To::Agent("fund".to_owned()), client.address())
+            .await?;
+        Ok(None)
+    }
+}
+
+/// Records whether it received any message from the world.
+#[derive(Debug, Deserialize, Serialize)]
+struct Outsider;
+
+#[async_trait::async_trait]
+impl Behavior<()> for Outsider {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        mut messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        let received = timeout(Duration::from_millis(100), messager.get_next())
+            .await
+            .is_ok();
+        messager.state::<bool>().publish(received);
+        Ok(None)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn sub_agents() {
+    let mut world = World::new("world");
+    world.add_agent(
+        Agent::builder("fund")
+            .with_behavior(Fund { strategies: 2 })
+            .with_sub_agent(Agent::builder("momentum").with_behavior(Strategy))
+            .with_sub_agent(Agent::builder("carry").with_behavior(Strategy)),
+    );
+    world.add_agent(Agent::builder("outsider").with_behavior(Outsider));
+
+    let agents = world.agents.as_ref().unwrap();
+    assert_eq!(agents.len(), 2);
+    let fund = &agents["fund"];
+    let mut sub_agents: Vec<_> = fund.sub_agents.keys().cloned().collect();
+    sub_agents.sort();
+    assert_eq!(sub_agents, ["fund/carry", "fund/momentum"]);
+    let address = fund.client.address();
+    assert!(fund
+        .sub_agents
+        .values()
+        .all(|sub_agent| sub_agent.client.address() == address));
+
     world.run().await.unwrap();
+
+    // Both strategies reported to the fund from the fund's wallet.
+    let mut reports = REPORTS.lock().unwrap().clone();
+    reports.sort();
+    let address = serde_json::to_string(&address).unwrap();
+    assert_eq!(
+        reports,
+        [
+            ("fund/carry".to_owned(), address.clone()),
+            ("fund/momentum".to_owned(), address),
+        ]
+    );
+    // The reports stayed within the fund.
+    assert_eq!(world.state::<bool>().get(), Some(false));
+}
+
+#[test]
+fn sub_agent_gas_limit() {
+    let world = World::new("world");
+    let environment = world.environment.as_ref().unwrap();
+    let client = ArbiterMiddleware::new(environment, Some("fund")).unwrap();
+    let error = Agent::builder("fund")
+        .with_sub_agent(
+            Agent::builder("strategy")
+                .with_behavior(Strategy)
+                .with_transaction_gas_limit(100_000),
+        )
+        .build(client, world.messager.for_agent("fund"))
+        .unwrap_err();
+    assert!(error.to_string().contains("fund/strategy"));
+}
+
+#[tokio::test]
+async fn validate_agents_as_they_are_added() {
+    let mut world = World::new("world");
+    world
+        .try_add_agent(
+            Agent::builder("fund")
+                .with_initial_eth(5)
+                .with_behavior(Strategy),
+        )
+        .unwrap();
+    let client = world.agents.as_ref().unwrap()["fund"].client.clone();
+    assert_eq!(
+        client.get_balance(client.address(), None).await.unwrap(),
+        U256::exp10(18) * 5
+    );
+
+    let build_error = |error: ArbiterEngineError| match error {
+        ArbiterEngineError::AgentBuildError(error) => error,
+        error => panic!("unexpected error: {}", error),
+    };
+    let error = world
+        .try_add_agent(Agent::builder("fund").with_behavior(Strategy))
+        .unwrap_err();
+    assert_eq!(
+        build_error(error),
+        AgentBuildError::DuplicateId("fund".to_owned())
+    );
+    let error = world.try_add_agent(Agent::builder("idle")).unwrap_err();
+    assert_eq!(
+        build_error(error),
+        AgentBuildError::NoBehaviors("idle".to_owned())
+    );
+    let error = world
+        .try_add_agent(
+            Agent::builder("bank").with_sub_agent(
+                Agent::builder("desk")
+                    .with_initial_eth(1)
+                    .with_behavior(Strategy),
+            ),
+        )
+        .unwrap_err();
+    assert_eq!(
+        build_error(error),
+        AgentBuildError::SharedClient {
+            sub_agent: "bank/desk".to_owned(),
+            agent: "bank".to_owned(),
+            setting: "an initial balance",
+        }
+    );
+
+    // An agent built by hand needs the messager of its id.
+    let error = Agent::builder("alice")
+        .with_behavior(Strategy)
+        .build(client, world.messager.clone())
+        .unwrap_err();
+    assert_eq!(
+        build_error(error),
+        AgentBuildError::UnregisteredMessager {
+            agent: "alice".to_owned(),
+            messager: None,
+        }
+    );
+}
+
+/// Sends a message and mints tokens whose contents depend on how often it has
+/// been started, which makes every run of a world with it differ.
+#[derive(Debug, Deserialize, Serialize)]
+struct Nondeterministic {
+    message: bool,
+}
+
+static STARTS: AtomicU64 = AtomicU64::new(0);
+
+#[async_trait::async_trait]
+impl Behavior<()> for Nondeterministic {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        let starts = STARTS.fetch_add(1, Ordering::Relaxed);
+        let token =
+            ArbiterToken::deploy(client.clone(), ("Token".to_owned(), "TKN".to_owned(), 18u8))?
+                .send()
+                .await?;
+        if self.message {
+            messager.send(To::All, starts).await?;
+        } else {
+            token
+                .mint(client.address(), starts.into())
+                .send()
+                .await?
+                .await?;
+        }
+        Ok(None)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn determinism_audit() {
+    let divergence =
+        determinism::audit(|| World::from_config::<Behaviors>("tests/gas_limit_config.toml"))
+            .await
+            .unwrap();
+    assert_eq!(divergence, None);
+
+    let divergence = determinism::audit(|| {
+        let mut world = World::new("world");
+        world.add_agent(
+            Agent::builder("messenger").with_behavior(Nondeterministic { message: true }),
+        );
+        world
+            .add_agent(Agent::builder("minter").with_behavior(Nondeterministic { message: false }));
+        Ok(world)
+    })
+    .await
+    .unwrap();
+    // Both agents deploy the same token first, so the messenger's first
+    // message diverges before the minter's second transaction does.
+    match divergence.unwrap() {
+        Divergence::Message {
+            agent,
+            index,
+            first,
+            second,
+        } => {
+            assert_eq!(agent, "messenger");
+            assert_eq!(index, 0);
+            assert_ne!(first.unwrap().data, second.unwrap().data);
+        }
+        divergence => panic!("Expected a diverging message, got {}", divergence),
+    }
+
+    let world = World::new("world").with_tracing();
+    assert!(world.trace().agents.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn transaction_divergence() {
+    let divergence = determinism::audit(|| {
+        let mut world = World::new("world");
+        world
+            .add_agent(Agent::builder("minter").with_behavior(Nondeterministic { message: false }));
+        Ok(world)
+    })
+    .await
+    .unwrap();
+    match divergence.unwrap() {
+        Divergence::Transaction {
+            agent,
+            index,
+            first,
+            second,
+        } => {
+            assert_eq!(agent, "minter");
+            assert_eq!(index, 1);
+            assert_ne!(first.unwrap().input, second.unwrap().input);
+        }
+        divergence => panic!("Expected a diverging transaction, got {}", divergence),
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn profiling() {
+    let mut world = World::new("world").with_profiling();
+
+    let behavior_ping = TimedMessage::new(
+        1,
+        "pong".to_owned(),
+        "ping".to_owned(),
+        Some(2),
+        Some("ping".to_owned()),
+    );
+    let behavior_pong = TimedMessage::new(1, "ping".to_owned(), "pong".to_owned(), Some(2), None);
+    world.add_agent(Agent::builder("agent_ping").with_behavior(behavior_ping));
+    world.add_agent(Agent::builder("agent_pong").with_behavior(behavior_pong));
+    world.run().await.unwrap();
+
+    let profile = world.profile();
+    for section in [
+        "environment/add_account",
+        "agent_ping/TimedMessage/startup",
+        "agent_ping/TimedMessage/process",
+        "agent_pong/TimedMessage/wait",
+        "agent_ping/serialization",
+    ] {
+        assert!(
+            profile.timings.contains_key(section),
+            "missing section {}",
+            section
+        );
+    }
+    assert!(profile.timings["agent_pong/TimedMessage/process"].count >= 2);
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Greeter;
+
+#[async_trait::async_trait]
+impl Behavior<()> for Greeter {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<()>>> {
+        tracing::debug!("hello from {}", messager.id.unwrap());
+        Ok(None)
+    }
+}
+
+/// Collects everything a subscriber writes.
+#[derive(Clone, Default)]
+struct Logs(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Logs {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn per_agent_log_levels() {
+    let logging = LogConfig::from_config("tests/logging_config.toml").unwrap();
+    assert_eq!(logging.level.as_deref(), Some("error"));
+    assert_eq!(logging.agents["alice"], "debug");
+    // The `logging` table is not mistaken for an agent.
+    let world = World::from_config::<Behaviors>("tests/logging_config.toml").unwrap();
+    assert_eq!(world.agents.as_ref().unwrap().len(), 2);
+
+    // The subscriber is only installed for this thread, which runs every task
+    // of the current thread runtime.
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(logging.filter(tracing::Level::ERROR).unwrap())
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let mut world = World::new("world");
+    world.add_agent(Agent::builder("alice").with_behavior(Greeter));
+    world.add_agent(Agent::builder("bob").with_behavior(Greeter));
+    world.run().await.unwrap();
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    assert!(logs.contains("hello from alice"));
+    assert!(!logs.contains("hello from bob"));
+}
+
+/// Mints tokens to itself for each of its events.
+#[derive(Debug, Deserialize, Serialize)]
+struct Minter {
+    #[serde(skip)]
+    token: Option<ArbiterToken<ArbiterMiddleware>>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Minter {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        _messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        let token = ArbiterToken::deploy(client, ("Token".to_owned(), "TKN".to_owned(), 18u8))?
+            .send()
+            .await?;
+        self.token = Some(token);
+        Ok(Some(Box::pin(futures_util::stream::iter([1, 2]))))
+    }
+
+    async fn process(&mut self, amount: u64) -> Result<ControlFlow, BehaviorError> {
+        let token = self.token.as_ref().unwrap();
+        token
+            .mint(token.client().address(), amount.into())
+            .send()
+            .await?
+            .await?;
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn behavior_and_event_spans() {
+    let logs = Logs::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter("info,arbiter_core::middleware=debug")
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let mut world = World::new("spans");
+    world.add_agent(Agent::builder("minter").with_behavior(Minter { token: None }));
+    world.run().await.unwrap();
+
+    // The transactions sent while processing an event are logged in the spans
+    // of the agent, the behavior, and the event.
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    let sent: Vec<_> = logs
+        .lines()
+        .filter(|line| line.contains("Sent transaction"))
+        .collect();
+    assert_eq!(sent.len(), 3);
+    assert!(sent[0].contains("agent{world=\"spans\" id=\"minter\"}:behavior{name=\"Minter\"}: "));
+    assert!(sent[2].contains(
+        "agent{world=\"spans\" id=\"minter\"}:behavior{name=\"Minter\"}:event{seq=2 \
+         trace=minter/0/Minter#2}: "
+    ));
+}
+
+/// Replies to "size" with its trade size, which it takes from its
+/// configuration when it is reloaded, and halts on "stop".
+#[derive(Debug, Serialize, Deserialize)]
+struct Tunable {
+    size: u64,
+    name: String,
+    #[serde(skip)]
+    messager: Option<Messager>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Tunable {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        self.messager = Some(messager.clone());
+        Ok(Some(messager.stream()?))
+    }
+
+    async fn process(&mut self, message: Message) -> Result<ControlFlow, BehaviorError> {
+        if message.data == "\"stop\"" {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        let messager = self.messager.as_ref().unwrap();
+        messager
+            .send(To::Agent(message.from), (&self.name, self.size))
+            .await?;
+        Ok(ControlFlow::Continue)
+    }
+
+    fn reload(&mut self, reconfigured: Self) {
+        self.size = reconfigured.size;
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Behaviors)]
+enum TunableBehaviors {
+    Tunable(Tunable),
+}
+
+fn tunable_config(trader_size: u64, other_name: &str) -> String {
+    format!(
+        r#"[[trader]]
+Tunable = {{ size = {}, name = "trader" }}
+
+[[other]]
+Tunable = {{ size = 5, name = "{}" }}
+"#,
+        trader_size, other_name
+    )
+}
+
+/// Asks `agent` for its size until it replies with `expected`, asking again
+/// if it does not reply, e.g., as it has not started yet.
+async fn wait_for_size(
+    messager: &Messager,
+    replies: &mut EventStream<Message>,
+    agent: &str,
+    expected: u64,
+) {
+    timeout(Duration::from_secs(5), async {
+        loop {
+            messager
+                .send(To::Agent(agent.to_owned()), "size")
+                .await
+                .unwrap();
+            if let Ok(Some(reply)) = timeout(Duration::from_millis(50), replies.next()).await {
+                let (_, size): (String, u64) = serde_json::from_str(&reply.data).unwrap();
+                if size == expected {
+                    break;
+                }
+            }
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn reload_parameters() {
+    let path = std::env::temp_dir().join("arbiter_reload_config.toml");
+    std::fs::write(&path, tunable_config(1, "other")).unwrap();
+    let mut world = World::from_config::<TunableBehaviors>(path.to_str().unwrap())
+        .unwrap()
+        .with_config_watching(Duration::from_millis(10));
+    let reloader = world.reloader();
+    let stopper = world.stopper();
+    let messager = world.messager.for_agent("tuner");
+    let mut replies = messager.clone().stream().unwrap();
+    let run = tokio::spawn(async move { world.run().await.map(|_| world.metrics()) });
+    wait_for_size(&messager, &mut replies, "trader", 1).await;
+
+    // Only the numeric parameters are reloaded.
+    std::fs::write(&path, tunable_config(2, "renamed")).unwrap();
+    assert_eq!(reloader.reload().unwrap(), ["trader/0"]);
+    wait_for_size(&messager, &mut replies, "trader", 2).await;
+    messager
+        .send(To::Agent("other".to_owned()), "size")
+        .await
+        .unwrap();
+    // Replies the trader was late with are skipped.
+    let reply = loop {
+        let reply = replies.next().await.unwrap();
+        if reply.from == "other" {
+            break reply;
+        }
+    };
+    assert_eq!(reply.data, "[\"other\",5]");
+
+    // The watched configuration is reloaded once it changes.
+    std::fs::write(&path, tunable_config(3, "renamed")).unwrap();
+    wait_for_size(&messager, &mut replies, "trader", 3).await;
+
+    stopper.stop();
+    let metrics = run.await.unwrap().unwrap();
+    assert_eq!(
+        metrics.behaviors["trader/Tunable"].halts,
+        [HaltReason::Stopped]
+    );
+    assert!(World::new("world").reloader().reload().is_err());
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Query)]
+enum CounterQuery {
+    #[reply(Count)]
+    Add(u64),
+    Reset,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Count(u64);
+
+impl Payload for Count {
+    const TAG: &'static str = "Count";
+}
+
+#[derive(Default)]
+struct Counter {
+    count: u64,
+    resets: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl CounterQueryHandler for Counter {
+    async fn add(&mut self, _from: &str, amount: u64) -> Result<Count, BehaviorError> {
+        self.count += amount;
+        Ok(Count(self.count))
+    }
+
+    async fn reset(&mut self, from: &str) -> Result<(), BehaviorError> {
+        self.count = 0;
+        self.resets.push(from.to_owned());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn dispatch_queries() {
+    assert_eq!(CounterQuery::TAG, "CounterQuery");
+    let messager = Messager::new();
+    let counter_messager = messager.for_agent("counter");
+    let mut queries = counter_messager.clone().stream().unwrap();
+    let client = messager.for_agent("client");
+    let mut counts = client.clone().payloads::<Count>().unwrap();
+
+    let mut counter = Counter::default();
+    client
+        .send_payload(To::Agent("counter".to_owned()), &CounterQuery::Add(2))
+        .await
+        .unwrap();
+    client
+        .send(To::Agent("counter".to_owned()), "not a query")
+        .await
+        .unwrap();
+    client
+        .send_payload(To::Agent("counter".to_owned()), &CounterQuery::Reset)
+        .await
+        .unwrap();
+    client
+        .send_payload(To::Agent("counter".to_owned()), &CounterQuery::Add(3))
+        .await
+        .unwrap();
+
+    let mut dispatched = vec![];
+    for _ in 0..4 {
+        let query = queries.next().await.unwrap();
+        dispatched.push(
+            CounterQuery::dispatch(&mut counter, &counter_messager, &query)
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(dispatched, [true, false, true, true]);
+    assert_eq!(counter.resets, ["client"]);
+
+    // Only the additions are replied to, with the count after them.
+    let first = counts.next().await.unwrap();
+    assert_eq!((first.from.as_str(), first.data), ("counter", Count(2)));
+    assert_eq!(counts.next().await.unwrap().data, Count(3));
+}
+
+#[tokio::test]
+async fn request_replies() {
+    let messager = Messager::new();
+    let counter_messager = messager.for_agent("counter");
+    let mut queries = counter_messager.clone().stream().unwrap();
+    let counter = tokio::spawn(async move {
+        let mut counter = Counter::default();
+        while let Some(query) = queries.next().await {
+            CounterQuery::dispatch(&mut counter, &counter_messager, &query)
+                .await
+                .unwrap();
+        }
+    });
+    let client = messager.for_agent("client");
+
+    // Each request gets its own reply, even when they overlap.
+    let to = || To::Agent("counter".to_owned());
+    let timeout = Duration::from_secs(1);
+    let (first, second) = tokio::join!(
+        client.request::<_, Count>(to(), &CounterQuery::Add(2), timeout),
+        client.request::<_, Count>(to(), &CounterQuery::Add(3), timeout),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+    assert_eq!(first.from, "counter");
+    assert_eq!(first.correlation_id.as_deref(), Some("client/0"));
+    assert_eq!(second.correlation_id.as_deref(), Some("client/1"));
+    assert_eq!((first.data, second.data), (Count(2), Count(5)));
+
+    // A query that is not replied to times out.
+    let error = client
+        .request::<_, Count>(to(), &CounterQuery::Reset, Duration::from_millis(50))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "MessagerError: No reply to the request `client/2` for `CounterQuery` within 50ms"
+    );
+    counter.abort();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn message_log() {
+    let output = std::env::temp_dir().join("arbiter_message_log/messages.jsonl");
+    let mut world = World::new("world").with_message_log(&output);
+    let ping = TimedMessage::new(
+        1,
+        "pong".to_owned(),
+        "ping".to_owned(),
+        Some(2),
+        Some("ping".to_owned()),
+    );
+    let pong = TimedMessage::new(1, "ping".to_owned(), "pong".to_owned(), Some(2), None);
+    world.add_agent(Agent::builder("ping").with_behavior(ping));
+    world.add_agent(Agent::builder("pong").with_behavior(pong));
+    world.run().await.unwrap();
+
+    let log = MessageLog::load(&output).unwrap();
+    let data: Vec<_> = log.messages.iter().map(|m| m.data.as_str()).collect();
+    assert_eq!(
+        data,
+        ["\"ping\"", "\"pong\"", "\"ping\"", "\"pong\"", "\"ping\""]
+    );
+    assert!(log
+        .messages
+        .iter()
+        .enumerate()
+        .all(|(index, message)| message.sequence == index as u64));
+    assert_eq!(log.sent_by("ping").count(), 3);
+    assert_eq!(log.sent_to("pong").count(), 5);
+    assert_eq!(log.messages[1].message().from, "pong");
+
+    std::fs::write(&output, "{\"sequence\":0}\n").unwrap();
+    let error = MessageLog::load(&output).unwrap_err().to_string();
+    assert!(
+        error.contains("messages.jsonl:1: invalid message"),
+        "{}",
+        error
+    );
+}
+
+/// Sends a message to each of `to` on startup and halts.
+#[derive(Debug, Serialize, Deserialize)]
+struct Courier {
+    to: Vec<To>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Courier {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        for to in &self.to {
+            messager.send(to.clone(), "hello").await?;
+        }
+        Ok(None)
+    }
+}
+
+#[tokio::test]
+async fn dead_letters() {
+    let mut world = World::new("world");
+    let to = vec![
+        To::Agent("courier".to_owned()),
+        To::Agent("nobody".to_owned()),
+        To::Group("empty".to_owned()),
+        To::All,
+    ];
+    world.add_agent(Agent::builder("courier").with_behavior(Courier { to }));
+    let outside = world.messager.for_agent("outside");
+    world.run().await.unwrap();
+    outside
+        .send(To::Agent("courier".to_owned()), "too late")
+        .await
+        .unwrap();
+
+    let letters: Vec<_> = world
+        .dead_letters()
+        .into_iter()
+        .map(|letter| (letter.message.to, letter.reason))
+        .collect();
+    assert_eq!(
+        letters,
+        [
+            (To::Agent("nobody".to_owned()), Undeliverable::UnknownAgent),
+            (To::Group("empty".to_owned()), Undeliverable::EmptyGroup),
+            (To::Agent("courier".to_owned()), Undeliverable::Halted),
+        ]
+    );
+}
+
+/// Claims the tag of a [`CounterQuery`] without being one.
+#[derive(Debug, Serialize, Deserialize)]
+struct Impostor(String);
+
+impl Payload for Impostor {
+    const TAG: &'static str = "CounterQuery";
+}
+
+#[tokio::test]
+async fn schema_validated_payloads() {
+    let world = World::new("world")
+        .with_schema::<CounterQuery>()
+        .with_recipient_schema::<Count>(To::Agent("client".to_owned()));
+    let counter = world.messager.for_agent("counter");
+    let mut received = world.messager.for_agent("client").stream().unwrap();
+
+    // The messages that match their schemas are sent.
+    counter
+        .send_payload(To::All, &CounterQuery::Add(1))
+        .await
+        .unwrap();
+    counter
+        .send(To::Agent("client".to_owned()), Count(1))
+        .await
+        .unwrap();
+
+    // The others are rejected before they reach anyone.
+    let error = counter
+        .send_payload(To::All, &Impostor("add one".to_owned()))
+        .await
+        .unwrap_err();
+    assert!(error.to_string().starts_with(
+        "MessagerError: Rejected a message from `counter` to All that does not match the \
+         schema `CounterQuery`: Could not decode a `CounterQuery` from `counter`: "
+    ));
+    let error = counter
+        .send_payload(To::Agent("client".to_owned()), &CounterQuery::Reset)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "MessagerError: Rejected a message from `counter` to Agent(\"client\") that does not \
+         match the schema `Count`: Expected `Count` from `counter` but received `CounterQuery`"
+    );
+    assert!(counter
+        .send_after(
+            Duration::from_millis(1),
+            To::Agent("client".to_owned()),
+            &CounterQuery::Reset
+        )
+        .is_err());
+
+    let tags: Vec<_> = received
+        .by_ref()
+        .take(2)
+        .map(|message| message.tag)
+        .collect()
+        .await;
+    assert_eq!(tags, [Some("CounterQuery".to_owned()), None]);
+    assert!(timeout(Duration::from_millis(20), received.next())
+        .await
+        .is_err());
+}
+
+/// Sends each of its events on to `relay` and halts once it sent them all.
+#[derive(Debug, Serialize, Deserialize)]
+struct Origin {
+    events: Vec<u64>,
+    #[serde(skip)]
+    messager: Option<Messager>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<u64> for Origin {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<u64>>> {
+        self.messager = Some(messager);
+        Ok(Some(Box::pin(futures_util::stream::iter(
+            self.events.clone(),
+        ))))
+    }
+
+    async fn process(&mut self, event: u64) -> Result<ControlFlow, BehaviorError> {
+        let messager = self.messager.as_ref().unwrap();
+        messager.send(To::Agent("relay".to_owned()), event).await?;
+        if Some(&event) == self.events.last() {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+/// Passes the messages it receives on to `sink`, halting after `count`.
+#[derive(Debug, Serialize, Deserialize)]
+struct Relay {
+    to: String,
+    count: usize,
+    #[serde(skip)]
+    messager: Option<Messager>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message> for Relay {
+    async fn startup(
+        &mut self,
+        _client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        self.messager = Some(messager.clone());
+        Ok(Some(messager.stream()?))
+    }
+
+    async fn process(&mut self, message: Message) -> Result<ControlFlow, BehaviorError> {
+        let number: u64 = serde_json::from_str(&message.data)?;
+        let messager = self.messager.as_ref().unwrap();
+        messager.send(To::Agent(self.to.clone()), number).await?;
+        self.count -= 1;
+        if self.count == 0 {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn causal_traces() {
+    let path = std::env::temp_dir().join("arbiter_causal_traces/messages.jsonl");
+    let mut world = World::new("world").with_message_log(&path);
+    world.add_agent(Agent::builder("origin").with_behavior(Origin {
+        events: vec![1, 2],
+        messager: None,
+    }));
+    for (id, to) in [("relay", "sink"), ("sink", "nobody")] {
+        world.add_agent(Agent::builder(id).with_behavior(Relay {
+            to: to.to_owned(),
+            count: 2,
+            messager: None,
+        }));
+    }
+    world.run().await.unwrap();
+
+    // Each event of the origin starts a trace that the messages it caused
+    // carry through the relays.
+    let log = MessageLog::load(&path).unwrap();
+    for (event, trace_id) in [("1", "origin/0/Origin#1"), ("2", "origin/0/Origin#2")] {
+        let chain: Vec<_> = log
+            .trace(trace_id)
+            .map(|message| (message.from.as_str(), message.data.as_str()))
+            .collect();
+        assert_eq!(
+            chain,
+            [("origin", event), ("relay", event), ("sink", event)]
+        );
+    }
+    assert_eq!(log.messages.len(), 6);
 }
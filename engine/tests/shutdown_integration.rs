@@ -0,0 +1,71 @@
+use std::{path::Path, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use arbiter_core::{middleware::ArbiterMiddleware, shutdown::ShutdownCoordinator};
+use arbiter_engine::{
+    agent::Agent,
+    errors::BehaviorError,
+    machine::{Behavior, ControlFlow, EventStream},
+    messager::{Messager, To},
+    trigger::{Tick, Trigger},
+    world::World,
+};
+use serde::{Deserialize, Serialize};
+
+/// Messages itself on every tick and never halts.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Ticker {
+    #[serde(skip)]
+    messager: Option<Messager>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Tick> for Ticker {
+    async fn startup(
+        &mut self,
+        client: Arc<ArbiterMiddleware>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Tick>>> {
+        self.messager = Some(messager);
+        Ok(Some(
+            Trigger::Interval(Duration::from_millis(5)).stream(client),
+        ))
+    }
+
+    async fn process(&mut self, _tick: Tick) -> Result<ControlFlow, BehaviorError> {
+        let messager = self.messager.as_ref().unwrap();
+        messager
+            .send(To::Agent("ticker".to_owned()), "tick")
+            .await?;
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn interrupted_world_writes_its_output() {
+    let directory = Path::new("./interrupted_world");
+    let mut world = World::new("interrupted")
+        .with_message_log(directory.join("messages.jsonl"))
+        .with_metrics_output(directory.join("metrics.json"));
+    world.add_agent(Agent::builder("ticker").with_behavior(Ticker::default()));
+    let interrupt = tokio::spawn(async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // Simulate a Ctrl-C while the world runs.
+        ShutdownCoordinator::global().trigger();
+    });
+    // The world only finishes once it is interrupted, since the ticker never
+    // halts.
+    world.run().await.unwrap();
+    interrupt.await.unwrap();
+    assert!(
+        ShutdownCoordinator::global()
+            .wait_for_sinks(Duration::from_secs(1))
+            .await
+    );
+
+    let messages = std::fs::read_to_string(directory.join("messages.jsonl")).unwrap();
+    let metrics = directory.join("metrics.json").exists();
+    std::fs::remove_dir_all(directory).unwrap();
+    assert!(messages.lines().count() > 0);
+    assert!(metrics);
+}
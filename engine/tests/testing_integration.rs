@@ -0,0 +1,80 @@
+use arbiter_engine::testing::{event_channel, run, scripted, MockClient, MockMessager};
+use ethers::{providers::Middleware, types::U64};
+
+include!("common.rs");
+
+/// Replies to every message with the block number it read on startup, and
+/// halts on "stop".
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BlockReporter {
+    #[serde(skip)]
+    state: Option<(Messager, u64)>,
+}
+
+#[async_trait::async_trait]
+impl Behavior<Message, MockClient> for BlockReporter {
+    async fn startup(
+        &mut self,
+        client: Arc<MockClient>,
+        messager: Messager,
+    ) -> Result<Option<EventStream<Message>>> {
+        let block_number = client.get_block_number().await?.as_u64();
+        self.state = Some((messager.clone(), block_number));
+        Ok(Some(messager.stream()?))
+    }
+
+    async fn process(&mut self, message: Message) -> Result<ControlFlow, BehaviorError> {
+        if message.data == "\"stop\"" {
+            return Ok(ControlFlow::Halt(HaltReason::done()));
+        }
+        let (messager, block_number) = self.state.as_ref().unwrap();
+        messager.send(To::Agent(message.from), block_number).await?;
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[tokio::test]
+async fn behavior_runs_with_mocks() {
+    let client = Arc::new(MockClient::new());
+    client.mock().push(U64::from(42)).unwrap();
+    let mut messager = MockMessager::new("reporter");
+    let mut behavior = BlockReporter::default();
+    let stream = behavior
+        .startup(client.clone(), messager.messager())
+        .await
+        .unwrap()
+        .unwrap();
+    client.mock().assert_request("eth_blockNumber", ()).unwrap();
+
+    // Scripted events.
+    let to = To::Agent("reporter".to_owned());
+    let events = [
+        messager.message("alice", to.clone(), "block?"),
+        messager.message("bob", to.clone(), "block?"),
+    ];
+    let flow = run(&mut behavior, scripted(events)).await.unwrap();
+    assert_eq!(flow, ControlFlow::Continue);
+    let sent = messager.sent();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[0].to, To::Agent("alice".to_owned()));
+    assert_eq!(sent[1].to, To::Agent("bob".to_owned()));
+    assert!(sent.iter().all(|message| message.data == "42"));
+
+    // Events sent while the behavior runs.
+    let (sender, events) = event_channel();
+    sender
+        .send(messager.message("alice", to.clone(), "block?"))
+        .unwrap();
+    sender.send(messager.message("alice", to, "stop")).unwrap();
+    let flow = run(&mut behavior, events).await.unwrap();
+    assert_eq!(flow, ControlFlow::Halt(HaltReason::done()));
+    assert_eq!(messager.next_sent().await.unwrap().data, "42");
+
+    // Messages sent through the messager reach the behavior's own stream.
+    messager
+        .send("alice", To::Agent("reporter".to_owned()), "stop")
+        .await
+        .unwrap();
+    let flow = run(&mut behavior, stream).await.unwrap();
+    assert_eq!(flow, ControlFlow::Halt(HaltReason::done()));
+}
@@ -1,6 +1,6 @@
 use arbiter_bindings::bindings::arbiter_token::TransferFilter;
 use arbiter_core::events::stream_event;
-use token_admin::{MintRequest, TokenAdminQuery};
+use token_admin::{MintRequest, TokenAddress, TokenAdminQuery};
 
 use super::*;
 
@@ -34,23 +34,26 @@ impl Behavior<TransferFilter> for TokenRequester {
     async fn startup(
         &mut self,
         client: Arc<ArbiterMiddleware>,
-        mut messager: Messager,
+        messager: Messager,
     ) -> Result<Option<EventStream<TransferFilter>>> {
-        messager
-            .send(
+        let reply = messager
+            .request::<_, TokenAddress>(
                 To::Agent(self.request_to.clone()),
-                &TokenAdminQuery::AddressOf(self.token_data.name.clone()),
+                &TokenAdminQuery::AddressOf(self.token_data.symbol.clone()),
+                Duration::from_secs(10),
             )
             .await?;
-        let message = messager.get_next().await.unwrap();
-        let token_address = serde_json::from_str::<eAddress>(&message.data).unwrap();
+        let token_address = reply
+            .data
+            .0
+            .ok_or_else(|| anyhow::anyhow!("The token admin has no {}", self.token_data.symbol))?;
         let token = ArbiterToken::new(token_address, client.clone());
         self.token_data.address = Some(token_address);
 
         let mint_data = TokenAdminQuery::MintRequest(MintRequest {
-            token: self.token_data.name.clone(),
+            token: self.token_data.symbol.clone(),
             mint_to: client.address(),
-            mint_amount: 1,
+            mint_amount: eU256::one(),
         });
         messager
             .send(To::Agent(self.request_to.clone()), mint_data)
@@ -64,14 +67,14 @@ impl Behavior<TransferFilter> for TokenRequester {
 
     #[tracing::instrument(skip(self), fields(id =
  self.messager.as_ref().unwrap().id.as_deref()))]
-    async fn process(&mut self, _event: TransferFilter) -> Result<ControlFlow> {
+    async fn process(&mut self, _event: TransferFilter) -> Result<ControlFlow, BehaviorError> {
         let messager = self.messager.as_ref().unwrap();
         while self.count < self.max_count.unwrap() {
             debug!("sending message from requester");
             let mint_data = TokenAdminQuery::MintRequest(MintRequest {
-                token: self.token_data.name.clone(),
+                token: self.token_data.symbol.clone(),
                 mint_to: self.client.as_ref().unwrap().address(),
-                mint_amount: 1,
+                mint_amount: eU256::one(),
             });
             messager
                 .send(To::Agent(self.request_to.clone()), mint_data)
@@ -79,6 +82,6 @@ impl Behavior<TransferFilter> for TokenRequester {
             self.count += 1;
         }
         warn!("Reached max count. Halting behavior.");
-        Ok(ControlFlow::Halt)
+        Ok(ControlFlow::Halt(HaltReason::MaxCount(self.count)))
     }
 }
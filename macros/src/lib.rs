@@ -5,9 +5,10 @@ extern crate syn;
 use proc_macro::TokenStream;
 use quote::quote;
 use syn::{
+    parenthesized,
     parse::{Parse, ParseStream},
-    parse_macro_input, Data, DataEnum, DeriveInput, Fields, Ident, ItemFn, Lit,
-    Result as ParseResult, Type,
+    parse_macro_input, token, Attribute, Data, DataEnum, DeriveInput, Expr, Fields, Ident, ItemFn,
+    Lit, LitStr, Result as ParseResult, Token, Type,
 };
 
 /// A procedural macro to derive the `Behaviors` trait for enums.
@@ -18,6 +19,18 @@ use syn::{
 /// contains unnamed fields, ideally a single field that represents the
 /// state data for that variant.
 ///
+/// The generated implementation also knows the names each variant is
+/// configured with, honoring `#[serde(rename = "...")]` on the variants and
+/// `#[serde(rename_all = "...")]` on the enum, and checks the configuration of
+/// a variant by deserializing its field, so that `#[serde(default)]` and
+/// renamed fields of the behaviors are taken into account. An inherent
+/// `validate` function is generated as well, which checks a configuration file
+/// with `World::validate_config` and reports unknown behaviors and invalid
+/// configurations, e.g., with missing required fields, along with the line
+/// they are configured at. A variant configured with a `for_each` key is
+/// checked once for each of the instances it expands to, see
+/// `World::from_config`.
+///
 /// # Panics
 /// The macro will panic if it is applied to anything other than an enum, or if
 /// any of the enum's variants do not contain exactly one unnamed field.
@@ -28,11 +41,15 @@ use syn::{
 /// single unnamed field that implements the `StateMachine` trait.
 ///
 /// ```ignore
-/// #[derive(Behaviors)]
+/// #[derive(Behaviors, Serialize, Deserialize)]
+/// #[serde(rename_all = "snake_case")]
 /// enum MyBehavior {
 ///     StateOne(StateDataOne),
+///     #[serde(rename = "two")]
 ///     StateTwo(StateDataTwo),
 /// }
+///
+/// MyBehavior::validate("config.toml")?;
 /// ```
 #[proc_macro_derive(Behaviors)]
 pub fn create_behavior_from_enum(input: TokenStream) -> TokenStream {
@@ -41,6 +58,7 @@ pub fn create_behavior_from_enum(input: TokenStream) -> TokenStream {
 
     // Extract the identifier (name) of the enum.
     let name = input.ident;
+    let rename_all = serde_rename(&input.attrs, "rename_all");
 
     // Attempt to extract enum data, panicking if the input is not an enum.
     let enum_data = if let Data::Enum(DataEnum { variants, .. }) = input.data {
@@ -50,11 +68,15 @@ pub fn create_behavior_from_enum(input: TokenStream) -> TokenStream {
     };
 
     // Generate match arms for the `create_state_machine` function, one for each
-    // enum variant.
-    let match_arms = enum_data.into_iter().map(|variant| {
+    // enum variant, along with the name of each variant in a configuration and
+    // the match arm that validates its configuration.
+    let mut match_arms = vec![];
+    let mut behavior_names = vec![];
+    let mut validate_arms = vec![];
+    for variant in enum_data {
         // Extract the variant name and the type of its single unnamed field.
         let variant_name = variant.ident;
-        let _inner_type = if let Fields::Unnamed(fields) = variant.fields {
+        let inner_type = if let Fields::Unnamed(fields) = variant.fields {
             fields.unnamed.first().unwrap().ty.clone()
         } else {
             panic!("Expected unnamed fields in enum variant");
@@ -62,12 +84,24 @@ pub fn create_behavior_from_enum(input: TokenStream) -> TokenStream {
 
         // Generate a match arm that constructs a new state machine instance for the
         // variant.
-        quote! {
+        match_arms.push(quote! {
             #name::#variant_name(inner) => {
-                Box::new(Engine::new(inner))
+                let engine: Engine<_, _> = Engine::new(inner);
+                Box::new(engine)
             }
-        }
-    });
+        });
+
+        // The variant is configured by the name serde deserializes it from.
+        let behavior_name = serde_rename(&variant.attrs, "rename")
+            .unwrap_or_else(|| rename_variant(&variant_name.to_string(), rename_all.as_deref()));
+        validate_arms.push(quote! {
+            #behavior_name => config
+                .try_into::<#inner_type>()
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        });
+        behavior_names.push(behavior_name);
+    }
 
     // Generate the full implementation of the `CreateStateMachine` trait for the
     // enum.
@@ -78,6 +112,30 @@ pub fn create_behavior_from_enum(input: TokenStream) -> TokenStream {
                     #(#match_arms,)*
                 }
             }
+
+            fn behavior_names() -> &'static [&'static str] {
+                &[#(#behavior_names),*]
+            }
+
+            fn validate_behavior(
+                name: &str,
+                config: arbiter_engine::machine::BehaviorConfig,
+            ) -> Result<(), String> {
+                match name {
+                    #(#validate_arms,)*
+                    _ => Err(format!("unknown behavior `{}`", name)),
+                }
+            }
+        }
+
+        impl #name {
+            /// Checks the behaviors configured in the file at `config_path`,
+            /// see `World::validate_config`.
+            pub fn validate(
+                config_path: &str,
+            ) -> Result<(), arbiter_engine::errors::ArbiterEngineError> {
+                arbiter_engine::world::World::validate_config::<Self>(config_path)
+            }
         }
     };
 
@@ -86,6 +144,236 @@ pub fn create_behavior_from_enum(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
+/// A procedural macro to derive the messager dispatch of a query enum, i.e.,
+/// of the requests that a behavior serves for other agents, e.g., the
+/// `TokenAdminQuery` of a `TokenAdmin`.
+///
+/// The enum is made a `Payload` tagged with its name, and a handler trait
+/// named after the enum, e.g., `TokenAdminQueryHandler`, is generated with an
+/// async method for each variant, named after the variant in `snake_case`.
+/// Each method is handed the id of the agent that sent the query and the
+/// field of the variant, if it has one, and returns the reply, which is sent
+/// back to the sender. The type of the reply is given with `#[reply(...)]` on
+/// the variant and has to be a `Payload` as well, and the queries of variants
+/// without it are not replied to.
+///
+/// An inherent `dispatch` function is generated as well, which decodes a
+/// message into the query, calls the method of its variant on the handler,
+/// sends the reply, and returns whether the message was a query at all.
+///
+/// # Panics
+/// The macro will panic if it is applied to anything other than an enum, or if
+/// any of the enum's variants has more than one field or a named field.
+///
+/// # Usage
+/// The generated code refers to `async_trait` and to the `messager` and
+/// `errors` modules of `arbiter_engine`, so both crates have to be
+/// dependencies.
+///
+/// ```ignore
+/// #[derive(Query, Serialize, Deserialize)]
+/// enum PoolQuery {
+///     #[reply(PoolReply)]
+///     Swap(SwapRequest),
+///     Reset,
+/// }
+///
+/// #[async_trait::async_trait]
+/// impl PoolQueryHandler for Pool {
+///     async fn swap(&mut self, from: &str, request: SwapRequest) -> Result<PoolReply, BehaviorError> {
+///         // ...
+///     }
+///
+///     async fn reset(&mut self, from: &str) -> Result<(), BehaviorError> {
+///         // ...
+///     }
+/// }
+///
+/// // And in `Behavior::process` of the pool.
+/// PoolQuery::dispatch(self, &messager, &message).await?;
+/// ```
+#[proc_macro_derive(Query, attributes(reply))]
+pub fn derive_query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let name = input.ident;
+    let vis = input.vis;
+    let tag = name.to_string();
+    let handler = Ident::new(&format!("{}Handler", name), name.span());
+
+    let variants = if let Data::Enum(DataEnum { variants, .. }) = input.data {
+        variants
+    } else {
+        panic!("Query is only defined for enums");
+    };
+
+    // Generate a method of the handler and a match arm of the dispatch for
+    // each variant.
+    let mut methods = vec![];
+    let mut dispatch_arms = vec![];
+    for variant in variants {
+        let variant_name = variant.ident;
+        let method = Ident::new(
+            &rename_variant(&variant_name.to_string(), Some("snake_case")),
+            variant_name.span(),
+        );
+        let reply = variant
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("reply"))
+            .map(|attr| {
+                attr.parse_args::<Type>()
+                    .expect("Expected the type of the reply in `#[reply(...)]`")
+            });
+        let doc = format!("Handles a `{}::{}` query.", name, variant_name);
+        let output = match &reply {
+            Some(reply) => quote! { #reply },
+            None => quote! { () },
+        };
+        let (pattern, parameter, argument) = match variant.fields {
+            Fields::Unit => (quote! { #name::#variant_name }, quote! {}, quote! {}),
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed.first().unwrap().ty;
+                (
+                    quote! { #name::#variant_name(query) },
+                    quote! { , query: #ty },
+                    quote! { , query },
+                )
+            }
+            _ => panic!("Expected a single unnamed field or none in query variant"),
+        };
+        methods.push(quote! {
+            #[doc = #doc]
+            async fn #method(
+                &mut self,
+                from: &str #parameter
+            ) -> Result<#output, arbiter_engine::errors::BehaviorError>;
+        });
+        dispatch_arms.push(match reply {
+            Some(_) => quote! {
+                #pattern => {
+                    let reply = handler.#method(&message.from #argument).await?;
+                    messager.reply(message, &reply).await?;
+                }
+            },
+            None => quote! {
+                #pattern => handler.#method(&message.from #argument).await?,
+            },
+        });
+    }
+
+    let handler_doc = format!(
+        "Handles the queries of a [`{}`], which are dispatched with `{}::dispatch`.",
+        name, name
+    );
+    let expanded = quote! {
+        impl arbiter_engine::messager::Payload for #name {
+            const TAG: &'static str = #tag;
+        }
+
+        #[doc = #handler_doc]
+        #[async_trait::async_trait]
+        #vis trait #handler: Send {
+            #(#methods)*
+        }
+
+        impl #name {
+            /// Decodes `message` into a query, has `handler` handle it, and
+            /// replies to the sender with `messager`, returning whether the
+            /// message was a query.
+            #vis async fn dispatch<H: #handler>(
+                handler: &mut H,
+                messager: &arbiter_engine::messager::Messager,
+                message: &arbiter_engine::messager::Message,
+            ) -> Result<bool, arbiter_engine::errors::BehaviorError> {
+                let Ok(query) = message.decode::<Self>() else {
+                    return Ok(false);
+                };
+                match query.data {
+                    #(#dispatch_arms)*
+                }
+                Ok(true)
+            }
+        }
+    };
+    TokenStream::from(expanded)
+}
+
+/// Returns the name given by `#[serde(<key> = "...")]` among the `attrs`, or
+/// by `#[serde(<key>(deserialize = "..."))]` since the configuration is only
+/// ever deserialized.
+fn serde_rename(attrs: &[Attribute], key: &str) -> Option<String> {
+    let mut rename = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        // Other serde attributes are none of our business, so they are parsed
+        // loosely and skipped.
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) {
+                if meta.input.peek(Token![=]) {
+                    let value: LitStr = meta.value()?.parse()?;
+                    rename = Some(value.value());
+                } else {
+                    meta.parse_nested_meta(|nested| {
+                        if nested.path.is_ident("deserialize") {
+                            let value: LitStr = nested.value()?.parse()?;
+                            rename = Some(value.value());
+                        } else {
+                            let _: LitStr = nested.value()?.parse()?;
+                        }
+                        Ok(())
+                    })?;
+                }
+            } else if meta.input.peek(Token![=]) {
+                let _: Expr = meta.value()?.parse()?;
+            } else if meta.input.peek(token::Paren) {
+                let _content;
+                parenthesized!(_content in meta.input);
+            }
+            Ok(())
+        });
+    }
+    rename
+}
+
+/// Renames a `PascalCase` variant by the `rename_all` rule of serde.
+fn rename_variant(variant: &str, rule: Option<&str>) -> String {
+    let words = || {
+        let mut words: Vec<String> = vec![];
+        for c in variant.chars() {
+            match words.last_mut() {
+                Some(word) if !c.is_uppercase() => word.push(c),
+                _ => words.push(c.to_string()),
+            }
+        }
+        words
+    };
+    let join = |separator: &str, upper: bool| {
+        let words: Vec<_> = words()
+            .into_iter()
+            .map(|word| match upper {
+                true => word.to_uppercase(),
+                false => word.to_lowercase(),
+            })
+            .collect();
+        words.join(separator)
+    };
+    match rule {
+        Some("lowercase") => variant.to_lowercase(),
+        Some("UPPERCASE") => variant.to_uppercase(),
+        Some("camelCase") => {
+            let mut chars = variant.chars();
+            chars
+                .next()
+                .map(|first| first.to_lowercase().chain(chars).collect())
+                .unwrap_or_default()
+        }
+        Some("snake_case") => join("_", false),
+        Some("SCREAMING_SNAKE_CASE") => join("_", true),
+        Some("kebab-case") => join("-", false),
+        Some("SCREAMING-KEBAB-CASE") => join("-", true),
+        _ => variant.to_owned(),
+    }
+}
+
 /// `MacroArgs` is a struct designed to capture and store the attributes
 /// provided to our custom macro. It specifically targets the parsing of `name`,
 /// `about`, and `behaviors` attributes, which are essential for configuring the
@@ -250,7 +538,18 @@ pub fn main(attr: TokenStream, item: TokenStream) -> TokenStream {
                 3 => Level::DEBUG,
                 _ => Level::TRACE,
             };
-            tracing_subscriber::fmt().with_max_level(log_level).init();
+            // The levels of the `logging` table of the configuration apply
+            // unless a verbosity is given on the command line.
+            let mut logging = match &args.command {
+                Some(Commands::Simulate { config_path }) => {
+                    arbiter_engine::logging::LogConfig::from_config(config_path)?
+                }
+                None => Default::default(),
+            };
+            if args.verbose.unwrap_or(0) > 0 {
+                logging.level = Some(log_level.to_string().to_lowercase());
+            }
+            logging.init(log_level)?;
 
             match &args.command {
                 Some(Commands::Simulate { config_path }) => {